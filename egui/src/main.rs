@@ -0,0 +1,260 @@
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+use eframe::egui::{self, Color32, ColorImage, Key, RichText, TextureHandle, TextureOptions};
+
+use rust_6502_emulator::bus::{Address, Data};
+use rust_6502_emulator::debugger::{parse_address, DebugEvent, Debugger, RunMode};
+use rust_6502_emulator::devices::framebuffer::{HEIGHT, WIDTH};
+use rust_6502_emulator::disasm::Disassembler;
+use rust_6502_emulator::machines::{easy6502, Easy6502};
+
+// 60 frames a second at 1MHz
+const CYCLES_PER_FRAME: usize = 16_667;
+const SCREEN_SCALE: f32 = 10.0;
+const MEMORY_ROWS: usize = 16;
+const DISASSEMBLY_LINES: usize = 12;
+const LOG_LINES: usize = 200;
+
+// The easy6502 machine in a window: run controls, registers, disassembly, a memory viewer and
+// the screen, with a debugger command line underneath. Everything goes through the Debugger,
+// as any other front end would
+struct App {
+    machine: Easy6502,
+    debugger: Debugger,
+    disassembler: Disassembler,
+    running: bool,
+    screen: Option<TextureHandle>,
+    memory_start: Address,
+    memory_address: String,
+    breakpoint: String,
+    command: String,
+    log: Vec<String>,
+}
+
+impl App {
+    fn new(program: &[Data]) -> App {
+        let mut machine = easy6502();
+        machine.load(program);
+        let debugger = Debugger::new(&machine.system.get_processor(), &machine.system.get_bus());
+        App {
+            machine,
+            debugger,
+            disassembler: Disassembler::new(),
+            running: false,
+            screen: None,
+            memory_start: 0x0000,
+            memory_address: "0000".to_string(),
+            breakpoint: String::new(),
+            command: String::new(),
+            log: vec![],
+        }
+    }
+
+    fn print(&mut self, text: &str) {
+        self.log.extend(text.lines().map(str::to_string));
+        let excess = self.log.len().saturating_sub(LOG_LINES);
+        self.log.drain(..excess);
+    }
+
+    fn resume(&mut self, mode: RunMode) {
+        match self.debugger.resume(mode) {
+            Ok(events) => {
+                for event in events {
+                    match event {
+                        DebugEvent::Stopped { reason, registers } => {
+                            self.running = false;
+                            self.print(&format!("{}: {}", reason, registers));
+                        }
+                        DebugEvent::ScriptOutput(text) => self.print(&text),
+                        DebugEvent::Stepped { .. } | DebugEvent::CyclesElapsed { .. } => (),
+                    }
+                }
+            }
+            Err(e) => {
+                self.running = false;
+                self.print(&e.to_string());
+            }
+        }
+    }
+
+    fn execute(&mut self) {
+        let line = std::mem::take(&mut self.command);
+        self.print(&format!("> {}", line));
+        match self.debugger.execute(&line) {
+            Ok(output) => self.print(&output),
+            Err(e) => self.print(&e.to_string()),
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let run = if self.running { "Pause" } else { "Run" };
+            if ui.button(run).clicked() {
+                self.running = !self.running;
+            }
+            if ui.add_enabled(!self.running, egui::Button::new("Step")).clicked() {
+                self.resume(RunMode::Step(1));
+            }
+            if ui.button("Reset").clicked() {
+                self.machine.system.reset();
+                self.running = false;
+                self.print("reset");
+            }
+            ui.separator();
+            ui.label("Breakpoint $");
+            let response = ui.add(egui::TextEdit::singleline(&mut self.breakpoint).desired_width(48.0));
+            if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                match parse_address(&self.breakpoint) {
+                    Ok(address) => {
+                        self.debugger.add_breakpoint(address);
+                        self.print(&format!("breakpoint at ${:04X}", address));
+                    }
+                    Err(e) => self.print(&e.to_string()),
+                }
+                self.breakpoint.clear();
+            }
+            let breakpoints: Vec<String> = self.debugger.get_breakpoints().iter().map(|a| format!("${:04X}", a)).collect();
+            ui.label(breakpoints.join(" "));
+        });
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        ui.heading("Registers");
+        let Ok(registers) = self.debugger.get_registers() else {
+            return;
+        };
+        ui.monospace(format!("PC ${:04X}", registers.pc));
+        ui.monospace(format!("A  ${:02X}", registers.a));
+        ui.monospace(format!("X  ${:02X}", registers.x));
+        ui.monospace(format!("Y  ${:02X}", registers.y));
+        let flags: String = "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, flag)| if registers.status & (0x80 >> i) != 0 { flag } else { '.' })
+            .collect();
+        ui.monospace(format!("P  ${:02X} {}", registers.status, flags));
+        ui.monospace(format!("{} cycles", self.machine.system.get_total_cycles()));
+
+        ui.separator();
+        ui.heading("Disassembly");
+        let bus = self.machine.system.get_bus();
+        let mut address = registers.pc;
+        for _ in 0..DISASSEMBLY_LINES {
+            let line = self.disassembler.decode(&*bus.borrow(), address);
+            let text = RichText::new(line.to_string()).monospace();
+            ui.label(if address == registers.pc { text.color(Color32::YELLOW) } else { text });
+            address = address.wrapping_add(line.len().max(1) as Address);
+        }
+    }
+
+    fn memory(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Memory");
+        ui.horizontal(|ui| {
+            ui.label("$");
+            let response = ui.add(egui::TextEdit::singleline(&mut self.memory_address).desired_width(48.0));
+            if response.changed() {
+                if let Ok(address) = parse_address(&self.memory_address) {
+                    self.memory_start = address & 0xfff0;
+                }
+            }
+            if ui.small_button("<").clicked() {
+                self.memory_start = self.memory_start.wrapping_sub((MEMORY_ROWS * 16) as Address);
+            }
+            if ui.small_button(">").clicked() {
+                self.memory_start = self.memory_start.wrapping_add((MEMORY_ROWS * 16) as Address);
+            }
+        });
+        let Ok(data) = self.debugger.read_memory(self.memory_start, MEMORY_ROWS * 16) else {
+            return;
+        };
+        for (row, bytes) in data.chunks(16).enumerate() {
+            let address = self.memory_start.wrapping_add((row * 16) as Address);
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = bytes.iter().map(|b| if b.is_ascii_graphic() { *b as char } else { '.' }).collect();
+            ui.monospace(format!("{:04X}  {}  {}", address, hex.join(" "), ascii));
+        }
+    }
+
+    fn screen(&mut self, ui: &mut egui::Ui) {
+        let rgba = self.machine.screen.borrow().to_rgba();
+        let image = ColorImage::from_rgba_unmultiplied([WIDTH, HEIGHT], &rgba);
+        let texture = self.screen.get_or_insert_with(|| {
+            ui.ctx().load_texture("screen", ColorImage::new([WIDTH, HEIGHT], Color32::BLACK), TextureOptions::NEAREST)
+        });
+        texture.set(image, TextureOptions::NEAREST);
+        let size = egui::vec2(WIDTH as f32 * SCREEN_SCALE, HEIGHT as f32 * SCREEN_SCALE);
+        ui.image((texture.id(), size));
+    }
+
+    fn console(&mut self, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical().max_height(160.0).stick_to_bottom(true).show(ui, |ui| {
+            for line in &self.log {
+                ui.monospace(line.as_str());
+            }
+        });
+        let command = egui::TextEdit::singleline(&mut self.command).desired_width(f32::INFINITY).hint_text("debugger command");
+        let response = ui.add(command);
+        if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+            self.execute();
+            response.request_focus();
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // typing goes to the machine's key at $FF unless a text box has it
+        if !ctx.wants_keyboard_input() {
+            let keys: Vec<Data> = ctx.input(|i| {
+                i.events
+                    .iter()
+                    .filter_map(|event| match event {
+                        egui::Event::Text(text) => Some(text.bytes().collect::<Vec<_>>()),
+                        egui::Event::Key { key: Key::Enter, pressed: true, .. } => Some(vec![b'\r']),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect()
+            });
+            for key in keys {
+                self.machine.io.borrow_mut().press(key);
+            }
+        }
+        if self.running {
+            self.resume(RunMode::Cycles(CYCLES_PER_FRAME));
+            ctx.request_repaint();
+        }
+
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| self.controls(ui));
+        egui::TopBottomPanel::bottom("console").show(ctx, |ui| self.console(ui));
+        egui::SidePanel::left("registers").show(ctx, |ui| self.registers(ui));
+        egui::SidePanel::right("memory").show(ctx, |ui| self.memory(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.screen(ui));
+    }
+}
+
+fn main() -> ExitCode {
+    // an easy6502 program, loaded at $0600
+    let program = match env::args().nth(1).map(fs::read) {
+        Some(Ok(program)) => program,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+        None => vec![],
+    };
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([1100.0, 720.0]),
+        ..Default::default()
+    };
+    let app = App::new(&program);
+    match eframe::run_native("sim6502", options, Box::new(|_| Ok(Box::new(app)))) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,71 @@
+/* The sim6502 C API, from src/ffi.rs. Link against the library built with --features ffi */
+
+#ifndef SIM6502_H
+#define SIM6502_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* A System with 64K of RAM, from sim6502_create */
+typedef struct Sim6502 Sim6502;
+
+typedef struct Sim6502Registers {
+  uint16_t pc;
+  uint8_t a;
+  uint8_t x;
+  uint8_t y;
+  uint8_t status;
+} Sim6502Registers;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+Sim6502 *sim6502_create(void);
+
+void sim6502_destroy(Sim6502 *machine);
+
+/* Copies len bytes into RAM at address. 0, or -1 if they'd run past $FFFF */
+int sim6502_load(Sim6502 *machine, uint16_t address, const uint8_t *data, size_t len);
+
+/* Copies a file into RAM at address, returning its length, or -1 if it can't be read or doesn't fit */
+long sim6502_load_file(Sim6502 *machine, uint16_t address, const char *path);
+
+void sim6502_set_reset_vector(Sim6502 *machine, uint16_t address);
+
+/* Also clears a fault */
+void sim6502_reset(Sim6502 *machine);
+
+/* One instruction, returning its cycles; the first is the boot sequence. 0 once halted or faulted */
+size_t sim6502_step(Sim6502 *machine);
+
+/* Whole instructions until at least this many cycles have gone by, the program breaks or the
+   machine faults, returning the cycles run */
+size_t sim6502_run(Sim6502 *machine, size_t cycles);
+
+/* What's at address, without a read's side effects on an I/O register */
+uint8_t sim6502_peek(const Sim6502 *machine, uint16_t address);
+
+void sim6502_poke(Sim6502 *machine, uint16_t address, uint8_t value);
+
+void sim6502_get_registers(const Sim6502 *machine, Sim6502Registers *registers);
+
+void sim6502_set_registers(Sim6502 *machine, const Sim6502Registers *registers);
+
+size_t sim6502_get_total_cycles(const Sim6502 *machine);
+
+/* True once the program has hit BRK, until the next reset */
+bool sim6502_is_halted(const Sim6502 *machine);
+
+/* True once the machine has panicked, as the processor does on an opcode it doesn't know, until the next reset */
+bool sim6502_is_faulted(const Sim6502 *machine);
+
+/* The version of the crate, a static NUL terminated string */
+const char *sim6502_version(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SIM6502_H */
@@ -0,0 +1,48 @@
+/* The sim6502 device plugin ABI, from src/plugin.rs. A plugin is a shared object exporting
+ * sim6502_plugin, named in a machine description:
+ *
+ *   [[device]]
+ *   type = "plugin"
+ *   base = 0xd000
+ *   path = "libmydevice.so"
+ *   options = "anything the plugin reads"
+ */
+
+#ifndef SIM6502_PLUGIN_H
+#define SIM6502_PLUGIN_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* A table with any other version is refused */
+#define SIM6502_PLUGIN_ABI_VERSION 1
+
+typedef struct Sim6502Plugin {
+  uint32_t abi_version;
+  const char *name;
+  /* One device at base, or NULL if the options are no good */
+  void *(*create)(uint16_t base, const char *options);
+  void (*destroy)(void *device);
+  /* Whether the device answers reads / writes at address */
+  bool (*is_readable_for)(void *device, uint16_t address);
+  bool (*is_writable_for)(void *device, uint16_t address);
+  uint8_t (*read)(void *device, uint16_t address);
+  void (*write)(void *device, uint16_t address, uint8_t data);
+  /* May be NULL: the cycles each instruction took, the RESET line, the IRQ output */
+  void (*tick)(void *device, uint32_t cycles);
+  void (*reset)(void *device);
+  bool (*irq_asserted)(void *device);
+} Sim6502Plugin;
+
+#ifdef __cplusplus
+extern "C" {
+#endif  // __cplusplus
+
+/* The table, which has to live as long as the library */
+const Sim6502Plugin *sim6502_plugin(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SIM6502_PLUGIN_H */
@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use rust_6502_emulator::asm::Assembler;
+use rust_6502_emulator::bus::{Address, Data};
+use rust_6502_emulator::system::{System, Watchdog};
+
+// A 6502 with 64K of RAM, as in Rust, for scripts and notebooks:
+//   import sim6502
+//   system = sim6502.System()
+//   system.load_asm(".org $0200\n LDX #5\n BRK")
+//   stop, address, cycles = system.run_until(max_cycles=1_000_000)
+//   system.x, system.read_range(0x0200, 16)
+// The System isn't Send, so each one stays on the Python thread that made it
+#[pyclass(unsendable, name = "System")]
+struct PySystem {
+    system: System,
+}
+
+#[pymethods]
+impl PySystem {
+    #[new]
+    fn new() -> Self {
+        PySystem { system: System::new() }
+    }
+
+    // bytes or a list of ints
+    fn load(&mut self, address: Address, data: Vec<Data>) {
+        self.system.load(address, &data);
+    }
+
+    // Returns the length loaded
+    fn load_file(&mut self, address: Address, path: PathBuf) -> PyResult<usize> {
+        self.system.load_file(address, path).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    // Assembles source into RAM and points the reset vector at its origin, which it returns
+    fn load_asm(&mut self, source: &str) -> PyResult<Address> {
+        let assembly = Assembler::new().assemble(source).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        assembly.load(&mut self.system);
+        self.system.set_reset_vector(assembly.get_origin());
+        Ok(assembly.get_origin())
+    }
+
+    fn set_reset_vector(&mut self, address: Address) {
+        self.system.set_reset_vector(address);
+    }
+
+    fn reset(&mut self) {
+        self.system.reset();
+    }
+
+    // One instruction, returning its cycles. The first is the boot sequence
+    fn step(&mut self) -> usize {
+        self.system.step()
+    }
+
+    // Whole instructions until at least this many cycles have gone by, returning the cycles run
+    fn run(&mut self, cycles: usize) -> usize {
+        self.system.run(cycles)
+    }
+
+    // Runs until a BRK, a trap, an unknown opcode or a limit, returning (stop, address, cycles):
+    // stop is "break", "trap", "unknown_opcode", "cycle_limit" or "timeout" and address where a
+    // trap or unknown opcode was, else None
+    #[pyo3(signature = (max_cycles=None, timeout=None))]
+    fn run_until(&mut self, max_cycles: Option<usize>, timeout: Option<f64>) -> (&'static str, Option<Address>, usize) {
+        let mut watchdog = Watchdog::new();
+        if let Some(cycles) = max_cycles {
+            watchdog = watchdog.with_max_cycles(cycles);
+        }
+        if let Some(seconds) = timeout {
+            watchdog = watchdog.with_timeout(Duration::from_secs_f64(seconds.max(0.0)));
+        }
+        let report = watchdog.run(&mut self.system);
+        (report.stop.name(), report.stop.get_address(), report.cycles)
+    }
+
+    fn read(&self, address: Address) -> Data {
+        self.system.read(address)
+    }
+
+    fn write(&self, address: Address, data: Data) {
+        self.system.write(address, data);
+    }
+
+    // length bytes from start, wrapping at $FFFF
+    fn read_range<'py>(&self, py: Python<'py>, start: Address, length: usize) -> Bound<'py, PyBytes> {
+        let data: Vec<Data> = (0..length).map(|i| self.system.read(start.wrapping_add(i as Address))).collect();
+        PyBytes::new_bound(py, &data)
+    }
+
+    #[getter]
+    fn get_pc(&self) -> Address {
+        self.system.get_registers().pc
+    }
+
+    #[setter]
+    fn set_pc(&mut self, pc: Address) {
+        let mut registers = self.system.get_registers();
+        registers.pc = pc;
+        self.system.set_registers(&registers);
+    }
+
+    #[getter]
+    fn get_a(&self) -> Data {
+        self.system.get_registers().a
+    }
+
+    #[setter]
+    fn set_a(&mut self, a: Data) {
+        let mut registers = self.system.get_registers();
+        registers.a = a;
+        self.system.set_registers(&registers);
+    }
+
+    #[getter]
+    fn get_x(&self) -> Data {
+        self.system.get_registers().x
+    }
+
+    #[setter]
+    fn set_x(&mut self, x: Data) {
+        let mut registers = self.system.get_registers();
+        registers.x = x;
+        self.system.set_registers(&registers);
+    }
+
+    #[getter]
+    fn get_y(&self) -> Data {
+        self.system.get_registers().y
+    }
+
+    #[setter]
+    fn set_y(&mut self, y: Data) {
+        let mut registers = self.system.get_registers();
+        registers.y = y;
+        self.system.set_registers(&registers);
+    }
+
+    #[getter]
+    fn get_status(&self) -> Data {
+        self.system.get_registers().status
+    }
+
+    #[setter]
+    fn set_status(&mut self, status: Data) {
+        let mut registers = self.system.get_registers();
+        registers.status = status;
+        self.system.set_registers(&registers);
+    }
+
+    #[getter]
+    fn get_total_cycles(&self) -> usize {
+        self.system.get_total_cycles()
+    }
+
+    #[getter]
+    fn get_halted(&self) -> bool {
+        self.system.is_halted()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<System {}>", self.system.get_registers())
+    }
+}
+
+#[pymodule]
+fn sim6502(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PySystem>()?;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+import sim6502
+
+
+def test_load_and_read():
+    system = sim6502.System()
+    system.load(0x0200, b"\xa9\x42")
+    system.write(0x0300, 0x99)
+    assert system.read(0x0201) == 0x42
+    assert system.read_range(0x0200, 2) == b"\xa9\x42"
+    assert system.read_range(0xFFFF, 2) == bytes([system.read(0xFFFF), system.read(0x0000)])
+    assert system.read(0x0300) == 0x99
+
+
+def test_registers():
+    system = sim6502.System()
+    system.pc = 0x1234
+    system.a = 1
+    system.x = 2
+    system.y = 3
+    assert (system.pc, system.a, system.x, system.y) == (0x1234, 1, 2, 3)
+    assert "1234" in repr(system)
+
+
+def test_run_until():
+    system = sim6502.System()
+    assert system.load_asm(".org $0200\n NOP\n NOP\n BRK") == 0x0200
+    stop, address, cycles = system.run_until(max_cycles=1000)
+    assert (stop, address) == ("break", None)
+    assert system.pc == 0x0202
+    # the boot sequence comes first and isn't counted
+    assert 0 < cycles < system.total_cycles
+
+
+def test_bad_source():
+    import pytest
+
+    with pytest.raises(ValueError):
+        sim6502.System().load_asm(" LDA #")
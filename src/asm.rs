@@ -0,0 +1,685 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::bus::{Address, Data};
+use crate::processor::AddressingMode::{self, *};
+use crate::processor::DataRegister::{self, X, Y};
+use crate::system::System;
+
+mod expr;
+mod harness;
+pub(crate) mod opcodes;
+mod preprocess;
+
+use expr::{Expr, Scope};
+use opcodes::{is_mnemonic, opcode};
+use preprocess::Preprocessor;
+
+pub use harness::{run_asm, AsmRun, AsmTest, RunEnd};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    // None for source passed as a string
+    pub file: Option<PathBuf>,
+    // 1 based
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} line {}: {}", file.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+impl From<AsmError> for io::Error {
+    fn from(e: AsmError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+// The operand as written, before knowing which addressing mode it assembles to
+#[derive(Debug)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr),
+    Indexed(Expr, DataRegister),
+    Indirect(Expr),
+    IndexedIndirect(Expr),
+    IndirectIndexed(Expr),
+}
+
+#[derive(Debug)]
+enum ByteArg {
+    Value(Expr),
+    Text(Vec<Data>),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Assign(String, Expr),
+    Org(Expr),
+    Byte(Vec<ByteArg>),
+    Word(Vec<Expr>),
+    Instruction { mnemonic: String, operand: Operand },
+    If(Expr),
+    // the name, and whether it should be defined (.ifdef) or not (.ifndef)
+    IfDef(String, bool),
+    Else,
+    EndIf,
+}
+
+struct Line {
+    file: Option<Rc<Path>>,
+    number: usize,
+    label: Option<String>,
+    statement: Option<Statement>,
+}
+
+// The output of a successful assembly: the bytes of each .org'd block and the value of
+// every label and constant
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Assembly {
+    segments: Vec<(Address, Vec<Data>)>,
+    symbols: BTreeMap<String, Address>,
+}
+
+impl Assembly {
+    pub fn get_segments(&self) -> &[(Address, Vec<Data>)] {
+        &self.segments
+    }
+
+    pub fn get_symbols(&self) -> &BTreeMap<String, Address> {
+        &self.symbols
+    }
+
+    pub fn get_symbol(&self, name: &str) -> Option<Address> {
+        self.symbols.get(name).copied()
+    }
+
+    // Where the lowest block starts
+    pub fn get_origin(&self) -> Address {
+        self.segments.iter().map(|(address, _)| *address).min().unwrap_or(0)
+    }
+
+    // A single image from the origin to the end of the highest block, gaps filled with zeroes
+    pub fn to_binary(&self) -> Vec<Data> {
+        let origin = self.get_origin() as usize;
+        let end = self.segments.iter().map(|(a, d)| *a as usize + d.len()).max().unwrap_or(origin);
+        let mut image = vec![0; end - origin];
+        for (address, data) in &self.segments {
+            let start = *address as usize - origin;
+            image[start..start + data.len()].copy_from_slice(data);
+        }
+        image
+    }
+
+    pub fn load(&self, system: &mut System) {
+        for (address, data) in &self.segments {
+            system.load(*address, data);
+        }
+    }
+
+    // VICE style "al C000 .name" lines, which the debugger's symbols command reads
+    pub fn label_file(&self) -> String {
+        self.symbols.iter().map(|(name, address)| format!("al {:06X} .{}\n", address, name)).collect()
+    }
+}
+
+// A two pass assembler for 6502 source. Each line is an optional label ("name:", or a name
+// in the first column) followed by an instruction or directive; ; starts a comment.
+// - instructions take the usual operands: #imm, zp, abs, zp,X, abs,Y, (zp,X), (zp),Y, (abs), A
+// - .org / *= set the address, .byte / .db take values and "strings", .word / .dw little endian words
+// - name = expr (or .equ) defines a constant
+// - expressions: $hex, %binary, decimal, 'c', symbols, * for the current address, + - * / %
+//   & | ^ << >>, comparisons (= == != < > <= >=, giving 1 or 0), parentheses, and unary - ~
+//   < (low byte) > (high byte)
+// - .include "file" reads a file relative to the including one, or from an include directory
+// - .macro name [param, ...] ... .endmacro defines a macro, called as "name arg, ...". The body
+//   has the parameters replaced by the arguments and \@ by a number unique to the call, for labels
+// - .if expr, .ifdef name, .ifndef name, .else and .endif assemble lines conditionally. The
+//   condition is taken on the first pass, so it can only use symbols defined above it
+// Operands that are still undefined on the first pass (forward references) are assumed to be
+// absolute addresses, so zero page variables should be defined before they're used.
+#[derive(Default)]
+pub struct Assembler {
+    // where code goes before any .org, 0 by default
+    origin: Address,
+    include_dirs: Vec<PathBuf>,
+    defines: Vec<(String, i64)>,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::default()
+    }
+
+    pub fn with_origin(mut self, origin: Address) -> Assembler {
+        self.origin = origin;
+        self
+    }
+
+    // Searched for .include files after the including file's own directory
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Assembler {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    // A symbol defined before the source starts, e.g. for .ifdef
+    pub fn with_define(mut self, name: &str, value: i64) -> Assembler {
+        self.defines.push((name.to_string(), value));
+        self
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Assembly, AsmError> {
+        self.assemble_source(source, None)
+    }
+
+    pub fn assemble_file(&self, path: impl AsRef<Path>) -> io::Result<Assembly> {
+        let path = path.as_ref();
+        Ok(self.assemble_source(&fs::read_to_string(path)?, Some(Rc::from(path)))?)
+    }
+
+    fn assemble_source(&self, source: &str, file: Option<Rc<Path>>) -> Result<Assembly, AsmError> {
+        let lines = Preprocessor::new(&self.include_dirs)
+            .expand(source, file)?
+            .into_iter()
+            .map(|source_line| {
+                let error = |message| AsmError {
+                    file: source_line.file.as_deref().map(Path::to_path_buf),
+                    line: source_line.number,
+                    message,
+                };
+                let line = parse_line(&source_line.text).map_err(error)?;
+                Ok(Line {
+                    file: source_line.file,
+                    number: source_line.number,
+                    ..line
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut pass = Pass {
+            pc: self.origin as i64,
+            symbols: self.defines.iter().cloned().collect(),
+            ..Pass::default()
+        };
+        pass.run(&lines, false)?;
+        pass.pc = self.origin as i64;
+        pass.run(&lines, true)?;
+        let symbols = pass
+            .symbols
+            .iter()
+            .filter(|(_, value)| (0..=0xffff).contains(*value))
+            .map(|(name, value)| (name.clone(), *value as Address))
+            .collect();
+        Ok(Assembly {
+            segments: pass.segments,
+            symbols,
+        })
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    Assembler::new().assemble(source)
+}
+
+#[derive(Default)]
+struct Pass {
+    pc: i64,
+    symbols: HashMap<String, i64>,
+    // the addressing mode picked for each instruction on the first pass, by statement, so the
+    // second pass makes the same sizes
+    modes: HashMap<usize, AddressingMode>,
+    // likewise each condition taken
+    conditions: HashMap<usize, bool>,
+    // whether each enclosing .if's lines are being assembled
+    nesting: Vec<bool>,
+    segments: Vec<(Address, Vec<Data>)>,
+}
+
+impl Pass {
+    fn run(&mut self, lines: &[Line], last: bool) -> Result<(), AsmError> {
+        for (index, line) in lines.iter().enumerate() {
+            self.line(index, line, last).map_err(|message| AsmError {
+                file: line.file.as_deref().map(Path::to_path_buf),
+                line: line.number,
+                message,
+            })?;
+        }
+        match lines.last() {
+            Some(line) if !self.nesting.is_empty() => Err(AsmError {
+                file: line.file.as_deref().map(Path::to_path_buf),
+                line: line.number,
+                message: ".if is missing .endif".to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn conditional(&mut self, index: usize, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Else => {
+                let taken = self.nesting.last_mut().ok_or(".else without .if")?;
+                *taken = !*taken;
+            }
+            Statement::EndIf => {
+                self.nesting.pop().ok_or(".endif without .if")?;
+            }
+            _ => {
+                let active = self.nesting.iter().all(|taken| *taken);
+                let taken = match (active, self.conditions.get(&index), statement) {
+                    (false, _, _) => false,
+                    (true, Some(taken), _) => *taken,
+                    (true, None, Statement::IfDef(name, defined)) => self.symbols.contains_key(name) == *defined,
+                    (true, None, Statement::If(expr)) => self.eval(expr, true)?.unwrap_or_default() != 0,
+                    _ => unreachable!(),
+                };
+                self.conditions.insert(index, taken);
+                self.nesting.push(taken);
+            }
+        }
+        Ok(())
+    }
+
+    // Undefined symbols are only an error on the last pass
+    fn eval(&self, expr: &Expr, last: bool) -> Result<Option<i64>, String> {
+        let scope = Scope {
+            symbols: &self.symbols,
+            pc: self.pc,
+        };
+        match expr.eval(&scope)? {
+            None if last => {
+                let missing = expr.symbols().into_iter().find(|s| !self.symbols.contains_key(*s)).unwrap_or_default();
+                Err(format!("undefined symbol '{}'", missing))
+            }
+            value => Ok(value),
+        }
+    }
+
+    fn define(&mut self, name: &str, value: i64, last: bool) -> Result<(), String> {
+        match self.symbols.insert(name.to_string(), value) {
+            // the second pass sees the first pass's definitions again
+            Some(old) if !last || old != value => Err(format!("'{}' is already defined", name)),
+            _ => Ok(()),
+        }
+    }
+
+    fn line(&mut self, index: usize, line: &Line, last: bool) -> Result<(), String> {
+        if let Some(statement @ (Statement::If(_) | Statement::IfDef(..) | Statement::Else | Statement::EndIf)) = &line.statement {
+            return self.conditional(index, statement);
+        }
+        if !self.nesting.iter().all(|taken| *taken) {
+            return Ok(());
+        }
+        if let Some(label) = &line.label {
+            self.define(label, self.pc, last)?;
+        }
+        match &line.statement {
+            None => (),
+            Some(Statement::Assign(name, expr)) => {
+                if let Some(value) = self.eval(expr, last)? {
+                    self.define(name, value, last)?;
+                }
+            }
+            Some(Statement::Org(expr)) => {
+                let address = self.eval(expr, true)?.unwrap_or_default();
+                if !(0..=0xffff).contains(&address) {
+                    return Err(format!("origin ${:X} is outside memory", address));
+                }
+                self.pc = address;
+            }
+            Some(Statement::Byte(args)) => {
+                for arg in args {
+                    match arg {
+                        ByteArg::Value(expr) => {
+                            let value = self.eval(expr, last)?.unwrap_or_default();
+                            self.emit(&[to_byte(value)?], last)?;
+                        }
+                        ByteArg::Text(text) => self.emit(text, last)?,
+                    }
+                }
+            }
+            Some(Statement::Word(args)) => {
+                for expr in args {
+                    let value = to_word(self.eval(expr, last)?.unwrap_or_default())?;
+                    self.emit(&[(value & 0xff) as Data, (value >> 8) as Data], last)?;
+                }
+            }
+            Some(Statement::Instruction { mnemonic, operand }) => self.instruction(index, mnemonic, operand, last)?,
+            Some(_) => (),
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, index: usize, mnemonic: &str, operand: &Operand, last: bool) -> Result<(), String> {
+        let mode = match self.modes.get(&index) {
+            Some(mode) => *mode,
+            None => {
+                let mode = self.pick_mode(mnemonic, operand)?;
+                self.modes.insert(index, mode);
+                mode
+            }
+        };
+        let opcode = opcode(mnemonic, &mode).ok_or_else(|| format!("{} can't take that operand", mnemonic))?;
+        let value = match operand {
+            Operand::None | Operand::Accumulator => 0,
+            Operand::Immediate(e)
+            | Operand::Direct(e)
+            | Operand::Indexed(e, _)
+            | Operand::Indirect(e)
+            | Operand::IndexedIndirect(e)
+            | Operand::IndirectIndexed(e) => self.eval(e, last)?.unwrap_or_default(),
+        };
+        let mut bytes = vec![opcode];
+        match mode {
+            Implied | Accumulator => (),
+            Relative => {
+                let offset = if last { value - (self.pc + 2) } else { 0 };
+                if !(-128..=127).contains(&offset) {
+                    return Err(format!("branch to ${:04X} is out of range", value));
+                }
+                bytes.push(offset as Data);
+            }
+            Immediate => bytes.push(to_byte(value)?),
+            ZeroPage | ZeroPageIndexed { .. } | IndexedIndirect | IndirectIndexed => {
+                if last && !(0..=0xff).contains(&value) {
+                    return Err(format!("${:X} isn't a zero page address", value));
+                }
+                bytes.push(value as Data);
+            }
+            Absolute | AbsIndexed { .. } | Indirect => {
+                let value = to_word(value)?;
+                bytes.extend([(value & 0xff) as Data, (value >> 8) as Data]);
+            }
+        }
+        self.emit(&bytes, last)
+    }
+
+    // Zero page when the value is already known to fit and the instruction has the mode,
+    // absolute otherwise
+    fn pick_mode(&self, mnemonic: &str, operand: &Operand) -> Result<AddressingMode, String> {
+        let has = |mode: &AddressingMode| opcode(mnemonic, mode).is_some();
+        let small = |e: &Expr| matches!(self.eval(e, false), Ok(Some(v)) if (0..=0xff).contains(&v));
+        let mode = match operand {
+            Operand::None if has(&Implied) => Implied,
+            Operand::None => Accumulator,
+            Operand::Accumulator => Accumulator,
+            Operand::Immediate(_) => Immediate,
+            Operand::Direct(_) if has(&Relative) => Relative,
+            Operand::Direct(e) if small(e) && has(&ZeroPage) => ZeroPage,
+            Operand::Direct(_) => Absolute,
+            Operand::Indexed(e, reg) => {
+                let zero_page = ZeroPageIndexed { reg: *reg };
+                let absolute = AbsIndexed { reg: *reg };
+                if (small(e) && has(&zero_page)) || !has(&absolute) {
+                    zero_page
+                } else {
+                    absolute
+                }
+            }
+            // a parenthesised expression, for anything but JMP
+            Operand::Indirect(_) if !has(&Indirect) && has(&Relative) => Relative,
+            Operand::Indirect(e) if !has(&Indirect) && small(e) && has(&ZeroPage) => ZeroPage,
+            Operand::Indirect(_) if !has(&Indirect) => Absolute,
+            Operand::Indirect(_) => Indirect,
+            Operand::IndexedIndirect(_) => IndexedIndirect,
+            Operand::IndirectIndexed(_) => IndirectIndexed,
+        };
+        if has(&mode) {
+            Ok(mode)
+        } else {
+            Err(format!("{} can't take that operand", mnemonic.to_ascii_uppercase()))
+        }
+    }
+
+    fn emit(&mut self, bytes: &[Data], last: bool) -> Result<(), String> {
+        if self.pc + bytes.len() as i64 > 0x10000 {
+            return Err("assembled past $FFFF".to_string());
+        }
+        if last {
+            let pc = self.pc as usize;
+            match self.segments.last_mut() {
+                Some((start, data)) if *start as usize + data.len() == pc => data.extend_from_slice(bytes),
+                _ => self.segments.push((self.pc as Address, bytes.to_vec())),
+            }
+        }
+        self.pc += bytes.len() as i64;
+        Ok(())
+    }
+}
+
+// Negative values as two's complement
+fn to_byte(value: i64) -> Result<Data, String> {
+    if (-0x80..=0xff).contains(&value) {
+        Ok(value as Data)
+    } else {
+        Err(format!("${:X} doesn't fit in a byte", value))
+    }
+}
+
+fn to_word(value: i64) -> Result<Address, String> {
+    if (-0x8000..=0xffff).contains(&value) {
+        Ok(value as Address)
+    } else {
+        Err(format!("${:X} doesn't fit in a word", value))
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// The line up to a ; that isn't inside quotes
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            (';', None) => return &text[..i],
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            _ => (),
+        }
+    }
+    text
+}
+
+// Splits on commas outside quotes and parentheses
+fn split_args(text: &str) -> Vec<&str> {
+    let mut args = vec![];
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('(', None) => depth += 1,
+            (')', None) => depth -= 1,
+            (',', None) if depth == 0 => {
+                args.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    args.push(text[start..].trim());
+    args
+}
+
+// The leading label, "name:" or a name in the first column followed by a statement, and the
+// rest of the code
+fn split_label<'a>(code: &'a str, starts_statement: &dyn Fn(&str) -> bool) -> (Option<&'a str>, &'a str) {
+    let rest = code.trim();
+    let first = rest.split(|c: char| c.is_whitespace() || c == ':' || c == '=').next().unwrap_or("");
+    let after = rest[first.len()..].trim_start();
+    if is_name(first) && after.starts_with(':') {
+        (Some(first), after[1..].trim_start())
+    } else if is_name(first) && !code.starts_with(char::is_whitespace) && !starts_statement(first) && starts_statement(after) {
+        (Some(first), after)
+    } else {
+        (None, rest)
+    }
+}
+
+fn parse_line(text: &str) -> Result<Line, String> {
+    let code = strip_comment(text);
+    let (label, rest) = split_label(code, &starts_statement);
+    let first = rest.split(|c: char| c.is_whitespace() || c == '=').next().unwrap_or("");
+    let after = rest[first.len()..].trim_start();
+    if label.is_none() && is_name(first) && (after.starts_with('=') || starts_with_word(after, ".equ") || starts_with_word(after, "equ")) {
+        let value = after.trim_start_matches('=').trim_start();
+        let value = strip_word(value, ".equ").or_else(|| strip_word(value, "equ")).unwrap_or(value);
+        return Ok(Line {
+            number: 0,
+            file: None,
+            label: None,
+            statement: Some(Statement::Assign(first.to_string(), Expr::parse(value)?)),
+        });
+    }
+    let statement = if rest.is_empty() { None } else { Some(parse_statement(rest)?) };
+    Ok(Line {
+        number: 0,
+        file: None,
+        label: label.map(str::to_string),
+        statement,
+    })
+}
+
+fn starts_statement(text: &str) -> bool {
+    let word = text.split_whitespace().next().unwrap_or("");
+    let directive = word.starts_with('.') && !word.eq_ignore_ascii_case(".equ");
+    word.is_empty() || directive || word.starts_with("*=") || is_mnemonic(word)
+}
+
+fn starts_with_word(text: &str, word: &str) -> bool {
+    strip_word(text, word).is_some()
+}
+
+// The text after a leading word, case insensitively
+fn strip_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let head = text.get(..word.len())?;
+    let tail = &text[word.len()..];
+    if head.eq_ignore_ascii_case(word) && (tail.is_empty() || tail.starts_with(char::is_whitespace)) {
+        Some(tail.trim_start())
+    } else {
+        None
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    if let Some(value) = text.strip_prefix("*=") {
+        return Ok(Statement::Org(Expr::parse(value)?));
+    }
+    let (word, args) = match text.split_once(char::is_whitespace) {
+        Some((word, args)) => (word, args.trim()),
+        None => (text, ""),
+    };
+    match word.to_ascii_lowercase().as_str() {
+        ".org" => Ok(Statement::Org(Expr::parse(args)?)),
+        ".byte" | ".db" => Ok(Statement::Byte(split_args(args).into_iter().map(parse_byte_arg).collect::<Result<_, _>>()?)),
+        ".word" | ".dw" => Ok(Statement::Word(split_args(args).into_iter().map(Expr::parse).collect::<Result<_, _>>()?)),
+        ".if" => Ok(Statement::If(Expr::parse(args)?)),
+        ".ifdef" | ".ifndef" if is_name(args) => Ok(Statement::IfDef(args.to_string(), word.eq_ignore_ascii_case(".ifdef"))),
+        ".ifdef" | ".ifndef" => Err(format!("expected a name after {}", word)),
+        ".else" => Ok(Statement::Else),
+        ".endif" => Ok(Statement::EndIf),
+        _ if word.starts_with('.') => Err(format!("unknown directive {}", word)),
+        _ if is_mnemonic(word) => Ok(Statement::Instruction {
+            mnemonic: word.to_ascii_uppercase(),
+            operand: parse_operand(word, args)?,
+        }),
+        _ => Err(format!("unknown instruction {}", word)),
+    }
+}
+
+fn parse_byte_arg(text: &str) -> Result<ByteArg, String> {
+    match text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(inner) => {
+            let mut bytes = vec![];
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                let c = match c {
+                    '\\' => match chars.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some(c) => c,
+                        None => '\\',
+                    },
+                    c => c,
+                };
+                if !c.is_ascii() {
+                    return Err(format!("'{}' isn't ASCII", c));
+                }
+                bytes.push(c as Data);
+            }
+            Ok(ByteArg::Text(bytes))
+        }
+        None => Ok(ByteArg::Value(Expr::parse(text)?)),
+    }
+}
+
+fn parse_operand(mnemonic: &str, text: &str) -> Result<Operand, String> {
+    let compact = squeeze(text);
+    let upper = compact.to_ascii_uppercase();
+    let inner = |trim: usize| Expr::parse(&compact[1..compact.len() - trim]);
+    Ok(if compact.is_empty() {
+        Operand::None
+    } else if upper == "A" && opcode(mnemonic, &Accumulator).is_some() {
+        Operand::Accumulator
+    } else if let Some(value) = compact.strip_prefix('#') {
+        Operand::Immediate(Expr::parse(value)?)
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        Operand::IndexedIndirect(inner(3)?)
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        Operand::IndirectIndexed(inner(3)?)
+    } else if upper.ends_with(",X") || upper.ends_with(",Y") {
+        let reg = if upper.ends_with('X') { X } else { Y };
+        Operand::Indexed(Expr::parse(&compact[..compact.len() - 2])?, reg)
+    } else if compact.starts_with('(') && closing_paren(&compact) == Some(compact.len() - 1) {
+        Operand::Indirect(inner(1)?)
+    } else {
+        Operand::Direct(Expr::parse(&compact)?)
+    })
+}
+
+// Without whitespace, other than in quotes
+fn squeeze(text: &str) -> String {
+    let mut quote = None;
+    let mut squeezed = String::new();
+    for c in text.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => continue,
+            _ => (),
+        }
+        squeezed.push(c);
+    }
+    squeezed
+}
+
+// Where the parenthesis opening text closes
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some(i),
+            ')' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
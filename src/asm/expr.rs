@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+// An operand or directive argument, parsed once and evaluated on each pass
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    // *, the address the line is assembled at
+    Pc,
+    Unary(char, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+// Lowest precedence first
+const BINARY: [&[&str]; 7] = [
+    &["==", "!=", "<=", ">=", "=", "<", ">"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+// What names evaluate to, and where the line is
+pub struct Scope<'a> {
+    pub symbols: &'a HashMap<String, i64>,
+    pub pc: i64,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let expr = parser.binary(0)?;
+        parser.skip_space();
+        if parser.pos < text.len() {
+            return Err(format!("unexpected '{}' in '{}'", &text[parser.pos..], text));
+        }
+        Ok(expr)
+    }
+
+    // None while a symbol isn't defined yet, e.g. a label further on during the first pass
+    pub fn eval(&self, scope: &Scope) -> Result<Option<i64>, String> {
+        Ok(match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Symbol(name) => scope.symbols.get(name).copied(),
+            Expr::Pc => Some(scope.pc),
+            Expr::Unary(op, e) => e.eval(scope)?.map(|v| match op {
+                '-' => -v,
+                '~' => !v,
+                '<' => v & 0xff,
+                _ => (v >> 8) & 0xff,
+            }),
+            Expr::Binary(op, l, r) => match (l.eval(scope)?, r.eval(scope)?) {
+                (Some(l), Some(r)) => Some(binary(op, l, r)?),
+                _ => None,
+            },
+        })
+    }
+
+    // The symbols it refers to
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expr::Symbol(name) => vec![name.as_str()],
+            Expr::Unary(_, e) => e.symbols(),
+            Expr::Binary(_, l, r) => [l.symbols(), r.symbols()].concat(),
+            _ => vec![],
+        }
+    }
+}
+
+fn binary(op: &str, l: i64, r: i64) -> Result<i64, String> {
+    Ok(match op {
+        "==" | "=" => (l == r) as i64,
+        "!=" => (l != r) as i64,
+        "<=" => (l <= r) as i64,
+        ">=" => (l >= r) as i64,
+        "<" => (l < r) as i64,
+        ">" => (l > r) as i64,
+        "|" => l | r,
+        "^" => l ^ r,
+        "&" => l & r,
+        "<<" => l.checked_shl(r as u32).unwrap_or(0),
+        ">>" => l.checked_shr(r as u32).unwrap_or(0),
+        "+" => l.wrapping_add(r),
+        "-" => l.wrapping_sub(r),
+        "*" => l.wrapping_mul(r),
+        _ if r == 0 => return Err("division by zero".to_string()),
+        "/" => l / r,
+        _ => l % r,
+    })
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        self.pos = self.text.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'more: loop {
+            for op in BINARY[level] {
+                if self.eat(op) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                    continue 'more;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        for op in ['-', '~', '<', '>'] {
+            if self.eat(&op.to_string()) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        self.skip_space();
+        if self.eat("(") {
+            let expr = self.binary(0)?;
+            return if self.eat(")") { Ok(expr) } else { Err(format!("missing ) in '{}'", self.text)) };
+        }
+        if self.eat("*") {
+            return Ok(Expr::Pc);
+        }
+        let rest = self.rest();
+        if let Some(quoted) = rest.strip_prefix('\'') {
+            let c = quoted.chars().next().ok_or_else(|| format!("bad character in '{}'", self.text))?;
+            self.pos += 1 + c.len_utf8();
+            self.eat("'");
+            return Ok(Expr::Number(c as i64));
+        }
+        let (radix, digits) = match rest.chars().next() {
+            Some('$') => (16, &rest[1..]),
+            Some('%') => (2, &rest[1..]),
+            _ if rest.starts_with("0x") => (16, &rest[2..]),
+            _ => (10, rest),
+        };
+        let word_len = digits.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(digits.len());
+        let word = &digits[..word_len];
+        if word.is_empty() {
+            return Err(format!("expected a value in '{}'", self.text));
+        }
+        self.pos += rest.len() - digits.len() + word_len;
+        if radix == 10 && !word.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(Expr::Symbol(word.to_string()));
+        }
+        i64::from_str_radix(word, radix).map(Expr::Number).map_err(|_| format!("bad number '{}'", word))
+    }
+}
@@ -0,0 +1,124 @@
+use crate::asm::{AsmError, Assembler, Assembly};
+use crate::bus::{Address, Data};
+use crate::processor::{create_instruction_table, Registers};
+use crate::system::System;
+
+// How an AsmTest run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunEnd {
+    // about to execute BRK, which is left unexecuted
+    Break,
+    // an opcode the processor doesn't have, at this address
+    UnknownOpcode(Address),
+    // an instruction that jumps or branches to itself, the usual way test suites stop
+    Trap(Address),
+    CycleLimit,
+}
+
+// Assembles a snippet, runs it in a fresh 64K System until it reaches a BRK, traps or runs out
+// of cycles, and keeps the System for looking at afterwards:
+//   let run = run_asm("LDX #5\n LDA #$AA\n STA $01,X\n BRK");
+//   assert_eq!(run.read(0x06), 0xaa);
+// Source without an .org starts at $0200, and runs from its start label if it has one
+pub struct AsmTest {
+    source: String,
+    assembler: Assembler,
+    max_cycles: usize,
+}
+
+impl AsmTest {
+    pub fn new(source: &str) -> AsmTest {
+        AsmTest {
+            source: source.to_string(),
+            assembler: Assembler::new().with_origin(0x0200),
+            max_cycles: 1_000_000,
+        }
+    }
+
+    pub fn with_max_cycles(mut self, cycles: usize) -> AsmTest {
+        self.max_cycles = cycles;
+        self
+    }
+
+    // e.g. with_assembler(Assembler::new().with_origin(0x0200).with_define("DEBUG", 1))
+    pub fn with_assembler(mut self, assembler: Assembler) -> AsmTest {
+        self.assembler = assembler;
+        self
+    }
+
+    pub fn run(&self) -> Result<AsmRun, AsmError> {
+        let assembly = self.assembler.assemble(&self.source)?;
+        let mut system = System::new();
+        assembly.load(&mut system);
+        let entry = assembly
+            .get_symbol("start")
+            .or_else(|| assembly.get_segments().first().map(|(address, _)| *address))
+            .unwrap_or(0x0200);
+        system.set_reset_vector(entry);
+        // the boot sequence, leaving the pc at the entry point
+        system.step();
+        let start_cycles = system.get_total_cycles();
+        let instructions = create_instruction_table();
+        let end = loop {
+            let pc = system.get_registers().pc;
+            match system.read(pc) {
+                0x00 => break RunEnd::Break,
+                opcode if !instructions.contains(opcode) => break RunEnd::UnknownOpcode(pc),
+                _ => (),
+            }
+            system.step();
+            if system.get_registers().pc == pc {
+                break RunEnd::Trap(pc);
+            }
+            if system.get_total_cycles() - start_cycles >= self.max_cycles {
+                break RunEnd::CycleLimit;
+            }
+        };
+        Ok(AsmRun {
+            registers: system.get_registers(),
+            cycles: system.get_total_cycles() - start_cycles,
+            end,
+            assembly,
+            system,
+        })
+    }
+}
+
+// Runs source that's expected to assemble, panicking with the error if it doesn't
+pub fn run_asm(source: &str) -> AsmRun {
+    AsmTest::new(source).run().unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub struct AsmRun {
+    pub registers: Registers,
+    // from the entry point on
+    pub cycles: usize,
+    pub end: RunEnd,
+    pub assembly: Assembly,
+    pub system: System,
+}
+
+impl AsmRun {
+    pub fn read(&self, address: Address) -> Data {
+        self.system.read(address)
+    }
+
+    // Little endian
+    pub fn read_word(&self, address: Address) -> Address {
+        self.read(address) as Address | (self.read(address.wrapping_add(1)) as Address) << 8
+    }
+
+    pub fn read_range(&self, address: Address, length: usize) -> Vec<Data> {
+        (0..length).map(|i| self.read(address.wrapping_add(i as Address))).collect()
+    }
+
+    // The value of a label or constant, panicking if the source doesn't define it
+    pub fn symbol(&self, name: &str) -> Address {
+        self.assembly.get_symbol(name).unwrap_or_else(|| panic!("no symbol {}", name))
+    }
+
+    // What's at a label
+    pub fn read_symbol(&self, name: &str) -> Data {
+        self.read(self.symbol(name))
+    }
+}
@@ -0,0 +1,91 @@
+use crate::processor::AddressingMode::{self, *};
+use crate::processor::DataRegister::{X, Y};
+
+// Every documented NMOS 6502 opcode. The assembler keeps its own table rather than the
+// processor's, which only has the instructions emulated so far
+const OPCODES: &[(&str, AddressingMode, u8)] = &[
+    ("ADC", Immediate, 0x69), ("ADC", ZeroPage, 0x65), ("ADC", ZeroPageIndexed { reg: X }, 0x75),
+    ("ADC", Absolute, 0x6d), ("ADC", AbsIndexed { reg: X }, 0x7d), ("ADC", AbsIndexed { reg: Y }, 0x79),
+    ("ADC", IndexedIndirect, 0x61), ("ADC", IndirectIndexed, 0x71),
+    ("AND", Immediate, 0x29), ("AND", ZeroPage, 0x25), ("AND", ZeroPageIndexed { reg: X }, 0x35),
+    ("AND", Absolute, 0x2d), ("AND", AbsIndexed { reg: X }, 0x3d), ("AND", AbsIndexed { reg: Y }, 0x39),
+    ("AND", IndexedIndirect, 0x21), ("AND", IndirectIndexed, 0x31),
+    ("ASL", Accumulator, 0x0a), ("ASL", ZeroPage, 0x06), ("ASL", ZeroPageIndexed { reg: X }, 0x16),
+    ("ASL", Absolute, 0x0e), ("ASL", AbsIndexed { reg: X }, 0x1e),
+    ("BCC", Relative, 0x90), ("BCS", Relative, 0xb0), ("BEQ", Relative, 0xf0), ("BMI", Relative, 0x30),
+    ("BNE", Relative, 0xd0), ("BPL", Relative, 0x10), ("BVC", Relative, 0x50), ("BVS", Relative, 0x70),
+    ("BIT", ZeroPage, 0x24), ("BIT", Absolute, 0x2c),
+    ("BRK", Implied, 0x00),
+    ("CLC", Implied, 0x18), ("CLD", Implied, 0xd8), ("CLI", Implied, 0x58), ("CLV", Implied, 0xb8),
+    ("CMP", Immediate, 0xc9), ("CMP", ZeroPage, 0xc5), ("CMP", ZeroPageIndexed { reg: X }, 0xd5),
+    ("CMP", Absolute, 0xcd), ("CMP", AbsIndexed { reg: X }, 0xdd), ("CMP", AbsIndexed { reg: Y }, 0xd9),
+    ("CMP", IndexedIndirect, 0xc1), ("CMP", IndirectIndexed, 0xd1),
+    ("CPX", Immediate, 0xe0), ("CPX", ZeroPage, 0xe4), ("CPX", Absolute, 0xec),
+    ("CPY", Immediate, 0xc0), ("CPY", ZeroPage, 0xc4), ("CPY", Absolute, 0xcc),
+    ("DEC", ZeroPage, 0xc6), ("DEC", ZeroPageIndexed { reg: X }, 0xd6),
+    ("DEC", Absolute, 0xce), ("DEC", AbsIndexed { reg: X }, 0xde),
+    ("DEX", Implied, 0xca), ("DEY", Implied, 0x88),
+    ("EOR", Immediate, 0x49), ("EOR", ZeroPage, 0x45), ("EOR", ZeroPageIndexed { reg: X }, 0x55),
+    ("EOR", Absolute, 0x4d), ("EOR", AbsIndexed { reg: X }, 0x5d), ("EOR", AbsIndexed { reg: Y }, 0x59),
+    ("EOR", IndexedIndirect, 0x41), ("EOR", IndirectIndexed, 0x51),
+    ("INC", ZeroPage, 0xe6), ("INC", ZeroPageIndexed { reg: X }, 0xf6),
+    ("INC", Absolute, 0xee), ("INC", AbsIndexed { reg: X }, 0xfe),
+    ("INX", Implied, 0xe8), ("INY", Implied, 0xc8),
+    ("JMP", Absolute, 0x4c), ("JMP", Indirect, 0x6c),
+    ("JSR", Absolute, 0x20),
+    ("LDA", Immediate, 0xa9), ("LDA", ZeroPage, 0xa5), ("LDA", ZeroPageIndexed { reg: X }, 0xb5),
+    ("LDA", Absolute, 0xad), ("LDA", AbsIndexed { reg: X }, 0xbd), ("LDA", AbsIndexed { reg: Y }, 0xb9),
+    ("LDA", IndexedIndirect, 0xa1), ("LDA", IndirectIndexed, 0xb1),
+    ("LDX", Immediate, 0xa2), ("LDX", ZeroPage, 0xa6), ("LDX", ZeroPageIndexed { reg: Y }, 0xb6),
+    ("LDX", Absolute, 0xae), ("LDX", AbsIndexed { reg: Y }, 0xbe),
+    ("LDY", Immediate, 0xa0), ("LDY", ZeroPage, 0xa4), ("LDY", ZeroPageIndexed { reg: X }, 0xb4),
+    ("LDY", Absolute, 0xac), ("LDY", AbsIndexed { reg: X }, 0xbc),
+    ("LSR", Accumulator, 0x4a), ("LSR", ZeroPage, 0x46), ("LSR", ZeroPageIndexed { reg: X }, 0x56),
+    ("LSR", Absolute, 0x4e), ("LSR", AbsIndexed { reg: X }, 0x5e),
+    ("NOP", Implied, 0xea),
+    ("ORA", Immediate, 0x09), ("ORA", ZeroPage, 0x05), ("ORA", ZeroPageIndexed { reg: X }, 0x15),
+    ("ORA", Absolute, 0x0d), ("ORA", AbsIndexed { reg: X }, 0x1d), ("ORA", AbsIndexed { reg: Y }, 0x19),
+    ("ORA", IndexedIndirect, 0x01), ("ORA", IndirectIndexed, 0x11),
+    ("PHA", Implied, 0x48), ("PHP", Implied, 0x08), ("PLA", Implied, 0x68), ("PLP", Implied, 0x28),
+    ("ROL", Accumulator, 0x2a), ("ROL", ZeroPage, 0x26), ("ROL", ZeroPageIndexed { reg: X }, 0x36),
+    ("ROL", Absolute, 0x2e), ("ROL", AbsIndexed { reg: X }, 0x3e),
+    ("ROR", Accumulator, 0x6a), ("ROR", ZeroPage, 0x66), ("ROR", ZeroPageIndexed { reg: X }, 0x76),
+    ("ROR", Absolute, 0x6e), ("ROR", AbsIndexed { reg: X }, 0x7e),
+    ("RTI", Implied, 0x40), ("RTS", Implied, 0x60),
+    ("SBC", Immediate, 0xe9), ("SBC", ZeroPage, 0xe5), ("SBC", ZeroPageIndexed { reg: X }, 0xf5),
+    ("SBC", Absolute, 0xed), ("SBC", AbsIndexed { reg: X }, 0xfd), ("SBC", AbsIndexed { reg: Y }, 0xf9),
+    ("SBC", IndexedIndirect, 0xe1), ("SBC", IndirectIndexed, 0xf1),
+    ("SEC", Implied, 0x38), ("SED", Implied, 0xf8), ("SEI", Implied, 0x78),
+    ("STA", ZeroPage, 0x85), ("STA", ZeroPageIndexed { reg: X }, 0x95), ("STA", Absolute, 0x8d),
+    ("STA", AbsIndexed { reg: X }, 0x9d), ("STA", AbsIndexed { reg: Y }, 0x99),
+    ("STA", IndexedIndirect, 0x81), ("STA", IndirectIndexed, 0x91),
+    ("STX", ZeroPage, 0x86), ("STX", ZeroPageIndexed { reg: Y }, 0x96), ("STX", Absolute, 0x8e),
+    ("STY", ZeroPage, 0x84), ("STY", ZeroPageIndexed { reg: X }, 0x94), ("STY", Absolute, 0x8c),
+    ("TAX", Implied, 0xaa), ("TAY", Implied, 0xa8), ("TSX", Implied, 0xba),
+    ("TXA", Implied, 0x8a), ("TXS", Implied, 0x9a), ("TYA", Implied, 0x98),
+];
+
+pub fn opcode(mnemonic: &str, mode: &AddressingMode) -> Option<u8> {
+    OPCODES
+        .iter()
+        .find(|(m, a, _)| m.eq_ignore_ascii_case(mnemonic) && a == mode)
+        .map(|(_, _, opcode)| *opcode)
+}
+
+pub fn is_mnemonic(word: &str) -> bool {
+    OPCODES.iter().any(|(m, _, _)| m.eq_ignore_ascii_case(word))
+}
+
+// The mnemonic and addressing mode of a documented opcode, for the test suite
+#[cfg(feature = "testsuite")]
+pub fn decode(opcode: u8) -> Option<(&'static str, &'static AddressingMode)> {
+    OPCODES.iter().find(|(_, _, o)| *o == opcode).map(|(m, mode, _)| (*m, mode))
+}
+
+// (opcode, mnemonic, mode) for all of them, in opcode order
+#[cfg(feature = "testsuite")]
+pub fn documented() -> Vec<(u8, &'static str, &'static AddressingMode)> {
+    let mut opcodes: Vec<_> = OPCODES.iter().map(|(m, mode, o)| (*o, *m, mode)).collect();
+    opcodes.sort_by_key(|(o, _, _)| *o);
+    opcodes
+}
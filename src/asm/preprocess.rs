@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::{is_mnemonic, is_name, split_args, split_label, starts_statement, strip_comment, AsmError};
+
+// Includes and macro calls nested deeper than this are taken to be recursion
+const MAX_DEPTH: usize = 16;
+
+// A line once includes and macros are expanded, and where it came from
+pub struct SourceLine {
+    pub file: Option<Rc<Path>>,
+    // the line in the file, or of the outermost macro call for lines a macro made
+    pub number: usize,
+    pub text: String,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+// Expands .include and macros, leaving everything else, conditionals included, to the passes
+pub struct Preprocessor<'a> {
+    include_dirs: &'a [PathBuf],
+    macros: HashMap<String, Macro>,
+    // numbers expansions, for \@
+    expansions: usize,
+    lines: Vec<SourceLine>,
+}
+
+// The first word and the rest
+fn split_word(text: &str) -> (&str, &str) {
+    match text.trim().split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text.trim(), ""),
+    }
+}
+
+fn is_end(line: &str) -> bool {
+    let (_, rest) = split_label(strip_comment(line), &starts_statement);
+    let (word, _) = split_word(rest);
+    word.eq_ignore_ascii_case(".endmacro") || word.eq_ignore_ascii_case(".endm")
+}
+
+// A macro body line with the parameters replaced by the arguments and \@ by the expansion's number
+fn substitute(line: &str, params: &[String], args: &[&str], expansion: usize) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('\\', None) if chars.peek().map(|(_, c)| *c) == Some('@') => {
+                chars.next();
+                out.push_str(&expansion.to_string());
+                continue;
+            }
+            (c, None) if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, c)) = chars.peek().copied().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                let word = &line[i..end];
+                // not the digits of a $hex or %binary number
+                let number = line[..i].ends_with(['$', '%']);
+                match params.iter().position(|p| p == word) {
+                    Some(n) if !number => out.push_str(args[n]),
+                    _ => out.push_str(word),
+                }
+                continue;
+            }
+            _ => (),
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl<'a> Preprocessor<'a> {
+    pub fn new(include_dirs: &'a [PathBuf]) -> Preprocessor<'a> {
+        Preprocessor {
+            include_dirs,
+            macros: HashMap::new(),
+            expansions: 0,
+            lines: vec![],
+        }
+    }
+
+    pub fn expand(mut self, text: &str, file: Option<Rc<Path>>) -> Result<Vec<SourceLine>, AsmError> {
+        self.source(text, file, None, 0)?;
+        Ok(self.lines)
+    }
+
+    // call_line, for a macro body, is the line of the call
+    fn source(&mut self, text: &str, file: Option<Rc<Path>>, call_line: Option<usize>, depth: usize) -> Result<(), AsmError> {
+        let mut lines = text.lines().enumerate();
+        while let Some((n, line)) = lines.next() {
+            let number = call_line.unwrap_or(n + 1);
+            let error = |message: String| AsmError {
+                file: file.as_deref().map(Path::to_path_buf),
+                line: number,
+                message,
+            };
+            let (label, rest) = split_label(strip_comment(line), &|s| {
+                starts_statement(s) || self.macros.contains_key(split_word(s).0)
+            });
+            let (word, args) = split_word(rest);
+            // the label of a line that's replaced goes on a line of its own
+            let replaced = word.eq_ignore_ascii_case(".include") || self.macros.contains_key(word);
+            if let (Some(label), true) = (label, replaced) {
+                self.push(&file, number, format!("{}:", label));
+            }
+            match word.to_ascii_lowercase().as_str() {
+                ".macro" => {
+                    let (name, params) = split_word(args);
+                    if !is_name(name) || is_mnemonic(name) {
+                        return Err(error(format!("bad macro name '{}'", name)));
+                    }
+                    if self.macros.contains_key(name) {
+                        return Err(error(format!("macro {} is already defined", name)));
+                    }
+                    let params: Vec<String> = if params.is_empty() { vec![] } else { split_args(params).iter().map(|p| p.to_string()).collect() };
+                    if let Some(bad) = params.iter().find(|p| !is_name(p)) {
+                        return Err(error(format!("bad macro parameter '{}'", bad)));
+                    }
+                    let mut body = vec![];
+                    loop {
+                        match lines.next() {
+                            Some((_, line)) if is_end(line) => break,
+                            Some((_, line)) => body.push(line.to_string()),
+                            None => return Err(error(format!("macro {} is missing .endmacro", name))),
+                        }
+                    }
+                    self.macros.insert(name.to_string(), Macro { params, body });
+                }
+                ".endmacro" | ".endm" => return Err(error(".endmacro without .macro".to_string())),
+                ".include" => {
+                    let name = args.strip_prefix('"').and_then(|a| a.strip_suffix('"'));
+                    let name = name.ok_or_else(|| error("expected .include \"file\"".to_string()))?;
+                    let path = self.resolve(name, file.as_deref()).ok_or_else(|| error(format!("can't find {}", name)))?;
+                    if depth >= MAX_DEPTH {
+                        return Err(error("includes nested too deeply".to_string()));
+                    }
+                    let text = fs::read_to_string(&path).map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+                    self.source(&text, Some(Rc::from(path.as_path())), None, depth + 1)?;
+                }
+                _ if self.macros.contains_key(word) => {
+                    if depth >= MAX_DEPTH {
+                        return Err(error("macros nested too deeply".to_string()));
+                    }
+                    let body = self.call(word, args).map_err(error)?;
+                    self.source(&body, file.clone(), Some(number), depth + 1)?;
+                }
+                _ => self.push(&file, number, line.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, file: &Option<Rc<Path>>, number: usize, text: String) {
+        self.lines.push(SourceLine {
+            file: file.clone(),
+            number,
+            text,
+        });
+    }
+
+    // The body of a call, with its arguments in place
+    fn call(&mut self, name: &str, args: &str) -> Result<String, String> {
+        let m = &self.macros[name];
+        let args = if args.is_empty() { vec![] } else { split_args(args) };
+        if args.len() != m.params.len() {
+            return Err(format!("macro {} takes {} arguments", name, m.params.len()));
+        }
+        self.expansions += 1;
+        Ok(m.body.iter().map(|line| substitute(line, &m.params, &args, self.expansions) + "\n").collect())
+    }
+
+    // Relative to the including file (or the working directory), then each include directory
+    fn resolve(&self, name: &str, including: Option<&Path>) -> Option<PathBuf> {
+        let base = including.and_then(Path::parent).map_or_else(PathBuf::new, Path::to_path_buf);
+        std::iter::once(base)
+            .chain(self.include_dirs.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
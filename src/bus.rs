@@ -1,31 +1,94 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+
+use crate::logging::BUS;
 
 pub type Address = u16;
 
 pub type Data = u8;
 
+// The saved state of every device on a bus, in registration order
+pub type DeviceStates = Vec<Option<Vec<Data>>>;
+
+// A device's internal state as the debugger shows it, e.g. "info device via"
+pub trait DebugView {
+    fn get_name(&self) -> String;
+    fn get_fields(&self) -> Vec<(String, String)>;
+
+    fn format_state(&self) -> String {
+        let mut out = format!("{}\n", self.get_name());
+        for (field, value) in self.get_fields() {
+            out.push_str(&format!("  {:<12} {}\n", field, value));
+        }
+        out
+    }
+}
+
 // a device on bus that handles read / write / isReadable... callbacks
 pub trait BusDevice {
     fn do_read(&self, address: Address) -> Data;
     fn do_write(&mut self, address: Address, data: Data);
     fn is_readable_for(&self, address: Address) -> bool;
     fn is_writable_for(&self, address: Address) -> bool;
+
+    // An opaque copy of the device's internal state for snapshots. Stateless devices return None
+    fn save_state(&self) -> Option<Vec<Data>> {
+        None
+    }
+
+    fn load_state(&mut self, _state: &[Data]) {}
+
+    // What a read would return, without the read's side effects: no flag acknowledged, no key
+    // taken, nothing polled. The debugger looks at memory through this. Devices whose reads
+    // change their state override it
+    fn peek(&self, address: Address) -> Data {
+        self.do_read(address)
+    }
+
+    // Devices that want to be inspected from the debugger return themselves here
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        None
+    }
+
+    // True when is_readable_for and is_writable_for never change their answer for an address,
+    // so a bus can work out once which pages the device is on. Devices that bank themselves in
+    // and out keep the default and are asked about every access
+    fn has_fixed_mapping(&self) -> bool {
+        false
+    }
+
+    // Plain RAM with nothing behind the reads and writes: its first address and its bytes, which
+    // a bus may then read and write without going through the device
+    fn get_ram(&self) -> Option<(Address, Rc<[Cell<Data>]>)> {
+        None
+    }
 }
 
 // holds devices
 pub trait Bus {
     fn write(&self, address: Address, data: Data);
     fn read(&self, address: Address) -> Data;
+    // A read for looking, through BusDevice::peek, which isn't counted or logged
+    fn peek(&self, address: Address) -> Data;
     fn register_device(&mut self, device: &Rc<RefCell<dyn BusDevice>>);
+    fn save_state(&self) -> DeviceStates;
+    fn load_state(&self, states: &DeviceStates);
+    // (name, formatted state) for every device with a DebugView
+    fn describe_devices(&self) -> Vec<(String, String)>;
 }
 
 pub struct SimpleBus {
     pub registered: Vec<Rc<RefCell<dyn BusDevice>>>,
 }
 
+// Every access is logged at trace under logging::BUS, the debugger's included
 impl Bus for SimpleBus {
     fn write(&self, address: Address, data: Data) {
+        log::trace!(target: BUS, "write ${:04X}=${:02X}", address, data);
         for d in self.registered.iter() {
             if d.borrow().is_writable_for(address) {
                 d.borrow_mut().do_write(address, data);
@@ -35,16 +98,292 @@ impl Bus for SimpleBus {
 
     fn read(&self, address: Address) -> Data {
         for d in &self.registered {
-            if !(d.borrow().is_readable_for(address)) {
-                continue;
-            } else {
-                return d.borrow_mut().do_read(address);
+            // do_read takes &self, so one borrow does for both
+            let d = d.borrow();
+            if d.is_readable_for(address) {
+                let data = d.do_read(address);
+                log::trace!(target: BUS, "read ${:04X}=${:02X}", address, data);
+                return data;
             }
         }
+        log::trace!(target: BUS, "read ${:04X} unmapped", address);
         0x0
     }
 
+    fn peek(&self, address: Address) -> Data {
+        self.registered
+            .iter()
+            .map(|d| d.borrow())
+            .find(|d| d.is_readable_for(address))
+            .map_or(0x0, |d| d.peek(address))
+    }
+
     fn register_device(&mut self, device: &Rc<RefCell<dyn BusDevice>>) {
         self.registered.push(Rc::clone(device));
     }
+
+    fn save_state(&self) -> DeviceStates {
+        self.registered.iter().map(|d| d.borrow().save_state()).collect()
+    }
+
+    fn load_state(&self, states: &DeviceStates) {
+        for (d, state) in self.registered.iter().zip(states.iter()) {
+            if let Some(s) = state {
+                d.borrow_mut().load_state(s);
+            }
+        }
+    }
+
+    fn describe_devices(&self) -> Vec<(String, String)> {
+        self.registered
+            .iter()
+            .filter_map(|d| d.borrow().debug_view().map(|v| (v.get_name(), v.format_state())))
+            .collect()
+    }
+}
+
+pub const PAGE_SIZE: usize = 0x100;
+
+const PAGES: usize = 0x10000 / PAGE_SIZE;
+
+enum Page {
+    // RAM's bytes, the offset of the page in them and the RAM's index
+    Ram(Rc<[Cell<Data>]>, usize, usize),
+    // the devices that may answer on the page, in registration order
+    Devices(Vec<usize>),
+}
+
+// The reads a device has answered and the writes it's been given
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Traffic {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+// A SimpleBus that looks addresses up in a table of 256 byte pages. A page only RAM is on maps
+// straight to its bytes, so most accesses are an index and a Cell; other pages keep the devices
+// that answer there and ask them as SimpleBus would. Which pages a device is on is worked out
+// when it's registered, from is_readable_for and is_writable_for, if it has a fixed mapping;
+// devices without one are asked on every page. It counts each device's traffic as it goes
+pub struct PagedBus {
+    // shared with the devices' other handles, so each access to one borrows it
+    registered: Vec<Rc<RefCell<dyn BusDevice>>>,
+    // per device, the pages it answers on, or None for anywhere
+    footprints: Vec<Option<[bool; PAGES]>>,
+    traffic: Vec<Cell<Traffic>>,
+    pages: Vec<Page>,
+}
+
+impl Default for PagedBus {
+    fn default() -> Self {
+        PagedBus::new()
+    }
+}
+
+impl PagedBus {
+    pub fn new() -> PagedBus {
+        PagedBus {
+            registered: vec![],
+            footprints: vec![],
+            traffic: vec![],
+            pages: (0..PAGES).map(|_| Page::Devices(vec![])).collect(),
+        }
+    }
+
+    // Puts a device at a place in the order, ahead of the ones already from there on
+    pub fn insert_device(&mut self, index: usize, device: &Rc<RefCell<dyn BusDevice>>) {
+        self.footprints.insert(index, footprint(&*device.borrow()));
+        self.traffic.insert(index, Cell::default());
+        self.registered.insert(index, Rc::clone(device));
+        self.map_pages();
+    }
+
+    // The devices in the order they're asked
+    pub fn get_devices(&self) -> &[Rc<RefCell<dyn BusDevice>>] {
+        &self.registered
+    }
+
+    // Each device's traffic since it was registered, in the same order
+    pub fn get_traffic(&self) -> Vec<Traffic> {
+        self.traffic.iter().map(Cell::get).collect()
+    }
+
+    // How many pages go straight to RAM
+    pub fn get_ram_page_count(&self) -> usize {
+        self.pages.iter().filter(|page| matches!(page, Page::Ram(..))).count()
+    }
+
+    fn map_pages(&mut self) {
+        for (number, page) in self.pages.iter_mut().enumerate() {
+            let devices: Vec<usize> = (0..self.registered.len())
+                .filter(|i| self.footprints[*i].is_none_or(|pages| pages[number]))
+                .collect();
+            *page = match devices.as_slice() {
+                [only] => match ram_page(&*self.registered[*only].borrow(), number) {
+                    Some((ram, offset)) => Page::Ram(ram, offset, *only),
+                    None => Page::Devices(devices),
+                },
+                _ => Page::Devices(devices),
+            };
+        }
+    }
+}
+
+fn footprint(device: &dyn BusDevice) -> Option<[bool; PAGES]> {
+    if !device.has_fixed_mapping() {
+        return None;
+    }
+    let mut pages = [false; PAGES];
+    for address in 0..=Address::MAX {
+        if device.is_readable_for(address) || device.is_writable_for(address) {
+            pages[address as usize / PAGE_SIZE] = true;
+        }
+    }
+    Some(pages)
+}
+
+// The device's RAM and the page's offset in it, when the RAM covers the whole page
+fn ram_page(device: &dyn BusDevice, number: usize) -> Option<(Rc<[Cell<Data>]>, usize)> {
+    let (start, ram) = device.get_ram()?;
+    let offset = (number * PAGE_SIZE).checked_sub(start as usize)?;
+    match offset + PAGE_SIZE <= ram.len() {
+        true => Some((ram, offset)),
+        false => None,
+    }
+}
+
+impl PagedBus {
+    fn count_read(&self, device: usize) {
+        let traffic = &self.traffic[device];
+        traffic.set(Traffic { reads: traffic.get().reads + 1, ..traffic.get() });
+    }
+
+    fn count_write(&self, device: usize) {
+        let traffic = &self.traffic[device];
+        traffic.set(Traffic { writes: traffic.get().writes + 1, ..traffic.get() });
+    }
+}
+
+impl Bus for PagedBus {
+    fn write(&self, address: Address, data: Data) {
+        log::trace!(target: BUS, "write ${:04X}=${:02X}", address, data);
+        match &self.pages[address as usize / PAGE_SIZE] {
+            Page::Ram(ram, offset, device) => {
+                self.count_write(*device);
+                ram[offset + address as usize % PAGE_SIZE].set(data);
+            }
+            Page::Devices(devices) => {
+                for i in devices {
+                    let d = &self.registered[*i];
+                    if d.borrow().is_writable_for(address) {
+                        self.count_write(*i);
+                        d.borrow_mut().do_write(address, data);
+                    }
+                }
+            }
+        }
+    }
+
+    fn read(&self, address: Address) -> Data {
+        let data = match &self.pages[address as usize / PAGE_SIZE] {
+            Page::Ram(ram, offset, device) => {
+                self.count_read(*device);
+                Some(ram[offset + address as usize % PAGE_SIZE].get())
+            }
+            Page::Devices(devices) => devices.iter().find_map(|i| {
+                let d = self.registered[*i].borrow();
+                d.is_readable_for(address).then(|| {
+                    self.count_read(*i);
+                    d.do_read(address)
+                })
+            }),
+        };
+        match data {
+            Some(data) => {
+                log::trace!(target: BUS, "read ${:04X}=${:02X}", address, data);
+                data
+            }
+            None => {
+                log::trace!(target: BUS, "read ${:04X} unmapped", address);
+                0x0
+            }
+        }
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        match &self.pages[address as usize / PAGE_SIZE] {
+            Page::Ram(ram, offset, _) => ram[offset + address as usize % PAGE_SIZE].get(),
+            Page::Devices(devices) => devices
+                .iter()
+                .map(|i| self.registered[*i].borrow())
+                .find(|d| d.is_readable_for(address))
+                .map_or(0x0, |d| d.peek(address)),
+        }
+    }
+
+    fn register_device(&mut self, device: &Rc<RefCell<dyn BusDevice>>) {
+        self.insert_device(self.registered.len(), device);
+    }
+
+    fn save_state(&self) -> DeviceStates {
+        self.registered.iter().map(|d| d.borrow().save_state()).collect()
+    }
+
+    fn load_state(&self, states: &DeviceStates) {
+        for (d, state) in self.registered.iter().zip(states.iter()) {
+            if let Some(s) = state {
+                d.borrow_mut().load_state(s);
+            }
+        }
+    }
+
+    fn describe_devices(&self) -> Vec<(String, String)> {
+        self.registered
+            .iter()
+            .filter_map(|d| d.borrow().debug_view().map(|v| (v.get_name(), v.format_state())))
+            .collect()
+    }
+}
+
+// A bus seen through fewer address lines, as on the 6507 whose 13 bit bus makes $F000 and
+// $1000 the same place. The top address bits are dropped before the inner bus sees them
+pub struct MaskedBus {
+    inner: Rc<RefCell<dyn Bus>>,
+    mask: Address,
+}
+
+impl MaskedBus {
+    pub fn new(inner: Rc<RefCell<dyn Bus>>, mask: Address) -> MaskedBus {
+        MaskedBus { inner, mask }
+    }
+}
+
+impl Bus for MaskedBus {
+    fn write(&self, address: Address, data: Data) {
+        self.inner.borrow().write(address & self.mask, data);
+    }
+
+    fn read(&self, address: Address) -> Data {
+        self.inner.borrow().read(address & self.mask)
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        self.inner.borrow().peek(address & self.mask)
+    }
+
+    fn register_device(&mut self, device: &Rc<RefCell<dyn BusDevice>>) {
+        self.inner.borrow_mut().register_device(device);
+    }
+
+    fn save_state(&self) -> DeviceStates {
+        self.inner.borrow().save_state()
+    }
+
+    fn load_state(&self, states: &DeviceStates) {
+        self.inner.borrow().load_state(states);
+    }
+
+    fn describe_devices(&self) -> Vec<(String, String)> {
+        self.inner.borrow().describe_devices()
+    }
 }
@@ -0,0 +1,177 @@
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::pin;
+use std::task::Poll;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::oneshot;
+
+use crate::bus::Data;
+use crate::devices::acia::SerialBackend;
+
+// Adapters between the synchronous emulation loop and async tasks, for network facing devices
+// built on tokio, with the async feature. The emulation side only ever try_recv's and
+// try_send's, so a slow or stalled task never holds up the machine; the channels are bounded,
+// so a fast one can't run away with memory either.
+
+// A serial line whose far end is an async task: the ACIA side of async_serial
+pub struct ChannelSerial {
+    input: Receiver<Data>,
+    output: Sender<Data>,
+    dropped: usize,
+}
+
+// The task side of async_serial
+pub struct SerialTask {
+    input: Sender<Data>,
+    output: Receiver<Data>,
+}
+
+// A serial line to an async task, with room for capacity bytes queued each way. Bytes the
+// machine sends while the task's queue is full are dropped, as a real line would overrun
+//   let (serial, task) = async_serial(1024);
+//   system.add_device(Acia::new(0x5000, Box::new(serial)));
+//   tokio::spawn(async move {
+//       let (stream, _) = listener.accept().await?;
+//       let (reader, writer) = stream.into_split();
+//       task.serve(reader, writer).await
+//   });
+pub fn async_serial(capacity: usize) -> (ChannelSerial, SerialTask) {
+    let (input_sender, input) = mpsc::channel(capacity);
+    let (output, output_receiver) = mpsc::channel(capacity);
+    (
+        ChannelSerial {
+            input,
+            output,
+            dropped: 0,
+        },
+        SerialTask {
+            input: input_sender,
+            output: output_receiver,
+        },
+    )
+}
+
+impl ChannelSerial {
+    // Bytes sent while the task was behind or gone
+    pub fn get_dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+impl SerialBackend for ChannelSerial {
+    fn receive(&mut self) -> Option<Data> {
+        self.input.try_recv().ok()
+    }
+
+    fn transmit(&mut self, data: Data) {
+        if self.output.try_send(data).is_err() {
+            self.dropped += 1;
+        }
+    }
+}
+
+impl SerialTask {
+    // The next byte the machine sent, None once the ChannelSerial is gone
+    pub async fn recv(&mut self) -> Option<Data> {
+        self.output.recv().await
+    }
+
+    // A byte for the machine, waiting while its queue is full. False once the ChannelSerial
+    // is gone
+    pub async fn send(&self, data: Data) -> bool {
+        self.input.send(data).await.is_ok()
+    }
+
+    // Pumps bytes between the machine and a stream, e.g. the halves of a TcpStream, until the
+    // stream closes or the machine goes away
+    pub async fn serve<R, W>(self, mut reader: R, mut writer: W) -> io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let SerialTask { input, mut output } = self;
+        let mut inbound = pin!(async move {
+            let mut buffer = [0; 256];
+            loop {
+                let n = reader.read(&mut buffer).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                for b in &buffer[..n] {
+                    if input.send(*b).await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        });
+        let mut outbound = pin!(async move {
+            while let Some(data) = output.recv().await {
+                writer.write_all(&[data]).await?;
+                writer.flush().await?;
+            }
+            Ok(())
+        });
+        // whichever direction finishes first ends it
+        poll_fn(|cx| match inbound.as_mut().poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => outbound.as_mut().poll(cx),
+        })
+        .await
+    }
+}
+
+// Requests from async tasks, e.g. a remote debugger or a control server, answered on the
+// emulation thread between frames: the task side of service
+pub struct ServiceClient<Req, Resp> {
+    requests: Sender<(Req, oneshot::Sender<Resp>)>,
+}
+
+// The emulation side of service
+pub struct ServicePort<Req, Resp> {
+    requests: Receiver<(Req, oneshot::Sender<Resp>)>,
+}
+
+// A request channel with room for capacity outstanding requests. The client is cheap to clone,
+// one per connection; the port stays with the System and answers whatever has come in
+//   let (client, mut port) = service(16);
+//   loop {
+//       system.run_frame(16_667, |_| ());
+//       port.answer(|address: Address| system.read(address));
+//   }
+pub fn service<Req, Resp>(capacity: usize) -> (ServiceClient<Req, Resp>, ServicePort<Req, Resp>) {
+    let (requests, receiver) = mpsc::channel(capacity);
+    (ServiceClient { requests }, ServicePort { requests: receiver })
+}
+
+impl<Req, Resp> Clone for ServiceClient<Req, Resp> {
+    fn clone(&self) -> Self {
+        ServiceClient {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<Req, Resp> ServiceClient<Req, Resp> {
+    // Waits for room in the queue and then the answer. None if the port is gone or dropped
+    // the request unanswered
+    pub async fn call(&self, request: Req) -> Option<Resp> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests.send((request, sender)).await.ok()?;
+        receiver.await.ok()
+    }
+}
+
+impl<Req, Resp> ServicePort<Req, Resp> {
+    // Answers every request waiting, without blocking, returning how many there were
+    pub fn answer<F: FnMut(Req) -> Resp>(&mut self, mut handler: F) -> usize {
+        let mut answered = 0;
+        while let Ok((request, reply)) = self.requests.try_recv() {
+            // the caller may have given up waiting
+            let _ = reply.send(handler(request));
+            answered += 1;
+        }
+        answered
+    }
+}
@@ -1,26 +1,1315 @@
 use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Write;
 use std::rc::{Rc, Weak};
-use crate::bus::Bus;
-use crate::processor::ProcessorTrait;
 
-// TODO thinking about how to add a debugger to the system
+use crate::bus::{Address, Bus, Data};
+use crate::debugger::mini_assembler::MiniAssembler;
+use crate::debugger::profile::Profiler;
+use crate::debugger::search::{find_pattern, format_match};
+use crate::debugger::symbols::Symbols;
+use crate::debugger::trace::Trace;
+use crate::debugger::write_log::WriteLog;
+use crate::debugger::watch::WatchExpression;
+use crate::debugger::history::{History, HistoryEntry, RecordingBus};
+#[cfg(feature = "scripting")]
+use crate::debugger::script::Script;
+use crate::debugger::snapshots::{MachineSnapshot, Snapshots};
+use crate::devices::Peripherals;
+use crate::monitor::Monitor;
+use crate::disasm::Disassembler;
+use crate::loader::hexdump;
+use crate::processor::{ProcessorTrait, Registers};
+use crate::system::{Clock, Speed, DEFAULT_CLOCK_HZ};
+
+mod coverage;
+mod dap;
+mod debug_info;
+mod events;
+mod gdb;
+mod histogram;
+mod history;
+mod mini_assembler;
+#[cfg(feature = "scripting")]
+mod script;
+mod profile;
+mod remote;
+mod search;
+mod snapshots;
+mod symbols;
+mod trace;
+#[cfg(feature = "tui")]
+mod tui;
+mod watch;
+mod wire;
+mod write_log;
+
+pub use crate::debugger::coverage::Coverage;
+pub use crate::debugger::debug_info::DebugInfo;
+pub use crate::debugger::events::{DebugEvent, RunMode};
+pub use crate::debugger::histogram::InstructionHistogram;
+pub use crate::debugger::trace::TraceFilter;
+pub use crate::debugger::write_log::WriteRecord;
+#[cfg(feature = "tui")]
+pub use crate::debugger::tui::{draw_tui, run_tui};
+
+const DEFAULT_HISTORY_DEPTH: usize = 1000;
+const DEFAULT_SNAPSHOT_INTERVAL: usize = 1_000_000;
+const DEFAULT_SNAPSHOT_CAPACITY: usize = 32;
+const MAX_FIND_MATCHES_SHOWN: usize = 64;
+const DEFAULT_REPORT_LINES: usize = 20;
+const DEFAULT_WRITE_LOG_CAPACITY: usize = 10_000;
+
+type Attached = (Rc<RefCell<dyn ProcessorTrait>>, Rc<RefCell<dyn Bus>>);
+
+// The machine a debugger is attached to. The debugger never keeps the machine alive, and the
+// link is shared with script bindings so they follow attach and detach
+#[derive(Default)]
+pub(crate) struct Link {
+    processor: Option<Weak<RefCell<dyn ProcessorTrait>>>,
+    bus: Option<Weak<RefCell<dyn Bus>>>,
+    // ticked with the cycles of each instruction the debugger runs, when it has them
+    peripherals: Option<Weak<RefCell<Peripherals>>>,
+}
+
+impl Link {
+    pub(crate) fn upgrade(&self) -> Result<Attached, DebuggerError> {
+        let processor = self.processor.as_ref().and_then(|p| p.upgrade());
+        let bus = self.bus.as_ref().and_then(|b| b.upgrade());
+        match (processor, bus) {
+            (Some(p), Some(b)) => Ok((p, b)),
+            _ => Err(DebuggerError::NotAttached),
+        }
+    }
+
+    fn tick_peripherals(&self, cycles: usize) {
+        if let Some(peripherals) = self.peripherals.as_ref().and_then(|p| p.upgrade()) {
+            peripherals.borrow().tick(cycles);
+        }
+    }
+}
+
 pub struct Debugger {
-    processor: Weak<RefCell<dyn ProcessorTrait>>,
+    link: Rc<RefCell<Link>>,
+    history: History,
+    snapshots: Snapshots,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: BTreeSet<Address>,
+    symbols: Symbols,
+    // source lines for addresses, from ca65 debug info
+    debug_info: Option<DebugInfo>,
+    watches: Vec<WatchExpression>,
+    assembler: Option<MiniAssembler>,
+    // where the next line goes while in assembly mode
+    assembling: Option<Address>,
+    // lines go to the Woz Monitor until "exit"
+    monitor: Option<Monitor>,
+    trace: Option<Trace>,
+    profiler: Option<Profiler>,
+    // built on the first step, it needs the instruction table
+    coverage: Option<Coverage>,
+    histogram: Option<InstructionHistogram>,
+    write_log: WriteLog,
+    // paces run and continue; single step makes them step
+    speed: Speed,
+    clock: Clock,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+}
+
+// Why execution handed control back to the debugger
+#[derive(PartialEq, Debug, Clone)]
+pub enum StopReason {
+    Break,
+    Breakpoint(Address),
+    Watchpoint(Address),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Break => write!(f, "stopped at break"),
+            StopReason::Breakpoint(a) => write!(f, "breakpoint at ${:04X}", a),
+            StopReason::Watchpoint(a) => write!(f, "watchpoint ${:04X} written", a),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum DebuggerError {
+    NotAttached,
+    UnknownCommand(String),
+    BadArgument(String),
+    NoSnapshotBefore(usize),
+    Script(String),
+}
+
+impl fmt::Display for DebuggerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DebuggerError::NotAttached => write!(f, "no machine attached"),
+            DebuggerError::UnknownCommand(c) => write!(f, "unknown command '{}'", c),
+            DebuggerError::BadArgument(a) => write!(f, "bad argument '{}'", a),
+            DebuggerError::NoSnapshotBefore(c) => write!(f, "no snapshot at or before cycle {}", c),
+            DebuggerError::Script(e) => write!(f, "script error: {}", e),
+        }
+    }
 }
 
+#[derive(PartialEq, Debug)]
 enum Commands {
-    DumpMemoryRange,
-    STEP,
+    DumpMemoryRange { start: Address, end: Address },
+    Disassemble { start: Option<Address>, count: usize },
+    STEP { count: usize },
+    ReverseStep { count: usize },
+    ShowRegisters,
+    HistoryDepth { depth: Option<usize> },
+    Run { cycles: usize },
+    Travel { cycle: usize },
+    SnapshotInterval { interval: Option<usize> },
+    Break { address: Address },
+    Delete { address: Address },
+    ListBreakpoints,
+    Continue,
+    LoadScript { path: String },
+    GdbServer { port: u16 },
+    DapServer { port: u16 },
+    RemoteServer { port: u16 },
+    Detach,
+    Watchpoint { address: Address },
+    Symbol { name: String, address: Address },
+    LoadSymbols { path: String },
+    LoadDebugInfo { path: String },
+    ListSymbols,
+    SaveSession { path: String },
+    Source { path: String },
+    Watch { expression: WatchExpression },
+    Unwatch { index: usize },
+    ListWatches,
+    Assemble { address: Address, instruction: Option<String> },
+    Load { path: String, address: Option<Address> },
+    Find { pattern: Vec<Option<Data>>, start: Address, end: Address },
+    InfoDevice { name: Option<String> },
+    TraceOn { filter: TraceFilter, path: Option<String> },
+    TraceOff,
+    TraceStatus,
+    Profile { action: ProfileAction },
+    Coverage { start: Address, end: Address, path: Option<String> },
+    CoverageReset,
+    Histogram { per_opcode: bool, lines: usize },
+    HistogramReset,
+    Writes { address: Option<Address>, lines: usize },
+    Undo { count: usize },
+    Monitor,
+    Speed { speed: Option<Speed> },
+}
+
+#[derive(PartialEq, Debug)]
+enum ProfileAction {
+    On,
+    Off,
+    Reset,
+    Report { lines: usize },
+}
+
+// Addresses are hex, optionally written as $0200 or 0x0200
+pub fn parse_address(s: &str) -> Result<Address, DebuggerError> {
+    let digits = s.trim_start_matches('$').trim_start_matches("0x");
+    Address::from_str_radix(digits, 16).map_err(|_| DebuggerError::BadArgument(s.to_string()))
+}
+
+fn parse_count(s: Option<&str>) -> Result<usize, DebuggerError> {
+    match s {
+        None => Ok(1),
+        Some(c) => c.replace('_', "").parse().map_err(|_| DebuggerError::BadArgument(c.to_string())),
+    }
+}
+
+// How many lines of a report to show
+fn parse_report_lines(s: Option<&str>) -> Result<usize, DebuggerError> {
+    match s {
+        None => Ok(DEFAULT_REPORT_LINES),
+        Some(n) => parse_count(Some(n)),
+    }
+}
+
+fn parse_required_count(s: Option<&str>, line: &str) -> Result<usize, DebuggerError> {
+    match s {
+        None => Err(DebuggerError::BadArgument(line.to_string())),
+        c => parse_count(c),
+    }
+}
+
+// A symbol name or an address
+fn resolve_address(symbols: &Symbols, s: &str) -> Result<Address, DebuggerError> {
+    match symbols.get(s) {
+        Some(address) => Ok(address),
+        None => parse_address(s),
+    }
+}
+
+// An optional "start..end" range, the whole address space by default
+fn parse_range(s: Option<&str>, symbols: &Symbols) -> Result<(Address, Address), DebuggerError> {
+    match s {
+        None => Ok((0x0000, 0xffff)),
+        Some(range) => match range.split_once("..") {
+            Some((start, end)) => Ok((resolve_address(symbols, start)?, resolve_address(symbols, end)?)),
+            None => Err(DebuggerError::BadArgument(range.to_string())),
+        },
+    }
+}
+
+fn parse_path(s: Option<&str>, line: &str) -> Result<String, DebuggerError> {
+    match s {
+        Some(path) => Ok(path.to_string()),
+        None => Err(DebuggerError::BadArgument(line.to_string())),
+    }
+}
+
+fn parse_command(line: &str, symbols: &Symbols) -> Result<Commands, DebuggerError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let parse_required_address = |s: Option<&str>| match s {
+        Some(a) => resolve_address(symbols, a),
+        None => Err(DebuggerError::BadArgument(line.to_string())),
+    };
+    match command {
+        "s" | "step" => Ok(Commands::STEP { count: parse_count(words.next())? }),
+        "rs" | "rstep" => Ok(Commands::ReverseStep { count: parse_count(words.next())? }),
+        "r" | "regs" => Ok(Commands::ShowRegisters),
+        "m" | "mem" => {
+            let start = parse_required_address(words.next())?;
+            let end = match words.next() {
+                Some(e) => resolve_address(symbols, e)?,
+                None => start.saturating_add(0x0f),
+            };
+            Ok(Commands::DumpMemoryRange { start, end })
+        }
+        "dis" | "disasm" => {
+            let start = words.next().map(|s| resolve_address(symbols, s)).transpose()?;
+            Ok(Commands::Disassemble { start, count: parse_report_lines(words.next())? })
+        }
+        "history" => match words.next() {
+            None => Ok(Commands::HistoryDepth { depth: None }),
+            Some(d) => Ok(Commands::HistoryDepth { depth: Some(parse_count(Some(d))?) }),
+        },
+        "run" => Ok(Commands::Run { cycles: parse_required_count(words.next(), line)? }),
+        "travel" => Ok(Commands::Travel { cycle: parse_required_count(words.next(), line)? }),
+        "snapshots" => match words.next() {
+            None => Ok(Commands::SnapshotInterval { interval: None }),
+            Some(i) => Ok(Commands::SnapshotInterval { interval: Some(parse_count(Some(i))?) }),
+        },
+        "b" | "break" => Ok(Commands::Break { address: parse_required_address(words.next())? }),
+        "d" | "delete" => Ok(Commands::Delete { address: parse_required_address(words.next())? }),
+        "wp" | "watchpoint" => Ok(Commands::Watchpoint { address: parse_required_address(words.next())? }),
+        "breakpoints" => Ok(Commands::ListBreakpoints),
+        "c" | "continue" => Ok(Commands::Continue),
+        "script" => Ok(Commands::LoadScript { path: parse_path(words.next(), line)? }),
+        "symbol" => match (words.next(), words.next()) {
+            (Some(name), Some(address)) => Ok(Commands::Symbol { name: name.to_string(), address: parse_address(address)? }),
+            _ => Err(DebuggerError::BadArgument(line.to_string())),
+        },
+        "symbols" => match words.next() {
+            Some(path) => Ok(Commands::LoadSymbols { path: path.to_string() }),
+            None => Ok(Commands::ListSymbols),
+        },
+        "dbginfo" => Ok(Commands::LoadDebugInfo { path: parse_path(words.next(), line)? }),
+        "detach" => Ok(Commands::Detach),
+        "watch" => {
+            let expression = line.trim_start().trim_start_matches("watch").trim();
+            Ok(Commands::Watch { expression: WatchExpression::parse(expression, symbols)? })
+        }
+        "unwatch" => Ok(Commands::Unwatch { index: parse_required_count(words.next(), line)? }),
+        "watches" => Ok(Commands::ListWatches),
+        "find" => {
+            let mut pattern = vec![];
+            let mut range = None;
+            for word in words {
+                if word.contains("..") {
+                    range = Some(word);
+                } else if word == "??" {
+                    pattern.push(None);
+                } else {
+                    let byte = Data::from_str_radix(word.trim_start_matches('$'), 16).map_err(|_| DebuggerError::BadArgument(word.to_string()))?;
+                    pattern.push(Some(byte));
+                }
+            }
+            if pattern.is_empty() {
+                return Err(DebuggerError::BadArgument(line.to_string()));
+            }
+            let (start, end) = parse_range(range, symbols)?;
+            Ok(Commands::Find { pattern, start, end })
+        }
+        "find-text" => {
+            let text = line.trim_start().trim_start_matches("find-text").trim();
+            let text = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text);
+            if text.is_empty() {
+                return Err(DebuggerError::BadArgument(line.to_string()));
+            }
+            let pattern = text.bytes().map(Some).collect();
+            Ok(Commands::Find { pattern, start: 0x0000, end: 0xffff })
+        }
+        "info" => match (words.next(), words.next()) {
+            (Some("device"), name) | (Some("devices"), name) => Ok(Commands::InfoDevice { name: name.map(|n| n.to_string()) }),
+            _ => Err(DebuggerError::BadArgument(line.to_string())),
+        },
+        "trace" => match words.next() {
+            None => Ok(Commands::TraceStatus),
+            Some("off") => Ok(Commands::TraceOff),
+            Some("on") => {
+                let mut filter = TraceFilter::default();
+                let mut path = None;
+                while let Some(word) = words.next() {
+                    let argument = words.next().ok_or_else(|| DebuggerError::BadArgument(line.to_string()))?;
+                    match word {
+                        "range" => filter.range = Some(parse_range(Some(argument), symbols)?),
+                        "mnemonic" => filter.mnemonics = argument.split(',').map(|m| m.to_ascii_uppercase()).collect(),
+                        "to" => path = Some(argument.to_string()),
+                        _ => return Err(DebuggerError::BadArgument(word.to_string())),
+                    }
+                }
+                Ok(Commands::TraceOn { filter, path })
+            }
+            Some(other) => Err(DebuggerError::BadArgument(other.to_string())),
+        },
+        "profile" => {
+            let action = match words.next() {
+                Some("on") => ProfileAction::On,
+                Some("off") => ProfileAction::Off,
+                Some("reset") => ProfileAction::Reset,
+                n => ProfileAction::Report { lines: parse_report_lines(n)? },
+            };
+            Ok(Commands::Profile { action })
+        }
+        "coverage" => {
+            let mut range = None;
+            let mut path = None;
+            while let Some(word) = words.next() {
+                match word {
+                    "reset" => return Ok(Commands::CoverageReset),
+                    "to" => path = Some(parse_path(words.next(), line)?),
+                    _ => range = Some(word),
+                }
+            }
+            let (start, end) = parse_range(range, symbols)?;
+            Ok(Commands::Coverage { start, end, path })
+        }
+        "histogram" => match words.next() {
+            Some("reset") => Ok(Commands::HistogramReset),
+            Some("opcodes") => Ok(Commands::Histogram { per_opcode: true, lines: parse_report_lines(words.next())? }),
+            n => Ok(Commands::Histogram { per_opcode: false, lines: parse_report_lines(n)? }),
+        },
+        "writes" => match words.next() {
+            Some("to") => {
+                let address = parse_required_address(words.next())?;
+                Ok(Commands::Writes { address: Some(address), lines: parse_report_lines(words.next())? })
+            }
+            n => Ok(Commands::Writes { address: None, lines: parse_report_lines(n)? }),
+        },
+        "undo" => Ok(Commands::Undo { count: parse_count(words.next())? }),
+        "speed" => match words.next() {
+            None => Ok(Commands::Speed { speed: None }),
+            Some(s) => Ok(Commands::Speed { speed: Some(s.parse().map_err(DebuggerError::BadArgument)?) }),
+        },
+        "monitor" => Ok(Commands::Monitor),
+        "a" | "assemble" => {
+            let address = parse_required_address(words.next())?;
+            let rest: Vec<&str> = words.collect();
+            let instruction = if rest.is_empty() { None } else { Some(rest.join(" ")) };
+            Ok(Commands::Assemble { address, instruction })
+        }
+        "load" => {
+            let path = parse_path(words.next(), line)?;
+            let address = words.next().map(|s| resolve_address(symbols, s)).transpose()?;
+            Ok(Commands::Load { path, address })
+        }
+        "save" => Ok(Commands::SaveSession { path: parse_path(words.next(), line)? }),
+        "source" => Ok(Commands::Source { path: parse_path(words.next(), line)? }),
+        "gdb" => match words.next().map(|p| p.parse::<u16>()) {
+            Some(Ok(port)) => Ok(Commands::GdbServer { port }),
+            _ => Err(DebuggerError::BadArgument(line.to_string())),
+        },
+        "dap" => match words.next().map(|p| p.parse::<u16>()) {
+            Some(Ok(port)) => Ok(Commands::DapServer { port }),
+            _ => Err(DebuggerError::BadArgument(line.to_string())),
+        },
+        "remote" => match words.next().map(|p| p.parse::<u16>()) {
+            Some(Ok(port)) => Ok(Commands::RemoteServer { port }),
+            _ => Err(DebuggerError::BadArgument(line.to_string())),
+        },
+        _ => Err(DebuggerError::UnknownCommand(command.to_string())),
+    }
 }
+
 impl Debugger {
-    pub fn register_processor(&self) {
+    pub fn new(processor: &Rc<RefCell<dyn ProcessorTrait>>, bus: &Rc<RefCell<dyn Bus>>) -> Debugger {
+        let mut debugger = Debugger::detached();
+        debugger.attach(processor, bus);
+        debugger
+    }
+
+    // A debugger with no machine yet. Breakpoints, symbols and settings can be configured before attaching
+    pub fn detached() -> Debugger {
+        Debugger {
+            link: Rc::new(RefCell::new(Link::default())),
+            history: History::new(DEFAULT_HISTORY_DEPTH),
+            snapshots: Snapshots::new(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_SNAPSHOT_CAPACITY),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            symbols: Symbols::default(),
+            debug_info: None,
+            watches: vec![],
+            assembler: None,
+            assembling: None,
+            monitor: None,
+            trace: None,
+            profiler: None,
+            coverage: None,
+            histogram: None,
+            write_log: WriteLog::new(DEFAULT_WRITE_LOG_CAPACITY),
+            speed: Speed::Max,
+            clock: Clock::unthrottled(),
+            #[cfg(feature = "scripting")]
+            script: None,
+        }
+    }
+
+    // Start debugging a machine, which may already be running. Reverse step history and snapshots
+    // from any previous machine are discarded; breakpoints, watchpoints and symbols are kept
+    pub fn attach(&mut self, processor: &Rc<RefCell<dyn ProcessorTrait>>, bus: &Rc<RefCell<dyn Bus>>) {
+        {
+            let mut link = self.link.borrow_mut();
+            link.processor = Some(Rc::downgrade(processor));
+            link.bus = Some(Rc::downgrade(bus));
+            link.peripherals = None;
+        }
+        self.history.clear();
+        self.snapshots.clear();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.clear();
+        }
+        if let Some(histogram) = &mut self.histogram {
+            histogram.clear();
+        }
+        self.write_log.clear();
+    }
+
+    // Let go of the machine. It carries on untouched by the debugger
+    pub fn detach(&mut self) {
+        *self.link.borrow_mut() = Link::default();
+        self.history.clear();
+        self.snapshots.clear();
+    }
+
+    // The machine's devices, e.g. System::get_peripherals, ticked with every instruction the
+    // debugger runs as System::step ticks them, so timers and IRQ lines keep up with the
+    // processor. Without them only the processor runs. attach forgets them
+    pub fn attach_peripherals(&mut self, peripherals: &Rc<RefCell<Peripherals>>) {
+        self.link.borrow_mut().peripherals = Some(Rc::downgrade(peripherals));
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.attached().is_ok()
+    }
+
+    pub fn add_breakpoint(&mut self, address: Address) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.remove(&address)
+    }
 
+    pub fn get_breakpoints(&self) -> Vec<Address> {
+        self.breakpoints.iter().copied().collect()
     }
 
-    pub fn run(&mut self, bus: Rc<RefCell<dyn Bus>>, proc: &Rc<RefCell<dyn ProcessorTrait>>) {
-        let mut x = self.processor.upgrade().unwrap();
-        x.borrow_mut().tick(bus);
+    // Stop after any instruction that writes to `address`
+    pub fn add_watchpoint(&mut self, address: Address) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: Address) -> bool {
+        self.watchpoints.remove(&address)
+    }
 
+    pub fn get_watchpoints(&self) -> Vec<Address> {
+        self.watchpoints.iter().copied().collect()
+    }
+
+    pub fn add_symbol(&mut self, name: &str, address: Address) {
+        self.symbols.insert(name, address);
+    }
 
+    // Load an assembler label file, returning how many symbols it defined
+    pub fn load_symbols(&mut self, text: &str) -> usize {
+        self.symbols.load(text)
     }
-}
\ No newline at end of file
+
+    // Source lines for stepping and breakpoints by line, with the labels added to the symbols.
+    // Returns the number of lines with code
+    pub fn set_debug_info(&mut self, info: DebugInfo) -> usize {
+        for (name, address) in info.get_labels() {
+            self.symbols.insert(name, *address);
+        }
+        let lines = info.len();
+        self.debug_info = Some(info);
+        lines
+    }
+
+    pub fn get_debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    // A ld65 --dbgfile, whose relative source names are taken to be next to it
+    pub fn load_debug_info_file(&mut self, path: &str) -> Result<usize, DebuggerError> {
+        let info = DebugInfo::parse(&read_file(path)?).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))?;
+        let dir = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+        Ok(self.set_debug_info(info.with_directory(dir)))
+    }
+
+    // Step until the pc lands on a breakpoint (whose script handler, if any, does not ask to
+    // keep going), a watchpoint is written or the processor hits a break. Returns any script output.
+    pub fn continue_execution(&mut self) -> Result<String, DebuggerError> {
+        let mut out = String::new();
+        for event in self.resume(RunMode::UntilStop)? {
+            match event {
+                DebugEvent::ScriptOutput(text) => out.push_str(&text),
+                DebugEvent::Stopped { reason, .. } => writeln!(out, "{}", reason).unwrap(),
+                _ => {}
+            }
+        }
+        Ok(out)
+    }
+
+    // Real time is DEFAULT_CLOCK_HZ, the debugger doesn't know the machine's clock
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.clock.set_rate(speed.get_rate(DEFAULT_CLOCK_HZ));
+    }
+
+    pub fn get_speed(&self) -> Speed {
+        self.speed
+    }
+
+    // The commands that recreate this session's breakpoints, watchpoints, symbols and settings
+    pub fn session_commands(&self) -> Vec<String> {
+        let mut commands = vec![];
+        for (name, address) in self.symbols.iter() {
+            commands.push(format!("symbol {} ${:04X}", name, address));
+        }
+        for address in &self.breakpoints {
+            commands.push(format!("break ${:04X}", address));
+        }
+        for address in &self.watchpoints {
+            commands.push(format!("watchpoint ${:04X}", address));
+        }
+        for watch in &self.watches {
+            commands.push(format!("watch {}", watch));
+        }
+        commands.push(format!("history {}", self.history.get_depth()));
+        commands.push(format!("snapshots {}", self.snapshots.get_interval()));
+        commands.push(format!("speed {}", self.speed));
+        commands
+    }
+
+    pub fn save_session(&self, path: &str) -> Result<(), DebuggerError> {
+        let mut text = String::new();
+        for command in self.session_commands() {
+            writeln!(text, "{}", command).unwrap();
+        }
+        std::fs::write(path, text).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))
+    }
+
+    // Execute every line of a file of debugger commands, such as a saved session.
+    // Blank lines and lines starting with # are skipped
+    pub fn source(&mut self, path: &str) -> Result<String, DebuggerError> {
+        let text = read_file(path)?;
+        let mut out = String::new();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            out.push_str(&self.execute(line)?);
+        }
+        Ok(out)
+    }
+
+    #[cfg(feature = "scripting")]
+    fn run_break_handler(&mut self, pc: Address, out: &mut String) -> Result<bool, DebuggerError> {
+        let keep_going = match &mut self.script {
+            Some(script) => script.on_break(pc)?,
+            None => false,
+        };
+        self.apply_script_requests(out);
+        Ok(keep_going)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn run_break_handler(&mut self, _pc: Address, _out: &mut String) -> Result<bool, DebuggerError> {
+        Ok(false)
+    }
+
+    // Run a Rhai script. Its top level runs immediately; handlers it registers with on_break run
+    // whenever `continue` reaches their address
+    #[cfg(feature = "scripting")]
+    pub fn load_script(&mut self, source: &str) -> Result<String, DebuggerError> {
+        self.script = Some(Script::new(source, Rc::clone(&self.link))?);
+        let mut out = String::new();
+        self.apply_script_requests(&mut out);
+        Ok(out)
+    }
+
+    #[cfg(feature = "scripting")]
+    fn apply_script_requests(&mut self, out: &mut String) {
+        if let Some(script) = &self.script {
+            let requests = script.take_requests();
+            for address in requests.add_breakpoints {
+                self.breakpoints.insert(address);
+            }
+            for address in requests.remove_breakpoints {
+                self.breakpoints.remove(&address);
+            }
+            out.push_str(&requests.output);
+        }
+    }
+
+    #[cfg(feature = "scripting")]
+    fn load_script_file(&mut self, path: &str) -> Result<String, DebuggerError> {
+        let source = read_file(path)?;
+        self.load_script(&source)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn load_script_file(&mut self, _path: &str) -> Result<String, DebuggerError> {
+        Err(DebuggerError::Script("built without the scripting feature".to_string()))
+    }
+
+    // Show an expression after every stop
+    pub fn add_watch(&mut self, expression: &str) -> Result<(), DebuggerError> {
+        self.watches.push(WatchExpression::parse(expression, &self.symbols)?);
+        Ok(())
+    }
+
+    // The current value of every watch expression, one per line
+    pub fn show_watches(&self) -> Result<String, DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        let registers = processor.borrow().get_registers();
+        let bus = bus.borrow();
+        let mut out = String::new();
+        for watch in &self.watches {
+            writeln!(out, "{}", watch.display(&registers, &*bus)).unwrap();
+        }
+        Ok(out)
+    }
+
+    // Assemble one instruction into memory at `address`, returning the next free address
+    pub fn assemble(&mut self, address: Address, instruction: &str) -> Result<Address, DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        let assembler = self.assembler.get_or_insert_with(MiniAssembler::new);
+        let bytes = assembler.assemble(instruction, address, &self.symbols)?;
+        for (i, b) in bytes.iter().enumerate() {
+            bus.borrow().write(address.wrapping_add(i as Address), *b);
+            processor.borrow_mut().invalidate(address.wrapping_add(i as Address));
+        }
+        Ok(address.wrapping_add(bytes.len() as Address))
+    }
+
+    // Addresses in start..=end where the pattern occurs. None matches any byte
+    pub fn find(&self, pattern: &[Option<Data>], start: Address, end: Address) -> Result<Vec<Address>, DebuggerError> {
+        let (_, bus) = self.attached()?;
+        let matches = find_pattern(&*bus.borrow(), pattern, start, end);
+        Ok(matches)
+    }
+
+    pub fn is_in_monitor(&self) -> bool {
+        self.monitor.is_some()
+    }
+
+    // "R" in the monitor sets the pc and continues until something stops execution
+    fn monitor_line(&mut self, line: &str) -> Result<String, DebuggerError> {
+        if line.trim() == "exit" {
+            self.monitor = None;
+            return Ok(String::new());
+        }
+        let (processor, bus) = self.attached()?;
+        let output = self.monitor.as_mut().unwrap().execute(line, &*bus.borrow());
+        let mut out = output.text;
+        if let Some(address) = output.run {
+            let mut registers = processor.borrow().get_registers();
+            registers.pc = address;
+            processor.borrow_mut().set_registers(&registers);
+            out.push_str(&self.continue_execution()?);
+        }
+        Ok(out)
+    }
+
+    // While assembling, the address the next line typed will be assembled at
+    pub fn get_assembly_address(&self) -> Option<Address> {
+        self.assembling
+    }
+
+    fn assemble_line(&mut self, address: Address, instruction: &str) -> Result<String, DebuggerError> {
+        let next = self.assemble(address, instruction)?;
+        if self.assembling.is_some() {
+            self.assembling = Some(next);
+        }
+        let (_, bus) = self.attached()?;
+        let bytes: Vec<String> = (0..next.wrapping_sub(address)).map(|i| format!("{:02X}", bus.borrow().peek(address.wrapping_add(i)))).collect();
+        Ok(format!("{:04X}: {:<9} {}\n", address, bytes.join(" "), instruction.trim().to_ascii_uppercase()))
+    }
+
+    // Traced instructions come before the stop output, watches after it
+    fn with_watches(&mut self, out: String) -> Result<String, DebuggerError> {
+        let mut traced = match &mut self.trace {
+            Some(trace) => trace.take_output(),
+            None => String::new(),
+        };
+        traced.push_str(&out);
+        traced.push_str(&self.show_watches()?);
+        Ok(traced)
+    }
+
+    // Log executed instructions matching the filter, to a file or to the command output
+    pub fn start_trace(&mut self, filter: TraceFilter, path: Option<&str>) -> Result<(), DebuggerError> {
+        self.stop_trace();
+        self.trace = Some(Trace::new(filter, path)?);
+        Ok(())
+    }
+
+    // Start attributing executed cycles to addresses. Profiling is off by default
+    pub fn start_profile(&mut self) {
+        self.profiler.get_or_insert_with(Profiler::default);
+    }
+
+    pub fn stop_profile(&mut self) {
+        self.profiler = None;
+    }
+
+    pub fn get_profile_report(&self, lines: usize) -> Option<String> {
+        self.profiler.as_ref().map(|p| p.report(&self.symbols, lines))
+    }
+
+    // Which addresses have executed since the debugger attached. None before the first step
+    pub fn get_coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Executions per opcode since the debugger attached. None before the first step
+    pub fn get_histogram(&self) -> Option<&InstructionHistogram> {
+        self.histogram.as_ref()
+    }
+
+    // The most recent write to address, "who wrote $D012 last"
+    pub fn get_last_write(&self, address: Address) -> Option<&WriteRecord> {
+        self.write_log.writes_to(address).next()
+    }
+
+    // Put back the old values of the most recent count writes, newest first. Returns how many were undone
+    pub fn undo_writes(&mut self, count: usize) -> Result<usize, DebuggerError> {
+        let (_, bus) = self.attached()?;
+        let mut undone = 0;
+        while undone < count {
+            match self.write_log.pop() {
+                Some(record) => bus.borrow().write(record.address, record.old),
+                None => break,
+            }
+            undone += 1;
+        }
+        Ok(undone)
+    }
+
+    // Stops tracing, returning how many lines were logged
+    pub fn stop_trace(&mut self) -> Option<usize> {
+        let mut trace = self.trace.take()?;
+        trace.take_output();
+        Some(trace.get_lines())
+    }
+
+    fn symbol_suffix(&self, address: Address) -> String {
+        match self.symbols.name_for(address) {
+            Some(name) => format!(" <{}>", name),
+            None => String::new(),
+        }
+    }
+
+    // Cycles between automatic machine snapshots, 0 turns them off
+    pub fn set_snapshot_interval(&mut self, cycles: usize) {
+        self.snapshots.set_interval(cycles);
+    }
+
+    // How many instructions can be stepped backwards
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_depth(depth);
+    }
+
+    pub fn get_history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn get_snapshot_count(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    fn attached(&self) -> Result<Attached, DebuggerError> {
+        self.link.borrow().upgrade()
+    }
+
+    // Run a single instruction to completion, remembering how to undo it
+    pub fn step(&mut self) -> Result<Registers, DebuggerError> {
+        self.step_instruction()?;
+        let (processor, _) = self.attached()?;
+        let registers = processor.borrow().get_registers();
+        Ok(registers)
+    }
+
+    // Step instructions until at least `cycles` have elapsed or the processor hits a break,
+    // paced to the debugger's speed
+    pub fn run(&mut self, cycles: usize) -> Result<Registers, DebuggerError> {
+        let (processor, _) = self.attached()?;
+        if self.speed == Speed::SingleStep {
+            self.step_instruction()?;
+            return Ok(processor.borrow().get_registers());
+        }
+        self.clock.resync();
+        let end = processor.borrow().get_total_cycles() + cycles;
+        while processor.borrow().get_total_cycles() < end {
+            let before = processor.borrow().get_total_cycles();
+            let stopped = self.step_instruction()?.is_some();
+            self.clock.advance(processor.borrow().get_total_cycles() - before);
+            if stopped {
+                break;
+            }
+        }
+        let registers = processor.borrow().get_registers();
+        Ok(registers)
+    }
+
+    // Restore the nearest snapshot at or before `cycle` and re-execute up to exactly that cycle,
+    // ticking the peripherals with each instruction as stepping does. Reverse step history, and
+    // the snapshots and logged writes from after `cycle`, do not survive the trip.
+    pub fn travel(&mut self, cycle: usize) -> Result<Registers, DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        let snapshot = self.snapshots.nearest(cycle).ok_or(DebuggerError::NoSnapshotBefore(cycle))?;
+        processor.borrow_mut().restore(&snapshot.processor);
+        bus.borrow().load_state(&snapshot.devices);
+        self.history.clear();
+        self.snapshots.forget_after(cycle);
+        self.write_log.forget_from(cycle);
+
+        let mut cycles = 0;
+        while processor.borrow().get_total_cycles() < cycle {
+            let (_, at_break) = processor.borrow_mut().tick(&*bus.borrow());
+            cycles += 1;
+            if at_break {
+                break;
+            }
+            if processor.borrow().is_at_instruction_boundary() {
+                self.link.borrow().tick_peripherals(cycles);
+                cycles = 0;
+            }
+        }
+        // an instruction stopped part way has had its cycles so far
+        if cycles > 0 {
+            self.link.borrow().tick_peripherals(cycles);
+        }
+        let registers = processor.borrow().get_registers();
+        Ok(registers)
+    }
+
+    fn take_snapshot_if_due(&mut self) -> Result<(), DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        let processor = processor.borrow();
+        if processor.is_at_instruction_boundary() && self.snapshots.is_due(processor.get_total_cycles()) {
+            self.snapshots.push(MachineSnapshot {
+                processor: processor.snapshot(),
+                devices: bus.borrow().save_state(),
+            });
+        }
+        Ok(())
+    }
+
+    // Returns why the instruction stopped execution, if it did
+    fn step_instruction(&mut self) -> Result<Option<StopReason>, DebuggerError> {
+        self.take_snapshot_if_due()?;
+        let (processor, bus) = self.attached()?;
+        let recorder = Rc::new(RefCell::new(RecordingBus::new(Rc::clone(&bus))));
+        let recording_bus: Rc<RefCell<dyn Bus>> = recorder.clone();
+
+        let before = processor.borrow().snapshot();
+        if processor.borrow().is_at_instruction_boundary() {
+            let pc = before.get_pc();
+            let opcode = bus.borrow().peek(pc);
+            self.coverage.get_or_insert_with(Coverage::new).mark(pc, opcode);
+            self.histogram.get_or_insert_with(InstructionHistogram::new).record(opcode);
+            if let Some(trace) = &mut self.trace {
+                trace.record(&processor.borrow().get_registers(), &*bus.borrow());
+            }
+        }
+        let at_break = loop {
+            let (_, at_break) = processor.borrow_mut().tick(&*recording_bus.borrow());
+            if at_break || processor.borrow().is_at_instruction_boundary() {
+                break at_break;
+            }
+        };
+        let cycles = processor.borrow().get_total_cycles() - before.get_total_cycles();
+        self.link.borrow().tick_peripherals(cycles);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(before.get_pc(), cycles as u64);
+        }
+        let writes = recorder.borrow().take_writes();
+        let watched = writes.iter().map(|(a, _, _)| *a).find(|a| self.watchpoints.contains(a));
+        for (address, old, new) in writes.iter() {
+            self.write_log.push(WriteRecord {
+                address: *address,
+                old: *old,
+                new: *new,
+                pc: before.get_pc(),
+                cycle: before.get_total_cycles(),
+            });
+        }
+        let writes = writes.into_iter().map(|(address, old, _)| (address, old)).collect();
+        self.history.push(HistoryEntry { before, writes });
+
+        if at_break {
+            Ok(Some(StopReason::Break))
+        } else {
+            Ok(watched.map(StopReason::Watchpoint))
+        }
+    }
+
+    // Undo the most recent instruction. None when the history is exhausted
+    pub fn reverse_step(&mut self) -> Result<Option<Registers>, DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        match self.history.pop() {
+            None => Ok(None),
+            Some(entry) => {
+                for (address, old) in entry.writes.iter().rev() {
+                    bus.borrow().write(*address, *old);
+                }
+                self.write_log.forget_from(entry.before.get_total_cycles());
+                processor.borrow_mut().restore(&entry.before);
+                let registers = processor.borrow().get_registers();
+                Ok(Some(registers))
+            }
+        }
+    }
+
+    // Execute one line of debugger input returning the text to show the user
+    pub fn execute(&mut self, line: &str) -> Result<String, DebuggerError> {
+        if let Some(address) = self.assembling {
+            let line = line.trim();
+            if line.is_empty() || line == "." {
+                self.assembling = None;
+                return Ok(String::new());
+            }
+            return self.assemble_line(address, line);
+        }
+        if self.monitor.is_some() {
+            return self.monitor_line(line);
+        }
+        match parse_command(line, &self.symbols)? {
+            Commands::STEP { count } => {
+                let mut out = String::new();
+                for _ in 0..count {
+                    writeln!(out, "{}", self.step()?).unwrap();
+                }
+                self.with_watches(out)
+            }
+            Commands::ReverseStep { count } => {
+                let mut out = String::new();
+                for _ in 0..count {
+                    match self.reverse_step()? {
+                        Some(registers) => writeln!(out, "{}", registers).unwrap(),
+                        None => {
+                            writeln!(out, "no more history").unwrap();
+                            break;
+                        }
+                    }
+                }
+                self.with_watches(out)
+            }
+            Commands::ShowRegisters => {
+                let (processor, _) = self.attached()?;
+                let registers = processor.borrow().get_registers();
+                Ok(format!("{}\n", registers))
+            }
+            Commands::DumpMemoryRange { start, end } => {
+                let (_, bus) = self.attached()?;
+                let dump = hexdump::dump(&*bus.borrow(), start, end);
+                Ok(dump)
+            }
+            Commands::Disassemble { start, count } => {
+                let (processor, bus) = self.attached()?;
+                let mut address = start.unwrap_or_else(|| processor.borrow().get_registers().pc);
+                let disassembler = Disassembler::new();
+                let mut out = String::new();
+                for _ in 0..count {
+                    let line = disassembler.decode(&*bus.borrow(), address);
+                    writeln!(out, "{}{}", line, self.symbol_suffix(address)).unwrap();
+                    address = address.wrapping_add(line.len() as Address);
+                }
+                Ok(out)
+            }
+            Commands::HistoryDepth { depth } => {
+                if let Some(d) = depth {
+                    self.history.set_depth(d);
+                }
+                Ok(format!("{} of {} instructions recorded\n", self.history.len(), self.history.get_depth()))
+            }
+            Commands::Run { cycles } => {
+                let out = format!("{}\n", self.run(cycles)?);
+                self.with_watches(out)
+            }
+            Commands::Travel { cycle } => {
+                let out = format!("{}\n", self.travel(cycle)?);
+                self.with_watches(out)
+            }
+            Commands::Break { address } => {
+                self.add_breakpoint(address);
+                Ok(format!("breakpoint at ${:04X}\n", address))
+            }
+            Commands::Delete { address } => {
+                if self.remove_breakpoint(address) {
+                    Ok(format!("deleted breakpoint at ${:04X}\n", address))
+                } else if self.remove_watchpoint(address) {
+                    Ok(format!("deleted watchpoint at ${:04X}\n", address))
+                } else {
+                    Err(DebuggerError::BadArgument(format!("${:04X}", address)))
+                }
+            }
+            Commands::Watchpoint { address } => {
+                self.add_watchpoint(address);
+                Ok(format!("watchpoint at ${:04X}\n", address))
+            }
+            Commands::ListBreakpoints => {
+                let mut out = String::new();
+                for address in &self.breakpoints {
+                    writeln!(out, "break ${:04X}{}", address, self.symbol_suffix(*address)).unwrap();
+                }
+                for address in &self.watchpoints {
+                    writeln!(out, "watchpoint ${:04X}{}", address, self.symbol_suffix(*address)).unwrap();
+                }
+                Ok(out)
+            }
+            Commands::Symbol { name, address } => {
+                self.add_symbol(&name, address);
+                Ok(String::new())
+            }
+            Commands::LoadSymbols { path } => {
+                let count = self.load_symbols(&read_file(&path)?);
+                Ok(format!("loaded {} symbols\n", count))
+            }
+            Commands::LoadDebugInfo { path } => {
+                let lines = self.load_debug_info_file(&path)?;
+                Ok(format!("loaded {} source lines\n", lines))
+            }
+            Commands::ListSymbols => {
+                let mut out = String::new();
+                for (name, address) in self.symbols.iter() {
+                    writeln!(out, "${:04X} {}", address, name).unwrap();
+                }
+                Ok(out)
+            }
+            Commands::SaveSession { path } => {
+                self.save_session(&path)?;
+                Ok(format!("saved session to {}\n", path))
+            }
+            Commands::Source { path } => self.source(&path),
+            Commands::Detach => {
+                self.detach();
+                Ok("detached\n".to_string())
+            }
+            Commands::Watch { expression } => {
+                self.watches.push(expression);
+                self.show_watches()
+            }
+            Commands::Unwatch { index } => {
+                if index == 0 || index > self.watches.len() {
+                    return Err(DebuggerError::BadArgument(index.to_string()));
+                }
+                let removed = self.watches.remove(index - 1);
+                Ok(format!("removed watch {}\n", removed))
+            }
+            Commands::Find { pattern, start, end } => {
+                let matches = self.find(&pattern, start, end)?;
+                let (_, bus) = self.attached()?;
+                let mut out = String::new();
+                for address in matches.iter().take(MAX_FIND_MATCHES_SHOWN) {
+                    writeln!(out, "{}", format_match(&*bus.borrow(), *address, pattern.len())).unwrap();
+                }
+                if matches.len() > MAX_FIND_MATCHES_SHOWN {
+                    writeln!(out, "... {} more", matches.len() - MAX_FIND_MATCHES_SHOWN).unwrap();
+                }
+                writeln!(out, "{} matches", matches.len()).unwrap();
+                Ok(out)
+            }
+            Commands::InfoDevice { name } => {
+                let (_, bus) = self.attached()?;
+                let devices = bus.borrow().describe_devices();
+                match name {
+                    None => Ok(devices.iter().map(|(n, _)| format!("{}\n", n)).collect()),
+                    Some(name) => {
+                        let matching: String =
+                            devices.iter().filter(|(n, _)| n.eq_ignore_ascii_case(&name)).map(|(_, state)| state.as_str()).collect();
+                        if matching.is_empty() {
+                            Err(DebuggerError::BadArgument(name))
+                        } else {
+                            Ok(matching)
+                        }
+                    }
+                }
+            }
+            Commands::TraceOn { filter, path } => {
+                self.start_trace(filter, path.as_deref())?;
+                Ok(format!("{}\n", self.trace.as_ref().unwrap().describe()))
+            }
+            Commands::TraceOff => match self.stop_trace() {
+                Some(lines) => Ok(format!("trace off, {} lines\n", lines)),
+                None => Ok("not tracing\n".to_string()),
+            },
+            Commands::TraceStatus => match &self.trace {
+                Some(trace) => Ok(format!("{}\n", trace.describe())),
+                None => Ok("not tracing\n".to_string()),
+            },
+            Commands::Profile { action } => match action {
+                ProfileAction::On => {
+                    self.start_profile();
+                    Ok("profiling\n".to_string())
+                }
+                ProfileAction::Off => {
+                    self.stop_profile();
+                    Ok("profiling off\n".to_string())
+                }
+                ProfileAction::Reset => {
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.clear();
+                    }
+                    Ok(String::new())
+                }
+                ProfileAction::Report { lines } => match self.get_profile_report(lines) {
+                    Some(report) => Ok(report),
+                    None => Ok("not profiling, use profile on\n".to_string()),
+                },
+            },
+            Commands::Coverage { start, end, path } => {
+                let report = match &self.coverage {
+                    Some(coverage) => coverage.report(start, end),
+                    None => "nothing executed yet\n".to_string(),
+                };
+                match path {
+                    None => Ok(report),
+                    Some(path) => {
+                        std::fs::write(&path, &report).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))?;
+                        Ok(format!("coverage written to {}\n", path))
+                    }
+                }
+            }
+            Commands::CoverageReset => {
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.clear();
+                }
+                Ok(String::new())
+            }
+            Commands::Histogram { per_opcode, lines } => match &self.histogram {
+                Some(histogram) => Ok(histogram.report(per_opcode, lines)),
+                None => Ok("nothing executed yet\n".to_string()),
+            },
+            Commands::HistogramReset => {
+                if let Some(histogram) = &mut self.histogram {
+                    histogram.clear();
+                }
+                Ok(String::new())
+            }
+            Commands::Writes { address, lines } => {
+                let records: Vec<&WriteRecord> = match address {
+                    Some(address) => self.write_log.writes_to(address).take(lines).collect(),
+                    None => self.write_log.recent().take(lines).collect(),
+                };
+                if records.is_empty() {
+                    return Ok("no writes recorded\n".to_string());
+                }
+                let mut out = String::new();
+                for record in records {
+                    writeln!(out, "{}{}", record, self.symbol_suffix(record.pc)).unwrap();
+                }
+                Ok(out)
+            }
+            Commands::Undo { count } => {
+                let undone = self.undo_writes(count)?;
+                Ok(format!("undid {} writes\n", undone))
+            }
+            Commands::Speed { speed } => {
+                if let Some(speed) = speed {
+                    self.set_speed(speed);
+                }
+                Ok(format!("speed {}\n", self.speed))
+            }
+            Commands::Monitor => {
+                self.monitor.get_or_insert_with(Monitor::new);
+                Ok("woz monitor, exit to leave\n".to_string())
+            }
+            Commands::Assemble { address, instruction } => match instruction {
+                Some(i) => self.assemble_line(address, &i),
+                None => {
+                    self.assembling = Some(address);
+                    Ok(String::new())
+                }
+            },
+            Commands::Load { path, address } => {
+                // a .hex dump says where its bytes go; a binary image goes at the address given
+                let blocks = if path.ends_with(".hex") {
+                    let text = read_file(&path)?;
+                    hexdump::parse(&text, address.unwrap_or(0)).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))?
+                } else {
+                    let address = address.ok_or_else(|| DebuggerError::BadArgument(format!("load {} needs an address", path)))?;
+                    let data = std::fs::read(&path).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))?;
+                    vec![(address, data)]
+                };
+                let mut out = String::new();
+                for (address, data) in &blocks {
+                    self.write_memory(*address, data)?;
+                    writeln!(out, "loaded {} bytes at ${:04X}", data.len(), address).unwrap();
+                }
+                Ok(out)
+            }
+            Commands::ListWatches => {
+                let mut out = String::new();
+                for (i, watch) in self.watches.iter().enumerate() {
+                    writeln!(out, "{}: {}", i + 1, watch).unwrap();
+                }
+                Ok(out)
+            }
+            Commands::Continue => {
+                let mut out = self.continue_execution()?;
+                let (processor, _) = self.attached()?;
+                writeln!(out, "{}", processor.borrow().get_registers()).unwrap();
+                self.with_watches(out)
+            }
+            Commands::LoadScript { path } => self.load_script_file(&path),
+            Commands::GdbServer { port } => {
+                self.serve_gdb(("127.0.0.1", port))
+                    .map_err(|e| DebuggerError::BadArgument(format!("gdb server: {}", e)))?;
+                Ok("gdb detached\n".to_string())
+            }
+            Commands::DapServer { port } => {
+                self.serve_dap(("127.0.0.1", port))
+                    .map_err(|e| DebuggerError::BadArgument(format!("dap server: {}", e)))?;
+                Ok("dap client disconnected\n".to_string())
+            }
+            Commands::RemoteServer { port } => {
+                self.serve_remote(("127.0.0.1", port))
+                    .map_err(|e| DebuggerError::BadArgument(format!("remote server: {}", e)))?;
+                Ok("remote server shut down\n".to_string())
+            }
+            Commands::SnapshotInterval { interval } => {
+                if let Some(i) = interval {
+                    self.snapshots.set_interval(i);
+                }
+                Ok(format!("{} snapshots, one every {} cycles\n", self.snapshots.len(), self.snapshots.get_interval()))
+            }
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<String, DebuggerError> {
+    std::fs::read_to_string(path).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))
+}
@@ -0,0 +1,82 @@
+use std::fmt::Write;
+
+use crate::bus::Address;
+use crate::processor::create_instruction_table;
+
+// One bit per address for every byte of every instruction executed, opcode and operands
+pub struct Coverage {
+    bits: Vec<u64>,
+    // bytes covered by each opcode, unknown opcodes count as one
+    lengths: [u8; 256],
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        let mut lengths = [1; 256];
+        for (opcode, instruction) in create_instruction_table().iter() {
+            lengths[opcode as usize] = instruction.get_addressing().operand_length() as u8 + 1;
+        }
+        Coverage { bits: vec![0; 0x10000 / 64], lengths }
+    }
+
+    pub fn mark(&mut self, address: Address, opcode: u8) {
+        for i in 0..self.lengths[opcode as usize] {
+            let a = address.wrapping_add(i as Address) as usize;
+            self.bits[a / 64] |= 1 << (a % 64);
+        }
+    }
+
+    pub fn is_executed(&self, address: Address) -> bool {
+        let a = address as usize;
+        self.bits[a / 64] & (1 << (a % 64)) != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|b| *b = 0);
+    }
+
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    // Runs of executed addresses within start..=end
+    pub fn ranges(&self, start: Address, end: Address) -> Vec<(Address, Address)> {
+        let mut ranges = vec![];
+        let mut run: Option<Address> = None;
+        for a in start as u32..=end as u32 {
+            let address = a as Address;
+            match (run, self.is_executed(address)) {
+                (None, true) => run = Some(address),
+                (Some(first), false) => {
+                    ranges.push((first, address - 1));
+                    run = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = run {
+            ranges.push((first, end));
+        }
+        ranges
+    }
+
+    // "$0200-$020F  16 bytes" per executed run, then the total within the range
+    pub fn report(&self, start: Address, end: Address) -> String {
+        let mut out = String::new();
+        let mut total = 0;
+        for (first, last) in self.ranges(start, end) {
+            let bytes = (last - first) as usize + 1;
+            total += bytes;
+            writeln!(out, "${:04X}-${:04X}  {} bytes", first, last, bytes).unwrap();
+        }
+        let size = (end as usize).saturating_sub(start as usize) + 1;
+        writeln!(out, "{} of {} bytes executed", total, size).unwrap();
+        out
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use crate::bus::{Address, Data};
+use crate::debugger::wire::{base64, base64_decode, json_string};
+use crate::debugger::{parse_address, DebugEvent, Debugger, DebuggerError, RunMode, StopReason};
+use crate::disasm::Disassembler;
+use crate::processor::Registers;
+use crate::json::Value;
+
+// The Debug Adapter Protocol, so VS Code and other editors can debug a program in the emulator:
+// breakpoints on source lines or addresses, stepping, registers as variables, a memory view and
+// disassembly, and debugger commands in the debug console. The machine is set up before the
+// adapter starts, typically by `sim6502 debug program.bin --dap` as the editor's adapter
+// command. Source lines come from ca65 debug info, loaded beforehand or named by a launch
+// request's "debugInfo"; without it breakpoints go on addresses from the disassembly view.
+// There's one thread, the processor, and one stack frame, the pc.
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+// A frame's worth at 1MHz between looks for requests while running
+const CYCLES_PER_SLICE: usize = 16_667;
+// How far a line step or step out goes before giving up
+const STEP_LIMIT: usize = 1_000_000;
+// The longest message body taken from a client, as for the remote server's messages
+const MAX_MESSAGE: usize = 1 << 20;
+
+const JSR: Data = 0x20;
+const RTS: Data = 0x60;
+const RTI: Data = 0x40;
+
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"))?;
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too long"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).to_string()))
+}
+
+fn memory_reference(address: Address) -> String {
+    format!("\"0x{:04X}\"", address)
+}
+
+fn get_reference(arguments: &Value, key: &str) -> Result<Address, String> {
+    let reference = arguments.get(key).and_then(Value::as_str).ok_or(format!("expected a {}", key))?;
+    parse_address(reference).map_err(|e| e.to_string())
+}
+
+fn get_offset(arguments: &Value, key: &str) -> i64 {
+    match arguments.get(key) {
+        Some(Value::Number(n)) => *n as i64,
+        _ => 0,
+    }
+}
+
+fn stop_reason(reason: &StopReason) -> (&'static str, String) {
+    match reason {
+        StopReason::Break => ("exception", "BRK".to_string()),
+        StopReason::Breakpoint(_) => ("breakpoint", reason.to_string()),
+        StopReason::Watchpoint(_) => ("data breakpoint", reason.to_string()),
+    }
+}
+
+fn flags(status: Data) -> String {
+    "NV-BDIZC".chars().enumerate().map(|(i, flag)| if status & (0x80 >> i) != 0 { flag } else { '.' }).collect()
+}
+
+struct Dap<W: Write> {
+    output: W,
+    seq: u64,
+    running: bool,
+    faulted: bool,
+    stop_on_entry: bool,
+    done: bool,
+    // the addresses each source's breakpoints went on, replaced on each setBreakpoints
+    source_breakpoints: HashMap<String, Vec<Address>>,
+    instruction_breakpoints: Vec<Address>,
+    disassembler: Disassembler,
+}
+
+impl<W: Write> Dap<W> {
+    fn send(&mut self, message: String) -> io::Result<()> {
+        self.seq += 1;
+        let message = format!("{{\"seq\":{},{}", self.seq, &message[1..]);
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", message.len(), message)?;
+        self.output.flush()
+    }
+
+    fn event(&mut self, event: &str, body: &str) -> io::Result<()> {
+        self.send(format!("{{\"type\":\"event\",\"event\":\"{}\",\"body\":{{{}}}}}", event, body))
+    }
+
+    fn stopped(&mut self, reason: &str, description: &str) -> io::Result<()> {
+        let body = format!(
+            "\"reason\":\"{}\",\"description\":{},\"threadId\":{},\"allThreadsStopped\":true",
+            reason,
+            json_string(description),
+            THREAD_ID
+        );
+        self.event("stopped", &body)
+    }
+
+    fn output(&mut self, text: &str) -> io::Result<()> {
+        self.event("output", &format!("\"category\":\"console\",\"output\":{}", json_string(text)))
+    }
+}
+
+impl Debugger {
+    // Serve the Debug Adapter Protocol on `address` until the client disconnects
+    pub fn serve_dap(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        self.serve_dap_on(&listener)
+    }
+
+    // Accept a single client from an already bound listener
+    pub fn serve_dap_on(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        self.serve_dap_stream(stream.try_clone()?, stream)
+    }
+
+    // Serve one client over a pair of streams, e.g. stdin and stdout when the editor starts
+    // the adapter itself. Requests are read on a thread of their own so the machine can run
+    // between them
+    pub fn serve_dap_stream<R, W>(&mut self, input: R, output: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut dap = Dap {
+            output,
+            seq: 0,
+            running: false,
+            faulted: false,
+            stop_on_entry: false,
+            done: false,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: vec![],
+            disassembler: Disassembler::new(),
+        };
+        while !dap.done {
+            let message = if dap.running {
+                match requests.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                match requests.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                }
+            };
+            match message {
+                // anything that isn't JSON is ignored, there's no seq to answer
+                Some(message) => match Value::parse(&message) {
+                    Ok(request) => self.dap_request(&mut dap, &request)?,
+                    Err(e) => log::warn!("dap: bad message: {}", e),
+                },
+                None => self.dap_slice(&mut dap)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn dap_slice<W: Write>(&mut self, dap: &mut Dap<W>) -> io::Result<()> {
+        match self.dap_guarded(dap, |debugger| debugger.resume(RunMode::Cycles(CYCLES_PER_SLICE))) {
+            Ok(events) => {
+                for event in events {
+                    match event {
+                        DebugEvent::Stopped { reason, .. } => {
+                            dap.running = false;
+                            let (reason, description) = stop_reason(&reason);
+                            dap.stopped(reason, &description)?;
+                        }
+                        DebugEvent::ScriptOutput(text) => dap.output(&text)?,
+                        DebugEvent::Stepped { .. } | DebugEvent::CyclesElapsed { .. } => (),
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                dap.running = false;
+                dap.stopped("exception", &e)
+            }
+        }
+    }
+
+    // Runs the machine, turning a processor panic into a fault
+    fn dap_guarded<W: Write, T>(
+        &mut self,
+        dap: &mut Dap<W>,
+        run: impl FnOnce(&mut Debugger) -> Result<T, DebuggerError>,
+    ) -> Result<T, String> {
+        if dap.faulted {
+            return Err("the processor has faulted".to_string());
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| run(self))) {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => {
+                dap.faulted = true;
+                Err("the processor has faulted on an opcode it doesn't know".to_string())
+            }
+        }
+    }
+
+    fn dap_request<W: Write>(&mut self, dap: &mut Dap<W>, request: &Value) -> io::Result<()> {
+        let seq = request.get("seq").and_then(Value::as_u64).unwrap_or(0);
+        let command = request.get("command").and_then(Value::as_str).unwrap_or("").to_string();
+        let empty = Value::Object(vec![]);
+        let arguments = request.get("arguments").unwrap_or(&empty);
+        let result = self.dap_command(dap, &command, arguments);
+        let response = match &result {
+            Ok(body) => format!(
+                "{{\"type\":\"response\",\"request_seq\":{},\"success\":true,\"command\":{},\"body\":{{{}}}}}",
+                seq,
+                json_string(&command),
+                body
+            ),
+            Err(e) => format!(
+                "{{\"type\":\"response\",\"request_seq\":{},\"success\":false,\"command\":{},\"message\":{}}}",
+                seq,
+                json_string(&command),
+                json_string(e)
+            ),
+        };
+        dap.send(response)?;
+        if result.is_err() {
+            return Ok(());
+        }
+        // events that have to follow the response
+        match command.as_str() {
+            "initialize" => dap.event("initialized", ""),
+            "configurationDone" if dap.stop_on_entry => dap.stopped("entry", "entry"),
+            "configurationDone" => {
+                dap.running = true;
+                Ok(())
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let granularity = arguments.get("granularity").and_then(Value::as_str).unwrap_or("statement");
+                let (reason, description) = match self.dap_step(dap, &command, granularity == "instruction") {
+                    Ok(Some(reason)) => stop_reason(&reason),
+                    Ok(None) => ("step", "step".to_string()),
+                    Err(e) => ("exception", e),
+                };
+                dap.stopped(reason, &description)
+            }
+            "pause" if dap.running => {
+                dap.running = false;
+                dap.stopped("pause", "paused")
+            }
+            "terminate" => dap.event("terminated", ""),
+            _ => Ok(()),
+        }
+    }
+
+    // The response's body, the fields inside its braces
+    fn dap_command<W: Write>(&mut self, dap: &mut Dap<W>, command: &str, arguments: &Value) -> Result<String, String> {
+        match command {
+            "initialize" => Ok([
+                "\"supportsConfigurationDoneRequest\":true",
+                "\"supportsSetVariable\":true",
+                "\"supportsReadMemoryRequest\":true",
+                "\"supportsWriteMemoryRequest\":true",
+                "\"supportsDisassembleRequest\":true",
+                "\"supportsInstructionBreakpoints\":true",
+                "\"supportsSteppingGranularity\":true",
+                "\"supportsTerminateRequest\":true",
+            ]
+            .join(",")),
+            "launch" | "attach" => {
+                dap.stop_on_entry = matches!(arguments.get("stopOnEntry"), Some(Value::Bool(true)));
+                if let Some(path) = arguments.get("debugInfo").and_then(Value::as_str) {
+                    self.load_debug_info_file(path).map_err(|e| e.to_string())?;
+                }
+                Ok(String::new())
+            }
+            "configurationDone" | "setExceptionBreakpoints" => Ok(String::new()),
+            "disconnect" | "terminate" => {
+                dap.done = command == "disconnect";
+                Ok(String::new())
+            }
+            "threads" => Ok(format!("\"threads\":[{{\"id\":{},\"name\":\"6502\"}}]", THREAD_ID)),
+            "setBreakpoints" => self.dap_set_breakpoints(dap, arguments),
+            "setInstructionBreakpoints" => {
+                for address in dap.instruction_breakpoints.drain(..) {
+                    self.breakpoints.remove(&address);
+                }
+                let mut breakpoints = vec![];
+                for breakpoint in arguments.get("breakpoints").and_then(Value::as_array).unwrap_or(&[]) {
+                    let address = get_reference(breakpoint, "instructionReference")?;
+                    let address = address.wrapping_add(get_offset(breakpoint, "offset") as Address);
+                    self.add_breakpoint(address);
+                    dap.instruction_breakpoints.push(address);
+                    breakpoints.push(format!("{{\"verified\":true,\"instructionReference\":{}}}", memory_reference(address)));
+                }
+                Ok(format!("\"breakpoints\":[{}]", breakpoints.join(",")))
+            }
+            "stackTrace" => {
+                let registers = self.get_registers().map_err(|e| e.to_string())?;
+                let name = match self.symbols.containing(registers.pc) {
+                    Some((name, address)) if address == registers.pc => name.to_string(),
+                    Some((name, address)) => format!("{}+{}", name, registers.pc - address),
+                    None => format!("${:04X}", registers.pc),
+                };
+                let source = match self.debug_info.as_ref().and_then(|info| info.location(registers.pc)) {
+                    Some((path, line)) => {
+                        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+                        format!(",\"source\":{{\"name\":{},\"path\":{}}},\"line\":{}", json_string(name), json_string(path), line)
+                    }
+                    None => ",\"line\":0".to_string(),
+                };
+                Ok(format!(
+                    "\"stackFrames\":[{{\"id\":1,\"name\":{},\"column\":0,\"instructionPointerReference\":{}{}}}],\"totalFrames\":1",
+                    json_string(&name),
+                    memory_reference(registers.pc),
+                    source
+                ))
+            }
+            "scopes" => Ok(format!(
+                "\"scopes\":[{{\"name\":\"Registers\",\"presentationHint\":\"registers\",\"variablesReference\":{},\"expensive\":false}}]",
+                REGISTERS_REFERENCE
+            )),
+            "variables" => {
+                if arguments.get("variablesReference").and_then(Value::as_u64) != Some(REGISTERS_REFERENCE) {
+                    return Ok("\"variables\":[]".to_string());
+                }
+                let r = self.get_registers().map_err(|e| e.to_string())?;
+                let (processor, _) = self.attached().map_err(|e| e.to_string())?;
+                let cycles = processor.borrow().get_total_cycles();
+                let variable = |name: &str, value: String| {
+                    format!("{{\"name\":\"{}\",\"value\":{},\"variablesReference\":0}}", name, json_string(&value))
+                };
+                let variables = [
+                    variable("PC", format!("${:04X}", r.pc)),
+                    variable("A", format!("${:02X}", r.a)),
+                    variable("X", format!("${:02X}", r.x)),
+                    variable("Y", format!("${:02X}", r.y)),
+                    variable("P", format!("${:02X} {}", r.status, flags(r.status))),
+                    variable("cycles", cycles.to_string()),
+                ];
+                Ok(format!("\"variables\":[{}]", variables.join(",")))
+            }
+            "setVariable" => {
+                let name = arguments.get("name").and_then(Value::as_str).ok_or("expected a name")?;
+                let text = arguments.get("value").and_then(Value::as_str).ok_or("expected a value")?;
+                let value = parse_address(text.trim()).map_err(|e| e.to_string())?;
+                let mut r: Registers = self.get_registers().map_err(|e| e.to_string())?;
+                let byte = || Data::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", text));
+                let shown = match name {
+                    "PC" => {
+                        r.pc = value;
+                        format!("${:04X}", r.pc)
+                    }
+                    "A" | "X" | "Y" | "P" => {
+                        let register = match name {
+                            "A" => &mut r.a,
+                            "X" => &mut r.x,
+                            "Y" => &mut r.y,
+                            _ => &mut r.status,
+                        };
+                        *register = byte()?;
+                        format!("${:02X}", register)
+                    }
+                    _ => return Err(format!("{} can't be set", name)),
+                };
+                self.set_registers(&r).map_err(|e| e.to_string())?;
+                Ok(format!("\"value\":{}", json_string(&shown)))
+            }
+            "continue" if dap.faulted => Err("the processor has faulted, restart to carry on".to_string()),
+            "continue" => {
+                dap.running = true;
+                Ok("\"allThreadsContinued\":true".to_string())
+            }
+            "next" | "stepIn" | "stepOut" | "pause" => Ok(String::new()),
+            "readMemory" => {
+                let address = get_reference(arguments, "memoryReference")?;
+                let address = address.wrapping_add(get_offset(arguments, "offset") as Address);
+                let count = arguments.get("count").and_then(Value::as_u64).unwrap_or(0).min(0x10000) as usize;
+                let data = self.read_memory(address, count).map_err(|e| e.to_string())?;
+                Ok(format!("\"address\":{},\"data\":\"{}\"", memory_reference(address), base64(&data)))
+            }
+            "writeMemory" => {
+                let address = get_reference(arguments, "memoryReference")?;
+                let address = address.wrapping_add(get_offset(arguments, "offset") as Address);
+                let data = arguments.get("data").and_then(Value::as_str).ok_or("expected data")?;
+                let data = base64_decode(data).ok_or("data isn't base64")?;
+                self.write_memory(address, &data).map_err(|e| e.to_string())?;
+                Ok(format!("\"bytesWritten\":{}", data.len()))
+            }
+            "disassemble" => self.dap_disassemble(dap, arguments),
+            "evaluate" => {
+                let expression = arguments.get("expression").and_then(Value::as_str).ok_or("expected an expression")?.to_string();
+                let output = self.dap_guarded(dap, |debugger| debugger.execute(&expression))?;
+                Ok(format!("\"result\":{},\"variablesReference\":0", json_string(output.trim_end())))
+            }
+            _ => Err(format!("{} isn't supported", command)),
+        }
+    }
+
+    fn dap_set_breakpoints<W: Write>(&mut self, dap: &mut Dap<W>, arguments: &Value) -> Result<String, String> {
+        let source = arguments.get("source").ok_or("expected a source")?;
+        let path = source.get("path").or_else(|| source.get("name")).and_then(Value::as_str).ok_or("expected a source path")?;
+        for address in dap.source_breakpoints.remove(path).unwrap_or_default() {
+            self.breakpoints.remove(&address);
+        }
+        let mut addresses = vec![];
+        let mut breakpoints = vec![];
+        for breakpoint in arguments.get("breakpoints").and_then(Value::as_array).unwrap_or(&[]) {
+            let line = breakpoint.get("line").and_then(Value::as_u64).unwrap_or(0) as usize;
+            let address = self.debug_info.as_ref().and_then(|info| info.address_of(path, line));
+            match address {
+                Some(address) => {
+                    self.add_breakpoint(address);
+                    addresses.push(address);
+                    breakpoints.push(format!(
+                        "{{\"verified\":true,\"line\":{},\"instructionReference\":{}}}",
+                        line,
+                        memory_reference(address)
+                    ));
+                }
+                None => {
+                    let message = if self.debug_info.is_some() { "no code on this line" } else { "no debug info loaded" };
+                    breakpoints.push(format!("{{\"verified\":false,\"line\":{},\"message\":\"{}\"}}", line, message));
+                }
+            }
+        }
+        dap.source_breakpoints.insert(path.to_string(), addresses);
+        Ok(format!("\"breakpoints\":[{}]", breakpoints.join(",")))
+    }
+
+    // Instructions before the reference are found by decoding from further back and keeping
+    // the ones that line up with it, which is right for code that isn't preceded by data
+    fn dap_disassemble<W: Write>(&self, dap: &mut Dap<W>, arguments: &Value) -> Result<String, String> {
+        let (_, bus) = self.attached().map_err(|e| e.to_string())?;
+        let bus = bus.borrow();
+        let reference = get_reference(arguments, "memoryReference")?;
+        let reference = reference.wrapping_add(get_offset(arguments, "offset") as Address);
+        let skip = get_offset(arguments, "instructionOffset");
+        let count = arguments.get("instructionCount").and_then(Value::as_u64).unwrap_or(0).min(0x10000) as usize;
+
+        let mut address = reference;
+        if skip < 0 {
+            let back = skip.unsigned_abs() as usize;
+            let mut before = vec![];
+            let mut a = reference.saturating_sub((back * 3) as Address);
+            while a < reference {
+                before.push(a);
+                a = a.wrapping_add(dap.disassembler.decode(&*bus, a).len().max(1) as Address);
+                if a == 0 {
+                    break;
+                }
+            }
+            address = before.len().checked_sub(back).map_or(before.first().copied().unwrap_or(reference), |i| before[i]);
+        } else {
+            for _ in 0..skip {
+                address = address.wrapping_add(dap.disassembler.decode(&*bus, address).len().max(1) as Address);
+            }
+        }
+        let mut instructions = vec![];
+        for _ in 0..count {
+            let line = dap.disassembler.decode(&*bus, address);
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let mut instruction = format!(
+                "{{\"address\":{},\"instructionBytes\":\"{}\",\"instruction\":{}",
+                memory_reference(address),
+                bytes.join(" "),
+                json_string(format!("{} {}", line.mnemonic, line.operand).trim_end())
+            );
+            if let Some((path, line)) = self.debug_info.as_ref().and_then(|info| info.location(address)) {
+                instruction += &format!(",\"location\":{{\"path\":{}}},\"line\":{}", json_string(path), line);
+            }
+            instruction.push('}');
+            instructions.push(instruction);
+            address = address.wrapping_add(line.len().max(1) as Address);
+        }
+        Ok(format!("\"instructions\":[{}]", instructions.join(",")))
+    }
+
+    // One instruction, or with debug info one source line, stepping over subroutine calls for
+    // next. Step out runs to the first RTS or RTI, the stack pointer not being to hand. Returns
+    // what stopped it early, a breakpoint say
+    fn dap_step<W: Write>(&mut self, dap: &mut Dap<W>, command: &str, instruction: bool) -> Result<Option<StopReason>, String> {
+        let (processor, bus) = self.attached().map_err(|e| e.to_string())?;
+        let pc = || processor.borrow().get_registers().pc;
+        let location = |debugger: &Debugger, pc| {
+            let info = debugger.debug_info.as_ref()?;
+            info.location(pc).map(|(file, line)| (file.to_string(), line))
+        };
+        let start = location(self, pc());
+        let by_line = !instruction && start.is_some();
+        let mut returning_to = None;
+        for _ in 0..STEP_LIMIT {
+            let opcode = bus.borrow().peek(pc());
+            if command == "next" && opcode == JSR && returning_to.is_none() {
+                returning_to = Some(pc().wrapping_add(3));
+            }
+            let events = self.dap_guarded(dap, |debugger| debugger.resume(RunMode::Step(1)))?;
+            if let Some(DebugEvent::Stopped { reason, .. }) = events.last() {
+                return Ok(Some(reason.clone()));
+            }
+            if returning_to == Some(pc()) {
+                returning_to = None;
+            }
+            let done = match command {
+                "stepOut" => opcode == RTS || opcode == RTI,
+                _ if returning_to.is_some() => false,
+                _ if !by_line => true,
+                // lines without code of their own, e.g. in a macro, are stepped through
+                _ => location(self, pc()).is_some_and(|here| Some(here) != start),
+            };
+            if done {
+                return Ok(None);
+            }
+            if self.breakpoints.contains(&pc()) {
+                return Ok(Some(StopReason::Breakpoint(pc())));
+            }
+        }
+        Ok(None)
+    }
+}
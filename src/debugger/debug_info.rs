@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::bus::Address;
+
+// Source lines and labels from a cc65 debug info file (ld65 --dbgfile), for showing where the
+// pc is in the source and putting breakpoints on lines. Only what that needs is read: files,
+// segments, spans, lines and label symbols
+#[derive(Default, Debug)]
+pub struct DebugInfo {
+    files: Vec<String>,
+    // the first address of each span to its end, file and line
+    spans: BTreeMap<Address, (usize, usize, usize)>,
+    // a file and line to the lowest address generated for it
+    addresses: HashMap<(usize, usize), Address>,
+    labels: Vec<(String, Address)>,
+}
+
+// line type=: 0 assembler source, 1 C source, 2 a macro expansion
+const LINE_C: u64 = 1;
+const LINE_MACRO: u64 = 2;
+
+// The key=value fields of a record, where a value may be a quoted string with commas in it
+fn fields(text: &str) -> HashMap<&str, &str> {
+    let mut fields = HashMap::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let Some((key, value)) = rest.split_once('=') else {
+            break;
+        };
+        let end = if let Some(quoted) = value.strip_prefix('"') {
+            quoted.find('"').map_or(value.len(), |i| i + 2)
+        } else {
+            value.find(',').unwrap_or(value.len())
+        };
+        fields.insert(key.trim(), value[..end].trim_matches('"'));
+        rest = value[end..].trim_start_matches(',');
+    }
+    fields
+}
+
+fn number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn field(fields: &HashMap<&str, &str>, key: &str, record: &str) -> Result<u64, String> {
+    fields.get(key).and_then(|v| number(v)).ok_or_else(|| format!("{} record without a {}", record, key))
+}
+
+// Whether two paths name the same file: equal, or one ends with the other's components, as a
+// relative name in the debug info does with the absolute path an editor has
+fn same_file(a: &str, b: &str) -> bool {
+    let a: Vec<&str> = a.split(['/', '\\']).filter(|c| !c.is_empty() && *c != ".").collect();
+    let b: Vec<&str> = b.split(['/', '\\']).filter(|c| !c.is_empty() && *c != ".").collect();
+    let n = a.len().min(b.len());
+    n > 0 && a[a.len() - n..] == b[b.len() - n..]
+}
+
+// The records as read, before spans are resolved to addresses
+#[derive(Default)]
+struct Records {
+    files: BTreeMap<u64, String>,
+    // segment id to its start
+    segments: HashMap<u64, u64>,
+    // span id to its segment, offset and size
+    spans: HashMap<u64, (u64, u64, u64)>,
+    // file, line, type and spans
+    lines: Vec<(u64, u64, u64, Vec<u64>)>,
+    labels: Vec<(String, Address)>,
+}
+
+impl Records {
+    fn add(&mut self, record: &str, fields: &HashMap<&str, &str>) -> Result<(), String> {
+        match record {
+            "file" => {
+                let name = fields.get("name").ok_or("file record without a name")?;
+                self.files.insert(field(fields, "id", record)?, name.to_string());
+            }
+            "seg" => {
+                self.segments.insert(field(fields, "id", record)?, field(fields, "start", record)?);
+            }
+            "span" => {
+                let span = (field(fields, "seg", record)?, field(fields, "start", record)?, field(fields, "size", record)?);
+                self.spans.insert(field(fields, "id", record)?, span);
+            }
+            "line" => {
+                let kind = fields.get("type").and_then(|v| number(v)).unwrap_or(0);
+                if let (Some(span), false) = (fields.get("span"), kind == LINE_MACRO) {
+                    let ids = span.split('+').filter_map(number).collect();
+                    self.lines.push((field(fields, "file", record)?, field(fields, "line", record)?, kind, ids));
+                }
+            }
+            "sym" if fields.get("type") == Some(&"lab") => {
+                if let (Some(name), Some(value)) = (fields.get("name"), fields.get("val").and_then(|v| number(v))) {
+                    self.labels.push((name.to_string(), (value & 0xffff) as Address));
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+impl DebugInfo {
+    pub fn parse(text: &str) -> Result<DebugInfo, String> {
+        let mut records = Records::default();
+        for (n, line) in text.lines().enumerate() {
+            if let Some((record, rest)) = line.split_once(char::is_whitespace) {
+                records.add(record, &fields(rest.trim())).map_err(|e| format!("line {}: {}", n + 1, e))?;
+            }
+        }
+        let Records { files, segments, spans, mut lines, labels } = records;
+
+        let mut info = DebugInfo {
+            labels,
+            ..Default::default()
+        };
+        // file ids are dense in practice, but don't have to be
+        let index: HashMap<u64, usize> = files.keys().enumerate().map(|(i, id)| (*id, i)).collect();
+        info.files = files.into_values().collect();
+        // C lines go in last, so they win over the assembly generated for them
+        lines.sort_by_key(|(_, _, kind, _)| *kind == LINE_C);
+        for (file, line, _, ids) in lines {
+            let Some(&file) = index.get(&file) else {
+                continue;
+            };
+            for (segment, offset, size) in ids.iter().filter_map(|id| spans.get(id)) {
+                let start = segments.get(segment).ok_or("span in an unknown segment")? + offset;
+                if *size == 0 || start > 0xffff {
+                    continue;
+                }
+                let start = start as Address;
+                let end = (start as usize + *size as usize).min(0x10000);
+                info.spans.insert(start, (end, file, line as usize));
+                let lowest = info.addresses.entry((file, line as usize)).or_insert(start);
+                *lowest = (*lowest).min(start);
+            }
+        }
+        Ok(info)
+    }
+
+    // Makes relative file names relative to dir, usually the debug info file's own directory
+    pub fn with_directory(mut self, dir: &Path) -> DebugInfo {
+        for file in &mut self.files {
+            if Path::new(file).is_relative() {
+                *file = dir.join(&*file).to_string_lossy().to_string();
+            }
+        }
+        self
+    }
+
+    pub fn get_files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn get_labels(&self) -> &[(String, Address)] {
+        &self.labels
+    }
+
+    // The number of source lines with code
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    // The file and line that generated the byte at address
+    pub fn location(&self, address: Address) -> Option<(&str, usize)> {
+        let (_, (end, file, line)) = self.spans.range(..=address).next_back()?;
+        ((address as usize) < *end).then_some((self.files[*file].as_str(), *line))
+    }
+
+    // Where a line's code starts, for a breakpoint on it. The path can be the file's full path
+    // or the name the debug info has for it
+    pub fn address_of(&self, path: &str, line: usize) -> Option<Address> {
+        self.files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| same_file(file, path))
+            .find_map(|(i, _)| self.addresses.get(&(i, line)).copied())
+    }
+}
@@ -0,0 +1,116 @@
+use crate::bus::{Address, Data};
+use crate::debugger::{Debugger, DebuggerError, StopReason};
+use crate::processor::Registers;
+use crate::system::Speed;
+
+// What happened while the debugger ran the machine, for test harnesses and GUIs that drive it
+// without parsing command output
+#[derive(PartialEq, Debug, Clone)]
+pub enum DebugEvent {
+    // one instruction finished (RunMode::Step only)
+    Stepped { registers: Registers },
+    Stopped { reason: StopReason, registers: Registers },
+    // RunMode::Cycles used up its cycles without anything stopping it
+    CyclesElapsed { registers: Registers },
+    // printed by a script's break handler
+    ScriptOutput(String),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum RunMode {
+    // n instructions, ignoring breakpoints
+    Step(usize),
+    // at least n cycles, stopping early at breakpoints and watchpoints
+    Cycles(usize),
+    // until a break, breakpoint or watchpoint
+    UntilStop,
+}
+
+impl Debugger {
+    // Run the machine and report what happened. The last event says why it returned. Runs are
+    // paced to the debugger's speed, and at single step speed they're a single step
+    pub fn resume(&mut self, mode: RunMode) -> Result<Vec<DebugEvent>, DebuggerError> {
+        let (processor, _) = self.attached()?;
+        let start = processor.borrow().get_total_cycles();
+        let mut events = vec![];
+        let mut steps = 0;
+        let mode = match mode {
+            RunMode::Step(_) => mode,
+            _ if self.speed == Speed::SingleStep => RunMode::Step(1),
+            _ => {
+                // the time spent at the prompt doesn't count
+                self.clock.resync();
+                mode
+            }
+        };
+        if mode == RunMode::Step(0) {
+            return Ok(events);
+        }
+        loop {
+            let before = processor.borrow().get_total_cycles();
+            let reason = self.step_instruction()?;
+            let registers = processor.borrow().get_registers();
+            if !matches!(mode, RunMode::Step(_)) {
+                self.clock.advance(processor.borrow().get_total_cycles() - before);
+            }
+            if let Some(reason) = reason {
+                events.push(DebugEvent::Stopped { reason, registers });
+                break;
+            }
+            if let RunMode::Step(count) = mode {
+                events.push(DebugEvent::Stepped { registers });
+                steps += 1;
+                if steps == count {
+                    break;
+                }
+                continue;
+            }
+            if self.breakpoints.contains(&registers.pc) {
+                let mut output = String::new();
+                let keep_going = self.run_break_handler(registers.pc, &mut output)?;
+                if !output.is_empty() {
+                    events.push(DebugEvent::ScriptOutput(output));
+                }
+                if !keep_going {
+                    let reason = StopReason::Breakpoint(registers.pc);
+                    events.push(DebugEvent::Stopped { reason, registers });
+                    break;
+                }
+            }
+            if let RunMode::Cycles(cycles) = mode {
+                if processor.borrow().get_total_cycles() - start >= cycles {
+                    events.push(DebugEvent::CyclesElapsed { registers });
+                    break;
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn get_registers(&self) -> Result<Registers, DebuggerError> {
+        let (processor, _) = self.attached()?;
+        let registers = processor.borrow().get_registers();
+        Ok(registers)
+    }
+
+    pub fn set_registers(&self, registers: &Registers) -> Result<(), DebuggerError> {
+        let (processor, _) = self.attached()?;
+        processor.borrow_mut().set_registers(registers);
+        Ok(())
+    }
+
+    pub fn read_memory(&self, start: Address, length: usize) -> Result<Vec<Data>, DebuggerError> {
+        let (_, bus) = self.attached()?;
+        let bus = bus.borrow();
+        Ok((0..length).map(|i| bus.peek(start.wrapping_add(i as Address))).collect())
+    }
+
+    pub fn write_memory(&self, start: Address, data: &[Data]) -> Result<(), DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        for (i, d) in data.iter().enumerate() {
+            bus.borrow().write(start.wrapping_add(i as Address), *d);
+            processor.borrow_mut().invalidate(start.wrapping_add(i as Address));
+        }
+        Ok(())
+    }
+}
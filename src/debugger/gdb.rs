@@ -0,0 +1,321 @@
+use std::io;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::bus::{Address, Data};
+use crate::debugger::{DebugEvent, Debugger, DebuggerError, RunMode, StopReason};
+use crate::processor::Registers;
+
+// Register layout reported to gdb: a, x, y, p then the 16 bit pc, little endian
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rust6502.cpu">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8" regnum="1"/>
+    <reg name="y" bitsize="8" type="uint8" regnum="2"/>
+    <reg name="p" bitsize="8" type="uint8" regnum="3"/>
+    <reg name="pc" bitsize="16" type="code_ptr" regnum="4"/>
+  </feature>
+</target>
+"#;
+
+const STOPPED: &str = "S05"; // SIGTRAP
+const INTERRUPTED: &str = "S02"; // SIGINT
+// the client's Ctrl-C while the target runs, sent outside of any packet
+const INTERRUPT: u8 = 0x03;
+
+// The largest packet either side sends, as advertised in qSupported. Memory reads are clamped
+// to what fits in a reply and longer packets from the client end the session
+const PACKET_SIZE: usize = 0x4000;
+// A frame's worth at 1MHz between looks for an interrupt while continuing
+const CYCLES_PER_SLICE: usize = 16_667;
+
+enum Reply {
+    Packet(String),
+    Detach,
+}
+
+enum Incoming {
+    Packet(String),
+    // the checksum didn't match, so the client is asked to send it again
+    Corrupt,
+}
+
+fn hex_bytes(bytes: &[Data]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex_bytes(s: &str) -> Option<Vec<Data>> {
+    // checked first, as slicing through a multi-byte character would panic
+    if s.len() & 1 == 1 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| Data::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn registers_to_bytes(r: &Registers) -> Vec<Data> {
+    vec![r.a, r.x, r.y, r.status, (r.pc & 0xff) as Data, (r.pc >> 8) as Data]
+}
+
+fn error_reply(e: DebuggerError) -> Reply {
+    match e {
+        DebuggerError::NotAttached => Reply::Packet("E01".to_string()),
+        _ => Reply::Packet("E02".to_string()),
+    }
+}
+
+fn read_packet(reader: &mut impl Read) -> io::Result<Option<Incoming>> {
+    let mut byte = [0u8; 1];
+    // skip acks and anything else until the start of a packet
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'$' {
+            break;
+        }
+    }
+    let mut body = vec![];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        if body.len() == PACKET_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "gdb packet too long"));
+        }
+        body.push(byte[0]);
+    }
+    let mut checksum = [0u8; 2];
+    reader.read_exact(&mut checksum)?;
+    let expected = body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    let checksum = std::str::from_utf8(&checksum).ok().and_then(|c| Data::from_str_radix(c, 16).ok());
+    if checksum != Some(expected) {
+        return Ok(Some(Incoming::Corrupt));
+    }
+    Ok(Some(Incoming::Packet(String::from_utf8_lossy(&body).to_string())))
+}
+
+fn write_packet(stream: &mut impl Write, body: &str) -> io::Result<()> {
+    let checksum = body.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    write!(stream, "${}#{:02x}", body, checksum)?;
+    stream.flush()
+}
+
+impl Debugger {
+    // Serve the GDB remote serial protocol on `address` until the client detaches or disconnects
+    pub fn serve_gdb(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        self.serve_gdb_on(&listener)
+    }
+
+    // Accept a single client from an already bound listener
+    pub fn serve_gdb_on(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        self.serve_gdb_client(stream)
+    }
+
+    fn serve_gdb_client(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        while let Some(incoming) = read_packet(&mut reader)? {
+            let packet = match incoming {
+                Incoming::Packet(packet) => packet,
+                Incoming::Corrupt => {
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+            };
+            stream.write_all(b"+")?;
+            let reply = if packet.starts_with('c') {
+                match self.gdb_continue(&mut reader)? {
+                    Some(reply) => reply,
+                    None => break,
+                }
+            } else {
+                self.gdb_reply(&packet)
+            };
+            match reply {
+                Reply::Packet(body) => write_packet(&mut stream, &body)?,
+                Reply::Detach => {
+                    write_packet(&mut stream, "OK")?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn gdb_reply(&mut self, packet: &str) -> Reply {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let result = match command {
+            "?" => Ok(STOPPED.to_string()),
+            "g" => self.attached().map(|(p, _)| hex_bytes(&registers_to_bytes(&p.borrow().get_registers()))),
+            "G" => self.gdb_write_registers(args),
+            "p" => self.gdb_read_register(args),
+            "m" => self.gdb_read_memory(args),
+            "M" => self.gdb_write_memory(args),
+            "s" => self.step().map(|_| STOPPED.to_string()),
+            "Z" | "z" => self.gdb_breakpoint(command == "Z", args),
+            "D" | "k" => return Reply::Detach,
+            "q" => Ok(self.gdb_query(args)),
+            _ => Ok(String::new()),
+        };
+        match result {
+            Ok(body) => Reply::Packet(body),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    // Run until something stops the machine or the client sends an interrupt, looking for one
+    // between slices of the run. None when the client disconnects meanwhile
+    fn gdb_continue(&mut self, reader: &mut BufReader<TcpStream>) -> io::Result<Option<Reply>> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.resume(RunMode::Cycles(CYCLES_PER_SLICE)) {
+                Ok(events) => match events.last() {
+                    // gdb is told which watchpoint, or it takes the stop for a breakpoint
+                    Some(DebugEvent::Stopped { reason: StopReason::Watchpoint(a), .. }) => {
+                        return Ok(Some(Reply::Packet(format!("T05watch:{:x};", a))));
+                    }
+                    Some(DebugEvent::Stopped { .. }) => return Ok(Some(Reply::Packet(STOPPED.to_string()))),
+                    _ => {}
+                },
+                Err(e) => return Ok(Some(error_reply(e))),
+            }
+            reader.get_ref().set_nonblocking(true)?;
+            let read = reader.read(&mut byte);
+            reader.get_ref().set_nonblocking(false)?;
+            match read {
+                Ok(0) => return Ok(None),
+                Ok(_) if byte[0] == INTERRUPT => return Ok(Some(Reply::Packet(INTERRUPTED.to_string()))),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn gdb_query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+        } else if query == "Attached" {
+            "1".to_string()
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let mut parts = range.split(',');
+            let offset = parts.next().and_then(parse_hex).unwrap_or(0).min(TARGET_XML.len());
+            let length = parts.next().and_then(parse_hex).unwrap_or(0);
+            let end = offset.saturating_add(length).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            format!("{}{}", marker, &TARGET_XML[offset..end])
+        } else {
+            String::new()
+        }
+    }
+
+    fn gdb_read_register(&self, args: &str) -> Result<String, DebuggerError> {
+        let (processor, _) = self.attached()?;
+        let bytes = registers_to_bytes(&processor.borrow().get_registers());
+        match parse_hex(args) {
+            Some(n) if n < 4 => Ok(hex_bytes(&bytes[n..n + 1])),
+            Some(4) => Ok(hex_bytes(&bytes[4..6])),
+            _ => Err(DebuggerError::BadArgument(args.to_string())),
+        }
+    }
+
+    fn gdb_write_registers(&self, args: &str) -> Result<String, DebuggerError> {
+        let (processor, _) = self.attached()?;
+        match parse_hex_bytes(args) {
+            Some(b) if b.len() >= 6 => {
+                let registers = Registers {
+                    a: b[0],
+                    x: b[1],
+                    y: b[2],
+                    status: b[3],
+                    pc: b[4] as Address | (b[5] as Address) << 8,
+                };
+                processor.borrow_mut().set_registers(&registers);
+                Ok("OK".to_string())
+            }
+            _ => Err(DebuggerError::BadArgument(args.to_string())),
+        }
+    }
+
+    fn gdb_read_memory(&self, args: &str) -> Result<String, DebuggerError> {
+        let (_, bus) = self.attached()?;
+        let mut parts = args.split(',');
+        match (parts.next().and_then(parse_hex), parts.next().and_then(parse_hex)) {
+            (Some(start), Some(length)) => {
+                // two hex digits a byte in the reply
+                let length = length.min(PACKET_SIZE / 2);
+                let end = start.checked_add(length).ok_or_else(|| DebuggerError::BadArgument(args.to_string()))?;
+                let bus = bus.borrow();
+                let bytes: Vec<Data> = (start..end).map(|a| bus.peek(a as Address)).collect();
+                Ok(hex_bytes(&bytes))
+            }
+            _ => Err(DebuggerError::BadArgument(args.to_string())),
+        }
+    }
+
+    fn gdb_write_memory(&self, args: &str) -> Result<String, DebuggerError> {
+        let (processor, bus) = self.attached()?;
+        let (range, data) = args.split_once(':').ok_or_else(|| DebuggerError::BadArgument(args.to_string()))?;
+        let start = range.split(',').next().and_then(parse_hex);
+        match (start, parse_hex_bytes(data)) {
+            (Some(start), Some(bytes)) => {
+                for (i, b) in bytes.iter().enumerate() {
+                    bus.borrow().write(start.wrapping_add(i) as Address, *b);
+                    processor.borrow_mut().invalidate(start.wrapping_add(i) as Address);
+                }
+                Ok("OK".to_string())
+            }
+            _ => Err(DebuggerError::BadArgument(args.to_string())),
+        }
+    }
+
+    // Software and hardware breakpoints are the same thing here, and write watchpoints (Z2) watch
+    // each byte of their length. The debugger doesn't watch reads, so read and access
+    // watchpoints (Z3, Z4) are left unsupported rather than only stopping on writes
+    fn gdb_breakpoint(&mut self, insert: bool, args: &str) -> Result<String, DebuggerError> {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let address = parts.next().and_then(parse_hex);
+        let length = parts.next().and_then(parse_hex);
+        let in_range = |a: usize, len: usize| a.checked_add(len).is_some_and(|end| end <= 0x10000);
+        match (kind, address, length) {
+            (Some("0" | "1"), Some(a), _) if a > 0xffff => Err(DebuggerError::BadArgument(args.to_string())),
+            (Some("0" | "1"), Some(a), _) => {
+                if insert {
+                    self.add_breakpoint(a as Address);
+                } else {
+                    self.remove_breakpoint(a as Address);
+                }
+                Ok("OK".to_string())
+            }
+            (Some("2"), Some(a), Some(len)) if len == 0 || !in_range(a, len) => {
+                Err(DebuggerError::BadArgument(args.to_string()))
+            }
+            (Some("2"), Some(a), Some(len)) => {
+                for address in a..a + len {
+                    if insert {
+                        self.add_watchpoint(address as Address);
+                    } else {
+                        self.remove_watchpoint(address as Address);
+                    }
+                }
+                Ok("OK".to_string())
+            }
+            _ => Ok(String::new()),
+        }
+    }
+}
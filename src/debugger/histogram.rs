@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::bus::Data;
+use crate::processor::create_instruction_table;
+
+// How often each opcode executed
+pub struct InstructionHistogram {
+    counts: Vec<u64>,
+    // "LDA #" style names for the report, "???" for opcodes missing from the table
+    names: Vec<(String, String)>,
+}
+
+impl Default for InstructionHistogram {
+    fn default() -> Self {
+        InstructionHistogram::new()
+    }
+}
+
+impl InstructionHistogram {
+    pub fn new() -> InstructionHistogram {
+        let mut names = vec![("???".to_string(), String::new()); 256];
+        for (opcode, instruction) in create_instruction_table().iter() {
+            names[opcode as usize] = (instruction.get_mnemonic().to_string(), format!("{:?}", instruction.get_addressing()));
+        }
+        InstructionHistogram { counts: vec![0; 256], names }
+    }
+
+    pub fn record(&mut self, opcode: Data) {
+        self.counts[opcode as usize] += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+    }
+
+    pub fn get_count(&self, opcode: Data) -> u64 {
+        self.counts[opcode as usize]
+    }
+
+    pub fn get_total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Executed opcodes, most frequent first
+    pub fn by_opcode(&self) -> Vec<(Data, u64)> {
+        let mut counts: Vec<(Data, u64)> = self.counts.iter().enumerate().filter(|(_, c)| **c > 0).map(|(o, c)| (o as Data, *c)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    // Opcodes summed per mnemonic, most frequent first
+    pub fn by_mnemonic(&self) -> Vec<(String, u64)> {
+        let mut totals: HashMap<&str, u64> = HashMap::new();
+        for (opcode, count) in self.by_opcode() {
+            *totals.entry(&self.names[opcode as usize].0).or_insert(0) += count;
+        }
+        let mut counts: Vec<(String, u64)> = totals.into_iter().map(|(m, c)| (m.to_string(), c)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    pub fn report(&self, per_opcode: bool, lines: usize) -> String {
+        let total = self.get_total().max(1);
+        let mut out = format!("{} instructions\n", self.get_total());
+        if per_opcode {
+            for (opcode, count) in self.by_opcode().iter().take(lines) {
+                let (mnemonic, mode) = &self.names[*opcode as usize];
+                let name = format!("{:02X} {} {}", opcode, mnemonic, mode);
+                writeln!(out, "  {:<32} {:>10} {:5.1}%", name, count, *count as f64 * 100.0 / total as f64).unwrap();
+            }
+        } else {
+            for (mnemonic, count) in self.by_mnemonic().iter().take(lines) {
+                writeln!(out, "  {:<8} {:>10} {:5.1}%", mnemonic, count, *count as f64 * 100.0 / total as f64).unwrap();
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,113 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::bus::{Address, Bus, BusDevice, Data, DeviceStates};
+use crate::processor::ProcessorSnapshot;
+
+// Everything needed to undo one instruction: the processor before it ran and the
+// previous contents of every address it wrote to (in write order)
+pub struct HistoryEntry {
+    pub before: ProcessorSnapshot,
+    pub writes: Vec<(Address, Data)>,
+}
+
+// A bounded ring of the most recently executed instructions, oldest dropped first
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    depth: usize,
+}
+
+impl History {
+    pub fn new(depth: usize) -> History {
+        History {
+            entries: VecDeque::with_capacity(depth),
+            depth,
+        }
+    }
+
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if self.depth == 0 {
+            return;
+        }
+        if self.entries.len() == self.depth {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<HistoryEntry> {
+        self.entries.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        while self.entries.len() > depth {
+            self.entries.pop_front();
+        }
+        self.depth = depth;
+    }
+}
+
+// Sits between the processor and the real bus remembering (address, old, new) for every write
+pub struct RecordingBus {
+    inner: Rc<RefCell<dyn Bus>>,
+    writes: RefCell<Vec<(Address, Data, Data)>>,
+}
+
+impl RecordingBus {
+    pub fn new(inner: Rc<RefCell<dyn Bus>>) -> RecordingBus {
+        RecordingBus {
+            inner,
+            writes: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn take_writes(&self) -> Vec<(Address, Data, Data)> {
+        self.writes.take()
+    }
+}
+
+impl Bus for RecordingBus {
+    fn write(&self, address: Address, data: Data) {
+        let inner = self.inner.borrow();
+        // peeked, so remembering the old value doesn't acknowledge anything on an I/O register
+        self.writes.borrow_mut().push((address, inner.peek(address), data));
+        inner.write(address, data);
+    }
+
+    fn read(&self, address: Address) -> Data {
+        self.inner.borrow().read(address)
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        self.inner.borrow().peek(address)
+    }
+
+    fn register_device(&mut self, device: &Rc<RefCell<dyn BusDevice>>) {
+        self.inner.borrow_mut().register_device(device);
+    }
+
+    fn save_state(&self) -> DeviceStates {
+        self.inner.borrow().save_state()
+    }
+
+    fn load_state(&self, states: &DeviceStates) {
+        self.inner.borrow().load_state(states);
+    }
+
+    fn describe_devices(&self) -> Vec<(String, String)> {
+        self.inner.borrow().describe_devices()
+    }
+}
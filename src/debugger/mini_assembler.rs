@@ -0,0 +1,120 @@
+use crate::bus::{Address, Data};
+use crate::debugger::symbols::Symbols;
+use crate::debugger::DebuggerError;
+use crate::processor::AddressingMode::*;
+use crate::processor::DataRegister::{X, Y};
+use crate::processor::{create_instruction_table, find_opcode, AddressingMode, DataRegister, InstructionTable};
+
+// The operand as typed, before knowing which addressing modes the mnemonic supports
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Address),
+    Direct(Address, bool),
+    DirectIndexed(Address, bool, DataRegister),
+    Indirect(Address),
+    IndexedIndirect(Address),
+    IndirectIndexed(Address),
+}
+
+// Assembles one instruction at a time, Apple monitor style, for patching memory from the debugger
+pub struct MiniAssembler {
+    instructions: &'static InstructionTable,
+}
+
+// A value and whether it was written small enough to be a zero page address
+fn parse_value(s: &str, symbols: &Symbols) -> Result<(Address, bool), DebuggerError> {
+    if let Some(address) = symbols.get(s) {
+        return Ok((address, address <= 0xff));
+    }
+    let digits = s.trim_start_matches('$').trim_start_matches("0x");
+    match Address::from_str_radix(digits, 16) {
+        Ok(value) => Ok((value, digits.len() <= 2)),
+        Err(_) => Err(DebuggerError::BadArgument(s.to_string())),
+    }
+}
+
+fn parse_operand(s: &str, symbols: &Symbols) -> Result<Operand, DebuggerError> {
+    let s: String = s.split_whitespace().collect();
+    let upper = s.to_ascii_uppercase();
+    if s.is_empty() {
+        Ok(Operand::None)
+    } else if upper == "A" {
+        Ok(Operand::Accumulator)
+    } else if let Some(v) = s.strip_prefix('#') {
+        Ok(Operand::Immediate(parse_value(v, symbols)?.0))
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        Ok(Operand::IndexedIndirect(parse_value(&s[1..s.len() - 3], symbols)?.0))
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        Ok(Operand::IndirectIndexed(parse_value(&s[1..s.len() - 3], symbols)?.0))
+    } else if s.starts_with('(') && s.ends_with(')') {
+        Ok(Operand::Indirect(parse_value(&s[1..s.len() - 1], symbols)?.0))
+    } else if upper.ends_with(",X") || upper.ends_with(",Y") {
+        let reg = if upper.ends_with('X') { X } else { Y };
+        let (value, small) = parse_value(&s[..s.len() - 2], symbols)?;
+        Ok(Operand::DirectIndexed(value, small, reg))
+    } else {
+        let (value, small) = parse_value(&s, symbols)?;
+        Ok(Operand::Direct(value, small))
+    }
+}
+
+impl MiniAssembler {
+    pub fn new() -> MiniAssembler {
+        MiniAssembler {
+            instructions: create_instruction_table(),
+        }
+    }
+
+    // The bytes for one line like "LDA #$05" or "STA $0200,X" placed at `address`
+    pub fn assemble(&self, line: &str, address: Address, symbols: &Symbols) -> Result<Vec<Data>, DebuggerError> {
+        let line = line.trim();
+        let (mnemonic, operand_text) = match line.split_once(char::is_whitespace) {
+            Some((m, o)) => (m, o),
+            None => (line, ""),
+        };
+        let operand = parse_operand(operand_text, symbols)?;
+        let candidates: Vec<(AddressingMode, Address)> = match operand {
+            Operand::None => vec![(Implied, 0), (Accumulator, 0)],
+            Operand::Accumulator => vec![(Accumulator, 0)],
+            Operand::Immediate(v) => vec![(Immediate, v)],
+            Operand::Direct(v, small) => {
+                let offset = v.wrapping_sub(address.wrapping_add(2));
+                let mut modes = vec![(Relative, offset)];
+                if small {
+                    modes.push((ZeroPage, v));
+                }
+                modes.push((Absolute, v));
+                modes
+            }
+            Operand::DirectIndexed(v, small, reg) => {
+                let mut modes = vec![];
+                if small {
+                    modes.push((ZeroPageIndexed { reg }, v));
+                }
+                modes.push((AbsIndexed { reg }, v));
+                modes
+            }
+            Operand::Indirect(v) => vec![(Indirect, v)],
+            Operand::IndexedIndirect(v) => vec![(IndexedIndirect, v)],
+            Operand::IndirectIndexed(v) => vec![(IndirectIndexed, v)],
+        };
+
+        for (mode, value) in candidates {
+            if let Some(opcode) = find_opcode(self.instructions, mnemonic, &mode) {
+                if mode == Relative && (0x80..0xff80).contains(&value) {
+                    return Err(DebuggerError::BadArgument(format!("branch out of range: {}", line)));
+                }
+                let mut bytes = vec![opcode];
+                match mode.operand_length() {
+                    1 if value > 0xff && mode != Relative => return Err(DebuggerError::BadArgument(operand_text.to_string())),
+                    1 => bytes.push((value & 0xff) as Data),
+                    2 => bytes.extend([(value & 0xff) as Data, (value >> 8) as Data]),
+                    _ => {}
+                }
+                return Ok(bytes);
+            }
+        }
+        Err(DebuggerError::BadArgument(format!("cannot assemble '{}'", line)))
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::bus::Address;
+use crate::debugger::symbols::Symbols;
+
+// Cycles spent per instruction address, rolled up by symbol for the report
+#[derive(Default)]
+pub struct Profiler {
+    cycles: HashMap<Address, u64>,
+    total: u64,
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+impl Profiler {
+    pub fn record(&mut self, address: Address, cycles: u64) {
+        *self.cycles.entry(address).or_insert(0) += cycles;
+        self.total += cycles;
+    }
+
+    pub fn clear(&mut self) {
+        self.cycles.clear();
+        self.total = 0;
+    }
+
+    // Hottest first
+    pub fn by_address(&self) -> Vec<(Address, u64)> {
+        let mut hot: Vec<(Address, u64)> = self.cycles.iter().map(|(a, c)| (*a, *c)).collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot
+    }
+
+    // Every address is charged to the nearest symbol at or below it, hottest first
+    pub fn by_symbol(&self, symbols: &Symbols) -> Vec<(String, u64)> {
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        for (address, cycles) in self.cycles.iter() {
+            let name = match symbols.containing(*address) {
+                Some((name, _)) => name.to_string(),
+                None => "?".to_string(),
+            };
+            *totals.entry(name).or_insert(0) += cycles;
+        }
+        let mut hot: Vec<(String, u64)> = totals.into_iter().collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot
+    }
+
+    pub fn report(&self, symbols: &Symbols, lines: usize) -> String {
+        let mut out = format!("{} cycles profiled\n", self.total);
+        if symbols.iter().next().is_some() {
+            writeln!(out, "by symbol:").unwrap();
+            for (name, cycles) in self.by_symbol(symbols).iter().take(lines) {
+                writeln!(out, "  {:<20} {:>10} {:5.1}%", name, cycles, percent(*cycles, self.total)).unwrap();
+            }
+        }
+        writeln!(out, "by address:").unwrap();
+        for (address, cycles) in self.by_address().iter().take(lines) {
+            let name = symbols.name_for(*address).map(|n| format!(" <{}>", n)).unwrap_or_default();
+            let label = format!("${:04X}{}", address, name);
+            writeln!(out, "  {:<20} {:>10} {:5.1}%", label, cycles, percent(*cycles, self.total)).unwrap();
+        }
+        out
+    }
+}
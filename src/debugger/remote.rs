@@ -0,0 +1,542 @@
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bus::{Address, Data};
+use crate::debugger::wire::{base64, json_string};
+use crate::debugger::{DebugEvent, Debugger, DebuggerError, RunMode, StopReason};
+use crate::processor::Registers;
+use crate::json::Value;
+
+// Run control, memory, registers and events as JSON over WebSocket, for dashboards and CI jobs
+// driving the emulator from elsewhere. Every request is a text message holding an object with a
+// "cmd" and, optionally, an "id" that comes back on the reply:
+//   {"id": 1, "cmd": "write", "address": 512, "data": [169, 1]}
+//   {"id": 1, "ok": true}
+//   {"id": 2, "cmd": "registers"}
+//   {"id": 2, "ok": true, "registers": {"pc": 512, "a": 0, "x": 0, "y": 0, "status": 0, "cycles": 0}}
+// Failures are {"id": .., "ok": false, "error": ".."}. The commands are registers, set_registers
+// (any of pc, a, x, y, status), read (address, length), write (address, data), step [count],
+// run [cycles], pause, reset, break and delete (address), execute (a debugger command line),
+// subscribe, unsubscribe and shutdown. run without cycles keeps the machine going between
+// requests until it stops or a pause; subscribers then get events, messages with an "event"
+// rather than an "id": running, paused, stopped (with the reason), output from a script's break
+// handler, reset and faulted. A processor that panics, on an opcode it doesn't know, faults the
+// machine until a reset rather than taking the server down.
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+// A frame's worth at 1MHz between looks at the sockets while running
+const CYCLES_PER_SLICE: usize = 16_667;
+const IDLE: Duration = Duration::from_millis(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_HANDSHAKE: usize = 8192;
+const MAX_MESSAGE: usize = 1 << 20;
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn registers_json(r: &Registers, cycles: usize) -> String {
+    format!(
+        "{{\"pc\":{},\"a\":{},\"x\":{},\"y\":{},\"status\":{},\"cycles\":{}}}",
+        r.pc, r.a, r.x, r.y, r.status, cycles
+    )
+}
+
+fn stop_json(reason: &StopReason) -> String {
+    match reason {
+        StopReason::Break => "{\"reason\":\"break\"}".to_string(),
+        StopReason::Breakpoint(a) => format!("{{\"reason\":\"breakpoint\",\"address\":{}}}", a),
+        StopReason::Watchpoint(a) => format!("{{\"reason\":\"watchpoint\",\"address\":{}}}", a),
+    }
+}
+
+fn get_address(request: &Value, key: &str) -> Result<Address, String> {
+    match request.get(key).and_then(Value::as_u64) {
+        Some(a) if a <= 0xffff => Ok(a as Address),
+        _ => Err(format!("expected an address in {}", key)),
+    }
+}
+
+fn get_byte(value: &Value) -> Result<Data, String> {
+    match value.as_u64() {
+        Some(b) if b <= 0xff => Ok(b as Data),
+        _ => Err("expected bytes".to_string()),
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+// A frame from the front of buffer and its length, None until it's all there
+fn parse_frame(buffer: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+    let masked = buffer[1] & 0x80 != 0;
+    let (length, mut pos) = match buffer[1] & 0x7f {
+        126 if buffer.len() >= 4 => (u16::from_be_bytes([buffer[2], buffer[3]]) as usize, 4),
+        127 if buffer.len() >= 10 => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&buffer[2..10]);
+            (u64::from_be_bytes(bytes) as usize, 10)
+        }
+        126 | 127 => return Ok(None),
+        n => (n as usize, 2),
+    };
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(ErrorKind::InvalidData, "message too long"));
+    }
+    let mut mask = [0; 4];
+    if masked {
+        if buffer.len() < pos + 4 {
+            return Ok(None);
+        }
+        mask.copy_from_slice(&buffer[pos..pos + 4]);
+        pos += 4;
+    }
+    if buffer.len() < pos + length {
+        return Ok(None);
+    }
+    let payload = buffer[pos..pos + length].iter().enumerate().map(|(i, b)| b ^ mask[i % 4]).collect();
+    let frame = Frame {
+        fin: buffer[0] & 0x80 != 0,
+        opcode: buffer[0] & 0x0f,
+        payload,
+    };
+    Ok(Some((frame, pos + length)))
+}
+
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => out.push(n as u8),
+        n if n <= 0xffff => {
+            out.push(126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    // a text message still arriving in continuation frames
+    message: Vec<u8>,
+    // the HTTP upgrade has been answered, and when the client connected until then
+    upgraded: bool,
+    connected: Instant,
+    subscribed: bool,
+    closed: bool,
+}
+
+impl Client {
+    // Nothing is read here: the upgrade request is buffered by poll like frames are, so a client
+    // slow to send it doesn't hold up the others
+    fn new(stream: TcpStream) -> io::Result<Client> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Client {
+            stream,
+            buffer: vec![],
+            message: vec![],
+            upgraded: false,
+            connected: Instant::now(),
+            subscribed: false,
+            closed: false,
+        })
+    }
+
+    // Answers the HTTP upgrade once the whole request is in the buffer. A client that botches it,
+    // or takes too long, is closed
+    fn handshake(&mut self) {
+        let Some(end) = self.buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.buffer.len() > MAX_HANDSHAKE || self.connected.elapsed() > HANDSHAKE_TIMEOUT {
+                self.closed = true;
+            }
+            return;
+        };
+        let request: Vec<u8> = self.buffer.drain(..end + 4).collect();
+        let request = String::from_utf8_lossy(&request);
+        let key = request.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim().eq_ignore_ascii_case("sec-websocket-key").then(|| value.trim().to_string())
+        });
+        let Some(key) = key else {
+            self.write(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+            self.closed = true;
+            return;
+        };
+        let accept = base64(&sha1(format!("{}{}", key, GUID).as_bytes()));
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        );
+        self.write(response.as_bytes());
+        self.upgraded = true;
+    }
+
+    // Whole text messages that have come in since last time, answering pings and closes
+    fn poll(&mut self) -> Vec<String> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+        if !self.upgraded {
+            self.handshake();
+            if !self.upgraded {
+                return vec![];
+            }
+        }
+        let mut messages = vec![];
+        loop {
+            let (frame, length) = match parse_frame(&self.buffer) {
+                Ok(Some(parsed)) => parsed,
+                Ok(None) => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            };
+            self.buffer.drain(..length);
+            match frame.opcode {
+                OPCODE_TEXT | OPCODE_CONTINUATION => {
+                    self.message.extend_from_slice(&frame.payload);
+                    if self.message.len() > MAX_MESSAGE {
+                        self.closed = true;
+                        break;
+                    }
+                    if frame.fin {
+                        messages.push(String::from_utf8_lossy(&self.message).to_string());
+                        self.message.clear();
+                    }
+                }
+                OPCODE_CLOSE => {
+                    self.write(&encode_frame(OPCODE_CLOSE, &[]));
+                    self.closed = true;
+                    break;
+                }
+                OPCODE_PING => self.write(&encode_frame(OPCODE_PONG, &frame.payload)),
+                // pongs, and binary messages nobody asked for
+                _ => (),
+            }
+        }
+        messages
+    }
+
+    fn send(&mut self, text: &str) {
+        self.write(&encode_frame(OPCODE_TEXT, text.as_bytes()));
+    }
+
+    // Blocks for the write, so a message never goes out half sent
+    fn write(&mut self, bytes: &[u8]) {
+        let sent = self.stream.set_nonblocking(false).and_then(|_| self.stream.write_all(bytes));
+        if sent.and_then(|_| self.stream.set_nonblocking(true)).is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Remote {
+    running: bool,
+    faulted: bool,
+    shutdown: bool,
+    // for subscribers, sent once the current request is answered
+    events: Vec<String>,
+}
+
+impl Debugger {
+    // Serve JSON requests over WebSocket on `address` until a client asks for a shutdown
+    pub fn serve_remote(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        self.serve_remote_on(&listener)
+    }
+
+    // Serve any number of clients from an already bound listener
+    pub fn serve_remote_on(&mut self, listener: &TcpListener) -> io::Result<()> {
+        listener.set_nonblocking(true)?;
+        let mut remote = Remote::default();
+        let mut clients: Vec<Client> = vec![];
+        while !remote.shutdown {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => clients.extend(Client::new(stream).ok()),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                    Err(e) => return Err(e),
+                }
+            }
+            let mut busy = false;
+            for i in 0..clients.len() {
+                for message in clients[i].poll() {
+                    busy = true;
+                    let reply = self.remote_reply(&mut remote, &mut clients[i], &message);
+                    clients[i].send(&reply);
+                    broadcast(&mut clients, &mut remote.events);
+                }
+            }
+            clients.retain(|client| !client.closed);
+            if remote.running {
+                self.remote_slice(&mut remote);
+                broadcast(&mut clients, &mut remote.events);
+            } else if !busy {
+                thread::sleep(IDLE);
+            }
+        }
+        for client in clients.iter_mut().filter(|client| client.upgraded) {
+            client.write(&encode_frame(OPCODE_CLOSE, &[]));
+        }
+        Ok(())
+    }
+
+    fn remote_slice(&mut self, remote: &mut Remote) {
+        match self.remote_guarded(remote, |debugger| debugger.resume(RunMode::Cycles(CYCLES_PER_SLICE))) {
+            Ok(events) => {
+                self.remote_events(remote, &events);
+            }
+            // a fault has already said so
+            Err(_) if remote.faulted => (),
+            Err(e) => {
+                remote.running = false;
+                remote.events.push(format!("{{\"event\":\"paused\",\"error\":{}}}", json_string(&e)));
+            }
+        }
+    }
+
+    // Runs the machine, turning a processor panic into a fault
+    fn remote_guarded<T>(
+        &mut self,
+        remote: &mut Remote,
+        run: impl FnOnce(&mut Debugger) -> Result<T, DebuggerError>,
+    ) -> Result<T, String> {
+        if remote.faulted {
+            return Err("machine faulted, reset it".to_string());
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| run(self))) {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => {
+                remote.faulted = true;
+                remote.running = false;
+                remote.events.push("{\"event\":\"faulted\"}".to_string());
+                Err("machine faulted".to_string())
+            }
+        }
+    }
+
+    // Queues events for subscribers, returning the stop that ended the run if there was one
+    fn remote_events(&self, remote: &mut Remote, events: &[DebugEvent]) -> Option<String> {
+        let cycles = self.attached().map_or(0, |(p, _)| p.borrow().get_total_cycles());
+        let mut stop = None;
+        for event in events {
+            match event {
+                DebugEvent::Stopped { reason, registers } => {
+                    remote.running = false;
+                    remote.events.push(format!(
+                        "{{\"event\":\"stopped\",\"stop\":{},\"registers\":{}}}",
+                        stop_json(reason),
+                        registers_json(registers, cycles)
+                    ));
+                    stop = Some(stop_json(reason));
+                }
+                DebugEvent::ScriptOutput(text) => {
+                    remote.events.push(format!("{{\"event\":\"output\",\"text\":{}}}", json_string(text)));
+                }
+                DebugEvent::Stepped { .. } | DebugEvent::CyclesElapsed { .. } => (),
+            }
+        }
+        stop
+    }
+
+    fn remote_reply(&mut self, remote: &mut Remote, client: &mut Client, message: &str) -> String {
+        let request = Value::parse(message);
+        let id = match request.as_ref().ok().and_then(|r| r.get("id")) {
+            Some(Value::Number(n)) => n.to_string(),
+            Some(Value::String(s)) => json_string(s),
+            _ => "null".to_string(),
+        };
+        let result = request.and_then(|request| self.remote_command(remote, client, &request));
+        match result {
+            Ok(fields) => format!("{{\"id\":{},\"ok\":true{}}}", id, fields),
+            Err(e) => format!("{{\"id\":{},\"ok\":false,\"error\":{}}}", id, json_string(&e)),
+        }
+    }
+
+    // The reply's fields beyond the id and ok, each with a leading comma
+    fn remote_command(&mut self, remote: &mut Remote, client: &mut Client, request: &Value) -> Result<String, String> {
+        let (processor, _) = self.attached().map_err(|e| e.to_string())?;
+        let cycles = || processor.borrow().get_total_cycles();
+        let command = request.get("cmd").and_then(Value::as_str).ok_or("expected a cmd")?;
+        match command {
+            "registers" => Ok(format!(",\"registers\":{}", registers_json(&processor.borrow().get_registers(), cycles()))),
+            "set_registers" => {
+                let mut r = processor.borrow().get_registers();
+                if request.get("pc").is_some() {
+                    r.pc = get_address(request, "pc")?;
+                }
+                for (key, register) in [("a", &mut r.a), ("x", &mut r.x), ("y", &mut r.y), ("status", &mut r.status)] {
+                    if let Some(value) = request.get(key) {
+                        *register = get_byte(value)?;
+                    }
+                }
+                processor.borrow_mut().set_registers(&r);
+                Ok(format!(",\"registers\":{}", registers_json(&r, cycles())))
+            }
+            "read" => {
+                let address = get_address(request, "address")?;
+                let length = request.get("length").and_then(Value::as_u64).filter(|n| *n <= 0x10000);
+                let length = length.ok_or("expected a length up to 65536")?;
+                let data = self.read_memory(address, length as usize).map_err(|e| e.to_string())?;
+                let data: Vec<String> = data.iter().map(|b| b.to_string()).collect();
+                Ok(format!(",\"data\":[{}]", data.join(",")))
+            }
+            "write" => {
+                let address = get_address(request, "address")?;
+                let data = request.get("data").and_then(Value::as_array).ok_or("expected data")?;
+                let data = data.iter().map(get_byte).collect::<Result<Vec<Data>, String>>()?;
+                self.write_memory(address, &data).map_err(|e| e.to_string())?;
+                Ok(String::new())
+            }
+            "step" | "run" if remote.running => Err("already running".to_string()),
+            "step" => {
+                let count = request.get("count").and_then(Value::as_u64).unwrap_or(1) as usize;
+                let events = self.remote_guarded(remote, |debugger| debugger.resume(RunMode::Step(count)))?;
+                self.remote_run_reply(remote, &events)
+            }
+            "run" => match request.get("cycles").and_then(Value::as_u64) {
+                Some(n) => {
+                    let events = self.remote_guarded(remote, |debugger| debugger.resume(RunMode::Cycles(n as usize)))?;
+                    self.remote_run_reply(remote, &events)
+                }
+                None if remote.faulted => Err("machine faulted, reset it".to_string()),
+                None => {
+                    remote.running = true;
+                    remote.events.push("{\"event\":\"running\"}".to_string());
+                    Ok(String::new())
+                }
+            },
+            "pause" => {
+                if remote.running {
+                    remote.running = false;
+                    let registers = registers_json(&processor.borrow().get_registers(), cycles());
+                    remote.events.push(format!("{{\"event\":\"paused\",\"registers\":{}}}", registers));
+                }
+                Ok(String::new())
+            }
+            // the processor only; devices on the bus keep their state
+            "reset" => {
+                processor.borrow_mut().reset();
+                remote.faulted = false;
+                remote.running = false;
+                remote.events.push("{\"event\":\"reset\"}".to_string());
+                Ok(String::new())
+            }
+            "break" => {
+                self.add_breakpoint(get_address(request, "address")?);
+                Ok(String::new())
+            }
+            "delete" => {
+                self.remove_breakpoint(get_address(request, "address")?);
+                Ok(String::new())
+            }
+            "execute" => {
+                let line = request.get("line").and_then(Value::as_str).ok_or("expected a line")?.to_string();
+                let output = self.remote_guarded(remote, |debugger| debugger.execute(&line))?;
+                Ok(format!(",\"output\":{}", json_string(&output)))
+            }
+            "subscribe" | "unsubscribe" => {
+                client.subscribed = command == "subscribe";
+                Ok(String::new())
+            }
+            "shutdown" => {
+                remote.shutdown = true;
+                Ok(String::new())
+            }
+            _ => Err(format!("unknown cmd '{}'", command)),
+        }
+    }
+
+    fn remote_run_reply(&self, remote: &mut Remote, events: &[DebugEvent]) -> Result<String, String> {
+        let stop = self.remote_events(remote, events).unwrap_or_else(|| "null".to_string());
+        let registers = self.get_registers().map_err(|e| e.to_string())?;
+        let cycles = self.attached().map_or(0, |(p, _)| p.borrow().get_total_cycles());
+        Ok(format!(",\"stop\":{},\"registers\":{}", stop, registers_json(&registers, cycles)))
+    }
+}
+
+fn broadcast(clients: &mut [Client], events: &mut Vec<String>) {
+    for event in events.drain(..) {
+        for client in clients.iter_mut().filter(|client| client.subscribed) {
+            client.send(&event);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, Scope, AST, INT};
+
+use crate::bus::{Address, Data};
+use crate::debugger::{DebuggerError, Link};
+use crate::loader::hexdump;
+
+// Things a script asked the debugger to do, applied once the script returns
+#[derive(Default)]
+pub struct ScriptRequests {
+    pub add_breakpoints: Vec<Address>,
+    pub remove_breakpoints: Vec<Address>,
+    pub output: String,
+}
+
+// A loaded Rhai script and the on_break handlers it registered
+//
+//   let count = 0;
+//   on_break(0x0205, || {
+//       count += 1;
+//       print(dump(0x0200, 0x020f));
+//       count < 10   // true keeps running
+//   });
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    handlers: Rc<RefCell<HashMap<Address, FnPtr>>>,
+    requests: Rc<RefCell<ScriptRequests>>,
+}
+
+fn script_error(e: Box<EvalAltResult>) -> DebuggerError {
+    DebuggerError::Script(e.to_string())
+}
+
+fn script_result<T>(r: Result<T, DebuggerError>) -> Result<T, Box<EvalAltResult>> {
+    r.map_err(|e| e.to_string().into())
+}
+
+impl Script {
+    pub fn new(source: &str, link: Rc<RefCell<Link>>) -> Result<Script, DebuggerError> {
+        let handlers: Rc<RefCell<HashMap<Address, FnPtr>>> = Rc::new(RefCell::new(HashMap::new()));
+        let requests: Rc<RefCell<ScriptRequests>> = Rc::new(RefCell::new(ScriptRequests::default()));
+        let mut engine = Engine::new();
+
+        let r = Rc::clone(&requests);
+        engine.on_print(move |s| {
+            let mut r = r.borrow_mut();
+            r.output.push_str(s);
+            r.output.push('\n');
+        });
+
+        let l = Rc::clone(&link);
+        engine.register_fn("reg", move |name: &str| -> Result<INT, Box<EvalAltResult>> {
+            let (processor, _) = script_result(l.borrow().upgrade())?;
+            let registers = processor.borrow().get_registers();
+            match name.to_ascii_uppercase().as_str() {
+                "PC" => Ok(registers.pc as INT),
+                "A" => Ok(registers.a as INT),
+                "X" => Ok(registers.x as INT),
+                "Y" => Ok(registers.y as INT),
+                "P" => Ok(registers.status as INT),
+                _ => Err(format!("no register {}", name).into()),
+            }
+        });
+
+        let l = Rc::clone(&link);
+        engine.register_fn("set_reg", move |name: &str, value: INT| -> Result<(), Box<EvalAltResult>> {
+            let (processor, _) = script_result(l.borrow().upgrade())?;
+            let mut registers = processor.borrow().get_registers();
+            match name.to_ascii_uppercase().as_str() {
+                "PC" => registers.pc = value as Address,
+                "A" => registers.a = value as Data,
+                "X" => registers.x = value as Data,
+                "Y" => registers.y = value as Data,
+                "P" => registers.status = value as Data,
+                _ => return Err(format!("no register {}", name).into()),
+            }
+            processor.borrow_mut().set_registers(&registers);
+            Ok(())
+        });
+
+        let l = Rc::clone(&link);
+        engine.register_fn("cycles", move || -> Result<INT, Box<EvalAltResult>> {
+            let (processor, _) = script_result(l.borrow().upgrade())?;
+            let cycles = processor.borrow().get_total_cycles();
+            Ok(cycles as INT)
+        });
+
+        let l = Rc::clone(&link);
+        engine.register_fn("peek", move |address: INT| -> Result<INT, Box<EvalAltResult>> {
+            let (_, bus) = script_result(l.borrow().upgrade())?;
+            let data = bus.borrow().peek(address as Address);
+            Ok(data as INT)
+        });
+
+        let l = Rc::clone(&link);
+        engine.register_fn("poke", move |address: INT, data: INT| -> Result<(), Box<EvalAltResult>> {
+            let (processor, bus) = script_result(l.borrow().upgrade())?;
+            bus.borrow().write(address as Address, data as Data);
+            processor.borrow_mut().invalidate(address as Address);
+            Ok(())
+        });
+
+        let l = link;
+        engine.register_fn("dump", move |start: INT, end: INT| -> Result<String, Box<EvalAltResult>> {
+            let (_, bus) = script_result(l.borrow().upgrade())?;
+            let dump = hexdump::dump(&*bus.borrow(), start as Address, end as Address);
+            Ok(dump.trim_end().to_string())
+        });
+
+        let r = Rc::clone(&requests);
+        engine.register_fn("break_at", move |address: INT| {
+            r.borrow_mut().add_breakpoints.push(address as Address);
+        });
+
+        let r = Rc::clone(&requests);
+        let h = Rc::clone(&handlers);
+        engine.register_fn("clear_break", move |address: INT| {
+            h.borrow_mut().remove(&(address as Address));
+            r.borrow_mut().remove_breakpoints.push(address as Address);
+        });
+
+        let r = Rc::clone(&requests);
+        let h = Rc::clone(&handlers);
+        engine.register_fn("on_break", move |address: INT, handler: FnPtr| {
+            h.borrow_mut().insert(address as Address, handler);
+            r.borrow_mut().add_breakpoints.push(address as Address);
+        });
+
+        let ast = engine.compile(source).map_err(|e| DebuggerError::Script(e.to_string()))?;
+        engine.run_ast_with_scope(&mut Scope::new(), &ast).map_err(script_error)?;
+
+        Ok(Script { engine, ast, handlers, requests })
+    }
+
+    // Run the handler registered for `pc`. Returns true when it asks for execution to continue
+    pub fn on_break(&mut self, pc: Address) -> Result<bool, DebuggerError> {
+        let handler = self.handlers.borrow().get(&pc).cloned();
+        match handler {
+            None => Ok(false),
+            Some(f) => {
+                let result: Dynamic = f.call(&self.engine, &self.ast, ()).map_err(script_error)?;
+                Ok(result.as_bool().unwrap_or(false))
+            }
+        }
+    }
+
+    pub fn take_requests(&self) -> ScriptRequests {
+        self.requests.take()
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt::Write;
+
+use crate::bus::{Address, Bus, Data};
+
+const CONTEXT: u32 = 4;
+
+// Every address in start..=end where the pattern matches. None in the pattern matches any byte
+pub fn find_pattern(bus: &dyn Bus, pattern: &[Option<Data>], start: Address, end: Address) -> Vec<Address> {
+    if pattern.is_empty() {
+        return vec![];
+    }
+    let memory: Vec<Data> = (start as u32..=end as u32).map(|a| bus.peek(a as Address)).collect();
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| window.iter().zip(pattern).all(|(b, p)| p.is_none() || *p == Some(*b)))
+        .map(|(i, _)| start.wrapping_add(i as Address))
+        .collect()
+}
+
+// "$0203: 8D 00 02 [A9 05] 85 10" showing a few bytes either side of the match
+pub fn format_match(bus: &dyn Bus, address: Address, length: usize) -> String {
+    let start = (address as u32).saturating_sub(CONTEXT);
+    let end = (address as u32 + length as u32 + CONTEXT).min(0x10000);
+    let mut out = format!("${:04X}:", address);
+    for a in start..end {
+        let data = bus.peek(a as Address);
+        if a == address as u32 {
+            write!(out, " [{:02X}", data).unwrap();
+        } else {
+            write!(out, " {:02X}", data).unwrap();
+        }
+        if a == address as u32 + length as u32 - 1 {
+            out.push(']');
+        }
+    }
+    out
+}
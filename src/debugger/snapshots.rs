@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+
+use crate::bus::DeviceStates;
+use crate::processor::ProcessorSnapshot;
+
+// The processor and every device on the bus at one moment in time
+#[derive(Clone)]
+pub struct MachineSnapshot {
+    pub processor: ProcessorSnapshot,
+    pub devices: DeviceStates,
+}
+
+impl MachineSnapshot {
+    pub fn get_cycle(&self) -> usize {
+        self.processor.get_total_cycles()
+    }
+}
+
+// A ring of machine snapshots taken every `interval` cycles, oldest dropped first
+pub struct Snapshots {
+    ring: VecDeque<MachineSnapshot>,
+    capacity: usize,
+    interval: usize,
+}
+
+impl Snapshots {
+    pub fn new(interval: usize, capacity: usize) -> Snapshots {
+        Snapshots {
+            ring: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+        }
+    }
+
+    pub fn get_interval(&self) -> usize {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: usize) {
+        self.interval = interval;
+    }
+
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    // A snapshot is due once `interval` cycles have passed since the newest one
+    pub fn is_due(&self, cycle: usize) -> bool {
+        if self.interval == 0 || self.capacity == 0 {
+            return false;
+        }
+        match self.ring.back() {
+            None => true,
+            Some(newest) => cycle >= newest.get_cycle() + self.interval,
+        }
+    }
+
+    pub fn push(&mut self, snapshot: MachineSnapshot) {
+        if self.ring.len() == self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(snapshot);
+    }
+
+    // Drops the snapshots taken after `cycle`, a future that's been abandoned
+    pub fn forget_after(&mut self, cycle: usize) {
+        while self.ring.back().is_some_and(|s| s.get_cycle() > cycle) {
+            self.ring.pop_back();
+        }
+    }
+
+    // The newest snapshot taken at or before `cycle`
+    pub fn nearest(&self, cycle: usize) -> Option<&MachineSnapshot> {
+        self.ring.iter().rev().find(|s| s.get_cycle() <= cycle)
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use crate::bus::Address;
+
+// Labels for addresses, usually loaded from an assembler's label file
+#[derive(Default)]
+pub struct Symbols {
+    by_name: BTreeMap<String, Address>,
+}
+
+fn parse_hex_address(s: &str) -> Option<Address> {
+    let digits = s.trim_start_matches("C:").trim_start_matches('$').trim_start_matches("0x");
+    // ld65 label files write 24 bit addresses, e.g. 00C000
+    u32::from_str_radix(digits, 16).ok().map(|a| (a & 0xffff) as Address)
+}
+
+impl Symbols {
+    pub fn insert(&mut self, name: &str, address: Address) {
+        self.by_name.insert(name.to_string(), address);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Address> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn name_for(&self, address: Address) -> Option<&str> {
+        self.by_name.iter().find(|(_, a)| **a == address).map(|(n, _)| n.as_str())
+    }
+
+    // The nearest symbol at or below address, for attributing code to the routine it belongs to
+    pub fn containing(&self, address: Address) -> Option<(&str, Address)> {
+        self.iter().filter(|(_, a)| *a <= address).max_by_key(|(_, a)| *a)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Address)> {
+        self.by_name.iter().map(|(n, a)| (n.as_str(), *a))
+    }
+
+    // Understands VICE/ld65 -Ln lines ("al 00C000 .reset") and "name = $C000" / "name $C000" lines.
+    // Returns the number of symbols added
+    pub fn load(&mut self, text: &str) -> usize {
+        let mut added = 0;
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().filter(|w| *w != "=").collect();
+            let parsed = match words.as_slice() {
+                ["al", address, name] => parse_hex_address(address).map(|a| (name.trim_start_matches('.'), a)),
+                [name, address] => parse_hex_address(address).map(|a| (name.trim_end_matches(':'), a)),
+                _ => None,
+            };
+            if let Some((name, address)) = parsed {
+                self.insert(name, address);
+                added += 1;
+            }
+        }
+        added
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::bus::{Address, Bus};
+use crate::debugger::DebuggerError;
+use crate::disasm::Disassembler;
+use crate::processor::Registers;
+
+// Which instructions get logged. Empty filters let everything through
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub range: Option<(Address, Address)>,
+    pub mnemonics: Vec<String>,
+}
+
+enum TraceSink {
+    Console(String),
+    File(BufWriter<File>),
+}
+
+// Logs executed instructions matching a filter, "trace on range 0200..02ff mnemonic LDA,STA to run.log"
+pub struct Trace {
+    filter: TraceFilter,
+    sink: TraceSink,
+    disassembler: Disassembler,
+    lines: usize,
+}
+
+impl TraceFilter {
+    fn matches(&self, address: Address, mnemonic: &str) -> bool {
+        let in_range = match self.range {
+            Some((start, end)) => start <= address && address <= end,
+            None => true,
+        };
+        in_range && (self.mnemonics.is_empty() || self.mnemonics.iter().any(|m| m.eq_ignore_ascii_case(mnemonic)))
+    }
+}
+
+impl Trace {
+    pub fn new(filter: TraceFilter, path: Option<&str>) -> Result<Trace, DebuggerError> {
+        let sink = match path {
+            Some(path) => {
+                let file = File::create(path).map_err(|e| DebuggerError::BadArgument(format!("{}: {}", path, e)))?;
+                TraceSink::File(BufWriter::new(file))
+            }
+            None => TraceSink::Console(String::new()),
+        };
+        Ok(Trace {
+            filter,
+            sink,
+            disassembler: Disassembler::new(),
+            lines: 0,
+        })
+    }
+
+    // Called before the instruction at registers.pc executes
+    pub fn record(&mut self, registers: &Registers, bus: &dyn Bus) {
+        let line = self.disassembler.decode(bus, registers.pc);
+        if !self.filter.matches(registers.pc, &line.mnemonic) {
+            return;
+        }
+        let text = line.to_string();
+        self.lines += 1;
+        match &mut self.sink {
+            TraceSink::Console(out) => writeln!(out, "{:<32}{}", text, registers).unwrap(),
+            // a failing log file shouldn't stop the machine
+            TraceSink::File(file) => writeln!(file, "{:<32}{}", text, registers).unwrap_or(()),
+        }
+    }
+
+    // Lines logged to the console since the last call
+    pub fn take_output(&mut self) -> String {
+        match &mut self.sink {
+            TraceSink::Console(out) => std::mem::take(out),
+            TraceSink::File(file) => {
+                file.flush().unwrap_or(());
+                String::new()
+            }
+        }
+    }
+
+    pub fn get_lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn describe(&self) -> String {
+        let mut out = String::from("tracing");
+        if let Some((start, end)) = self.filter.range {
+            write!(out, " ${:04X}..${:04X}", start, end).unwrap();
+        }
+        if !self.filter.mnemonics.is_empty() {
+            write!(out, " {}", self.filter.mnemonics.join(",")).unwrap();
+        }
+        if let TraceSink::File(_) = self.sink {
+            out.push_str(" to file");
+        }
+        write!(out, ", {} lines", self.lines).unwrap();
+        out
+    }
+}
@@ -0,0 +1,207 @@
+use std::io;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::backend::Backend;
+use ratatui::{DefaultTerminal, Frame, Terminal};
+
+use crate::bus::{Address, Bus};
+use crate::debugger::{DebugEvent, Debugger, RunMode};
+use crate::disasm::Disassembler;
+use crate::loader::hexdump;
+use crate::system::{Speed, DEFAULT_CLOCK_HZ};
+
+const CONSOLE_LINES: usize = 200;
+const MEMORY_PAGE: Address = 0x80;
+
+// Full screen front end for a Debugger:
+//   s step, b step backwards, c continue, f change speed, : type a debugger command,
+//   PgUp/PgDn scroll memory, q quit
+// Below max speed continue redraws as it goes and any key stops it
+struct Tui<'a> {
+    debugger: &'a mut Debugger,
+    disassembler: Disassembler,
+    console: Vec<String>,
+    memory_start: Address,
+    input: Option<String>,
+}
+
+pub fn run_tui(debugger: &mut Debugger) -> io::Result<()> {
+    let mut tui = Tui::new(debugger);
+    let mut terminal = ratatui::init();
+    let result = tui.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+// Draws the panes once onto any terminal without waiting for keys, e.g. a TestBackend's
+pub fn draw_tui<B: Backend>(debugger: &mut Debugger, terminal: &mut Terminal<B>) -> Result<(), B::Error> {
+    let tui = Tui::new(debugger);
+    terminal.draw(|frame| tui.draw(frame))?;
+    Ok(())
+}
+
+impl<'a> Tui<'a> {
+    fn new(debugger: &'a mut Debugger) -> Tui<'a> {
+        Tui {
+            debugger,
+            disassembler: Disassembler::new(),
+            console: vec!["s step  b back  c continue  f speed  : command  PgUp/PgDn memory  q quit".to_string()],
+            memory_start: 0x0000,
+            input: None,
+        }
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(input) = &mut self.input {
+                    match key.code {
+                        KeyCode::Enter => {
+                            let line = input.clone();
+                            self.input = None;
+                            self.run_command(&line);
+                        }
+                        KeyCode::Esc => self.input = None,
+                        KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        KeyCode::Char(c) => input.push(c),
+                        _ => {}
+                    }
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('s') => self.run_command("step"),
+                    KeyCode::Char('b') => self.run_command("rstep"),
+                    KeyCode::Char('c') => match self.debugger.get_speed() {
+                        Speed::RealTime | Speed::SlowMotion(_) => self.run_visibly(terminal)?,
+                        _ => self.run_command("continue"),
+                    },
+                    KeyCode::Char('f') => {
+                        let speed = self.debugger.get_speed().next();
+                        self.run_command(&format!("speed {}", speed));
+                    }
+                    KeyCode::Char(':') => self.input = Some(String::new()),
+                    KeyCode::PageUp => self.memory_start = self.memory_start.wrapping_sub(MEMORY_PAGE),
+                    KeyCode::PageDown => self.memory_start = self.memory_start.wrapping_add(MEMORY_PAGE),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Continues a frame at a time, drawing each, until the machine stops or a key is pressed
+    fn run_visibly(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        self.console.push("> continue, any key stops".to_string());
+        let rate = self.debugger.get_speed().get_rate(DEFAULT_CLOCK_HZ).unwrap_or(DEFAULT_CLOCK_HZ);
+        let frame = (rate / 30).max(1) as usize;
+        loop {
+            match self.debugger.resume(RunMode::Cycles(frame)) {
+                Ok(events) => {
+                    for event in events {
+                        match event {
+                            DebugEvent::ScriptOutput(text) => self.console.extend(text.lines().map(|l| l.to_string())),
+                            DebugEvent::Stopped { reason, .. } => {
+                                self.console.push(reason.to_string());
+                                return Ok(());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.console.push(e.to_string());
+                    return Ok(());
+                }
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(Duration::ZERO)? {
+                // the key only stops the run
+                event::read()?;
+                self.console.push("stopped".to_string());
+                return Ok(());
+            }
+        }
+    }
+
+    fn run_command(&mut self, line: &str) {
+        self.console.push(format!("> {}", line));
+        match self.debugger.execute(line) {
+            Ok(out) => self.console.extend(out.lines().map(|l| l.to_string())),
+            Err(e) => self.console.push(e.to_string()),
+        }
+        if self.console.len() > CONSOLE_LINES {
+            self.console.drain(..self.console.len() - CONSOLE_LINES);
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [top, memory, console, input] = Layout::vertical([
+            Constraint::Percentage(45),
+            Constraint::Length(10),
+            Constraint::Min(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [disassembly, side] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(top);
+        let [registers, stack] = Layout::vertical([Constraint::Length(4), Constraint::Min(3)]).areas(side);
+
+        // the panes are read through Bus::peek, by hexdump::dump and the disassembler, so a redraw
+        // doesn't acknowledge the I/O registers on show
+        let attached = self.debugger.attached().ok();
+        match &attached {
+            Some((processor, bus)) => {
+                let r = processor.borrow().get_registers();
+                let bus = bus.borrow();
+                self.draw_disassembly(frame, disassembly, &*bus, r.pc);
+                let flags: String = "NV-BDIZC"
+                    .chars()
+                    .enumerate()
+                    .map(|(i, f)| if r.status & (0x80 >> i) != 0 { f } else { '.' })
+                    .collect();
+                let text = format!("{}\n{}  cycles {}", r, flags, processor.borrow().get_total_cycles());
+                frame.render_widget(Paragraph::new(text).block(Block::bordered().title("Registers")), registers);
+                let stack_dump = hexdump::dump(&*bus, 0x01c0, 0x01ff);
+                frame.render_widget(Paragraph::new(stack_dump).block(Block::bordered().title("Stack page")), stack);
+                let end = self.memory_start.saturating_add(MEMORY_PAGE - 1);
+                let memory_dump = hexdump::dump(&*bus, self.memory_start, end);
+                frame.render_widget(Paragraph::new(memory_dump).block(Block::bordered().title("Memory")), memory);
+            }
+            None => frame.render_widget(Paragraph::new("no machine attached").block(Block::bordered()), top),
+        }
+
+        let visible = console.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self.console.iter().skip(self.console.len().saturating_sub(visible)).map(|l| Line::from(l.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Console")), console);
+
+        let prompt = match (&self.input, self.debugger.get_assembly_address()) {
+            (Some(i), Some(a)) => format!("{:04X}: {}", a, i),
+            (Some(i), None) if self.debugger.is_in_monitor() => format!("\\{}", i),
+            (Some(i), None) => format!(":{}", i),
+            (None, _) => String::new(),
+        };
+        frame.render_widget(Paragraph::new(prompt), input);
+    }
+
+    fn draw_disassembly(&self, frame: &mut Frame, area: Rect, bus: &dyn Bus, pc: Address) {
+        let mut lines = vec![];
+        let mut address = pc;
+        for i in 0..area.height.saturating_sub(2) {
+            let line = self.disassembler.decode(bus, address);
+            let style = if i == 0 { Style::default().add_modifier(Modifier::REVERSED) } else { Style::default() };
+            lines.push(Line::styled(line.to_string(), style));
+            address = address.wrapping_add(line.len() as Address);
+        }
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Disassembly")), area);
+    }
+}
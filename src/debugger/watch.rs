@@ -0,0 +1,117 @@
+use std::fmt;
+
+use crate::bus::{Address, Bus};
+use crate::debugger::symbols::Symbols;
+use crate::debugger::{parse_address, DebuggerError};
+use crate::processor::Registers;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Register {
+    A,
+    X,
+    Y,
+    P,
+    PC,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Source {
+    Register(Register),
+    Memory(Address),
+    Constant(Address),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Width {
+    Byte,
+    Word,
+}
+
+// An expression shown after every stop, e.g. "A", "*($10)", "*(ptr) as u16"
+#[derive(PartialEq, Debug, Clone)]
+pub struct WatchExpression {
+    text: String,
+    source: Source,
+    width: Width,
+}
+
+impl WatchExpression {
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<WatchExpression, DebuggerError> {
+        let bad = || DebuggerError::BadArgument(text.to_string());
+        let (term, cast) = match text.split_once(" as ") {
+            Some((term, cast)) => (term.trim(), Some(cast.trim())),
+            None => (text.trim(), None),
+        };
+
+        let source = if let Some(pointer) = term.strip_prefix('*') {
+            let pointer = pointer.trim().trim_start_matches('(').trim_end_matches(')').trim();
+            let address = match symbols.get(pointer) {
+                Some(a) => a,
+                None => parse_address(pointer).map_err(|_| bad())?,
+            };
+            Source::Memory(address)
+        } else {
+            match term.to_ascii_uppercase().as_str() {
+                "A" => Source::Register(Register::A),
+                "X" => Source::Register(Register::X),
+                "Y" => Source::Register(Register::Y),
+                "P" => Source::Register(Register::P),
+                "PC" => Source::Register(Register::PC),
+                _ => match symbols.get(term) {
+                    Some(a) => Source::Constant(a),
+                    None => Source::Constant(parse_address(term).map_err(|_| bad())?),
+                },
+            }
+        };
+
+        let width = match cast {
+            Some("u8") => Width::Byte,
+            Some("u16") => Width::Word,
+            Some(_) => return Err(bad()),
+            None => match source {
+                Source::Register(Register::PC) | Source::Constant(_) => Width::Word,
+                _ => Width::Byte,
+            },
+        };
+
+        Ok(WatchExpression {
+            text: text.trim().to_string(),
+            source,
+            width,
+        })
+    }
+
+    pub fn evaluate(&self, registers: &Registers, bus: &dyn Bus) -> u16 {
+        let value = match self.source {
+            Source::Register(Register::A) => registers.a as u16,
+            Source::Register(Register::X) => registers.x as u16,
+            Source::Register(Register::Y) => registers.y as u16,
+            Source::Register(Register::P) => registers.status as u16,
+            Source::Register(Register::PC) => registers.pc,
+            Source::Memory(address) => match self.width {
+                Width::Byte => bus.peek(address) as u16,
+                Width::Word => bus.peek(address) as u16 | (bus.peek(address.wrapping_add(1)) as u16) << 8,
+            },
+            Source::Constant(c) => c,
+        };
+        match self.width {
+            Width::Byte => value & 0xff,
+            Width::Word => value,
+        }
+    }
+
+    // "text = $value" using the expression's width
+    pub fn display(&self, registers: &Registers, bus: &dyn Bus) -> String {
+        let value = self.evaluate(registers, bus);
+        match self.width {
+            Width::Byte => format!("{} = ${:02X}", self.text, value),
+            Width::Word => format!("{} = ${:04X}", self.text, value),
+        }
+    }
+}
+
+impl fmt::Display for WatchExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
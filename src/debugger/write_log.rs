@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::bus::{Address, Data};
+
+// One memory write made by the program
+#[derive(PartialEq, Debug, Clone)]
+pub struct WriteRecord {
+    pub address: Address,
+    pub old: Data,
+    pub new: Data,
+    // the instruction that wrote it and the cycle it started on
+    pub pc: Address,
+    pub cycle: usize,
+}
+
+impl fmt::Display for WriteRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${:04X} ${:02X} -> ${:02X} by ${:04X} at cycle {}",
+            self.address, self.old, self.new, self.pc, self.cycle
+        )
+    }
+}
+
+// The most recent writes, oldest dropped first
+pub struct WriteLog {
+    records: VecDeque<WriteRecord>,
+    capacity: usize,
+}
+
+impl WriteLog {
+    pub fn new(capacity: usize) -> WriteLog {
+        WriteLog {
+            records: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: WriteRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    // Removes and returns the most recent write
+    pub fn pop(&mut self) -> Option<WriteRecord> {
+        self.records.pop_back()
+    }
+
+    // Drop writes made by instructions starting at or after cycle, after stepping backwards
+    pub fn forget_from(&mut self, cycle: usize) {
+        while self.records.back().is_some_and(|r| r.cycle >= cycle) {
+            self.records.pop_back();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    // Most recent first
+    pub fn recent(&self) -> impl Iterator<Item = &WriteRecord> {
+        self.records.iter().rev()
+    }
+
+    // Writes to one address, most recent first
+    pub fn writes_to(&self, address: Address) -> impl Iterator<Item = &WriteRecord> {
+        self.recent().filter(move |r| r.address == address)
+    }
+}
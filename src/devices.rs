@@ -0,0 +1,139 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::bus::{Bus, BusDevice};
+#[cfg(feature = "devices")]
+use crate::bus::Data;
+use crate::logging::DEVICE;
+
+// Memory mapped peripherals to put on a bus next to Memory, with the devices feature. The
+// Peripheral traits the System drives them through are always here
+#[cfg(feature = "devices")]
+pub mod acia;
+#[cfg(feature = "devices")]
+pub mod beeper;
+#[cfg(feature = "devices")]
+pub mod cartridge;
+#[cfg(feature = "devices")]
+pub mod char_out;
+#[cfg(feature = "devices")]
+pub mod cia;
+#[cfg(feature = "devices")]
+pub mod disk;
+#[cfg(feature = "devices")]
+pub mod easy6502;
+#[cfg(feature = "devices")]
+pub mod framebuffer;
+#[cfg(feature = "devices")]
+pub mod gpio;
+#[cfg(feature = "serial")]
+pub mod host_serial;
+#[cfg(feature = "devices")]
+pub mod interrupt_controller;
+#[cfg(feature = "devices")]
+pub mod keyboard;
+#[cfg(feature = "devices")]
+pub mod pia;
+#[cfg(feature = "devices")]
+pub mod printer;
+#[cfg(feature = "devices")]
+pub mod riot;
+#[cfg(feature = "devices")]
+pub mod rtc;
+#[cfg(feature = "devices")]
+pub mod sim65;
+#[cfg(feature = "devices")]
+pub mod tcp_serial;
+#[cfg(feature = "devices")]
+pub mod text_screen;
+#[cfg(feature = "devices")]
+pub mod timer;
+#[cfg(feature = "devices")]
+pub mod via;
+#[cfg(feature = "devices")]
+pub mod watchdog;
+
+// A device with a life of its own between bus accesses. The machine ticks each one with the
+// cycles every instruction took, resets them along with the processor and ORs their IRQ outputs
+// for System::irq_asserted, which reports them but doesn't interrupt the processor
+pub trait Peripheral: BusDevice {
+    fn tick(&mut self, _cycles: usize) {}
+
+    // The chip's RESET line: registers back to their power on values
+    fn reset(&mut self) {}
+
+    fn irq_asserted(&self) -> bool {
+        false
+    }
+}
+
+// An 8 bit I/O port with a data direction register, as on the RIOT, VIA and CIA
+#[cfg(feature = "devices")]
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Port {
+    pub(crate) output: Data,
+    pub(crate) direction: Data,
+    pub(crate) input: Data,
+}
+
+#[cfg(feature = "devices")]
+impl Port {
+    // Output pins read back what was written, input pins whatever drives them
+    pub(crate) fn read(&self) -> Data {
+        (self.output & self.direction) | (self.input & !self.direction)
+    }
+}
+
+// Every peripheral in a machine, driven together
+#[derive(Default)]
+pub struct Peripherals {
+    devices: Vec<Rc<RefCell<dyn Peripheral>>>,
+    // the cycles each has been ticked for
+    serviced: Vec<Cell<u64>>,
+}
+
+impl Peripherals {
+    pub fn new() -> Peripherals {
+        Peripherals {
+            devices: vec![],
+            serviced: vec![],
+        }
+    }
+
+    pub fn add(&mut self, device: Rc<RefCell<dyn Peripheral>>) {
+        self.devices.push(device);
+        self.serviced.push(Cell::new(0));
+    }
+
+    // Registers the device on the bus and drives it
+    pub fn attach<P: Peripheral + 'static>(&mut self, bus: &mut dyn Bus, device: &Rc<RefCell<P>>) {
+        let bus_device: Rc<RefCell<dyn BusDevice>> = device.clone();
+        bus.register_device(&bus_device);
+        self.add(device.clone());
+    }
+
+    pub fn tick(&self, cycles: usize) {
+        for (device, serviced) in self.devices.iter().zip(&self.serviced) {
+            device.borrow_mut().tick(cycles);
+            serviced.set(serviced.get() + cycles as u64);
+        }
+    }
+
+    pub fn reset(&self) {
+        log::debug!(target: DEVICE, "resetting {} peripherals", self.devices.len());
+        for device in &self.devices {
+            device.borrow_mut().reset();
+        }
+    }
+
+    pub fn irq_asserted(&self) -> bool {
+        self.devices.iter().any(|d| d.borrow().irq_asserted())
+    }
+
+    // The cycles a device on the bus has been ticked for, or None if it isn't one of these
+    pub fn get_serviced_cycles(&self, device: &Rc<RefCell<dyn BusDevice>>) -> Option<u64> {
+        let device = Rc::as_ptr(device).cast::<()>();
+        let index = self.devices.iter().position(|d| Rc::as_ptr(d).cast::<()>() == device)?;
+        Some(self.serviced[index].get())
+    }
+}
@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::ToSocketAddrs;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+#[cfg(feature = "serial")]
+use crate::devices::host_serial::HostSerial;
+use crate::devices::tcp_serial::TcpSerial;
+use crate::devices::Peripheral;
+
+const IRQ: Data = 0x80;
+const TDRE: Data = 0x10;
+const RDRF: Data = 0x08;
+const OVERRUN: Data = 0x04;
+
+// Where an ACIA's bytes come from and go to
+pub trait SerialBackend {
+    // The next received byte, without waiting
+    fn receive(&mut self) -> Option<Data>;
+    fn transmit(&mut self, data: Data);
+}
+
+// The host terminal: transmitted bytes go to stdout, stdin is read on its own thread so the
+// emulation never blocks waiting for a key
+pub struct StdioSerial {
+    input: Receiver<Data>,
+}
+
+impl Default for StdioSerial {
+    fn default() -> Self {
+        StdioSerial::new()
+    }
+}
+
+impl StdioSerial {
+    pub fn new() -> StdioSerial {
+        let (sender, input) = channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 64];
+            while let Ok(n) = io::stdin().read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                for b in &buffer[..n] {
+                    // terminals send \n, 6502 software expects a carriage return
+                    let b = if *b == b'\n' { b'\r' } else { *b };
+                    if sender.send(b).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        StdioSerial { input }
+    }
+}
+
+impl SerialBackend for StdioSerial {
+    fn receive(&mut self) -> Option<Data> {
+        self.input.try_recv().ok()
+    }
+
+    fn transmit(&mut self, data: Data) {
+        let mut out = io::stdout();
+        let _ = out.write_all(&[data]);
+        let _ = out.flush();
+    }
+}
+
+struct Registers {
+    received: Data,
+    status: Data,
+    command: Data,
+    control: Data,
+}
+
+// The 6551 ACIA at base..base+3: data, status (writing it resets the chip), command, control.
+// Transmission is instant so the transmit register always reads as empty. The receiver takes
+// the next byte from the backend whenever the receive register is empty
+pub struct Acia {
+    base: Address,
+    backend: RefCell<Box<dyn SerialBackend>>,
+    registers: RefCell<Registers>,
+}
+
+impl Acia {
+    pub fn new(base: Address, backend: Box<dyn SerialBackend>) -> Acia {
+        Acia {
+            base,
+            backend: RefCell::new(backend),
+            registers: RefCell::new(Registers {
+                received: 0,
+                status: TDRE,
+                command: 0,
+                control: 0,
+            }),
+        }
+    }
+
+    // The host terminal ACIA
+    pub fn stdio(base: Address) -> Acia {
+        Acia::new(base, Box::new(StdioSerial::new()))
+    }
+
+    // A console on a TCP port, e.g. Acia::tcp(0x8400, "127.0.0.1:6502")
+    pub fn tcp(base: Address, address: impl ToSocketAddrs) -> io::Result<Acia> {
+        Ok(Acia::new(base, Box::new(TcpSerial::listen(address)?)))
+    }
+
+    // Wired to a real serial port, e.g. Acia::serial(0x8400, "/dev/ttyUSB0", 9600)
+    #[cfg(feature = "serial")]
+    pub fn serial(base: Address, path: &str, baud: u32) -> io::Result<Acia> {
+        Ok(Acia::new(base, Box::new(HostSerial::open(path, baud)?)))
+    }
+
+    fn poll(&self) {
+        let mut registers = self.registers.borrow_mut();
+        if registers.status & RDRF == 0 {
+            if let Some(data) = self.backend.borrow_mut().receive() {
+                registers.received = data;
+                registers.status |= RDRF | IRQ;
+            }
+        }
+    }
+}
+
+impl Peripheral for Acia {
+    fn tick(&mut self, _cycles: usize) {
+        self.poll();
+    }
+
+    fn reset(&mut self) {
+        *self.registers.get_mut() = Registers {
+            received: 0,
+            status: TDRE,
+            command: 0,
+            control: 0,
+        };
+    }
+
+    fn irq_asserted(&self) -> bool {
+        let registers = self.registers.borrow();
+        let receive_irq = registers.command & 0x03 == 0x01 && registers.status & RDRF != 0;
+        let transmit_irq = registers.command & 0x0c == 0x04;
+        receive_irq || transmit_irq
+    }
+}
+
+impl BusDevice for Acia {
+    fn do_read(&self, address: Address) -> Data {
+        self.poll();
+        let mut registers = self.registers.borrow_mut();
+        match address - self.base {
+            0 => {
+                registers.status &= !(RDRF | OVERRUN);
+                registers.received
+            }
+            1 => {
+                let status = registers.status;
+                registers.status &= !IRQ;
+                status
+            }
+            2 => registers.command,
+            _ => registers.control,
+        }
+    }
+
+    // without polling the backend, so a byte that's arrived since the last read doesn't show
+    fn peek(&self, address: Address) -> Data {
+        let registers = self.registers.borrow();
+        match address - self.base {
+            0 => registers.received,
+            1 => registers.status,
+            2 => registers.command,
+            _ => registers.control,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        let registers = self.registers.get_mut();
+        match address - self.base {
+            0 => self.backend.get_mut().transmit(data),
+            1 => {
+                // programmed reset keeps the control register and the parity bits
+                registers.command &= 0xe0;
+                registers.status &= !OVERRUN;
+            }
+            2 => registers.command = data,
+            _ => registers.control = data,
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 4
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 4
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Acia {
+    fn get_name(&self) -> String {
+        "acia".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let registers = self.registers.borrow();
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("status".to_string(), format!("${:02X}", registers.status)),
+            ("command".to_string(), format!("${:02X}", registers.command)),
+            ("control".to_string(), format!("${:02X}", registers.control)),
+            ("received".to_string(), format!("${:02X}", registers.received)),
+            ("irq".to_string(), self.irq_asserted().to_string()),
+        ]
+    }
+}
@@ -0,0 +1,215 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+#[cfg(feature = "audio")]
+use std::io;
+#[cfg(feature = "audio")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const AMPLITUDE: f32 = 0.25;
+// the speaker is treated as silent once it hasn't moved for a tenth of a second
+const IDLE_DIVISOR: u64 = 10;
+
+// Where sound goes, mono samples between -1 and 1
+pub trait AudioSink {
+    fn get_sample_rate(&self) -> u32;
+    fn play(&mut self, samples: &[f32]);
+}
+
+// An Apple II style one bit speaker: any access to its address ($C030 there) flips the cone.
+// Flips are stamped with the cycle count so tick() can turn them into samples at the sink's
+// rate; tick after every instruction to keep the pitch right
+pub struct Beeper {
+    address: Address,
+    clock_hz: u64,
+    cycle: u64,
+    // cycles at which the speaker flipped, not yet turned into samples
+    edges: RefCell<VecDeque<u64>>,
+    toggles: Cell<usize>,
+    level: bool,
+    last_edge: Option<u64>,
+    next_sample: f64,
+    sink: Option<Box<dyn AudioSink>>,
+}
+
+impl Beeper {
+    pub fn new(address: Address, clock_hz: u64) -> Beeper {
+        Beeper {
+            address,
+            clock_hz: clock_hz.max(1),
+            cycle: 0,
+            edges: RefCell::new(VecDeque::new()),
+            toggles: Cell::new(0),
+            level: false,
+            last_edge: None,
+            next_sample: 0.0,
+            sink: None,
+        }
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn AudioSink>) -> Beeper {
+        self.sink = Some(sink);
+        self
+    }
+
+    pub fn get_toggles(&self) -> usize {
+        self.toggles.get()
+    }
+
+    fn toggle(&self) {
+        self.edges.borrow_mut().push_back(self.cycle);
+        self.toggles.set(self.toggles.get() + 1);
+    }
+}
+
+impl Peripheral for Beeper {
+    fn tick(&mut self, cycles: usize) {
+        let end = self.cycle + cycles as u64;
+        let sink = match &mut self.sink {
+            Some(s) => s,
+            None => {
+                self.edges.get_mut().clear();
+                self.cycle = end;
+                return;
+            }
+        };
+        let per_sample = self.clock_hz as f64 / sink.get_sample_rate().max(1) as f64;
+        let edges = self.edges.get_mut();
+        let mut samples = vec![];
+        while self.next_sample < end as f64 {
+            while edges.front().is_some_and(|&e| e as f64 <= self.next_sample) {
+                self.last_edge = edges.pop_front();
+                self.level = !self.level;
+            }
+            let idle = match self.last_edge {
+                Some(edge) => (self.next_sample as u64).saturating_sub(edge) > self.clock_hz / IDLE_DIVISOR,
+                None => true,
+            };
+            samples.push(match (idle, self.level) {
+                (true, _) => 0.0,
+                (false, true) => AMPLITUDE,
+                (false, false) => -AMPLITUDE,
+            });
+            self.next_sample += per_sample;
+        }
+        self.cycle = end;
+        if !samples.is_empty() {
+            sink.play(&samples);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.edges.get_mut().clear();
+        self.level = false;
+        self.last_edge = None;
+    }
+}
+
+impl BusDevice for Beeper {
+    // reading flips the speaker too, as the Apple II's does
+    fn do_read(&self, _address: Address) -> Data {
+        self.toggle();
+        0
+    }
+
+    fn peek(&self, _address: Address) -> Data {
+        0
+    }
+
+    fn do_write(&mut self, _address: Address, _data: Data) {
+        self.toggle();
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address == self.address
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address == self.address
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Beeper {
+    fn get_name(&self) -> String {
+        "beeper".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("address".to_string(), format!("${:04X}", self.address)),
+            ("clock".to_string(), format!("{} Hz", self.clock_hz)),
+            ("toggles".to_string(), self.toggles.get().to_string()),
+        ]
+    }
+}
+
+// The host's default output device. Samples queue up for the audio thread; anything more
+// than a quarter of a second behind is dropped so the sound doesn't lag the emulation
+#[cfg(feature = "audio")]
+pub struct CpalOutput {
+    _stream: cpal::Stream,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+}
+
+#[cfg(feature = "audio")]
+impl CpalOutput {
+    pub fn new() -> io::Result<CpalOutput> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| io::Error::other("no audio output device"))?;
+        let config: cpal::StreamConfig = device.default_output_config().map_err(io::Error::other)?.into();
+        let channels = config.channels as usize;
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let source = Arc::clone(&queue);
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut queue = source.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(queue.pop_front().unwrap_or(0.0));
+                    }
+                },
+                |e| log::error!("audio stream error: {}", e),
+                None,
+            )
+            .map_err(io::Error::other)?;
+        stream.play().map_err(io::Error::other)?;
+        Ok(CpalOutput {
+            _stream: stream,
+            queue,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioSink for CpalOutput {
+    fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let most = self.sample_rate as usize / 4;
+        if queue.len() > most {
+            let late = queue.len() - most;
+            queue.drain(..late);
+        }
+    }
+}
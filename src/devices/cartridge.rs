@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const BANK_8K: usize = 0x2000;
+const WINDOW_16K: usize = 0x4000;
+
+// Decides which ROM byte the CPU sees at an address, and what writes do to the banking
+pub trait Mapper {
+    // Offset into the ROM image, or None when the cartridge doesn't answer this address
+    fn map(&self, address: Address) -> Option<usize>;
+    // A write into the cartridge's window, typically a bank latch
+    fn write(&mut self, address: Address, data: Data);
+    // e.g. "latch 8K, bank 2 of 4"
+    fn describe(&self) -> String;
+
+    // Bank registers for snapshots
+    fn save_state(&self) -> Vec<Data> {
+        vec![]
+    }
+
+    fn load_state(&mut self, _state: &[Data]) {}
+
+    // Back to the power on banking
+    fn reset(&mut self) {}
+}
+
+// 16K of ROM at base with no banking; smaller images repeat to fill the window
+pub struct Fixed16K {
+    base: Address,
+}
+
+impl Fixed16K {
+    pub fn new(base: Address) -> Fixed16K {
+        Fixed16K { base }
+    }
+}
+
+impl Mapper for Fixed16K {
+    fn map(&self, address: Address) -> Option<usize> {
+        let offset = address.wrapping_sub(self.base) as usize;
+        if address >= self.base && offset < WINDOW_16K {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn write(&mut self, _address: Address, _data: Data) {}
+
+    fn describe(&self) -> String {
+        format!("fixed 16K at ${:04X}", self.base)
+    }
+}
+
+// An 8K window at base onto one of `banks` 8K banks; writing anything in the window selects
+// bank data % banks
+pub struct Latch8K {
+    base: Address,
+    banks: usize,
+    bank: usize,
+}
+
+impl Latch8K {
+    pub fn new(base: Address, banks: usize) -> Latch8K {
+        Latch8K {
+            base,
+            banks: banks.max(1),
+            bank: 0,
+        }
+    }
+
+    pub fn get_bank(&self) -> usize {
+        self.bank
+    }
+}
+
+impl Mapper for Latch8K {
+    fn map(&self, address: Address) -> Option<usize> {
+        let offset = address.wrapping_sub(self.base) as usize;
+        if address >= self.base && offset < BANK_8K {
+            Some(self.bank * BANK_8K + offset)
+        } else {
+            None
+        }
+    }
+
+    fn write(&mut self, _address: Address, data: Data) {
+        self.bank = data as usize % self.banks;
+    }
+
+    fn describe(&self) -> String {
+        format!("latch 8K at ${:04X}, bank {} of {}", self.base, self.bank, self.banks)
+    }
+
+    fn save_state(&self) -> Vec<Data> {
+        vec![self.bank as Data]
+    }
+
+    fn load_state(&mut self, state: &[Data]) {
+        if let Some(bank) = state.first() {
+            self.bank = *bank as usize % self.banks;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+}
+
+// A ROM image whose banking is left to a Mapper. Offsets past the end of the image wrap
+// around, as the missing address lines would on real hardware
+pub struct Cartridge {
+    rom: Vec<Data>,
+    mapper: Box<dyn Mapper>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<Data>, mapper: Box<dyn Mapper>) -> Cartridge {
+        Cartridge { rom, mapper }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>, mapper: Box<dyn Mapper>) -> io::Result<Cartridge> {
+        let rom = fs::read(path)?;
+        if rom.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty cartridge image"));
+        }
+        Ok(Cartridge::new(rom, mapper))
+    }
+}
+
+impl Peripheral for Cartridge {
+    fn reset(&mut self) {
+        self.mapper.reset();
+    }
+}
+
+impl BusDevice for Cartridge {
+    fn do_read(&self, address: Address) -> Data {
+        match self.mapper.map(address) {
+            Some(offset) if !self.rom.is_empty() => self.rom[offset % self.rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        self.mapper.write(address, data);
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.mapper.map(address).is_some()
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.mapper.map(address).is_some()
+    }
+
+    fn save_state(&self) -> Option<Vec<Data>> {
+        Some(self.mapper.save_state())
+    }
+
+    fn load_state(&mut self, state: &[Data]) {
+        self.mapper.load_state(state);
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Cartridge {
+    fn get_name(&self) -> String {
+        "cartridge".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("rom".to_string(), format!("{} bytes", self.rom.len())),
+            ("mapper".to_string(), self.mapper.describe()),
+        ]
+    }
+}
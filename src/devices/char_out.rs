@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+// The smallest possible console: a byte written to `address` is printed, e.g. $F001.
+// Output is held until a newline or until anything is written to the control register
+// at address + 1
+pub struct CharOut {
+    address: Address,
+    writer: Box<dyn Write>,
+    pending: Vec<Data>,
+    written: usize,
+}
+
+impl CharOut {
+    // Prints to stdout
+    pub fn new(address: Address) -> CharOut {
+        CharOut::with_writer(address, Box::new(io::stdout()))
+    }
+
+    pub fn with_writer(address: Address, writer: Box<dyn Write>) -> CharOut {
+        CharOut {
+            address,
+            writer,
+            pending: vec![],
+            written: 0,
+        }
+    }
+
+    pub fn flush(&mut self) {
+        // a console that went away shouldn't stop the program
+        let _ = self.writer.write_all(&self.pending);
+        let _ = self.writer.flush();
+        self.pending.clear();
+    }
+}
+
+impl Peripheral for CharOut {
+    fn reset(&mut self) {
+        self.flush();
+    }
+}
+
+impl Drop for CharOut {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl BusDevice for CharOut {
+    fn do_read(&self, _address: Address) -> Data {
+        0
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if address == self.address {
+            self.pending.push(data);
+            self.written += 1;
+            if data == b'\n' {
+                self.flush();
+            }
+        } else {
+            self.flush();
+        }
+    }
+
+    fn is_readable_for(&self, _address: Address) -> bool {
+        false
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address == self.address || address == self.address.wrapping_add(1)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for CharOut {
+    fn get_name(&self) -> String {
+        "charout".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("address".to_string(), format!("${:04X}", self.address)),
+            ("written".to_string(), self.written.to_string()),
+            ("pending".to_string(), String::from_utf8_lossy(&self.pending).escape_debug().to_string()),
+        ]
+    }
+}
@@ -0,0 +1,243 @@
+use std::cell::Cell;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::{Peripheral, Port};
+
+const TA_FLAG: Data = 0x01;
+const TB_FLAG: Data = 0x02;
+const START: Data = 0x01;
+const ONE_SHOT: Data = 0x08;
+const FORCE_LOAD: Data = 0x10;
+
+#[derive(Clone, Copy)]
+struct CiaTimer {
+    latch: u16,
+    counter: u16,
+    control: Data,
+}
+
+impl CiaTimer {
+    fn new() -> CiaTimer {
+        CiaTimer {
+            latch: 0xffff,
+            counter: 0xffff,
+            control: 0,
+        }
+    }
+
+    // Counts down system cycles, returning how many times it underflowed
+    fn tick(&mut self, cycles: usize) -> usize {
+        if self.control & START == 0 {
+            return 0;
+        }
+        if cycles <= self.counter as usize {
+            self.counter -= cycles as u16;
+            return 0;
+        }
+        // the counter reloads from the latch on the cycle after it reaches 0
+        let over = cycles - self.counter as usize - 1;
+        if self.control & ONE_SHOT != 0 {
+            self.control &= !START;
+            self.counter = self.latch;
+            return 1;
+        }
+        let period = self.latch as usize + 1;
+        self.counter = self.latch - (over % period) as u16;
+        1 + over / period
+    }
+
+    fn write_control(&mut self, data: Data) {
+        if data & FORCE_LOAD != 0 {
+            self.counter = self.latch;
+        }
+        self.control = data & !FORCE_LOAD;
+    }
+}
+
+// The 6526 CIA at base..base+$F (mirrored every 16 bytes, as the C64 decodes it):
+//   $0/$1 ports A and B, $2/$3 their directions
+//   $4/$5 timer A, $6/$7 timer B (reads give the counter, writes the latch; writing the high
+//   byte of a stopped timer loads it)
+//   $8-$B time of day, held but not counted, $C serial data, held
+//   $D ICR: reads give the flags (bit 7 any enabled) and clear them, writes set (bit 7) or
+//   clear the mask. Bit 0 timer A, bit 1 timer B, bit 4 FLAG pin
+//   $E/$F CRA/CRB: bit 0 start, bit 3 one shot, bit 4 force load
+// Timers count system cycles only, not CNT or timer A underflows
+pub struct Cia {
+    base: Address,
+    port_a: Port,
+    port_b: Port,
+    timer_a: CiaTimer,
+    timer_b: CiaTimer,
+    tod: [Data; 4],
+    serial: Data,
+    mask: Data,
+    // reading the ICR clears it, so it lives in a cell
+    flags: Cell<Data>,
+}
+
+impl Cia {
+    pub fn new(base: Address) -> Cia {
+        Cia {
+            base,
+            port_a: Port::default(),
+            port_b: Port::default(),
+            timer_a: CiaTimer::new(),
+            timer_b: CiaTimer::new(),
+            tod: [0; 4],
+            serial: 0,
+            mask: 0,
+            flags: Cell::new(0),
+        }
+    }
+
+    // Drive the port pins from outside. Pins set as outputs ignore it
+    pub fn set_port_a_input(&mut self, input: Data) {
+        self.port_a.input = input;
+    }
+
+    pub fn set_port_b_input(&mut self, input: Data) {
+        self.port_b.input = input;
+    }
+
+    // What the chip drives onto the pins; input pins read as 1, as if pulled up
+    pub fn get_port_a_output(&self) -> Data {
+        self.port_a.output | !self.port_a.direction
+    }
+
+    pub fn get_port_b_output(&self) -> Data {
+        self.port_b.output | !self.port_b.direction
+    }
+
+    // A falling edge on the FLAG pin
+    pub fn flag(&mut self) {
+        self.flags.set(self.flags.get() | 0x10);
+    }
+
+    fn get_icr(&self) -> Data {
+        let flags = self.flags.get();
+        if flags & self.mask != 0 {
+            flags | 0x80
+        } else {
+            flags
+        }
+    }
+}
+
+impl Peripheral for Cia {
+    fn tick(&mut self, cycles: usize) {
+        if self.timer_a.tick(cycles) > 0 {
+            self.flags.set(self.flags.get() | TA_FLAG);
+        }
+        if self.timer_b.tick(cycles) > 0 {
+            self.flags.set(self.flags.get() | TB_FLAG);
+        }
+    }
+
+    fn reset(&mut self) {
+        let (input_a, input_b) = (self.port_a.input, self.port_b.input);
+        *self = Cia::new(self.base);
+        self.port_a.input = input_a;
+        self.port_b.input = input_b;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.flags.get() & self.mask & 0x1f != 0
+    }
+}
+
+impl BusDevice for Cia {
+    fn do_read(&self, address: Address) -> Data {
+        let data = self.peek(address);
+        // reading the ICR acknowledges every interrupt in it
+        if (address - self.base) & 0x0f == 0xd {
+            self.flags.set(0);
+        }
+        data
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        match (address - self.base) & 0x0f {
+            0x0 => self.port_a.read(),
+            0x1 => self.port_b.read(),
+            0x2 => self.port_a.direction,
+            0x3 => self.port_b.direction,
+            0x4 => self.timer_a.counter as Data,
+            0x5 => (self.timer_a.counter >> 8) as Data,
+            0x6 => self.timer_b.counter as Data,
+            0x7 => (self.timer_b.counter >> 8) as Data,
+            r @ 0x8..=0xb => self.tod[(r - 8) as usize],
+            0xc => self.serial,
+            0xd => self.get_icr(),
+            0xe => self.timer_a.control,
+            _ => self.timer_b.control,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        let high = |timer: &mut CiaTimer| {
+            timer.latch = (timer.latch & 0x00ff) | (data as u16) << 8;
+            if timer.control & START == 0 {
+                timer.counter = timer.latch;
+            }
+        };
+        match (address - self.base) & 0x0f {
+            0x0 => self.port_a.output = data,
+            0x1 => self.port_b.output = data,
+            0x2 => self.port_a.direction = data,
+            0x3 => self.port_b.direction = data,
+            0x4 => self.timer_a.latch = (self.timer_a.latch & 0xff00) | data as u16,
+            0x5 => high(&mut self.timer_a),
+            0x6 => self.timer_b.latch = (self.timer_b.latch & 0xff00) | data as u16,
+            0x7 => high(&mut self.timer_b),
+            r @ 0x8..=0xb => self.tod[(r - 8) as usize] = data,
+            0xc => self.serial = data,
+            0xd => {
+                if data & 0x80 != 0 {
+                    self.mask |= data & 0x1f;
+                } else {
+                    self.mask &= !data;
+                }
+            }
+            0xe => self.timer_a.write_control(data),
+            _ => self.timer_b.write_control(data),
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 0x100
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Cia {
+    fn get_name(&self) -> String {
+        "cia".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let timer = |t: &CiaTimer| {
+            let state = if t.control & START != 0 { "running" } else { "stopped" };
+            format!("${:04X} latch ${:04X} {}", t.counter, t.latch, state)
+        };
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("port a".to_string(), format!("${:02X} ddr ${:02X}", self.port_a.read(), self.port_a.direction)),
+            ("port b".to_string(), format!("${:02X} ddr ${:02X}", self.port_b.read(), self.port_b.direction)),
+            ("timer a".to_string(), timer(&self.timer_a)),
+            ("timer b".to_string(), timer(&self.timer_b)),
+            ("icr mask".to_string(), format!("${:02X} ${:02X}", self.get_icr(), self.mask)),
+        ]
+    }
+}
@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+pub const SECTOR_SIZE: usize = 256;
+const DEFAULT_TRACKS: Data = 35;
+const DEFAULT_SECTORS: Data = 16;
+
+const READ_SECTOR: Data = 0x01;
+const WRITE_SECTOR: Data = 0x02;
+
+const ERROR: Data = 0x01;
+
+// A sector based disk over a flat host image file, track after track of 256 byte sectors.
+// Registers at base..base+4: command (write 1 to read a sector into memory, 2 to write one
+// out; reading gives status, bit 0 set when the last command failed), track, sector and the
+// buffer address lo/hi. Sectors move by DMA straight into the `ram` device, so the command
+// has finished by the next instruction. Reading past the end of the image gives zeros and
+// writing there grows the file
+pub struct Disk {
+    base: Address,
+    image: File,
+    ram: Rc<RefCell<dyn BusDevice>>,
+    tracks: Data,
+    sectors: Data,
+    track: Data,
+    sector: Data,
+    buffer: Address,
+    status: Data,
+}
+
+impl Disk {
+    pub fn open(base: Address, path: impl AsRef<Path>, ram: &Rc<RefCell<dyn BusDevice>>) -> io::Result<Disk> {
+        let image = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Ok(Disk {
+            base,
+            image,
+            ram: Rc::clone(ram),
+            tracks: DEFAULT_TRACKS,
+            sectors: DEFAULT_SECTORS,
+            track: 0,
+            sector: 0,
+            buffer: 0,
+            status: 0,
+        })
+    }
+
+    pub fn set_geometry(&mut self, tracks: Data, sectors: Data) {
+        self.tracks = tracks;
+        self.sectors = sectors;
+    }
+
+    fn offset(&self) -> Option<u64> {
+        if self.track >= self.tracks || self.sector >= self.sectors {
+            return None;
+        }
+        Some((self.track as u64 * self.sectors as u64 + self.sector as u64) * SECTOR_SIZE as u64)
+    }
+
+    fn read_sector(&mut self, offset: u64) -> io::Result<()> {
+        let mut data = [0; SECTOR_SIZE];
+        self.image.seek(SeekFrom::Start(offset))?;
+        let mut filled = 0;
+        while filled < SECTOR_SIZE {
+            match self.image.read(&mut data[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        let mut ram = self.ram.borrow_mut();
+        for (i, byte) in data.iter().enumerate() {
+            ram.do_write(self.buffer.wrapping_add(i as Address), *byte);
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self, offset: u64) -> io::Result<()> {
+        let data: Vec<Data> = {
+            let ram = self.ram.borrow();
+            (0..SECTOR_SIZE).map(|i| ram.do_read(self.buffer.wrapping_add(i as Address))).collect()
+        };
+        self.image.seek(SeekFrom::Start(offset))?;
+        self.image.write_all(&data)?;
+        self.image.flush()
+    }
+
+    fn command(&mut self, command: Data) {
+        let result = match (command, self.offset()) {
+            (READ_SECTOR, Some(offset)) => self.read_sector(offset),
+            (WRITE_SECTOR, Some(offset)) => self.write_sector(offset),
+            _ => Err(io::Error::other("bad command, track or sector")),
+        };
+        self.status = if result.is_ok() { 0 } else { ERROR };
+    }
+}
+
+impl Peripheral for Disk {
+    fn reset(&mut self) {
+        self.track = 0;
+        self.sector = 0;
+        self.buffer = 0;
+        self.status = 0;
+    }
+}
+
+impl BusDevice for Disk {
+    fn do_read(&self, address: Address) -> Data {
+        match address - self.base {
+            0 => self.status,
+            1 => self.track,
+            2 => self.sector,
+            3 => self.buffer as Data,
+            _ => (self.buffer >> 8) as Data,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        match address - self.base {
+            0 => self.command(data),
+            1 => self.track = data,
+            2 => self.sector = data,
+            3 => self.buffer = (self.buffer & 0xff00) | data as Address,
+            _ => self.buffer = (self.buffer & 0x00ff) | (data as Address) << 8,
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 5
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Disk {
+    fn get_name(&self) -> String {
+        "disk".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("geometry".to_string(), format!("{} tracks x {} sectors", self.tracks, self.sectors)),
+            ("track".to_string(), self.track.to_string()),
+            ("sector".to_string(), self.sector.to_string()),
+            ("buffer".to_string(), format!("${:04X}", self.buffer)),
+            ("status".to_string(), format!("${:02X}", self.status)),
+        ]
+    }
+}
@@ -0,0 +1,125 @@
+use std::cell::{Cell, RefCell};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::keyboard::KeySource;
+use crate::devices::Peripheral;
+
+const RANDOM: Address = 0x00fe;
+const LAST_KEY: Address = 0x00ff;
+
+// The easy6502.com zero page conventions: $FE reads a new random byte every time and $FF holds
+// the last key pressed (programs write 0 there once they've handled it).
+// Register it on the bus before the RAM covering page zero so these two addresses win
+pub struct Easy6502Io {
+    random: Cell<u32>,
+    last_key: Cell<Data>,
+    source: Option<RefCell<Box<dyn KeySource>>>,
+}
+
+impl Default for Easy6502Io {
+    fn default() -> Self {
+        Easy6502Io::new()
+    }
+}
+
+impl Easy6502Io {
+    // Seeded from the clock, except on wasm32-unknown-unknown where there isn't one to read
+    pub fn new() -> Easy6502Io {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(1);
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        let seed = 1;
+        Easy6502Io::with_seed(seed)
+    }
+
+    // The same random bytes every run, for tests
+    pub fn with_seed(seed: u32) -> Easy6502Io {
+        Easy6502Io {
+            random: Cell::new(seed.max(1)),
+            last_key: Cell::new(0),
+            source: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: Box<dyn KeySource>) -> Easy6502Io {
+        self.source = Some(RefCell::new(source));
+        self
+    }
+
+    pub fn press(&mut self, key: Data) {
+        self.last_key.set(key);
+    }
+
+    // xorshift32, plenty for games
+    fn next_random(&self) -> Data {
+        let mut x = self.random.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random.set(x);
+        (x >> 24) as Data
+    }
+}
+
+impl Peripheral for Easy6502Io {
+    fn reset(&mut self) {
+        self.last_key.set(0);
+    }
+}
+
+impl BusDevice for Easy6502Io {
+    fn do_read(&self, address: Address) -> Data {
+        if address == RANDOM {
+            return self.next_random();
+        }
+        if let Some(source) = &self.source {
+            while let Some(key) = source.borrow_mut().next_key() {
+                self.last_key.set(key);
+            }
+        }
+        self.last_key.get()
+    }
+
+    // the last random number drawn and the last key taken, leaving the generator and the source be
+    fn peek(&self, address: Address) -> Data {
+        if address == RANDOM {
+            (self.random.get() >> 24) as Data
+        } else {
+            self.last_key.get()
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if address == LAST_KEY {
+            self.last_key.set(data);
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address == RANDOM || address == LAST_KEY
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address == LAST_KEY
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Easy6502Io {
+    fn get_name(&self) -> String {
+        "easy6502".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![("last key".to_string(), format!("${:02X}", self.last_key.get()))]
+    }
+}
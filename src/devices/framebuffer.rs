@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+const DEFAULT_CYCLES_PER_FRAME: usize = 16_667; // 60 frames a second at 1MHz
+
+// The easy6502 colours, indexed by the low nibble of a pixel byte
+pub const PALETTE: [u32; 16] = [
+    0x000000, 0xffffff, 0x880000, 0xaaffee, 0xcc44cc, 0x00cc55, 0x0000aa, 0xeeee77, 0xdd8855, 0x664400, 0xff7777, 0x333333,
+    0x777777, 0xaaff66, 0x0088ff, 0xbbbbbb,
+];
+
+// Somewhere to show finished frames, 0xRRGGBB pixels row by row
+pub trait FrameSink {
+    fn present(&mut self, pixels: &[u32], width: usize, height: usize);
+}
+
+// Draws frames in a truecolor terminal, two pixel rows per line using half blocks
+pub struct AnsiDisplay {
+    out: Box<dyn Write>,
+}
+
+impl Default for AnsiDisplay {
+    fn default() -> Self {
+        AnsiDisplay::new()
+    }
+}
+
+impl AnsiDisplay {
+    pub fn new() -> AnsiDisplay {
+        AnsiDisplay::with_writer(Box::new(io::stdout()))
+    }
+
+    pub fn with_writer(out: Box<dyn Write>) -> AnsiDisplay {
+        AnsiDisplay { out }
+    }
+}
+
+impl FrameSink for AnsiDisplay {
+    fn present(&mut self, pixels: &[u32], width: usize, height: usize) {
+        let mut frame = String::from("\x1b[H");
+        for y in (0..height).step_by(2) {
+            for x in 0..width {
+                let top = pixels[y * width + x];
+                let bottom = pixels.get((y + 1) * width + x).copied().unwrap_or(0);
+                frame.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                    top >> 16,
+                    (top >> 8) & 0xff,
+                    top & 0xff,
+                    bottom >> 16,
+                    (bottom >> 8) & 0xff,
+                    bottom & 0xff
+                ));
+            }
+            frame.push_str("\x1b[0m\r\n");
+        }
+        let _ = self.out.write_all(frame.as_bytes());
+        let _ = self.out.flush();
+    }
+}
+
+// easy6502's screen: 32x32 pixels, one byte each, at base..base+$3FF ($0200-$05FF there).
+// It holds the screen memory itself, so register it before the RAM it overlaps. Changed
+// frames go to the sink at most once every cycles_per_frame cycles; without a sink they wait
+// for the front end to take them
+pub struct Framebuffer {
+    base: Address,
+    pixels: Vec<Data>,
+    dirty: bool,
+    cycles_per_frame: usize,
+    cycles: usize,
+    sink: Option<Box<dyn FrameSink>>,
+}
+
+impl Framebuffer {
+    pub fn new(base: Address) -> Framebuffer {
+        Framebuffer {
+            base,
+            pixels: vec![0; WIDTH * HEIGHT],
+            dirty: true,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            cycles: 0,
+            sink: None,
+        }
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn FrameSink>) -> Framebuffer {
+        self.sink = Some(sink);
+        self
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.cycles_per_frame = cycles.max(1);
+    }
+
+    // The screen as 0xRRGGBB pixels
+    pub fn to_rgb(&self) -> Vec<u32> {
+        self.pixels.iter().map(|p| PALETTE[(p & 0x0f) as usize]).collect()
+    }
+
+    // The screen as R, G, B, A bytes, the layout of a canvas's ImageData
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.to_rgb().iter().flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8, 0xff]).collect()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // The frame as RGBA if it's changed since the last one presented or taken
+    pub fn take_frame(&mut self) -> Option<Vec<u8>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(self.to_rgba())
+    }
+
+    // Send the frame to the sink now, changed or not
+    pub fn present(&mut self) {
+        let rgb = self.to_rgb();
+        if let Some(sink) = &mut self.sink {
+            sink.present(&rgb, WIDTH, HEIGHT);
+        }
+        self.dirty = false;
+    }
+
+    fn contains(&self, address: Address) -> bool {
+        address >= self.base && ((address - self.base) as usize) < WIDTH * HEIGHT
+    }
+}
+
+impl Peripheral for Framebuffer {
+    fn tick(&mut self, cycles: usize) {
+        self.cycles += cycles;
+        if self.cycles >= self.cycles_per_frame {
+            self.cycles %= self.cycles_per_frame;
+            if self.dirty && self.sink.is_some() {
+                self.present();
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cycles = 0;
+    }
+}
+
+impl BusDevice for Framebuffer {
+    fn do_read(&self, address: Address) -> Data {
+        self.pixels[(address - self.base) as usize]
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        let pixel = &mut self.pixels[(address - self.base) as usize];
+        if *pixel != data {
+            *pixel = data;
+            self.dirty = true;
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.contains(address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.contains(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self) -> Option<Vec<Data>> {
+        Some(self.pixels.clone())
+    }
+
+    fn load_state(&mut self, state: &[Data]) {
+        self.pixels.copy_from_slice(state);
+        self.dirty = true;
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Framebuffer {
+    fn get_name(&self) -> String {
+        "framebuffer".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("size".to_string(), format!("{}x{}", WIDTH, HEIGHT)),
+            ("frame every".to_string(), format!("{} cycles", self.cycles_per_frame)),
+            ("dirty".to_string(), self.dirty.to_string()),
+        ]
+    }
+}
@@ -0,0 +1,146 @@
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const BITS: usize = 8;
+
+// Told the new level of an output bit whenever it changes, e.g. to light an LED
+pub type OnChange = Box<dyn FnMut(bool)>;
+// Asked the level of an input bit whenever the firmware reads the port, e.g. a test fixture's switch
+pub type OnRead = Box<dyn Fn() -> bool>;
+
+// An eight bit port at base..base+1 whose pins are host closures rather than a BusDevice of
+// their own: $0 data, $1 direction (1 = output). Output bits read back what was written,
+// input bits come from their on_read closure or else from set_inputs()
+pub struct Gpio {
+    base: Address,
+    output: Data,
+    direction: Data,
+    inputs: Data,
+    on_change: Vec<Option<OnChange>>,
+    on_read: Vec<Option<OnRead>>,
+}
+
+impl Gpio {
+    pub fn new(base: Address) -> Gpio {
+        Gpio {
+            base,
+            output: 0,
+            direction: 0,
+            inputs: 0,
+            on_change: (0..BITS).map(|_| None).collect(),
+            on_read: (0..BITS).map(|_| None).collect(),
+        }
+    }
+
+    pub fn on_change(mut self, bit: usize, handler: impl FnMut(bool) + 'static) -> Gpio {
+        self.on_change[bit % BITS] = Some(Box::new(handler));
+        self
+    }
+
+    pub fn on_read(mut self, bit: usize, handler: impl Fn() -> bool + 'static) -> Gpio {
+        self.on_read[bit % BITS] = Some(Box::new(handler));
+        self
+    }
+
+    // Levels for input bits that have no on_read closure
+    pub fn set_inputs(&mut self, inputs: Data) {
+        self.inputs = inputs;
+    }
+
+    // What the port is driving, 0 for input bits
+    pub fn get_outputs(&self) -> Data {
+        self.output & self.direction
+    }
+
+    fn update(&mut self, output: Data, direction: Data) {
+        let before = self.get_outputs();
+        self.output = output;
+        self.direction = direction;
+        let after = self.get_outputs();
+        for (bit, handler) in self.on_change.iter_mut().enumerate() {
+            if let Some(handler) = handler {
+                if (before ^ after) & 1 << bit != 0 {
+                    handler(after & 1 << bit != 0);
+                }
+            }
+        }
+    }
+
+    fn read_pins(&self) -> Data {
+        (0..BITS).fold(self.get_outputs(), |pins, bit| {
+            let mask = 1 << bit;
+            let high = match &self.on_read[bit] {
+                _ if self.direction & mask != 0 => false,
+                Some(handler) => handler(),
+                None => self.inputs & mask != 0,
+            };
+            if high {
+                pins | mask
+            } else {
+                pins
+            }
+        })
+    }
+}
+
+impl Peripheral for Gpio {
+    fn reset(&mut self) {
+        // every pin back to an input
+        self.update(0, 0);
+    }
+}
+
+impl BusDevice for Gpio {
+    fn do_read(&self, address: Address) -> Data {
+        match address - self.base {
+            0 => self.read_pins(),
+            _ => self.direction,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        match address - self.base {
+            0 => self.update(data, self.direction),
+            _ => self.update(self.output, data),
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address == self.base || address == self.base.wrapping_add(1)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self) -> Option<Vec<Data>> {
+        Some(vec![self.output, self.direction])
+    }
+
+    fn load_state(&mut self, state: &[Data]) {
+        self.update(state[0], state[1]);
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Gpio {
+    fn get_name(&self) -> String {
+        "gpio".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("direction".to_string(), format!("{:08b}", self.direction)),
+            ("outputs".to_string(), format!("{:08b}", self.get_outputs())),
+            ("pins".to_string(), format!("{:08b}", self.read_pins())),
+        ]
+    }
+}
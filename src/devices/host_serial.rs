@@ -0,0 +1,50 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::bus::Data;
+use crate::devices::acia::SerialBackend;
+
+// A real serial port on the host, e.g. /dev/ttyUSB0 or COM3, so firmware can talk to
+// external hardware. Bytes are passed through untouched; receive never waits
+pub struct HostSerial {
+    port: Box<dyn SerialPort>,
+    received: VecDeque<Data>,
+}
+
+impl HostSerial {
+    pub fn open(path: &str, baud: u32) -> io::Result<HostSerial> {
+        let port = serialport::new(path, baud).timeout(Duration::from_millis(1)).open()?;
+        Ok(HostSerial {
+            port,
+            received: VecDeque::new(),
+        })
+    }
+
+    fn read_port(&mut self) {
+        let waiting = self.port.bytes_to_read().unwrap_or(0) as usize;
+        if waiting == 0 {
+            return;
+        }
+        let mut buffer = vec![0; waiting];
+        if let Ok(n) = self.port.read(&mut buffer) {
+            self.received.extend(&buffer[..n]);
+        }
+    }
+}
+
+impl SerialBackend for HostSerial {
+    fn receive(&mut self) -> Option<Data> {
+        if self.received.is_empty() {
+            self.read_port();
+        }
+        self.received.pop_front()
+    }
+
+    fn transmit(&mut self, data: Data) {
+        // a cable that was pulled shouldn't stop the program
+        let _ = self.port.write_all(&[data]);
+    }
+}
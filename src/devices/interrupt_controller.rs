@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+pub const MAX_SOURCES: usize = 8;
+const NONE: Data = 0xff;
+
+// Something that can pull the IRQ line, e.g. move || timer.borrow().irq_asserted()
+pub type IrqLine = Box<dyn Fn() -> bool>;
+
+// Collects up to eight device IRQ outputs onto the one CPU IRQ line, source 0 the highest
+// priority. Registers at base..base+2:
+//   $0 pending: bit n set while enabled source n is interrupting
+//   $1 enable mask, all sources enabled at reset
+//   $2 the number of the highest priority source interrupting, $FF when none
+pub struct InterruptController {
+    base: Address,
+    sources: Vec<(String, IrqLine)>,
+    enabled: Data,
+}
+
+impl InterruptController {
+    pub fn new(base: Address) -> InterruptController {
+        InterruptController {
+            base,
+            sources: vec![],
+            enabled: 0xff,
+        }
+    }
+
+    // Adds the next source, returning its number, or None when all eight are taken
+    pub fn add_source(&mut self, name: &str, line: IrqLine) -> Option<usize> {
+        if self.sources.len() == MAX_SOURCES {
+            return None;
+        }
+        self.sources.push((name.to_string(), line));
+        Some(self.sources.len() - 1)
+    }
+
+    // A source that is a Peripheral's IRQ output
+    pub fn add_peripheral<P: Peripheral + 'static>(&mut self, name: &str, device: &Rc<RefCell<P>>) -> Option<usize> {
+        let device = Rc::clone(device);
+        self.add_source(name, Box::new(move || device.borrow().irq_asserted()))
+    }
+
+    pub fn get_pending(&self) -> Data {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, line))| line())
+            .fold(0, |bits, (n, _)| bits | 1 << n)
+            & self.enabled
+    }
+
+    // The highest priority source interrupting
+    pub fn get_active(&self) -> Option<usize> {
+        let pending = self.get_pending();
+        (0..MAX_SOURCES).find(|n| pending & 1 << n != 0)
+    }
+}
+
+impl Peripheral for InterruptController {
+    fn reset(&mut self) {
+        self.enabled = 0xff;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.get_pending() != 0
+    }
+}
+
+impl BusDevice for InterruptController {
+    fn do_read(&self, address: Address) -> Data {
+        match address - self.base {
+            0 => self.get_pending(),
+            1 => self.enabled,
+            _ => self.get_active().map_or(NONE, |n| n as Data),
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if address - self.base == 1 {
+            self.enabled = data;
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 3
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for InterruptController {
+    fn get_name(&self) -> String {
+        "irq".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let pending = self.get_pending();
+        let mut fields = vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("enabled".to_string(), format!("{:08b}", self.enabled)),
+        ];
+        for (n, (name, _)) in self.sources.iter().enumerate() {
+            let state = if pending & 1 << n != 0 { "interrupting" } else { "quiet" };
+            fields.push((format!("{} {}", n, name), state.to_string()));
+        }
+        fields
+    }
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const KEY_AVAILABLE: Data = 0x80;
+const IRQ_ENABLE: Data = 0x01;
+
+// Where key presses come from
+pub trait KeySource {
+    // The next key as ASCII, without waiting
+    fn next_key(&mut self) -> Option<Data>;
+}
+
+// A keyboard at base..base+2: status (bit 7 a key is waiting), data (reading it takes the key)
+// and control (bit 0 raises IRQ while a key is waiting). Keys come from a KeySource or press()
+pub struct Keyboard {
+    base: Address,
+    source: Option<RefCell<Box<dyn KeySource>>>,
+    keys: RefCell<VecDeque<Data>>,
+    control: Data,
+}
+
+impl Keyboard {
+    pub fn new(base: Address) -> Keyboard {
+        Keyboard {
+            base,
+            source: None,
+            keys: RefCell::new(VecDeque::new()),
+            control: 0,
+        }
+    }
+
+    pub fn with_source(base: Address, source: Box<dyn KeySource>) -> Keyboard {
+        Keyboard {
+            source: Some(RefCell::new(source)),
+            ..Keyboard::new(base)
+        }
+    }
+
+    // Queue a key as if it was typed
+    pub fn press(&mut self, key: Data) {
+        self.keys.get_mut().push_back(key);
+    }
+
+    fn poll(&self) {
+        if let Some(source) = &self.source {
+            let mut keys = self.keys.borrow_mut();
+            while let Some(key) = source.borrow_mut().next_key() {
+                keys.push_back(key);
+            }
+        }
+    }
+}
+
+impl Peripheral for Keyboard {
+    fn tick(&mut self, _cycles: usize) {
+        self.poll();
+    }
+
+    fn reset(&mut self) {
+        self.control = 0;
+        self.keys.get_mut().clear();
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.control & IRQ_ENABLE != 0 && !self.keys.borrow().is_empty()
+    }
+}
+
+impl BusDevice for Keyboard {
+    fn do_read(&self, address: Address) -> Data {
+        self.poll();
+        match address - self.base {
+            0 => {
+                if self.keys.borrow().is_empty() {
+                    0
+                } else {
+                    KEY_AVAILABLE
+                }
+            }
+            1 => self.keys.borrow_mut().pop_front().unwrap_or(0),
+            _ => self.control,
+        }
+    }
+
+    // the keys already taken from the source, leaving them queued
+    fn peek(&self, address: Address) -> Data {
+        let keys = self.keys.borrow();
+        match address - self.base {
+            0 if keys.is_empty() => 0,
+            0 => KEY_AVAILABLE,
+            1 => keys.front().copied().unwrap_or(0),
+            _ => self.control,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if address - self.base == 2 {
+            self.control = data;
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 3
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 3
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Keyboard {
+    fn get_name(&self) -> String {
+        "keyboard".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let keys: Vec<String> = self.keys.borrow().iter().map(|k| format!("{:02X}", k)).collect();
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("waiting".to_string(), keys.join(" ")),
+            ("control".to_string(), format!("${:02X}", self.control)),
+        ]
+    }
+}
+
+// Keys typed at the host terminal. The terminal is in raw mode while this exists, so every
+// key arrives immediately, unechoed
+#[cfg(feature = "terminal")]
+pub struct TerminalKeys {
+    keys: std::sync::mpsc::Receiver<Data>,
+}
+
+#[cfg(feature = "terminal")]
+impl TerminalKeys {
+    pub fn new() -> std::io::Result<TerminalKeys> {
+        use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+        crossterm::terminal::enable_raw_mode()?;
+        let (sender, keys) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(e) = event::read() {
+                let key = match e {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                    _ => continue,
+                };
+                let ascii = match key.code {
+                    KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => (c as u8) & 0x1f,
+                    KeyCode::Char(c) if c.is_ascii() => c as u8,
+                    KeyCode::Enter => b'\r',
+                    KeyCode::Backspace => 0x08,
+                    KeyCode::Tab => b'\t',
+                    KeyCode::Esc => 0x1b,
+                    _ => continue,
+                };
+                if sender.send(ascii).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(TerminalKeys { keys })
+    }
+}
+
+#[cfg(feature = "terminal")]
+impl Drop for TerminalKeys {
+    fn drop(&mut self) {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(feature = "terminal")]
+impl KeySource for TerminalKeys {
+    fn next_key(&mut self) -> Option<Data> {
+        self.keys.try_recv().ok()
+    }
+}
@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const IRQ1_FLAG: Data = 0x80;
+const IRQ2_FLAG: Data = 0x40;
+const DATA_ACCESS: Data = 0x04;
+const C2_OUTPUT: Data = 0x20;
+
+// One half of the PIA: a port, its direction and control registers and the C1/C2 lines
+#[derive(Default)]
+struct Side {
+    output: Data,
+    direction: Data,
+    input: Data,
+    control: Data,
+    c1: bool,
+    c2: bool,
+    // C2 as an output, high when idle
+    c2_out: bool,
+    // cycles until a pulse mode C2 goes high again
+    c2_pulse: usize,
+}
+
+impl Side {
+    fn new() -> Side {
+        Side { c2_out: true, ..Default::default() }
+    }
+
+    fn read_port(&self) -> Data {
+        (self.output & self.direction) | (self.input & !self.direction)
+    }
+
+    // CA1/CB1 input. The control register picks the active edge
+    fn set_c1(&mut self, level: bool) {
+        let rising = self.control & 0x02 != 0;
+        if level != self.c1 && level == rising {
+            self.control |= IRQ1_FLAG;
+            // handshake mode: the C1 transition brings C2 back up
+            if self.control & 0x38 == C2_OUTPUT {
+                self.c2_out = true;
+            }
+        }
+        self.c1 = level;
+    }
+
+    // CA2/CB2 when it's an input
+    fn set_c2(&mut self, level: bool) {
+        if self.control & C2_OUTPUT == 0 {
+            let rising = self.control & 0x10 != 0;
+            if level != self.c2 && level == rising {
+                self.control |= IRQ2_FLAG;
+            }
+        }
+        self.c2 = level;
+    }
+
+    // Reading port A or writing port B acknowledges interrupts and starts a C2 handshake
+    fn handshake(&mut self) {
+        match self.control & 0x38 {
+            0x20 => self.c2_out = false,
+            0x28 => {
+                self.c2_out = false;
+                self.c2_pulse = 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn write_control(&mut self, data: Data) {
+        self.control = (self.control & (IRQ1_FLAG | IRQ2_FLAG)) | (data & 0x3f);
+        if data & 0x30 == 0x30 {
+            self.c2_out = data & 0x08 != 0;
+        }
+        if data & C2_OUTPUT != 0 {
+            self.control &= !IRQ2_FLAG;
+        }
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        if self.c2_pulse > 0 {
+            self.c2_pulse = self.c2_pulse.saturating_sub(cycles);
+            if self.c2_pulse == 0 {
+                self.c2_out = true;
+            }
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        let c1 = self.control & IRQ1_FLAG != 0 && self.control & 0x01 != 0;
+        let c2 = self.control & IRQ2_FLAG != 0 && self.control & (C2_OUTPUT | 0x08) == 0x08;
+        c1 || c2
+    }
+}
+
+// The 6520/6821 PIA at base..base+3: port A data or direction, control A, port B data or
+// direction, control B. Bit 2 of a control register picks data (1) or direction (0).
+// Control bits 7 and 6 are the C1 and C2 interrupt flags, cleared by reading that side's data.
+pub struct Pia {
+    base: Address,
+    a: RefCell<Side>,
+    b: RefCell<Side>,
+}
+
+impl Pia {
+    pub fn new(base: Address) -> Pia {
+        Pia {
+            base,
+            a: RefCell::new(Side::new()),
+            b: RefCell::new(Side::new()),
+        }
+    }
+
+    pub fn set_port_a_input(&mut self, input: Data) {
+        self.a.get_mut().input = input;
+    }
+
+    pub fn set_port_b_input(&mut self, input: Data) {
+        self.b.get_mut().input = input;
+    }
+
+    // Output pins as driven, input pins read as 1
+    pub fn get_port_a_output(&self) -> Data {
+        let a = self.a.borrow();
+        a.output | !a.direction
+    }
+
+    pub fn get_port_b_output(&self) -> Data {
+        let b = self.b.borrow();
+        b.output | !b.direction
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.get_mut().set_c1(level);
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        self.a.get_mut().set_c2(level);
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.get_mut().set_c1(level);
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        self.b.get_mut().set_c2(level);
+    }
+
+    // CA2/CB2 when programmed as outputs
+    pub fn get_ca2(&self) -> bool {
+        self.a.borrow().c2_out
+    }
+
+    pub fn get_cb2(&self) -> bool {
+        self.b.borrow().c2_out
+    }
+
+    pub fn irqa_asserted(&self) -> bool {
+        self.a.borrow().irq_asserted()
+    }
+
+    pub fn irqb_asserted(&self) -> bool {
+        self.b.borrow().irq_asserted()
+    }
+
+    fn side(&self, offset: Address) -> &RefCell<Side> {
+        if offset < 2 {
+            &self.a
+        } else {
+            &self.b
+        }
+    }
+}
+
+impl Peripheral for Pia {
+    fn tick(&mut self, cycles: usize) {
+        self.a.get_mut().tick(cycles);
+        self.b.get_mut().tick(cycles);
+    }
+
+    fn reset(&mut self) {
+        // the registers clear, whatever drives the pins keeps driving them
+        for side in [self.a.get_mut(), self.b.get_mut()] {
+            *side = Side {
+                input: side.input,
+                c1: side.c1,
+                c2: side.c2,
+                ..Side::new()
+            };
+        }
+    }
+
+    // IRQA and IRQB are usually wired together
+    fn irq_asserted(&self) -> bool {
+        self.irqa_asserted() || self.irqb_asserted()
+    }
+}
+
+impl BusDevice for Pia {
+    fn do_read(&self, address: Address) -> Data {
+        let offset = address - self.base;
+        let mut side = self.side(offset).borrow_mut();
+        if offset & 1 == 1 {
+            return side.control;
+        }
+        if side.control & DATA_ACCESS == 0 {
+            return side.direction;
+        }
+        side.control &= !(IRQ1_FLAG | IRQ2_FLAG);
+        if offset == 0 {
+            side.handshake();
+        }
+        side.read_port()
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        let offset = address - self.base;
+        let side = self.side(offset).borrow();
+        if offset & 1 == 1 {
+            side.control
+        } else if side.control & DATA_ACCESS == 0 {
+            side.direction
+        } else {
+            side.read_port()
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        let offset = address - self.base;
+        let mut side = self.side(offset).borrow_mut();
+        if offset & 1 == 1 {
+            side.write_control(data);
+        } else if side.control & DATA_ACCESS == 0 {
+            side.direction = data;
+        } else {
+            side.output = data;
+            if offset == 2 {
+                side.handshake();
+            }
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 4
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 4
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Pia {
+    fn get_name(&self) -> String {
+        "pia".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let a = self.a.borrow();
+        let b = self.b.borrow();
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("port a".to_string(), format!("${:02X} ddr ${:02X} cr ${:02X}", a.read_port(), a.direction, a.control)),
+            ("port b".to_string(), format!("${:02X} ddr ${:02X} cr ${:02X}", b.read_port(), b.direction, b.control)),
+            ("ca1 ca2".to_string(), format!("{} {}", a.c1 as u8, a.c2_out as u8)),
+            ("cb1 cb2".to_string(), format!("{} {}", b.c1 as u8, b.c2_out as u8)),
+            ("irq".to_string(), format!("a {} b {}", self.irqa_asserted(), self.irqb_asserted())),
+        ]
+    }
+}
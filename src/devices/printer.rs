@@ -0,0 +1,131 @@
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const READY: Data = 0x80;
+const ERROR: Data = 0x01;
+const DEFAULT_BUSY_CYCLES: usize = 0;
+
+// A line printer at base..base+1: $0 data (a byte written while ready is printed),
+// $1 status (bit 7 ready, bit 0 the host file couldn't be written). After each byte it is
+// busy for busy_cycles cycles and bytes written meanwhile are lost, so firmware should wait
+// for ready. Output is appended to a host file a line at a time
+pub struct Printer {
+    base: Address,
+    out: Box<dyn Write>,
+    busy_cycles: usize,
+    busy: usize,
+    printed: usize,
+    dropped: usize,
+    error: bool,
+}
+
+impl Printer {
+    // Appends to the file, creating it if needed
+    pub fn open(base: Address, path: impl AsRef<Path>) -> io::Result<Printer> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Printer::with_writer(base, Box::new(LineWriter::new(file))))
+    }
+
+    pub fn with_writer(base: Address, out: Box<dyn Write>) -> Printer {
+        Printer {
+            base,
+            out,
+            busy_cycles: DEFAULT_BUSY_CYCLES,
+            busy: 0,
+            printed: 0,
+            dropped: 0,
+            error: false,
+        }
+    }
+
+    // How long each byte keeps the printer busy, e.g. 10_000 for 100 characters a second at 1MHz
+    pub fn set_busy_cycles(&mut self, cycles: usize) {
+        self.busy_cycles = cycles;
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.busy == 0
+    }
+
+    pub fn flush(&mut self) {
+        if self.out.flush().is_err() {
+            self.error = true;
+        }
+    }
+}
+
+impl Peripheral for Printer {
+    fn tick(&mut self, cycles: usize) {
+        self.busy = self.busy.saturating_sub(cycles);
+    }
+
+    fn reset(&mut self) {
+        self.busy = 0;
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl BusDevice for Printer {
+    fn do_read(&self, address: Address) -> Data {
+        match address - self.base {
+            0 => 0,
+            _ => (if self.is_ready() { READY } else { 0 }) | (if self.error { ERROR } else { 0 }),
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if address != self.base {
+            return;
+        }
+        if !self.is_ready() {
+            self.dropped += 1;
+            return;
+        }
+        if self.out.write_all(&[data]).is_err() {
+            self.error = true;
+        }
+        self.printed += 1;
+        self.busy = self.busy_cycles;
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address == self.base || address == self.base.wrapping_add(1)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Printer {
+    fn get_name(&self) -> String {
+        "printer".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("ready".to_string(), self.is_ready().to_string()),
+            ("printed".to_string(), self.printed.to_string()),
+            ("dropped".to_string(), self.dropped.to_string()),
+            ("error".to_string(), self.error.to_string()),
+        ]
+    }
+}
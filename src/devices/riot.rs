@@ -0,0 +1,239 @@
+use std::cell::Cell;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::{Peripheral, Port};
+
+const RAM_SIZE: Address = 128;
+const IO_SIZE: Address = 32;
+const TIMER_FLAG: Data = 0x80;
+const PA7_FLAG: Data = 0x40;
+
+// The 6532 RIOT: 128 bytes of RAM, two 8 bit ports and an interval timer.
+//
+// The RAM and the I/O registers sit at their own base addresses, as the RS pin selects them on a
+// real board. I/O register offsets follow the chip's address lines:
+//   $00 port A data, $01 port A direction, $02 port B data, $03 port B direction
+//   read  $04/$0C timer (bit 3 turns the timer IRQ off/on), $05 interrupt flags (bit 7 timer, bit 6 PA7)
+//   write $14-$17 start the timer at 1, 8, 64 or 1024 cycles a count, $1C-$1F the same with IRQ on
+//   write $04-$07 PA7 edge detect: bit 0 rising edge, bit 1 IRQ on
+pub struct Riot {
+    ram_base: Address,
+    io_base: Address,
+    ram: Vec<Data>,
+    port_a: Port,
+    port_b: Port,
+    timer: Data,
+    // cycles per timer count and how far into the current count we are
+    interval: usize,
+    prescale: usize,
+    // reading the timer sets it from address bit 3, so it lives in a cell
+    timer_irq: Cell<bool>,
+    pa7_rising: bool,
+    pa7_irq: bool,
+    // reading the timer or the flags clears them, so they live in cells
+    flags: Cell<Data>,
+}
+
+impl Riot {
+    pub fn new(ram_base: Address, io_base: Address) -> Riot {
+        Riot {
+            ram_base,
+            io_base,
+            ram: vec![0; RAM_SIZE as usize],
+            port_a: Port::default(),
+            port_b: Port::default(),
+            timer: 0xff,
+            interval: 1024,
+            prescale: 0,
+            timer_irq: Cell::new(false),
+            pa7_rising: false,
+            pa7_irq: false,
+            flags: Cell::new(0),
+        }
+    }
+
+    // Drive the port A pins from outside. Pins set as outputs ignore it
+    pub fn set_port_a_input(&mut self, input: Data) {
+        let before = self.port_a.read() & 0x80;
+        self.port_a.input = input;
+        let after = self.port_a.read() & 0x80;
+        let edge = if self.pa7_rising { before == 0 && after != 0 } else { before != 0 && after == 0 };
+        if edge {
+            self.flags.set(self.flags.get() | PA7_FLAG);
+        }
+    }
+
+    pub fn set_port_b_input(&mut self, input: Data) {
+        self.port_b.input = input;
+    }
+
+    // What the chip drives onto the pins; input pins read as 1, as if pulled up
+    pub fn get_port_a_output(&self) -> Data {
+        self.port_a.output | !self.port_a.direction
+    }
+
+    pub fn get_port_b_output(&self) -> Data {
+        self.port_b.output | !self.port_b.direction
+    }
+
+    fn io_read(&self, offset: Address) -> Data {
+        let data = self.io_peek(offset);
+        if offset & 0x04 != 0 {
+            // reading the timer acknowledges its interrupt and turns it on or off by bit 3, reading
+            // the flags acknowledges the PA7 edge
+            let acknowledged = if offset & 0x01 == 0 {
+                self.timer_irq.set(offset & 0x08 != 0);
+                TIMER_FLAG
+            } else {
+                PA7_FLAG
+            };
+            self.flags.set(self.flags.get() & !acknowledged);
+        }
+        data
+    }
+
+    fn io_peek(&self, offset: Address) -> Data {
+        if offset & 0x04 == 0 {
+            return match offset & 0x03 {
+                0 => self.port_a.read(),
+                1 => self.port_a.direction,
+                2 => self.port_b.read(),
+                _ => self.port_b.direction,
+            };
+        }
+        if offset & 0x01 == 0 {
+            self.timer
+        } else {
+            self.flags.get()
+        }
+    }
+
+    fn io_write(&mut self, offset: Address, data: Data) {
+        if offset & 0x04 == 0 {
+            match offset & 0x03 {
+                0 => self.port_a.output = data,
+                1 => self.port_a.direction = data,
+                2 => self.port_b.output = data,
+                _ => self.port_b.direction = data,
+            }
+        } else if offset & 0x10 != 0 {
+            self.interval = [1, 8, 64, 1024][(offset & 0x03) as usize];
+            self.prescale = 0;
+            self.timer = data;
+            self.timer_irq.set(offset & 0x08 != 0);
+            self.flags.set(self.flags.get() & !TIMER_FLAG);
+        } else {
+            self.pa7_rising = offset & 0x01 != 0;
+            self.pa7_irq = offset & 0x02 != 0;
+        }
+    }
+
+    fn is_ram(&self, address: Address) -> bool {
+        address >= self.ram_base && address - self.ram_base < RAM_SIZE
+    }
+
+    fn is_io(&self, address: Address) -> bool {
+        address >= self.io_base && address - self.io_base < IO_SIZE
+    }
+}
+
+impl Peripheral for Riot {
+    // Advance the timer by a number of CPU cycles
+    fn tick(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.prescale += 1;
+            if self.prescale < self.interval {
+                continue;
+            }
+            self.prescale = 0;
+            let (timer, underflow) = self.timer.overflowing_sub(1);
+            self.timer = timer;
+            if underflow {
+                // after time out the timer keeps counting down once a cycle
+                self.flags.set(self.flags.get() | TIMER_FLAG);
+                self.interval = 1;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        // RAM survives, the ports go back to inputs and the interrupts off
+        self.port_a = Port {
+            input: self.port_a.input,
+            ..Port::default()
+        };
+        self.port_b = Port {
+            input: self.port_b.input,
+            ..Port::default()
+        };
+        self.timer_irq.set(false);
+        self.pa7_rising = false;
+        self.pa7_irq = false;
+        self.flags.set(0);
+    }
+
+    fn irq_asserted(&self) -> bool {
+        let flags = self.flags.get();
+        (self.timer_irq.get() && flags & TIMER_FLAG != 0) || (self.pa7_irq && flags & PA7_FLAG != 0)
+    }
+}
+
+impl BusDevice for Riot {
+    fn do_read(&self, address: Address) -> Data {
+        if self.is_ram(address) {
+            self.ram[(address - self.ram_base) as usize]
+        } else {
+            self.io_read(address - self.io_base)
+        }
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        if self.is_ram(address) {
+            self.ram[(address - self.ram_base) as usize]
+        } else {
+            self.io_peek(address - self.io_base)
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if self.is_ram(address) {
+            self.ram[(address - self.ram_base) as usize] = data;
+        } else {
+            self.io_write(address - self.io_base, data);
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.is_ram(address) || self.is_io(address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_ram(address) || self.is_io(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Riot {
+    fn get_name(&self) -> String {
+        "riot".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("ram".to_string(), format!("${:04X}", self.ram_base)),
+            ("io".to_string(), format!("${:04X}", self.io_base)),
+            ("port a".to_string(), format!("${:02X} ddr ${:02X}", self.port_a.read(), self.port_a.direction)),
+            ("port b".to_string(), format!("${:02X} ddr ${:02X}", self.port_b.read(), self.port_b.direction)),
+            ("timer".to_string(), format!("${:02X} /{}", self.timer, self.interval)),
+            ("flags".to_string(), format!("${:02X}", self.flags.get())),
+            ("irq".to_string(), self.irq_asserted().to_string()),
+        ]
+    }
+}
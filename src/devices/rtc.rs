@@ -0,0 +1,157 @@
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const IRQ_ENABLE: Data = 0x01;
+const SECOND_PASSED: Data = 0x80;
+
+// Where the time of day comes from
+enum Clock {
+    // the host's clock, UTC
+    Host,
+    // starts at a given second of the day and advances with emulated cycles, for repeatable runs
+    Fixed { start: u64, clock_hz: u64, cycles: u64 },
+}
+
+// A real time clock at base..base+4:
+//   $0 seconds, $1 minutes, $2 hours (binary, 24 hour)
+//   $3 control: bit 0 raises IRQ once a second
+//   $4 status: bit 7 a second has passed, cleared by reading it
+// The time is sampled on tick()
+pub struct Rtc {
+    base: Address,
+    clock: Clock,
+    // seconds since midnight
+    now: u64,
+    control: Data,
+    second_passed: Cell<bool>,
+}
+
+fn host_seconds() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) % SECONDS_PER_DAY
+}
+
+impl Rtc {
+    pub fn new(base: Address) -> Rtc {
+        Rtc {
+            base,
+            clock: Clock::Host,
+            now: host_seconds(),
+            control: 0,
+            second_passed: Cell::new(false),
+        }
+    }
+
+    // Starts at hours:minutes:seconds and counts emulated time at clock_hz cycles a second
+    pub fn fixed(base: Address, hours: Data, minutes: Data, seconds: Data, clock_hz: u64) -> Rtc {
+        let start = (hours as u64 * 3600 + minutes as u64 * 60 + seconds as u64) % SECONDS_PER_DAY;
+        Rtc {
+            base,
+            clock: Clock::Fixed {
+                start,
+                clock_hz: clock_hz.max(1),
+                cycles: 0,
+            },
+            now: start,
+            control: 0,
+            second_passed: Cell::new(false),
+        }
+    }
+
+    // (hours, minutes, seconds)
+    pub fn get_time(&self) -> (Data, Data, Data) {
+        ((self.now / 3600) as Data, (self.now / 60 % 60) as Data, (self.now % 60) as Data)
+    }
+}
+
+impl Peripheral for Rtc {
+    fn tick(&mut self, cycles: usize) {
+        let now = match &mut self.clock {
+            Clock::Host => host_seconds(),
+            Clock::Fixed { start, clock_hz, cycles: elapsed } => {
+                *elapsed += cycles as u64;
+                (*start + *elapsed / *clock_hz) % SECONDS_PER_DAY
+            }
+        };
+        if now != self.now {
+            self.now = now;
+            self.second_passed.set(true);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.control = 0;
+        self.second_passed.set(false);
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.control & IRQ_ENABLE != 0 && self.second_passed.get()
+    }
+}
+
+impl BusDevice for Rtc {
+    fn do_read(&self, address: Address) -> Data {
+        let data = self.peek(address);
+        if address - self.base == 4 {
+            self.second_passed.set(false);
+        }
+        data
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        let (hours, minutes, seconds) = self.get_time();
+        match address - self.base {
+            0 => seconds,
+            1 => minutes,
+            2 => hours,
+            3 => self.control,
+            _ if self.second_passed.get() => SECOND_PASSED,
+            _ => 0,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if address - self.base == 3 {
+            self.control = data;
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 5
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Rtc {
+    fn get_name(&self) -> String {
+        "rtc".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let (hours, minutes, seconds) = self.get_time();
+        let source = match self.clock {
+            Clock::Host => "host".to_string(),
+            Clock::Fixed { clock_hz, .. } => format!("fixed, {} cycles a second", clock_hz),
+        };
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("time".to_string(), format!("{:02}:{:02}:{:02}", hours, minutes, seconds)),
+            ("clock".to_string(), source),
+            ("irq".to_string(), self.irq_asserted().to_string()),
+        ]
+    }
+}
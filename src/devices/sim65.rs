@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+
+use crate::bus::{Address, Bus, Data};
+use crate::memory::Memory;
+use crate::processor::ProcessorTrait;
+
+// cc65's sim65 paravirtualisation: a JSR to one of these runs the host function, then the
+// RTS sitting at that address returns to the program
+pub const PARAVIRT_BASE: Address = 0xfff4;
+const OPEN: Address = PARAVIRT_BASE;
+const CLOSE: Address = PARAVIRT_BASE + 1;
+const READ: Address = PARAVIRT_BASE + 2;
+const WRITE: Address = PARAVIRT_BASE + 3;
+const ARGS: Address = PARAVIRT_BASE + 4;
+const EXIT: Address = PARAVIRT_BASE + 5;
+const RTS: Data = 0x60;
+
+const MAGIC: &[u8] = b"sim65";
+const VERSION: Data = 2;
+const HEADER_SIZE: usize = 12;
+const RESET_VECTOR: Address = 0xfffc;
+
+// cc65's fcntl.h open flags
+const O_RDONLY: u16 = 0x01;
+const O_WRONLY: u16 = 0x02;
+const O_ACCESS: u16 = 0x03;
+const O_CREAT: u16 = 0x10;
+const O_TRUNC: u16 = 0x20;
+const O_APPEND: u16 = 0x40;
+const O_EXCL: u16 = 0x80;
+
+const FAILED: u16 = 0xffff;
+
+// A program built with cc65's sim6502/sim65c02 target: "sim65", version 2, cpu, the zero page
+// address of the C stack pointer, load and reset addresses, then the code
+pub struct Sim65Image {
+    pub cpu: Data,
+    pub sp_address: Data,
+    pub load: Address,
+    pub reset: Address,
+    pub code: Vec<Data>,
+}
+
+impl Sim65Image {
+    pub fn parse(bytes: &[Data]) -> io::Result<Sim65Image> {
+        if bytes.len() < HEADER_SIZE || &bytes[..5] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a sim65 binary"));
+        }
+        if bytes[5] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("sim65 header version {} not supported", bytes[5])));
+        }
+        Ok(Sim65Image {
+            cpu: bytes[6],
+            sp_address: bytes[7],
+            load: bytes[8] as Address | (bytes[9] as Address) << 8,
+            reset: bytes[10] as Address | (bytes[11] as Address) << 8,
+            code: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    // The code, the reset vector and an RTS at each paravirtualisation hook
+    pub fn load_into(&self, memory: &mut Memory) {
+        memory.write(self.load, self.code.clone());
+        memory.write(PARAVIRT_BASE, vec![RTS; 6]);
+        memory.write(RESET_VECTOR, vec![self.reset as Data, (self.reset >> 8) as Data]);
+    }
+}
+
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+// The host side of sim65's open/close/read/write/args/exit. Call trap() at every instruction
+// boundary; when the pc is on a hook it takes the arguments from A/X and the C stack, does
+// the work against host files and leaves the result in A/X. File descriptors 0-2 are the
+// host's stdin, stdout and stderr
+pub struct Sim65 {
+    sp_address: Address,
+    files: HashMap<u16, Handle>,
+    args: Vec<String>,
+    exit_code: Option<Data>,
+}
+
+impl Sim65 {
+    pub fn new(sp_address: Data) -> Sim65 {
+        let mut files = HashMap::new();
+        files.insert(0, Handle::Stdin);
+        files.insert(1, Handle::Stdout);
+        files.insert(2, Handle::Stderr);
+        Sim65 {
+            sp_address: sp_address as Address,
+            files,
+            args: vec![],
+            exit_code: None,
+        }
+    }
+
+    // argv for the program, starting with its name
+    pub fn with_args(mut self, args: Vec<String>) -> Sim65 {
+        self.args = args;
+        self
+    }
+
+    // Set once the program has called exit()
+    pub fn get_exit_code(&self) -> Option<Data> {
+        self.exit_code
+    }
+
+    // Runs the hook the pc is on, returning false when it isn't on one
+    pub fn trap(&mut self, processor: &mut dyn ProcessorTrait, bus: &dyn Bus) -> bool {
+        let mut registers = processor.get_registers();
+        let ax = registers.a as u16 | (registers.x as u16) << 8;
+        let result = match registers.pc {
+            OPEN => self.open(bus, registers.y),
+            CLOSE => self.close(ax),
+            READ => self.read(bus, ax),
+            WRITE => self.write(bus, ax),
+            ARGS => self.write_args(bus, ax),
+            EXIT => {
+                self.exit_code = Some(registers.a);
+                return true;
+            }
+            _ => return false,
+        };
+        registers.a = result as Data;
+        registers.x = (result >> 8) as Data;
+        processor.set_registers(&registers);
+        true
+    }
+
+    fn read_word(bus: &dyn Bus, address: Address) -> u16 {
+        bus.read(address) as u16 | (bus.read(address.wrapping_add(1)) as u16) << 8
+    }
+
+    fn write_word(bus: &dyn Bus, address: Address, value: u16) {
+        bus.write(address, value as Data);
+        bus.write(address.wrapping_add(1), (value >> 8) as Data);
+    }
+
+    // The word on top of the C stack, dropping `size` bytes
+    fn pop_param(&self, bus: &dyn Bus, size: u16) -> u16 {
+        let sp = Sim65::read_word(bus, self.sp_address);
+        let value = Sim65::read_word(bus, sp);
+        Sim65::write_word(bus, self.sp_address, sp.wrapping_add(size));
+        value
+    }
+
+    // open(name, flags, ...) with Y the number of argument bytes
+    fn open(&mut self, bus: &dyn Bus, y: Data) -> u16 {
+        let _mode = self.pop_param(bus, (y as u16).saturating_sub(4));
+        let flags = self.pop_param(bus, 2);
+        let mut address = self.pop_param(bus, 2);
+        let mut name = vec![];
+        loop {
+            let c = bus.read(address);
+            if c == 0 {
+                break;
+            }
+            name.push(c);
+            address = address.wrapping_add(1);
+        }
+        let mut options = OpenOptions::new();
+        match flags & O_ACCESS {
+            O_RDONLY => options.read(true),
+            O_WRONLY => options.write(true),
+            _ => options.read(true).write(true),
+        };
+        options
+            .create(flags & O_CREAT != 0)
+            .truncate(flags & O_TRUNC != 0)
+            .append(flags & O_APPEND != 0)
+            .create_new(flags & O_EXCL != 0);
+        match options.open(String::from_utf8_lossy(&name).as_ref()) {
+            Ok(file) => {
+                let fd = (3..FAILED).find(|fd| !self.files.contains_key(fd)).unwrap_or(FAILED);
+                self.files.insert(fd, Handle::File(file));
+                fd
+            }
+            Err(_) => FAILED,
+        }
+    }
+
+    fn close(&mut self, fd: u16) -> u16 {
+        match self.files.remove(&fd) {
+            Some(_) => 0,
+            None => FAILED,
+        }
+    }
+
+    // read(fd, buffer, count)
+    fn read(&mut self, bus: &dyn Bus, count: u16) -> u16 {
+        let buffer = self.pop_param(bus, 2);
+        let fd = self.pop_param(bus, 2);
+        let mut data = vec![0; count as usize];
+        let result = match self.files.get_mut(&fd) {
+            Some(Handle::Stdin) => io::stdin().read(&mut data),
+            Some(Handle::File(file)) => file.read(&mut data),
+            _ => return FAILED,
+        };
+        match result {
+            Ok(n) => {
+                for (i, byte) in data[..n].iter().enumerate() {
+                    bus.write(buffer.wrapping_add(i as Address), *byte);
+                }
+                n as u16
+            }
+            Err(_) => FAILED,
+        }
+    }
+
+    // write(fd, buffer, count)
+    fn write(&mut self, bus: &dyn Bus, count: u16) -> u16 {
+        let buffer = self.pop_param(bus, 2);
+        let fd = self.pop_param(bus, 2);
+        let data: Vec<Data> = (0..count).map(|i| bus.read(buffer.wrapping_add(i))).collect();
+        let result = match self.files.get_mut(&fd) {
+            Some(Handle::Stdout) => io::stdout().write_all(&data),
+            Some(Handle::Stderr) => io::stderr().write_all(&data),
+            Some(Handle::File(file)) => file.write_all(&data),
+            _ => return FAILED,
+        };
+        match result {
+            Ok(()) => count,
+            Err(_) => FAILED,
+        }
+    }
+
+    // Copies argv below the C stack and stores its address at argv, returning argc
+    fn write_args(&mut self, bus: &dyn Bus, argv: u16) -> u16 {
+        let mut sp = Sim65::read_word(bus, self.sp_address);
+        let mut pointers = sp.wrapping_sub((self.args.len() as u16 + 1) * 2);
+        Sim65::write_word(bus, argv, pointers);
+        sp = pointers;
+        for arg in &self.args {
+            sp = sp.wrapping_sub(arg.len() as u16 + 1);
+            for (i, byte) in arg.bytes().chain([0]).enumerate() {
+                bus.write(sp.wrapping_add(i as Address), byte);
+            }
+            Sim65::write_word(bus, pointers, sp);
+            pointers = pointers.wrapping_add(2);
+        }
+        Sim65::write_word(bus, pointers, 0);
+        Sim65::write_word(bus, self.sp_address, sp);
+        self.args.len() as u16
+    }
+}
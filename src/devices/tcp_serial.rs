@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::bus::Data;
+use crate::devices::acia::SerialBackend;
+use crate::logging::DEVICE;
+
+const IAC: Data = 255;
+
+// A serial line on a TCP port, `telnet localhost 6502` to get at the machine's console.
+// One client at a time; a new one can connect after the last hangs up. Bytes sent while
+// nobody is connected are dropped, like a terminal that's switched off
+pub struct TcpSerial {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    received: VecDeque<Data>,
+    // telnet negotiation bytes still to skip and the last byte seen, for line endings
+    skip: usize,
+    last: Data,
+}
+
+impl TcpSerial {
+    pub fn listen(address: impl ToSocketAddrs) -> io::Result<TcpSerial> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(TcpSerial {
+            listener,
+            client: None,
+            received: VecDeque::new(),
+            skip: 0,
+            last: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn accept(&mut self) {
+        if self.client.is_none() {
+            if let Ok((stream, peer)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() && stream.set_nodelay(true).is_ok() {
+                    log::info!(target: DEVICE, "serial client connected from {}", peer);
+                    self.client = Some(stream);
+                }
+            }
+        }
+    }
+
+    fn read_client(&mut self) {
+        let mut buffer = [0; 256];
+        let read = match &mut self.client {
+            Some(client) => client.read(&mut buffer),
+            None => return,
+        };
+        match read {
+            Ok(0) => self.hang_up(),
+            Ok(n) => {
+                for b in &buffer[..n] {
+                    self.filter(*b);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => self.hang_up(),
+        }
+    }
+
+    fn hang_up(&mut self) {
+        log::info!(target: DEVICE, "serial client disconnected");
+        self.client = None;
+    }
+
+    // Drop telnet option negotiation and turn \r\n, \r\0 and bare \n into \r
+    fn filter(&mut self, b: Data) {
+        let last = self.last;
+        self.last = b;
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        match b {
+            IAC => self.skip = 2,
+            b'\n' | 0 if last == b'\r' => {}
+            b'\n' => self.received.push_back(b'\r'),
+            _ => self.received.push_back(b),
+        }
+    }
+}
+
+impl SerialBackend for TcpSerial {
+    fn receive(&mut self) -> Option<Data> {
+        if self.received.is_empty() {
+            self.accept();
+            self.read_client();
+        }
+        self.received.pop_front()
+    }
+
+    fn transmit(&mut self, data: Data) {
+        self.accept();
+        let failed = match &mut self.client {
+            Some(client) => client.write_all(&[data]).is_err(),
+            None => false,
+        };
+        if failed {
+            self.hang_up();
+        }
+    }
+}
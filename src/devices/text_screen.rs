@@ -0,0 +1,170 @@
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+pub const COLUMNS: usize = 40;
+pub const ROWS: usize = 25;
+const DEFAULT_CYCLES_PER_FRAME: usize = 16_667;
+const CURSOR_VISIBLE: Data = 0x01;
+
+// A 40x25 character screen. Screen memory is base..base+999, one ASCII byte per cell, row by
+// row. Three registers sit at `registers`: cursor column, cursor row and control (bit 0 shows
+// the cursor). It holds screen memory itself, so register it before any RAM it overlaps.
+// With the `terminal` feature the screen can be drawn live in the host terminal
+pub struct TextScreen {
+    base: Address,
+    registers: Address,
+    cells: Vec<Data>,
+    cursor_column: Data,
+    cursor_row: Data,
+    control: Data,
+    dirty: bool,
+    cycles_per_frame: usize,
+    cycles: usize,
+    #[cfg(feature = "terminal")]
+    live: bool,
+}
+
+impl TextScreen {
+    pub fn new(base: Address, registers: Address) -> TextScreen {
+        TextScreen {
+            base,
+            registers,
+            cells: vec![b' '; COLUMNS * ROWS],
+            cursor_column: 0,
+            cursor_row: 0,
+            control: CURSOR_VISIBLE,
+            dirty: true,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            cycles: 0,
+            #[cfg(feature = "terminal")]
+            live: false,
+        }
+    }
+
+    // Redraw the host terminal from tick() whenever the screen changes
+    #[cfg(feature = "terminal")]
+    pub fn with_terminal(mut self) -> TextScreen {
+        self.live = true;
+        self
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.cycles_per_frame = cycles.max(1);
+    }
+
+    // The screen as text, anything unprintable shown as a space
+    pub fn lines(&self) -> Vec<String> {
+        self.cells
+            .chunks(COLUMNS)
+            .map(|row| row.iter().map(|c| if (0x20..0x7f).contains(c) { *c as char } else { ' ' }).collect())
+            .collect()
+    }
+
+    // (column, row) while the cursor is shown
+    pub fn get_cursor(&self) -> Option<(usize, usize)> {
+        if self.control & CURSOR_VISIBLE == 0 {
+            return None;
+        }
+        Some(((self.cursor_column as usize).min(COLUMNS - 1), (self.cursor_row as usize).min(ROWS - 1)))
+    }
+
+    #[cfg(feature = "terminal")]
+    pub fn draw(&mut self, out: &mut impl std::io::Write) -> std::io::Result<()> {
+        use crossterm::cursor::{Hide, MoveTo, Show};
+        use crossterm::style::Print;
+        use crossterm::queue;
+
+        for (row, line) in self.lines().iter().enumerate() {
+            queue!(out, MoveTo(0, row as u16), Print(line))?;
+        }
+        match self.get_cursor() {
+            Some((column, row)) => queue!(out, MoveTo(column as u16, row as u16), Show)?,
+            None => queue!(out, Hide)?,
+        }
+        self.dirty = false;
+        out.flush()
+    }
+
+    fn is_screen(&self, address: Address) -> bool {
+        address >= self.base && ((address - self.base) as usize) < COLUMNS * ROWS
+    }
+
+    fn is_register(&self, address: Address) -> bool {
+        address >= self.registers && address - self.registers < 3
+    }
+}
+
+impl Peripheral for TextScreen {
+    fn tick(&mut self, cycles: usize) {
+        self.cycles += cycles;
+        if self.cycles < self.cycles_per_frame {
+            return;
+        }
+        self.cycles %= self.cycles_per_frame;
+        #[cfg(feature = "terminal")]
+        if self.live && self.dirty {
+            let _ = self.draw(&mut std::io::stdout());
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cycles = 0;
+    }
+}
+
+impl BusDevice for TextScreen {
+    fn do_read(&self, address: Address) -> Data {
+        if self.is_screen(address) {
+            return self.cells[(address - self.base) as usize];
+        }
+        match address - self.registers {
+            0 => self.cursor_column,
+            1 => self.cursor_row,
+            _ => self.control,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        if self.is_screen(address) {
+            self.cells[(address - self.base) as usize] = data;
+        } else {
+            match address - self.registers {
+                0 => self.cursor_column = data,
+                1 => self.cursor_row = data,
+                _ => self.control = data,
+            }
+        }
+        self.dirty = true;
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.is_screen(address) || self.is_register(address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_screen(address) || self.is_register(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for TextScreen {
+    fn get_name(&self) -> String {
+        "text".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("screen".to_string(), format!("${:04X}", self.base)),
+            ("registers".to_string(), format!("${:04X}", self.registers)),
+            ("cursor".to_string(), format!("{},{} control ${:02X}", self.cursor_column, self.cursor_row, self.control)),
+            ("dirty".to_string(), self.dirty.to_string()),
+        ]
+    }
+}
@@ -0,0 +1,151 @@
+use std::cell::Cell;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+use crate::logging::DEVICE;
+
+const START: Data = 0x01;
+const FREE_RUN: Data = 0x02;
+const IRQ_ENABLE: Data = 0x04;
+const EXPIRED: Data = 0x80;
+
+// A 16 bit cycle counting timer at base..base+3:
+//   $0/$1 reload value low/high (reads give the current count)
+//   $2 control: bit 0 start (writing it loads the count), bit 1 free run, bit 2 IRQ on expiry
+//   $3 status: bit 7 expired, cleared by reading it
+// One shot timers stop at zero, free running ones reload and keep going
+pub struct Timer {
+    base: Address,
+    reload: u16,
+    count: u16,
+    control: Data,
+    expired: Cell<bool>,
+}
+
+impl Timer {
+    pub fn new(base: Address) -> Timer {
+        Timer {
+            base,
+            reload: 0,
+            count: 0,
+            control: 0,
+            expired: Cell::new(false),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.control & START != 0
+    }
+}
+
+impl Peripheral for Timer {
+    fn tick(&mut self, cycles: usize) {
+        let mut remaining = cycles;
+        while self.is_running() && remaining > 0 {
+            if remaining < self.count as usize {
+                self.count -= remaining as u16;
+                return;
+            }
+            remaining -= self.count as usize;
+            log::trace!(target: DEVICE, "timer at ${:04X} expired", self.base);
+            self.expired.set(true);
+            if self.control & FREE_RUN != 0 && self.reload > 0 {
+                self.count = self.reload;
+            } else {
+                self.count = 0;
+                self.control &= !START;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reload = 0;
+        self.count = 0;
+        self.control = 0;
+        self.expired.set(false);
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.control & IRQ_ENABLE != 0 && self.expired.get()
+    }
+}
+
+impl BusDevice for Timer {
+    fn do_read(&self, address: Address) -> Data {
+        let data = self.peek(address);
+        // reading the status acknowledges the expiry
+        if address - self.base == 3 {
+            self.expired.set(false);
+        }
+        data
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        match address - self.base {
+            0 => (self.count & 0xff) as Data,
+            1 => (self.count >> 8) as Data,
+            2 => self.control,
+            _ if self.expired.get() => EXPIRED,
+            _ => 0,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        match address - self.base {
+            0 => self.reload = (self.reload & 0xff00) | data as u16,
+            1 => self.reload = (self.reload & 0x00ff) | (data as u16) << 8,
+            2 => {
+                if data & START != 0 {
+                    self.count = self.reload;
+                    self.expired.set(false);
+                }
+                self.control = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 4
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 4
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self) -> Option<Vec<Data>> {
+        let [reload_lo, reload_hi] = self.reload.to_le_bytes();
+        let [count_lo, count_hi] = self.count.to_le_bytes();
+        Some(vec![reload_lo, reload_hi, count_lo, count_hi, self.control, self.expired.get() as Data])
+    }
+
+    fn load_state(&mut self, state: &[Data]) {
+        self.reload = u16::from_le_bytes([state[0], state[1]]);
+        self.count = u16::from_le_bytes([state[2], state[3]]);
+        self.control = state[4];
+        self.expired.set(state[5] != 0);
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Timer {
+    fn get_name(&self) -> String {
+        "timer".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("count".to_string(), format!("{} of {}", self.count, self.reload)),
+            ("control".to_string(), format!("${:02X}", self.control)),
+            ("expired".to_string(), self.expired.get().to_string()),
+        ]
+    }
+}
@@ -0,0 +1,324 @@
+use std::cell::Cell;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::{Peripheral, Port};
+
+const CA2_FLAG: Data = 0x01;
+const CA1_FLAG: Data = 0x02;
+const SR_FLAG: Data = 0x04;
+const CB2_FLAG: Data = 0x08;
+const CB1_FLAG: Data = 0x10;
+const T2_FLAG: Data = 0x20;
+const T1_FLAG: Data = 0x40;
+const T1_FREE_RUN: Data = 0x40;
+
+// The 6522 VIA at base..base+$F:
+//   $0 port B, $1 port A, $2 DDRB, $3 DDRA
+//   $4/$5 timer 1 counter (writing $5 loads it from the latch and starts it), $6/$7 timer 1 latch
+//   $8/$9 timer 2 counter (writing $9 starts it), $A shift register (held, not shifted)
+//   $B ACR (bit 6 timer 1 free runs), $C PCR (bit 0 CA1, bit 4 CB1 rising edge; CA2/CB2 inputs
+//   or constant outputs), $D IFR (write 1s to clear), $E IER (bit 7 set or clear), $F port A
+//   without touching the CA flags
+// Reading or writing a port clears its C1/C2 flags, reading the low counter clears its timer
+// flag. IRQ is held while any enabled flag is set
+pub struct Via {
+    base: Address,
+    port_a: Port,
+    port_b: Port,
+    t1_counter: usize,
+    t1_latch: u16,
+    t1_running: bool,
+    t2_counter: usize,
+    t2_latch_low: Data,
+    t2_running: bool,
+    shift: Data,
+    acr: Data,
+    pcr: Data,
+    ier: Data,
+    // reads clear flags, so they live in a cell
+    ifr: Cell<Data>,
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+}
+
+impl Via {
+    pub fn new(base: Address) -> Via {
+        Via {
+            base,
+            port_a: Port::default(),
+            port_b: Port::default(),
+            t1_counter: 0xffff,
+            t1_latch: 0xffff,
+            t1_running: false,
+            t2_counter: 0xffff,
+            t2_latch_low: 0xff,
+            t2_running: false,
+            shift: 0,
+            acr: 0,
+            pcr: 0,
+            ier: 0,
+            ifr: Cell::new(0),
+            ca1: false,
+            ca2: false,
+            cb1: false,
+            cb2: false,
+        }
+    }
+
+    // Drive the port pins from outside. Pins set as outputs ignore it
+    pub fn set_port_a_input(&mut self, input: Data) {
+        self.port_a.input = input;
+    }
+
+    pub fn set_port_b_input(&mut self, input: Data) {
+        self.port_b.input = input;
+    }
+
+    // What the chip drives onto the pins; input pins read as 1, as if pulled up
+    pub fn get_port_a_output(&self) -> Data {
+        self.port_a.output | !self.port_a.direction
+    }
+
+    pub fn get_port_b_output(&self) -> Data {
+        self.port_b.output | !self.port_b.direction
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        if level != self.ca1 && level == (self.pcr & 0x01 != 0) {
+            self.set_flag(CA1_FLAG);
+        }
+        self.ca1 = level;
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        if level != self.cb1 && level == (self.pcr & 0x10 != 0) {
+            self.set_flag(CB1_FLAG);
+        }
+        self.cb1 = level;
+    }
+
+    // CA2/CB2 as inputs, PCR bits 3-1 or 7-5 of 0xx; bit 2 of those picks the rising edge
+    pub fn set_ca2(&mut self, level: bool) {
+        let control = self.pcr >> 1 & 0x07;
+        if control & 0x04 == 0 && level != self.ca2 && level == (control & 0x02 != 0) {
+            self.set_flag(CA2_FLAG);
+        }
+        self.ca2 = level;
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        let control = self.pcr >> 5 & 0x07;
+        if control & 0x04 == 0 && level != self.cb2 && level == (control & 0x02 != 0) {
+            self.set_flag(CB2_FLAG);
+        }
+        self.cb2 = level;
+    }
+
+    // CA2/CB2 programmed as constant outputs (110 low, 111 high), high otherwise
+    pub fn get_ca2(&self) -> bool {
+        self.pcr & 0x0e != 0x0c
+    }
+
+    pub fn get_cb2(&self) -> bool {
+        self.pcr & 0xe0 != 0xc0
+    }
+
+    fn set_flag(&self, flag: Data) {
+        self.ifr.set(self.ifr.get() | flag);
+    }
+
+    fn clear_flag(&self, flag: Data) {
+        self.ifr.set(self.ifr.get() & !flag);
+    }
+
+    // The independent interrupt input modes leave the C2 flag alone on port access
+    fn port_flags(&self, c1: Data, c2: Data, control: Data) -> Data {
+        if control & 0x0a == 0x02 {
+            c1
+        } else {
+            c1 | c2
+        }
+    }
+
+    fn get_ifr(&self) -> Data {
+        let flags = self.ifr.get() & 0x7f;
+        if flags & self.ier != 0 {
+            flags | 0x80
+        } else {
+            flags
+        }
+    }
+}
+
+impl Peripheral for Via {
+    fn tick(&mut self, cycles: usize) {
+        if self.t1_running {
+            if cycles <= self.t1_counter {
+                self.t1_counter -= cycles;
+            } else {
+                self.set_flag(T1_FLAG);
+                if self.acr & T1_FREE_RUN != 0 {
+                    // the count passes through -1 before the reload, so a period is latch + 2
+                    let period = self.t1_latch as usize + 2;
+                    let over = (cycles - self.t1_counter - 1) % period;
+                    self.t1_counter = self.t1_latch as usize + 1 - over;
+                } else {
+                    self.t1_running = false;
+                    self.t1_counter = 0xffff;
+                }
+            }
+        }
+        if self.t2_running {
+            if cycles <= self.t2_counter {
+                self.t2_counter -= cycles;
+            } else {
+                self.set_flag(T2_FLAG);
+                self.t2_running = false;
+                self.t2_counter = 0xffff;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        // the registers clear, the timers and shift register don't
+        let (input_a, input_b) = (self.port_a.input, self.port_b.input);
+        self.port_a = Port { input: input_a, ..Port::default() };
+        self.port_b = Port { input: input_b, ..Port::default() };
+        self.acr = 0;
+        self.pcr = 0;
+        self.ier = 0;
+        self.ifr.set(0);
+        self.t1_running = false;
+        self.t2_running = false;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.ifr.get() & self.ier & 0x7f != 0
+    }
+}
+
+impl BusDevice for Via {
+    fn do_read(&self, address: Address) -> Data {
+        let data = self.peek(address);
+        // reading a port or the low byte of a timer or the shift register acknowledges its interrupt
+        match (address - self.base) & 0x0f {
+            0x0 => self.clear_flag(self.port_flags(CB1_FLAG, CB2_FLAG, self.pcr >> 4)),
+            0x1 => self.clear_flag(self.port_flags(CA1_FLAG, CA2_FLAG, self.pcr)),
+            0x4 => self.clear_flag(T1_FLAG),
+            0x8 => self.clear_flag(T2_FLAG),
+            0xa => self.clear_flag(SR_FLAG),
+            _ => {}
+        }
+        data
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        match (address - self.base) & 0x0f {
+            0x0 => self.port_b.read(),
+            0x1 => self.port_a.read(),
+            0x2 => self.port_b.direction,
+            0x3 => self.port_a.direction,
+            0x4 => self.t1_counter as Data,
+            0x5 => (self.t1_counter >> 8) as Data,
+            0x6 => self.t1_latch as Data,
+            0x7 => (self.t1_latch >> 8) as Data,
+            0x8 => self.t2_counter as Data,
+            0x9 => (self.t2_counter >> 8) as Data,
+            0xa => self.shift,
+            0xb => self.acr,
+            0xc => self.pcr,
+            0xd => self.get_ifr(),
+            0xe => self.ier | 0x80,
+            _ => self.port_a.read(),
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        match (address - self.base) & 0x0f {
+            0x0 => {
+                self.clear_flag(self.port_flags(CB1_FLAG, CB2_FLAG, self.pcr >> 4));
+                self.port_b.output = data;
+            }
+            0x1 => {
+                self.clear_flag(self.port_flags(CA1_FLAG, CA2_FLAG, self.pcr));
+                self.port_a.output = data;
+            }
+            0x2 => self.port_b.direction = data,
+            0x3 => self.port_a.direction = data,
+            0x4 | 0x6 => self.t1_latch = (self.t1_latch & 0xff00) | data as u16,
+            0x5 => {
+                self.t1_latch = (self.t1_latch & 0x00ff) | (data as u16) << 8;
+                self.t1_counter = self.t1_latch as usize;
+                self.t1_running = true;
+                self.clear_flag(T1_FLAG);
+            }
+            0x7 => {
+                self.t1_latch = (self.t1_latch & 0x00ff) | (data as u16) << 8;
+                self.clear_flag(T1_FLAG);
+            }
+            0x8 => self.t2_latch_low = data,
+            0x9 => {
+                self.t2_counter = (data as usize) << 8 | self.t2_latch_low as usize;
+                self.t2_running = true;
+                self.clear_flag(T2_FLAG);
+            }
+            0xa => {
+                self.clear_flag(SR_FLAG);
+                self.shift = data;
+            }
+            0xb => self.acr = data,
+            0xc => self.pcr = data,
+            0xd => self.clear_flag(data & 0x7f),
+            0xe => {
+                if data & 0x80 != 0 {
+                    self.ier |= data & 0x7f;
+                } else {
+                    self.ier &= !data;
+                }
+            }
+            _ => self.port_a.output = data,
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 0x10
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Via {
+    fn get_name(&self) -> String {
+        "via".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("port a".to_string(), format!("${:02X} ddr ${:02X}", self.port_a.read(), self.port_a.direction)),
+            ("port b".to_string(), format!("${:02X} ddr ${:02X}", self.port_b.read(), self.port_b.direction)),
+            (
+                "timer 1".to_string(),
+                format!("${:04X} latch ${:04X} {}", self.t1_counter, self.t1_latch, if self.t1_running { "running" } else { "stopped" }),
+            ),
+            (
+                "timer 2".to_string(),
+                format!("${:04X} {}", self.t2_counter, if self.t2_running { "running" } else { "stopped" }),
+            ),
+            ("acr pcr".to_string(), format!("${:02X} ${:02X}", self.acr, self.pcr)),
+            ("ifr ier".to_string(), format!("${:02X} ${:02X}", self.get_ifr(), self.ier)),
+        ]
+    }
+}
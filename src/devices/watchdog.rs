@@ -0,0 +1,143 @@
+use std::cell::Cell;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::Peripheral;
+
+pub const PET: Data = 0x5a;
+const ENABLE: Data = 0x01;
+const USE_NMI: Data = 0x02;
+const FIRED: Data = 0x80;
+
+// A watchdog at base..base+2 that must be petted at least every timeout cycles once enabled:
+//   $0 writing $5A restarts the count (anything else is ignored, so stray writes don't count)
+//   $1 control: bit 0 enable, bit 1 fire NMI rather than reset
+//   $2 status: bit 7 the watchdog has fired, cleared by reading it so firmware can tell why it restarted
+// When it runs out it holds its reset or NMI line until acknowledge() is called by whatever
+// acted on it, and then starts counting again. Nothing acts on them by itself: System doesn't
+// deliver either line, so a machine that wants the restart polls reset_asserted and calls
+// System::reset, which resets the watchdog along with everything else
+pub struct Watchdog {
+    base: Address,
+    timeout: usize,
+    remaining: usize,
+    control: Data,
+    fired: Cell<bool>,
+    asserted: bool,
+}
+
+impl Watchdog {
+    pub fn new(base: Address, timeout: usize) -> Watchdog {
+        Watchdog {
+            base,
+            timeout: timeout.max(1),
+            remaining: timeout.max(1),
+            control: 0,
+            fired: Cell::new(false),
+            asserted: false,
+        }
+    }
+
+    pub fn get_remaining(&self) -> usize {
+        self.remaining
+    }
+
+    pub fn reset_asserted(&self) -> bool {
+        self.asserted && self.control & USE_NMI == 0
+    }
+
+    pub fn nmi_asserted(&self) -> bool {
+        self.asserted && self.control & USE_NMI != 0
+    }
+
+    // The reset or NMI has been taken
+    pub fn acknowledge(&mut self) {
+        self.asserted = false;
+        self.remaining = self.timeout;
+    }
+}
+
+impl Peripheral for Watchdog {
+    fn tick(&mut self, cycles: usize) {
+        if self.control & ENABLE == 0 || self.asserted {
+            return;
+        }
+        if cycles < self.remaining {
+            self.remaining -= cycles;
+        } else {
+            self.remaining = 0;
+            self.fired.set(true);
+            self.asserted = true;
+        }
+    }
+
+    fn reset(&mut self) {
+        // disabled again, but the fired flag survives so firmware can see why it restarted
+        self.control = 0;
+        self.asserted = false;
+        self.remaining = self.timeout;
+    }
+}
+
+impl BusDevice for Watchdog {
+    fn do_read(&self, address: Address) -> Data {
+        let data = self.peek(address);
+        if address - self.base == 2 {
+            self.fired.set(false);
+        }
+        data
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        match address - self.base {
+            0 => 0,
+            1 => self.control,
+            _ if self.fired.get() => FIRED,
+            _ => 0,
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        match address - self.base {
+            0 if data == PET => self.remaining = self.timeout,
+            1 => {
+                if self.control & ENABLE == 0 && data & ENABLE != 0 {
+                    self.remaining = self.timeout;
+                }
+                self.control = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address >= self.base && address - self.base < 3
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Watchdog {
+    fn get_name(&self) -> String {
+        "watchdog".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("base".to_string(), format!("${:04X}", self.base)),
+            ("enabled".to_string(), (self.control & ENABLE != 0).to_string()),
+            ("action".to_string(), if self.control & USE_NMI != 0 { "NMI" } else { "reset" }.to_string()),
+            ("remaining".to_string(), format!("{} of {} cycles", self.remaining, self.timeout)),
+            ("asserted".to_string(), self.asserted.to_string()),
+        ]
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+use crate::bus::{Address, Bus, Data};
+use crate::processor::AddressingMode::*;
+use crate::processor::{create_instruction_table, InstructionTable};
+
+// Data lines take up to this many bytes
+const BYTES_PER_DATA_LINE: usize = 8;
+// A run of this many $00 or $FF bytes is taken for padding rather than code
+const MIN_FILL_RUN: usize = 4;
+
+// One decoded instruction, or a run of data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisasmLine {
+    pub address: Address,
+    pub bytes: Vec<Data>,
+    // "???" for an opcode missing from the instruction table, ".byte" for data
+    pub mnemonic: String,
+    pub operand: String,
+}
+
+impl DisasmLine {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.mnemonic == ".byte"
+    }
+
+    fn data(address: Address, bytes: Vec<Data>) -> DisasmLine {
+        let operand = bytes.iter().map(|b| format!("${:02X}", b)).collect::<Vec<_>>().join(",");
+        DisasmLine {
+            address,
+            bytes,
+            mnemonic: ".byte".to_string(),
+            operand,
+        }
+    }
+}
+
+// "C000  A9 00     LDA #$00"
+impl fmt::Display for DisasmLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().take(3).map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:04X}  {:<9} {} {}", self.address, bytes.join(" "), self.mnemonic, self.operand)
+    }
+}
+
+// Decodes memory or byte slices with the processor's instruction table, for the debugger,
+// the trace log and the CLI. With data heuristics on, a range is split into code and data:
+// unknown opcodes, runs of $00/$FF padding and instructions running off the end of the range
+// come out as .byte lines
+pub struct Disassembler {
+    instructions: &'static InstructionTable,
+    data_heuristics: bool,
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Disassembler::new()
+    }
+}
+
+impl Disassembler {
+    pub fn new() -> Disassembler {
+        Disassembler {
+            instructions: create_instruction_table(),
+            data_heuristics: false,
+        }
+    }
+
+    pub fn with_data_heuristics(mut self, on: bool) -> Disassembler {
+        self.data_heuristics = on;
+        self
+    }
+
+    pub fn get_mnemonic(&self, opcode: Data) -> Option<&str> {
+        self.instructions.get(opcode).map(|i| i.get_mnemonic())
+    }
+
+    // The instruction at address, whatever the heuristics say
+    pub fn decode(&self, bus: &dyn Bus, address: Address) -> DisasmLine {
+        self.decode_from(&|a| bus.peek(a), address)
+    }
+
+    // Every line from start to end inclusive
+    pub fn disassemble(&self, bus: &dyn Bus, start: Address, end: Address) -> Vec<DisasmLine> {
+        self.disassemble_from(&|a| bus.peek(a), start, end)
+    }
+
+    // Bytes as if loaded at org
+    pub fn disassemble_bytes(&self, bytes: &[Data], org: Address) -> Vec<DisasmLine> {
+        if bytes.is_empty() {
+            return vec![];
+        }
+        // past the end reads as an open bus
+        let read = |a: Address| bytes.get(a.wrapping_sub(org) as usize).copied().unwrap_or(0xff);
+        self.disassemble_from(&read, org, org.wrapping_add(bytes.len() as Address - 1))
+    }
+
+    fn decode_from(&self, read: &dyn Fn(Address) -> Data, address: Address) -> DisasmLine {
+        let opcode = read(address);
+        let instruction = match self.instructions.get(opcode) {
+            Some(i) => i,
+            None => {
+                return DisasmLine {
+                    address,
+                    bytes: vec![opcode],
+                    mnemonic: "???".to_string(),
+                    operand: String::new(),
+                }
+            }
+        };
+        let length = instruction.get_addressing().operand_length();
+        let lo = read(address.wrapping_add(1));
+        let hi = read(address.wrapping_add(2));
+        let word = (hi as Address) << 8 | lo as Address;
+        let operand = match instruction.get_addressing() {
+            Implied => String::new(),
+            Accumulator => "A".to_string(),
+            Immediate => format!("#${:02X}", lo),
+            ZeroPage => format!("${:02X}", lo),
+            ZeroPageIndexed { reg } => format!("${:02X},{:?}", lo, reg),
+            Absolute => format!("${:04X}", word),
+            AbsIndexed { reg } => format!("${:04X},{:?}", word, reg),
+            Indirect => format!("(${:04X})", word),
+            IndexedIndirect => format!("(${:02X},X)", lo),
+            IndirectIndexed => format!("(${:02X}),Y", lo),
+            Relative => format!("${:04X}", address.wrapping_add(2).wrapping_add(lo as i8 as Address)),
+        };
+        DisasmLine {
+            address,
+            bytes: (0..=length as Address).map(|i| read(address.wrapping_add(i))).collect(),
+            mnemonic: instruction.get_mnemonic().to_string(),
+            operand,
+        }
+    }
+
+    fn disassemble_from(&self, read: &dyn Fn(Address) -> Data, start: Address, end: Address) -> Vec<DisasmLine> {
+        let mut lines = vec![];
+        let mut address = start as u32;
+        while address <= end as u32 {
+            let line = self.next_line(read, address as Address, end as u32 - address + 1);
+            address += line.len() as u32;
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn next_line(&self, read: &dyn Fn(Address) -> Data, address: Address, left: u32) -> DisasmLine {
+        let line = self.decode_from(read, address);
+        if !self.data_heuristics {
+            return line;
+        }
+        let fill = read(address);
+        let run = (0..left.min(BYTES_PER_DATA_LINE as u32))
+            .take_while(|i| read(address.wrapping_add(*i as Address)) == fill)
+            .count();
+        let padding = (fill == 0x00 || fill == 0xff) && run >= MIN_FILL_RUN.min(left as usize);
+        if padding && run > 1 {
+            return DisasmLine::data(address, vec![fill; run]);
+        }
+        if line.mnemonic == "???" || line.len() as u32 > left {
+            return DisasmLine::data(address, vec![fill]);
+        }
+        line
+    }
+}
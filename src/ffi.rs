@@ -0,0 +1,226 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::Cell;
+use std::ffi::{c_char, c_int, c_long, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::bus::{Address, Data};
+use crate::system::System;
+
+// A C API for embedding the emulator, built into the cdylib (and rlib) with the ffi feature.
+// include/sim6502.h declares it; cbindgen.toml regenerates that from here. A machine is a
+// System with 64K of RAM behind an opaque pointer, made by sim6502_create and freed by
+// sim6502_destroy:
+//   Sim6502 *machine = sim6502_create();
+//   sim6502_load(machine, 0x0200, program, sizeof program);
+//   sim6502_set_reset_vector(machine, 0x0200);
+//   sim6502_run(machine, 100000);
+//   sim6502_destroy(machine);
+// Every machine pointer has to be one from sim6502_create that hasn't been destroyed, and only
+// used from one thread at a time; other pointers have to be valid for the length given. NULL
+// machines are ignored, returning 0 or an error. Panics don't cross into C: every function
+// catches them, and one that panics, as the processor does on an opcode it doesn't know,
+// faults the machine, which then stops until a reset
+pub struct Sim6502 {
+    system: System,
+    faulted: Cell<bool>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sim6502Registers {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+}
+
+// Runs f, returning default rather than unwinding into C if it panics
+fn catch<T>(default: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+impl Sim6502 {
+    // Runs f on the System, faulting the machine if it panics
+    fn guarded<T>(&self, default: T, f: impl FnOnce(&System) -> T) -> T {
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&self.system))) {
+            Ok(value) => value,
+            Err(_) => {
+                self.faulted.set(true);
+                default
+            }
+        }
+    }
+
+    fn guarded_mut<T>(&mut self, default: T, f: impl FnOnce(&mut System) -> T) -> T {
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&mut self.system))) {
+            Ok(value) => value,
+            Err(_) => {
+                self.faulted.set(true);
+                default
+            }
+        }
+    }
+
+    // Runs the machine, unless it's faulted
+    fn run_guarded(&mut self, run: impl FnOnce(&mut System) -> usize) -> usize {
+        if self.faulted.get() {
+            return 0;
+        }
+        self.guarded_mut(0, run)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sim6502_create() -> *mut Sim6502 {
+    catch(ptr::null_mut(), || {
+        Box::into_raw(Box::new(Sim6502 {
+            system: System::new(),
+            faulted: Cell::new(false),
+        }))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_destroy(machine: *mut Sim6502) {
+    if !machine.is_null() {
+        catch((), || drop(Box::from_raw(machine)));
+    }
+}
+
+// Copies len bytes into RAM at address. 0, or -1 if they'd run past $FFFF
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_load(machine: *mut Sim6502, address: u16, data: *const u8, len: usize) -> c_int {
+    let Some(machine) = machine.as_mut() else {
+        return -1;
+    };
+    if address as usize + len > 0x10000 || (data.is_null() && len > 0) {
+        return -1;
+    }
+    let data = if len == 0 { &[][..] } else { slice::from_raw_parts(data, len) };
+    machine.guarded_mut(-1, |system| {
+        system.load(address, data);
+        0
+    })
+}
+
+// Copies a file into RAM at address, returning its length, or -1 if it can't be read or
+// doesn't fit
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_load_file(machine: *mut Sim6502, address: u16, path: *const c_char) -> c_long {
+    let Some(machine) = machine.as_mut() else {
+        return -1;
+    };
+    if path.is_null() {
+        return -1;
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return -1;
+    };
+    machine.guarded_mut(-1, |system| match system.load_file(address, path) {
+        Ok(len) => len as c_long,
+        Err(_) => -1,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_set_reset_vector(machine: *mut Sim6502, address: u16) {
+    if let Some(machine) = machine.as_mut() {
+        machine.guarded_mut((), |system| system.set_reset_vector(address));
+    }
+}
+
+// Also clears a fault
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_reset(machine: *mut Sim6502) {
+    if let Some(machine) = machine.as_mut() {
+        machine.faulted.set(false);
+        machine.guarded_mut((), System::reset);
+    }
+}
+
+// One instruction, returning its cycles; the first is the boot sequence. 0 once halted or
+// faulted
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_step(machine: *mut Sim6502) -> usize {
+    machine.as_mut().map_or(0, |machine| machine.run_guarded(System::step))
+}
+
+// Whole instructions until at least this many cycles have gone by, the program breaks or the
+// machine faults, returning the cycles run
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_run(machine: *mut Sim6502, cycles: usize) -> usize {
+    machine.as_mut().map_or(0, |machine| machine.run_guarded(|system| system.run(cycles)))
+}
+
+// What's at address, without a read's side effects on an I/O register
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_peek(machine: *const Sim6502, address: u16) -> u8 {
+    machine.as_ref().map_or(0, |machine| machine.guarded(0, |system| system.peek(address as Address)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_poke(machine: *mut Sim6502, address: u16, value: u8) {
+    if let Some(machine) = machine.as_mut() {
+        machine.guarded_mut((), |system| system.write(address as Address, value as Data));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_get_registers(machine: *const Sim6502, registers: *mut Sim6502Registers) {
+    let (Some(machine), Some(registers)) = (machine.as_ref(), registers.as_mut()) else {
+        return;
+    };
+    let Some(r) = machine.guarded(None, |system| Some(system.get_registers())) else {
+        return;
+    };
+    *registers = Sim6502Registers {
+        pc: r.pc,
+        a: r.a,
+        x: r.x,
+        y: r.y,
+        status: r.status,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_set_registers(machine: *mut Sim6502, registers: *const Sim6502Registers) {
+    let (Some(machine), Some(registers)) = (machine.as_mut(), registers.as_ref()) else {
+        return;
+    };
+    machine.guarded_mut((), |system| {
+        let mut r = system.get_registers();
+        r.pc = registers.pc;
+        r.a = registers.a;
+        r.x = registers.x;
+        r.y = registers.y;
+        r.status = registers.status;
+        system.set_registers(&r);
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_get_total_cycles(machine: *const Sim6502) -> usize {
+    machine.as_ref().map_or(0, |machine| machine.guarded(0, System::get_total_cycles))
+}
+
+// True once the program has hit BRK, until the next reset
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_is_halted(machine: *const Sim6502) -> bool {
+    machine.as_ref().is_some_and(|machine| machine.guarded(false, System::is_halted))
+}
+
+// True once the machine has panicked, until the next reset
+#[no_mangle]
+pub unsafe extern "C" fn sim6502_is_faulted(machine: *const Sim6502) -> bool {
+    machine.as_ref().is_some_and(|machine| machine.faulted.get())
+}
+
+// The version of the crate, a static NUL terminated string
+#[no_mangle]
+pub extern "C" fn sim6502_version() -> *const c_char {
+    catch(ptr::null(), || concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char)
+}
@@ -0,0 +1,308 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, MouseButton, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::{Window, WindowId};
+
+use crate::bus::{Bus, Data};
+use crate::devices::framebuffer::{self, Framebuffer};
+use crate::devices::Peripherals;
+use crate::processor::ProcessorTrait;
+
+const DEFAULT_CYCLES_PER_FRAME: usize = 16_667; // 60 frames a second at 1MHz
+const DEFAULT_SCALE: u32 = 12;
+
+// (x, y, left button down) in emulated pixels
+type MouseHandler = Box<dyn FnMut(usize, usize, bool)>;
+
+// A device the window can show, 0xRRGGBB pixels row by row
+pub trait FrameSource {
+    fn get_size(&self) -> (usize, usize);
+    fn to_rgb(&self) -> Vec<u32>;
+}
+
+impl FrameSource for Framebuffer {
+    fn get_size(&self) -> (usize, usize) {
+        (framebuffer::WIDTH, framebuffer::HEIGHT)
+    }
+
+    fn to_rgb(&self) -> Vec<u32> {
+        Framebuffer::to_rgb(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum GuiError {
+    EventLoop(String),
+    Window(String),
+    Pixels(String),
+}
+
+impl fmt::Display for GuiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GuiError::EventLoop(e) => write!(f, "event loop failed: {}", e),
+            GuiError::Window(e) => write!(f, "could not open a window: {}", e),
+            GuiError::Pixels(e) => write!(f, "could not draw: {}", e),
+        }
+    }
+}
+
+pub struct GuiOptions {
+    pub title: String,
+    // window pixels per emulated pixel
+    pub scale: u32,
+    pub cycles_per_frame: usize,
+    pub frame_time: Duration,
+}
+
+impl Default for GuiOptions {
+    fn default() -> Self {
+        GuiOptions {
+            title: "6502".to_string(),
+            scale: DEFAULT_SCALE,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            frame_time: Duration::from_micros(16_667),
+        }
+    }
+}
+
+// A window onto a machine: every frame_time it runs cycles_per_frame processor cycles
+// (stopping at BRK) and shows the frame source. Typed keys go to the on_key handlers as
+// ASCII and the mouse to on_mouse as (x, y, button down) in emulated pixels
+pub struct Gui {
+    processor: Rc<RefCell<dyn ProcessorTrait>>,
+    bus: Rc<RefCell<dyn Bus>>,
+    display: Rc<RefCell<dyn FrameSource>>,
+    options: GuiOptions,
+    on_key: Vec<Box<dyn FnMut(Data)>>,
+    on_mouse: Vec<MouseHandler>,
+    // ticked after every instruction
+    peripherals: Peripherals,
+    // after each frame's cycles, with the number run
+    on_frame: Vec<Box<dyn FnMut(usize)>>,
+    halted: bool,
+}
+
+impl Gui {
+    pub fn new(
+        processor: &Rc<RefCell<dyn ProcessorTrait>>,
+        bus: &Rc<RefCell<dyn Bus>>,
+        display: Rc<RefCell<dyn FrameSource>>,
+    ) -> Gui {
+        Gui {
+            processor: Rc::clone(processor),
+            bus: Rc::clone(bus),
+            display,
+            options: GuiOptions::default(),
+            on_key: vec![],
+            on_mouse: vec![],
+            peripherals: Peripherals::new(),
+            on_frame: vec![],
+            halted: false,
+        }
+    }
+
+    pub fn with_options(mut self, options: GuiOptions) -> Gui {
+        self.options = options;
+        self
+    }
+
+    pub fn with_peripherals(mut self, peripherals: Peripherals) -> Gui {
+        self.peripherals = peripherals;
+        self
+    }
+
+    pub fn on_key(mut self, handler: impl FnMut(Data) + 'static) -> Gui {
+        self.on_key.push(Box::new(handler));
+        self
+    }
+
+    pub fn on_mouse(mut self, handler: impl FnMut(usize, usize, bool) + 'static) -> Gui {
+        self.on_mouse.push(Box::new(handler));
+        self
+    }
+
+    pub fn on_frame(mut self, handler: impl FnMut(usize) + 'static) -> Gui {
+        self.on_frame.push(Box::new(handler));
+        self
+    }
+
+    // Opens the window and runs until it is closed
+    pub fn run(self) -> Result<(), GuiError> {
+        let event_loop = EventLoop::new().map_err(|e| GuiError::EventLoop(e.to_string()))?;
+        let mut app = App {
+            gui: self,
+            window: None,
+            pixels: None,
+            cursor: None,
+            button_down: false,
+            next_frame: Instant::now(),
+            error: None,
+        };
+        event_loop.run_app(&mut app).map_err(|e| GuiError::EventLoop(e.to_string()))?;
+        match app.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Run one frame's worth of cycles, returning how many ran. Nothing runs after a BRK
+    pub fn run_frame(&mut self) -> usize {
+        let mut cycles = 0;
+        let mut instruction_cycles = 0;
+        while !self.halted && cycles < self.options.cycles_per_frame {
+            cycles += 1;
+            instruction_cycles += 1;
+            self.halted = self.processor.borrow_mut().tick(&*self.bus.borrow()).1;
+            if self.processor.borrow().is_at_instruction_boundary() {
+                self.peripherals.tick(instruction_cycles);
+                instruction_cycles = 0;
+            }
+        }
+        self.peripherals.tick(instruction_cycles);
+        for handler in self.on_frame.iter_mut() {
+            handler(cycles);
+        }
+        cycles
+    }
+
+    fn key(&mut self, key: Data) {
+        for handler in self.on_key.iter_mut() {
+            handler(key);
+        }
+    }
+
+    fn mouse(&mut self, x: usize, y: usize, down: bool) {
+        for handler in self.on_mouse.iter_mut() {
+            handler(x, y, down);
+        }
+    }
+}
+
+struct App {
+    gui: Gui,
+    window: Option<Arc<Window>>,
+    pixels: Option<Pixels<'static>>,
+    cursor: Option<(usize, usize)>,
+    button_down: bool,
+    next_frame: Instant,
+    error: Option<GuiError>,
+}
+
+impl App {
+    fn fail(&mut self, event_loop: &ActiveEventLoop, error: GuiError) {
+        self.error = Some(error);
+        event_loop.exit();
+    }
+
+    fn draw(&mut self) -> Result<(), GuiError> {
+        let pixels = match &mut self.pixels {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let rgb = self.gui.display.borrow().to_rgb();
+        for (out, pixel) in pixels.frame_mut().chunks_exact_mut(4).zip(rgb) {
+            out.copy_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, 0xff]);
+        }
+        pixels.render().map_err(|e| GuiError::Pixels(e.to_string()))
+    }
+}
+
+// Typed characters and the few named keys a 6502 program expects, as ASCII
+fn to_ascii(key: &Key) -> Option<Data> {
+    match key {
+        Key::Character(s) => s.chars().next().filter(|c| c.is_ascii()).map(|c| c as Data),
+        Key::Named(NamedKey::Enter) => Some(0x0d),
+        Key::Named(NamedKey::Backspace) => Some(0x08),
+        Key::Named(NamedKey::Tab) => Some(0x09),
+        Key::Named(NamedKey::Escape) => Some(0x1b),
+        Key::Named(NamedKey::Space) => Some(0x20),
+        Key::Named(NamedKey::Delete) => Some(0x7f),
+        _ => None,
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        let (width, height) = self.gui.display.borrow().get_size();
+        let scale = self.gui.options.scale.max(1);
+        let attributes = Window::default_attributes()
+            .with_title(self.gui.options.title.clone())
+            .with_inner_size(LogicalSize::new(width as u32 * scale, height as u32 * scale));
+        let window = match event_loop.create_window(attributes) {
+            Ok(w) => Arc::new(w),
+            Err(e) => return self.fail(event_loop, GuiError::Window(e.to_string())),
+        };
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
+        match Pixels::new(width as u32, height as u32, surface) {
+            Ok(p) => self.pixels = Some(p),
+            Err(e) => return self.fail(event_loop, GuiError::Pixels(e.to_string())),
+        }
+        self.window = Some(window);
+        self.next_frame = Instant::now();
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(size) => {
+                if let Some(pixels) = &mut self.pixels {
+                    if let Err(e) = pixels.resize_surface(size.width, size.height) {
+                        self.fail(event_loop, GuiError::Pixels(e.to_string()));
+                    }
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                if let Err(e) = self.draw() {
+                    self.fail(event_loop, e);
+                }
+            }
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                if let Some(key) = to_ascii(&event.logical_key) {
+                    self.gui.key(key);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = self
+                    .pixels
+                    .as_ref()
+                    .and_then(|p| p.window_pos_to_pixel((position.x as f32, position.y as f32)).ok());
+                if let Some((x, y)) = self.cursor {
+                    self.gui.mouse(x, y, self.button_down);
+                }
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.button_down = state == ElementState::Pressed;
+                if let Some((x, y)) = self.cursor {
+                    self.gui.mouse(x, y, self.button_down);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let now = Instant::now();
+        if now >= self.next_frame {
+            self.gui.run_frame();
+            if let Some(window) = &self.window {
+                window.request_redraw();
+            }
+            self.next_frame = (self.next_frame + self.gui.options.frame_time).max(now);
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+    }
+}
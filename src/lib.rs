@@ -1,6 +1,43 @@
 #![feature(bigint_helper_methods)]
+// Without std only the processor, bus and memory are built, on core and alloc
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 pub mod bus;
 pub mod memory;
 pub mod processor;
-mod debugger;
\ No newline at end of file
+pub mod logging;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod devices;
+#[cfg(feature = "std")]
+pub mod monitor;
+#[cfg(feature = "std")]
+pub mod system;
+#[cfg(any(feature = "debugger", feature = "testsuite"))]
+mod json;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "asm")]
+pub mod asm;
+#[cfg(feature = "loader")]
+pub mod loader;
+#[cfg(feature = "devices")]
+pub mod machines;
+#[cfg(feature = "testsuite")]
+pub mod testsuite;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "async")]
+pub mod channels;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "plugins")]
+pub mod plugin;
@@ -0,0 +1,326 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::bus::Data;
+use crate::devices::framebuffer::{HEIGHT, WIDTH};
+use crate::machines::{easy6502, Easy6502};
+
+// The easy6502 machine as a libretro core, built into the cdylib with the libretro feature, for
+// RetroArch and other front ends: `retroarch -L librust_6502_emulator.so snake.bin`. Content is
+// a binary loaded at $0600. The joypad's d-pad is WASD and start is return, the keys easy6502
+// programs read from $FF; the screen is 32x32 at 60 frames a second of 1MHz. There's no sound,
+// so frames are paced by silence, and no save states. A processor that panics, on an opcode it
+// doesn't know, stops the machine on its last frame until a reset rather than unwinding into
+// the front end
+
+pub const API_VERSION: c_uint = 1;
+
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+pub const DEVICE_JOYPAD: c_uint = 1;
+pub const JOYPAD_START: c_uint = 3;
+pub const JOYPAD_UP: c_uint = 4;
+pub const JOYPAD_DOWN: c_uint = 5;
+pub const JOYPAD_LEFT: c_uint = 6;
+pub const JOYPAD_RIGHT: c_uint = 7;
+pub const JOYPAD_A: c_uint = 8;
+const REGION_NTSC: c_uint = 0;
+
+const CYCLES_PER_FRAME: usize = 16_667;
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44_100.0;
+const SAMPLES_PER_FRAME: usize = 735;
+
+// joypad button to the key it presses
+const KEYS: [(c_uint, Data); 6] = [
+    (JOYPAD_UP, b'w'),
+    (JOYPAD_DOWN, b's'),
+    (JOYPAD_LEFT, b'a'),
+    (JOYPAD_RIGHT, b'd'),
+    (JOYPAD_START, b'\r'),
+    (JOYPAD_A, b' '),
+];
+
+pub type EnvironmentFn = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type VideoRefreshFn = extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+pub type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type InputPollFn = extern "C" fn();
+pub type InputStateFn = extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    pub geometry: GameGeometry,
+    pub timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[derive(Default)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    machine: Easy6502,
+    // the buttons held last frame, so holding one presses its key once
+    held: [bool; KEYS.len()],
+    frame: Vec<u32>,
+    faulted: bool,
+}
+
+// Front ends call a core from one thread, and the System isn't Send anyway
+thread_local! {
+    static CALLBACKS: RefCell<Callbacks> = RefCell::new(Callbacks::default());
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with(|core| core.borrow_mut().take());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    if let Some(info) = info.as_mut() {
+        *info = SystemInfo {
+            library_name: c"sim6502".as_ptr(),
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: c"bin|prg".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        };
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    if let Some(info) = info.as_mut() {
+        *info = SystemAvInfo {
+            geometry: GameGeometry {
+                base_width: WIDTH as c_uint,
+                base_height: HEIGHT as c_uint,
+                max_width: WIDTH as c_uint,
+                max_height: HEIGHT as c_uint,
+                aspect_ratio: 1.0,
+            },
+            timing: SystemTiming {
+                fps: FPS,
+                sample_rate: SAMPLE_RATE,
+            },
+        };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.with(|c| c.borrow_mut().environment = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.with(|c| c.borrow_mut().video_refresh = Some(callback));
+}
+
+// Only the batch callback is used
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.with(|c| c.borrow_mut().audio_sample_batch = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.with(|c| c.borrow_mut().input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.with(|c| c.borrow_mut().input_state = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    CORE.with(|core| {
+        if let Some(core) = core.borrow_mut().as_mut() {
+            core.machine.system.reset();
+            core.faulted = false;
+        }
+    });
+}
+
+// One frame: the buttons, a frame's worth of cycles, then the screen and silence
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = CALLBACKS.with(|c| {
+        let c = c.borrow();
+        (c.video_refresh, c.audio_sample_batch, c.input_poll, c.input_state)
+    });
+    let (video_refresh, audio_sample_batch, input_poll, input_state) = callbacks;
+    CORE.with(|core| {
+        let mut core = core.borrow_mut();
+        let Some(core) = core.as_mut() else {
+            return;
+        };
+        if let Some(input_poll) = input_poll {
+            input_poll();
+        }
+        if let Some(input_state) = input_state {
+            for (i, (button, key)) in KEYS.iter().enumerate() {
+                let down = input_state(0, DEVICE_JOYPAD, 0, *button) != 0;
+                if down && !core.held[i] {
+                    core.machine.io.borrow_mut().press(*key);
+                }
+                core.held[i] = down;
+            }
+        }
+        if !core.faulted {
+            let system = &mut core.machine.system;
+            core.faulted = panic::catch_unwind(AssertUnwindSafe(|| system.run_frame(CYCLES_PER_FRAME, |_| ()))).is_err();
+        }
+        core.frame = core.machine.screen.borrow().to_rgb();
+        if let Some(video_refresh) = video_refresh {
+            video_refresh(core.frame.as_ptr() as *const c_void, WIDTH as c_uint, HEIGHT as c_uint, WIDTH * 4);
+        }
+        if let Some(audio_sample_batch) = audio_sample_batch {
+            let silence = [0i16; SAMPLES_PER_FRAME * 2];
+            audio_sample_batch(silence.as_ptr(), SAMPLES_PER_FRAME);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize(_data: *mut c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unserialize(_data: *const c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+// Loads the content at $0600 on a fresh machine. False if the front end can't take XRGB8888,
+// or the content is missing or doesn't fit below $FFFF
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    if game.data.is_null() || game.size > 0x10000 - 0x0600 {
+        return false;
+    }
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    let environment = CALLBACKS.with(|c| c.borrow().environment);
+    if let Some(environment) = environment {
+        if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+            return false;
+        }
+    }
+    let mut machine = easy6502();
+    machine.load(slice::from_raw_parts(game.data as *const Data, game.size));
+    CORE.with(|core| {
+        *core.borrow_mut() = Some(Core {
+            machine,
+            held: [false; KEYS.len()],
+            frame: vec![],
+            faulted: false,
+        })
+    });
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.with(|core| core.borrow_mut().take());
+}
+
+// True once the machine has stopped on an opcode the processor doesn't know, until a reset.
+// Not part of libretro, for front ends that want to say why nothing's moving
+#[no_mangle]
+pub extern "C" fn sim6502_retro_is_faulted() -> bool {
+    CORE.with(|core| core.borrow().as_ref().is_some_and(|core| core.faulted))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+// RAM is a sparse map rather than a block, so there's none to hand out
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -0,0 +1,3 @@
+// Reading programs and memory images in the formats the tools pass around
+pub mod hexdump;
+pub mod ihex;
@@ -0,0 +1,113 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::io;
+
+use crate::bus::{Address, Bus, Data};
+
+// Bytes per line when dumping
+const BYTES_PER_LINE: u32 = 16;
+
+// A line that isn't part of a hex dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    // 1 based
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.text)
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+fn hex_digits(s: &str) -> &str {
+    s.strip_prefix('$').or_else(|| s.strip_prefix("0x")).unwrap_or(s)
+}
+
+// "BB", "$BB" or "0xBB"
+pub fn parse_byte(s: &str) -> Option<Data> {
+    let digits = hex_digits(s);
+    if digits.len() > 2 {
+        return None;
+    }
+    Data::from_str_radix(digits, 16).ok()
+}
+
+// "0200", "$0200" or "0x0200"
+pub fn parse_address(s: &str) -> Option<Address> {
+    Address::from_str_radix(hex_digits(s), 16).ok()
+}
+
+// The blocks of a hex dump: "ADDR: BB BB .." lines, lines without an address carrying on
+// from the last (from org before any address), # comments
+pub fn parse(text: &str, org: Address) -> Result<Vec<(Address, Vec<Data>)>, ParseError> {
+    let mut blocks: Vec<(Address, Vec<Data>)> = vec![];
+    let mut next = org;
+    for (n, line) in text.lines().enumerate() {
+        let bad = || ParseError {
+            line: n + 1,
+            text: line.to_string(),
+        };
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = match line.split_once(':') {
+            Some((address, bytes)) => {
+                next = parse_address(address.trim()).ok_or_else(bad)?;
+                blocks.push((next, vec![]));
+                bytes
+            }
+            None => {
+                if blocks.is_empty() {
+                    blocks.push((next, vec![]));
+                }
+                line
+            }
+        };
+        let block = &mut blocks.last_mut().unwrap().1;
+        for byte in bytes.split_whitespace() {
+            block.push(parse_byte(byte).ok_or_else(bad)?);
+            next = next.wrapping_add(1);
+        }
+    }
+    Ok(blocks)
+}
+
+// Lines of "ADDR: BB BB .." over start..=end, up to 16 bytes a line, which parse reads back
+pub fn dump(bus: &dyn Bus, start: Address, end: Address) -> String {
+    dump_from(&|a| bus.peek(a), start, end)
+}
+
+// Bytes as if loaded at org
+pub fn dump_bytes(bytes: &[Data], org: Address) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let read = |a: Address| bytes[a.wrapping_sub(org) as usize];
+    dump_from(&read, org, org.wrapping_add(bytes.len() as Address - 1))
+}
+
+fn dump_from(read: &dyn Fn(Address) -> Data, start: Address, end: Address) -> String {
+    let mut out = String::new();
+    let mut address = start as u32;
+    while address <= end as u32 {
+        write!(out, "{:04X}:", address).unwrap();
+        let line_end = (address + BYTES_PER_LINE).min(end as u32 + 1);
+        for a in address..line_end {
+            write!(out, " {:02X}", read(a as Address)).unwrap();
+        }
+        out.push('\n');
+        address = line_end;
+    }
+    out
+}
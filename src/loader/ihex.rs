@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::bus::{Address, Data};
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+// A bad record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    // 1 based
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+// The blocks of an Intel HEX file, records that follow on from each other joined up. Data has
+// to fall in the 64K the 6502 addresses, so extended addresses are only allowed as 0; start
+// address records are ignored, the reset vector being the program's own business
+pub fn parse(text: &str) -> Result<Vec<(Address, Vec<Data>)>, ParseError> {
+    let mut blocks: Vec<(Address, Vec<Data>)> = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let error = |message: &str| ParseError {
+            line: n + 1,
+            message: message.to_string(),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let digits = line.strip_prefix(':').ok_or_else(|| error("records start with ':'"))?;
+        if digits.len() % 2 != 0 || !digits.is_ascii() {
+            return Err(error("not whole hex bytes"));
+        }
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|i| Data::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<Vec<Data>, _>>()
+            .map_err(|_| error("not whole hex bytes"))?;
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(error("the length doesn't match the record"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(error("bad checksum"));
+        }
+        let address = (bytes[1] as Address) << 8 | bytes[2] as Address;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            DATA => {
+                if address as usize + data.len() > 0x10000 {
+                    return Err(error("data runs past $FFFF"));
+                }
+                match blocks.last_mut() {
+                    Some((start, block)) if *start as usize + block.len() == address as usize => block.extend_from_slice(data),
+                    _ => blocks.push((address, data.to_vec())),
+                }
+            }
+            END_OF_FILE => break,
+            EXTENDED_SEGMENT_ADDRESS | EXTENDED_LINEAR_ADDRESS => {
+                if data.iter().any(|b| *b != 0) {
+                    return Err(error("addresses above $FFFF"));
+                }
+            }
+            START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS => (),
+            _ => return Err(error("unknown record type")),
+        }
+    }
+    Ok(blocks.into_iter().filter(|(_, data)| !data.is_empty()).collect())
+}
@@ -0,0 +1,146 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use log::LevelFilter;
+#[cfg(feature = "std")]
+use log::{Log, Metadata, Record, SetLoggerError};
+
+// The targets the emulator logs under, for filtering, e.g. RUST_LOG=sim6502::bus=trace. Per
+// instruction and per access events are at trace, so with the level lower the only cost is
+// log's check of the max level; nothing is formatted
+pub const INSTRUCTION: &str = "sim6502::instruction";
+pub const BUS: &str = "sim6502::bus";
+pub const DEVICE: &str = "sim6502::device";
+
+pub const ENV_VAR: &str = "RUST_LOG";
+
+// The level for each target, in RUST_LOG's syntax without the regexes: a comma separated list
+// of "level", "target" (everything) or "target=level", e.g. "warn,sim6502::instruction=trace".
+// A target covers the targets under it, the longest match winning
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(LevelFilter::Warn)
+    }
+}
+
+impl Filter {
+    pub fn new(default: LevelFilter) -> Filter {
+        Filter { default, targets: vec![] }
+    }
+
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter::new(LevelFilter::Error);
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let level = |s: &str| s.parse::<LevelFilter>().map_err(|_| format!("{} isn't a log level", s));
+            match directive.split_once('=') {
+                Some((target, l)) => filter = filter.with_target(target.trim(), level(l.trim())?),
+                None => match directive.parse::<LevelFilter>() {
+                    Ok(l) => filter.default = l,
+                    Err(_) => filter = filter.with_target(directive, LevelFilter::Trace),
+                },
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn with_target(mut self, target: &str, level: LevelFilter) -> Filter {
+        self.targets.retain(|(t, _)| t != target);
+        self.targets.push((target.to_string(), level));
+        self
+    }
+
+    // At least this verbose everywhere
+    pub fn with_minimum(mut self, level: LevelFilter) -> Filter {
+        self.default = self.default.max(level);
+        for (_, l) in &mut self.targets {
+            *l = (*l).max(level);
+        }
+        self
+    }
+
+    pub fn get_level(&self, target: &str) -> LevelFilter {
+        let covers = |t: &str| target == t || target.strip_prefix(t).is_some_and(|rest| rest.starts_with("::"));
+        self.targets
+            .iter()
+            .filter(|(t, _)| covers(t))
+            .max_by_key(|(t, _)| t.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    pub fn get_max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|(_, l)| *l).fold(self.default, |a, b| a.max(b))
+    }
+}
+
+// "-v" counts: warnings and errors only, then info, debug and trace
+pub fn verbosity_level(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+// Lines of "LEVEL target: message" on stderr, out of the way of a program's output
+#[cfg(feature = "std")]
+pub struct StderrLogger {
+    filter: Filter,
+}
+
+#[cfg(feature = "std")]
+impl StderrLogger {
+    pub fn new(filter: Filter) -> StderrLogger {
+        StderrLogger { filter }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.get_level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let _ = writeln!(io::stderr().lock(), "{:<5} {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+// Installs a StderrLogger filtered by RUST_LOG, or warnings only without it (or with a RUST_LOG
+// it can't read, which it warns about), raised to the -v count's level. Fails if there's a
+// logger already
+#[cfg(feature = "std")]
+pub fn init(verbose: u8) -> Result<(), SetLoggerError> {
+    let parsed = env::var(ENV_VAR).map(|spec| Filter::parse(&spec));
+    let filter = match &parsed {
+        Ok(Ok(filter)) => filter.clone(),
+        _ => Filter::default(),
+    }
+    .with_minimum(verbosity_level(verbose));
+    let max_level = filter.get_max_level();
+    // installed for the life of the program
+    log::set_logger(Box::leak(Box::new(StderrLogger::new(filter))))?;
+    log::set_max_level(max_level);
+    if let Ok(Err(e)) = parsed {
+        log::warn!("{}: {}", ENV_VAR, e);
+    }
+    Ok(())
+}
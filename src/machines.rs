@@ -0,0 +1,15 @@
+// Ready made machines, each a System laid out like a well known computer along with handles
+// to the devices a front end needs
+mod apple1;
+mod atari2600;
+mod ben_eater;
+mod c64;
+mod easy6502;
+mod pet;
+
+pub use apple1::{apple1, apple1_rom_file, Apple1, Apple1Io};
+pub use atari2600::{atari2600, atari2600_rom_file, Atari2600, Tia};
+pub use ben_eater::{ben_eater, ben_eater_rom_file, BenEater};
+pub use c64::{c64, c64_rom_files, C64Board, C64};
+pub use easy6502::{easy6502, easy6502_with_seed, Easy6502};
+pub use pet::{pet, pet_rom_file, Pet, PetKeyboard, PetScreen};
@@ -0,0 +1,153 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::acia::{SerialBackend, StdioSerial};
+use crate::devices::pia::Pia;
+use crate::devices::Peripheral;
+use crate::memory::Rom;
+use crate::system::System;
+
+pub const PIA: Address = 0xd010;
+const KBD: Address = PIA;
+
+// The Apple-1 keyboard and display on a PIA at $D010-$D013, driven from a terminal:
+//   KBD $D010 the key with bit 7 set, KBDCR $D011 bit 7 a key is waiting (CA1 strobe)
+//   DSP $D012 the character to show, bit 7 reads 0 as the display is always ready, DSPCR $D013
+// Keys are upper cased as the Apple-1 only had capitals, and backspace becomes the underscore
+// Wozmon rubs out with. A character written to DSP drops CB2 and the display raises CB1 once
+// it has shown it, so each write is seen exactly once
+pub struct Apple1Io {
+    pia: Pia,
+    terminal: Box<dyn SerialBackend>,
+    // a key has been strobed in and not read yet
+    key_waiting: Cell<bool>,
+}
+
+impl Apple1Io {
+    pub fn new(terminal: Box<dyn SerialBackend>) -> Apple1Io {
+        Apple1Io {
+            pia: Pia::new(PIA),
+            terminal,
+            key_waiting: Cell::new(false),
+        }
+    }
+}
+
+fn to_apple1(key: Data) -> Data {
+    match key {
+        b'\n' => b'\r',
+        0x08 | 0x7f => b'_',
+        _ => key.to_ascii_uppercase(),
+    }
+}
+
+impl Peripheral for Apple1Io {
+    fn tick(&mut self, cycles: usize) {
+        self.pia.tick(cycles);
+        if !self.key_waiting.get() {
+            if let Some(key) = self.terminal.receive() {
+                self.pia.set_port_a_input(to_apple1(key) | 0x80);
+                self.pia.set_ca1(false);
+                self.pia.set_ca1(true);
+                self.key_waiting.set(true);
+            }
+        }
+        if !self.pia.get_cb2() {
+            match self.pia.get_port_b_output() & 0x7f {
+                b'\r' => self.terminal.transmit(b'\n'),
+                c => self.terminal.transmit(c),
+            }
+            self.pia.set_cb1(false);
+            self.pia.set_cb1(true);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pia.reset();
+        self.key_waiting.set(false);
+    }
+}
+
+impl BusDevice for Apple1Io {
+    fn do_read(&self, address: Address) -> Data {
+        if address == KBD {
+            self.key_waiting.set(false);
+        }
+        self.pia.do_read(address)
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        self.pia.peek(address)
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        self.pia.do_write(address, data);
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.pia.is_readable_for(address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.pia.is_writable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Apple1Io {
+    fn get_name(&self) -> String {
+        "apple1".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let mut fields = self.pia.get_fields();
+        fields.push(("key waiting".to_string(), self.key_waiting.get().to_string()));
+        fields
+    }
+}
+
+// An Apple-1 with 64K of RAM, its PIA on a terminal and a monitor ROM such as Wozmon at the top
+// of memory. It starts wherever the ROM's reset vector points
+pub struct Apple1 {
+    pub system: System,
+    pub io: Rc<RefCell<Apple1Io>>,
+}
+
+// On the host terminal
+pub fn apple1(rom: Vec<Data>) -> io::Result<Apple1> {
+    Apple1::with_terminal(rom, Box::new(StdioSerial::new()))
+}
+
+pub fn apple1_rom_file(path: impl AsRef<Path>) -> io::Result<Apple1> {
+    apple1(fs::read(path)?)
+}
+
+impl Apple1 {
+    // The ROM ends at $FFFF, so Wozmon's 256 bytes go at $FF00
+    pub fn with_terminal(rom: Vec<Data>, terminal: Box<dyn SerialBackend>) -> io::Result<Apple1> {
+        if rom.len() < 6 || rom.len() > 0x1000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("a {} byte image isn't a monitor ROM ending at $FFFF", rom.len()),
+            ));
+        }
+        let start = (0x10000 - rom.len()) as Address;
+        let reset = rom[rom.len() - 4] as Address | (rom[rom.len() - 3] as Address) << 8;
+        let mut system = System::new();
+        let io = system.add_peripheral(Apple1Io::new(terminal));
+        system.add_device(Rom::new(start, rom));
+        system.set_reset_vector(reset);
+        Ok(Apple1 { system, io })
+    }
+}
@@ -0,0 +1,142 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::riot::Riot;
+use crate::devices::Peripheral;
+use crate::memory::{Memory, Rom};
+use crate::processor::BOOT_VECTOR;
+use crate::system::{CpuModel, System};
+
+const CARTRIDGE: Address = 0x1000;
+const INPT4: usize = 0x0c;
+const INPT5: usize = 0x0d;
+
+// The TIA's registers at $00-$7F: writes land in 64 registers (A0-A5), reads come from 16
+// (A0-A3). Nothing is drawn or heard; collisions read 0 and the fire buttons read released.
+// WSYNC writes are counted so a trace shows where the scanlines fall
+pub struct Tia {
+    registers: [Data; 64],
+    inputs: [Data; 16],
+    wsyncs: usize,
+}
+
+impl Tia {
+    pub fn new() -> Tia {
+        let mut inputs = [0; 16];
+        inputs[INPT4] = 0x80;
+        inputs[INPT5] = 0x80;
+        Tia {
+            registers: [0; 64],
+            inputs,
+            wsyncs: 0,
+        }
+    }
+
+    // Last value written to a write register
+    pub fn get_register(&self, register: usize) -> Data {
+        self.registers[register & 0x3f]
+    }
+
+    pub fn get_wsyncs(&self) -> usize {
+        self.wsyncs
+    }
+
+    // The left or right fire button
+    pub fn set_fire(&mut self, player: usize, pressed: bool) {
+        self.inputs[INPT4 + (player & 1)] = if pressed { 0 } else { 0x80 };
+    }
+}
+
+impl Default for Tia {
+    fn default() -> Self {
+        Tia::new()
+    }
+}
+
+impl Peripheral for Tia {}
+
+impl BusDevice for Tia {
+    fn do_read(&self, address: Address) -> Data {
+        self.inputs[(address & 0x0f) as usize]
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        let register = (address & 0x3f) as usize;
+        if register == 0x02 {
+            self.wsyncs += 1;
+        }
+        self.registers[register] = data;
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        address < 0x80
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        address < 0x80
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for Tia {
+    fn get_name(&self) -> String {
+        "tia".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("wsyncs".to_string(), self.wsyncs.to_string()),
+            ("colubk".to_string(), format!("${:02X}", self.registers[0x09])),
+            ("fire".to_string(), format!("{} {}", self.inputs[INPT4] == 0, self.inputs[INPT5] == 0)),
+        ]
+    }
+}
+
+// An Atari 2600 skeleton for tracing and stepping cartridges: a 6507, the TIA register stub
+// at $00-$7F, the RIOT's RAM at $80-$FF and I/O at $280 (joysticks released, console switches
+// on colour) and a 2K or 4K cartridge at $1000 (2K ones mirrored). There's no other RAM; the
+// System's only holds the boot vector, pointed at the cartridge's reset vector
+pub struct Atari2600 {
+    pub system: System,
+    pub tia: Rc<RefCell<Tia>>,
+    pub riot: Rc<RefCell<Riot>>,
+}
+
+pub fn atari2600(rom: Vec<Data>) -> io::Result<Atari2600> {
+    let rom = match rom.len() {
+        0x800 => rom.repeat(2),
+        0x1000 => rom,
+        n => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} bytes isn't a 2K or 4K cartridge; bank switching isn't supported", n),
+            ))
+        }
+    };
+    let reset = rom[0xffc] as Address | (rom[0xffd] as Address) << 8;
+    let mut system = System::with_memory(Memory::new(BOOT_VECTOR, BOOT_VECTOR + 1));
+    system.set_address_mask(CpuModel::Mos6507.get_address_mask());
+    let tia = system.add_peripheral(Tia::new());
+    let mut riot = Riot::new(0x0080, 0x0280);
+    riot.set_port_a_input(0xff);
+    riot.set_port_b_input(0x0b);
+    let riot = system.add_peripheral(riot);
+    system.add_device(Rom::new(CARTRIDGE, rom));
+    system.set_reset_vector(reset);
+    Ok(Atari2600 { system, tia, riot })
+}
+
+pub fn atari2600_rom_file(path: impl AsRef<Path>) -> io::Result<Atari2600> {
+    atari2600(fs::read(path)?)
+}
@@ -0,0 +1,51 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, Data};
+use crate::devices::acia::{Acia, SerialBackend, StdioSerial};
+use crate::devices::via::Via;
+use crate::memory::{Memory, Rom};
+use crate::system::System;
+
+pub const VIA: Address = 0x6000;
+pub const ACIA: Address = 0x5000;
+
+// Ben Eater's breadboard 6502: 32K of RAM at $0000, a 6522 at $6000, a 6551 at $5000 on a
+// terminal and a 32K ROM at $8000. A shorter ROM image sits at the top so the vectors line up.
+// The VIA's ports are left for the caller to wire up, e.g. to an LCD
+pub struct BenEater {
+    pub system: System,
+    pub via: Rc<RefCell<Via>>,
+    pub acia: Rc<RefCell<Acia>>,
+}
+
+// The ACIA on the host terminal
+pub fn ben_eater(rom: Vec<Data>) -> io::Result<BenEater> {
+    BenEater::with_terminal(rom, Box::new(StdioSerial::new()))
+}
+
+pub fn ben_eater_rom_file(path: impl AsRef<Path>) -> io::Result<BenEater> {
+    ben_eater(fs::read(path)?)
+}
+
+impl BenEater {
+    pub fn with_terminal(rom: Vec<Data>, terminal: Box<dyn SerialBackend>) -> io::Result<BenEater> {
+        if rom.len() < 6 || rom.len() > 0x8000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("a {} byte image isn't a ROM for $8000-$FFFF", rom.len()),
+            ));
+        }
+        let start = (0x10000 - rom.len()) as Address;
+        let reset = rom[rom.len() - 4] as Address | (rom[rom.len() - 3] as Address) << 8;
+        let mut system = System::with_memory(Memory::new(0x0000, 0x7fff));
+        let via = system.add_peripheral(Via::new(VIA));
+        let acia = system.add_peripheral(Acia::new(ACIA, terminal));
+        system.add_device(Rom::new(start, rom));
+        system.set_reset_vector(reset);
+        Ok(BenEater { system, via, acia })
+    }
+}
@@ -0,0 +1,380 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::acia::{SerialBackend, StdioSerial};
+use crate::devices::cia::Cia;
+use crate::devices::Peripheral;
+use crate::machines::pet::{ansi_frame, to_ascii, COLUMNS, ROWS};
+use crate::memory::Memory;
+use crate::system::System;
+
+pub const CIA1: Address = 0xdc00;
+pub const CIA2: Address = 0xdd00;
+const LORAM: Data = 0x01;
+const HIRAM: Data = 0x02;
+const CHAREN: Data = 0x04;
+const CYCLES_PER_LINE: usize = 63;
+const LINES: usize = 263;
+// the KERNAL's keyboard buffer and its length
+const KEYD: Address = 0x0277;
+const NDX: Address = 0x00c6;
+
+// What the CPU sees at an address under the current banking
+#[derive(Debug, PartialEq)]
+enum Bank {
+    Ram,
+    Port,
+    Basic,
+    Kernal,
+    Chargen,
+    Io,
+}
+
+// The VIC-II's registers, mirrored every 64 bytes over $D000-$D3FF. Only the raster counter
+// ($D011 bit 7, $D012), its compare and the raster interrupt ($D019/$D01A) do anything
+struct Vic {
+    registers: [Data; 64],
+    cycles: usize,
+    raster_compare: usize,
+    flags: Data,
+}
+
+impl Vic {
+    fn new() -> Vic {
+        Vic {
+            registers: [0; 64],
+            cycles: 0,
+            raster_compare: 0,
+            flags: 0,
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.cycles / CYCLES_PER_LINE
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        let before = self.line();
+        self.cycles = (self.cycles + cycles) % (CYCLES_PER_LINE * LINES);
+        let after = self.line();
+        let passed = if after >= before {
+            (before + 1..=after).contains(&self.raster_compare)
+        } else {
+            self.raster_compare > before || self.raster_compare <= after
+        };
+        if passed {
+            self.flags |= 0x01;
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.flags & self.registers[0x1a] & 0x0f != 0
+    }
+
+    fn read(&self, offset: usize) -> Data {
+        match offset & 0x3f {
+            0x11 => (self.registers[0x11] & 0x7f) | ((self.line() >> 1) & 0x80) as Data,
+            0x12 => self.line() as Data,
+            0x19 => self.flags | 0x70 | if self.irq_asserted() { 0x80 } else { 0 },
+            0x2f..=0x3f => 0xff,
+            r => self.registers[r],
+        }
+    }
+
+    fn write(&mut self, offset: usize, data: Data) {
+        let r = offset & 0x3f;
+        match r {
+            0x11 => self.raster_compare = (self.raster_compare & 0xff) | ((data as usize & 0x80) << 1),
+            0x12 => self.raster_compare = (self.raster_compare & 0x100) | data as usize,
+            0x19 => self.flags &= !data,
+            _ => {}
+        }
+        self.registers[r] = data;
+    }
+}
+
+// The C64's banking, I/O and console in one board:
+//   $00/$01 the 6510 port; bits 0-2 LORAM, HIRAM and CHAREN choose what overlays the RAM
+//   $A000-$BFFF BASIC with LORAM and HIRAM, $E000-$FFFF the KERNAL with HIRAM
+//   $D000-$DFFF with LORAM or HIRAM: I/O with CHAREN, else the character ROM
+//   I/O: VIC-II and SID register stubs, 1K of colour RAM nibbles, CIA 1 at $DC00, CIA 2 at $DD00
+// Writes under ROM fall through to the RAM, as on the real thing. It sits in front of the
+// System's RAM, which it also reads to draw the text screen on a terminal once a frame and
+// to feed typed keys into the KERNAL's keyboard buffer
+pub struct C64Board {
+    direction: Data,
+    port: Data,
+    basic: Vec<Data>,
+    kernal: Vec<Data>,
+    chargen: Vec<Data>,
+    vic: Vic,
+    sid: [Data; 32],
+    color: Vec<Data>,
+    pub cia1: Cia,
+    pub cia2: Cia,
+    ram: Rc<RefCell<Memory>>,
+    keys: Box<dyn SerialBackend>,
+    out: Option<Box<dyn Write>>,
+    frame: usize,
+    drawn: Vec<Data>,
+}
+
+impl C64Board {
+    fn new(basic: Vec<Data>, kernal: Vec<Data>, chargen: Vec<Data>, ram: Rc<RefCell<Memory>>, keys: Box<dyn SerialBackend>) -> C64Board {
+        C64Board {
+            direction: 0,
+            port: 0,
+            basic,
+            kernal,
+            chargen,
+            vic: Vic::new(),
+            sid: [0; 32],
+            color: vec![0; 0x400],
+            cia1: Cia::new(CIA1),
+            cia2: Cia::new(CIA2),
+            ram,
+            keys,
+            out: None,
+            frame: 0,
+            drawn: vec![],
+        }
+    }
+
+    // The port pins, inputs pulled up
+    pub fn get_banking(&self) -> Data {
+        (self.port | !self.direction) & 0x07
+    }
+
+    fn bank(&self, address: Address) -> Bank {
+        let banking = self.get_banking();
+        match address {
+            0x0000 | 0x0001 => Bank::Port,
+            0xa000..=0xbfff if banking & (LORAM | HIRAM) == LORAM | HIRAM => Bank::Basic,
+            0xe000..=0xffff if banking & HIRAM != 0 => Bank::Kernal,
+            0xd000..=0xdfff if banking & (LORAM | HIRAM) != 0 => {
+                if banking & CHAREN != 0 {
+                    Bank::Io
+                } else {
+                    Bank::Chargen
+                }
+            }
+            _ => Bank::Ram,
+        }
+    }
+
+    // Where the VIC finds the screen: its 16K bank from CIA 2 port A, the 1K within it from $D018
+    pub fn get_screen(&self) -> Address {
+        let bank = (3 - (self.cia2.get_port_a_output() & 0x03) as Address) * 0x4000;
+        bank + (self.vic.registers[0x18] >> 4) as Address * 0x400
+    }
+
+    // The screen as text
+    pub fn lines(&self) -> Vec<String> {
+        let ram = self.ram.borrow();
+        let screen = self.get_screen();
+        (0..ROWS)
+            .map(|row| (0..COLUMNS).map(|column| to_ascii(ram.do_read(screen + (row * COLUMNS + column) as Address))).collect())
+            .collect()
+    }
+
+    fn draw(&mut self) {
+        let screen = self.get_screen();
+        let cells: Vec<Data> = {
+            let ram = self.ram.borrow();
+            (0..(COLUMNS * ROWS) as Address).map(|i| ram.do_read(screen.wrapping_add(i))).collect()
+        };
+        if cells == self.drawn {
+            return;
+        }
+        let out = match &mut self.out {
+            Some(out) => out,
+            None => return,
+        };
+        let _ = out.write_all(ansi_frame(&cells).as_bytes());
+        let _ = out.flush();
+        self.drawn = cells;
+    }
+
+    // One key at a time, whenever the KERNAL has emptied its buffer
+    fn type_key(&mut self) {
+        let mut ram = self.ram.borrow_mut();
+        if ram.do_read(NDX) != 0 {
+            return;
+        }
+        if let Some(key) = self.keys.receive() {
+            let key = match key {
+                b'\n' => b'\r',
+                0x08 | 0x7f => 0x14,
+                _ => key.to_ascii_uppercase(),
+            };
+            ram.do_write(KEYD, key);
+            ram.do_write(NDX, 1);
+        }
+    }
+
+    fn io_read(&self, address: Address) -> Data {
+        let offset = (address - 0xd000) as usize;
+        match address {
+            0xd000..=0xd3ff => self.vic.read(offset),
+            // only the oscillator 3 and envelope 3 read back, and they don't run
+            0xd400..=0xd7ff => 0,
+            0xd800..=0xdbff => self.color[offset - 0x800] | 0xf0,
+            0xdc00..=0xdcff => self.cia1.do_read(address),
+            _ => self.cia2.do_read(address),
+        }
+    }
+
+    fn io_write(&mut self, address: Address, data: Data) {
+        let offset = (address - 0xd000) as usize;
+        match address {
+            0xd000..=0xd3ff => self.vic.write(offset, data),
+            0xd400..=0xd7ff => self.sid[offset & 0x1f] = data,
+            0xd800..=0xdbff => self.color[offset - 0x800] = data & 0x0f,
+            0xdc00..=0xdcff => self.cia1.do_write(address, data),
+            _ => self.cia2.do_write(address, data),
+        }
+    }
+}
+
+impl Peripheral for C64Board {
+    fn tick(&mut self, cycles: usize) {
+        self.vic.tick(cycles);
+        self.cia1.tick(cycles);
+        self.cia2.tick(cycles);
+        self.type_key();
+        self.frame += cycles;
+        if self.frame >= CYCLES_PER_LINE * LINES {
+            self.frame %= CYCLES_PER_LINE * LINES;
+            self.draw();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.direction = 0;
+        self.port = 0;
+        self.vic = Vic::new();
+        self.cia1.reset();
+        self.cia2.reset();
+    }
+
+    // CIA 2 drives NMI, which isn't modelled
+    fn irq_asserted(&self) -> bool {
+        self.vic.irq_asserted() || self.cia1.irq_asserted()
+    }
+}
+
+impl BusDevice for C64Board {
+    fn do_read(&self, address: Address) -> Data {
+        match self.bank(address) {
+            Bank::Port if address == 0 => self.direction,
+            Bank::Port => (self.port & self.direction) | (0x17 & !self.direction),
+            Bank::Basic => self.basic[(address - 0xa000) as usize],
+            Bank::Kernal => self.kernal[(address - 0xe000) as usize],
+            Bank::Chargen => self.chargen[(address - 0xd000) as usize],
+            Bank::Io => self.io_read(address),
+            Bank::Ram => 0,
+        }
+    }
+
+    // the CIAs are the only registers whose reads acknowledge anything
+    fn peek(&self, address: Address) -> Data {
+        match self.bank(address) {
+            Bank::Io if (0xdc00..=0xdcff).contains(&address) => self.cia1.peek(address),
+            Bank::Io if address >= 0xdd00 => self.cia2.peek(address),
+            _ => self.do_read(address),
+        }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        match self.bank(address) {
+            Bank::Port if address == 0 => self.direction = data,
+            Bank::Port => self.port = data,
+            Bank::Io => self.io_write(address, data),
+            _ => {}
+        }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        match self.bank(address) {
+            Bank::Ram => false,
+            Bank::Io => address < 0xde00,
+            _ => true,
+        }
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        match self.bank(address) {
+            Bank::Port => true,
+            Bank::Io => address < 0xde00,
+            _ => false,
+        }
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for C64Board {
+    fn get_name(&self) -> String {
+        "c64".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let banking = self.get_banking();
+        let name = |bit: Data, name: &str| if banking & bit != 0 { name.to_uppercase() } else { name.to_string() };
+        vec![
+            ("port".to_string(), format!("${:02X} ddr ${:02X}", self.port, self.direction)),
+            ("banking".to_string(), format!("{} {} {}", name(LORAM, "loram"), name(HIRAM, "hiram"), name(CHAREN, "charen"))),
+            ("raster".to_string(), self.vic.line().to_string()),
+            ("screen".to_string(), format!("${:04X}", self.get_screen())),
+        ]
+    }
+}
+
+// A C64 as far as its memory map goes: 64K of RAM, BASIC, KERNAL and character ROMs banked by
+// the 6510 port, the I/O window with stub VIC-II and SID, colour RAM and two CIAs, and the
+// text screen on a terminal. No video, sound or disk
+pub struct C64 {
+    pub system: System,
+    pub board: Rc<RefCell<C64Board>>,
+}
+
+// Typing and drawing on the host terminal
+pub fn c64(basic: Vec<Data>, kernal: Vec<Data>, chargen: Vec<Data>) -> io::Result<C64> {
+    C64::with_terminal(basic, kernal, chargen, Box::new(StdioSerial::new()), Box::new(io::stdout()))
+}
+
+pub fn c64_rom_files(basic: impl AsRef<Path>, kernal: impl AsRef<Path>, chargen: impl AsRef<Path>) -> io::Result<C64> {
+    c64(fs::read(basic)?, fs::read(kernal)?, fs::read(chargen)?)
+}
+
+impl C64 {
+    pub fn with_terminal(
+        basic: Vec<Data>,
+        kernal: Vec<Data>,
+        chargen: Vec<Data>,
+        keys: Box<dyn SerialBackend>,
+        out: Box<dyn Write>,
+    ) -> io::Result<C64> {
+        for (rom, name, size) in [(&basic, "BASIC", 0x2000), (&kernal, "KERNAL", 0x2000), (&chargen, "character", 0x1000)] {
+            if rom.len() != size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("the {} ROM should be {} bytes, not {}", name, size, rom.len()),
+                ));
+            }
+        }
+        let reset = kernal[0x1ffc] as Address | (kernal[0x1ffd] as Address) << 8;
+        let mut system = System::new();
+        let mut board = C64Board::new(basic, kernal, chargen, system.get_memory(), keys);
+        board.out = Some(out);
+        let board = system.add_peripheral(board);
+        system.set_reset_vector(reset);
+        Ok(C64 { system, board })
+    }
+}
@@ -0,0 +1,43 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bus::{Address, Data};
+use crate::devices::easy6502::Easy6502Io;
+use crate::devices::framebuffer::Framebuffer;
+use crate::system::System;
+
+pub const START: Address = 0x0600;
+const SCREEN: Address = 0x0200;
+
+// The easy6502.com tutorial machine: $FE random, $FF last key, the 32x32 screen at $0200-$05FF
+// and programs loaded and started at $0600. Hand the screen to a Gui, or give it a sink, to see it
+pub struct Easy6502 {
+    pub system: System,
+    pub io: Rc<RefCell<Easy6502Io>>,
+    pub screen: Rc<RefCell<Framebuffer>>,
+}
+
+pub fn easy6502() -> Easy6502 {
+    with_io(Easy6502Io::new())
+}
+
+// The same random bytes at $FE every run, or seeded from outside where there's no clock
+pub fn easy6502_with_seed(seed: u32) -> Easy6502 {
+    with_io(Easy6502Io::with_seed(seed))
+}
+
+fn with_io(io: Easy6502Io) -> Easy6502 {
+    let mut system = System::new();
+    let io = system.add_peripheral(io);
+    let screen = system.add_peripheral(Framebuffer::new(SCREEN));
+    system.set_reset_vector(START);
+    Easy6502 { system, io, screen }
+}
+
+impl Easy6502 {
+    // Loads a program at $0600 and resets so the next step starts it
+    pub fn load(&mut self, program: &[Data]) {
+        self.system.load(START, program);
+        self.system.reset();
+    }
+}
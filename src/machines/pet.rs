@@ -0,0 +1,340 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, BusDevice, Data, DebugView};
+use crate::devices::acia::{SerialBackend, StdioSerial};
+use crate::devices::pia::Pia;
+use crate::devices::via::Via;
+use crate::devices::Peripheral;
+use crate::memory::{Memory, Rom};
+use crate::system::System;
+
+pub const SCREEN: Address = 0x8000;
+pub const PIA1: Address = 0xe810;
+pub const PIA2: Address = 0xe820;
+pub const VIA: Address = 0xe840;
+pub const COLUMNS: usize = 40;
+pub const ROWS: usize = 25;
+const CYCLES_PER_FRAME: usize = 16_667; // 60Hz at 1MHz
+// how long a typed key is held down, long enough for a few keyboard scans
+const KEY_DOWN_CYCLES: usize = 3 * CYCLES_PER_FRAME;
+const KEY_UP_CYCLES: usize = CYCLES_PER_FRAME;
+
+// The 2001's graphics keyboard by row and column, as PETSCII. Shift keys and gaps are 0
+const MATRIX: [[Data; 8]; 10] = [
+    [b'!', b'#', b'%', b'&', b'(', 0x5f, 0x13, 0x1d],
+    [b'"', b'$', b'\'', b'\\', b')', 0, 0x11, 0x14],
+    [b'Q', b'E', b'T', b'U', b'O', 0x5e, b'7', b'9'],
+    [b'W', b'R', b'Y', b'I', b'P', 0, b'8', b'/'],
+    [b'A', b'D', b'G', b'J', b'L', 0, b'4', b'6'],
+    [b'S', b'F', b'H', b'K', b':', 0, b'5', b'*'],
+    [b'Z', b'C', b'B', b'M', b';', 0x0d, b'1', b'3'],
+    [b'X', b'V', b'N', b',', b'?', 0, b'2', b'+'],
+    [0, b'@', b']', 0, b'>', 0, b'0', b'-'],
+    [0x12, b'[', b' ', b'<', 0x03, 0, b'.', b'='],
+];
+
+// (row, column) of the key that types an ASCII character
+fn find_key(key: Data) -> Option<(usize, usize)> {
+    let key = match key {
+        b'\n' | b'\r' => 0x0d,
+        0x08 | 0x7f => 0x14,
+        _ => key.to_ascii_uppercase(),
+    };
+    if key == 0 {
+        return None;
+    }
+    MATRIX
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.iter().position(|k| *k == key).map(|column| (row, column)))
+}
+
+// PIA 1 of a PET: port A bits 0-3 pick a keyboard row, port B reads its columns active low.
+// Characters from the terminal are held down on the matrix for a few scans each, and CB1
+// gets the 60Hz retrace that drives the KERNAL's interrupt
+pub struct PetKeyboard {
+    pia: Pia,
+    terminal: Box<dyn SerialBackend>,
+    key: Option<(usize, usize)>,
+    // cycles left with the key down, then up before the next one
+    down: usize,
+    up: usize,
+    frame: usize,
+}
+
+impl PetKeyboard {
+    pub fn new(terminal: Box<dyn SerialBackend>) -> PetKeyboard {
+        PetKeyboard {
+            pia: Pia::new(PIA1),
+            terminal,
+            key: None,
+            down: 0,
+            up: 0,
+            frame: 0,
+        }
+    }
+
+    fn scan(&mut self) {
+        let row = (self.pia.get_port_a_output() & 0x0f) as usize;
+        let columns = match self.key {
+            Some((r, column)) if r == row => !(1 << column),
+            _ => 0xff,
+        };
+        self.pia.set_port_b_input(columns);
+    }
+}
+
+impl Peripheral for PetKeyboard {
+    fn tick(&mut self, cycles: usize) {
+        self.pia.tick(cycles);
+        if self.key.is_some() {
+            self.down = self.down.saturating_sub(cycles);
+            if self.down == 0 {
+                self.key = None;
+                self.up = KEY_UP_CYCLES;
+            }
+        } else if self.up > 0 {
+            self.up = self.up.saturating_sub(cycles);
+        } else if let Some(key) = self.terminal.receive() {
+            self.key = find_key(key);
+            self.down = KEY_DOWN_CYCLES;
+        }
+        self.frame += cycles;
+        if self.frame >= CYCLES_PER_FRAME {
+            self.frame %= CYCLES_PER_FRAME;
+            self.pia.set_cb1(true);
+            self.pia.set_cb1(false);
+        }
+        self.scan();
+    }
+
+    fn reset(&mut self) {
+        self.pia.reset();
+        self.key = None;
+        self.frame = 0;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.pia.irq_asserted()
+    }
+}
+
+impl BusDevice for PetKeyboard {
+    fn do_read(&self, address: Address) -> Data {
+        self.pia.do_read(address)
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        self.pia.peek(address)
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        self.pia.do_write(address, data);
+        self.scan();
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.pia.is_readable_for(address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.pia.is_writable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for PetKeyboard {
+    fn get_name(&self) -> String {
+        "petkeyboard".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let mut fields = self.pia.get_fields();
+        let key = match self.key {
+            Some((row, column)) => format!("row {} column {}", row, column),
+            None => "none".to_string(),
+        };
+        fields.push(("key".to_string(), key));
+        fields
+    }
+}
+
+// A screen code as ASCII: letters and symbols as themselves, graphics as #. The C64 shares them
+pub(crate) fn to_ascii(code: Data) -> char {
+    match code & 0x7f {
+        c @ 0x00..=0x1f => (c + 0x40) as char,
+        c @ 0x20..=0x3f => c as char,
+        _ => '#',
+    }
+}
+
+// A screen of codes for a terminal, from the top left, reversed cells in inverse video
+pub(crate) fn ansi_frame(cells: &[Data]) -> String {
+    let mut frame = String::from("\x1b[H");
+    for row in cells.chunks(COLUMNS) {
+        for c in row {
+            if c & 0x80 != 0 {
+                frame.push_str(&format!("\x1b[7m{}\x1b[0m", to_ascii(*c)));
+            } else {
+                frame.push(to_ascii(*c));
+            }
+        }
+        frame.push_str("\r\n");
+    }
+    frame
+}
+
+// The PET's 40x25 screen memory at $8000, one screen code per cell with bit 7 reversing it.
+// Changed frames are drawn to a terminal with ANSI escapes once per retrace
+pub struct PetScreen {
+    cells: Vec<Data>,
+    out: Option<Box<dyn Write>>,
+    dirty: bool,
+    cycles: usize,
+}
+
+impl PetScreen {
+    pub fn new() -> PetScreen {
+        PetScreen {
+            cells: vec![0x20; 0x400],
+            out: None,
+            dirty: true,
+            cycles: 0,
+        }
+    }
+
+    pub fn with_writer(mut self, out: Box<dyn Write>) -> PetScreen {
+        self.out = Some(out);
+        self
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.cells[..COLUMNS * ROWS].chunks(COLUMNS).map(|row| row.iter().map(|c| to_ascii(*c)).collect()).collect()
+    }
+
+    fn draw(&mut self) {
+        let out = match &mut self.out {
+            Some(out) => out,
+            None => return,
+        };
+        let _ = out.write_all(ansi_frame(&self.cells[..COLUMNS * ROWS]).as_bytes());
+        let _ = out.flush();
+        self.dirty = false;
+    }
+}
+
+impl Default for PetScreen {
+    fn default() -> Self {
+        PetScreen::new()
+    }
+}
+
+impl Peripheral for PetScreen {
+    fn tick(&mut self, cycles: usize) {
+        self.cycles += cycles;
+        if self.cycles >= CYCLES_PER_FRAME {
+            self.cycles %= CYCLES_PER_FRAME;
+            if self.dirty {
+                self.draw();
+            }
+        }
+    }
+}
+
+impl BusDevice for PetScreen {
+    fn do_read(&self, address: Address) -> Data {
+        self.cells[(address - SCREEN) as usize]
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        self.cells[(address - SCREEN) as usize] = data;
+        self.dirty = true;
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        (SCREEN..SCREEN + 0x400).contains(&address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.is_readable_for(address)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+}
+
+impl DebugView for PetScreen {
+    fn get_name(&self) -> String {
+        "petscreen".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("screen".to_string(), format!("${:04X}", SCREEN)),
+            ("dirty".to_string(), self.dirty.to_string()),
+        ]
+    }
+}
+
+// A PET 2001 with 32K of RAM, the screen at $8000, PIA 1 scanning the keyboard at $E810,
+// PIA 2 at $E820, the VIA at $E840 and a ROM image ending at $FFFF, normally the 16K of
+// BASIC, editor and KERNAL from $C000 (the I/O page at $E800 sits in front of it)
+pub struct Pet {
+    pub system: System,
+    pub keyboard: Rc<RefCell<PetKeyboard>>,
+    pub screen: Rc<RefCell<PetScreen>>,
+    pub pia2: Rc<RefCell<Pia>>,
+    pub via: Rc<RefCell<Via>>,
+}
+
+// Typing and drawing on the host terminal
+pub fn pet(rom: Vec<Data>) -> io::Result<Pet> {
+    Pet::with_terminal(rom, Box::new(StdioSerial::new()), Box::new(io::stdout()))
+}
+
+pub fn pet_rom_file(path: impl AsRef<Path>) -> io::Result<Pet> {
+    pet(fs::read(path)?)
+}
+
+impl Pet {
+    pub fn with_terminal(rom: Vec<Data>, keys: Box<dyn SerialBackend>, out: Box<dyn Write>) -> io::Result<Pet> {
+        if rom.len() < 6 || rom.len() > 0x4000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("a {} byte image isn't a ROM for $C000-$FFFF", rom.len()),
+            ));
+        }
+        let start = (0x10000 - rom.len()) as Address;
+        let reset = rom[rom.len() - 4] as Address | (rom[rom.len() - 3] as Address) << 8;
+        let mut system = System::with_memory(Memory::new(0x0000, 0x7fff));
+        let screen = system.add_peripheral(PetScreen::new().with_writer(out));
+        let keyboard = system.add_peripheral(PetKeyboard::new(keys));
+        let pia2 = system.add_peripheral(Pia::new(PIA2));
+        let via = system.add_peripheral(Via::new(VIA));
+        system.add_device(Rom::new(start, rom));
+        system.set_reset_vector(reset);
+        Ok(Pet {
+            system,
+            keyboard,
+            screen,
+            pia2,
+            via,
+        })
+    }
+}
@@ -1,44 +1,482 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use rust_6502_emulator::bus::{Address, Bus, SimpleBus};
-use rust_6502_emulator::memory::Memory;
-use rust_6502_emulator::processor::{create6502, ProcessorTrait};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use rust_6502_emulator::asm::Assembler;
+use rust_6502_emulator::bus::Address;
+use rust_6502_emulator::debugger::{parse_address, Debugger};
+use rust_6502_emulator::disasm::Disassembler;
+use rust_6502_emulator::loader::{hexdump, ihex};
+use rust_6502_emulator::logging;
+use rust_6502_emulator::machines;
+use rust_6502_emulator::monitor::Monitor;
+use rust_6502_emulator::system::{bench, Clock, System, Watchdog, WatchdogStop};
+use rust_6502_emulator::testsuite::coverage;
+use rust_6502_emulator::testsuite::decimal::DecimalTest;
+use rust_6502_emulator::testsuite::harte;
+use rust_6502_emulator::testsuite::klaus::FunctionalTest;
+use rust_6502_emulator::testsuite::nestest::{self, NestestCheck};
+#[cfg(feature = "config")]
+use rust_6502_emulator::system::MachineConfig;
+#[cfg(feature = "tui")]
+use rust_6502_emulator::debugger::run_tui;
+
+#[derive(Parser)]
+#[command(name = "sim6502", about = "A 6502 simulator")]
+struct Cli {
+    /// Log more: -v info, -vv debug, -vvv trace. RUST_LOG picks targets, e.g. sim6502::bus=trace
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    // Clap takes each command's help from its doc comment, hence /// below
+    /// Run a program until it breaks or traps, then print the registers. Exits 0 at BRK, 2 on
+    /// a trap, 3 at an unknown opcode, 4 out of cycles and 5 on timing out
+    Run {
+        /// A binary image, a .hex dump of "ADDR: BB BB .." lines, .ihex Intel HEX or .s/.asm
+        /// source, or - to read one from stdin
+        program: PathBuf,
+        #[command(flatten)]
+        load: Load,
+        /// Stop after this many cycles, e.g. 1e6
+        #[arg(long, value_parser = parse_cycles)]
+        max_cycles: Option<usize>,
+        /// Stop after this many seconds of wall clock time
+        #[arg(long)]
+        timeout: Option<f64>,
+        /// Print memory over start..end afterwards, e.g. 0000..000F, as often as needed
+        #[arg(long, value_parser = parse_range)]
+        dump: Vec<(Address, Address)>,
+        /// Write the registers, cycles, why the run stopped and the --dump ranges here as JSON
+        #[arg(long, value_name = "FILE")]
+        dump_state_json: Option<PathBuf>,
+        /// Print each device's reads, writes and cycles afterwards
+        #[arg(long)]
+        stats: bool,
+    },
+    /// Load a program and debug it at a command prompt
+    Debug {
+        program: PathBuf,
+        #[command(flatten)]
+        load: Load,
+        /// The full screen debugger (needs the tui feature)
+        #[arg(long)]
+        tui: bool,
+        /// Serve the Debug Adapter Protocol on stdin and stdout, for an editor to start as its
+        /// debug adapter
+        #[arg(long, conflicts_with = "tui")]
+        dap: bool,
+        /// A ca65 debug info file (ld65 --dbgfile), for source lines and labels
+        #[arg(long, value_name = "FILE")]
+        debug_info: Option<PathBuf>,
+    },
+    /// Disassemble a binary image
+    Disasm {
+        image: PathBuf,
+        /// Where the image is loaded
+        #[arg(long, value_parser = parse_addr, default_value = "0000")]
+        org: Address,
+        /// Show padding, unknown opcodes and cut off instructions as .byte data
+        #[arg(long)]
+        data: bool,
+    },
+    /// Assemble 6502 source into a binary image
+    Asm {
+        source: PathBuf,
+        /// The image, from the lowest address assembled to the highest
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Also write the symbols as a VICE label file, which the debugger's symbols command loads
+        #[arg(long)]
+        labels: Option<PathBuf>,
+    },
+    /// Run a standard workload flat out and report the emulated speed (build with --release)
+    Bench {
+        /// How long to run for
+        #[arg(long, default_value = "5")]
+        seconds: f64,
+    },
+    /// List the documented opcodes and how far the processor has got with each
+    Coverage {
+        /// Only the opcodes that are missing, misdecoded or have micro-ops still to do
+        #[arg(long)]
+        incomplete: bool,
+    },
+    /// Run Klaus Dormann's 6502_functional_test and report which test failed
+    FunctionalTest {
+        /// The 64K image, loaded at $0000
+        image: PathBuf,
+        /// Where the tests start
+        #[arg(long, value_parser = parse_addr, default_value = "0400")]
+        start: Address,
+        /// The trap reached once every test has passed, from the listing
+        #[arg(long, value_parser = parse_addr, default_value = "3469")]
+        success: Address,
+        /// Give up after this many cycles
+        #[arg(long, value_parser = parse_cycles, default_value = "2e8")]
+        max_cycles: usize,
+    },
+    /// Run Bruce Clark's decimal mode test and report the case that failed
+    DecimalTest {
+        image: PathBuf,
+        /// Where the image is loaded and starts
+        #[arg(long, value_parser = parse_addr, default_value = "0200")]
+        start: Address,
+        /// Where the program ends, if not at a BRK or a trap
+        #[arg(long, value_parser = parse_addr)]
+        end: Option<Address>,
+    },
+    /// Run an iNES ROM against a nestest.log style trace, stopping where they first differ
+    Nestest {
+        rom: PathBuf,
+        log: PathBuf,
+        /// Compare the CYC counts too
+        #[arg(long)]
+        cycles: bool,
+    },
+    /// Run Tom Harte's SingleStepTests, a directory of them or one opcode's file, and report each opcode
+    SingleStepTests {
+        path: PathBuf,
+        /// Only print the opcodes with failures
+        #[arg(long)]
+        failures: bool,
+    },
+    /// The Woz Monitor on the console, with an optional program loaded
+    Monitor {
+        program: Option<PathBuf>,
+        #[command(flatten)]
+        load: Load,
+    },
+    /// A machine built from a TOML description (needs the config feature)
+    Machine { description: PathBuf },
+    /// An Apple-1 with a monitor ROM such as Wozmon
+    Apple1 { rom: PathBuf },
+    /// An Atari 2600 cartridge on the 6507 skeleton
+    Atari2600 { cartridge: PathBuf },
+    /// Ben Eater's breadboard computer with its 32K ROM
+    BenEater { rom: PathBuf },
+    /// The C64 memory map, with basic, kernal and chargen ROMs in a directory
+    C64 { dir: PathBuf },
+    /// A PET 2001 with its 16K ROM image
+    Pet { rom: PathBuf },
+}
+
+#[derive(clap::Args)]
+struct Load {
+    /// Where a binary image is loaded
+    #[arg(long, value_parser = parse_addr, default_value = "0200")]
+    org: Address,
+    /// What the program is, by default going by its extension (binary for stdin)
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// Where execution starts, by default the first address loaded
+    #[arg(long, value_parser = parse_addr)]
+    pc: Option<Address>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Binary,
+    Hexdump,
+    Ihex,
+    Asm,
+}
 
 fn main() {
-    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
-
-    let processor = Rc::new(RefCell::new(create6502()));
-    let memory: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new(0x000, 0xffff)));
-
-    // write the boot vector
-    memory
-        .borrow_mut()
-        .write(0xffc, vec![0x00, 0x02]); // , 0xea, 0x4c, 0xfe, 0x0f, 0xfe, 0x0f]);
-    // write a program starting at boot vector
-    memory
-        .borrow_mut()
-        .write(0x0200, vec![
-            0xea, // NOP
-            0xa2, // LDX #
-            0x05,
-            0xa9, // LDA #
-            0xaa,
-            0x95, // STA zp,X
-            0x01,
-            0xea,
-        ]);
-    bus.borrow_mut()
-        .register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
-        //   bus.borrow_mut().register_device(&processor.borrow().as_cloned_bus_device(Rc::clone(&processor)));
-
-    let break_address: Address = 0x0208;
-    loop {
-        let address = processor.borrow_mut().tick(Rc::clone(&bus));
-        if address.1 {
-            break
-        }
-    }
-
-    memory.borrow().dump_memory(0x0000, 0x0010);
+    let cli = Cli::parse();
+    let _ = logging::init(cli.verbose);
+    let result = match cli.command {
+        Commands::Run { program, load, max_cycles, timeout, dump, dump_state_json, stats } => {
+            run(&program, &load, max_cycles, timeout, &dump, dump_state_json.as_deref(), stats)
+        }
+        Commands::Debug { program, load, tui, dap, debug_info } => debug(&program, &load, tui, dap, debug_info.as_deref()),
+        Commands::Disasm { image, org, data } => disasm(&image, org, data),
+        Commands::Asm { source, output, labels } => asm(&source, &output, labels.as_deref()),
+        Commands::Bench { seconds } => {
+            println!("{}", bench(Duration::from_secs_f64(seconds.max(0.0))));
+            Ok(())
+        }
+        Commands::Coverage { incomplete } => {
+            let report = coverage::coverage();
+            for opcode in report.iter().filter(|c| !incomplete || !c.is_complete()) {
+                println!("{}", opcode);
+            }
+            println!("{}", coverage::summary(&report));
+            Ok(())
+        }
+        Commands::FunctionalTest { image, start, success, max_cycles } => functional_test(&image, start, success, max_cycles),
+        Commands::DecimalTest { image, start, end } => decimal_test(&image, start, end),
+        Commands::Nestest { rom, log, cycles } => nestest(&rom, &log, cycles),
+        Commands::SingleStepTests { path, failures } => single_step_tests(&path, failures),
+        Commands::Monitor { program, load } => monitor(program.as_deref(), &load),
+        Commands::Machine { description } => run_machine(&description),
+        Commands::Apple1 { rom } => run_preset(machines::apple1_rom_file(rom).map(|m| m.system)),
+        Commands::Atari2600 { cartridge } => run_preset(machines::atari2600_rom_file(cartridge).map(|m| m.system)),
+        Commands::BenEater { rom } => run_preset(machines::ben_eater_rom_file(rom).map(|m| m.system)),
+        Commands::C64 { dir } => {
+            let machine = machines::c64_rom_files(dir.join("basic"), dir.join("kernal"), dir.join("chargen"));
+            run_preset(machine.map(|m| m.system))
+        }
+        Commands::Pet { rom } => run_preset(machines::pet_rom_file(rom).map(|m| m.system)),
+    };
+    if let Err(e) = result {
+        eprintln!("sim6502: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_addr(s: &str) -> Result<Address, String> {
+    parse_address(s).map_err(|e| e.to_string())
+}
+
+fn parse_range(s: &str) -> Result<(Address, Address), String> {
+    let (start, end) = s.split_once("..").ok_or_else(|| format!("{} isn't start..end", s))?;
+    Ok((parse_addr(start)?, parse_addr(end)?))
+}
+
+// Whole numbers, or like 1e6
+fn parse_cycles(s: &str) -> Result<usize, String> {
+    s.parse::<usize>()
+        .or_else(|_| s.parse::<f64>().map(|n| n as usize))
+        .map_err(|_| format!("{} isn't a number of cycles", s))
+}
+
+fn format_for(path: &Path) -> Format {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or("") {
+        "hex" => Format::Hexdump,
+        "ihex" | "ihx" => Format::Ihex,
+        "s" | "asm" => Format::Asm,
+        _ => Format::Binary,
+    }
+}
+
+// A 64K System with the program loaded and the reset vector pointing at it. A path of - reads
+// the program from stdin
+fn load_program(path: &Path, load: &Load) -> io::Result<System> {
+    let stdin = path == Path::new("-");
+    let format = load.format.unwrap_or(if stdin { Format::Binary } else { format_for(path) });
+    let blocks = if format == Format::Asm && !stdin {
+        // the assembler reads the file itself, to find includes next to it
+        Assembler::new().assemble_file(path)?.get_segments().to_vec()
+    } else {
+        let mut bytes = vec![];
+        if stdin {
+            io::stdin().lock().read_to_end(&mut bytes)?;
+        } else {
+            bytes = fs::read(path)?;
+        }
+        let text = |bytes| String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        match format {
+            Format::Binary => vec![(load.org, bytes)],
+            Format::Hexdump => hexdump::parse(&text(bytes)?, load.org)?,
+            Format::Ihex => ihex::parse(&text(bytes)?)?,
+            Format::Asm => Assembler::new().assemble(&text(bytes)?)?.get_segments().to_vec(),
+        }
+    };
+    let mut system = System::new();
+    for (address, data) in &blocks {
+        system.load(*address, data);
+    }
+    let start = blocks.first().map_or(load.org, |(address, _)| *address);
+    system.set_reset_vector(load.pc.unwrap_or(start));
+    Ok(system)
+}
+
+fn run(
+    path: &Path,
+    load: &Load,
+    max_cycles: Option<usize>,
+    timeout: Option<f64>,
+    dump: &[(Address, Address)],
+    dump_state_json: Option<&Path>,
+    stats: bool,
+) -> io::Result<()> {
+    let mut system = load_program(path, load)?;
+    let mut watchdog = Watchdog::new();
+    if let Some(cycles) = max_cycles {
+        watchdog = watchdog.with_max_cycles(cycles);
+    }
+    if let Some(seconds) = timeout {
+        watchdog = watchdog.with_timeout(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    let report = watchdog.run(&mut system);
+    println!("{}", system.get_registers());
+    println!("{}", report);
+    for &(start, end) in dump {
+        print!("{}", hexdump::dump(&*system.get_bus().borrow(), start, end));
+    }
+    if stats {
+        for device in system.stats() {
+            println!("{}", device);
+        }
+    }
+    if let Some(path) = dump_state_json {
+        fs::write(path, report.state_json(&system, dump))?;
+    }
+    if report.stop != WatchdogStop::Break {
+        io::stdout().flush()?;
+        std::process::exit(report.stop.exit_code());
+    }
+    Ok(())
+}
+
+fn debug(path: &Path, load: &Load, tui: bool, dap: bool, debug_info: Option<&Path>) -> io::Result<()> {
+    let system = load_program(path, load)?;
+    let mut debugger = Debugger::new(&system.get_processor(), &system.get_bus());
+    debugger.attach_peripherals(&system.get_peripherals());
+    if let Some(debug_info) = debug_info {
+        debugger
+            .load_debug_info_file(&debug_info.to_string_lossy())
+            .map_err(|e| io::Error::other(e.to_string()))?;
+    }
+    if dap {
+        return debugger.serve_dap_stream(io::stdin(), io::stdout());
+    }
+    if tui {
+        return run_debugger_tui(&mut debugger);
+    }
+    print!("> ");
+    io::stdout().flush()?;
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim() == "quit" {
+            break;
+        }
+        match debugger.execute(&line) {
+            Ok(out) => print!("{}", out),
+            Err(e) => println!("{}", e),
+        }
+        print!("> ");
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+fn disasm(path: &Path, org: Address, data: bool) -> io::Result<()> {
+    let image = fs::read(path)?;
+    for line in Disassembler::new().with_data_heuristics(data).disassemble_bytes(&image, org) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn asm(path: &Path, output: &Path, labels: Option<&Path>) -> io::Result<()> {
+    let assembly = Assembler::new().assemble_file(path)?;
+    let image = assembly.to_binary();
+    fs::write(output, &image)?;
+    if let Some(labels) = labels {
+        fs::write(labels, assembly.label_file())?;
+    }
+    println!("${:04X}-${:04X}, {} bytes", assembly.get_origin(), assembly.get_origin() as usize + image.len().max(1) - 1, image.len());
+    Ok(())
+}
+
+fn functional_test(path: &Path, start: Address, success: Address, max_cycles: usize) -> io::Result<()> {
+    let test = FunctionalTest::new().with_start(start).with_success(success).with_max_cycles(max_cycles);
+    let report = test.run_file(path)?;
+    println!("{}", report);
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn decimal_test(path: &Path, start: Address, end: Option<Address>) -> io::Result<()> {
+    let mut test = DecimalTest::new().with_load(start).with_start(start);
+    if let Some(end) = end {
+        test = test.with_end(end);
+    }
+    let report = test.run_file(path)?;
+    println!("{}", report);
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn nestest(rom: &Path, log: &Path, cycles: bool) -> io::Result<()> {
+    let mut system = System::new();
+    nestest::load_ines(&mut system, &fs::read(rom)?)?;
+    match NestestCheck::from_log_file(log)?.with_cycles(cycles).run(&mut system) {
+        Ok(lines) => println!("all {} lines match", lines),
+        Err(divergence) => {
+            print!("{}", divergence);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn single_step_tests(path: &Path, failures: bool) -> io::Result<()> {
+    let reports = if path.is_dir() { harte::run_dir(path)? } else { vec![harte::run_file(path)?] };
+    for report in reports.iter().filter(|r| !failures || r.failed() > 0) {
+        println!("{}", report);
+    }
+    let implemented = reports.iter().filter(|r| r.is_implemented()).count();
+    let failed = reports.iter().filter(|r| r.failed() > 0).count();
+    println!("{} opcodes, {} implemented, {} with failures", reports.len(), implemented, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// The Woz Monitor on the console. R runs until the program breaks
+fn monitor(path: Option<&Path>, load: &Load) -> io::Result<()> {
+    let mut system = match path {
+        Some(path) => load_program(path, load)?,
+        None => System::new(),
+    };
+    let mut monitor = Monitor::new();
+    println!("\\");
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let output = monitor.execute(&line, &*system.get_bus().borrow());
+        print!("{}", output.text);
+        if let Some(address) = output.run {
+            let mut registers = system.get_registers();
+            registers.pc = address;
+            system.set_registers(&registers);
+            system.run_until_break();
+        }
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+// Runs a preset machine at its real speed until it breaks
+fn run_preset(system: io::Result<System>) -> io::Result<()> {
+    let mut system = system?;
+    let mut clock = Clock::new(system.get_clock_hz());
+    while !system.is_halted() {
+        system.run_clocked(10_000, &mut clock);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+fn run_debugger_tui(debugger: &mut Debugger) -> io::Result<()> {
+    run_tui(debugger)
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_debugger_tui(_debugger: &mut Debugger) -> io::Result<()> {
+    Err(io::Error::other("--tui needs the tui feature: cargo run --features tui -- debug --tui program.bin"))
+}
+
+#[cfg(feature = "config")]
+fn run_machine(path: &Path) -> io::Result<()> {
+    run_preset(MachineConfig::load(path).and_then(|config| config.build()))
+}
+
+#[cfg(not(feature = "config"))]
+fn run_machine(_path: &Path) -> io::Result<()> {
+    Err(io::Error::other("machine needs the config feature: cargo run --features config -- machine machine.toml"))
 }
@@ -1,33 +1,17 @@
-use crate::bus::{Address, BusDevice, Data};
+use crate::bus::{Address, BusDevice, Data, DebugView};
 
-use core::hash::{BuildHasherDefault, Hasher};
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-
-#[derive(Debug, Clone, Copy, Default)]
-pub struct IdentityHasher(Address);
-
-impl Hasher for IdentityHasher {
-    fn finish(&self) -> u64 {
-        self.0 as u64
-    }
-
-    fn write(&mut self, _bytes: &[u8]) {
-        panic!("Only implemented for u16 (Address)");
-    }
-
-    fn write_u16(&mut self, i: u16) {
-        self.0 = i;
-    }
-}
-
-type BuildIdentityHasher = BuildHasherDefault<IdentityHasher>;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::cell::{Cell, RefCell};
 
+// RAM from lower_bound to upper_bound. The bytes are Cells shared with the bus, which reads and
+// writes the pages nothing else is on without going through the device
 pub struct Memory {
     pub lower_bound: Address,
     pub upper_bound: Address,
-    pub mem: HashMap<Address, Data, BuildIdentityHasher>,
+    mem: Rc<[Cell<Data>]>,
 }
 
 impl Memory {
@@ -35,7 +19,7 @@ impl Memory {
         let mut addr = start;
         data.iter().for_each(|d| {
             self.do_write(addr, *d);
-            addr += 1;
+            addr = addr.wrapping_add(1);
         })
     }
 
@@ -48,25 +32,26 @@ impl Memory {
         Memory {
             lower_bound: start,
             upper_bound: end,
-            mem: Default::default(),
+            mem: (start..=end).map(|_| Cell::new(0)).collect(),
         }
     }
 
-    pub fn dump_memory(&self, start: Address, end: Address) {
-        for i in start .. end {
-            println!("{:#06x}: {:#04x}", i, self.do_read(i));
-        }
+    // One "address: byte" line for each of start..end
+    pub fn dump_memory(&self, start: Address, end: Address) -> String {
+        (start..end).map(|i| format!("{:#06x}: {:#04x}\n", i, self.do_read(i))).collect()
     }
 }
 
 impl BusDevice for Memory {
     fn do_read(&self, address: Address) -> Data {
-        let x = self.mem.get(&(address - self.lower_bound)).unwrap_or(&0);
-        *x
+        // outside the range, as for dump_memory, there's nothing
+        self.mem.get(address.wrapping_sub(self.lower_bound) as usize).map_or(0, Cell::get)
     }
 
     fn do_write(&mut self, address: Address, data: Data) {
-        self.mem.insert(address - self.lower_bound, data);
+        if let Some(cell) = self.mem.get(address.wrapping_sub(self.lower_bound) as usize) {
+            cell.set(data);
+        }
     }
 
     fn is_readable_for(&self, address: Address) -> bool {
@@ -76,4 +61,168 @@ impl BusDevice for Memory {
     fn is_writable_for(&self, address: Address) -> bool {
         address >= self.lower_bound && address <= self.upper_bound
     }
+
+    fn save_state(&self) -> Option<Vec<Data>> {
+        Some(self.mem.iter().map(Cell::get).collect())
+    }
+
+    // in place, as the bus holds on to the bytes
+    fn load_state(&mut self, state: &[Data]) {
+        for (offset, cell) in self.mem.iter().enumerate() {
+            cell.set(state.get(offset).copied().unwrap_or(0));
+        }
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+
+    fn get_ram(&self) -> Option<(Address, Rc<[Cell<Data>]>)> {
+        Some((self.lower_bound, Rc::clone(&self.mem)))
+    }
+}
+
+impl DebugView for Memory {
+    fn get_name(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("range".to_string(), format!("${:04X}-${:04X}", self.lower_bound, self.upper_bound)),
+            ("bytes set".to_string(), self.mem.iter().filter(|d| d.get() != 0).count().to_string()),
+        ]
+    }
+}
+
+// Read only memory holding an image from start, a Vec or, without a heap, a &'static [Data] in
+// flash. Writes are ignored, so RAM registered behind it still gets them
+pub struct Rom<D: AsRef<[Data]> = Vec<Data>> {
+    start: Address,
+    data: D,
+}
+
+impl<D: AsRef<[Data]>> Rom<D> {
+    pub fn new(start: Address, data: D) -> Rom<D> {
+        Rom { start, data }
+    }
+
+    fn contains(&self, address: Address) -> bool {
+        address >= self.start && ((address - self.start) as usize) < self.data.as_ref().len()
+    }
+}
+
+impl<D: AsRef<[Data]>> BusDevice for Rom<D> {
+    fn do_read(&self, address: Address) -> Data {
+        self.data.as_ref()[(address - self.start) as usize]
+    }
+
+    fn do_write(&mut self, _address: Address, _data: Data) {}
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.contains(address)
+    }
+
+    fn is_writable_for(&self, _address: Address) -> bool {
+        false
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+}
+
+impl<D: AsRef<[Data]>> DebugView for Rom<D> {
+    fn get_name(&self) -> String {
+        "rom".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let end = self.start as usize + self.data.as_ref().len().max(1) - 1;
+        vec![("range".to_string(), format!("${:04X}-${:04X}", self.start, end))]
+    }
+}
+
+// SIZE bytes of RAM from start in an array rather than on the heap like Memory, so it can be a static on a microcontroller. Every byte is there from the start, zeroed
+pub struct ArrayMemory<const SIZE: usize> {
+    start: Address,
+    data: [Data; SIZE],
+}
+
+impl<const SIZE: usize> ArrayMemory<SIZE> {
+    pub const fn new(start: Address) -> ArrayMemory<SIZE> {
+        assert!(start as usize + SIZE <= 0x10000, "memory runs past $FFFF");
+        ArrayMemory { start, data: [0; SIZE] }
+    }
+
+    // Copies data in from start, as far as the end of the array
+    pub fn write(&mut self, start: Address, data: &[Data]) {
+        let offset = start.wrapping_sub(self.start) as usize;
+        if offset < SIZE {
+            let len = data.len().min(SIZE - offset);
+            self.data[offset..offset + len].copy_from_slice(&data[..len]);
+        }
+    }
+
+    pub fn as_slice(&self) -> &[Data] {
+        &self.data
+    }
+
+    fn contains(&self, address: Address) -> bool {
+        address >= self.start && ((address - self.start) as usize) < SIZE
+    }
+}
+
+impl<const SIZE: usize> BusDevice for ArrayMemory<SIZE> {
+    fn do_read(&self, address: Address) -> Data {
+        self.data[(address - self.start) as usize]
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        self.data[(address - self.start) as usize] = data;
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.contains(address)
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        self.contains(address)
+    }
+
+    fn save_state(&self) -> Option<Vec<Data>> {
+        Some(self.data.to_vec())
+    }
+
+    fn load_state(&mut self, state: &[Data]) {
+        let len = state.len().min(SIZE);
+        self.data[..len].copy_from_slice(&state[..len]);
+    }
+
+    fn debug_view(&self) -> Option<&dyn DebugView> {
+        Some(self)
+    }
+
+    fn has_fixed_mapping(&self) -> bool {
+        true
+    }
+}
+
+impl<const SIZE: usize> DebugView for ArrayMemory<SIZE> {
+    fn get_name(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn get_fields(&self) -> Vec<(String, String)> {
+        let end = self.start as usize + SIZE.max(1) - 1;
+        vec![("range".to_string(), format!("${:04X}-${:04X}", self.start, end))]
+    }
 }
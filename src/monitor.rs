@@ -0,0 +1,93 @@
+use std::fmt::Write;
+
+use crate::bus::{Address, Bus};
+
+// What the last number on a line means, set by '.' and ':' as in the Apple 1 Woz Monitor
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Mode {
+    Examine,
+    Block,
+    Store,
+}
+
+// A Woz Monitor personality:
+//   200        examine one address       0200: A9
+//   200.20F    examine a block, 8 bytes a line
+//   200: A9 00 deposit, printing the old byte first; ": 4C" keeps going from there
+//   200R       run from an address ("R" alone runs from the last one examined)
+// Several commands can share a line, e.g. "200.207 300: EA 300R"
+#[derive(Default)]
+pub struct Monitor {
+    examine: Address,
+    store: Address,
+}
+
+// Text to show and, for "R", where to start running
+#[derive(PartialEq, Debug)]
+pub struct MonitorOutput {
+    pub text: String,
+    pub run: Option<Address>,
+}
+
+impl Monitor {
+    pub fn new() -> Monitor {
+        Monitor::default()
+    }
+
+    pub fn execute(&mut self, line: &str, bus: &dyn Bus) -> MonitorOutput {
+        let mut out = MonitorOutput { text: String::new(), run: None };
+        let mut mode = Mode::Examine;
+        let mut chars = line.trim().chars().peekable();
+        while let Some(c) = chars.next() {
+            match c.to_ascii_uppercase() {
+                '.' => mode = Mode::Block,
+                ':' => mode = Mode::Store,
+                'R' => {
+                    out.run = Some(self.examine);
+                    break;
+                }
+                c if c.is_ascii_hexdigit() => {
+                    let mut value = c.to_digit(16).unwrap() as Address;
+                    while let Some(d) = chars.peek().and_then(|d| d.to_digit(16)) {
+                        value = value.wrapping_shl(4) | d as Address;
+                        chars.next();
+                    }
+                    self.number(value, mode, bus, &mut out.text);
+                }
+                _ => {}
+            }
+        }
+        if !out.text.is_empty() {
+            out.text.push('\n');
+        }
+        out
+    }
+
+    fn number(&mut self, value: Address, mode: Mode, bus: &dyn Bus, text: &mut String) {
+        match mode {
+            Mode::Store => {
+                bus.write(self.store, value as u8);
+                self.store = self.store.wrapping_add(1);
+            }
+            Mode::Examine => {
+                self.examine = value;
+                self.store = value;
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                write!(text, "{:04X}: {:02X}", value, bus.read(value)).unwrap();
+            }
+            Mode::Block => {
+                let mut address = self.examine;
+                while address < value {
+                    address += 1;
+                    if address & 7 == 0 {
+                        write!(text, "\n{:04X}:", address).unwrap();
+                    }
+                    write!(text, " {:02X}", bus.read(address)).unwrap();
+                }
+                self.examine = address;
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use libloading::Library;
+
+use crate::bus::{Address, BusDevice, Data};
+use crate::devices::Peripheral;
+
+// Devices from shared objects, so a peripheral can ship on its own without a fork of the
+// emulator. include/sim6502_plugin.h declares the ABI for C. A plugin exports
+//   const Sim6502Plugin *sim6502_plugin(void);
+// returning a table that lives as long as the library, with abi_version set to
+// SIM6502_PLUGIN_ABI_VERSION. create makes one device at a base address from an options string
+// (the machine description's, or empty) and returns an opaque pointer, or NULL on failure;
+// the other entries get that pointer back until destroy. tick, reset and irq_asserted may be
+// NULL for devices that don't need them. A device is only used from one thread at a time
+pub const SIM6502_PLUGIN_ABI_VERSION: u32 = 1;
+
+// The symbol a plugin exports
+pub const SIM6502_PLUGIN_ENTRY: &str = "sim6502_plugin";
+
+#[repr(C)]
+pub struct Sim6502Plugin {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub create: unsafe extern "C" fn(base: u16, options: *const c_char) -> *mut c_void,
+    pub destroy: unsafe extern "C" fn(device: *mut c_void),
+    // whether the device answers reads / writes at address
+    pub is_readable_for: unsafe extern "C" fn(device: *mut c_void, address: u16) -> bool,
+    pub is_writable_for: unsafe extern "C" fn(device: *mut c_void, address: u16) -> bool,
+    pub read: unsafe extern "C" fn(device: *mut c_void, address: u16) -> u8,
+    pub write: unsafe extern "C" fn(device: *mut c_void, address: u16, data: u8),
+    pub tick: Option<unsafe extern "C" fn(device: *mut c_void, cycles: u32)>,
+    pub reset: Option<unsafe extern "C" fn(device: *mut c_void)>,
+    pub irq_asserted: Option<unsafe extern "C" fn(device: *mut c_void) -> bool>,
+}
+
+// The table is only read, and name points at a constant string, so plugins can keep it in a static
+unsafe impl Sync for Sim6502Plugin {}
+
+type Entry = unsafe extern "C" fn() -> *const Sim6502Plugin;
+
+#[derive(Debug)]
+pub enum PluginError {
+    Load(PathBuf, String),
+    NoEntry(PathBuf),
+    AbiVersion { found: u32, expected: u32 },
+    // create returned NULL
+    Create(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Load(path, e) => write!(f, "can't load plugin {}: {}", path.display(), e),
+            PluginError::NoEntry(path) => write!(f, "{} doesn't export {}", path.display(), SIM6502_PLUGIN_ENTRY),
+            PluginError::AbiVersion { found, expected } => {
+                write!(f, "plugin ABI version {}, the emulator wants {}", found, expected)
+            }
+            PluginError::Create(name) => write!(f, "plugin {} couldn't make a device", name),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+// A loaded plugin, which makes as many devices as asked for. The library stays loaded while
+// the plugin or any of its devices is alive
+pub struct Plugin {
+    table: *const Sim6502Plugin,
+    _library: Option<Rc<Library>>,
+}
+
+impl Plugin {
+    pub fn load(path: impl AsRef<Path>) -> Result<Plugin, PluginError> {
+        let path = path.as_ref();
+        // loading runs the library's initialisers, which is as much as trusting it at all
+        let library = unsafe { Library::new(path) }.map_err(|e| PluginError::Load(path.to_path_buf(), e.to_string()))?;
+        let table = unsafe {
+            let entry = library
+                .get::<Entry>(SIM6502_PLUGIN_ENTRY.as_bytes())
+                .map_err(|_| PluginError::NoEntry(path.to_path_buf()))?;
+            entry()
+        };
+        if table.is_null() {
+            return Err(PluginError::NoEntry(path.to_path_buf()));
+        }
+        let plugin = Plugin {
+            table,
+            _library: Some(Rc::new(library)),
+        };
+        plugin.check_version()?;
+        log::info!("loaded plugin {} from {}", plugin.get_name(), path.display());
+        Ok(plugin)
+    }
+
+    // A plugin linked into the program rather than loaded, e.g. to test one
+    pub fn from_table(table: &'static Sim6502Plugin) -> Result<Plugin, PluginError> {
+        let plugin = Plugin {
+            table,
+            _library: None,
+        };
+        plugin.check_version()?;
+        Ok(plugin)
+    }
+
+    fn check_version(&self) -> Result<(), PluginError> {
+        match self.table().abi_version {
+            SIM6502_PLUGIN_ABI_VERSION => Ok(()),
+            found => Err(PluginError::AbiVersion {
+                found,
+                expected: SIM6502_PLUGIN_ABI_VERSION,
+            }),
+        }
+    }
+
+    fn table(&self) -> &Sim6502Plugin {
+        unsafe { &*self.table }
+    }
+
+    pub fn get_name(&self) -> String {
+        match self.table().name.is_null() {
+            true => String::from("(unnamed)"),
+            false => unsafe { CStr::from_ptr(self.table().name) }.to_string_lossy().into_owned(),
+        }
+    }
+
+    pub fn create(&self, base: Address, options: &str) -> Result<PluginDevice, PluginError> {
+        // an options string with a NUL in it is cut there, as C would read it anyway
+        let options = CString::new(options.split('\0').next().unwrap_or_default()).unwrap_or_default();
+        let device = unsafe { (self.table().create)(base, options.as_ptr()) };
+        if device.is_null() {
+            return Err(PluginError::Create(self.get_name()));
+        }
+        Ok(PluginDevice {
+            table: self.table,
+            device,
+            _library: self._library.clone(),
+        })
+    }
+}
+
+// One device made by a plugin, destroyed when dropped
+pub struct PluginDevice {
+    table: *const Sim6502Plugin,
+    device: *mut c_void,
+    _library: Option<Rc<Library>>,
+}
+
+impl PluginDevice {
+    fn table(&self) -> &Sim6502Plugin {
+        unsafe { &*self.table }
+    }
+}
+
+impl Drop for PluginDevice {
+    fn drop(&mut self) {
+        unsafe { (self.table().destroy)(self.device) };
+    }
+}
+
+impl BusDevice for PluginDevice {
+    // the plugin ABI has no side-effect-free read, so the debugger's peeks come here too
+    fn do_read(&self, address: Address) -> Data {
+        unsafe { (self.table().read)(self.device, address) }
+    }
+
+    fn do_write(&mut self, address: Address, data: Data) {
+        unsafe { (self.table().write)(self.device, address, data) }
+    }
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        unsafe { (self.table().is_readable_for)(self.device, address) }
+    }
+
+    fn is_writable_for(&self, address: Address) -> bool {
+        unsafe { (self.table().is_writable_for)(self.device, address) }
+    }
+}
+
+impl Peripheral for PluginDevice {
+    fn tick(&mut self, cycles: usize) {
+        if let Some(tick) = self.table().tick {
+            unsafe { tick(self.device, cycles.min(u32::MAX as usize) as u32) }
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(reset) = self.table().reset {
+            unsafe { reset(self.device) }
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        match self.table().irq_asserted {
+            Some(irq_asserted) => unsafe { irq_asserted(self.device) },
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,11 @@
+// The types most programs need, from one place: use rust_6502_emulator::prelude::*;
+// Each is still in its own module for anything the prelude leaves out
+pub use crate::bus::{Address, Bus, BusDevice, Data, PagedBus, SimpleBus};
+pub use crate::memory::{ArrayMemory, Memory, Rom};
+pub use crate::processor::{create6502, Proc6502, ProcessorTrait, Registers, BOOT_VECTOR};
+#[cfg(feature = "std")]
+pub use crate::devices::{Peripheral, Peripherals};
+#[cfg(feature = "std")]
+pub use crate::system::{Clock, Runner, Speed, System, SystemBuilder};
+#[cfg(feature = "debugger")]
+pub use crate::debugger::{DebugEvent, Debugger, RunMode};
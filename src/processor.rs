@@ -1,24 +1,96 @@
-use std::cell::RefCell;
-use std::collections::{HashMap, LinkedList};
-use std::fmt;
-use std::rc::Rc;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::Index;
+
+use arrayvec::ArrayVec;
 
 use crate::bus::{Address, Bus, BusDevice, Data};
+use crate::logging::INSTRUCTION;
 use crate::processor::AddressRegister::*;
 use crate::processor::AddressingMode::*;
 use crate::processor::DataRegister::*;
 use crate::processor::Function::*;
 use crate::processor::InternalOperations::*;
 
+#[cfg(feature = "blocks")]
+mod blocks;
+#[cfg(feature = "closure_cache")]
+mod closure_cache;
+
+// A processor has no interrupt inputs. Peripherals' IRQ lines are exposed through
+// Peripheral::irq_asserted and System::irq_asserted, and a watchdog's reset and NMI through its
+// own methods, but none of them is delivered here: whatever wants one acted on polls it between
+// steps
 pub trait ProcessorTrait: BusDevice {
-    fn tick(&mut self, bus: Rc<RefCell<dyn Bus>>) -> (Address, bool);
+    fn tick(&mut self, bus: &dyn Bus) -> (Address, bool);
 
     fn reset(&mut self);
 
     fn get_user_cycles(&self) -> usize;
+
+    fn get_total_cycles(&self) -> usize;
+
+    fn get_registers(&self) -> Registers;
+
+    fn set_registers(&mut self, registers: &Registers);
+
+    // true when the next tick will fetch a new opcode
+    fn is_at_instruction_boundary(&self) -> bool;
+
+    // Drops anything cached about the instruction bytes at address, for a write to it that the
+    // processor didn't make itself, e.g. from System::write or the debugger
+    fn invalidate(&mut self, address: Address);
+
+    fn snapshot(&self) -> ProcessorSnapshot;
+
+    fn restore(&mut self, snapshot: &ProcessorSnapshot);
+}
+
+// The programmer visible registers
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Registers {
+    pub pc: Address,
+    pub a: Data,
+    pub x: Data,
+    pub y: Data,
+    pub status: Data,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PC={:04X} A={:02X} X={:02X} Y={:02X} P={:02X}", self.pc, self.a, self.x, self.y, self.status)
+    }
+}
+
+// Everything needed to put a processor back exactly where it was, including a partially executed instruction
+#[derive(Clone)]
+pub struct ProcessorSnapshot {
+    pc: Address,
+    x: Data,
+    y: Data,
+    a: Data,
+    internal_address: Address,
+    internal_operand: Data,
+    at_break: bool,
+    overflow: bool,
+    carry: bool,
+    status: Data,
+    operation_stream: OperationQueue,
+    total_cycles: usize,
+}
+
+impl ProcessorSnapshot {
+    pub fn get_pc(&self) -> Address {
+        self.pc
+    }
+
+    pub fn get_total_cycles(&self) -> usize {
+        self.total_cycles
+    }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum DataRegister {
     X,
     Y,
@@ -26,13 +98,13 @@ pub enum DataRegister {
     InternalOperand,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum AddressRegister {
     PC,
     InternalAddress,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Function {
     OR,
     AND,
@@ -42,14 +114,35 @@ pub enum Function {
     SubtractWithBorrow
 }
 
-// This is the thing that represents work ending in a clock tick
-#[derive(Clone)]
+// This is the thing that represents work ending in a clock tick. The operations are a static
+// slice, so a cycle is a pointer to copy and tick() never allocates to queue one
+#[derive(Clone, Copy)]
 pub struct SingleCycleOperation {
-    internal_operations: Vec<InternalOperations>
+    internal_operations: &'static [InternalOperations],
+    // the operations' handlers, in the same order
+    #[cfg(feature = "threaded")]
+    handlers: [Handler; MAX_CYCLE_OPERATIONS],
+}
+
+// The most cycles queued at once: the boot sequence's one, or an instruction's fetch cycles, the
+// two of indirect at most
+const QUEUE_CAPACITY: usize = 4;
+
+// Cycles waiting to run, kept inline so neither the processor nor a snapshot points at the heap
+type OperationQueue = ArrayVec<SingleCycleOperation, QUEUE_CAPACITY>;
+
+// The most operations in a cycle, the boot sequence's and absolute indexed's three
+#[cfg(feature = "threaded")]
+const MAX_CYCLE_OPERATIONS: usize = 3;
+
+impl SingleCycleOperation {
+    pub fn get_operations(&self) -> &'static [InternalOperations] {
+        self.internal_operations
+    }
 }
 
 // These are the definitions of little micro operations
-#[derive(PartialEq, Debug,  Clone)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum InternalOperations {
     NOP,
     BRK,
@@ -89,6 +182,18 @@ pub enum InternalOperations {
     AluIncr,
 }
 
+impl InternalOperations {
+    // false for the operations tick() still has as todo!() or an empty placeholder
+    pub fn is_implemented(&self) -> bool {
+        match self {
+            CompareToRegister { .. } => false,
+            ComputeAndStore { func, .. } => *func == AddWithCarry,
+            ReadAddressLo | ReadAddressHi | IncrementPCBySignedOperand | ReadFromAccumulator | AddIndexLo | AluIncr => false,
+            _ => true,
+        }
+    }
+}
+
 /**
 
 A       Accumulator             OPC A           operand is AC (implied single byte instruction)
@@ -107,7 +212,7 @@ zpg,Y   zeropage, Y-indexed     OPC $LL,Y       operand is zeropage address; eff
 
 **/
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum AddressingMode {
     Accumulator,
     Absolute,
@@ -122,19 +227,78 @@ pub enum AddressingMode {
     ZeroPageIndexed { reg: DataRegister },
 }
 
+impl AddressingMode {
+    // bytes following the opcode
+    pub fn operand_length(&self) -> usize {
+        match self {
+            Accumulator | Implied => 0,
+            Absolute | AbsIndexed { .. } | Indirect => 2,
+            Immediate | IndexedIndirect | IndirectIndexed | Relative | ZeroPage | ZeroPageIndexed { .. } => 1,
+        }
+    }
+}
+
 impl fmt::Display for AddressingMode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
-// Implementation of an instruction. addressing mode specific
+// Implementation of an instruction. addressing mode specific. Everything in it is static, so the
+// table is built at compile time and decoding never touches the heap
 pub struct Instruction {
-    mnemonic: String,
-    operations: Vec<SingleCycleOperation>,
+    mnemonic: &'static str,
+    operations: &'static [SingleCycleOperation],
+    // what the instruction does once its operand is fetched, not queued after the fetches yet
+    execute: &'static [InternalOperations],
     addressing: AddressingMode
 }
 
+impl Instruction {
+    pub fn get_mnemonic(&self) -> &'static str {
+        self.mnemonic
+    }
+
+    pub fn get_addressing(&self) -> &AddressingMode {
+        &self.addressing
+    }
+
+    // The cycles that fetch the operand
+    pub fn get_fetch_operations(&self) -> &'static [SingleCycleOperation] {
+        self.operations
+    }
+
+    pub fn get_execute_operations(&self) -> &'static [InternalOperations] {
+        self.execute
+    }
+
+    // Whether the instruction can send the pc anywhere but the next instruction
+    pub fn changes_flow(&self) -> bool {
+        self.addressing == Relative || matches!(self.mnemonic, "BRK" | "JMP" | "JSR" | "RTI" | "RTS")
+    }
+}
+
+// An instruction decoded at an address: the opcode it was decoded from, one past its last byte,
+// whether it can change the flow and its fetch cycles
+#[derive(Clone, Copy)]
+struct Decoded {
+    pc: Address,
+    opcode: Data,
+    end: Address,
+    #[cfg(feature = "blocks")]
+    changes_flow: bool,
+    cycles: &'static [SingleCycleOperation],
+}
+
+// Lines in the pre-decode cache, which keeps the instruction last decoded at each pc modulo this.
+// Instructions the block cache doesn't have, on the first pass over code or when a write has
+// dropped the block around them, are decoded from a line rather than looked up in the table.
+// A line is only used for the opcode it was decoded from, and a write into its bytes drops it
+const DECODE_CACHE_LINES: usize = 256;
+
+// The most bytes in an instruction, so the furthest back its pc can be from a byte in it
+const MAX_INSTRUCTION_BYTES: Address = 3;
+
 pub struct Proc6502 {
     pc: Address,
     x: Data,
@@ -146,84 +310,144 @@ pub struct Proc6502 {
     overflow: bool,
     carry: bool,
     status: Data,
-    operation_stream: Vec<SingleCycleOperation>,
-    instructions: HashMap<u8, Instruction>,
+    // cycles to run that aren't in a cached block: the boot sequence, an instruction being
+    // decoded or a restored snapshot's, from next_operation on
+    operation_stream: OperationQueue,
+    next_operation: usize,
     total_cycles: usize,
     boot_cycles: usize,
+    // decoded blocks, the one being run and the one being decoded (the blocks feature)
+    #[cfg(feature = "blocks")]
+    blocks: blocks::BlockCache,
+    // inline, like the queue, so the processor needs no heap without the block cache
+    decoded: [Option<Decoded>; DECODE_CACHE_LINES],
 }
 
-pub fn createSingleOperation(operations: &[InternalOperations]) -> SingleCycleOperation {
+pub const fn createSingleOperation(operations: &'static [InternalOperations]) -> SingleCycleOperation {
+    #[cfg(feature = "threaded")]
+    let mut handlers: [Handler; MAX_CYCLE_OPERATIONS] = [|_, _, _| {}; MAX_CYCLE_OPERATIONS];
+    #[cfg(feature = "threaded")]
+    {
+        let mut i = 0;
+        while i < operations.len() {
+            handlers[i] = operations[i].handler();
+            i += 1;
+        }
+    }
     SingleCycleOperation{
-        internal_operations: operations.to_vec()
+        internal_operations: operations,
+        #[cfg(feature = "threaded")]
+        handlers,
     }
 }
 
-pub fn fetch_operations_for_mode(mode: &AddressingMode) -> Vec<SingleCycleOperation> {
+pub const fn fetch_operations_for_mode(mode: &AddressingMode) -> &'static [SingleCycleOperation] {
     match mode {
-        Accumulator => vec![],
-        Absolute => vec![createSingleOperation(&[FetchAddrLo, FetchAddrHi])],
-        AbsIndexed { reg } => vec![createSingleOperation(&[FetchAddrLo, FetchAddrHi, IncrementAddressByReg { reg: reg.clone() }])],
-        Immediate => vec![createSingleOperation(&[FetchImmediateOperand])],
-        Implied => vec![],
-        Indirect => vec![createSingleOperation(&[FetchAddrLo, FetchAddrHi]), createSingleOperation(&[ReadAddressLo, ReadAddressHi])],
-        IndexedIndirect => vec![], // TODO
-        IndirectIndexed => vec![], // TODO
-        Relative => vec![createSingleOperation(&[FetchOperand, IncrementPCBySignedOperand])],
-        ZeroPage => vec![createSingleOperation(&[FetchZeroPageAddr])],
-        ZeroPageIndexed { reg } => vec![createSingleOperation(&[FetchZeroPageAddr, IncrementAddressByReg {reg: reg.clone()}])],
+        Accumulator => &[],
+        Absolute => const { &[createSingleOperation(&[FetchAddrLo, FetchAddrHi])] },
+        AbsIndexed { reg: X } => const { &[createSingleOperation(&[FetchAddrLo, FetchAddrHi, IncrementAddressByReg { reg: X }])] },
+        AbsIndexed { reg: Y } => const { &[createSingleOperation(&[FetchAddrLo, FetchAddrHi, IncrementAddressByReg { reg: Y }])] },
+        AbsIndexed { reg: A } => const { &[createSingleOperation(&[FetchAddrLo, FetchAddrHi, IncrementAddressByReg { reg: A }])] },
+        AbsIndexed { reg: InternalOperand } => const {
+            &[createSingleOperation(&[FetchAddrLo, FetchAddrHi, IncrementAddressByReg { reg: InternalOperand }])]
+        },
+        Immediate => const { &[createSingleOperation(&[FetchImmediateOperand])] },
+        Implied => &[],
+        Indirect => const { &[createSingleOperation(&[FetchAddrLo, FetchAddrHi]), createSingleOperation(&[ReadAddressLo, ReadAddressHi])] },
+        IndexedIndirect => &[], // TODO
+        IndirectIndexed => &[], // TODO
+        Relative => const { &[createSingleOperation(&[FetchOperand, IncrementPCBySignedOperand])] },
+        ZeroPage => const { &[createSingleOperation(&[FetchZeroPageAddr])] },
+        ZeroPageIndexed { reg: X } => const { &[createSingleOperation(&[FetchZeroPageAddr, IncrementAddressByReg { reg: X }])] },
+        ZeroPageIndexed { reg: Y } => const { &[createSingleOperation(&[FetchZeroPageAddr, IncrementAddressByReg { reg: Y }])] },
+        ZeroPageIndexed { reg: A } => const { &[createSingleOperation(&[FetchZeroPageAddr, IncrementAddressByReg { reg: A }])] },
+        ZeroPageIndexed { reg: InternalOperand } => const {
+            &[createSingleOperation(&[FetchZeroPageAddr, IncrementAddressByReg { reg: InternalOperand }])]
+        },
     }
 }
 
-pub fn create_instruction_for_mode(opcode: u8, mnemonic: &str, mode: AddressingMode, operations: &[InternalOperations]) -> (u8, Instruction) {
+pub const fn create_instruction_for_mode(opcode: u8, mnemonic: &'static str, mode: AddressingMode, operations: &'static [InternalOperations]) -> (u8, Instruction) {
     (opcode, Instruction {
-        mnemonic: mnemonic.to_string(),
+        mnemonic,
         operations: fetch_operations_for_mode(&mode),
+        execute: operations,
         addressing: mode,
     })
 }
 
-//
-// An opcode is  0baaabbbcc;
-// The base opcode specifies the aaa and cc.  We loop through the b which represents a different addressing mode, none.
-// See
-pub fn create_instructions(base_opcode: u8, mnemonic: &str, modes: &[Option<AddressingMode>], opcode_operations: &[InternalOperations]) -> Vec<(u8, Instruction)> {
-    let b_mask: u8 = 0b00011100;
-    let mut instructions: Vec<(u8, Instruction)> = vec!();
-
-    for b in 0..7 {
-        if let Some(mode) = modes[b].clone() {
-            let opcode = base_opcode | b_mask & ((b as u8) << 2);
-            println!("{:#04x}\t{}\t{}", opcode, mnemonic, mode);
-            instructions.push((opcode, Instruction {
-                mnemonic: mnemonic.to_string(),
-                operations: fetch_operations_for_mode(&mode),
-                addressing: mode,
-            }))
+// Opcode to instruction, indexed by opcode, None where the opcode isn't defined
+pub struct InstructionTable {
+    instructions: [Option<Instruction>; 256],
+}
+
+impl InstructionTable {
+    const fn insert(&mut self, (opcode, instruction): (u8, Instruction)) {
+        self.instructions[opcode as usize] = Some(instruction);
+    }
+
+    //
+    // An opcode is  0baaabbbcc;
+    // The base opcode specifies the aaa and cc.  We loop through the b which represents a different addressing mode, none.
+    // See
+    const fn create_instructions(&mut self, base_opcode: u8, mnemonic: &'static str, modes: &[Option<AddressingMode>; 8], opcode_operations: &'static [InternalOperations]) {
+        let b_mask: u8 = 0b00011100;
+        let mut b = 0;
+        while b < modes.len() {
+            if let Some(mode) = modes[b] {
+                let opcode = base_opcode | b_mask & ((b as u8) << 2);
+                self.insert(create_instruction_for_mode(opcode, mnemonic, mode, opcode_operations));
+            }
+            b += 1;
         }
     }
-    instructions
+
+    pub fn get(&self, opcode: u8) -> Option<&Instruction> {
+        self.instructions[opcode as usize].as_ref()
+    }
+
+    pub fn contains(&self, opcode: u8) -> bool {
+        self.instructions[opcode as usize].is_some()
+    }
+
+    // The defined opcodes and their instructions, in opcode order
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &Instruction)> {
+        self.instructions.iter().enumerate().filter_map(|(opcode, instruction)| Some((opcode as u8, instruction.as_ref()?)))
+    }
+}
+
+impl Index<u8> for InstructionTable {
+    type Output = Instruction;
+
+    fn index(&self, opcode: u8) -> &Instruction {
+        self.get(opcode).expect("no instruction for opcode")
+    }
 }
 
-fn make(f: Function) -> Vec<InternalOperations> {
-    let x = vec![ComputeAndStore {
+const fn compute(func: Function) -> InternalOperations {
+    ComputeAndStore {
         left: A, // right is implied as InternalOperand
         dst: A,
-        func: f
-    }];
-    x
+        func
+    }
 }
 
-pub fn create6502() -> Proc6502 {
-    let mut map_o_instructions: HashMap<u8, Instruction> = HashMap::new();
+// The instructions the processor decodes, built at compile time
+pub static INSTRUCTION_TABLE: InstructionTable = build_instruction_table();
+
+// The opcode to instruction table used by the processor, also handy for tools that need to decode bytes
+pub fn create_instruction_table() -> &'static InstructionTable {
+    &INSTRUCTION_TABLE
+}
 
+const fn build_instruction_table() -> InstructionTable {
+    let mut table = InstructionTable { instructions: [const { None }; 256] };
 
-    let nop = create_instruction_for_mode(0xea, "NOP", Implied, &[NOP]);
-    map_o_instructions.insert(nop.0, nop.1);
-    let brk = create_instruction_for_mode(0x00, "BRK", Implied, &[BRK]);
-    map_o_instructions.insert(brk.0, brk.1);
+    table.insert(create_instruction_for_mode(0xea, "NOP", Implied, &[NOP]));
+    table.insert(create_instruction_for_mode(0x00, "BRK", Implied, &[BRK]));
 
-    // Note we may end up creating illegal instruction opcodes but we can filter
-    let fam0 = vec![
+    // A family lists the modes by b, None where the opcode isn't a documented instruction
+    let fam0 = [
         Some(Immediate),
         Some(ZeroPage),
         None,
@@ -233,33 +457,37 @@ pub fn create6502() -> Proc6502 {
         None,
         Some(AbsIndexed {reg: X})
     ];
+    let fam0_store = [None, Some(ZeroPage), None, Some(Absolute), None, Some(ZeroPageIndexed { reg: X }), None, None];
+    let fam0_compare = [Some(Immediate), Some(ZeroPage), None, Some(Absolute), None, None, None, None];
 
-    map_o_instructions.extend(create_instructions(0x80, "STY", &fam0, &[WriteToAddress {src: Y, addr: InternalAddress}]));
-    map_o_instructions.extend(create_instructions(0xa0, "LDY", &fam0, &[StoreToRegister {src: InternalOperand, dst: Y}]));
-    map_o_instructions.extend(create_instructions(0xc0, "CPY", &fam0, &[CompareToRegister { src: InternalOperand, reg2: Y }]));
-    map_o_instructions.extend(create_instructions(0xe0, "CPX", &fam0, &[CompareToRegister {src: InternalOperand, reg2: X}]));
+    table.create_instructions(0x80, "STY", &fam0_store, &[WriteToAddress {src: Y, addr: InternalAddress}]);
+    table.create_instructions(0xa0, "LDY", &fam0, &[StoreToRegister {src: InternalOperand, dst: Y}]);
+    table.create_instructions(0xc0, "CPY", &fam0_compare, &[CompareToRegister { src: InternalOperand, reg2: Y }]);
+    table.create_instructions(0xe0, "CPX", &fam0_compare, &[CompareToRegister {src: InternalOperand, reg2: X}]);
 
-    let fam1 = vec![
-        Some(IndirectIndexed),
+    let fam1 = [
+        Some(IndexedIndirect),
         Some(ZeroPage),
         Some(Immediate),
         Some(Absolute),
-        None,
+        Some(IndirectIndexed),
         Some(ZeroPageIndexed { reg: X }),
-        None,
+        Some(AbsIndexed {reg: Y}),
         Some(AbsIndexed {reg: X})
     ];
-
-    map_o_instructions.extend(create_instructions(0x01, "ORA", &fam1, &*make(OR)));
-    map_o_instructions.extend(create_instructions(0x21, "AND", &fam1, &*make(AND)));
-    map_o_instructions.extend(create_instructions(0x41, "EOR", &fam1, &*make(EOR)));
-    map_o_instructions.extend(create_instructions(0x61, "ADC", &fam1, &*make(AddWithCarry)));
-    map_o_instructions.extend(create_instructions(0x81, "STA", &fam1, &[WriteToAddress { src: A, addr: InternalAddress }]));
-    map_o_instructions.extend(create_instructions(0xA1, "LDA", &fam1, &[StoreToRegister { src: InternalOperand, dst: A }]));
-    map_o_instructions.extend(create_instructions(0xC1, "CMP", &fam1, &*make(COMPARE)));
-    map_o_instructions.extend(create_instructions(0xE1, "SBC", &fam1, &*make(SubtractWithBorrow)));
-
-    let fam2_y = vec![
+    let mut fam1_store = fam1;
+    fam1_store[2] = None;
+
+    table.create_instructions(0x01, "ORA", &fam1, const { &[compute(OR)] });
+    table.create_instructions(0x21, "AND", &fam1, const { &[compute(AND)] });
+    table.create_instructions(0x41, "EOR", &fam1, const { &[compute(EOR)] });
+    table.create_instructions(0x61, "ADC", &fam1, const { &[compute(AddWithCarry)] });
+    table.create_instructions(0x81, "STA", &fam1_store, &[WriteToAddress { src: A, addr: InternalAddress }]);
+    table.create_instructions(0xA1, "LDA", &fam1, &[StoreToRegister { src: InternalOperand, dst: A }]);
+    table.create_instructions(0xC1, "CMP", &fam1, const { &[compute(COMPARE)] });
+    table.create_instructions(0xE1, "SBC", &fam1, const { &[compute(SubtractWithBorrow)] });
+
+    let fam2_y = [
         Some(Immediate),
         Some(ZeroPage),
         None,
@@ -270,10 +498,25 @@ pub fn create6502() -> Proc6502 {
         Some(AbsIndexed {reg: Y})
     ];
 
-    map_o_instructions.extend(create_instructions(0xA2, "LDX", &fam2_y, &[StoreToRegister { src: InternalOperand, dst: X }]));
+    table.create_instructions(0xA2, "LDX", &fam2_y, &[StoreToRegister { src: InternalOperand, dst: X }]);
+
+    table
+}
+
+// The opcode for a mnemonic in a particular addressing mode, the reverse of the instruction table
+pub fn find_opcode(instructions: &InstructionTable, mnemonic: &str, mode: &AddressingMode) -> Option<u8> {
+    instructions
+        .iter()
+        .find(|(_, i)| i.mnemonic.eq_ignore_ascii_case(mnemonic) && i.addressing == *mode)
+        .map(|(opcode, _)| opcode)
+}
+
+// Where the boot sequence reads the start address from
+pub const BOOT_VECTOR: Address = 0x0FFC;
 
+pub fn create6502() -> Proc6502 {
     let mut p = Proc6502 {
-        pc: 0x0FFC,
+        pc: BOOT_VECTOR,
         x: 0,
         y: 0,
         a: 0,
@@ -283,22 +526,28 @@ pub fn create6502() -> Proc6502 {
         overflow: false,
         carry: false,
         status: 0,
-        operation_stream: Vec::new(),
-        instructions: map_o_instructions,
+        operation_stream: ArrayVec::new(),
+        next_operation: 0,
         total_cycles: 0,
         boot_cycles: 0,
+        #[cfg(feature = "blocks")]
+        blocks: Default::default(),
+        decoded: [None; DECODE_CACHE_LINES],
     };
 
     // Prime the operation_stream with the boot sequence
-    p.pc = 0x0FFC; // BOOT location
-    p.operation_stream.extend(
-        vec![createSingleOperation(&[FetchAddrLo, FetchAddrHi, JumpToAddress])],
-    );
+    p.pc = BOOT_VECTOR;
+    p.operation_stream.push(boot_sequence());
     
     p.boot_cycles = p.operation_stream.len();
     p
 }
 
+// Reads the start address from BOOT_VECTOR and jumps there
+fn boot_sequence() -> SingleCycleOperation {
+    createSingleOperation(&[FetchAddrLo, FetchAddrHi, JumpToAddress])
+}
+
 impl Proc6502 {
     fn set_reg(&mut self, reg: &DataRegister, value: Data)  {
         match reg {
@@ -325,6 +574,46 @@ impl Proc6502 {
         }
     }
 
+    // Leaves the block being run or decoded, as when the pc is set from outside
+    fn leave_block(&mut self) {
+        #[cfg(feature = "blocks")]
+        self.blocks.leave();
+    }
+
+    // The number of instructions in the pre-decode cache
+    pub fn get_decoded_count(&self) -> usize {
+        self.decoded.iter().flatten().count()
+    }
+
+    // The instruction at pc from its line in the pre-decode cache if it was decoded from the same
+    // opcode, or else from the table, filling the line. None for an opcode the table doesn't have
+    fn decode(&mut self, opcode: Data) -> Option<Decoded> {
+        let line = &mut self.decoded[self.pc as usize % DECODE_CACHE_LINES];
+        if let Some(decoded) = line.filter(|decoded| decoded.pc == self.pc && decoded.opcode == opcode) {
+            return Some(decoded);
+        }
+        let instruction = INSTRUCTION_TABLE.get(opcode)?;
+        let decoded = Decoded {
+            pc: self.pc,
+            opcode,
+            end: self.pc.wrapping_add(1 + instruction.addressing.operand_length() as Address),
+            #[cfg(feature = "blocks")]
+            changes_flow: instruction.changes_flow(),
+            cycles: instruction.operations,
+        };
+        *line = Some(decoded);
+        Some(decoded)
+    }
+
+    // The cycles left of the instruction being run
+    fn remaining_cycles(&self) -> &[SingleCycleOperation] {
+        #[cfg(feature = "blocks")]
+        if let Some(cursor) = &self.blocks.cursor {
+            return cursor.remaining();
+        }
+        &self.operation_stream[self.next_operation..]
+    }
+
     pub fn as_cloned_bus_device(&self, me: Rc<RefCell<Proc6502>>) -> Rc<RefCell<dyn BusDevice>> {
         let rc: Rc<RefCell<dyn BusDevice>> = me;
         Rc::clone(&rc)
@@ -339,107 +628,238 @@ impl ProcessorTrait for Proc6502 {
         }
         self.total_cycles - self.boot_cycles
     }
-    
-    fn tick(&mut self, the_bus: Rc<RefCell<dyn Bus>>) -> (Address, bool) {
+
+    fn get_total_cycles(&self) -> usize {
+        self.total_cycles
+    }
+
+    fn get_registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status | (self.carry as Data) | ((self.overflow as Data) << 6),
+        }
+    }
+
+    fn set_registers(&mut self, registers: &Registers) {
+        self.pc = registers.pc;
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.carry = registers.status & 0x01 != 0;
+        self.overflow = registers.status & 0x40 != 0;
+        self.status = registers.status & !0x41;
+        self.leave_block();
+    }
+
+    fn is_at_instruction_boundary(&self) -> bool {
+        #[cfg(feature = "blocks")]
+        if self.blocks.cursor.as_ref().is_some_and(|cursor| cursor.is_mid_instruction()) {
+            return false;
+        }
+        self.next_operation == self.operation_stream.len()
+    }
+
+    fn invalidate(&mut self, address: Address) {
+        // the lines of the instructions that can start far enough back to hold address
+        for pc in (0..MAX_INSTRUCTION_BYTES).map(|back| address.wrapping_sub(back)) {
+            let line = &mut self.decoded[pc as usize % DECODE_CACHE_LINES];
+            if line.is_some_and(|decoded| decoded.pc == pc && address.wrapping_sub(pc) < decoded.end.wrapping_sub(pc)) {
+                *line = None;
+            }
+        }
+        #[cfg(feature = "blocks")]
+        self.blocks.invalidate(address);
+    }
+
+    fn snapshot(&self) -> ProcessorSnapshot {
+        ProcessorSnapshot {
+            pc: self.pc,
+            x: self.x,
+            y: self.y,
+            a: self.a,
+            internal_address: self.internal_address,
+            internal_operand: self.internal_operand,
+            at_break: self.at_break,
+            overflow: self.overflow,
+            carry: self.carry,
+            status: self.status,
+            operation_stream: self.remaining_cycles().iter().copied().collect(),
+            total_cycles: self.total_cycles,
+        }
+    }
+
+    fn restore(&mut self, snapshot: &ProcessorSnapshot) {
+        self.pc = snapshot.pc;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.a = snapshot.a;
+        self.internal_address = snapshot.internal_address;
+        self.internal_operand = snapshot.internal_operand;
+        self.at_break = snapshot.at_break;
+        self.overflow = snapshot.overflow;
+        self.carry = snapshot.carry;
+        self.status = snapshot.status;
+        self.operation_stream = snapshot.operation_stream.clone();
+        self.next_operation = 0;
+        self.total_cycles = snapshot.total_cycles;
+        self.leave_block();
+    }
+
+    fn tick(&mut self, the_bus: &dyn Bus) -> (Address, bool) {
         self.total_cycles += 1;
-        if self.operation_stream.is_empty() {
-            // fetch the opcode
-            self.operation_stream.extend([createSingleOperation(&[FetchOpcode])].into_iter());
+        // the next cycle of an instruction from a cached block
+        #[cfg(feature = "blocks")]
+        if self.run_block_cycle(the_bus) {
+            return (self.pc, self.at_break);
+        }
+        if self.next_operation == self.operation_stream.len() {
+            // fetch the opcode, which queues the instruction's cycles or points the cursor at them
+            self.operation_stream.clear();
+            self.next_operation = 0;
+            self.fetch_opcode(&FetchOpcode, the_bus);
 
             // The end of some instructions imply that a fetch of the next opcode should be done in parallel TODO
+            return (self.pc, self.at_break);
         }
 
-        for x in self.operation_stream.remove(0).internal_operations {
-            match x {
-                NOP => {}
-                BRK => { self.at_break = true }
-                DummyForOverlap => {}
-                FetchOpcode => {
-                    let opcode = the_bus.borrow().read(self.pc);
-                    // todo tests for illegal opcode
-                    if let Some(instruction) = self.instructions.get(&(opcode as u8)) {
-                        println!("Excecuting {} ", instruction.mnemonic);
-                        let foo: Vec<SingleCycleOperation> = vec![];
-
-                        for i in &instruction.operations {
-                            self.operation_stream.push(i.clone());
-                        }
-                        self.pc += 1;
-                    } else {
-                        panic!("No definition for opcode {:#04x}", opcode);
-                    }
-                }
-                FetchOperand => {
-                    self.internal_operand = the_bus.borrow().read(self.internal_address);
-                }
-                FetchAddrLo => {
-                    self.internal_address &= 0xff00;
-                    self.internal_address = the_bus.borrow().read(self.pc) as Address;
-                    self.pc += 1;
-                }
-                FetchAddrHi => {
-                    self.internal_address &= 0x00ff;
-                    self.internal_address |= (the_bus.borrow().read(self.pc) as Address) << 8;
-                    self.pc += 1;
-                }
-                FetchImmediateOperand => {
-                    self.internal_operand = the_bus.borrow().read(self.pc);
-                    self.pc += 1;
-                }
-                WriteToAddress { src, addr } => {
-                    the_bus
-                        .borrow()
-                        .write(self.get_addr_reg(&addr), self.get_reg(&src));
-                }
-                JumpToAddress => {
-                    self.pc = self.internal_address;
-                }
-                CompareToRegister { src, reg2 } => {
-                    todo!();
-                }
-                ReadFromAccumulator => {}
-                AddIndexLo => {}
-                AluIncr => {}
-                InternalOperations::IncrementAddressByReg { reg } => {
-                    self.internal_address += self.get_reg(&reg) as Address;
-                }
-                FetchZeroPageAddr => {
-                    self.internal_address &= 0x0000;
-                    self.internal_address = the_bus.borrow().read(self.pc) as Address;
-                    self.pc += 1;
-                }
-                IncrementPCBySignedOperand => {}
-                ReadAddressLo => {}
-                ReadAddressHi => {}
-                StoreToRegister { src, dst } => {
-                    self.set_reg(&dst, self.get_reg(&src));
-                }
-                ComputeAndStore { left, dst, func } => {
-                    match func {
-                        OR => todo!(),
-                        AND => todo!(),
-                        EOR => todo!(),
-                        AddWithCarry => {
-                            let (result, carry) = self.a.carrying_add(self.internal_operand, self.carry);
-                            self.carry = carry;
-                            // overflow is when two signed numbers with the same sign are added and the result is a different sign
-                            self.overflow = (self.a ^ result) & (self.internal_operand ^ result) & 0x80 != 0;
-                            self.set_reg(&dst, result)
-                        }
-                        COMPARE => todo!(),
-                        SubtractWithBorrow => todo!(),
-                    }
+        let cycle = self.operation_stream[self.next_operation];
+        self.next_operation += 1;
+        self.run_cycle(&cycle, the_bus);
+        (self.pc, self.at_break)
+    }
+
+    // Start the boot sequence again. A, X and Y keep their values, as they do on the real chip
+    fn reset(&mut self) {
+        self.pc = BOOT_VECTOR;
+        self.at_break = false;
+        self.leave_block();
+        self.operation_stream.clear();
+        self.next_operation = 0;
+        self.operation_stream.push(boot_sequence());
+    }
+}
+
+// Out of line so the formatting is only there on the way out
+#[cold]
+#[inline(never)]
+fn unknown_opcode(opcode: Data) -> ! {
+    panic!("No definition for opcode {:#04x}", opcode);
+}
+
+impl Proc6502 {
+    fn run_cycle(&mut self, cycle: &SingleCycleOperation, the_bus: &dyn Bus) {
+        #[cfg(feature = "threaded")]
+        for (handler, x) in cycle.handlers.iter().zip(cycle.internal_operations) {
+            handler(self, x, the_bus);
+        }
+        // the match in handler() is inlined here, so it's a jump table on the operation
+        #[cfg(not(feature = "threaded"))]
+        for x in cycle.internal_operations {
+            (x.handler())(self, x, the_bus);
+        }
+    }
+
+    fn fetch_opcode(&mut self, _: &InternalOperations, the_bus: &dyn Bus) {
+        let opcode = the_bus.read(self.pc);
+        #[cfg(feature = "blocks")]
+        if self.enter_cached_block(opcode) {
+            return;
+        }
+        // todo tests for illegal opcode
+        let Some(decoded) = self.decode(opcode) else {
+            unknown_opcode(opcode);
+        };
+        #[cfg(feature = "blocks")]
+        self.record(&decoded);
+        log::trace!(
+            target: INSTRUCTION,
+            "${:04X} {} {}",
+            self.pc,
+            INSTRUCTION_TABLE[opcode].mnemonic,
+            INSTRUCTION_TABLE[opcode].addressing
+        );
+        self.operation_stream.extend(decoded.cycles.iter().copied());
+        self.pc += 1;
+    }
+
+    fn write_to_address(&mut self, x: &InternalOperations, the_bus: &dyn Bus) {
+        if let WriteToAddress { src, addr } = x {
+            let address = self.get_addr_reg(addr);
+            self.invalidate(address);
+            the_bus.write(address, self.get_reg(src));
+        }
+    }
+
+    fn add_with_carry(&mut self, x: &InternalOperations, _: &dyn Bus) {
+        if let ComputeAndStore { dst, .. } = x {
+            let (result, carry) = self.a.carrying_add(self.internal_operand, self.carry);
+            self.carry = carry;
+            // overflow is when two signed numbers with the same sign are added and the result is a different sign
+            self.overflow = (self.a ^ result) & (self.internal_operand ^ result) & 0x80 != 0;
+            self.set_reg(dst, result)
+        }
+    }
+}
+
+// The code for a micro-op, one function per operation. By default run_cycle matches each
+// operation to its handler as it goes, which compiles to a jump table with the handlers inlined.
+// With the threaded feature a cycle looks its handlers up once, when it's built, and running it is
+// a call through each pointer (threaded code). On `sim6502 bench` in a release build threaded
+// dispatch measured about 15% slower (19.3 against 22.5 emulated MHz): the calls can't be inlined
+// and the bus reads behind them cost more than the branches saved, so it's left as an option
+type Handler = fn(&mut Proc6502, &InternalOperations, &dyn Bus);
+
+impl InternalOperations {
+    #[inline(always)]
+    const fn handler(&self) -> Handler {
+        match self {
+            NOP | DummyForOverlap => |_, _, _| {},
+            BRK => |p, _, _| p.at_break = true,
+            FetchOpcode => Proc6502::fetch_opcode,
+            FetchOperand => |p, _, bus| p.internal_operand = bus.read(p.internal_address),
+            FetchAddrLo => |p, _, bus| {
+                p.internal_address &= 0xff00;
+                p.internal_address = bus.read(p.pc) as Address;
+                p.pc += 1;
+            },
+            FetchAddrHi => |p, _, bus| {
+                p.internal_address &= 0x00ff;
+                p.internal_address |= (bus.read(p.pc) as Address) << 8;
+                p.pc += 1;
+            },
+            FetchImmediateOperand => |p, _, bus| {
+                p.internal_operand = bus.read(p.pc);
+                p.pc += 1;
+            },
+            WriteToAddress { .. } => Proc6502::write_to_address,
+            JumpToAddress => |p, _, _| p.pc = p.internal_address,
+            ReadFromAccumulator | AddIndexLo | AluIncr => |_, _, _| {},
+            // the index registers are the common case, so they get their own
+            IncrementAddressByReg { reg: DataRegister::X } => |p, _, _| p.internal_address += p.x as Address,
+            IncrementAddressByReg { reg: DataRegister::Y } => |p, _, _| p.internal_address += p.y as Address,
+            IncrementAddressByReg { .. } => |p, x, _| {
+                if let IncrementAddressByReg { reg } = x {
+                    p.internal_address += p.get_reg(reg) as Address;
                 }
-                CompareToRegister { src, reg2 } => {
-                    todo!();
+            },
+            FetchZeroPageAddr => |p, _, bus| {
+                p.internal_address &= 0x0000;
+                p.internal_address = bus.read(p.pc) as Address;
+                p.pc += 1;
+            },
+            IncrementPCBySignedOperand | ReadAddressLo | ReadAddressHi => |_, _, _| {},
+            StoreToRegister { .. } => |p, x, _| {
+                if let StoreToRegister { src, dst } = x {
+                    p.set_reg(dst, p.get_reg(src));
                 }
-            }
+            },
+            ComputeAndStore { func: AddWithCarry, .. } => Proc6502::add_with_carry,
+            ComputeAndStore { .. } | CompareToRegister { .. } => |_, _, _| todo!(),
         }
-        (self.pc, self.at_break)
     }
-
-    // TODO should tick through the
-    fn reset(&mut self) {}
 }
 
 impl BusDevice for Proc6502 {
@@ -0,0 +1,188 @@
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bus::{Address, Bus, Data};
+use crate::logging::INSTRUCTION;
+use crate::processor::{Decoded, Proc6502, SingleCycleOperation, INSTRUCTION_TABLE};
+#[cfg(feature = "closure_cache")]
+use crate::processor::closure_cache;
+
+// Straight-line code decoded once: the instructions from start up to and including the first
+// that can change the flow, each with the opcode it was decoded from and its fetch cycles, so a
+// loop over it doesn't look them up and copy them into the operation stream every pass
+pub(super) struct Block {
+    // one past the last instruction
+    end: Address,
+    pub(super) instructions: Vec<(Address, Data, &'static [SingleCycleOperation])>,
+    // how often it's been entered, and its cycles compiled once that's often enough
+    #[cfg(feature = "closure_cache")]
+    pub(super) runs: core::cell::Cell<u32>,
+    #[cfg(feature = "closure_cache")]
+    pub(super) translation: core::cell::OnceCell<closure_cache::Translation>,
+}
+
+impl Block {
+    fn new(end: Address, first: (Address, Data, &'static [SingleCycleOperation])) -> Block {
+        Block {
+            end,
+            instructions: vec![first],
+            #[cfg(feature = "closure_cache")]
+            runs: Default::default(),
+            #[cfg(feature = "closure_cache")]
+            translation: Default::default(),
+        }
+    }
+}
+
+// The longest a block gets before the next instruction starts another
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+// and so the furthest a block's start can be from a byte in it, at 3 bytes an instruction
+const MAX_BLOCK_BYTES: Address = MAX_BLOCK_INSTRUCTIONS as Address * 3;
+
+// Where the processor is in a cached block: the instruction and the next of its cycles to run
+pub(super) struct BlockCursor {
+    block: Rc<Block>,
+    instruction: usize,
+    cycle: usize,
+}
+
+impl BlockCursor {
+    pub(super) fn is_mid_instruction(&self) -> bool {
+        self.cycle < self.block.instructions[self.instruction].2.len()
+    }
+
+    pub(super) fn remaining(&self) -> &'static [SingleCycleOperation] {
+        &self.block.instructions[self.instruction].2[self.cycle..]
+    }
+}
+
+// Decoded blocks by start address, the one being run and the one being decoded
+#[derive(Default)]
+pub(super) struct BlockCache {
+    blocks: BTreeMap<Address, Rc<Block>>,
+    pub(super) cursor: Option<BlockCursor>,
+    recording: Option<(Address, Block)>,
+}
+
+impl BlockCache {
+    // Leaves the block being run or decoded
+    pub(super) fn leave(&mut self) {
+        self.cursor = None;
+        if let Some((start, block)) = self.recording.take() {
+            self.blocks.insert(start, Rc::new(block));
+        }
+    }
+
+    // Drops the blocks with an instruction at address, so a write into code decodes it afresh.
+    // A write the processor isn't told about is caught when the opcode read doesn't match, and is
+    // only a problem for an opcode: operands are read from the bus every time
+    pub(super) fn invalidate(&mut self, address: Address) {
+        // only blocks starting close enough before address can hold it
+        while let Some(start) = self
+            .blocks
+            .range(address.saturating_sub(MAX_BLOCK_BYTES)..=address)
+            .find(|(start, block)| (**start..block.end).contains(&address))
+            .map(|(start, _)| *start)
+        {
+            self.blocks.remove(&start);
+        }
+        if self.recording.as_ref().is_some_and(|(start, block)| (*start..block.end).contains(&address)) {
+            self.recording = None;
+        }
+    }
+
+    // The next instruction from a cached block: the one after the cursor's, or a block starting at
+    // pc, as long as it was decoded from the same opcode. Blocks written over are dropped
+    fn find(&mut self, pc: Address, opcode: Data) -> Option<BlockCursor> {
+        let next = self.cursor.as_ref().and_then(|cursor| {
+            let instruction = cursor.instruction + 1;
+            (cursor.block.instructions.get(instruction)?.0 == pc).then(|| (Rc::clone(&cursor.block), instruction))
+        });
+        let (block, instruction) = next.or_else(|| Some((Rc::clone(self.blocks.get(&pc)?), 0)))?;
+        if block.instructions[instruction].1 != opcode {
+            self.invalidate(pc);
+            return None;
+        }
+        Some(BlockCursor { block, instruction, cycle: 0 })
+    }
+
+    // Adds an instruction decoded at pc to the block being decoded, starting one if it isn't
+    // straight on from there and closing it after an instruction that changes the flow
+    fn record(&mut self, pc: Address, decoded: &Decoded) {
+        let end = decoded.end;
+        let entry = (pc, decoded.opcode, decoded.cycles);
+        match &mut self.recording {
+            Some((_, block)) if block.end == pc && end > pc => {
+                block.end = end;
+                block.instructions.push(entry);
+            }
+            _ => {
+                self.leave();
+                self.recording = Some((pc, Block::new(end, entry)));
+            }
+        }
+        let full = self.recording.as_ref().is_some_and(|(_, block)| block.instructions.len() >= MAX_BLOCK_INSTRUCTIONS);
+        if decoded.changes_flow || full || end <= pc {
+            self.leave();
+        }
+    }
+}
+
+impl Proc6502 {
+    // The number of decoded blocks cached
+    pub fn get_block_count(&self) -> usize {
+        self.blocks.blocks.len()
+    }
+
+    // The number of cached blocks hot enough to have been translated (the closure_cache feature)
+    #[cfg(feature = "closure_cache")]
+    pub fn get_translated_block_count(&self) -> usize {
+        self.blocks.blocks.values().filter(|block| block.translation.get().is_some()).count()
+    }
+
+    // Runs the next cycle of an instruction from a cached block, false when there isn't one
+    pub(super) fn run_block_cycle(&mut self, the_bus: &dyn Bus) -> bool {
+        let Some(cursor) = self.blocks.cursor.as_mut().filter(|cursor| cursor.is_mid_instruction()) else {
+            return false;
+        };
+        let block = Rc::clone(&cursor.block);
+        let (instruction, cycle) = (cursor.instruction, cursor.cycle);
+        cursor.cycle += 1;
+        #[cfg(feature = "closure_cache")]
+        if let Some(translation) = block.translation.get() {
+            translation[instruction][cycle](self, the_bus);
+            return true;
+        }
+        self.run_cycle(&block.instructions[instruction].2[cycle], the_bus);
+        true
+    }
+
+    // Points the cursor at the instruction at pc if a cached block has it, false if none does
+    pub(super) fn enter_cached_block(&mut self, opcode: Data) -> bool {
+        let Some(cursor) = self.blocks.find(self.pc, opcode) else {
+            self.blocks.cursor = None;
+            return false;
+        };
+        log::trace!(
+            target: INSTRUCTION,
+            "${:04X} {} {}",
+            self.pc,
+            INSTRUCTION_TABLE[opcode].mnemonic,
+            INSTRUCTION_TABLE[opcode].addressing
+        );
+        #[cfg(feature = "closure_cache")]
+        if cursor.instruction == 0 {
+            closure_cache::heat(&cursor.block);
+        }
+        self.blocks.cursor = Some(cursor);
+        self.pc += 1;
+        true
+    }
+
+    // Adds the instruction just decoded at pc to the block being decoded
+    pub(super) fn record(&mut self, decoded: &Decoded) {
+        self.blocks.record(self.pc, decoded);
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::bus::{Address, Bus};
+use crate::processor::blocks::Block;
+use crate::processor::{DataRegister, InternalOperations, Proc6502, SingleCycleOperation};
+use crate::processor::InternalOperations::*;
+
+// Hot blocks cached as closures. Once a block has been entered HOT_BLOCK_RUNS times each of its
+// cycles is built into one boxed closure with the registers, addresses and fused operations
+// worked out ahead of time, so running it is a call per cycle instead of a walk over the
+// micro-ops and a match on each. The closures go through the bus like the interpreter does, so
+// cycles and bus reads are unchanged. Translations live in their block: when code is written
+// over, the block goes and the interpreter runs the new code until it gets hot in turn. It isn't
+// a JIT: no machine code is generated, the closures are a threaded interpreter over the block
+pub(super) const HOT_BLOCK_RUNS: u32 = 16;
+
+pub(super) type CompiledCycle = Box<dyn Fn(&mut Proc6502, &dyn Bus)>;
+
+// Per instruction, per cycle
+pub(super) type Translation = Vec<Vec<CompiledCycle>>;
+
+// Counts an entry into the block and translates it the time it gets hot
+pub(super) fn heat(block: &Block) {
+    let runs = block.runs.get() + 1;
+    block.runs.set(runs);
+    if runs == HOT_BLOCK_RUNS {
+        let _ = block.translation.set(translate(block));
+    }
+}
+
+fn translate(block: &Block) -> Translation {
+    block
+        .instructions
+        .iter()
+        .map(|(_, _, cycles)| cycles.iter().map(compile).collect())
+        .collect()
+}
+
+// One register to another, picked now rather than on every run
+fn register(reg: &DataRegister) -> fn(&mut Proc6502) -> &mut u8 {
+    match reg {
+        DataRegister::X => |p| &mut p.x,
+        DataRegister::Y => |p| &mut p.y,
+        DataRegister::A => |p| &mut p.a,
+        DataRegister::InternalOperand => |p| &mut p.internal_operand,
+    }
+}
+
+fn compile(cycle: &SingleCycleOperation) -> CompiledCycle {
+    let operations: Vec<&InternalOperations> = cycle.get_operations().iter().filter(|op| !matches!(op, NOP | DummyForOverlap)).collect();
+    match operations.as_slice() {
+        [] => Box::new(|_, _| {}),
+        // the fetch cycle of the absolute modes reads the address as a word
+        [FetchAddrLo, FetchAddrHi] => Box::new(|p, bus| p.fetch_address(bus)),
+        [FetchAddrLo, FetchAddrHi, IncrementAddressByReg { reg }] => {
+            let reg = register(reg);
+            Box::new(move |p, bus| {
+                p.fetch_address(bus);
+                p.internal_address += *reg(p) as Address;
+            })
+        }
+        [FetchZeroPageAddr] => Box::new(|p, bus| {
+            p.internal_address = bus.read(p.pc) as Address;
+            p.pc += 1;
+        }),
+        [FetchZeroPageAddr, IncrementAddressByReg { reg }] => {
+            let reg = register(reg);
+            Box::new(move |p, bus| {
+                p.internal_address = bus.read(p.pc) as Address + *reg(p) as Address;
+                p.pc += 1;
+            })
+        }
+        [FetchImmediateOperand] => Box::new(|p, bus| {
+            p.internal_operand = bus.read(p.pc);
+            p.pc += 1;
+        }),
+        [StoreToRegister { src, dst }] => {
+            let (src, dst) = (register(src), register(dst));
+            Box::new(move |p, _| *dst(p) = *src(p))
+        }
+        // anything else is run as the interpreter would
+        _ => {
+            let cycle = *cycle;
+            Box::new(move |p, bus| p.run_cycle(&cycle, bus))
+        }
+    }
+}
+
+impl Proc6502 {
+    fn fetch_address(&mut self, bus: &dyn Bus) {
+        let lo = bus.read(self.pc) as Address;
+        self.pc += 1;
+        self.internal_address = (bus.read(self.pc) as Address) << 8 | lo;
+        self.pc += 1;
+    }
+}
@@ -0,0 +1,352 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::{Address, Bus, BusDevice, Data, MaskedBus, PagedBus};
+use crate::devices::{Peripheral, Peripherals};
+use crate::memory::Memory;
+use crate::processor::{create6502, ProcessorTrait, Registers, BOOT_VECTOR};
+
+mod bench;
+mod builder;
+mod clock;
+mod controller;
+#[cfg(feature = "devices")]
+mod replay;
+mod runner;
+mod scheduler;
+mod stats;
+mod watchdog;
+#[cfg(feature = "config")]
+mod config;
+pub use bench::{bench, bench_system, BenchReport};
+pub use builder::{CpuModel, SystemBuilder};
+pub use clock::{Clock, Speed};
+pub use controller::{run_controller, RunController, RunLoop};
+#[cfg(feature = "devices")]
+pub use replay::{
+    Input, InputRecord, Recorder, RecordingKeys, RecordingSerial, Replay, ReplayKeys, ReplaySerial,
+};
+pub use runner::{Command, Event, Runner, StopReason};
+pub use scheduler::{Callback, EventId, Scheduler};
+use scheduler::Timeline;
+pub use stats::DeviceStats;
+pub use watchdog::{Watchdog, WatchdogReport, WatchdogStop};
+#[cfg(feature = "config")]
+pub use config::{DeviceConfig, MachineConfig, RomConfig};
+
+pub const DEFAULT_CLOCK_HZ: u64 = 1_000_000;
+
+// A whole machine: a processor, a bus with RAM on it and any devices, driven together.
+// Devices go on the bus ahead of the RAM so they win the addresses they share with it, and
+// peripherals are ticked with the cycles of every instruction
+pub struct System {
+    processor: Rc<RefCell<dyn ProcessorTrait>>,
+    bus: Rc<RefCell<PagedBus>>,
+    // the bus as the processor sees it, through its address lines
+    cpu_bus: Rc<RefCell<dyn Bus>>,
+    memory: Rc<RefCell<Memory>>,
+    // shared with a debugger, which ticks them for the instructions it runs
+    peripherals: Rc<RefCell<Peripherals>>,
+    scheduler: Rc<RefCell<Scheduler>>,
+    timeline: Rc<Timeline>,
+    // devices on the bus ahead of the RAM
+    devices: usize,
+    clock_hz: u64,
+    // the cycle count, virtual time and clock rate at each clock change, oldest first
+    rates: Vec<(usize, u64, u64)>,
+    // cycles the last frame ran past its budget, taken off the next
+    frame_overrun: usize,
+    halted: bool,
+}
+
+impl Default for System {
+    fn default() -> Self {
+        System::new()
+    }
+}
+
+impl System {
+    // A 6502 with 64K of RAM
+    pub fn new() -> System {
+        System::with_memory(Memory::new(0x0000, 0xffff))
+    }
+
+    pub fn with_memory(memory: Memory) -> System {
+        let memory = Rc::new(RefCell::new(memory));
+        let bus = Rc::new(RefCell::new(PagedBus::new()));
+        bus.borrow_mut().register_device(&memory.borrow().as_cloned_bus_device(Rc::clone(&memory)));
+        let scheduler = Scheduler::new();
+        System {
+            processor: Rc::new(RefCell::new(create6502())),
+            cpu_bus: bus.clone(),
+            bus,
+            memory,
+            peripherals: Rc::new(RefCell::new(Peripherals::new())),
+            timeline: scheduler.get_timeline(),
+            scheduler: Rc::new(RefCell::new(scheduler)),
+            devices: 0,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            rates: vec![(0, 0, DEFAULT_CLOCK_HZ)],
+            frame_overrun: 0,
+            halted: false,
+        }
+    }
+
+    // Puts a device on the bus and hands back a handle to it
+    pub fn add_device<D: BusDevice + 'static>(&mut self, device: D) -> Rc<RefCell<D>> {
+        let device = Rc::new(RefCell::new(device));
+        let bus_device: Rc<RefCell<dyn BusDevice>> = device.clone();
+        self.bus.borrow_mut().insert_device(self.devices, &bus_device);
+        self.devices += 1;
+        device
+    }
+
+    // A device that is also ticked, reset and listened to for IRQs
+    pub fn add_peripheral<P: Peripheral + 'static>(&mut self, device: P) -> Rc<RefCell<P>> {
+        let device = self.add_device(device);
+        self.peripherals.borrow_mut().add(device.clone());
+        device
+    }
+
+    // Drops the address bits a smaller package doesn't bring out, e.g. $1FFF for the 6507
+    pub fn set_address_mask(&mut self, mask: Address) {
+        let bus: Rc<RefCell<dyn Bus>> = self.bus.clone();
+        self.cpu_bus = if mask == 0xffff { bus } else { Rc::new(RefCell::new(MaskedBus::new(bus, mask))) };
+    }
+
+    // Copies data into RAM
+    pub fn load(&mut self, address: Address, data: &[Data]) {
+        self.memory.borrow_mut().write(address, data.to_vec());
+    }
+
+    // Copies a file into RAM, returning its length
+    pub fn load_file(&mut self, address: Address, path: impl AsRef<Path>) -> io::Result<usize> {
+        let data = fs::read(path)?;
+        if address as usize + data.len() > 0x10000 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "image doesn't fit below $FFFF"));
+        }
+        self.load(address, &data);
+        Ok(data.len())
+    }
+
+    // Where the processor starts after a reset
+    pub fn set_reset_vector(&mut self, address: Address) {
+        self.load(BOOT_VECTOR, &[address as Data, (address >> 8) as Data]);
+    }
+
+    pub fn reset(&mut self) {
+        self.processor.borrow_mut().reset();
+        self.peripherals.borrow().reset();
+        self.frame_overrun = 0;
+        self.halted = false;
+    }
+
+    // Runs one instruction, returning the cycles it took. The boot sequence counts as one
+    pub fn step(&mut self) -> usize {
+        if self.halted {
+            return 0;
+        }
+        let mut cycles = 0;
+        loop {
+            // The processor and bus are borrowed once for as many cycles as run before a callback
+            // is due, usually the whole instruction, and each cycle only touches Cells
+            let due = {
+                let mut processor = self.processor.borrow_mut();
+                let bus = self.cpu_bus.borrow();
+                loop {
+                    cycles += 1;
+                    self.halted = processor.tick(&*bus).1;
+                    let now = processor.get_total_cycles();
+                    self.timeline.now.set(now);
+                    if now >= self.timeline.due.get() {
+                        break true;
+                    }
+                    if self.halted || processor.is_at_instruction_boundary() {
+                        break false;
+                    }
+                }
+            };
+            if !due {
+                break;
+            }
+            self.dispatch();
+            if self.halted || self.processor.borrow().is_at_instruction_boundary() {
+                break;
+            }
+        }
+        self.peripherals.borrow().tick(cycles);
+        cycles
+    }
+
+    // Runs the scheduled callbacks due by the cycle just ticked
+    fn dispatch(&mut self) {
+        let now = self.get_total_cycles();
+        self.scheduler.borrow_mut().set_now(now);
+        loop {
+            let callback = self.scheduler.borrow_mut().pop_due();
+            match callback {
+                Some(callback) => callback(self),
+                None => break,
+            }
+        }
+    }
+
+    // Calls back at an absolute cycle count, on that very cycle
+    pub fn schedule_at<F: FnOnce(&mut System) + 'static>(&mut self, cycle: usize, callback: F) -> EventId {
+        self.scheduler.borrow_mut().schedule_at(cycle, callback)
+    }
+
+    // Calls back `cycles` from now
+    pub fn schedule_in<F: FnOnce(&mut System) + 'static>(&mut self, cycles: usize, callback: F) -> EventId {
+        self.scheduler.borrow_mut().schedule_in(cycles, callback)
+    }
+
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        self.scheduler.borrow_mut().cancel(id)
+    }
+
+    // For devices that schedule their own callbacks
+    pub fn get_scheduler(&self) -> Rc<RefCell<Scheduler>> {
+        Rc::clone(&self.scheduler)
+    }
+
+    // Runs whole instructions until at least `cycles` have gone by or the program breaks,
+    // returning the cycles run
+    pub fn run(&mut self, cycles: usize) -> usize {
+        let mut run = 0;
+        while run < cycles && !self.halted {
+            run += self.step();
+        }
+        run
+    }
+
+    // Like run, but paced by a clock
+    pub fn run_clocked(&mut self, cycles: usize, clock: &mut Clock) -> usize {
+        let mut run = 0;
+        while run < cycles && !self.halted {
+            let step = self.step();
+            clock.advance(step);
+            run += step;
+        }
+        run
+    }
+
+    // One frame of a front end's main loop: runs cycles_per_frame, then hands the System to
+    // the host to render and poll input. Instructions don't split at frame boundaries, so a
+    // frame's overrun comes off the next and frames average out to the budget. Returns the
+    // cycles run
+    pub fn run_frame<F: FnMut(&mut System)>(&mut self, cycles_per_frame: usize, mut callback: F) -> usize {
+        let budget = cycles_per_frame.saturating_sub(self.frame_overrun);
+        let run = self.run(budget);
+        self.frame_overrun = run.saturating_sub(budget);
+        callback(self);
+        run
+    }
+
+    pub fn run_until_break(&mut self) -> usize {
+        let mut run = 0;
+        while !self.halted {
+            run += self.step();
+        }
+        run
+    }
+
+    // True once the program has hit BRK, until the next reset
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Whether any peripheral holds its IRQ line. The processor has no IRQ input, so it's only
+    // reported, never taken
+    pub fn irq_asserted(&self) -> bool {
+        self.peripherals.borrow().irq_asserted()
+    }
+
+    pub fn read(&self, address: Address) -> Data {
+        self.cpu_bus.borrow().read(address)
+    }
+
+    // What a read would return, without acknowledging anything on an I/O register
+    pub fn peek(&self, address: Address) -> Data {
+        self.cpu_bus.borrow().peek(address)
+    }
+
+    pub fn write(&self, address: Address, data: Data) {
+        self.cpu_bus.borrow().write(address, data);
+        self.processor.borrow_mut().invalidate(address);
+    }
+
+    pub fn get_registers(&self) -> Registers {
+        self.processor.borrow().get_registers()
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.processor.borrow_mut().set_registers(registers);
+    }
+
+    // The speed of the machine being emulated, for devices and front ends that keep time
+    pub fn get_clock_hz(&self) -> u64 {
+        self.clock_hz
+    }
+
+    pub fn set_clock_hz(&mut self, clock_hz: u64) {
+        let now = self.get_total_cycles();
+        let elapsed = self.elapsed();
+        // changes after now are from a future a restored snapshot has abandoned
+        self.rates.retain(|(cycles, _, _)| *cycles < now);
+        self.clock_hz = clock_hz.max(1);
+        self.rates.push((now, elapsed, self.clock_hz));
+    }
+
+    // Virtual nanoseconds since power on: the cycles run at the clock rate they ran at, so
+    // changing the rate doesn't rewrite the past. Resets don't restart it, and a snapshot
+    // restored from before a change goes back to the rate there was then
+    pub fn elapsed(&self) -> u64 {
+        let now = self.get_total_cycles();
+        let (cycles, elapsed, clock_hz) =
+            self.rates.iter().rev().copied().find(|(cycles, _, _)| *cycles <= now).unwrap_or((0, 0, self.clock_hz));
+        elapsed + ((now - cycles) as u128 * 1_000_000_000 / clock_hz as u128) as u64
+    }
+
+    pub fn get_total_cycles(&self) -> usize {
+        self.processor.borrow().get_total_cycles()
+    }
+
+    // For attaching a Debugger or a Gui
+    pub fn get_processor(&self) -> Rc<RefCell<dyn ProcessorTrait>> {
+        Rc::clone(&self.processor)
+    }
+
+    pub fn get_bus(&self) -> Rc<RefCell<dyn Bus>> {
+        Rc::clone(&self.cpu_bus)
+    }
+
+    pub fn get_memory(&self) -> Rc<RefCell<Memory>> {
+        Rc::clone(&self.memory)
+    }
+
+    // For a Debugger to tick as it steps, see Debugger::attach_peripherals
+    pub fn get_peripherals(&self) -> Rc<RefCell<Peripherals>> {
+        Rc::clone(&self.peripherals)
+    }
+
+    // Every device on the bus in the order it's asked, RAM last, with its traffic and the cycles
+    // it's been ticked for, to see where the work goes
+    pub fn stats(&self) -> Vec<DeviceStats> {
+        let bus = self.bus.borrow();
+        bus.get_devices()
+            .iter()
+            .zip(bus.get_traffic())
+            .enumerate()
+            .map(|(i, (device, traffic))| DeviceStats {
+                name: device.borrow().debug_view().map_or_else(|| format!("device {}", i), |view| view.get_name()),
+                reads: traffic.reads,
+                writes: traffic.writes,
+                cycles: self.peripherals.borrow().get_serviced_cycles(device),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::bus::{Address, Data};
+use crate::system::{System, DEFAULT_CLOCK_HZ};
+
+// The workload: loads, arithmetic, compares and stores in the immediate, zero page, indexed and
+// absolute modes, repeated through memory from BENCH_START. There are no jumps or branches in
+// the processor yet, so the bench puts the pc back at the start whenever it runs off the end
+const BENCH_BLOCK: [Data; 32] = [
+    0xa2, 0x00, // LDX #$00
+    0xa0, 0x10, // LDY #$10
+    0xa9, 0x55, // LDA #$55
+    0x69, 0x01, // ADC #$01
+    0x95, 0x10, // STA $10,X
+    0xa5, 0x10, // LDA $10
+    0xc9, 0x56, // CMP #$56
+    0x29, 0x0f, // AND #$0F
+    0x09, 0x80, // ORA #$80
+    0x49, 0xff, // EOR #$FF
+    0xe9, 0x01, // SBC #$01
+    0x84, 0x20, // STY $20
+    0xad, 0x00, 0x03, // LDA $0300
+    0xe0, 0x00, // CPX #$00
+    0xc0, 0x10, // CPY #$10
+    0xea, // NOP
+];
+const BENCH_START: Address = 0x0400;
+const BENCH_BLOCKS: usize = 64;
+// Instructions between looks at the clock
+const BATCH: usize = 10_000;
+
+pub struct BenchReport {
+    pub cycles: usize,
+    pub instructions: usize,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn cycles_per_second(&self) -> f64 {
+        self.cycles as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    pub fn mhz(&self) -> f64 {
+        self.cycles_per_second() / 1e6
+    }
+}
+
+// "12000000 cycles (6000000 instructions) in 2.00s: 6.00 MHz, 6.0x a 1 MHz 6502"
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} cycles ({} instructions) in {:.2}s: {:.2} MHz, {:.1}x a 1 MHz 6502",
+            self.cycles,
+            self.instructions,
+            self.elapsed.as_secs_f64(),
+            self.mhz(),
+            self.cycles_per_second() / DEFAULT_CLOCK_HZ as f64
+        )
+    }
+}
+
+// A 64K System with the workload loaded and the pc at its start
+pub fn bench_system() -> System {
+    let mut system = System::new();
+    system.load(BENCH_START, &BENCH_BLOCK.repeat(BENCH_BLOCKS));
+    system.set_reset_vector(BENCH_START);
+    // the boot sequence
+    system.step();
+    system
+}
+
+// Runs the standard workload unthrottled for about duration and reports the emulated speed, for
+// measuring changes to the core. Build with --release for meaningful numbers
+pub fn bench(duration: Duration) -> BenchReport {
+    let mut system = bench_system();
+    let end = BENCH_START + (BENCH_BLOCK.len() * BENCH_BLOCKS) as Address;
+    let start_cycles = system.get_total_cycles();
+    let mut instructions = 0;
+    let started = Instant::now();
+    loop {
+        for _ in 0..BATCH {
+            let mut registers = system.get_registers();
+            if registers.pc >= end {
+                registers.pc = BENCH_START;
+                system.set_registers(&registers);
+            }
+            system.step();
+        }
+        instructions += BATCH;
+        if started.elapsed() >= duration {
+            break;
+        }
+    }
+    BenchReport {
+        cycles: system.get_total_cycles() - start_cycles,
+        instructions,
+        elapsed: started.elapsed(),
+    }
+}
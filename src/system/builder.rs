@@ -0,0 +1,190 @@
+use std::fs;
+use std::io;
+use std::ops::{Bound, RangeBounds};
+use std::path::PathBuf;
+
+use crate::bus::{Address, BusDevice, Data};
+use crate::devices::Peripheral;
+use crate::memory::{Memory, Rom};
+use crate::system::{System, DEFAULT_CLOCK_HZ};
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "config", derive(serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum CpuModel {
+    #[default]
+    Nmos6502,
+    // a 6502 with only 13 address lines, as in the Atari 2600
+    Mos6507,
+}
+
+impl CpuModel {
+    pub fn get_address_mask(&self) -> Address {
+        match self {
+            CpuModel::Nmos6502 => 0xffff,
+            CpuModel::Mos6507 => 0x1fff,
+        }
+    }
+}
+
+enum RomImage {
+    Data(Vec<Data>),
+    File(PathBuf),
+}
+
+struct RomSpec {
+    start: Address,
+    // the last address the ROM may use, when the range gave one
+    end: Option<Address>,
+    image: RomImage,
+}
+
+type AddDevice = Box<dyn FnOnce(&mut System)>;
+
+// Declarative machine setup:
+//   SystemBuilder::new().cpu(CpuModel::Nmos6502).ram(0x0000..=0x7fff).rom_file(0xc000.., "rom.bin")
+//       .device(0xd010, Acia::stdio(0xd010)).reset_vector(0xc000).build()?
+// Devices go ahead of the ROMs, and the ROMs ahead of the RAM, on the bus
+pub struct SystemBuilder {
+    cpu: CpuModel,
+    ram: (Address, Address),
+    clock_hz: u64,
+    roms: Vec<RomSpec>,
+    devices: Vec<AddDevice>,
+    checks: Vec<(Address, bool)>,
+    reset_vector: Option<Address>,
+}
+
+impl Default for SystemBuilder {
+    fn default() -> Self {
+        SystemBuilder::new()
+    }
+}
+
+fn bounds(range: &impl RangeBounds<Address>) -> (Address, Option<Address>) {
+    let start = match range.start_bound() {
+        Bound::Included(s) => *s,
+        Bound::Excluded(s) => s.saturating_add(1),
+        Bound::Unbounded => 0x0000,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(e) => Some(*e),
+        Bound::Excluded(e) => Some(e.saturating_sub(1)),
+        Bound::Unbounded => None,
+    };
+    (start, end)
+}
+
+impl SystemBuilder {
+    // A 6502 with 64K of RAM unless ram() says otherwise
+    pub fn new() -> SystemBuilder {
+        SystemBuilder {
+            cpu: CpuModel::default(),
+            ram: (0x0000, 0xffff),
+            clock_hz: DEFAULT_CLOCK_HZ,
+            roms: vec![],
+            devices: vec![],
+            checks: vec![],
+            reset_vector: None,
+        }
+    }
+
+    pub fn cpu(mut self, cpu: CpuModel) -> SystemBuilder {
+        self.cpu = cpu;
+        self
+    }
+
+    pub fn ram(mut self, range: impl RangeBounds<Address>) -> SystemBuilder {
+        let (start, end) = bounds(&range);
+        self.ram = (start, end.unwrap_or(0xffff));
+        self
+    }
+
+    pub fn clock_hz(mut self, clock_hz: u64) -> SystemBuilder {
+        self.clock_hz = clock_hz;
+        self
+    }
+
+    pub fn rom(mut self, range: impl RangeBounds<Address>, data: Vec<Data>) -> SystemBuilder {
+        let (start, end) = bounds(&range);
+        self.roms.push(RomSpec {
+            start,
+            end,
+            image: RomImage::Data(data),
+        });
+        self
+    }
+
+    // The file is read by build()
+    pub fn rom_file(mut self, range: impl RangeBounds<Address>, path: impl Into<PathBuf>) -> SystemBuilder {
+        let (start, end) = bounds(&range);
+        self.roms.push(RomSpec {
+            start,
+            end,
+            image: RomImage::File(path.into()),
+        });
+        self
+    }
+
+    // A device that answers at address, which build() checks
+    pub fn device<D: BusDevice + 'static>(mut self, address: Address, device: D) -> SystemBuilder {
+        let answers = device.is_readable_for(address) || device.is_writable_for(address);
+        self.checks.push((address, answers));
+        self.devices.push(Box::new(move |system: &mut System| {
+            system.add_device(device);
+        }));
+        self
+    }
+
+    // A device that is also ticked, reset and listened to for IRQs
+    pub fn peripheral<P: Peripheral + 'static>(mut self, address: Address, device: P) -> SystemBuilder {
+        let answers = device.is_readable_for(address) || device.is_writable_for(address);
+        self.checks.push((address, answers));
+        self.devices.push(Box::new(move |system: &mut System| {
+            system.add_peripheral(device);
+        }));
+        self
+    }
+
+    pub fn reset_vector(mut self, address: Address) -> SystemBuilder {
+        self.reset_vector = Some(address);
+        self
+    }
+
+    pub fn build(self) -> io::Result<System> {
+        if let Some((address, _)) = self.checks.iter().find(|(_, answers)| !answers) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("device doesn't answer at ${:04X}", address),
+            ));
+        }
+        let (start, end) = self.ram;
+        if end < start {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty RAM range"));
+        }
+        let mut system = System::with_memory(Memory::new(start, end));
+        system.set_address_mask(self.cpu.get_address_mask());
+        system.set_clock_hz(self.clock_hz);
+        for add in self.devices {
+            add(&mut system);
+        }
+        for rom in self.roms {
+            let data = match rom.image {
+                RomImage::Data(data) => data,
+                RomImage::File(path) => fs::read(&path)?,
+            };
+            let last = rom.start as usize + data.len().max(1) - 1;
+            let limit = rom.end.map_or(0xffff, |e| e as usize);
+            if last > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} byte ROM at ${:04X} runs past ${:04X}", data.len(), rom.start, limit),
+                ));
+            }
+            system.add_device(Rom::new(rom.start, data));
+        }
+        if let Some(address) = self.reset_vector {
+            system.set_reset_vector(address);
+        }
+        Ok(system)
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Sleeping for less than this isn't worth the system call, the time is made up next round
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+// Further behind than this (a paused debugger, a slow host) and the clock starts afresh rather
+// than running flat out to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// Paces emulation to a clock rate against the wall clock. Cycles are reported with advance(),
+// which sleeps whenever the emulation has got ahead. The target is always worked out from
+// when the clock started, so rounding in one sleep is corrected by the next rather than
+// adding up. An unthrottled clock only counts
+pub struct Clock {
+    hz: Option<u64>,
+    epoch: Instant,
+    // cycles since epoch
+    cycles: u64,
+    total: u64,
+}
+
+impl Clock {
+    // e.g. Clock::new(1_023_000) for an Apple II
+    pub fn new(hz: u64) -> Clock {
+        Clock {
+            hz: Some(hz.max(1)),
+            epoch: Instant::now(),
+            cycles: 0,
+            total: 0,
+        }
+    }
+
+    // Runs as fast as the host can
+    pub fn unthrottled() -> Clock {
+        Clock {
+            hz: None,
+            ..Clock::new(1)
+        }
+    }
+
+    // None for unthrottled
+    pub fn set_rate(&mut self, hz: Option<u64>) {
+        self.hz = hz.map(|hz| hz.max(1));
+        self.resync();
+    }
+
+    pub fn get_rate(&self) -> Option<u64> {
+        self.hz
+    }
+
+    pub fn get_total_cycles(&self) -> u64 {
+        self.total
+    }
+
+    // Forget how far ahead or behind the emulation is, e.g. after a pause
+    pub fn resync(&mut self) {
+        self.epoch = Instant::now();
+        self.cycles = 0;
+    }
+
+    // How far ahead of the wall clock the emulation is, negative when behind
+    pub fn get_lead(&self) -> f64 {
+        match self.hz {
+            Some(hz) => self.cycles as f64 / hz as f64 - self.epoch.elapsed().as_secs_f64(),
+            None => 0.0,
+        }
+    }
+
+    pub fn advance(&mut self, cycles: usize) {
+        self.total += cycles as u64;
+        let hz = match self.hz {
+            Some(hz) => hz,
+            None => return,
+        };
+        self.cycles += cycles as u64;
+        let due = Duration::from_nanos((self.cycles as u128 * 1_000_000_000 / hz as u128) as u64);
+        let elapsed = self.epoch.elapsed();
+        if due > elapsed {
+            let ahead = due - elapsed;
+            if ahead >= MIN_SLEEP {
+                thread::sleep(ahead);
+            }
+        } else if elapsed - due > MAX_LAG {
+            self.resync();
+        }
+    }
+}
+
+// How fast a run goes, switchable while it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Speed {
+    #[default]
+    Max,
+    // at the machine's clock rate
+    RealTime,
+    // this many cycles a second, e.g. 10 to watch a program crawl for a demo
+    SlowMotion(u64),
+    // one instruction per run or continue
+    SingleStep,
+}
+
+impl Speed {
+    // The Clock rate for a machine clocked at clock_hz, None for as fast as possible
+    pub fn get_rate(&self, clock_hz: u64) -> Option<u64> {
+        match self {
+            Speed::RealTime => Some(clock_hz),
+            Speed::SlowMotion(hz) => Some(*hz),
+            Speed::Max | Speed::SingleStep => None,
+        }
+    }
+
+    // The next speed round, for a key that cycles through them
+    pub fn next(&self) -> Speed {
+        match self {
+            Speed::Max => Speed::RealTime,
+            Speed::RealTime => Speed::SlowMotion(10),
+            Speed::SlowMotion(_) => Speed::SingleStep,
+            Speed::SingleStep => Speed::Max,
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::Max => write!(f, "max"),
+            Speed::RealTime => write!(f, "realtime"),
+            Speed::SlowMotion(hz) => write!(f, "{}", hz),
+            Speed::SingleStep => write!(f, "step"),
+        }
+    }
+}
+
+// max, realtime, step or a slow motion rate in Hz
+impl FromStr for Speed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Speed, String> {
+        match s {
+            "max" => Ok(Speed::Max),
+            "realtime" => Ok(Speed::RealTime),
+            "step" => Ok(Speed::SingleStep),
+            _ => match s.trim_end_matches("hz").parse::<u64>() {
+                Ok(hz) if hz > 0 => Ok(Speed::SlowMotion(hz)),
+                _ => Err(format!("{} isn't max, realtime, step or a rate in Hz", s)),
+            },
+        }
+    }
+}
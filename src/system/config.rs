@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde::Deserialize;
+
+use crate::bus::{Address, BusDevice};
+use crate::devices::acia::Acia;
+use crate::devices::beeper::Beeper;
+use crate::devices::char_out::CharOut;
+use crate::devices::disk::Disk;
+use crate::devices::easy6502::Easy6502Io;
+use crate::devices::framebuffer::Framebuffer;
+use crate::devices::gpio::Gpio;
+use crate::devices::keyboard::Keyboard;
+use crate::devices::pia::Pia;
+use crate::devices::printer::Printer;
+use crate::devices::riot::Riot;
+use crate::devices::rtc::Rtc;
+use crate::devices::text_screen::TextScreen;
+use crate::devices::timer::Timer;
+use crate::devices::watchdog::Watchdog;
+#[cfg(feature = "plugins")]
+use crate::plugin::Plugin;
+use crate::system::{CpuModel, System, SystemBuilder, DEFAULT_CLOCK_HZ};
+
+// A machine layout read from a TOML file, e.g.
+//
+//   clock_hz = 1000000
+//   reset_vector = 0xc000
+//   ram = { start = 0x0000, end = 0x7fff }
+//
+//   [[rom]]
+//   start = 0xc000
+//   file = "rom.bin"
+//
+//   [[device]]
+//   type = "acia"
+//   base = 0xd010
+//
+// Files are found relative to the description file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineConfig {
+    #[serde(default)]
+    pub cpu: CpuModel,
+    pub clock_hz: Option<u64>,
+    pub reset_vector: Option<Address>,
+    pub ram: Option<RamConfig>,
+    #[serde(default)]
+    pub rom: Vec<RomConfig>,
+    #[serde(default)]
+    pub device: Vec<DeviceConfig>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RamConfig {
+    pub start: Address,
+    pub end: Address,
+}
+
+// Without an end the ROM covers the file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomConfig {
+    pub start: Address,
+    pub end: Option<Address>,
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DeviceConfig {
+    // on the host terminal, or a TCP port when listen is given
+    Acia { base: Address, listen: Option<String> },
+    Beeper { base: Address },
+    CharOut { base: Address },
+    Disk { base: Address, path: PathBuf },
+    // always at $FE/$FF
+    Easy6502,
+    Framebuffer { base: Address },
+    Gpio { base: Address },
+    Keyboard { base: Address },
+    Pia { base: Address },
+    // a device from a shared object (the plugins feature), given options as the plugin reads them
+    Plugin { base: Address, path: PathBuf, options: Option<String> },
+    Printer { base: Address, path: PathBuf },
+    Riot { ram_base: Address, io_base: Address },
+    Rtc { base: Address },
+    TextScreen { base: Address, registers: Address },
+    Timer { base: Address },
+    Watchdog { base: Address, timeout: usize },
+}
+
+fn invalid(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl MachineConfig {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<MachineConfig> {
+        let path = path.as_ref();
+        let mut config = MachineConfig::parse(&fs::read_to_string(path)?)?;
+        config.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(config)
+    }
+
+    // Files are found relative to the working directory
+    pub fn parse(text: &str) -> io::Result<MachineConfig> {
+        toml::from_str(text).map_err(invalid)
+    }
+
+    pub fn build(&self) -> io::Result<System> {
+        let mut builder = SystemBuilder::new().cpu(self.cpu);
+        if let Some(clock_hz) = self.clock_hz {
+            builder = builder.clock_hz(clock_hz);
+        }
+        if let Some(ram) = &self.ram {
+            builder = builder.ram(ram.start..=ram.end);
+        }
+        for rom in &self.rom {
+            let file = self.dir.join(&rom.file);
+            builder = match rom.end {
+                Some(end) => builder.rom_file(rom.start..=end, file),
+                None => builder.rom_file(rom.start.., file),
+            };
+        }
+        if let Some(address) = self.reset_vector {
+            builder = builder.reset_vector(address);
+        }
+        let clock_hz = self.clock_hz.unwrap_or(DEFAULT_CLOCK_HZ);
+        let mut disks = vec![];
+        for device in &self.device {
+            builder = match device {
+                DeviceConfig::Acia { base, listen: None } => builder.peripheral(*base, Acia::stdio(*base)),
+                DeviceConfig::Acia { base, listen: Some(address) } => {
+                    builder.peripheral(*base, Acia::tcp(*base, address.as_str())?)
+                }
+                DeviceConfig::Beeper { base } => builder.peripheral(*base, Beeper::new(*base, clock_hz)),
+                DeviceConfig::CharOut { base } => builder.peripheral(*base, CharOut::new(*base)),
+                // needs the RAM, so it goes on once the system is built
+                DeviceConfig::Disk { base, path } => {
+                    disks.push((*base, self.dir.join(path)));
+                    builder
+                }
+                DeviceConfig::Easy6502 => builder.peripheral(0xfe, Easy6502Io::new()),
+                DeviceConfig::Framebuffer { base } => builder.peripheral(*base, Framebuffer::new(*base)),
+                DeviceConfig::Gpio { base } => builder.peripheral(*base, Gpio::new(*base)),
+                DeviceConfig::Keyboard { base } => builder.peripheral(*base, Keyboard::new(*base)),
+                DeviceConfig::Pia { base } => builder.peripheral(*base, Pia::new(*base)),
+                #[cfg(feature = "plugins")]
+                DeviceConfig::Plugin { base, path, options } => {
+                    let plugin = Plugin::load(self.dir.join(path)).map_err(invalid)?;
+                    builder.peripheral(*base, plugin.create(*base, options.as_deref().unwrap_or_default()).map_err(invalid)?)
+                }
+                #[cfg(not(feature = "plugins"))]
+                DeviceConfig::Plugin { .. } => return Err(invalid("plugin devices need the plugins feature")),
+                DeviceConfig::Printer { base, path } => {
+                    builder.peripheral(*base, Printer::open(*base, self.dir.join(path))?)
+                }
+                DeviceConfig::Riot { ram_base, io_base } => builder.peripheral(*io_base, Riot::new(*ram_base, *io_base)),
+                DeviceConfig::Rtc { base } => builder.peripheral(*base, Rtc::new(*base)),
+                DeviceConfig::TextScreen { base, registers } => {
+                    builder.peripheral(*registers, TextScreen::new(*base, *registers))
+                }
+                DeviceConfig::Timer { base } => builder.peripheral(*base, Timer::new(*base)),
+                DeviceConfig::Watchdog { base, timeout } => builder.peripheral(*base, Watchdog::new(*base, *timeout)),
+            };
+        }
+        let mut system = builder.build()?;
+        let ram: Rc<RefCell<dyn BusDevice>> = system.get_memory();
+        for (base, path) in disks {
+            system.add_peripheral(Disk::open(base, path, &ram)?);
+        }
+        Ok(system)
+    }
+}
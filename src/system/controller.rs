@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+
+use crate::system::{Clock, System};
+
+// Cycles run between looks at the command channel, about a millisecond at 1MHz
+const SLICE: usize = 1_000;
+
+enum Command {
+    Pause,
+    Resume,
+    Step,
+    Stop,
+}
+
+#[derive(Default)]
+struct State {
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    cycles: AtomicU64,
+}
+
+// A pair for running a System on its own thread: the RunLoop goes to the thread that owns the
+// System (which isn't Send, so build it there), the RunController stays with the front end.
+//   let (controller, run_loop) = run_controller();
+//   thread::spawn(move || run_loop.run(&mut build_system(), &mut Clock::new(1_000_000)));
+//   controller.pause();
+pub fn run_controller() -> (RunController, RunLoop) {
+    let (commands, receiver) = channel();
+    let state = Arc::new(State::default());
+    (
+        RunController {
+            commands,
+            state: Arc::clone(&state),
+        },
+        RunLoop {
+            commands: receiver,
+            state,
+        },
+    )
+}
+
+// Pauses, resumes, steps and stops a RunLoop from any thread. Commands are carried out in
+// the order they're sent
+#[derive(Clone)]
+pub struct RunController {
+    commands: Sender<Command>,
+    state: Arc<State>,
+}
+
+impl RunController {
+    pub fn pause(&self) {
+        let _ = self.commands.send(Command::Pause);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.commands.send(Command::Resume);
+    }
+
+    // One instruction, while paused
+    pub fn step(&self) {
+        let _ = self.commands.send(Command::Step);
+    }
+
+    pub fn stop(&self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::Acquire)
+    }
+
+    // True once the loop has returned, stopped or at a BRK
+    pub fn is_stopped(&self) -> bool {
+        self.state.stopped.load(Ordering::Acquire)
+    }
+
+    // Cycles run so far
+    pub fn get_cycles(&self) -> u64 {
+        self.state.cycles.load(Ordering::Acquire)
+    }
+}
+
+// The emulation thread's side: runs the System until it's stopped or the program breaks
+pub struct RunLoop {
+    commands: Receiver<Command>,
+    state: Arc<State>,
+}
+
+impl RunLoop {
+    // Returns the cycles run. Dropping every controller while paused ends the loop too, as
+    // nothing could resume it
+    pub fn run(self, system: &mut System, clock: &mut Clock) -> usize {
+        let mut run = 0;
+        while !system.is_halted() {
+            let paused = self.state.paused.load(Ordering::Acquire);
+            let command = if paused {
+                match self.commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => break,
+                }
+            } else {
+                match self.commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            match command {
+                Some(Command::Pause) => self.state.paused.store(true, Ordering::Release),
+                Some(Command::Resume) => {
+                    // no racing to make up the time spent paused
+                    clock.resync();
+                    self.state.paused.store(false, Ordering::Release);
+                }
+                Some(Command::Step) if paused => run += system.step(),
+                Some(Command::Step) => (),
+                Some(Command::Stop) => break,
+                None => run += system.run_clocked(SLICE, clock),
+            }
+            self.state.cycles.store(run as u64, Ordering::Release);
+        }
+        self.state.cycles.store(run as u64, Ordering::Release);
+        self.state.stopped.store(true, Ordering::Release);
+        run
+    }
+}
@@ -0,0 +1,308 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::bus::Data;
+use crate::devices::acia::SerialBackend;
+use crate::devices::interrupt_controller::IrqLine;
+use crate::devices::keyboard::KeySource;
+use crate::system::{Scheduler, System};
+
+// One outside input to the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(Data),
+    Serial(Data),
+    // the new level of an IRQ line
+    Irq(bool),
+}
+
+// An input, the cycle it arrived on and the name of the source it came through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputRecord {
+    pub cycle: usize,
+    pub source: String,
+    pub input: Input,
+}
+
+// One line of a recording: cycle, source, kind and value, e.g. "10234 terminal serial 41"
+impl fmt::Display for InputRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.input {
+            Input::Key(key) => write!(f, "{} {} key {:02X}", self.cycle, self.source, key),
+            Input::Serial(data) => write!(f, "{} {} serial {:02X}", self.cycle, self.source, data),
+            Input::Irq(level) => write!(f, "{} {} irq {}", self.cycle, self.source, level as u8),
+        }
+    }
+}
+
+impl InputRecord {
+    pub fn parse(line: &str) -> Option<InputRecord> {
+        let mut words = line.split_whitespace();
+        let cycle = words.next()?.parse().ok()?;
+        let source = words.next()?.to_string();
+        let kind = words.next()?;
+        let value = Data::from_str_radix(words.next()?, 16).ok()?;
+        let input = match kind {
+            "key" => Input::Key(value),
+            "serial" => Input::Serial(value),
+            "irq" => Input::Irq(value != 0),
+            _ => return None,
+        };
+        Some(InputRecord { cycle, source, input })
+    }
+}
+
+// Where recordings and replays get the time: the System's scheduler once attached, cycle 0
+// before that (so sources can be made before the System they go into)
+#[derive(Default, Clone)]
+struct Now(Rc<RefCell<Option<Rc<RefCell<Scheduler>>>>>);
+
+impl Now {
+    fn attach(&self, system: &System) {
+        *self.0.borrow_mut() = Some(system.get_scheduler());
+    }
+
+    fn get(&self) -> usize {
+        self.0.borrow().as_ref().map_or(0, |scheduler| scheduler.borrow().get_now())
+    }
+}
+
+#[derive(Default)]
+struct Log {
+    records: Vec<InputRecord>,
+    file: Option<File>,
+}
+
+// Records every input that comes through its sources with the cycle it was taken on. The
+// emulation is deterministic, so feeding the same inputs on the same cycles (see Replay)
+// reproduces a run exactly. Wrap each source, attach the System and run:
+//   let recorder = Recorder::to_file("run.inputs")?;
+//   let apple1 = Apple1::with_terminal(rom, Box::new(recorder.serial("terminal", terminal)))?;
+//   recorder.attach(&apple1.system);
+#[derive(Clone, Default)]
+pub struct Recorder {
+    now: Now,
+    log: Rc<RefCell<Log>>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    // Also writes each record to a file as it happens, so nothing is lost if the run is killed
+    pub fn to_file(path: impl AsRef<Path>) -> io::Result<Recorder> {
+        let recorder = Recorder::new();
+        recorder.log.borrow_mut().file = Some(File::create(path)?);
+        Ok(recorder)
+    }
+
+    pub fn attach(&self, system: &System) {
+        self.now.attach(system);
+    }
+
+    pub fn get_records(&self) -> Vec<InputRecord> {
+        self.log.borrow().records.clone()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text: String = self.log.borrow().records.iter().map(|r| format!("{}\n", r)).collect();
+        fs::write(path, text)
+    }
+
+    fn record(&self, source: &str, input: Input) {
+        let record = InputRecord {
+            cycle: self.now.get(),
+            source: source.to_string(),
+            input,
+        };
+        let mut log = self.log.borrow_mut();
+        if let Some(file) = &mut log.file {
+            let _ = writeln!(file, "{}", record);
+        }
+        log.records.push(record);
+    }
+
+    pub fn serial(&self, source: &str, backend: Box<dyn SerialBackend>) -> RecordingSerial {
+        RecordingSerial {
+            recorder: self.clone(),
+            source: source.to_string(),
+            backend,
+        }
+    }
+
+    pub fn keys(&self, source: &str, keys: Box<dyn KeySource>) -> RecordingKeys {
+        RecordingKeys {
+            recorder: self.clone(),
+            source: source.to_string(),
+            keys,
+        }
+    }
+
+    // Records the line's changes of level
+    pub fn irq(&self, source: &str, line: IrqLine) -> IrqLine {
+        let recorder = self.clone();
+        let source = source.to_string();
+        let level = RefCell::new(false);
+        Box::new(move || {
+            let asserted = line();
+            if asserted != level.replace(asserted) {
+                recorder.record(&source, Input::Irq(asserted));
+            }
+            asserted
+        })
+    }
+}
+
+pub struct RecordingSerial {
+    recorder: Recorder,
+    source: String,
+    backend: Box<dyn SerialBackend>,
+}
+
+impl SerialBackend for RecordingSerial {
+    fn receive(&mut self) -> Option<Data> {
+        let data = self.backend.receive()?;
+        self.recorder.record(&self.source, Input::Serial(data));
+        Some(data)
+    }
+
+    fn transmit(&mut self, data: Data) {
+        self.backend.transmit(data);
+    }
+}
+
+pub struct RecordingKeys {
+    recorder: Recorder,
+    source: String,
+    keys: Box<dyn KeySource>,
+}
+
+impl KeySource for RecordingKeys {
+    fn next_key(&mut self) -> Option<Data> {
+        let key = self.keys.next_key()?;
+        self.recorder.record(&self.source, Input::Key(key));
+        Some(key)
+    }
+}
+
+// Plays a recording back: its sources hand out each input once the System reaches the cycle
+// it was recorded on. Sources are matched to the recording by name
+#[derive(Clone)]
+pub struct Replay {
+    now: Now,
+    records: Rc<Vec<InputRecord>>,
+}
+
+impl Replay {
+    pub fn new(records: Vec<InputRecord>) -> Replay {
+        Replay {
+            now: Now::default(),
+            records: Rc::new(records),
+        }
+    }
+
+    // Blank lines and lines starting with # are skipped
+    pub fn parse(text: &str) -> io::Result<Replay> {
+        let mut records = vec![];
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let record = InputRecord::parse(line)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", n + 1, line)))?;
+            records.push(record);
+        }
+        Ok(Replay::new(records))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Replay> {
+        Replay::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn attach(&self, system: &System) {
+        self.now.attach(system);
+    }
+
+    fn inputs(&self, source: &str) -> VecDeque<(usize, Input)> {
+        self.records.iter().filter(|r| r.source == source).map(|r| (r.cycle, r.input)).collect()
+    }
+
+    // Received bytes come from the recording; transmitted ones still go to output
+    pub fn serial(&self, source: &str, output: Box<dyn SerialBackend>) -> ReplaySerial {
+        ReplaySerial {
+            now: self.now.clone(),
+            inputs: self.inputs(source),
+            output,
+        }
+    }
+
+    pub fn keys(&self, source: &str) -> ReplayKeys {
+        ReplayKeys {
+            now: self.now.clone(),
+            inputs: self.inputs(source),
+        }
+    }
+
+    pub fn irq(&self, source: &str) -> IrqLine {
+        let now = self.now.clone();
+        let inputs = RefCell::new(self.inputs(source));
+        let level = RefCell::new(false);
+        Box::new(move || {
+            let mut inputs = inputs.borrow_mut();
+            while let Some((_, Input::Irq(asserted))) = inputs.front().filter(|(cycle, _)| *cycle <= now.get()) {
+                *level.borrow_mut() = *asserted;
+                inputs.pop_front();
+            }
+            let asserted = *level.borrow();
+            asserted
+        })
+    }
+}
+
+// The next input if it's due
+fn take_due(now: &Now, inputs: &mut VecDeque<(usize, Input)>) -> Option<Input> {
+    match inputs.front() {
+        Some((cycle, _)) if *cycle <= now.get() => inputs.pop_front().map(|(_, input)| input),
+        _ => None,
+    }
+}
+
+pub struct ReplaySerial {
+    now: Now,
+    inputs: VecDeque<(usize, Input)>,
+    output: Box<dyn SerialBackend>,
+}
+
+impl SerialBackend for ReplaySerial {
+    fn receive(&mut self) -> Option<Data> {
+        match take_due(&self.now, &mut self.inputs)? {
+            Input::Serial(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    fn transmit(&mut self, data: Data) {
+        self.output.transmit(data);
+    }
+}
+
+pub struct ReplayKeys {
+    now: Now,
+    inputs: VecDeque<(usize, Input)>,
+}
+
+impl KeySource for ReplayKeys {
+    fn next_key(&mut self) -> Option<Data> {
+        match take_due(&self.now, &mut self.inputs)? {
+            Input::Key(key) => Some(key),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::bus::{Address, Data};
+use crate::processor::Registers;
+use crate::system::{Clock, Speed, System};
+
+// What a front end can ask of the emulation thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    LoadProgram { address: Address, data: Vec<Data> },
+    SetBreakpoint(Address),
+    ClearBreakpoint(Address),
+    // a TraceRecord for every instruction, or not
+    Trace(bool),
+    // paces the clock from now on; SingleStep makes Run a Step
+    SetSpeed(Speed),
+    Run,
+    Pause,
+    // one instruction, while stopped
+    Step,
+    Reset,
+    ReadMemory { address: Address, length: usize },
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Paused,
+    Stepped,
+    Breakpoint,
+    // the program hit BRK
+    Halted,
+}
+
+// What the emulation thread reports back
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Stopped { reason: StopReason, registers: Registers },
+    // the registers after an instruction and the cycles it took
+    TraceRecord { registers: Registers, cycles: usize },
+    // a frame's worth of cycles has run, cycles being the total so far
+    FrameReady { cycles: usize },
+    Memory { address: Address, data: Vec<Data> },
+}
+
+// Owns a System on a background thread, taking Commands and sending Events over channels.
+// The System isn't Send, so the thread builds it. It starts stopped; frames are a 60th of a
+// second at the clock's rate, or the System's when unthrottled
+pub struct Runner {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Runner {
+    pub fn spawn<F>(build: F, clock: Clock) -> Runner
+    where
+        F: FnOnce() -> System + Send + 'static,
+    {
+        let (commands, command_receiver) = channel();
+        let (event_sender, events) = channel();
+        let thread = thread::spawn(move || {
+            let mut emulation = Emulation {
+                system: build(),
+                clock,
+                commands: command_receiver,
+                events: event_sender,
+                breakpoints: HashSet::new(),
+                trace: false,
+                speed: None,
+                running: false,
+                cycles: 0,
+            };
+            emulation.run();
+        });
+        Runner {
+            commands,
+            events,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    // A sender for other threads
+    pub fn get_sender(&self) -> Sender<Command> {
+        self.commands.clone()
+    }
+
+    pub fn get_events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+}
+
+impl Drop for Runner {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Emulation {
+    system: System,
+    clock: Clock,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    breakpoints: HashSet<Address>,
+    trace: bool,
+    // None until SetSpeed, leaving the clock as spawned
+    speed: Option<Speed>,
+    running: bool,
+    cycles: usize,
+}
+
+impl Emulation {
+    fn run(&mut self) {
+        loop {
+            let command = if self.running {
+                match self.commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            } else {
+                match self.commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                }
+            };
+            let ok = match command {
+                Some(Command::Quit) => return,
+                Some(command) => self.handle(command),
+                None => self.frame(),
+            };
+            // nobody is listening any more
+            if !ok {
+                return;
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) -> bool {
+        match command {
+            Command::LoadProgram { address, data } => self.system.load(address, &data),
+            Command::SetBreakpoint(address) => {
+                self.breakpoints.insert(address);
+            }
+            Command::ClearBreakpoint(address) => {
+                self.breakpoints.remove(&address);
+            }
+            Command::Trace(trace) => self.trace = trace,
+            Command::SetSpeed(speed) => {
+                self.speed = Some(speed);
+                self.clock.set_rate(speed.get_rate(self.system.get_clock_hz()));
+                if speed == Speed::SingleStep && self.running {
+                    return self.stop(StopReason::Paused);
+                }
+            }
+            Command::Run if self.speed == Some(Speed::SingleStep) => return self.handle(Command::Step),
+            Command::Run if !self.system.is_halted() => {
+                self.clock.resync();
+                self.running = true;
+            }
+            Command::Run => return self.stop(StopReason::Halted),
+            Command::Pause if self.running => return self.stop(StopReason::Paused),
+            Command::Pause => (),
+            Command::Step if !self.running => {
+                if !self.step() {
+                    return false;
+                }
+                let reason = if self.system.is_halted() { StopReason::Halted } else { StopReason::Stepped };
+                return self.stop(reason);
+            }
+            Command::Step => (),
+            Command::Reset => {
+                self.system.reset();
+                self.cycles = 0;
+            }
+            Command::ReadMemory { address, length } => {
+                let data = (0..length).map(|i| self.system.read(address.wrapping_add(i as Address))).collect();
+                return self.send(Event::Memory { address, data });
+            }
+            Command::Quit => (),
+        }
+        true
+    }
+
+    fn step(&mut self) -> bool {
+        let cycles = self.system.step();
+        self.cycles += cycles;
+        self.clock.advance(cycles);
+        !self.trace || self.send(Event::TraceRecord {
+            registers: self.system.get_registers(),
+            cycles,
+        })
+    }
+
+    // Runs up to a frame, stopping early at a breakpoint or BRK
+    fn frame(&mut self) -> bool {
+        let rate = self.clock.get_rate().unwrap_or(self.system.get_clock_hz());
+        let budget = (rate / 60).max(1) as usize;
+        let mut run = 0;
+        while run < budget {
+            let before = self.cycles;
+            if !self.step() {
+                return false;
+            }
+            run += self.cycles - before;
+            if self.system.is_halted() {
+                return self.stop(StopReason::Halted);
+            }
+            if self.breakpoints.contains(&self.system.get_registers().pc) {
+                return self.stop(StopReason::Breakpoint);
+            }
+        }
+        self.send(Event::FrameReady { cycles: self.cycles })
+    }
+
+    fn stop(&mut self, reason: StopReason) -> bool {
+        self.running = false;
+        self.send(Event::Stopped {
+            reason,
+            registers: self.system.get_registers(),
+        })
+    }
+
+    fn send(&self, event: Event) -> bool {
+        self.events.send(event).is_ok()
+    }
+}
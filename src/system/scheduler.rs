@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::rc::Rc;
+
+use crate::system::System;
+
+pub type Callback = Box<dyn FnOnce(&mut System)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventId(u64);
+
+// Callbacks due at a cycle count, run by the System on the cycle they fall due, part way
+// through an instruction if need be. Several due on the same cycle run in the order they
+// were scheduled. A callback that wants to repeat schedules itself again. Devices can hold
+// the System's scheduler (System::get_scheduler) to schedule from inside reads and writes
+#[derive(Default)]
+pub struct Scheduler {
+    timeline: Rc<Timeline>,
+    next_id: u64,
+    // (due, id), ids breaking ties in scheduling order
+    queue: BinaryHeap<Reverse<(usize, u64)>>,
+    callbacks: HashMap<u64, Callback>,
+}
+
+// What the System's step loop needs from the scheduler every cycle, shared in Cells so it can
+// keep the time and check for a callback without borrowing the scheduler
+pub(crate) struct Timeline {
+    // the System's total cycles as of the last tick
+    pub(crate) now: Cell<usize>,
+    // no later than the cycle the next callback is due on
+    pub(crate) due: Cell<usize>,
+}
+
+impl Default for Timeline {
+    fn default() -> Timeline {
+        Timeline {
+            now: Cell::new(0),
+            due: Cell::new(usize::MAX),
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    pub fn get_now(&self) -> usize {
+        self.timeline.now.get()
+    }
+
+    pub(crate) fn get_timeline(&self) -> Rc<Timeline> {
+        Rc::clone(&self.timeline)
+    }
+
+    // At an absolute cycle count. One already past runs on the next cycle
+    pub fn schedule_at<F: FnOnce(&mut System) + 'static>(&mut self, cycle: usize, callback: F) -> EventId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push(Reverse((cycle, id)));
+        self.callbacks.insert(id, Box::new(callback));
+        self.timeline.due.set(self.timeline.due.get().min(cycle));
+        EventId(id)
+    }
+
+    // `cycles` from now, e.g. schedule_in(17030, ...) for the next frame
+    pub fn schedule_in<F: FnOnce(&mut System) + 'static>(&mut self, cycles: usize, callback: F) -> EventId {
+        self.schedule_at(self.get_now() + cycles, callback)
+    }
+
+    // False if it has already run or been cancelled
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        self.callbacks.remove(&id.0).is_some()
+    }
+
+    // The cycle the next callback is due on
+    pub fn next_due(&mut self) -> Option<usize> {
+        while let Some(Reverse((due, id))) = self.queue.peek() {
+            if self.callbacks.contains_key(id) {
+                self.timeline.due.set(*due);
+                return Some(*due);
+            }
+            // cancelled
+            self.queue.pop();
+        }
+        self.timeline.due.set(usize::MAX);
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.callbacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    pub(crate) fn set_now(&mut self, now: usize) {
+        self.timeline.now.set(now);
+    }
+
+    // The next callback due by now, taken off the queue
+    pub(crate) fn pop_due(&mut self) -> Option<Callback> {
+        match self.next_due() {
+            Some(due) if due <= self.get_now() => {
+                let Reverse((_, id)) = self.queue.pop()?;
+                let callback = self.callbacks.remove(&id);
+                self.next_due();
+                callback
+            }
+            _ => None,
+        }
+    }
+}
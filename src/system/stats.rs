@@ -0,0 +1,24 @@
+use std::fmt;
+
+// One device's share of the work, from System::stats
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceStats {
+    // the device's name in the debugger, or "device N" for one without a DebugView
+    pub name: String,
+    // reads it answered and writes it was given, the RAM's included
+    pub reads: u64,
+    pub writes: u64,
+    // the cycles it's been ticked for, None for a device that isn't a peripheral
+    pub cycles: Option<u64>,
+}
+
+// "acia: 120 reads, 4 writes, 10000 cycles"
+impl fmt::Display for DeviceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} reads, {} writes", self.name, self.reads, self.writes)?;
+        if let Some(cycles) = self.cycles {
+            write!(f, ", {} cycles", cycles)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::bus::Address;
+use crate::processor::create_instruction_table;
+use crate::system::System;
+
+// Instructions between looks at the clock
+const BATCH: usize = 1_000;
+
+// Why a watched run stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchdogStop {
+    // the program reached a BRK, left unexecuted, or halted at one
+    Break,
+    // an instruction jumped or branched to itself, here
+    Trap(Address),
+    // an opcode the processor doesn't have, left unexecuted here
+    UnknownOpcode(Address),
+    CycleLimit,
+    Timeout,
+}
+
+impl WatchdogStop {
+    // The process exit code for scripted runs: 0 for BRK, 1 being left for errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WatchdogStop::Break => 0,
+            WatchdogStop::Trap(_) => 2,
+            WatchdogStop::UnknownOpcode(_) => 3,
+            WatchdogStop::CycleLimit => 4,
+            WatchdogStop::Timeout => 5,
+        }
+    }
+}
+
+impl WatchdogStop {
+    // For machine readable output
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchdogStop::Break => "break",
+            WatchdogStop::Trap(_) => "trap",
+            WatchdogStop::UnknownOpcode(_) => "unknown_opcode",
+            WatchdogStop::CycleLimit => "cycle_limit",
+            WatchdogStop::Timeout => "timeout",
+        }
+    }
+
+    // Where the program stopped, for the stops that have somewhere
+    pub fn get_address(&self) -> Option<Address> {
+        match self {
+            WatchdogStop::Trap(address) | WatchdogStop::UnknownOpcode(address) => Some(*address),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WatchdogStop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchdogStop::Break => write!(f, "at BRK"),
+            WatchdogStop::Trap(address) => write!(f, "trapped at ${:04X}", address),
+            WatchdogStop::UnknownOpcode(address) => write!(f, "unknown opcode at ${:04X}", address),
+            WatchdogStop::CycleLimit => write!(f, "out of cycles"),
+            WatchdogStop::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogReport {
+    pub stop: WatchdogStop,
+    pub cycles: usize,
+    pub instructions: usize,
+    pub elapsed: Duration,
+}
+
+impl WatchdogReport {
+    // The System's state after the run as JSON, for scripts to assert on. Numbers are decimal
+    // and ranges are inclusive:
+    //   {"stop": "trap", "stop_address": 515, "cycles": 1234, "instructions": 456,
+    //    "registers": {"pc": 515, "a": 0, "x": 0, "y": 0, "p": 0},
+    //    "memory": [{"start": 0, "end": 3, "data": [1, 2, 3, 4]}]}
+    pub fn state_json(&self, system: &System, ranges: &[(Address, Address)]) -> String {
+        let registers = system.get_registers();
+        let mut json = format!("{{\n  \"stop\": \"{}\",\n", self.stop.name());
+        if let Some(address) = self.stop.get_address() {
+            json += &format!("  \"stop_address\": {},\n", address);
+        }
+        json += &format!("  \"cycles\": {},\n  \"instructions\": {},\n", self.cycles, self.instructions);
+        json += &format!(
+            "  \"registers\": {{\"pc\": {}, \"a\": {}, \"x\": {}, \"y\": {}, \"p\": {}}},\n",
+            registers.pc, registers.a, registers.x, registers.y, registers.status
+        );
+        let memory: Vec<String> = ranges
+            .iter()
+            .map(|&(start, end)| {
+                let data: Vec<String> = (start..=end).map(|address| system.read(address).to_string()).collect();
+                format!("    {{\"start\": {}, \"end\": {}, \"data\": [{}]}}", start, end, data.join(", "))
+            })
+            .collect();
+        if memory.is_empty() {
+            json += "  \"memory\": []\n}\n";
+        } else {
+            json += &format!("  \"memory\": [\n{}\n  ]\n}}\n", memory.join(",\n"));
+        }
+        json
+    }
+}
+
+// "1234 cycles (456 instructions), trapped at $0203"
+impl fmt::Display for WatchdogReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} cycles ({} instructions), {}", self.cycles, self.instructions, self.stop)
+    }
+}
+
+// Runs a System unattended until the program breaks, traps or hits an opcode the processor
+// doesn't have, or a cycle or wall clock limit runs out. With no limits it only stops for the
+// program
+#[derive(Default)]
+pub struct Watchdog {
+    max_cycles: Option<usize>,
+    timeout: Option<Duration>,
+}
+
+impl Watchdog {
+    pub fn new() -> Watchdog {
+        Watchdog::default()
+    }
+
+    pub fn with_max_cycles(mut self, max_cycles: usize) -> Watchdog {
+        self.max_cycles = Some(max_cycles);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Watchdog {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // A System that hasn't run yet boots first, from its reset vector; cycles count from
+    // after that
+    pub fn run(&self, system: &mut System) -> WatchdogReport {
+        let started = Instant::now();
+        if system.get_total_cycles() == 0 {
+            system.step();
+        }
+        let start_cycles = system.get_total_cycles();
+        let table = create_instruction_table();
+        let mut instructions: usize = 0;
+        let stop = loop {
+            if system.is_halted() {
+                break WatchdogStop::Break;
+            }
+            let pc = system.get_registers().pc;
+            match system.read(pc) {
+                0x00 => break WatchdogStop::Break,
+                opcode if !table.contains(opcode) => break WatchdogStop::UnknownOpcode(pc),
+                _ => (),
+            }
+            system.step();
+            instructions += 1;
+            if system.is_halted() {
+                break WatchdogStop::Break;
+            }
+            if system.get_registers().pc == pc {
+                break WatchdogStop::Trap(pc);
+            }
+            if self.max_cycles.is_some_and(|max| system.get_total_cycles() - start_cycles >= max) {
+                break WatchdogStop::CycleLimit;
+            }
+            if instructions.is_multiple_of(BATCH) && self.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                break WatchdogStop::Timeout;
+            }
+        };
+        WatchdogReport {
+            stop,
+            cycles: system.get_total_cycles() - start_cycles,
+            instructions,
+            elapsed: started.elapsed(),
+        }
+    }
+}
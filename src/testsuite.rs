@@ -0,0 +1,102 @@
+use crate::bus::{Address, Data};
+use crate::disasm::Disassembler;
+use crate::processor::create_instruction_table;
+use crate::system::System;
+
+// Runners for the standard 6502 test programs and test vectors
+pub mod coverage;
+pub mod decimal;
+pub mod fuzz;
+pub mod golden;
+pub mod harte;
+pub mod klaus;
+pub mod nestest;
+
+// Where a test program stopped
+enum Stop {
+    // at one of the end addresses
+    End,
+    Break,
+    // jumped or branched to itself
+    Trap,
+    UnknownOpcode(Data),
+    CycleLimit,
+}
+
+struct Run {
+    stop: Stop,
+    // where it stopped
+    pc: Address,
+    instructions: usize,
+    cycles: usize,
+}
+
+// Puts the pc at start, giving the cycle count there. The boot sequence reads its vector from
+// wherever the program put it, so it runs first and the pc is set by hand after
+fn start_at(system: &mut System, start: Address) -> usize {
+    system.step();
+    let mut registers = system.get_registers();
+    registers.pc = start;
+    system.set_registers(&registers);
+    system.get_total_cycles()
+}
+
+// A trace line in nestest.log's layout, for the state before the instruction at the pc:
+//   C000  A9 00     LDA #$00                       A:00 X:00 Y:00 P:24 CYC:7
+fn trace_line(system: &System, disassembler: &Disassembler, cycles: usize) -> String {
+    let registers = system.get_registers();
+    let line = disassembler.decode(&*system.get_bus().borrow(), registers.pc);
+    format!(
+        "{:<48}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} CYC:{}",
+        line.to_string(),
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.status,
+        cycles
+    )
+}
+
+// Runs a program loaded into a System from start until it reaches an end address, a BRK (if
+// stop_at_brk), a trap, an opcode the processor doesn't have or the cycle limit. before_step
+// sees the System and the cycles run so far ahead of each instruction
+fn run_program(
+    system: &mut System,
+    start: Address,
+    ends: &[Address],
+    stop_at_brk: bool,
+    max_cycles: usize,
+    before_step: &mut dyn FnMut(&System, usize),
+) -> Run {
+    let start_cycles = start_at(system, start);
+    let instructions_table = create_instruction_table();
+    let mut instructions = 0;
+    let stop = loop {
+        let pc = system.get_registers().pc;
+        let opcode = system.read(pc);
+        if ends.contains(&pc) {
+            break Stop::End;
+        }
+        if opcode == 0x00 && stop_at_brk {
+            break Stop::Break;
+        }
+        if !instructions_table.contains(opcode) {
+            break Stop::UnknownOpcode(opcode);
+        }
+        before_step(system, system.get_total_cycles() - start_cycles);
+        system.step();
+        instructions += 1;
+        if system.get_registers().pc == pc {
+            break Stop::Trap;
+        }
+        if system.get_total_cycles() - start_cycles >= max_cycles {
+            break Stop::CycleLimit;
+        }
+    };
+    Run {
+        stop,
+        pc: system.get_registers().pc,
+        instructions,
+        cycles: system.get_total_cycles() - start_cycles,
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+
+use crate::asm::opcodes;
+use crate::processor::AddressingMode::{self, *};
+use crate::processor::DataRegister::{X, Y};
+use crate::processor::{create_instruction_table, InternalOperations};
+use crate::system::System;
+
+// The operand syntax of a mode, e.g. "(zp),Y"
+fn syntax(mode: &AddressingMode) -> &'static str {
+    match mode {
+        Accumulator => "A",
+        Absolute => "abs",
+        AbsIndexed { reg: X } => "abs,X",
+        AbsIndexed { .. } => "abs,Y",
+        Immediate => "#",
+        Implied => "",
+        Indirect => "(abs)",
+        IndexedIndirect => "(zp,X)",
+        IndirectIndexed => "(zp),Y",
+        Relative => "rel",
+        ZeroPage => "zp",
+        ZeroPageIndexed { reg: Y } => "zp,Y",
+        ZeroPageIndexed { .. } => "zp,X",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    // not in the processor's table, so running it panics
+    Missing,
+    // in the table as another instruction or mode
+    Misdecoded { mnemonic: String, mode: AddressingMode },
+    Decoded,
+}
+
+// How far the processor has got with one documented opcode
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodeCoverage {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub status: Status,
+    // the micro-ops still todo!() or empty for the opcode as the table has it: the addressing
+    // mode's fetches, then the instruction's own
+    pub todo: Vec<String>,
+}
+
+impl OpcodeCoverage {
+    pub fn is_complete(&self) -> bool {
+        self.status == Status::Decoded && self.todo.is_empty()
+    }
+}
+
+// "$C0 CPY #       decoded; todo: CompareToRegister"
+impl fmt::Display for OpcodeCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = format!("{} {}", self.mnemonic, syntax(&self.mode));
+        write!(f, "${:02X} {:<12}", self.opcode, name)?;
+        match &self.status {
+            Status::Missing => return write!(f, "missing"),
+            Status::Misdecoded { mnemonic, mode } => write!(f, "misdecoded as {} {}", mnemonic, syntax(mode))?,
+            Status::Decoded => write!(f, "decoded")?,
+        }
+        if !self.todo.is_empty() {
+            write!(f, "; todo: {}", self.todo.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(operation: &InternalOperations) -> String {
+    match operation {
+        InternalOperations::ComputeAndStore { func, .. } => format!("ComputeAndStore {:?}", func),
+        InternalOperations::CompareToRegister { .. } => "CompareToRegister".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+// Every documented NMOS opcode, in opcode order, against the processor's table
+pub fn coverage() -> Vec<OpcodeCoverage> {
+    let table = create_instruction_table();
+    opcodes::documented()
+        .into_iter()
+        .map(|(opcode, mnemonic, mode)| {
+            let Some(instruction) = table.get(opcode) else {
+                return OpcodeCoverage { opcode, mnemonic, mode: *mode, status: Status::Missing, todo: vec![] };
+            };
+            let status = if instruction.get_mnemonic() == mnemonic && instruction.get_addressing() == mode {
+                Status::Decoded
+            } else {
+                Status::Misdecoded {
+                    mnemonic: instruction.get_mnemonic().to_string(),
+                    mode: *instruction.get_addressing(),
+                }
+            };
+            let addressing = instruction.get_addressing();
+            let fetches: Vec<&InternalOperations> =
+                instruction.get_fetch_operations().iter().flat_map(|cycle| cycle.get_operations()).collect();
+            let mut todo = vec![];
+            // a mode with an operand that fetches nothing is still a TODO in the table
+            if addressing.operand_length() > 0 && fetches.is_empty() {
+                todo.push(format!("{} fetch", syntax(addressing)));
+            }
+            todo.extend(fetches.into_iter().filter(|op| !op.is_implemented()).map(describe));
+            todo.extend(instruction.get_execute_operations().iter().filter(|op| !op.is_implemented()).map(describe));
+            OpcodeCoverage { opcode, mnemonic, mode: *mode, status, todo }
+        })
+        .collect()
+}
+
+// Whether the processor carries out an instruction's own operations after its fetches, tried
+// on LDA #$42
+pub fn operations_run() -> bool {
+    let mut system = System::new();
+    system.load(0x0200, &[0xa9, 0x42]);
+    system.set_reset_vector(0x0200);
+    system.step();
+    system.step();
+    system.get_registers().a == 0x42
+}
+
+// "151 documented opcodes: 32 decoded (24 of them complete), 8 misdecoded, 111 missing"
+pub fn summary(coverage: &[OpcodeCoverage]) -> String {
+    let count = |f: &dyn Fn(&OpcodeCoverage) -> bool| coverage.iter().filter(|c| f(c)).count();
+    let mut summary = format!(
+        "{} documented opcodes: {} decoded ({} of them complete), {} misdecoded, {} missing",
+        coverage.len(),
+        count(&|c| c.status == Status::Decoded),
+        count(&|c| c.is_complete()),
+        count(&|c| matches!(c.status, Status::Misdecoded { .. })),
+        count(&|c| c.status == Status::Missing)
+    );
+    if !operations_run() {
+        summary += "\nthe processor only runs instructions' fetch cycles so far, not their operations";
+    }
+    summary
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{run_program, Stop};
+use crate::bus::{Address, Data};
+use crate::processor::Registers;
+use crate::system::System;
+
+// Where the usual build of Bruce Clark's decimal mode test starts, and where it keeps its
+// variables: the operands, the carry and result it got, the result it worked out and ERROR,
+// 0 once every case has passed
+pub const DEFAULT_START: Address = 0x0200;
+pub const DEFAULT_VARIABLES: Address = 0x0000;
+const N1: Address = 0x00;
+const N2: Address = 0x01;
+const DA: Address = 0x04;
+const DNVZC: Address = 0x05;
+const AR: Address = 0x06;
+const ERROR: Address = 0x0b;
+
+// The status bits that ADC and SBC set
+pub const N: Data = 0x80;
+pub const V: Data = 0x40;
+pub const Z: Data = 0x02;
+pub const C: Data = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    // ERROR was set when the program ended
+    Failed,
+    UnknownOpcode { address: Address, opcode: Data },
+    CycleLimit,
+}
+
+pub struct Report {
+    pub outcome: Outcome,
+    // the last case tried, the failing one for a failure: N1, N2 and the carry in (Y)
+    pub operands: (Data, Data, bool),
+    // what the processor gave and what the program worked out it should have
+    pub result: Data,
+    pub expected: Data,
+    // N, V, Z and C as the processor left them
+    pub flags: Data,
+    pub cycles: usize,
+    pub instructions: usize,
+    pub registers: Registers,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (n1, n2, carry) = self.operands;
+        match self.outcome {
+            Outcome::Passed => write!(f, "passed")?,
+            Outcome::Failed => write!(
+                f,
+                "failed on ${:02X} and ${:02X} with carry {}: got ${:02X} (flags ${:02X}), expected ${:02X}",
+                n1, n2, carry as u8, self.result, self.flags, self.expected
+            )?,
+            Outcome::UnknownOpcode { address, opcode } => {
+                write!(f, "stopped at unknown opcode ${:02X} at ${:04X}", opcode, address)?
+            }
+            Outcome::CycleLimit => write!(f, "ran out of cycles")?,
+        }
+        write!(f, " after {} instructions, {} cycles; {}", self.instructions, self.cycles, self.registers)
+    }
+}
+
+// Runs Bruce Clark's decimal mode test (6502.org's "Decimal Mode" tutorial, appendix B, or
+// 6502_decimal_test in Klaus Dormann's collection), which tries ADC and SBC on every pair of
+// operands, invalid BCD included, against the results and N, V, Z and C it works out itself.
+// It ends at a BRK, a trap or an end address from the listing:
+//   let report = DecimalTest::new().run_file("6502_decimal_test.bin")?;
+//   assert!(report.passed(), "{}", report);
+pub struct DecimalTest {
+    load: Address,
+    start: Address,
+    variables: Address,
+    end: Option<Address>,
+    max_cycles: usize,
+}
+
+impl Default for DecimalTest {
+    fn default() -> Self {
+        DecimalTest::new()
+    }
+}
+
+impl DecimalTest {
+    pub fn new() -> DecimalTest {
+        DecimalTest {
+            load: DEFAULT_START,
+            start: DEFAULT_START,
+            variables: DEFAULT_VARIABLES,
+            end: None,
+            // the full run of valid and invalid operands takes about 50 million
+            max_cycles: 100_000_000,
+        }
+    }
+
+    // Where the image goes, $0200 by default
+    pub fn with_load(mut self, load: Address) -> DecimalTest {
+        self.load = load;
+        self
+    }
+
+    pub fn with_start(mut self, start: Address) -> DecimalTest {
+        self.start = start;
+        self
+    }
+
+    // Where N1, the first of its variables, is
+    pub fn with_variables(mut self, variables: Address) -> DecimalTest {
+        self.variables = variables;
+        self
+    }
+
+    pub fn with_end(mut self, end: Address) -> DecimalTest {
+        self.end = Some(end);
+        self
+    }
+
+    pub fn with_max_cycles(mut self, cycles: usize) -> DecimalTest {
+        self.max_cycles = cycles;
+        self
+    }
+
+    pub fn run_file(&self, path: impl AsRef<Path>) -> io::Result<Report> {
+        Ok(self.run_image(&fs::read(path)?))
+    }
+
+    pub fn run_image(&self, image: &[Data]) -> Report {
+        let mut system = System::new();
+        system.load(self.load, image);
+        self.run(&mut system)
+    }
+
+    // A System with the program already loaded
+    pub fn run(&self, system: &mut System) -> Report {
+        let ends: Vec<Address> = self.end.into_iter().collect();
+        let run = run_program(system, self.start, &ends, true, self.max_cycles, &mut |_, _| ());
+        let variable = |offset: Address| system.read(self.variables.wrapping_add(offset));
+        let outcome = match run.stop {
+            Stop::End | Stop::Break | Stop::Trap if variable(ERROR) == 0 => Outcome::Passed,
+            Stop::End | Stop::Break | Stop::Trap => Outcome::Failed,
+            Stop::UnknownOpcode(opcode) => Outcome::UnknownOpcode { address: run.pc, opcode },
+            Stop::CycleLimit => Outcome::CycleLimit,
+        };
+        let registers = system.get_registers();
+        Report {
+            outcome,
+            operands: (variable(N1), variable(N2), registers.y != 0),
+            result: variable(DA),
+            expected: variable(AR),
+            flags: variable(DNVZC) & (N | V | Z | C),
+            cycles: run.cycles,
+            instructions: run.instructions,
+            registers,
+        }
+    }
+}
+
+// What an NMOS 6502 gives for ADC in decimal mode, invalid BCD included, following the tutorial's
+// appendix A: the result and N, V, Z and C. Z comes from the binary sum, N and V from the sum
+// before the high digit is adjusted
+pub fn nmos_adc(a: Data, b: Data, carry: bool) -> (Data, Data) {
+    let mut low = (a & 0x0f) as i16 + (b & 0x0f) as i16 + carry as i16;
+    if low >= 0x0a {
+        low = ((low + 0x06) & 0x0f) + 0x10;
+    }
+    let mut sum = (a & 0xf0) as i16 + (b & 0xf0) as i16 + low;
+    // the high digits as signed, for N and V
+    let signed = (a & 0xf0) as i8 as i16 + (b & 0xf0) as i8 as i16 + low;
+    if sum >= 0xa0 {
+        sum += 0x60;
+    }
+    let mut flags = 0;
+    if signed & 0x80 != 0 {
+        flags |= N;
+    }
+    if !(-128..=127).contains(&signed) {
+        flags |= V;
+    }
+    if a.wrapping_add(b).wrapping_add(carry as Data) == 0 {
+        flags |= Z;
+    }
+    if sum >= 0x100 {
+        flags |= C;
+    }
+    (sum as Data, flags)
+}
+
+// SBC in decimal mode on an NMOS 6502: the adjusted result, with every flag as binary SBC sets it
+pub fn nmos_sbc(a: Data, b: Data, carry: bool) -> (Data, Data) {
+    let mut low = (a & 0x0f) as i16 - (b & 0x0f) as i16 + carry as i16 - 1;
+    if low < 0 {
+        low = ((low - 0x06) & 0x0f) - 0x10;
+    }
+    let mut difference = (a & 0xf0) as i16 - (b & 0xf0) as i16 + low;
+    if difference < 0 {
+        difference -= 0x60;
+    }
+    let binary = a as i16 - b as i16 - !carry as i16;
+    let result = binary as Data;
+    let mut flags = 0;
+    if result & 0x80 != 0 {
+        flags |= N;
+    }
+    if (a ^ b) & (a ^ result) & 0x80 != 0 {
+        flags |= V;
+    }
+    if result == 0 {
+        flags |= Z;
+    }
+    if binary >= 0 {
+        flags |= C;
+    }
+    (difference as Data, flags)
+}
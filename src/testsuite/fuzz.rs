@@ -0,0 +1,207 @@
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::start_at;
+use crate::asm::opcodes;
+use crate::bus::{Address, Data};
+use crate::processor::AddressingMode::{Immediate, Relative};
+use crate::processor::Registers;
+use crate::system::System;
+
+// Status bits the reference model sets
+const N: Data = 0x80;
+const V: Data = 0x40;
+const D: Data = 0x08;
+const I: Data = 0x04;
+const Z: Data = 0x02;
+const C: Data = 0x01;
+
+// Instructions that don't simply move on to the next one, so their length isn't checked
+const FLOW: [&str; 5] = ["BRK", "JMP", "JSR", "RTI", "RTS"];
+
+// One instruction to try: the registers, the opcode and two operand bytes at pc, and the value
+// the pages it can reach directly are filled with: the zero page, the stack and the page an
+// absolute operand is in and the next
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzCase {
+    pub registers: Registers,
+    pub instruction: [Data; 3],
+    pub fill: Data,
+}
+
+impl FuzzCase {
+    // From a fuzzer's raw bytes: pc (low, high), A, X, Y, P, the instruction's three bytes and
+    // the fill. None when there are too few
+    pub fn from_bytes(data: &[Data]) -> Option<FuzzCase> {
+        let &[pc_lo, pc_hi, a, x, y, status, opcode, op1, op2, fill, ..] = data else {
+            return None;
+        };
+        Some(FuzzCase {
+            registers: Registers {
+                pc: pc_lo as Address | (pc_hi as Address) << 8,
+                a,
+                x,
+                y,
+                status,
+            },
+            instruction: [opcode, op1, op2],
+            fill,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FuzzFailure {
+    // with the panic's message
+    Panicked(String),
+    // the pc moved on by actual bytes rather than the instruction's documented length
+    Length { expected: usize, actual: usize },
+    // a register the reference model disagrees on
+    Reference { register: &'static str, expected: Data, actual: Data },
+}
+
+impl fmt::Display for FuzzFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FuzzFailure::Panicked(message) => write!(f, "panicked: {}", message),
+            FuzzFailure::Length { expected, actual } => write!(f, "moved the pc on {} bytes, expected {}", actual, expected),
+            FuzzFailure::Reference { register, expected, actual } => {
+                write!(f, "{}=${:02X}, the reference model has ${:02X}", register, actual, expected)
+            }
+        }
+    }
+}
+
+// Runs single instructions from arbitrary states on a fresh System, checking the core doesn't
+// panic, that the pc moves on by the documented length and, with_reference, that the registers
+// match a small reference model of the loads, transfers, increments and flag instructions.
+// From a cargo-fuzz target:
+//   fuzz_target!(|data: &[u8]| {
+//       if let Some(case) = FuzzCase::from_bytes(data) {
+//           CoreFuzzer::new().check(&case).unwrap();
+//       }
+//   });
+// or from proptest with the FuzzCase built from strategies. Undocumented opcodes are skipped
+// unless with_documented_only(false)
+pub struct CoreFuzzer {
+    documented_only: bool,
+    reference: bool,
+}
+
+impl Default for CoreFuzzer {
+    fn default() -> Self {
+        CoreFuzzer::new()
+    }
+}
+
+impl CoreFuzzer {
+    pub fn new() -> CoreFuzzer {
+        CoreFuzzer {
+            documented_only: true,
+            reference: false,
+        }
+    }
+
+    pub fn with_documented_only(mut self, documented_only: bool) -> CoreFuzzer {
+        self.documented_only = documented_only;
+        self
+    }
+
+    pub fn with_reference(mut self, reference: bool) -> CoreFuzzer {
+        self.reference = reference;
+        self
+    }
+
+    pub fn check(&self, case: &FuzzCase) -> Result<(), FuzzFailure> {
+        let documented = opcodes::decode(case.instruction[0]);
+        if documented.is_none() && self.documented_only {
+            return Ok(());
+        }
+        let mut system = System::new();
+        let page = case.instruction[2] as Address;
+        for page in [0x00, 0x01, page, (page + 1) & 0xff] {
+            system.load(page << 8, &[case.fill; 0x100]);
+        }
+        let pc = case.registers.pc;
+        start_at(&mut system, pc);
+        for (i, byte) in case.instruction.iter().enumerate() {
+            system.write(pc.wrapping_add(i as Address), *byte);
+        }
+        system.set_registers(&case.registers);
+        panic::catch_unwind(AssertUnwindSafe(|| system.step())).map_err(|payload| {
+            let message = payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_default();
+            FuzzFailure::Panicked(message)
+        })?;
+        let Some((mnemonic, mode)) = documented else {
+            return Ok(());
+        };
+        let after = system.get_registers();
+        if !FLOW.contains(&mnemonic) && *mode != Relative {
+            let expected = 1 + mode.operand_length();
+            let actual = after.pc.wrapping_sub(pc) as usize;
+            if actual != expected {
+                return Err(FuzzFailure::Length { expected, actual });
+            }
+        }
+        if !self.reference {
+            return Ok(());
+        }
+        let Some(expected) = reference(mnemonic, case) else {
+            return Ok(());
+        };
+        for (register, expected, actual) in [
+            ("A", expected.a, after.a),
+            ("X", expected.x, after.x),
+            ("Y", expected.y, after.y),
+            ("P", expected.status, after.status),
+        ] {
+            if expected != actual {
+                return Err(FuzzFailure::Reference { register, expected, actual });
+            }
+        }
+        Ok(())
+    }
+}
+
+// The registers after the instructions the model knows, None for the rest
+fn reference(mnemonic: &str, case: &FuzzCase) -> Option<Registers> {
+    let mut r = case.registers.clone();
+    let operand = case.instruction[1];
+    let immediate = opcodes::decode(case.instruction[0]).is_some_and(|(_, mode)| *mode == Immediate);
+    let set_nz = |status: &mut Data, value: Data| {
+        *status &= !(N | Z);
+        *status |= value & N;
+        if value == 0 {
+            *status |= Z;
+        }
+        value
+    };
+    match mnemonic {
+        "LDA" if immediate => r.a = set_nz(&mut r.status, operand),
+        "LDX" if immediate => r.x = set_nz(&mut r.status, operand),
+        "LDY" if immediate => r.y = set_nz(&mut r.status, operand),
+        "TAX" => r.x = set_nz(&mut r.status, r.a),
+        "TAY" => r.y = set_nz(&mut r.status, r.a),
+        "TXA" => r.a = set_nz(&mut r.status, r.x),
+        "TYA" => r.a = set_nz(&mut r.status, r.y),
+        "INX" => r.x = set_nz(&mut r.status, r.x.wrapping_add(1)),
+        "INY" => r.y = set_nz(&mut r.status, r.y.wrapping_add(1)),
+        "DEX" => r.x = set_nz(&mut r.status, r.x.wrapping_sub(1)),
+        "DEY" => r.y = set_nz(&mut r.status, r.y.wrapping_sub(1)),
+        "CLC" => r.status &= !C,
+        "SEC" => r.status |= C,
+        "CLD" => r.status &= !D,
+        "SED" => r.status |= D,
+        "CLI" => r.status &= !I,
+        "SEI" => r.status |= I,
+        "CLV" => r.status &= !V,
+        "NOP" => (),
+        _ => return None,
+    }
+    r.pc = case.registers.pc.wrapping_add(if immediate { 2 } else { 1 });
+    Some(r)
+}
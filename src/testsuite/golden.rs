@@ -0,0 +1,151 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{run_program, trace_line, Stop};
+use crate::bus::Address;
+use crate::disasm::Disassembler;
+use crate::loader::hexdump;
+use crate::system::System;
+
+// Set (to anything) to write golden files rather than compare against them
+pub const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+// Matching lines shown ahead of a difference, and differing lines shown from each side
+const CONTEXT_LINES: usize = 3;
+const DIFF_LINES: usize = 6;
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    Missing(PathBuf),
+    // the first line that differs (1 based) and the diff from there
+    Mismatch { path: PathBuf, line: usize, diff: String },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Io(e) => write!(f, "{}", e),
+            GoldenError::Missing(path) => write!(f, "{} doesn't exist, {}=1 writes it", path.display(), UPDATE_VAR),
+            GoldenError::Mismatch { path, line, diff } => write!(
+                f,
+                "{} differs from line {} ({}=1 rewrites it if that's intended):\n{}",
+                path.display(),
+                line,
+                UPDATE_VAR,
+                diff
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for GoldenError {
+    fn from(e: io::Error) -> GoldenError {
+        GoldenError::Io(e)
+    }
+}
+
+// Where two texts first differ, and a diff of the lines around it: "  " matching, "- " the
+// golden file's, "+ " the new text's
+fn diff(expected: &str, actual: &str) -> Option<(usize, String)> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let first = (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i))?;
+    let mut out = String::new();
+    for line in &expected[first.saturating_sub(CONTEXT_LINES)..first] {
+        out += &format!("  {}\n", line);
+    }
+    for line in expected.iter().skip(first).take(DIFF_LINES) {
+        out += &format!("- {}\n", line);
+    }
+    for line in actual.iter().skip(first).take(DIFF_LINES) {
+        out += &format!("+ {}\n", line);
+    }
+    if expected.len() != actual.len() {
+        out += &format!("({} lines, the golden file has {})\n", actual.len(), expected.len());
+    }
+    Some((first + 1, out))
+}
+
+// Compares text with a golden file, or writes the file when UPDATE_GOLDEN is set
+pub fn check(path: impl AsRef<Path>, actual: &str) -> Result<(), GoldenError> {
+    let path = path.as_ref();
+    if env::var_os(UPDATE_VAR).is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, actual)?;
+        return Ok(());
+    }
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(GoldenError::Missing(path.to_path_buf())),
+        Err(e) => return Err(e.into()),
+    };
+    match diff(&expected, actual) {
+        Some((line, diff)) => Err(GoldenError::Mismatch { path: path.to_path_buf(), line, diff }),
+        None => Ok(()),
+    }
+}
+
+// Captures a run as text for a golden file: a trace line per instruction (as nestest.log has
+// them), where and why it stopped, the registers and any memory asked for. Runs stop at a BRK,
+// a trap, an unknown opcode or the cycle limit:
+//   let mut system = System::new();
+//   assemble(source)?.load(&mut system);
+//   GoldenRun::new(0x0200).with_memory(0x0000, 0x000f).check(&mut system, "tests/golden/sum.trace")?;
+pub struct GoldenRun {
+    start: Address,
+    max_cycles: usize,
+    memory: Vec<(Address, Address)>,
+}
+
+impl GoldenRun {
+    pub fn new(start: Address) -> GoldenRun {
+        GoldenRun {
+            start,
+            max_cycles: 100_000,
+            memory: vec![],
+        }
+    }
+
+    pub fn with_max_cycles(mut self, cycles: usize) -> GoldenRun {
+        self.max_cycles = cycles;
+        self
+    }
+
+    // Dumps start..end inclusive after the run
+    pub fn with_memory(mut self, start: Address, end: Address) -> GoldenRun {
+        self.memory.push((start, end));
+        self
+    }
+
+    pub fn capture(&self, system: &mut System) -> String {
+        let disassembler = Disassembler::new();
+        let mut out = String::new();
+        let mut record = |system: &System, cycles: usize| {
+            out += &trace_line(system, &disassembler, cycles);
+            out.push('\n');
+        };
+        let run = run_program(system, self.start, &[], true, self.max_cycles, &mut record);
+        let stop = match run.stop {
+            Stop::Break => "at BRK".to_string(),
+            Stop::End => "at the end".to_string(),
+            Stop::Trap => "at a trap".to_string(),
+            Stop::UnknownOpcode(opcode) => format!("at unknown opcode ${:02X}", opcode),
+            Stop::CycleLimit => "out of cycles".to_string(),
+        };
+        out += &format!("stopped {} at ${:04X} after {} instructions, {} cycles\n", stop, run.pc, run.instructions, run.cycles);
+        out += &format!("{}\n", system.get_registers());
+        for &(start, end) in &self.memory {
+            out += &hexdump::dump(&*system.get_bus().borrow(), start, end);
+        }
+        out
+    }
+
+    pub fn check(&self, system: &mut System, path: impl AsRef<Path>) -> Result<(), GoldenError> {
+        check(path, &self.capture(system))
+    }
+}
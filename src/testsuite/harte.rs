@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::json::Value;
+use crate::bus::{Address, Bus, BusDevice, Data, DeviceStates, SimpleBus};
+use crate::memory::Memory;
+use crate::processor::{create6502, create_instruction_table, ProcessorTrait, Registers, BOOT_VECTOR};
+
+// Runs Tom Harte's SingleStepTests (github.com/SingleStepTests/65x02, the 6502 directory):
+// a JSON file per opcode, each test one instruction from a random state, with the state
+// after it and every bus cycle in between. There's no stack pointer here yet, so s is read
+// but not compared:
+//   for report in harte::run_dir("65x02/6502/v1")? { println!("{}", report); }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub pc: Address,
+    pub s: Data,
+    pub a: Data,
+    pub x: Data,
+    pub y: Data,
+    pub p: Data,
+    pub ram: Vec<(Address, Data)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusCycle {
+    pub address: Address,
+    pub data: Data,
+    pub write: bool,
+}
+
+impl fmt::Display for BusCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ${:04X}=${:02X}", if self.write { "write" } else { "read" }, self.address, self.data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub name: String,
+    pub initial: State,
+    pub expected: State,
+    pub cycles: Vec<BusCycle>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn number<T: TryFrom<u64>>(value: Option<&Value>, what: &str) -> Result<T, String> {
+    value.and_then(Value::as_u64).and_then(|n| T::try_from(n).ok()).ok_or_else(|| format!("bad or missing {}", what))
+}
+
+fn parse_state(value: Option<&Value>) -> Result<State, String> {
+    let value = value.ok_or("missing state")?;
+    let ram = value.get("ram").and_then(Value::as_array).ok_or("missing ram")?;
+    let ram = ram
+        .iter()
+        .map(|entry| match entry.as_array() {
+            Some([address, data]) => Ok((number(Some(address), "ram address")?, number(Some(data), "ram data")?)),
+            _ => Err("bad ram entry".to_string()),
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(State {
+        pc: number(value.get("pc"), "pc")?,
+        s: number(value.get("s"), "s")?,
+        a: number(value.get("a"), "a")?,
+        x: number(value.get("x"), "x")?,
+        y: number(value.get("y"), "y")?,
+        p: number(value.get("p"), "p")?,
+        ram,
+    })
+}
+
+fn parse_cycle(value: &Value) -> Result<BusCycle, String> {
+    match value.as_array() {
+        Some([address, data, kind]) => Ok(BusCycle {
+            address: number(Some(address), "cycle address")?,
+            data: number(Some(data), "cycle data")?,
+            write: match kind.as_str() {
+                Some("read") => false,
+                Some("write") => true,
+                _ => return Err("cycle isn't a read or a write".to_string()),
+            },
+        }),
+        _ => Err("bad cycle".to_string()),
+    }
+}
+
+// The tests in one file's JSON
+pub fn parse(text: &str) -> Result<Vec<TestCase>, String> {
+    let tests = Value::parse(text)?;
+    let tests = tests.as_array().ok_or("expected an array of tests")?;
+    tests
+        .iter()
+        .enumerate()
+        .map(|(i, test)| {
+            let name = test.get("name").and_then(Value::as_str).unwrap_or("").to_string();
+            let case = || -> Result<TestCase, String> {
+                let cycles = test.get("cycles").and_then(Value::as_array).ok_or("missing cycles")?;
+                Ok(TestCase {
+                    name: name.clone(),
+                    initial: parse_state(test.get("initial"))?,
+                    expected: parse_state(test.get("final"))?,
+                    cycles: cycles.iter().map(parse_cycle).collect::<Result<_, _>>()?,
+                })
+            };
+            case().map_err(|e| format!("test {} ({}): {}", i + 1, name, e))
+        })
+        .collect()
+}
+
+// Remembers every read and write that goes through it
+struct CycleBus {
+    inner: Rc<RefCell<dyn Bus>>,
+    cycles: RefCell<Vec<BusCycle>>,
+}
+
+impl Bus for CycleBus {
+    fn write(&self, address: Address, data: Data) {
+        self.cycles.borrow_mut().push(BusCycle { address, data, write: true });
+        self.inner.borrow().write(address, data);
+    }
+
+    fn read(&self, address: Address) -> Data {
+        let data = self.inner.borrow().read(address);
+        self.cycles.borrow_mut().push(BusCycle { address, data, write: false });
+        data
+    }
+
+    fn peek(&self, address: Address) -> Data {
+        self.inner.borrow().peek(address)
+    }
+
+    fn register_device(&mut self, device: &Rc<RefCell<dyn BusDevice>>) {
+        self.inner.borrow_mut().register_device(device);
+    }
+
+    fn save_state(&self) -> DeviceStates {
+        self.inner.borrow().save_state()
+    }
+
+    fn load_state(&self, states: &DeviceStates) {
+        self.inner.borrow().load_state(states);
+    }
+
+    fn describe_devices(&self) -> Vec<(String, String)> {
+        self.inner.borrow().describe_devices()
+    }
+}
+
+// Runs one test on a fresh Proc6502, giving what differed from the expected end state and
+// bus cycles, empty for a pass
+pub fn run_case(case: &TestCase) -> Vec<String> {
+    let memory = Rc::new(RefCell::new(Memory::new(0x0000, 0xffff)));
+    let inner = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    inner.borrow_mut().register_device(&memory.borrow().as_cloned_bus_device(Rc::clone(&memory)));
+    let recorder = Rc::new(RefCell::new(CycleBus { inner, cycles: RefCell::new(vec![]) }));
+    let bus: Rc<RefCell<dyn Bus>> = recorder.clone();
+    let mut processor = create6502();
+
+    // the boot sequence, then the test's state over the top of it
+    let initial = &case.initial;
+    memory.borrow_mut().write(BOOT_VECTOR, vec![initial.pc as Data, (initial.pc >> 8) as Data]);
+    processor.tick(&*bus.borrow());
+    recorder.borrow().cycles.take();
+    for &(address, data) in &initial.ram {
+        memory.borrow_mut().write(address, vec![data]);
+    }
+    processor.set_registers(&Registers {
+        pc: initial.pc,
+        a: initial.a,
+        x: initial.x,
+        y: initial.y,
+        status: initial.p,
+    });
+
+    // a few cycles over the expected count is enough to see an instruction that doesn't end
+    let limit = case.cycles.len() + 8;
+    let mut ticks = 0;
+    let ran = panic::catch_unwind(AssertUnwindSafe(|| loop {
+        processor.tick(&*bus.borrow());
+        ticks += 1;
+        if processor.is_at_instruction_boundary() || ticks == limit {
+            break;
+        }
+    }));
+    if ran.is_err() {
+        return vec!["the processor panicked".to_string()];
+    }
+
+    let mut mismatches = vec![];
+    let expected = &case.expected;
+    let registers = processor.get_registers();
+    if registers.pc != expected.pc {
+        mismatches.push(format!("PC=${:04X}, expected ${:04X}", registers.pc, expected.pc));
+    }
+    for (name, value, want) in [
+        ("A", registers.a, expected.a),
+        ("X", registers.x, expected.x),
+        ("Y", registers.y, expected.y),
+        ("P", registers.status, expected.p),
+    ] {
+        if value != want {
+            mismatches.push(format!("{}=${:02X}, expected ${:02X}", name, value, want));
+        }
+    }
+    for &(address, want) in &expected.ram {
+        let value = memory.borrow().do_read(address);
+        if value != want {
+            mismatches.push(format!("${:04X}=${:02X}, expected ${:02X}", address, value, want));
+        }
+    }
+    if ticks != case.cycles.len() {
+        mismatches.push(format!("took {} cycles, expected {}", ticks, case.cycles.len()));
+    }
+    let cycles = recorder.borrow().cycles.take();
+    let differs = cycles.iter().zip(&case.cycles).position(|(cycle, want)| cycle != want);
+    if let Some(i) = differs {
+        mismatches.push(format!("bus cycle {}: {}, expected {}", i + 1, cycles[i], case.cycles[i]));
+    } else if cycles.len() != case.cycles.len() {
+        mismatches.push(format!("{} bus accesses, expected {}", cycles.len(), case.cycles.len()));
+    }
+    mismatches
+}
+
+// Failures kept for each opcode's report
+const FAILURES_KEPT: usize = 3;
+
+// How one opcode's tests went. Opcodes the processor doesn't have yet are skipped
+pub struct OpcodeReport {
+    pub opcode: Data,
+    pub mnemonic: Option<String>,
+    pub tests: usize,
+    pub passed: usize,
+    // the first few, by name with what differed
+    pub failures: Vec<(String, Vec<String>)>,
+}
+
+impl OpcodeReport {
+    pub fn is_implemented(&self) -> bool {
+        self.mnemonic.is_some()
+    }
+
+    pub fn failed(&self) -> usize {
+        if self.is_implemented() { self.tests - self.passed } else { 0 }
+    }
+}
+
+impl fmt::Display for OpcodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(mnemonic) = &self.mnemonic else {
+            return write!(f, "${:02X}     not implemented, {} tests skipped", self.opcode, self.tests);
+        };
+        write!(f, "${:02X} {} {} of {} passed", self.opcode, mnemonic, self.passed, self.tests)?;
+        for (name, mismatches) in &self.failures {
+            write!(f, "\n  {}: {}", name, mismatches.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Runs the tests for one opcode
+pub fn run_tests(opcode: Data, cases: &[TestCase]) -> OpcodeReport {
+    let mut report = OpcodeReport {
+        opcode,
+        mnemonic: create_instruction_table().get(opcode).map(|i| i.get_mnemonic().to_string()),
+        tests: cases.len(),
+        passed: 0,
+        failures: vec![],
+    };
+    if !report.is_implemented() {
+        return report;
+    }
+    for case in cases {
+        let mismatches = run_case(case);
+        if mismatches.is_empty() {
+            report.passed += 1;
+        } else if report.failures.len() < FAILURES_KEPT {
+            report.failures.push((case.name.clone(), mismatches));
+        }
+    }
+    report
+}
+
+// A file named for its opcode in hex, e.g. a9.json
+pub fn run_file(path: impl AsRef<Path>) -> io::Result<OpcodeReport> {
+    let path = path.as_ref();
+    let opcode = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| Data::from_str_radix(stem, 16).ok())
+        .ok_or_else(|| invalid(format!("{} isn't named for an opcode, e.g. a9.json", path.display())))?;
+    let cases = parse(&fs::read_to_string(path)?).map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+    Ok(run_tests(opcode, &cases))
+}
+
+// Every opcode's file in a directory, in opcode order
+pub fn run_dir(dir: impl AsRef<Path>) -> io::Result<Vec<OpcodeReport>> {
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let opcode = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| Data::from_str_radix(stem, 16).ok());
+        if let (Some(opcode), Some("json")) = (opcode, path.extension().and_then(|e| e.to_str())) {
+            files.insert(opcode, path);
+        }
+    }
+    files.values().map(run_file).collect()
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{run_program, Stop};
+use crate::bus::{Address, Data};
+use crate::processor::Registers;
+use crate::system::System;
+
+// Where the standard build of 6502_functional_test.bin starts, and the trap it reaches when
+// every test passes
+pub const DEFAULT_START: Address = 0x0400;
+pub const DEFAULT_SUCCESS: Address = 0x3469;
+// test_case, the number of the test being run, first in the program's data segment
+pub const DEFAULT_TEST_CASE: Address = 0x0200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    // a test trapped (jumped or branched to itself) somewhere other than the success address
+    Failed { trap: Address },
+    // the processor doesn't have the opcode at address yet
+    UnknownOpcode { address: Address, opcode: Data },
+    CycleLimit,
+}
+
+pub struct Report {
+    pub outcome: Outcome,
+    // the test number at the end, which says which test failed
+    pub test_case: Data,
+    pub cycles: usize,
+    pub instructions: usize,
+    pub registers: Registers,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.outcome {
+            Outcome::Passed => write!(f, "passed")?,
+            Outcome::Failed { trap } => write!(f, "test ${:02X} failed, trapped at ${:04X}", self.test_case, trap)?,
+            Outcome::UnknownOpcode { address, opcode } => {
+                write!(f, "test ${:02X} stopped at unknown opcode ${:02X} at ${:04X}", self.test_case, opcode, address)?
+            }
+            Outcome::CycleLimit => write!(f, "test ${:02X} ran out of cycles", self.test_case)?,
+        }
+        write!(f, " after {} instructions, {} cycles; {}", self.instructions, self.cycles, self.registers)
+    }
+}
+
+// Runs Klaus Dormann's 6502_functional_test (github.com/Klaus2m5/6502_65C02_functional_tests).
+// The program runs test after test, trapping in a loop on a failure and at a known address
+// once all of them have passed. Builds configured differently need the addresses from their
+// listing:
+//   let report = FunctionalTest::new().run_file("6502_functional_test.bin")?;
+//   assert!(report.passed(), "{}", report);
+pub struct FunctionalTest {
+    start: Address,
+    success: Address,
+    test_case: Address,
+    max_cycles: usize,
+}
+
+impl Default for FunctionalTest {
+    fn default() -> Self {
+        FunctionalTest::new()
+    }
+}
+
+impl FunctionalTest {
+    pub fn new() -> FunctionalTest {
+        FunctionalTest {
+            start: DEFAULT_START,
+            success: DEFAULT_SUCCESS,
+            test_case: DEFAULT_TEST_CASE,
+            // a full pass takes about 100 million
+            max_cycles: 200_000_000,
+        }
+    }
+
+    pub fn with_start(mut self, start: Address) -> FunctionalTest {
+        self.start = start;
+        self
+    }
+
+    pub fn with_success(mut self, success: Address) -> FunctionalTest {
+        self.success = success;
+        self
+    }
+
+    pub fn with_test_case(mut self, test_case: Address) -> FunctionalTest {
+        self.test_case = test_case;
+        self
+    }
+
+    pub fn with_max_cycles(mut self, cycles: usize) -> FunctionalTest {
+        self.max_cycles = cycles;
+        self
+    }
+
+    // A 64K image, loaded from $0000
+    pub fn run_file(&self, path: impl AsRef<Path>) -> io::Result<Report> {
+        Ok(self.run_image(&fs::read(path)?))
+    }
+
+    pub fn run_image(&self, image: &[Data]) -> Report {
+        let mut system = System::new();
+        system.load(0x0000, image);
+        self.run(&mut system)
+    }
+
+    // A System with the program already loaded
+    pub fn run(&self, system: &mut System) -> Report {
+        // BRK is one of the things it tests, so only traps stop it
+        let run = run_program(system, self.start, &[self.success], false, self.max_cycles, &mut |_, _| ());
+        let outcome = match run.stop {
+            Stop::End => Outcome::Passed,
+            Stop::Trap | Stop::Break => Outcome::Failed { trap: run.pc },
+            Stop::UnknownOpcode(opcode) => Outcome::UnknownOpcode { address: run.pc, opcode },
+            Stop::CycleLimit => Outcome::CycleLimit,
+        };
+        Report {
+            outcome,
+            test_case: system.read(self.test_case),
+            cycles: run.cycles,
+            instructions: run.instructions,
+            registers: system.get_registers(),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{start_at, trace_line};
+use crate::bus::{Address, Data};
+use crate::disasm::Disassembler;
+use crate::loader::hexdump::{parse_address, parse_byte};
+use crate::processor::{create_instruction_table, Registers};
+use crate::system::System;
+
+// Matching lines shown ahead of a divergence
+const CONTEXT_LINES: usize = 3;
+
+// One line of a nestest.log style trace, the state before the instruction at pc:
+//   C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+// There's no stack pointer here yet, so SP is left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub pc: Address,
+    pub bytes: Vec<Data>,
+    pub a: Data,
+    pub x: Data,
+    pub y: Data,
+    pub p: Data,
+    pub cycles: Option<usize>,
+    // as it was in the log
+    pub text: String,
+}
+
+impl LogLine {
+    pub fn parse(text: &str) -> Option<LogLine> {
+        let mut words = text.split_whitespace();
+        let pc = words.next().filter(|w| w.len() == 4).and_then(parse_address)?;
+        // the bytes are up to three 2 digit words before the mnemonic
+        let bytes: Vec<Data> = words.take(3).map_while(|w| if w.len() == 2 { parse_byte(w) } else { None }).collect();
+        let field = |name: &str| {
+            let (_, rest) = text.split_once(name)?;
+            Some(rest.split_whitespace().next().unwrap_or(""))
+        };
+        let register = |name: &str| field(name).and_then(parse_byte);
+        Some(LogLine {
+            pc,
+            bytes,
+            a: register(" A:")?,
+            x: register(" X:")?,
+            y: register(" Y:")?,
+            p: register(" P:")?,
+            cycles: field("CYC:").and_then(|c| c.parse().ok()),
+            text: text.trim_end().to_string(),
+        })
+    }
+}
+
+// The lines of a log, blank lines skipped
+pub fn parse(text: &str) -> Result<Vec<LogLine>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| LogLine::parse(line).ok_or_else(|| format!("line {}: not a trace line: {}", n + 1, line)))
+        .collect()
+}
+
+// Copies an iNES image's PRG ROM into RAM, a single 16K bank at both $8000 and $C000 as
+// NROM-128 mirrors it
+pub fn load_ines(system: &mut System, image: &[Data]) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if image.len() < 16 || &image[..4] != b"NES\x1a" {
+        return Err(invalid("not an iNES image"));
+    }
+    // a trainer sits between the header and the PRG ROM
+    let start = if image[6] & 0x04 != 0 { 16 + 512 } else { 16 };
+    let banks = image[4] as usize;
+    let prg = image.get(start..start + banks * 0x4000).ok_or_else(|| invalid("the PRG ROM is cut off"))?;
+    match banks {
+        1 => {
+            system.load(0x8000, prg);
+            system.load(0xc000, prg);
+        }
+        2 => system.load(0x8000, prg),
+        _ => return Err(invalid("only 16K and 32K PRG ROMs fit without a mapper")),
+    }
+    Ok(())
+}
+
+// Where the run first differed from the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    // 1 based, counting instructions and the log's lines alike
+    pub instruction: usize,
+    // the fields that differ: PC, bytes, A, X, Y, P or CYC
+    pub fields: Vec<&'static str>,
+    // for an instruction the processor doesn't have, which ends the run
+    pub unknown_opcode: Option<Data>,
+    pub expected: LogLine,
+    // the processor's state in the same format
+    pub actual: LogLine,
+    // the log lines before it, which matched
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.unknown_opcode {
+            Some(opcode) => writeln!(f, "unknown opcode ${:02X} at instruction {}", opcode, self.instruction)?,
+            None => writeln!(f, "diverged at instruction {}: {}", self.instruction, self.fields.join(", "))?,
+        }
+        for line in &self.context {
+            writeln!(f, "           {}", line)?;
+        }
+        writeln!(f, "expected   {}", self.expected.text)?;
+        writeln!(f, "got        {}", self.actual.text)?;
+        // the two side by side, field by field
+        let (e, a) = (&self.expected, &self.actual);
+        let hex = |bytes: &[Data]| bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+        let cycles = |c: Option<usize>| c.map_or("-".to_string(), |c| c.to_string());
+        let rows = [
+            ("PC", format!("{:04X}", e.pc), format!("{:04X}", a.pc)),
+            ("bytes", hex(&e.bytes), hex(&a.bytes)),
+            ("A", format!("{:02X}", e.a), format!("{:02X}", a.a)),
+            ("X", format!("{:02X}", e.x), format!("{:02X}", a.x)),
+            ("Y", format!("{:02X}", e.y), format!("{:02X}", a.y)),
+            ("P", format!("{:02X}", e.p), format!("{:02X}", a.p)),
+            ("CYC", cycles(e.cycles), cycles(a.cycles)),
+        ];
+        writeln!(f, "{:<7}{:<10}{:<10}", "", "expected", "got")?;
+        for (name, expected, actual) in rows {
+            let marker = if self.fields.contains(&name) { "<" } else { "" };
+            writeln!(f, "{:<7}{:<10}{:<10}{}", name, expected, actual, marker)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs a program one instruction at a time against a reference trace, stopping at the first
+// line that differs. The registers start as the log's first line has them; cycle counts are
+// only compared with_cycles, counting from the first line's CYC:
+//   let mut system = System::new();
+//   nestest::load_ines(&mut system, &fs::read("nestest.nes")?)?;
+//   if let Err(divergence) = NestestCheck::from_log_file("nestest.log")?.run(&mut system) {
+//       print!("{}", divergence);
+//   }
+pub struct NestestCheck {
+    log: Vec<LogLine>,
+    cycles: bool,
+}
+
+impl NestestCheck {
+    pub fn new(log: Vec<LogLine>) -> NestestCheck {
+        NestestCheck { log, cycles: false }
+    }
+
+    pub fn with_cycles(mut self, cycles: bool) -> NestestCheck {
+        self.cycles = cycles;
+        self
+    }
+
+    pub fn from_log_file(path: impl AsRef<Path>) -> io::Result<NestestCheck> {
+        let log = parse(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(NestestCheck::new(log))
+    }
+
+    // The number of lines that matched, all of them, or where it diverged
+    pub fn run(&self, system: &mut System) -> Result<usize, Box<Divergence>> {
+        let Some(first) = self.log.first() else {
+            return Ok(0);
+        };
+        let start_cycles = start_at(system, first.pc);
+        system.set_registers(&Registers {
+            pc: first.pc,
+            a: first.a,
+            x: first.x,
+            y: first.y,
+            status: first.p,
+        });
+        let base = first.cycles.unwrap_or(0);
+        let disassembler = Disassembler::new();
+        let instructions = create_instruction_table();
+        let mut context = VecDeque::with_capacity(CONTEXT_LINES);
+        for (n, expected) in self.log.iter().enumerate() {
+            let registers = system.get_registers();
+            let line = disassembler.decode(&*system.get_bus().borrow(), registers.pc);
+            let cycles = base + system.get_total_cycles() - start_cycles;
+            let text = trace_line(system, &disassembler, cycles);
+            let actual = LogLine {
+                pc: registers.pc,
+                bytes: line.bytes.clone(),
+                a: registers.a,
+                x: registers.x,
+                y: registers.y,
+                p: registers.status,
+                cycles: Some(cycles),
+                text,
+            };
+            let mut fields = vec![];
+            for (name, differs) in [
+                ("PC", actual.pc != expected.pc),
+                ("bytes", actual.bytes != expected.bytes),
+                ("A", actual.a != expected.a),
+                ("X", actual.x != expected.x),
+                ("Y", actual.y != expected.y),
+                ("P", actual.p != expected.p),
+                ("CYC", self.cycles && expected.cycles.is_some() && actual.cycles != expected.cycles),
+            ] {
+                if differs {
+                    fields.push(name);
+                }
+            }
+            let opcode = system.read(registers.pc);
+            let unknown_opcode = Some(opcode).filter(|opcode| !instructions.contains(*opcode));
+            if !fields.is_empty() || unknown_opcode.is_some() {
+                return Err(Box::new(Divergence {
+                    instruction: n + 1,
+                    fields,
+                    unknown_opcode,
+                    expected: expected.clone(),
+                    actual,
+                    context: context.into_iter().collect(),
+                }));
+            }
+            if context.len() == CONTEXT_LINES {
+                context.pop_front();
+            }
+            context.push_back(expected.text.clone());
+            system.step();
+        }
+        Ok(self.log.len())
+    }
+}
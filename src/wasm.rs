@@ -0,0 +1,159 @@
+use js_sys::{Function, Uint8ClampedArray};
+use wasm_bindgen::prelude::*;
+
+use crate::bus::{Address, Data};
+use crate::devices::framebuffer::{HEIGHT, WIDTH};
+use crate::machines::{easy6502_with_seed, Easy6502};
+
+// JavaScript bindings for a browser playground, built for wasm32-unknown-unknown with the wasm
+// feature, e.g. `wasm-pack build --target web -- --features wasm`. The machine is easy6502's,
+// with the 32x32 screen at $0200 and programs started at $0600:
+//   const machine = new Machine(Math.random() * 2 ** 32);
+//   machine.set_canvas((rgba, width, height) => context.putImageData(new ImageData(rgba, width, height), 0, 0));
+//   machine.load_program(bytes);
+//   const frame = () => { machine.run_frame(16667); requestAnimationFrame(frame); };
+#[wasm_bindgen]
+pub struct Machine {
+    machine: Easy6502,
+    canvas: Option<Box<dyn CanvasSink>>,
+}
+
+// Where run_frame hands the screen when it's changed: RGBA bytes row by row, the layout of a
+// canvas's ImageData
+pub trait CanvasSink {
+    fn draw(&mut self, rgba: &[u8], width: usize, height: usize);
+}
+
+// A JS function taking (Uint8ClampedArray, width, height)
+impl CanvasSink for Function {
+    fn draw(&mut self, rgba: &[u8], width: usize, height: usize) {
+        let rgba = Uint8ClampedArray::from(rgba);
+        let _ = self.call3(&JsValue::NULL, &rgba, &JsValue::from(width as u32), &JsValue::from(height as u32));
+    }
+}
+
+#[wasm_bindgen]
+impl Machine {
+    // The seed is for the random bytes at $FE, as there's no clock to take one from
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Machine {
+        Machine {
+            machine: easy6502_with_seed(seed),
+            canvas: None,
+        }
+    }
+
+    // The function run_frame calls with each changed frame, see CanvasSink
+    pub fn set_canvas(&mut self, draw: Function) {
+        self.set_canvas_sink(Box::new(draw));
+    }
+
+    // Loads a program at $0600 and resets so the next step starts it
+    pub fn load_program(&mut self, program: &[u8]) {
+        self.machine.load(program);
+    }
+
+    // Copies data into RAM anywhere, leaving the processor alone
+    pub fn load(&mut self, address: Address, data: &[u8]) {
+        self.machine.system.load(address, data);
+    }
+
+    pub fn set_reset_vector(&mut self, address: Address) {
+        self.machine.system.set_reset_vector(address);
+    }
+
+    pub fn reset(&mut self) {
+        self.machine.system.reset();
+    }
+
+    // One instruction, returning its cycles. The first after a reset is the boot sequence
+    pub fn step(&mut self) -> usize {
+        self.machine.system.step()
+    }
+
+    // Whole instructions until at least this many cycles have gone by or the program breaks
+    pub fn run(&mut self, cycles: usize) -> usize {
+        self.machine.system.run(cycles)
+    }
+
+    // A frame's worth of cycles, for calling from requestAnimationFrame, then the screen to the
+    // canvas if it's changed
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> usize {
+        let cycles = self.machine.system.run_frame(cycles_per_frame, |_| ());
+        if let Some(canvas) = &mut self.canvas {
+            if let Some(rgba) = self.machine.screen.borrow_mut().take_frame() {
+                canvas.draw(&rgba, WIDTH, HEIGHT);
+            }
+        }
+        cycles
+    }
+
+    // The screen to the canvas now, changed or not
+    pub fn redraw(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            let rgba = self.machine.screen.borrow().to_rgba();
+            canvas.draw(&rgba, WIDTH, HEIGHT);
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.machine.system.is_halted()
+    }
+
+    pub fn read(&self, address: Address) -> Data {
+        self.machine.system.read(address)
+    }
+
+    pub fn write(&self, address: Address, data: Data) {
+        self.machine.system.write(address, data);
+    }
+
+    // The key a program finds at $FF
+    pub fn press_key(&mut self, key: Data) {
+        self.machine.io.borrow_mut().press(key);
+    }
+
+    pub fn get_pc(&self) -> Address {
+        self.machine.system.get_registers().pc
+    }
+
+    pub fn get_a(&self) -> Data {
+        self.machine.system.get_registers().a
+    }
+
+    pub fn get_x(&self) -> Data {
+        self.machine.system.get_registers().x
+    }
+
+    pub fn get_y(&self) -> Data {
+        self.machine.system.get_registers().y
+    }
+
+    pub fn get_status(&self) -> Data {
+        self.machine.system.get_registers().status
+    }
+
+    pub fn get_total_cycles(&self) -> usize {
+        self.machine.system.get_total_cycles()
+    }
+
+    // The screen as RGBA bytes, get_width by get_height pixels
+    pub fn get_framebuffer(&self) -> Vec<u8> {
+        self.machine.screen.borrow().to_rgba()
+    }
+
+    pub fn get_width(&self) -> usize {
+        WIDTH
+    }
+
+    pub fn get_height(&self) -> usize {
+        HEIGHT
+    }
+}
+
+// For Rust code on the web target, drawing with web-sys say
+impl Machine {
+    pub fn set_canvas_sink(&mut self, canvas: Box<dyn CanvasSink>) {
+        self.canvas = Some(canvas);
+    }
+}
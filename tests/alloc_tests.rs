@@ -0,0 +1,101 @@
+#![cfg(feature = "std")]
+
+use std::alloc::{GlobalAlloc, Layout, System as Heap};
+use std::cell::Cell;
+
+use rust_6502_emulator::bus::Address;
+use rust_6502_emulator::system::{bench_system, System};
+
+// Counts this thread's allocations, so tests running alongside don't add to them
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        Heap.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Heap.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        Heap.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+fn allocations_in(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+// Once through the bench workload, from its start to where it runs into empty memory
+fn run_workload(system: &mut System, end: Address) {
+    while system.get_registers().pc < end {
+        system.step();
+    }
+}
+
+// Without the blocks feature nothing allocates from the first instruction on. With it the first
+// pass over code allocates its blocks, and with closure_cache the pass that makes a block hot
+// allocates its closures, so there it's code already decoded that runs without allocating
+#[test]
+fn test_tick_does_not_allocate() {
+    let mut system = bench_system();
+    let start = system.get_registers().pc;
+    let end = start + 32 * 64;
+    // the warm-up: decode the code into the block cache and translate it
+    #[cfg(feature = "blocks")]
+    for _ in 0..20 {
+        let mut registers = system.get_registers();
+        registers.pc = start;
+        system.set_registers(&registers);
+        run_workload(&mut system, end);
+    }
+
+    let mut registers = system.get_registers();
+    registers.pc = start;
+    system.set_registers(&registers);
+    assert_eq!(allocations_in(|| run_workload(&mut system, end)), 0);
+    // nor does the boot sequence after a reset
+    system.reset();
+    let boot = allocations_in(|| {
+        system.step();
+    });
+    assert_eq!(boot, 0);
+    assert_eq!(system.get_registers().pc, start);
+}
+
+#[test]
+fn test_snapshots_do_not_allocate() {
+    let system = bench_system();
+    let processor = system.get_processor();
+    let bus = system.get_bus();
+    // fetch the first opcode, leaving its operand's cycle queued
+    processor.borrow_mut().tick(&*bus.borrow());
+    assert!(!processor.borrow().is_at_instruction_boundary());
+
+    let mut snapshot = None;
+    assert_eq!(allocations_in(|| snapshot = Some(processor.borrow().snapshot())), 0);
+    processor.borrow_mut().tick(&*bus.borrow());
+    assert!(processor.borrow().is_at_instruction_boundary());
+    let snapshot = snapshot.unwrap();
+    // the first restore files the block that was being decoded in the block cache
+    processor.borrow_mut().restore(&snapshot);
+    processor.borrow_mut().tick(&*bus.borrow());
+    assert_eq!(allocations_in(|| processor.borrow_mut().restore(&snapshot)), 0);
+    assert!(!processor.borrow().is_at_instruction_boundary());
+}
@@ -0,0 +1,286 @@
+#![cfg(feature = "asm")]
+
+use rust_6502_emulator::asm::{assemble, run_asm, AsmTest, Assembler, RunEnd};
+use rust_6502_emulator::system::System;
+
+#[test]
+fn test_addressing_modes() {
+    let source = "
+        .org $0200
+        zp = $10
+        LDA #$05
+        LDA zp
+        LDA zp,X
+        LDX zp,Y
+        LDA $1234
+        LDA $1234,X
+        LDA $1234,Y
+        LDA (zp,X)
+        LDA (zp),Y
+        JMP ($FFFC)
+        ASL A
+        ROL
+        CLC
+    ";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(
+        assembly.get_segments(),
+        &[(
+            0x0200,
+            vec![
+                0xa9, 0x05, 0xa5, 0x10, 0xb5, 0x10, 0xb6, 0x10, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12, 0xb9, 0x34, 0x12,
+                0xa1, 0x10, 0xb1, 0x10, 0x6c, 0xfc, 0xff, 0x0a, 0x2a, 0x18
+            ]
+        )]
+    );
+}
+
+#[test]
+fn test_labels_and_branches() {
+    let source = "
+        *= $C000
+reset   LDX #0
+loop:   INX
+        BNE loop
+        BEQ done        ; forward
+        JMP reset
+done:   RTS
+    ";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(assembly.get_symbol("reset"), Some(0xc000));
+    assert_eq!(assembly.get_symbol("loop"), Some(0xc002));
+    assert_eq!(assembly.get_symbol("done"), Some(0xc00a));
+    assert_eq!(
+        assembly.to_binary(),
+        vec![0xa2, 0x00, 0xe8, 0xd0, 0xfd, 0xf0, 0x03, 0x4c, 0x00, 0xc0, 0x60]
+    );
+    assert_eq!(assembly.get_origin(), 0xc000);
+}
+
+#[test]
+fn test_forward_references_stay_absolute() {
+    // var isn't known on the first pass, so LDA takes the absolute form both times
+    let assembly = assemble("LDA var\nvar = $20").unwrap();
+    assert_eq!(assembly.to_binary(), vec![0xad, 0x20, 0x00]);
+}
+
+#[test]
+fn test_data_directives_and_expressions() {
+    let source = "
+        .org $1000
+table:  .byte 1, $ff, %1010, 'A', \"hi\\n\", -1
+        .word table, table+2, $1234
+        .db <table, >table, (2+3)*4, 7/2, 1<<4 | 1, ~0 & $0f
+here    .dw *
+    ";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(
+        assembly.to_binary(),
+        vec![
+            1, 0xff, 0x0a, 0x41, b'h', b'i', b'\n', 0xff, 0x00, 0x10, 0x02, 0x10, 0x34, 0x12, 0x00, 0x10, 20, 3, 0x11,
+            0x0f, 0x14, 0x10
+        ]
+    );
+    assert_eq!(assembly.get_symbol("here"), Some(0x1014));
+}
+
+#[test]
+fn test_segments_and_loading() {
+    let source = "
+        .org $0200
+        LDA #$42
+        BRK
+        .org $FFFC
+        .word $0200
+    ";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(assembly.get_segments().len(), 2);
+    assert_eq!(assembly.to_binary().len(), 0xfffe - 0x0200);
+    let mut system = System::new();
+    assembly.load(&mut system);
+    assert_eq!(system.read(0x0200), 0xa9);
+    assert_eq!(system.read(0xfffd), 0x02);
+}
+
+#[test]
+fn test_errors_name_the_line() {
+    let error = |source: &str| assemble(source).unwrap_err().to_string();
+    assert_eq!(error("NOP\nFOO #1"), "line 2: unknown instruction FOO");
+    assert_eq!(error("LDA missing"), "line 1: undefined symbol 'missing'");
+    assert_eq!(error("x: NOP\nx: NOP"), "line 2: 'x' is already defined");
+    assert_eq!(error("LDA #$100"), "line 1: $100 doesn't fit in a byte");
+    assert_eq!(error("STX $1234,X"), "line 1: STX can't take that operand");
+    assert_eq!(error(".org $1000\nBNE $2000"), "line 2: branch to $2000 is out of range");
+    assert_eq!(error(".fill 3"), "line 1: unknown directive .fill");
+}
+
+#[test]
+fn test_label_file() {
+    let assembly = assemble(".org $C000\nstart: NOP\nend = $C0FF").unwrap();
+    assert_eq!(assembly.label_file(), "al 00C0FF .end\nal 00C000 .start\n");
+    assert_eq!(Assembler::new().assemble("").unwrap().to_binary(), Vec::<u8>::new());
+}
+
+#[test]
+fn test_encodings() {
+    let cases: [(&str, &[u8]); 12] = [
+        ("JSR $FFD2", &[0x20, 0xd2, 0xff]),
+        ("RTS", &[0x60]),
+        ("STX $10,Y", &[0x96, 0x10]),
+        ("LDY $1234,X", &[0xbc, 0x34, 0x12]),
+        ("INC $1234,X", &[0xfe, 0x34, 0x12]),
+        ("BIT $10", &[0x24, 0x10]),
+        ("CPX #' '", &[0xe0, 0x20]),
+        ("ora ($10,x)", &[0x01, 0x10]),
+        ("SBC ($10),Y", &[0xf1, 0x10]),
+        ("LSR $0010", &[0x46, 0x10]),
+        ("LDA (1+2)*3", &[0xa5, 0x09]),
+        ("TXS", &[0x9a]),
+    ];
+    for (source, bytes) in cases {
+        assert_eq!(assemble(source).unwrap().to_binary(), bytes, "{}", source);
+    }
+}
+
+#[test]
+fn test_macros() {
+    let source = "
+        .macro add16 dst, src
+        CLC
+        LDA dst
+        ADC #<src
+        STA dst
+        LDA dst+1
+        ADC #>src
+        STA dst+1
+        .endmacro
+
+        .macro wait count
+        LDX #count
+loop\\@: DEX
+        BNE loop\\@
+        .endm
+
+        .org $0200
+ptr = $10
+        add16 ptr, $0102
+start:  wait 3
+        wait $ff
+    ";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(
+        assembly.to_binary(),
+        vec![
+            0x18, 0xa5, 0x10, 0x69, 0x02, 0x85, 0x10, 0xa5, 0x11, 0x69, 0x01, 0x85, 0x11, // add16
+            0xa2, 0x03, 0xca, 0xd0, 0xfd, // wait 3
+            0xa2, 0xff, 0xca, 0xd0, 0xfd, // wait $ff
+        ]
+    );
+    assert_eq!(assembly.get_symbol("start"), Some(0x020d));
+    assert_eq!(assembly.get_symbol("loop2"), Some(0x020f));
+    assert_eq!(assembly.get_symbol("loop3"), Some(0x0214));
+}
+
+#[test]
+fn test_conditionals() {
+    let source = "
+        .ifdef DEBUG
+        .byte 1
+        .else
+        .byte 2
+        .endif
+        .if SIZE > 4
+          .if SIZE & 1
+          .byte 3
+          .endif
+        .byte 4
+        .endif
+        .ifndef missing
+        .byte 5
+        .endif
+    ";
+    let build = |assembler: Assembler| assembler.with_define("SIZE", 5).assemble(source).unwrap().to_binary();
+    assert_eq!(build(Assembler::new()), vec![2, 3, 4, 5]);
+    assert_eq!(build(Assembler::new().with_define("DEBUG", 1)), vec![1, 3, 4, 5]);
+    // .ifdef only sees what's defined above it, on both passes
+    assert_eq!(assemble(".ifdef later\n.byte 1\n.endif\nlater: .byte 2").unwrap().to_binary(), vec![2]);
+}
+
+#[test]
+fn test_includes() {
+    let dir = std::env::temp_dir().join(format!("asm_include_test_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/header.inc"), ".ifndef HEADER\nHEADER = 1\nCHROUT = $FFD2\n.endif\n").unwrap();
+    std::fs::write(dir.join("print.inc"), "print: JSR CHROUT\n RTS\n").unwrap();
+    std::fs::write(dir.join("main.s"), ".include \"header.inc\"\n.org $0300\n.include \"print.inc\"\n").unwrap();
+    let assembler = Assembler::new().with_include_dir(dir.join("lib"));
+    let assembly = assembler.assemble_file(dir.join("main.s")).unwrap();
+    assert_eq!(assembly.to_binary(), vec![0x20, 0xd2, 0xff, 0x60]);
+    assert_eq!(assembly.get_symbol("print"), Some(0x0300));
+
+    std::fs::write(dir.join("bad.inc"), "NOP\nLDA nowhere\n").unwrap();
+    std::fs::write(dir.join("main.s"), ".include \"bad.inc\"\n").unwrap();
+    let error = assembler.assemble_file(dir.join("main.s")).unwrap_err().to_string();
+    assert_eq!(error, format!("{} line 2: undefined symbol 'nowhere'", dir.join("bad.inc").display()));
+    std::fs::write(dir.join("main.s"), ".include \"main.s\"\n").unwrap();
+    let error = assembler.assemble_file(dir.join("main.s")).unwrap_err().to_string();
+    assert!(error.ends_with("includes nested too deeply"), "{}", error);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_macro_and_conditional_errors() {
+    let error = |source: &str| assemble(source).unwrap_err().to_string();
+    assert_eq!(error(".macro m a\nNOP\n.endm\nm 1, 2"), "line 4: macro m takes 1 arguments");
+    assert_eq!(error(".macro m\nNOP\n"), "line 1: macro m is missing .endmacro");
+    assert_eq!(error(".macro m\nm\n.endm\nm"), "line 4: macros nested too deeply");
+    assert_eq!(error(".macro m\nLDA #$100\n.endm\nNOP\nm"), "line 5: $100 doesn't fit in a byte");
+    assert_eq!(error(".if 1\nNOP"), "line 2: .if is missing .endif");
+    assert_eq!(error(".endif"), "line 1: .endif without .if");
+    assert_eq!(error(".if later\n.endif\nlater = 1"), "line 1: undefined symbol 'later'");
+}
+
+// The processor only runs the fetch cycles of each instruction so far, so these look at
+// where runs end rather than what the instructions did
+#[test]
+fn test_harness_runs_to_break() {
+    let run = run_asm(
+        "
+        LDX #$05
+        LDA #$AA
+        STA $01,X
+done:   BRK
+        ",
+    );
+    assert_eq!(run.end, RunEnd::Break);
+    assert_eq!(run.registers.pc, run.symbol("done"));
+    assert_eq!(run.symbol("done"), 0x0206);
+    assert!(run.cycles > 0);
+}
+
+#[test]
+fn test_harness_start_label_and_data() {
+    let run = run_asm(
+        "
+        .org $0300
+table:  .byte $11, $22, $33
+start:  LDY #2
+        LDA table
+        BRK
+        ",
+    );
+    assert_eq!(run.registers.pc, 0x0308);
+    assert_eq!(run.read_range(run.symbol("table"), 3), vec![0x11, 0x22, 0x33]);
+    assert_eq!(run.read_word(0x0300), 0x2211);
+    assert_eq!(run.read_symbol("start"), 0xa0);
+}
+
+#[test]
+fn test_harness_other_endings() {
+    let run = AsmTest::new(".byte $ea, $ea, $ea, $ea, 0").with_max_cycles(2).run().unwrap();
+    assert_eq!(run.end, RunEnd::CycleLimit);
+    let run = AsmTest::new("NOP\n.byte $02").run().unwrap();
+    assert_eq!(run.end, RunEnd::UnknownOpcode(0x0201));
+    let error = AsmTest::new("LDA #").run().err().unwrap();
+    assert_eq!(error.line, 1);
+}
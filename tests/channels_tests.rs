@@ -0,0 +1,79 @@
+#![cfg(feature = "async")]
+
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt};
+use tokio::runtime::Builder;
+use tokio::task::yield_now;
+
+use rust_6502_emulator::bus::{Address, Data};
+use rust_6502_emulator::channels::{async_serial, service};
+use rust_6502_emulator::devices::acia::SerialBackend;
+use rust_6502_emulator::system::System;
+
+#[test]
+fn test_async_serial() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let (mut serial, task) = async_serial(4);
+        let (near, mut far) = duplex(64);
+        let (reader, writer) = split(near);
+        let server = tokio::spawn(task.serve(reader, writer));
+
+        far.write_all(b"hi").await.unwrap();
+        let mut received = vec![];
+        while received.len() < 2 {
+            yield_now().await;
+            received.extend(serial.receive());
+        }
+        assert_eq!(received, b"hi");
+        assert_eq!(serial.receive(), None);
+
+        serial.transmit(b'A');
+        let mut byte = [0];
+        far.read_exact(&mut byte).await.unwrap();
+        assert_eq!(&byte, b"A");
+
+        // the task doesn't get to run in between, so past the capacity bytes are dropped
+        for b in 0..10 {
+            serial.transmit(b);
+        }
+        assert_eq!(serial.get_dropped(), 6);
+        let mut bytes = [0; 4];
+        far.read_exact(&mut bytes).await.unwrap();
+        assert_eq!(bytes, [0, 1, 2, 3]);
+
+        // hanging up ends the task
+        drop(far);
+        server.await.unwrap().unwrap();
+        serial.transmit(0);
+        assert_eq!(serial.get_dropped(), 7);
+    });
+}
+
+#[test]
+fn test_service() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let mut system = System::new();
+        system.load(0x0200, &[0x42, 0x43]);
+        let (client, mut port) = service::<Address, Data>(2);
+
+        let calls: Vec<_> = [0x0200, 0x0201]
+            .into_iter()
+            .map(|address| {
+                let client = client.clone();
+                tokio::spawn(async move { client.call(address).await })
+            })
+            .collect();
+        assert_eq!(port.answer(|address| system.read(address)), 0);
+        yield_now().await;
+        assert_eq!(port.answer(|address| system.read(address)), 2);
+        let mut answers = vec![];
+        for call in calls {
+            answers.push(call.await.unwrap());
+        }
+        assert_eq!(answers, [Some(0x42), Some(0x43)]);
+
+        drop(port);
+        assert_eq!(client.call(0x0200).await, None);
+    });
+}
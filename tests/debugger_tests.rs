@@ -0,0 +1,990 @@
+#![cfg(feature = "debugger")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rust_6502_emulator::bus::Bus;
+use rust_6502_emulator::debugger::{DebugEvent, DebugInfo, Debugger, DebuggerError, RunMode, StopReason, WriteRecord};
+use rust_6502_emulator::processor::ProcessorTrait;
+use rust_6502_emulator::system::{Speed, System};
+
+struct Machine {
+    processor: Rc<RefCell<dyn ProcessorTrait>>,
+    bus: Rc<RefCell<dyn Bus>>,
+    _system: System,
+}
+
+// boots to 0x0200 which is filled with NOPs
+fn nop_machine() -> Machine {
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 16]);
+    Machine {
+        processor: system.get_processor(),
+        bus: system.get_bus(),
+        _system: system,
+    }
+}
+
+#[test]
+fn test_reverse_step_restores_processor() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    debugger.step().unwrap(); // boot vector
+    let after_boot = debugger.step().unwrap();
+    assert_eq!(after_boot.pc, 0x0201);
+    let cycles = machine.processor.borrow().get_total_cycles();
+    debugger.step().unwrap();
+    debugger.step().unwrap();
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0203);
+
+    let back = debugger.reverse_step().unwrap().unwrap();
+    assert_eq!(back.pc, 0x0202);
+    debugger.reverse_step().unwrap();
+    assert_eq!(machine.processor.borrow().get_registers(), after_boot);
+    assert_eq!(machine.processor.borrow().get_total_cycles(), cycles);
+}
+
+#[test]
+fn test_reverse_step_is_bounded() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.set_history_depth(2);
+
+    debugger.execute("step 5").unwrap();
+    assert_eq!(debugger.get_history_len(), 2);
+    let out = debugger.execute("rstep 3").unwrap();
+    assert!(out.ends_with("no more history\n"));
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0202);
+}
+
+#[test]
+fn test_travel_restores_nearest_snapshot_and_replays() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.set_snapshot_interval(4);
+
+    debugger.run(7).unwrap();
+    let at_seven = machine.processor.borrow().get_registers();
+    debugger.run(20).unwrap();
+    machine.bus.borrow().write(0x0010, 0x55);
+
+    let travelled = debugger.travel(7).unwrap();
+    assert_eq!(travelled, at_seven);
+    assert_eq!(machine.processor.borrow().get_total_cycles(), 7);
+    assert_eq!(machine.bus.borrow().read(0x0010), 0x00);
+    assert_eq!(debugger.get_history_len(), 0);
+}
+
+// Replay ticks the devices as stepping did, and the snapshots after the target are dropped
+#[test]
+#[cfg(feature = "devices")]
+fn test_travel_ticks_peripherals() {
+    use rust_6502_emulator::devices::timer::Timer;
+
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 32]);
+    system.add_peripheral(Timer::new(0x0300));
+    system.write(0x0300, 100);
+    system.write(0x0302, 0x01);
+    let processor = system.get_processor();
+    let mut debugger = Debugger::new(&processor, &system.get_bus());
+    debugger.attach_peripherals(&system.get_peripherals());
+    debugger.set_snapshot_interval(4);
+
+    debugger.run(10).unwrap();
+    let at_ten = (processor.borrow().get_registers(), system.read(0x0300));
+    assert_eq!(at_ten.1, 90);
+    debugger.run(12).unwrap();
+    assert_eq!(system.read(0x0300), 78);
+    assert_eq!(debugger.get_snapshot_count(), 6);
+
+    debugger.travel(10).unwrap();
+    assert_eq!((processor.borrow().get_registers(), system.read(0x0300)), at_ten);
+    // the ones at 0, 4 and 8 are left
+    assert_eq!(debugger.get_snapshot_count(), 3);
+}
+
+#[test]
+fn test_travel_before_first_snapshot_fails() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    assert!(debugger.execute("travel 5").is_err());
+}
+
+#[test]
+fn test_continue_stops_at_breakpoint() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    debugger.execute("break $0205").unwrap();
+    let out = debugger.execute("continue").unwrap();
+    assert!(out.starts_with("breakpoint at $0205"));
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0205);
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn test_script_handler_runs_and_continues() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    debugger
+        .load_script(
+            r#"
+            let count = 0;
+            on_break(0x0203, || {
+                count += 1;
+                poke(0x10, 0x42);
+                print(`hit ${count} at ${reg("PC")}`);
+                true
+            });
+            break_at(0x0206);
+        "#,
+        )
+        .unwrap();
+    let out = debugger.continue_execution().unwrap();
+    assert_eq!(out, "hit 1 at 515\nbreakpoint at $0206\n");
+    assert_eq!(machine.bus.borrow().read(0x0010), 0x42);
+}
+
+fn gdb_exchange(stream: &mut std::net::TcpStream, body: &str) -> String {
+    use std::io::Write;
+    let checksum = body.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    write!(stream, "${}#{:02x}", body, checksum).unwrap();
+    gdb_reply(stream)
+}
+
+fn gdb_reply(stream: &mut std::net::TcpStream) -> String {
+    use std::io::Read;
+    let mut reply = vec![];
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        if byte[0] == b'#' {
+            break;
+        }
+        reply.push(byte[0]);
+    }
+    let mut checksum = [0u8; 2];
+    stream.read_exact(&mut checksum).unwrap();
+    // drop the ack and the leading '$'
+    String::from_utf8(reply).unwrap().trim_start_matches('+').trim_start_matches('$').to_string()
+}
+
+#[test]
+fn test_gdb_remote_protocol() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    machine.bus.borrow().write(0x0300, 0xa9);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut replies = vec![];
+        for packet in ["qSupported:xmlRegisters=i386", "s", "g", "m300,2", "M301,1:ff", "Z0,205,1", "c", "p4", "D"] {
+            replies.push(gdb_exchange(&mut stream, packet));
+        }
+        replies
+    });
+    debugger.serve_gdb_on(&listener).unwrap();
+
+    let replies = client.join().unwrap();
+    assert_eq!(replies[0], "PacketSize=4000;qXfer:features:read+");
+    assert_eq!(replies[1], "S05");
+    assert_eq!(replies[2], "000000000002");
+    assert_eq!(replies[3], "a900");
+    assert_eq!(replies[4], "OK");
+    assert_eq!(replies[6], "S05");
+    assert_eq!(replies[7], "0502");
+    assert_eq!(replies[8], "OK");
+    assert_eq!(machine.bus.borrow().read(0x0301), 0xff);
+}
+
+#[test]
+fn test_gdb_rejects_bad_packets() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        use std::io::{Read, Write};
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        // a wrong checksum is asked for again
+        stream.write_all(b"$g#00").unwrap();
+        let mut nak = [0u8; 1];
+        stream.read_exact(&mut nak).unwrap();
+        let mut replies = vec![(nak[0] as char).to_string()];
+        for packet in ["M300,1:\u{e9}", "m0,ffffffff", "mffffffffffffffff,10", "D"] {
+            replies.push(gdb_exchange(&mut stream, packet));
+        }
+        replies
+    });
+    debugger.serve_gdb_on(&listener).unwrap();
+
+    let replies = client.join().unwrap();
+    assert_eq!(replies[0], "-");
+    assert_eq!(replies[1], "E02", "non-hex data is refused, not sliced");
+    assert_eq!(replies[2].len(), 0x4000, "reads are clamped to the packet size");
+    assert_eq!(replies[3], "E02");
+    assert_eq!(replies[4], "OK");
+}
+
+#[test]
+fn test_gdb_breakpoints_and_watchpoints() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let packets = ["Z0,10000,1", "Z2,10,2", "Z2,300,3", "z2,300,1", "Z2,ffff,2", "Z3,10,1", "D"];
+        packets.iter().map(|packet| gdb_exchange(&mut stream, packet)).collect::<Vec<_>>()
+    });
+    debugger.serve_gdb_on(&listener).unwrap();
+
+    let replies = client.join().unwrap();
+    assert_eq!(replies, ["E02", "OK", "OK", "OK", "E02", "", "OK"]);
+    assert!(debugger.get_breakpoints().is_empty(), "$10000 isn't truncated to $0000");
+    assert_eq!(debugger.get_watchpoints(), vec![0x0010, 0x0011, 0x0301, 0x0302]);
+}
+
+#[test]
+fn test_gdb_interrupt_stops_continue() {
+    let mut system = System::new();
+    system.set_reset_vector(0x1000);
+    system.load(0x1000, &[0xea; 0xef00]);
+    let processor = system.get_processor();
+    let mut debugger = Debugger::new(&processor, &system.get_bus());
+    debugger.step().unwrap(); // boot vector
+    // slow enough that the NOPs would take seconds to run out
+    debugger.set_speed(Speed::SlowMotion(50_000));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        use std::io::Write;
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "$c#63").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        stream.write_all(&[0x03]).unwrap();
+        let stopped = gdb_reply(&mut stream);
+        gdb_exchange(&mut stream, "D");
+        stopped
+    });
+    debugger.serve_gdb_on(&listener).unwrap();
+    assert_eq!(client.join().unwrap(), "S02");
+    assert!(processor.borrow().get_registers().pc < 0xff00);
+}
+
+struct WebSocket {
+    stream: std::net::TcpStream,
+}
+
+impl WebSocket {
+    // The handshake from RFC 6455's example, returning the Sec-WebSocket-Accept
+    fn connect(port: u16) -> (WebSocket, String) {
+        use std::io::{BufRead, BufReader, Write};
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut accept = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Sec-WebSocket-Accept: ") {
+                accept = value.trim().to_string();
+            }
+        }
+        (WebSocket { stream }, accept)
+    }
+
+    // Clients mask what they send
+    fn send(&mut self, text: &str) {
+        use std::io::Write;
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        self.stream.write_all(&frame).unwrap();
+    }
+
+    fn recv(&mut self) -> String {
+        use std::io::Read;
+        let mut header = [0u8; 2];
+        self.stream.read_exact(&mut header).unwrap();
+        let length = match header[1] {
+            126 => {
+                let mut length = [0u8; 2];
+                self.stream.read_exact(&mut length).unwrap();
+                u16::from_be_bytes(length) as usize
+            }
+            n => n as usize,
+        };
+        let mut payload = vec![0; length];
+        self.stream.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    }
+}
+
+#[test]
+fn test_remote_server() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        let (mut socket, accept) = WebSocket::connect(port);
+        let mut replies = vec![accept];
+        for request in [
+            r#"{"id": 1, "cmd": "subscribe"}"#,
+            r#"{"id": 2, "cmd": "step", "count": 2}"#,
+            r#"{"id": 3, "cmd": "write", "address": 768, "data": [169, 255]}"#,
+            r#"{"id": "r", "cmd": "read", "address": 768, "length": 3}"#,
+            r#"{"id": 5, "cmd": "set_registers", "a": 66}"#,
+            r#"{"id": 6, "cmd": "break", "address": 517}"#,
+            r#"{"id": 7, "cmd": "run"}"#,
+        ] {
+            socket.send(request);
+            replies.push(socket.recv());
+        }
+        // the run's events, then the breakpoint it hits
+        replies.push(socket.recv());
+        replies.push(socket.recv());
+        for request in [r#"{"id": 8, "cmd": "nonsense"}"#, "not json", r#"{"id": 9, "cmd": "shutdown"}"#] {
+            socket.send(request);
+            replies.push(socket.recv());
+        }
+        replies
+    });
+    debugger.serve_remote_on(&listener).unwrap();
+
+    let replies = client.join().unwrap();
+    assert_eq!(replies[0], "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(replies[1], r#"{"id":1,"ok":true}"#);
+    assert_eq!(
+        replies[2],
+        r#"{"id":2,"ok":true,"stop":null,"registers":{"pc":513,"a":0,"x":0,"y":0,"status":0,"cycles":2}}"#
+    );
+    assert_eq!(replies[3], r#"{"id":3,"ok":true}"#);
+    assert_eq!(replies[4], r#"{"id":"r","ok":true,"data":[169,255,0]}"#);
+    assert!(replies[5].contains(r#""a":66"#));
+    assert_eq!(replies[6], r#"{"id":6,"ok":true}"#);
+    assert_eq!(replies[7], r#"{"id":7,"ok":true}"#);
+    assert_eq!(replies[8], r#"{"event":"running"}"#);
+    assert!(replies[9].starts_with(r#"{"event":"stopped","stop":{"reason":"breakpoint","address":517}"#));
+    assert_eq!(replies[10], r#"{"id":8,"ok":false,"error":"unknown cmd 'nonsense'"}"#);
+    assert!(replies[11].starts_with(r#"{"id":null,"ok":false,"error":"#));
+    assert_eq!(replies[12], r#"{"id":9,"ok":true}"#);
+    assert_eq!(machine.bus.borrow().read(0x0301), 0xff);
+}
+
+// a connection that never finishes its upgrade doesn't hold up the others
+#[test]
+fn test_remote_server_with_a_stalled_handshake() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        use std::io::Write;
+        let mut stalled = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stalled.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        let (mut socket, _) = WebSocket::connect(port);
+        socket.send(r#"{"id": 1, "cmd": "shutdown"}"#);
+        let reply = socket.recv();
+        (reply, start.elapsed())
+    });
+    debugger.serve_remote_on(&listener).unwrap();
+
+    let (reply, elapsed) = client.join().unwrap();
+    assert_eq!(reply, r#"{"id":1,"ok":true}"#);
+    assert!(elapsed < Duration::from_secs(1), "waited {:?} behind the stalled client", elapsed);
+}
+
+// 16 NOPs at $0200, as nop_machine loads them, over three lines of hello.s and a macro
+const HELLO_DBG: &str = r#"version	major=2,minor=0
+info	csym=0,file=1,lib=0,line=4,mod=1,scope=1,seg=1,span=3,sym=2,type=0
+file	id=0,name="src/hello.s",size=100,mtime=0x5c2b0b7a,mod=0
+line	id=0,file=0,line=3,span=0
+line	id=1,file=0,line=4,span=1
+line	id=2,file=0,line=6,span=2
+line	id=3,file=0,line=9,type=2,span=2
+seg	id=0,name="CODE",start=0x000200,size=0x0006,addrsize=absolute,type=ro,oname="hello.bin",ooffs=0
+span	id=0,seg=0,start=0,size=1
+span	id=1,seg=0,start=1,size=2
+span	id=2,seg=0,start=3,size=3
+sym	id=0,name="start",addrsize=absolute,scope=0,def=0,ref=1,val=0x200,seg=0,type=lab
+sym	id=1,name="COUNT",addrsize=zeropage,scope=0,def=1,val=0x10,type=equ
+"#;
+
+#[test]
+fn test_ca65_debug_info() {
+    let info = DebugInfo::parse(HELLO_DBG).unwrap();
+    assert_eq!(info.len(), 3);
+    assert_eq!(info.location(0x0200), Some(("src/hello.s", 3)));
+    assert_eq!(info.location(0x0202), Some(("src/hello.s", 4)));
+    assert_eq!(info.location(0x0205), Some(("src/hello.s", 6)));
+    assert_eq!(info.location(0x0206), None);
+    assert_eq!(info.address_of("/home/me/project/src/hello.s", 6), Some(0x0203));
+    assert_eq!(info.address_of("hello.s", 4), Some(0x0201));
+    assert_eq!(info.address_of("hello.s", 5), None);
+    assert_eq!(info.address_of("other/hello.s", 3), None);
+    assert_eq!(info.get_labels(), &[("start".to_string(), 0x0200)]);
+    assert_eq!(DebugInfo::parse("seg\tid=0,name=\"CODE\"\n").unwrap_err(), "line 1: seg record without a start");
+
+    let info = info.with_directory(std::path::Path::new("/work"));
+    assert_eq!(info.location(0x0200), Some(("/work/src/hello.s", 3)));
+
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    assert_eq!(debugger.set_debug_info(info), 3);
+    debugger.execute("break start").unwrap();
+    assert_eq!(debugger.get_breakpoints(), vec![0x0200]);
+}
+
+fn dap_send(stream: &mut std::net::TcpStream, seq: usize, command: &str, arguments: &str) {
+    use std::io::Write;
+    let body = format!(r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#, seq, command, arguments);
+    write!(stream, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+}
+
+fn dap_recv(reader: &mut impl std::io::BufRead) -> String {
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        match line.trim_end().strip_prefix("Content-Length: ") {
+            Some(n) => length = n.parse().unwrap(),
+            None if line.trim_end().is_empty() => break,
+            None => (),
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    String::from_utf8(body).unwrap()
+}
+
+// a Content-Length past the limit ends the session rather than being allocated
+#[test]
+fn test_dap_refuses_huge_messages() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    let input = std::io::Cursor::new(b"Content-Length: 1099511627776\r\n\r\n{}".to_vec());
+    let mut output = vec![];
+    debugger.serve_dap_stream(input, &mut output).unwrap();
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_dap_server() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.set_debug_info(DebugInfo::parse(HELLO_DBG).unwrap().with_directory(std::path::Path::new("/work")));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut messages = vec![];
+        let mut request = |command: &str, arguments: &str, replies: usize| {
+            dap_send(&mut stream, messages.len() + 1, command, arguments);
+            let replies: Vec<String> = (0..replies).map(|_| dap_recv(&mut reader)).collect();
+            messages.push(replies);
+        };
+        // each request's response, then the events that follow it
+        request("initialize", r#"{"adapterID":"sim6502"}"#, 2);
+        request("launch", r#"{"stopOnEntry":true}"#, 1);
+        let breakpoints = r#"{"source":{"path":"/work/src/hello.s"},"breakpoints":[{"line":6},{"line":5}]}"#;
+        request("setBreakpoints", breakpoints, 1);
+        request("configurationDone", "{}", 2);
+        request("threads", "{}", 1);
+        request("next", r#"{"threadId":1}"#, 2);
+        request("next", r#"{"threadId":1}"#, 2);
+        request("stackTrace", r#"{"threadId":1}"#, 1);
+        request("variables", r#"{"variablesReference":1}"#, 1);
+        request("continue", r#"{"threadId":1}"#, 2);
+        request("readMemory", r#"{"memoryReference":"0x0200","count":2}"#, 1);
+        request("disassemble", r#"{"memoryReference":"0x0203","instructionCount":1}"#, 1);
+        request("evaluate", r#"{"expression":"regs","context":"repl"}"#, 1);
+        request("stepBack", "{}", 1);
+        request("disconnect", "{}", 1);
+        messages
+    });
+    debugger.serve_dap_on(&listener).unwrap();
+
+    let m = client.join().unwrap();
+    assert!(m[0][0].contains(r#""command":"initialize","body":{"supportsConfigurationDoneRequest":true"#));
+    assert!(m[0][1].contains(r#""event":"initialized""#));
+    assert!(m[2][0].contains(r#"{"verified":true,"line":6,"instructionReference":"0x0203"}"#));
+    assert!(m[2][0].contains(r#"{"verified":false,"line":5,"message":"no code on this line"}"#));
+    assert!(m[3][1].contains(r#""reason":"entry""#));
+    assert!(m[4][0].contains(r#""threads":[{"id":1,"name":"6502"}]"#));
+    // the first step is the boot sequence, the second a line
+    assert!(m[5][1].contains(r#""reason":"step""#));
+    assert!(m[6][1].contains(r#""reason":"step""#));
+    assert!(m[7][0].contains(r#""name":"start+1""#));
+    assert!(m[7][0].contains(r#""path":"/work/src/hello.s"},"line":4"#));
+    assert!(m[8][0].contains(r#"{"name":"PC","value":"$0201","variablesReference":0}"#));
+    assert!(m[9][0].contains(r#""allThreadsContinued":true"#));
+    assert!(m[9][1].contains(r#""reason":"breakpoint","description":"breakpoint at $0203""#));
+    assert!(m[10][0].contains(r#""address":"0x0200","data":"6uo=""#));
+    assert!(m[11][0].contains(r#""instruction":"NOP","location":{"path":"/work/src/hello.s"},"line":6"#));
+    assert!(m[12][0].contains(r#""result":"PC=0203"#));
+    assert!(m[13][0].contains(r#""success":false,"command":"stepBack","message":"stepBack isn't supported""#));
+    assert!(m[14][0].contains(r#""success":true,"command":"disconnect""#));
+}
+
+#[test]
+fn test_session_save_and_restore() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    assert_eq!(debugger.load_symbols("al 000200 .start\nal 000204 .loop\n"), 2);
+    debugger.execute("symbol counter $10").unwrap();
+    debugger.execute("break loop").unwrap();
+    debugger.execute("break $0300").unwrap();
+    debugger.execute("watchpoint counter").unwrap();
+    debugger.execute("history 50").unwrap();
+
+    let path = std::env::temp_dir().join(format!("session-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    debugger.execute(&format!("save {}", path)).unwrap();
+
+    let mut restored = Debugger::new(&machine.processor, &machine.bus);
+    restored.execute(&format!("source {}", path)).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(restored.get_breakpoints(), vec![0x0204, 0x0300]);
+    assert_eq!(restored.get_watchpoints(), vec![0x0010]);
+    assert_eq!(restored.session_commands(), debugger.session_commands());
+    let out = restored.execute("continue").unwrap();
+    assert!(out.starts_with("breakpoint at $0204"));
+}
+
+#[test]
+fn test_attach_to_running_machine_and_detach() {
+    let machine = nop_machine();
+    for _ in 0..5 {
+        machine.processor.borrow_mut().tick(&*machine.bus.borrow());
+    }
+
+    let mut debugger = Debugger::detached();
+    debugger.add_breakpoint(0x0208);
+    assert_eq!(debugger.execute("regs"), Err(DebuggerError::NotAttached));
+
+    debugger.attach(&machine.processor, &machine.bus);
+    assert_eq!(debugger.step().unwrap().pc, 0x0205);
+    debugger.execute("continue").unwrap();
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0208);
+
+    debugger.execute("detach").unwrap();
+    assert!(!debugger.is_attached());
+    machine.processor.borrow_mut().tick(&*machine.bus.borrow());
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0209);
+    assert_eq!(debugger.step(), Err(DebuggerError::NotAttached));
+}
+
+#[test]
+fn test_debugger_does_not_keep_machine_alive() {
+    let machine = nop_machine();
+    let debugger = Debugger::new(&machine.processor, &machine.bus);
+    assert!(debugger.is_attached());
+    drop(machine);
+    assert!(!debugger.is_attached());
+}
+
+#[test]
+fn test_watch_expressions_shown_after_every_stop() {
+    let machine = nop_machine();
+    machine.bus.borrow().write(0x0010, 0x34);
+    machine.bus.borrow().write(0x0011, 0x12);
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.execute("symbol ptr $10").unwrap();
+
+    assert_eq!(debugger.execute("watch PC").unwrap(), "PC = $0FFC\n");
+    debugger.execute("watch *($10)").unwrap();
+    debugger.execute("watch *(ptr) as u16").unwrap();
+    debugger.add_watch("A").unwrap();
+    assert!(debugger.add_watch("*($10) as u32").is_err());
+
+    let out = debugger.execute("step 2").unwrap();
+    assert!(out.ends_with("PC = $0201\n*($10) = $34\n*(ptr) as u16 = $1234\nA = $00\n"));
+
+    debugger.execute("unwatch 2").unwrap();
+    assert_eq!(debugger.execute("watches").unwrap(), "1: PC\n2: *(ptr) as u16\n3: A\n");
+}
+
+#[test]
+fn test_mini_assembler() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.add_symbol("counter", 0x0010);
+
+    assert_eq!(debugger.execute("a $0300 lda #$05").unwrap(), "0300: A9 05     LDA #$05\n");
+    debugger.execute("a 0302").unwrap();
+    assert_eq!(debugger.get_assembly_address(), Some(0x0302));
+    debugger.execute("STA $0200").unwrap();
+    debugger.execute("sta $10,x").unwrap();
+    debugger.execute("LDY counter").unwrap();
+    assert!(debugger.execute("LDA ($10").is_err());
+    debugger.execute("").unwrap();
+    assert_eq!(debugger.get_assembly_address(), None);
+
+    let dump = debugger.execute("mem 0300 030a").unwrap();
+    assert_eq!(dump, "0300: A9 05 8D 00 02 95 10 A4 10 00 00\n");
+}
+
+#[test]
+fn test_find_bytes_and_text() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.execute("a 0300 LDA #$05").unwrap();
+    debugger.execute("a 0400 LDA #$07").unwrap();
+    for (i, c) in "HELLO".bytes().enumerate() {
+        machine.bus.borrow().write(0x1000 + i as u16, c);
+    }
+
+    assert_eq!(debugger.find(&[Some(0xa9), None], 0x0000, 0xffff).unwrap(), vec![0x0300, 0x0400]);
+    let out = debugger.execute("find A9 05").unwrap();
+    assert_eq!(out, "$0300: 00 00 00 00 [A9 05] 00 00 00 00\n1 matches\n");
+    assert_eq!(debugger.execute("find a9 ?? $0380..$0fff").unwrap().lines().last(), Some("1 matches"));
+    assert!(debugger.execute("find-text \"HELLO\"").unwrap().starts_with("$1000: "));
+    assert!(debugger.execute("find zz").is_err());
+}
+
+// mem, find, watches and the rest look at an I/O register without acknowledging it
+#[test]
+#[cfg(feature = "devices")]
+fn test_debugger_reads_have_no_side_effects() {
+    use rust_6502_emulator::devices::timer::Timer;
+    use rust_6502_emulator::devices::Peripheral;
+
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 16]);
+    let timer = system.add_peripheral(Timer::new(0x0300));
+    system.write(0x0300, 1);
+    system.write(0x0302, 0x05);
+    timer.borrow_mut().tick(1);
+    let mut debugger = Debugger::new(&system.get_processor(), &system.get_bus());
+
+    assert_eq!(debugger.read_memory(0x0303, 1).unwrap(), vec![0x80]);
+    debugger.execute("m 0300 030f").unwrap();
+    debugger.execute("find 80 $0300..$0303").unwrap();
+    debugger.execute("watch *($0303)").unwrap();
+    debugger.execute("dis 0300 1").unwrap();
+    assert!(timer.borrow().irq_asserted());
+    assert_eq!(system.read(0x0303), 0x80);
+    assert!(!timer.borrow().irq_asserted());
+}
+
+// NOPs at $0040-$007F that count the reads made of them, as an I/O register would notice them
+#[cfg(feature = "tui")]
+struct CountedReads {
+    reads: std::cell::Cell<usize>,
+}
+
+#[cfg(feature = "tui")]
+impl rust_6502_emulator::bus::BusDevice for CountedReads {
+    fn do_read(&self, _address: u16) -> u8 {
+        self.reads.set(self.reads.get() + 1);
+        0xea
+    }
+
+    fn peek(&self, _address: u16) -> u8 {
+        0xea
+    }
+
+    fn do_write(&mut self, _address: u16, _data: u8) {}
+
+    fn is_readable_for(&self, address: u16) -> bool {
+        (0x0040..0x0080).contains(&address)
+    }
+
+    fn is_writable_for(&self, _address: u16) -> bool {
+        false
+    }
+}
+
+// A redraw of every pane, the disassembly and memory over the device included, peeks
+#[test]
+#[cfg(feature = "tui")]
+fn test_tui_redraw_reads_nothing() {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use rust_6502_emulator::debugger::draw_tui;
+
+    let mut system = System::new();
+    let device = system.add_device(CountedReads { reads: std::cell::Cell::new(0) });
+    let mut registers = system.get_registers();
+    registers.pc = 0x0040;
+    system.set_registers(&registers);
+    let mut debugger = Debugger::new(&system.get_processor(), &system.get_bus());
+
+    let mut terminal = Terminal::new(TestBackend::new(100, 40)).unwrap();
+    draw_tui(&mut debugger, &mut terminal).unwrap();
+    let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+    assert!(screen.contains("0040: EA") && screen.contains("NOP"), "the panes show the device");
+    assert_eq!(device.borrow().reads.get(), 0);
+}
+
+#[test]
+fn test_info_device() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    assert_eq!(debugger.execute("info devices").unwrap(), "memory\n");
+    let out = debugger.execute("info device memory").unwrap();
+    assert!(out.starts_with("memory\n"));
+    assert!(out.contains("$0000-$FFFF"));
+    assert!(debugger.execute("info device via").is_err());
+}
+
+#[test]
+fn test_trace_with_filters() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.execute("a 0202 LDA #$05").unwrap();
+    debugger.step().unwrap(); // boot vector
+
+    assert_eq!(debugger.execute("trace").unwrap(), "not tracing\n");
+    debugger.execute("trace on range 0201..0203 mnemonic lda,nop").unwrap();
+    let out = debugger.execute("step 5").unwrap();
+    let traced: Vec<&str> = out.lines().filter(|l| !l.starts_with("PC=")).collect();
+    assert_eq!(traced.len(), 2);
+    assert!(traced[0].starts_with("0201  EA"));
+    assert!(traced[1].starts_with("0202  A9 05     LDA #$05"));
+    assert_eq!(debugger.execute("trace off").unwrap(), "trace off, 2 lines\n");
+
+    let path = std::env::temp_dir().join(format!("trace-{}.log", std::process::id()));
+    debugger.execute(&format!("trace on mnemonic NOP to {}", path.display())).unwrap();
+    let out = debugger.execute("step 2").unwrap();
+    assert_eq!(out.lines().count(), 2);
+    debugger.execute("trace off").unwrap();
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(log.lines().count(), 2);
+    assert!(debugger.execute("trace on speed 3").is_err());
+}
+
+#[test]
+fn test_profile_by_address_and_symbol() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.step().unwrap(); // boot vector
+
+    assert_eq!(debugger.execute("profile").unwrap(), "not profiling, use profile on\n");
+    debugger.execute("profile on").unwrap();
+    let cycles = machine.processor.borrow().get_total_cycles();
+    debugger.execute("step 4").unwrap();
+    let spent = machine.processor.borrow().get_total_cycles() - cycles;
+
+    let report = debugger.execute("profile").unwrap();
+    assert!(report.starts_with(&format!("{} cycles profiled\n", spent)));
+    assert!(!report.contains("by symbol"));
+    assert!(report.contains("$0200"));
+
+    debugger.add_symbol("main", 0x0200);
+    debugger.add_symbol("tail", 0x0203);
+    let report = debugger.execute("profile 1").unwrap();
+    assert!(report.contains("by symbol:\n  main"));
+    assert!(!report.contains("tail"));
+
+    debugger.execute("profile reset").unwrap();
+    assert!(debugger.execute("profile").unwrap().starts_with("0 cycles profiled"));
+}
+
+#[test]
+fn test_coverage_report() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.execute("a 0202 LDA #$05").unwrap();
+
+    assert_eq!(debugger.execute("coverage").unwrap(), "nothing executed yet\n");
+    debugger.execute("step 4").unwrap(); // boot vector, NOP, NOP, LDA #$05
+    let coverage = debugger.get_coverage().unwrap();
+    assert!(coverage.is_executed(0x0203));
+    assert!(!coverage.is_executed(0x0204));
+    assert_eq!(debugger.execute("coverage 0200..02ff").unwrap(), "$0200-$0203  4 bytes\n4 of 256 bytes executed\n");
+
+    let path = std::env::temp_dir().join(format!("coverage-{}.txt", std::process::id()));
+    debugger.execute(&format!("coverage 0200..02ff to {}", path.display())).unwrap();
+    let exported = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(exported.starts_with("$0200-$0203"));
+
+    debugger.execute("coverage reset").unwrap();
+    assert_eq!(debugger.get_coverage().unwrap().count(), 0);
+}
+
+#[test]
+fn test_instruction_histogram() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.execute("a 0203 LDA #$05").unwrap();
+    debugger.step().unwrap(); // boot vector
+
+    debugger.execute("histogram reset").unwrap();
+    debugger.execute("step 4").unwrap(); // NOP, NOP, NOP, LDA #$05
+    let histogram = debugger.get_histogram().unwrap();
+    assert_eq!(histogram.get_count(0xea), 3);
+    assert_eq!(histogram.get_count(0xa9), 1);
+    assert_eq!(histogram.by_mnemonic()[0], ("NOP".to_string(), 3));
+
+    let report = debugger.execute("histogram").unwrap();
+    assert!(report.starts_with("4 instructions\n  NOP"));
+    assert!(debugger.execute("histogram opcodes 1").unwrap().contains("EA NOP"));
+    assert_eq!(debugger.execute("histogram opcodes 1").unwrap().lines().count(), 2);
+}
+
+#[test]
+fn test_write_log_and_undo() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    // the instruction table doesn't give stores their write cycle yet, so nothing the
+    // processor runs here reaches memory
+    debugger.execute("a 0200 STA $10").unwrap();
+
+    assert_eq!(debugger.execute("writes").unwrap(), "no writes recorded\n");
+    debugger.execute("step 2").unwrap();
+    assert!(debugger.get_last_write(0x0010).is_none());
+    assert_eq!(debugger.execute("writes to $10").unwrap(), "no writes recorded\n");
+    assert_eq!(debugger.execute("undo 3").unwrap(), "undid 0 writes\n");
+    assert!(debugger.execute("writes to").is_err());
+
+    let record = WriteRecord { address: 0xd012, old: 0x00, new: 0x05, pc: 0x0203, cycle: 10 };
+    assert_eq!(record.to_string(), "$D012 $00 -> $05 by $0203 at cycle 10");
+}
+
+#[test]
+fn test_woz_monitor() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.step().unwrap(); // boot vector
+
+    debugger.execute("monitor").unwrap();
+    assert!(debugger.is_in_monitor());
+    assert_eq!(debugger.execute("300: A9 05").unwrap(), "0300: 00\n");
+    assert_eq!(debugger.execute(": 00").unwrap(), "");
+    assert_eq!(debugger.execute("300").unwrap(), "0300: A9\n");
+    assert_eq!(debugger.execute("2FE.309").unwrap(), "02FE: 00 00\n0300: A9 05 00 00 00 00 00 00\n0308: 00 00\n");
+    assert_eq!(debugger.execute("300 301").unwrap(), "0300: A9\n0301: 05\n");
+
+    debugger.add_breakpoint(0x0302);
+    assert_eq!(debugger.execute("300R").unwrap(), "0300: A9\nbreakpoint at $0302\n");
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0302);
+
+    debugger.execute("exit").unwrap();
+    assert!(!debugger.is_in_monitor());
+    assert!(debugger.execute("300").is_err());
+}
+
+#[test]
+fn test_headless_run_control_events() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+
+    let events = debugger.resume(RunMode::Step(2)).unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(matches!(events[1], DebugEvent::Stepped { ref registers } if registers.pc == 0x0201));
+
+    debugger.add_breakpoint(0x0204);
+    let events = debugger.resume(RunMode::UntilStop).unwrap();
+    match events.last() {
+        Some(DebugEvent::Stopped { reason, registers }) => {
+            assert_eq!(*reason, StopReason::Breakpoint(0x0204));
+            assert_eq!(registers.pc, 0x0204);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let events = debugger.resume(RunMode::Cycles(4)).unwrap();
+    assert!(matches!(events.last(), Some(DebugEvent::CyclesElapsed { .. })));
+    assert_eq!(debugger.resume(RunMode::Step(0)).unwrap(), vec![]);
+
+    debugger.write_memory(0x0300, &[0xa9, 0x05]).unwrap();
+    assert_eq!(debugger.read_memory(0x0300, 3).unwrap(), vec![0xa9, 0x05, 0x00]);
+    let mut registers = debugger.get_registers().unwrap();
+    registers.x = 0x42;
+    debugger.set_registers(&registers).unwrap();
+    assert_eq!(machine.processor.borrow().get_registers().x, 0x42);
+
+    debugger.detach();
+    assert_eq!(debugger.resume(RunMode::UntilStop), Err(DebuggerError::NotAttached));
+}
+
+#[test]
+fn test_speed_modes() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.step().unwrap(); // boot vector
+
+    // single step makes run and continue one instruction
+    assert_eq!(debugger.execute("speed step").unwrap(), "speed step\n");
+    assert_eq!(debugger.run(100).unwrap().pc, 0x0201);
+    debugger.execute("continue").unwrap();
+    assert_eq!(machine.processor.borrow().get_registers().pc, 0x0202);
+
+    // 20 cycles at 1000Hz take 20ms
+    debugger.execute("speed 1000hz").unwrap();
+    assert_eq!(debugger.get_speed(), Speed::SlowMotion(1000));
+    let start = Instant::now();
+    debugger.run(20).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(15));
+    assert!(debugger.session_commands().contains(&"speed 1000".to_string()));
+
+    assert!(debugger.execute("speed warp").is_err());
+    debugger.execute("speed max").unwrap();
+    assert_eq!(debugger.get_speed(), Speed::Max);
+}
+
+#[test]
+fn test_disassemble_command() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    debugger.step().unwrap(); // boot vector
+    debugger.add_symbol("start", 0x0200);
+    let out = debugger.execute("dis").unwrap();
+    assert_eq!(out.lines().count(), 20);
+    assert_eq!(out.lines().next(), Some("0200  EA        NOP  <start>"));
+    let out = debugger.execute("dis start 2").unwrap();
+    assert_eq!(out.lines().count(), 2);
+}
+
+#[test]
+fn test_load_command() {
+    let machine = nop_machine();
+    let mut debugger = Debugger::new(&machine.processor, &machine.bus);
+    let dir = std::env::temp_dir();
+    let hex = dir.join(format!("debugger_load_{}.hex", std::process::id()));
+    let bin = dir.join(format!("debugger_load_{}.bin", std::process::id()));
+    std::fs::write(&hex, "0300: 01 02 03\n0400: 04\n").unwrap();
+    std::fs::write(&bin, [0xa9, 0x42]).unwrap();
+    let out = debugger.execute(&format!("load {}", hex.display())).unwrap();
+    assert_eq!(out, "loaded 3 bytes at $0300\nloaded 1 bytes at $0400\n");
+    debugger.execute(&format!("load {} 0500", bin.display())).unwrap();
+    assert_eq!(debugger.read_memory(0x0300, 3).unwrap(), vec![1, 2, 3]);
+    assert_eq!(debugger.read_memory(0x0500, 2).unwrap(), vec![0xa9, 0x42]);
+    assert!(debugger.execute(&format!("load {}", bin.display())).is_err());
+    std::fs::remove_file(hex).unwrap();
+    std::fs::remove_file(bin).unwrap();
+}
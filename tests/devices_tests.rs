@@ -0,0 +1,881 @@
+#![cfg(feature = "devices")]
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rust_6502_emulator::bus::{Bus, BusDevice, Data, SimpleBus};
+use rust_6502_emulator::devices::acia::{Acia, SerialBackend};
+use rust_6502_emulator::devices::beeper::{AudioSink, Beeper};
+use rust_6502_emulator::devices::cartridge::{Cartridge, Fixed16K, Latch8K};
+use rust_6502_emulator::devices::char_out::CharOut;
+use rust_6502_emulator::devices::cia::Cia;
+use rust_6502_emulator::devices::disk::Disk;
+use rust_6502_emulator::devices::easy6502::Easy6502Io;
+use rust_6502_emulator::devices::framebuffer::{AnsiDisplay, Framebuffer};
+use rust_6502_emulator::devices::gpio::Gpio;
+use rust_6502_emulator::devices::interrupt_controller::InterruptController;
+use rust_6502_emulator::devices::keyboard::{KeySource, Keyboard};
+use rust_6502_emulator::devices::pia::Pia;
+use rust_6502_emulator::devices::printer::Printer;
+use rust_6502_emulator::devices::riot::Riot;
+use rust_6502_emulator::devices::rtc::Rtc;
+use rust_6502_emulator::devices::sim65::{Sim65, Sim65Image, PARAVIRT_BASE};
+use rust_6502_emulator::devices::tcp_serial::TcpSerial;
+use rust_6502_emulator::devices::text_screen::TextScreen;
+use rust_6502_emulator::devices::timer::Timer;
+use rust_6502_emulator::devices::via::Via;
+use rust_6502_emulator::devices::watchdog::{Watchdog, PET};
+use rust_6502_emulator::devices::{Peripheral, Peripherals};
+use rust_6502_emulator::memory::Memory;
+
+#[test]
+fn test_riot_ram_ports_and_timer() {
+    let mut riot = Riot::new(0x0080, 0x0280);
+    riot.do_write(0x0080, 0x12);
+    riot.do_write(0x00ff, 0x34);
+    assert_eq!((riot.do_read(0x0080), riot.do_read(0x00ff)), (0x12, 0x34));
+    assert!(!riot.is_readable_for(0x0100));
+
+    // low nibble of port A out, high nibble in
+    riot.do_write(0x0281, 0x0f);
+    riot.do_write(0x0280, 0xa5);
+    riot.set_port_a_input(0x30);
+    assert_eq!(riot.do_read(0x0280), 0x35);
+    assert_eq!(riot.get_port_a_output(), 0xf5);
+
+    // 3 counts of 8 cycles with the interrupt on, which reading the timer at $0C leaves on
+    riot.do_write(0x029d, 3);
+    riot.tick(8);
+    assert_eq!(riot.do_read(0x028c), 2);
+    riot.tick(16);
+    assert_eq!(riot.do_read(0x028c), 0);
+    assert!(!riot.irq_asserted());
+    riot.tick(8);
+    assert!(riot.irq_asserted());
+    assert_eq!(riot.do_read(0x0285) & 0x80, 0x80);
+    // counts once a cycle after time out, reading the timer acknowledges it
+    riot.tick(2);
+    assert_eq!(riot.do_read(0x0284), 0xfd);
+    assert!(!riot.irq_asserted());
+}
+
+#[test]
+fn test_riot_timer_read_turns_irq_on_and_off() {
+    let mut riot = Riot::new(0x0080, 0x0280);
+    // 2 counts of a cycle with the interrupt off
+    riot.do_write(0x0294, 2);
+    // reading at $0C turns it on
+    assert_eq!(riot.do_read(0x028c), 2);
+    riot.tick(3);
+    assert!(riot.irq_asserted());
+    // reading at $04 acknowledges it and turns it off, so the next time out doesn't assert it
+    riot.do_read(0x0284);
+    riot.tick(256);
+    assert_eq!(riot.peek(0x0285) & 0x80, 0x80);
+    assert!(!riot.irq_asserted());
+    riot.do_read(0x028c);
+    riot.tick(256);
+    assert!(riot.irq_asserted());
+}
+
+#[test]
+fn test_riot_pa7_edge_interrupt() {
+    let mut riot = Riot::new(0x0080, 0x0280);
+    riot.do_write(0x0287, 0); // rising edge, IRQ on
+    riot.set_port_a_input(0x00);
+    assert!(!riot.irq_asserted());
+    riot.set_port_a_input(0x80);
+    assert!(riot.irq_asserted());
+    assert_eq!(riot.do_read(0x0285), 0x40);
+    assert!(!riot.irq_asserted());
+}
+
+#[test]
+fn test_peek_acknowledges_nothing() {
+    let mut riot = Riot::new(0x0080, 0x0280);
+    riot.do_write(0x0287, 0);
+    riot.set_port_a_input(0x80);
+    assert_eq!(riot.peek(0x0285), 0x40);
+    assert!(riot.irq_asserted());
+
+    let mut keyboard = Keyboard::new(0xc000);
+    keyboard.press(b'A');
+    assert_eq!((keyboard.peek(0xc001), keyboard.peek(0xc000)), (b'A', 0x80));
+    assert_eq!(keyboard.do_read(0xc001), b'A');
+
+    let mut timer = Timer::new(0x9000);
+    timer.do_write(0x9000, 1);
+    timer.do_write(0x9002, 0x05);
+    timer.tick(1);
+    assert_eq!(timer.peek(0x9003), 0x80);
+    assert!(timer.irq_asserted());
+    assert_eq!(timer.do_read(0x9003), 0x80);
+    assert!(!timer.irq_asserted());
+
+    // through the bus as well
+    let mut bus = SimpleBus { registered: vec![] };
+    let via = Rc::new(RefCell::new(Via::new(0x6000)));
+    let device: Rc<RefCell<dyn BusDevice>> = via.clone();
+    bus.register_device(&device);
+    bus.write(0x6004, 2);
+    bus.write(0x6005, 0);
+    bus.write(0x600e, 0xc0);
+    via.borrow_mut().tick(3);
+    assert_eq!(bus.peek(0x600d) & 0x40, 0x40);
+    bus.peek(0x6004);
+    assert!(via.borrow().irq_asserted());
+    bus.read(0x6004);
+    assert!(!via.borrow().irq_asserted());
+}
+
+#[test]
+fn test_pia_ports_and_ca1_interrupt() {
+    // Apple 1 layout: keyboard on port A, display on port B
+    let mut pia = Pia::new(0xd010);
+    assert_eq!(pia.do_read(0xd010), 0x00); // direction register until CRA bit 2 is set
+    pia.do_write(0xd011, 0x07); // data access, CA1 rising edge, IRQ on
+    pia.set_port_a_input(0xc1);
+    pia.set_ca1(true);
+    assert!(pia.irqa_asserted());
+    assert_eq!(pia.do_read(0xd011) & 0x80, 0x80);
+    assert_eq!(pia.do_read(0xd010), 0xc1);
+    assert!(!pia.irq_asserted());
+
+    pia.do_write(0xd012, 0x7f); // DDRB
+    pia.do_write(0xd013, 0x04);
+    pia.do_write(0xd012, 0x41);
+    assert_eq!(pia.get_port_b_output(), 0xc1);
+    assert!(!pia.is_writable_for(0xd014));
+}
+
+#[test]
+fn test_pia_c2_modes() {
+    let mut pia = Pia::new(0x8000);
+    // CB2 handshake: low after a port B write, high again on the CB1 edge
+    pia.do_write(0x8003, 0x24);
+    pia.do_write(0x8002, 0x55);
+    assert!(!pia.get_cb2());
+    pia.set_cb1(true);
+    pia.set_cb1(false);
+    assert!(pia.get_cb2());
+
+    // CA2 pulse: low for a cycle after a port A read
+    pia.do_write(0x8001, 0x2c);
+    pia.do_read(0x8000);
+    assert!(!pia.get_ca2());
+    pia.tick(1);
+    assert!(pia.get_ca2());
+
+    // CA2 manual output, then as an interrupting input
+    pia.do_write(0x8001, 0x34);
+    assert!(!pia.get_ca2());
+    pia.do_write(0x8001, 0x0c); // input, falling edge, IRQ on
+    pia.set_ca2(true);
+    pia.set_ca2(false);
+    assert!(pia.irqa_asserted());
+}
+
+// A serial line the test can type into and read back
+#[derive(Clone, Default)]
+struct TestSerial {
+    input: Rc<RefCell<VecDeque<Data>>>,
+    output: Rc<RefCell<Vec<Data>>>,
+}
+
+impl SerialBackend for TestSerial {
+    fn receive(&mut self) -> Option<Data> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn transmit(&mut self, data: Data) {
+        self.output.borrow_mut().push(data);
+    }
+}
+
+#[test]
+fn test_acia_transmit_and_receive() {
+    let serial = TestSerial::default();
+    let mut acia = Acia::new(0x8400, Box::new(serial.clone()));
+
+    for b in b"OK" {
+        assert_eq!(acia.do_read(0x8401) & 0x10, 0x10);
+        acia.do_write(0x8400, *b);
+    }
+    assert_eq!(*serial.output.borrow(), b"OK".to_vec());
+
+    assert_eq!(acia.do_read(0x8401) & 0x08, 0x00);
+    serial.input.borrow_mut().extend(b"AB");
+    acia.do_write(0x8402, 0x09); // DTR, receive IRQ on, transmit IRQ off
+    assert_eq!(acia.do_read(0x8401) & 0x88, 0x88);
+    assert!(acia.irq_asserted());
+    assert_eq!(acia.do_read(0x8400), b'A');
+    assert!(!acia.irq_asserted());
+    assert_eq!(acia.do_read(0x8400), b'B');
+    assert_eq!(acia.do_read(0x8401) & 0x08, 0x00);
+}
+
+#[test]
+fn test_tcp_serial_console() {
+    let serial = TcpSerial::listen("127.0.0.1:0").unwrap();
+    let address = serial.local_addr().unwrap();
+    let mut acia = Acia::new(0x8400, Box::new(serial));
+    let mut client = TcpStream::connect(address).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    // telnet negotiation is dropped and the line ending becomes a carriage return
+    client.write_all(&[255, 251, 1, b'H', b'I', b'\r', b'\n']).unwrap();
+    let mut received = vec![];
+    for _ in 0..500 {
+        if acia.do_read(0x8401) & 0x08 != 0 {
+            received.push(acia.do_read(0x8400));
+            if received.len() == 3 {
+                break;
+            }
+        } else {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    assert_eq!(received, b"HI\r".to_vec());
+
+    acia.do_write(0x8400, b'>');
+    let mut echoed = [0; 1];
+    client.read_exact(&mut echoed).unwrap();
+    assert_eq!(&echoed, b">");
+}
+
+// Somewhere for output devices to write that the test can look at
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_char_out() {
+    let output = SharedOutput::default();
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    let char_out: Rc<RefCell<dyn BusDevice>> = Rc::new(RefCell::new(CharOut::with_writer(0xf001, Box::new(output.clone()))));
+    bus.borrow_mut().register_device(&char_out);
+
+    for b in b"hello\nwor" {
+        bus.borrow().write(0xf001, *b);
+    }
+    assert_eq!(*output.0.borrow(), b"hello\n".to_vec());
+    bus.borrow().write(0xf002, 0);
+    assert_eq!(*output.0.borrow(), b"hello\nwor".to_vec());
+    assert_eq!(bus.borrow().read(0xf001), 0);
+}
+
+#[test]
+fn test_printer_busy_and_host_file() {
+    let output = SharedOutput::default();
+    let mut printer = Printer::with_writer(0xc300, Box::new(output.clone()));
+    printer.set_busy_cycles(100);
+    assert_eq!(printer.do_read(0xc301), 0x80);
+    printer.do_write(0xc300, b'O');
+    assert_eq!(printer.do_read(0xc301), 0x00);
+    printer.do_write(0xc300, b'X');
+    printer.tick(100);
+    printer.do_write(0xc300, b'K');
+    assert_eq!(*output.0.borrow(), b"OK".to_vec());
+
+    let path = std::env::temp_dir().join(format!("printer_test_{}.txt", std::process::id()));
+    std::fs::write(&path, b"first\n").unwrap();
+    let mut printer = Printer::open(0xc300, &path).unwrap();
+    for b in b"second\n" {
+        printer.do_write(0xc300, *b);
+    }
+    assert_eq!(std::fs::read(&path).unwrap(), b"first\nsecond\n");
+    drop(printer);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cartridge_mappers() {
+    // an 8K image repeats through the 16K window
+    let fixed = Cartridge::new((0..0x2000).map(|i| (i >> 8) as Data).collect(), Box::new(Fixed16K::new(0xc000)));
+    assert_eq!((fixed.do_read(0xc100), fixed.do_read(0xe100)), (0x01, 0x01));
+    assert!(fixed.is_readable_for(0xffff) && !fixed.is_readable_for(0xbfff));
+
+    // four 8K banks, each filled with its number
+    let rom: Vec<Data> = (0..4 * 0x2000).map(|i| (i / 0x2000) as Data).collect();
+    let mut banked = Cartridge::new(rom, Box::new(Latch8K::new(0x8000, 4)));
+    assert_eq!(banked.do_read(0x8000), 0);
+    banked.do_write(0x9fff, 2);
+    assert_eq!((banked.do_read(0x8000), banked.do_read(0x9fff)), (2, 2));
+    assert!(!banked.is_readable_for(0xa000));
+    let state = banked.save_state().unwrap();
+    banked.do_write(0x8000, 7);
+    assert_eq!(banked.do_read(0x8000), 3);
+    banked.load_state(&state);
+    assert_eq!(banked.do_read(0x8000), 2);
+    assert!(banked.debug_view().unwrap().format_state().contains("bank 2 of 4"));
+}
+
+#[test]
+fn test_interrupt_controller_priorities_and_mask() {
+    let timer = Rc::new(RefCell::new(Timer::new(0xc000)));
+    let keyboard = Rc::new(RefCell::new(Keyboard::new(0xc010)));
+    let mut irq = InterruptController::new(0xc020);
+    let k = Rc::clone(&keyboard);
+    assert_eq!(irq.add_source("keyboard", Box::new(move || k.borrow().irq_asserted())), Some(0));
+    assert_eq!(irq.add_peripheral("timer", &timer), Some(1));
+    assert!(!irq.irq_asserted());
+    assert_eq!(irq.do_read(0xc022), 0xff);
+
+    // timer fires
+    timer.borrow_mut().do_write(0xc000, 10);
+    timer.borrow_mut().do_write(0xc002, 0x05);
+    timer.borrow_mut().tick(10);
+    assert!(irq.irq_asserted());
+    assert_eq!((irq.do_read(0xc020), irq.do_read(0xc022)), (0x02, 1));
+
+    // the keyboard outranks it
+    keyboard.borrow_mut().do_write(0xc012, 0x01);
+    keyboard.borrow_mut().press(b'k');
+    assert_eq!((irq.do_read(0xc020), irq.do_read(0xc022)), (0x03, 0));
+
+    // masked sources don't count
+    irq.do_write(0xc021, 0x02);
+    assert_eq!((irq.do_read(0xc020), irq.do_read(0xc022)), (0x02, 1));
+    irq.do_write(0xc021, 0x00);
+    assert!(!irq.irq_asserted());
+    for n in 2..8 {
+        assert_eq!(irq.add_source("spare", Box::new(|| false)), Some(n));
+    }
+    assert_eq!(irq.add_source("one too many", Box::new(|| false)), None);
+}
+
+#[test]
+fn test_gpio_bits_call_host_closures() {
+    let led = Rc::new(RefCell::new(vec![]));
+    let switch = Rc::new(RefCell::new(true));
+    let l = Rc::clone(&led);
+    let s = Rc::clone(&switch);
+    let mut gpio = Gpio::new(0xc400)
+        .on_change(0, move |on| l.borrow_mut().push(on))
+        .on_read(7, move || *s.borrow());
+    gpio.set_inputs(0x02);
+
+    // the LED only changes once bit 0 is an output
+    gpio.do_write(0xc400, 0x01);
+    assert!(led.borrow().is_empty());
+    gpio.do_write(0xc401, 0x01);
+    gpio.do_write(0xc400, 0x01);
+    gpio.do_write(0xc400, 0x00);
+    assert_eq!(*led.borrow(), vec![true, false]);
+
+    assert_eq!(gpio.do_read(0xc400), 0x82);
+    *switch.borrow_mut() = false;
+    gpio.do_write(0xc400, 0x01);
+    assert_eq!((gpio.do_read(0xc400), gpio.get_outputs()), (0x03, 0x01));
+}
+
+#[test]
+fn test_peripherals_tick_reset_and_irq_together() {
+    let mut bus = SimpleBus { registered: vec![] };
+    let timer = Rc::new(RefCell::new(Timer::new(0xc000)));
+    let watchdog = Rc::new(RefCell::new(Watchdog::new(0xc010, 50)));
+    let mut peripherals = Peripherals::new();
+    peripherals.attach(&mut bus, &timer);
+    peripherals.attach(&mut bus, &watchdog);
+
+    bus.write(0xc000, 20);
+    bus.write(0xc002, 0x05);
+    bus.write(0xc011, 0x01);
+    peripherals.tick(20);
+    assert!(peripherals.irq_asserted());
+    assert_eq!(watchdog.borrow().get_remaining(), 30);
+
+    peripherals.reset();
+    assert!(!peripherals.irq_asserted());
+    assert!(!timer.borrow().is_running());
+    peripherals.tick(100);
+    assert!(!watchdog.borrow().reset_asserted(), "reset disables the watchdog");
+}
+
+struct ScriptedKeys(VecDeque<Data>);
+
+impl KeySource for ScriptedKeys {
+    fn next_key(&mut self) -> Option<Data> {
+        self.0.pop_front()
+    }
+}
+
+#[test]
+fn test_keyboard_status_data_and_irq() {
+    let mut keyboard = Keyboard::with_source(0xc000, Box::new(ScriptedKeys(VecDeque::from(vec![b'A']))));
+    keyboard.do_write(0xc002, 0x01);
+    assert_eq!(keyboard.do_read(0xc000), 0x80);
+    assert!(keyboard.irq_asserted());
+    assert_eq!(keyboard.do_read(0xc001), b'A');
+    assert_eq!(keyboard.do_read(0xc000), 0x00);
+    assert!(!keyboard.irq_asserted());
+
+    keyboard.press(b'\r');
+    assert_eq!(keyboard.do_read(0xc000), 0x80);
+    assert_eq!(keyboard.do_read(0xc001), b'\r');
+}
+
+#[test]
+fn test_timer_one_shot_and_free_run() {
+    let mut timer = Timer::new(0x9000);
+    timer.do_write(0x9000, 100);
+    timer.do_write(0x9001, 0);
+    timer.do_write(0x9002, 0x05); // start, one shot, IRQ on
+    timer.tick(60);
+    assert_eq!(timer.do_read(0x9000), 40);
+    assert!(!timer.irq_asserted());
+    timer.tick(50);
+    assert!(timer.irq_asserted());
+    assert!(!timer.is_running());
+    assert_eq!(timer.do_read(0x9003), 0x80);
+    assert!(!timer.irq_asserted());
+
+    timer.do_write(0x9002, 0x03); // start, free run, no IRQ
+    timer.tick(250);
+    assert!(timer.is_running());
+    assert_eq!(timer.do_read(0x9000), 50);
+    assert_eq!(timer.do_read(0x9003), 0x80);
+    assert!(!timer.irq_asserted());
+
+    timer.do_write(0x9002, 0x00);
+    timer.tick(1000);
+    assert_eq!(timer.do_read(0x9000), 50);
+}
+
+#[test]
+fn test_easy6502_random_and_last_key() {
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    let io = Rc::new(RefCell::new(Easy6502Io::with_seed(6502)));
+    let memory = Rc::new(RefCell::new(Memory::new(0x0000, 0xffff)));
+    let device: Rc<RefCell<dyn BusDevice>> = io.clone();
+    bus.borrow_mut().register_device(&device);
+    bus.borrow_mut().register_device(&memory.borrow().as_cloned_bus_device(Rc::clone(&memory)));
+
+    let randoms: Vec<Data> = (0..16).map(|_| bus.borrow().read(0x00fe)).collect();
+    assert!(randoms.iter().any(|r| *r != randoms[0]));
+    let again = Easy6502Io::with_seed(6502);
+    assert_eq!(again.do_read(0x00fe), randoms[0]);
+
+    io.borrow_mut().press(b'w');
+    assert_eq!(bus.borrow().read(0x00ff), b'w');
+    bus.borrow().write(0x00ff, 0);
+    assert_eq!(bus.borrow().read(0x00ff), 0);
+    bus.borrow().write(0x00fd, 7);
+    assert_eq!(bus.borrow().read(0x00fd), 7);
+}
+
+#[test]
+fn test_framebuffer_palette_and_cadence() {
+    let output = SharedOutput::default();
+    let mut screen = Framebuffer::new(0x0200).with_sink(Box::new(AnsiDisplay::with_writer(Box::new(output.clone()))));
+    screen.set_cycles_per_frame(1000);
+    screen.do_write(0x0200, 0x01);
+    screen.do_write(0x05ff, 0x12); // only the low nibble picks the colour
+    let rgb = screen.to_rgb();
+    assert_eq!((rgb[0], rgb[1], rgb[1023]), (0xffffff, 0x000000, 0x880000));
+    assert!(!screen.is_writable_for(0x0600));
+
+    screen.tick(999);
+    assert!(output.0.borrow().is_empty());
+    screen.tick(1);
+    let frame = String::from_utf8(output.0.borrow().clone()).unwrap();
+    assert!(frame.starts_with("\x1b[H\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2580}"));
+    assert_eq!(frame.matches('\u{2580}').count(), 32 * 16);
+
+    // unchanged frames aren't redrawn
+    screen.tick(1000);
+    assert_eq!(output.0.borrow().len(), frame.len());
+}
+
+#[test]
+fn test_framebuffer_frames_without_a_sink() {
+    let mut screen = Framebuffer::new(0x0200);
+    screen.set_cycles_per_frame(10);
+    assert_eq!(screen.take_frame().map(|f| f.len()), Some(32 * 32 * 4));
+    assert_eq!(screen.take_frame(), None);
+    screen.do_write(0x0201, 0x05);
+    // ticking doesn't present a frame nobody is drawing, so it's still there to take
+    screen.tick(100);
+    let frame = screen.take_frame().unwrap();
+    assert_eq!(&frame[4..8], &[0x00, 0xcc, 0x55, 0xff]);
+    assert!(!screen.is_dirty());
+}
+
+#[test]
+fn test_text_screen_cells_and_cursor() {
+    let mut screen = TextScreen::new(0x0400, 0xd000);
+    for (i, c) in b"READY.".iter().enumerate() {
+        screen.do_write(0x0400 + i as u16, *c);
+    }
+    screen.do_write(0x0400 + 40, 0x07); // unprintable
+    screen.do_write(0xd000, 6);
+    assert_eq!(screen.lines()[0], format!("{:<40}", "READY."));
+    assert_eq!(screen.lines()[1], " ".repeat(40));
+    assert_eq!(screen.lines().len(), 25);
+    assert_eq!(screen.get_cursor(), Some((6, 0)));
+    screen.do_write(0xd002, 0);
+    assert_eq!(screen.get_cursor(), None);
+    assert!(screen.is_writable_for(0x07e7));
+    assert!(!screen.is_writable_for(0x07e8));
+}
+
+#[test]
+fn test_disk_reads_and_writes_sectors_by_dma() {
+    let path = std::env::temp_dir().join(format!("disk_test_{}.img", std::process::id()));
+    std::fs::write(&path, [0x11; 512]).unwrap();
+    let memory = Rc::new(RefCell::new(Memory::new(0x0000, 0xbfff)));
+    let ram = memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory));
+    let disk: Rc<RefCell<dyn BusDevice>> = Rc::new(RefCell::new(Disk::open(0xc0e0, &path, &ram).unwrap()));
+    let mut bus = SimpleBus { registered: vec![] };
+    bus.register_device(&disk);
+    bus.register_device(&ram);
+
+    // track 0 sector 1 into $0400
+    bus.write(0xc0e2, 1);
+    bus.write(0xc0e3, 0x00);
+    bus.write(0xc0e4, 0x04);
+    bus.write(0xc0e0, 0x01);
+    assert_eq!((bus.read(0xc0e0), bus.read(0x0400), bus.read(0x04ff)), (0x00, 0x11, 0x11));
+
+    // write it back out as track 1 sector 0, past the end of the image
+    bus.write(0x0400, 0xa5);
+    bus.write(0xc0e1, 1);
+    bus.write(0xc0e2, 0);
+    bus.write(0xc0e0, 0x02);
+    assert_eq!(bus.read(0xc0e0), 0x00);
+    let image = std::fs::read(&path).unwrap();
+    assert_eq!((image.len(), image[16 * 256], image[16 * 256 + 1]), (17 * 256, 0xa5, 0x11));
+
+    // no such sector
+    bus.write(0xc0e2, 16);
+    bus.write(0xc0e0, 0x01);
+    assert_eq!(bus.read(0xc0e0), 0x01);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_rtc_fixed_time_and_one_second_irq() {
+    let mut rtc = Rtc::fixed(0xc100, 23, 59, 58, 1000);
+    assert_eq!((rtc.do_read(0xc102), rtc.do_read(0xc101), rtc.do_read(0xc100)), (23, 59, 58));
+    rtc.do_write(0xc103, 0x01);
+    rtc.tick(999);
+    assert!(!rtc.irq_asserted());
+    rtc.tick(1);
+    assert!(rtc.irq_asserted());
+    assert_eq!(rtc.get_time(), (23, 59, 59));
+    assert_eq!(rtc.do_read(0xc104), 0x80);
+    assert!(!rtc.irq_asserted());
+
+    // past midnight
+    rtc.tick(1000);
+    assert_eq!(rtc.get_time(), (0, 0, 0));
+    assert!(Rtc::new(0xc100).get_time().0 < 24);
+}
+
+#[test]
+fn test_watchdog_fires_unless_petted() {
+    let mut watchdog = Watchdog::new(0xc200, 100);
+    watchdog.tick(500);
+    assert!(!watchdog.reset_asserted(), "disabled until firmware enables it");
+
+    watchdog.do_write(0xc201, 0x01);
+    watchdog.tick(90);
+    watchdog.do_write(0xc200, 0x00);
+    assert_eq!(watchdog.get_remaining(), 10, "only $5A pets it");
+    watchdog.do_write(0xc200, PET);
+    watchdog.tick(99);
+    assert!(!watchdog.reset_asserted());
+    watchdog.tick(1);
+    assert!(watchdog.reset_asserted() && !watchdog.nmi_asserted());
+
+    watchdog.acknowledge();
+    assert!(!watchdog.reset_asserted());
+    assert_eq!(watchdog.do_read(0xc202), 0x80);
+    assert_eq!(watchdog.do_read(0xc202), 0x00);
+
+    watchdog.do_write(0xc201, 0x03);
+    watchdog.tick(100);
+    assert!(watchdog.nmi_asserted() && !watchdog.reset_asserted());
+}
+
+// Pushes 16 bit words onto a cc65 C stack whose pointer lives at $00
+fn push_c_stack(bus: &dyn Bus, words: &[u16]) {
+    for word in words {
+        let sp = (bus.read(0x00) as u16 | (bus.read(0x01) as u16) << 8) - 2;
+        bus.write(sp, *word as Data);
+        bus.write(sp + 1, (*word >> 8) as Data);
+        bus.write(0x00, sp as Data);
+        bus.write(0x01, (sp >> 8) as Data);
+    }
+}
+
+#[test]
+fn test_sim65_image_and_host_file_hooks() {
+    use rust_6502_emulator::processor::{create6502, ProcessorTrait};
+
+    let header = [b's', b'i', b'm', b'6', b'5', 2, 0, 0x00, 0x00, 0x02, 0x00, 0x02, 0xea];
+    let image = Sim65Image::parse(&header).unwrap();
+    assert_eq!((image.sp_address, image.load, image.reset, image.code.len()), (0x00, 0x0200, 0x0200, 1));
+    assert!(Sim65Image::parse(b"sim65\x01rest of it").is_err());
+
+    let memory = Rc::new(RefCell::new(Memory::new(0x0000, 0xffff)));
+    image.load_into(&mut memory.borrow_mut());
+    assert_eq!(memory.borrow().do_read(PARAVIRT_BASE), 0x60);
+    let mut bus = SimpleBus { registered: vec![] };
+    bus.register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+    bus.write(0x00, 0x00);
+    bus.write(0x01, 0xc0);
+
+    let path = std::env::temp_dir().join(format!("sim65_test_{}.txt", std::process::id()));
+    let name = path.to_str().unwrap();
+    for (i, byte) in name.bytes().chain([0]).enumerate() {
+        bus.write(0x0300 + i as u16, byte);
+    }
+    memory.borrow_mut().write(0x0400, b"hello".to_vec());
+
+    let mut sim65 = Sim65::new(0x00).with_args(vec!["test".to_string()]);
+    let mut cpu = create6502();
+    let mut call = |sim65: &mut Sim65, bus: &SimpleBus, hook: u16, a: Data, x: Data, y: Data| {
+        let mut registers = cpu.get_registers();
+        registers.pc = PARAVIRT_BASE + hook;
+        (registers.a, registers.x, registers.y) = (a, x, y);
+        cpu.set_registers(&registers);
+        assert!(sim65.trap(&mut cpu, bus));
+        let r = cpu.get_registers();
+        r.a as u16 | (r.x as u16) << 8
+    };
+
+    // open(name, O_WRONLY | O_CREAT | O_TRUNC)
+    push_c_stack(&bus, &[0x0300, 0x0032]);
+    let fd = call(&mut sim65, &bus, 0, 0, 0, 4);
+    assert_eq!(fd, 3);
+    // write(fd, $0400, 5)
+    push_c_stack(&bus, &[fd, 0x0400]);
+    assert_eq!(call(&mut sim65, &bus, 3, 5, 0, 0), 5);
+    assert_eq!(call(&mut sim65, &bus, 1, fd as Data, 0, 0), 0);
+    assert_eq!(call(&mut sim65, &bus, 1, fd as Data, 0, 0), 0xffff);
+    assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+    // open it again read only and read it into $0500
+    push_c_stack(&bus, &[0x0300, 0x0001]);
+    let fd = call(&mut sim65, &bus, 0, 0, 0, 4);
+    push_c_stack(&bus, &[fd, 0x0500]);
+    assert_eq!(call(&mut sim65, &bus, 2, 0x10, 0, 0), 5);
+    assert_eq!(bus.read(0x0504), b'o');
+    std::fs::remove_file(&path).unwrap();
+
+    // argv goes below the C stack
+    assert_eq!(call(&mut sim65, &bus, 4, 0x10, 0x00, 0), 1);
+    let argv = bus.read(0x10) as u16 | (bus.read(0x11) as u16) << 8;
+    let arg0 = bus.read(argv) as u16 | (bus.read(argv + 1) as u16) << 8;
+    assert_eq!((bus.read(arg0), bus.read(arg0 + 4)), (b't', 0));
+
+    assert_eq!(sim65.get_exit_code(), None);
+    call(&mut sim65, &bus, 5, 3, 0, 0);
+    assert_eq!(sim65.get_exit_code(), Some(3));
+}
+
+struct SampleCollector {
+    samples: Rc<RefCell<Vec<f32>>>,
+}
+
+impl AudioSink for SampleCollector {
+    fn get_sample_rate(&self) -> u32 {
+        1000
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        self.samples.borrow_mut().extend_from_slice(samples);
+    }
+}
+
+#[test]
+fn test_beeper_turns_toggles_into_timed_samples() {
+    let samples = Rc::new(RefCell::new(vec![]));
+    // a 10kHz clock and 1kHz sink: one sample every 10 cycles
+    let mut beeper = Beeper::new(0xc030, 10_000).with_sink(Box::new(SampleCollector {
+        samples: Rc::clone(&samples),
+    }));
+    assert!(beeper.is_readable_for(0xc030) && !beeper.is_writable_for(0xc031));
+
+    // silent until the speaker moves
+    beeper.tick(20);
+    beeper.do_write(0xc030, 0);
+    beeper.tick(20);
+    beeper.do_read(0xc030);
+    beeper.tick(20);
+    assert_eq!(*samples.borrow(), vec![0.0, 0.0, 0.25, 0.25, -0.25, -0.25]);
+    assert_eq!(beeper.get_toggles(), 2);
+
+    // and silent again a tenth of a second after it stops
+    beeper.tick(2000);
+    assert_eq!(samples.borrow().last(), Some(&0.0));
+}
+
+#[cfg(feature = "terminal")]
+#[test]
+fn test_text_screen_draws_with_crossterm() {
+    let mut screen = TextScreen::new(0x0400, 0xd000);
+    screen.do_write(0x0400, b'A');
+    let mut out: Vec<u8> = vec![];
+    screen.draw(&mut out).unwrap();
+    let drawn = String::from_utf8(out).unwrap();
+    assert!(drawn.starts_with("\x1b[1;1HA"));
+    assert!(drawn.ends_with("\x1b[1;1H\x1b[?25h"));
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn test_gui_runs_a_fixed_cycle_budget_per_frame() {
+    use rust_6502_emulator::gui::{FrameSource, Gui, GuiOptions};
+    use rust_6502_emulator::processor::{create6502, ProcessorTrait};
+
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    let memory = Rc::new(RefCell::new(Memory::new(0x0000, 0xffff)));
+    memory.borrow_mut().write(0x0200, vec![0xea; 0x100]);
+    let framebuffer = Rc::new(RefCell::new(Framebuffer::new(0x0200)));
+    let processor: Rc<RefCell<dyn ProcessorTrait>> = Rc::new(RefCell::new(create6502()));
+    bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+    assert_eq!(framebuffer.borrow().get_size(), (32, 32));
+
+    let ran = Rc::new(RefCell::new(vec![]));
+    let seen = Rc::clone(&ran);
+    let mut gui = Gui::new(&processor, &bus, framebuffer)
+        .with_options(GuiOptions {
+            cycles_per_frame: 100,
+            ..GuiOptions::default()
+        })
+        .on_frame(move |cycles| seen.borrow_mut().push(cycles));
+    assert_eq!(gui.run_frame(), 100);
+    assert_eq!(gui.run_frame(), 100);
+    assert_eq!(*ran.borrow(), vec![100, 100]);
+    assert_eq!(processor.borrow().get_total_cycles(), 200);
+}
+
+#[cfg(feature = "serial")]
+#[test]
+fn test_acia_on_a_missing_host_serial_port() {
+    assert!(Acia::serial(0x8400, "/dev/no-such-tty", 9600).is_err());
+}
+
+#[test]
+fn test_via_ports_timers_and_irq() {
+    let mut via = Via::new(0x6000);
+    via.do_write(0x6002, 0xff); // port B all out
+    via.do_write(0x6000, 0x5a);
+    assert_eq!(via.get_port_b_output(), 0x5a);
+    via.do_write(0x6003, 0x0f); // port A low nibble out
+    via.do_write(0x6001, 0x03);
+    via.set_port_a_input(0xa0);
+    assert_eq!(via.do_read(0x6001), 0xa3);
+
+    // timer 1 one shot: 10 then underflow, IRQ only once enabled
+    via.do_write(0x6004, 10);
+    via.do_write(0x6005, 0);
+    via.tick(10);
+    assert_eq!(via.do_read(0x6004), 0);
+    assert_eq!(via.do_read(0x600d), 0);
+    via.tick(1);
+    assert_eq!(via.do_read(0x600d), 0x40);
+    assert!(!via.irq_asserted());
+    via.do_write(0x600e, 0xc0);
+    assert!(via.irq_asserted());
+    assert_eq!(via.do_read(0x600d), 0xc0);
+    assert_eq!(via.do_read(0x600e), 0xc0);
+    via.do_read(0x6004);
+    assert!(!via.irq_asserted(), "reading T1 low acknowledges it");
+
+    // free running reloads from the latch every latch + 2 cycles
+    via.do_write(0x600b, 0x40);
+    via.do_write(0x6004, 4);
+    via.do_write(0x6005, 0);
+    via.tick(5);
+    assert!(via.irq_asserted());
+    via.do_write(0x600d, 0x40);
+    via.tick(5);
+    assert!(!via.irq_asserted());
+    via.tick(1);
+    assert!(via.irq_asserted());
+    via.do_write(0x600e, 0x40);
+    assert!(!via.irq_asserted(), "disabled in IER");
+
+    // timer 2 one shot
+    via.do_write(0x600e, 0xa0);
+    via.do_write(0x6008, 3);
+    via.do_write(0x6009, 0);
+    via.tick(4);
+    assert!(via.irq_asserted());
+    assert_eq!(via.do_read(0x6008), 0xff);
+    assert!(!via.irq_asserted());
+}
+
+#[test]
+fn test_via_control_line_edges() {
+    let mut via = Via::new(0x6000);
+    via.do_write(0x600e, 0x92); // CA1 and CB1
+    via.do_write(0x600c, 0x10); // CA1 falling, CB1 rising
+    via.set_ca1(true);
+    assert!(!via.irq_asserted());
+    via.set_ca1(false);
+    assert_eq!(via.do_read(0x600d), 0x82);
+    via.do_read(0x6001);
+    assert!(!via.irq_asserted(), "reading port A clears CA1");
+    via.set_cb1(true);
+    assert_eq!(via.do_read(0x600d), 0x90);
+    via.do_write(0x6000, 0);
+    assert!(!via.irq_asserted(), "writing port B clears CB1");
+
+    via.do_write(0x600c, 0xc0 | 0x0e);
+    assert!(!via.get_cb2());
+    assert!(via.get_ca2());
+}
+
+#[test]
+fn test_cia_timers_and_icr() {
+    let mut cia = Cia::new(0xdc00);
+    cia.do_write(0xdc02, 0xff);
+    cia.do_write(0xdc00, 0x7f);
+    cia.set_port_b_input(0xfe);
+    assert_eq!((cia.get_port_a_output(), cia.do_read(0xdc01)), (0x7f, 0xfe));
+    assert_eq!(cia.do_read(0xdc10), 0x7f, "mirrored every 16 bytes");
+
+    // timer A continuous at 100 cycles with its interrupt on
+    cia.do_write(0xdc04, 100);
+    cia.do_write(0xdc05, 0);
+    assert_eq!(cia.do_read(0xdc04), 100, "a stopped timer loads from the high byte write");
+    cia.do_write(0xdc0d, 0x81);
+    cia.do_write(0xdc0e, 0x01);
+    cia.tick(100);
+    assert!(!cia.irq_asserted());
+    cia.tick(1);
+    assert!(cia.irq_asserted());
+    assert_eq!(cia.do_read(0xdc04), 100);
+    assert_eq!(cia.do_read(0xdc0d), 0x81);
+    assert!(!cia.irq_asserted(), "reading the ICR clears it");
+    cia.tick(101);
+    assert!(cia.irq_asserted());
+
+    // timer B one shot stops, its flag isn't enabled
+    cia.do_read(0xdc0d);
+    cia.do_write(0xdc06, 5);
+    cia.do_write(0xdc07, 0);
+    cia.do_write(0xdc0f, 0x09);
+    cia.tick(6);
+    assert_eq!(cia.do_read(0xdc0f) & 0x01, 0);
+    let icr = cia.do_read(0xdc0d);
+    assert_eq!(icr & 0x02, 0x02);
+}
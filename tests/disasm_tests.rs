@@ -0,0 +1,48 @@
+use rust_6502_emulator::disasm::Disassembler;
+use rust_6502_emulator::system::System;
+
+#[test]
+fn test_decode_records() {
+    let disassembler = Disassembler::new();
+    let lines = disassembler.disassemble_bytes(&[0xea, 0xa9, 0x42, 0x95, 0x01, 0x6c], 0xc000);
+    let fields: Vec<_> = lines.iter().map(|l| (l.address, l.len(), l.mnemonic.as_str(), l.operand.as_str())).collect();
+    assert_eq!(
+        fields,
+        vec![
+            (0xc000, 1, "NOP", ""),
+            (0xc001, 2, "LDA", "#$42"),
+            (0xc003, 2, "STA", "$01,X"),
+            (0xc005, 1, "???", ""),
+        ]
+    );
+    assert_eq!(lines[1].bytes, vec![0xa9, 0x42]);
+    assert_eq!(lines[1].to_string(), "C001  A9 42     LDA #$42");
+}
+
+#[test]
+fn test_decode_from_the_bus() {
+    let mut system = System::new();
+    system.load(0x0200, &[0xa2, 0x05]);
+    let line = Disassembler::new().decode(&*system.get_bus().borrow(), 0x0200);
+    assert_eq!((line.mnemonic.as_str(), line.operand.as_str()), ("LDX", "#$05"));
+    let lines = Disassembler::new().disassemble(&*system.get_bus().borrow(), 0x0200, 0x0203);
+    assert_eq!(lines.len(), 3);
+}
+
+#[test]
+fn test_data_heuristics() {
+    let image = [0xea, 0x00, 0x00, 0x00, 0x00, 0x00, 0x6c, 0xa9];
+    let disassembler = Disassembler::new().with_data_heuristics(true);
+    let lines = disassembler.disassemble_bytes(&image, 0x1000);
+    let summary: Vec<_> = lines.iter().map(|l| (l.address, l.is_data(), l.operand.as_str())).collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0x1000, false, ""),
+            // padding, an unknown opcode and an immediate load cut off by the end
+            (0x1001, true, "$00,$00,$00,$00,$00"),
+            (0x1006, true, "$6C"),
+            (0x1007, true, "$A9"),
+        ]
+    );
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "ffi")]
+
+use std::ffi::CStr;
+use std::fs;
+use std::ptr;
+
+use rust_6502_emulator::ffi::*;
+
+#[test]
+fn test_ffi() {
+    unsafe {
+        let machine = sim6502_create();
+        // LDA #$01, NOP, BRK
+        let program = [0xa9, 0x01, 0xea, 0x00];
+        assert_eq!(sim6502_load(machine, 0x0200, program.as_ptr(), program.len()), 0);
+        assert_eq!(sim6502_load(machine, 0xfffe, program.as_ptr(), program.len()), -1);
+        sim6502_set_reset_vector(machine, 0x0200);
+        assert_eq!(sim6502_peek(machine, 0x0201), 0x01);
+        sim6502_poke(machine, 0x0300, 0x42);
+        assert_eq!(sim6502_peek(machine, 0x0300), 0x42);
+
+        // the boot sequence, then LDA
+        assert!(sim6502_step(machine) > 0);
+        assert!(sim6502_step(machine) > 0);
+        let mut registers = Sim6502Registers::default();
+        sim6502_get_registers(machine, &mut registers);
+        assert_eq!(registers.pc, 0x0202);
+        assert!(sim6502_get_total_cycles(machine) > 0);
+
+        registers.x = 7;
+        registers.pc = 0x0202;
+        sim6502_set_registers(machine, &registers);
+        let mut read = Sim6502Registers::default();
+        sim6502_get_registers(machine, &mut read);
+        assert_eq!(read, registers);
+        assert!(!sim6502_is_halted(machine));
+
+        // JMP isn't in the processor yet, so it panics, which faults the machine
+        sim6502_poke(machine, 0x0202, 0x4c);
+        assert_eq!(sim6502_run(machine, 100), 0);
+        assert!(sim6502_is_faulted(machine));
+        assert_eq!(sim6502_step(machine), 0);
+        sim6502_reset(machine);
+        assert!(!sim6502_is_faulted(machine));
+
+        assert_eq!(sim6502_load_file(machine, 0x0200, c"/no/such/file".as_ptr()), -1);
+        sim6502_destroy(machine);
+
+        // NULLs are ignored
+        assert_eq!(sim6502_step(ptr::null_mut()), 0);
+        assert_eq!(sim6502_load(ptr::null_mut(), 0, program.as_ptr(), 1), -1);
+        sim6502_destroy(ptr::null_mut());
+        assert_eq!(CStr::from_ptr(sim6502_version()).to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+}
+
+// The header is kept by hand when cbindgen isn't around, so check it has every function
+#[test]
+fn test_header_declares_every_function() {
+    let source = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/src/ffi.rs")).unwrap();
+    let header = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/include/sim6502.h")).unwrap();
+    let functions: Vec<&str> = source
+        .lines()
+        .filter_map(|line| line.split_once("extern \"C\" fn ")?.1.split('(').next())
+        .collect();
+    assert_eq!(functions.len(), 16);
+    for function in functions {
+        let declared = header.contains(&format!(" {}(", function)) || header.contains(&format!("*{}(", function));
+        assert!(declared, "{} isn't in the header", function);
+    }
+}
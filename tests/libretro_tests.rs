@@ -0,0 +1,105 @@
+#![cfg(feature = "libretro")]
+
+use std::cell::RefCell;
+use std::ffi::{c_uint, c_void, CStr};
+use std::ptr;
+
+use rust_6502_emulator::libretro::*;
+
+#[derive(Default)]
+struct Frontend {
+    environment: Vec<(c_uint, c_uint)>,
+    frames: Vec<(usize, c_uint, c_uint, usize)>,
+    audio_frames: usize,
+    polls: usize,
+    queried: Vec<c_uint>,
+}
+
+thread_local! {
+    static FRONTEND: RefCell<Frontend> = RefCell::new(Frontend::default());
+}
+
+extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    let value = unsafe { *(data as *const c_uint) };
+    FRONTEND.with(|f| f.borrow_mut().environment.push((cmd, value)));
+    true
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    let pixels = unsafe { std::slice::from_raw_parts(data as *const u32, height as usize * pitch / 4) };
+    FRONTEND.with(|f| f.borrow_mut().frames.push((pixels.len(), width, height, pitch)));
+}
+
+extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    FRONTEND.with(|f| f.borrow_mut().audio_frames += frames);
+    frames
+}
+
+extern "C" fn input_poll() {
+    FRONTEND.with(|f| f.borrow_mut().polls += 1);
+}
+
+extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    assert_eq!((port, device), (0, DEVICE_JOYPAD));
+    FRONTEND.with(|f| f.borrow_mut().queried.push(id));
+    (id == JOYPAD_UP) as i16
+}
+
+#[test]
+fn test_libretro_core() {
+    assert_eq!(retro_api_version(), API_VERSION);
+    let mut info = SystemInfo {
+        library_name: ptr::null(),
+        library_version: ptr::null(),
+        valid_extensions: ptr::null(),
+        need_fullpath: true,
+        block_extract: true,
+    };
+    unsafe { retro_get_system_info(&mut info) };
+    assert_eq!(unsafe { CStr::from_ptr(info.library_name) }.to_str().unwrap(), "sim6502");
+    assert!(!info.need_fullpath);
+
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    // nothing loaded, nothing run
+    retro_run();
+    assert!(FRONTEND.with(|f| f.borrow().frames.is_empty()));
+
+    // NOPs at $0600
+    let program = [0xea; 16];
+    let game = GameInfo {
+        path: ptr::null(),
+        data: program.as_ptr() as *const c_void,
+        size: program.len(),
+        meta: ptr::null(),
+    };
+    assert!(unsafe { retro_load_game(&game) });
+    retro_run();
+    retro_run();
+    FRONTEND.with(|f| {
+        let f = f.borrow();
+        // XRGB8888
+        assert_eq!(f.environment, [(10, 1)]);
+        assert_eq!(f.frames, [(32 * 32, 32, 32, 128); 2]);
+        assert_eq!(f.audio_frames, 735 * 2);
+        assert_eq!(f.polls, 2);
+        assert_eq!(f.queried.len(), 12);
+        assert!(f.queried.contains(&JOYPAD_LEFT) && f.queried.contains(&JOYPAD_START));
+    });
+
+    // without JMP the processor runs off the end of the program, onto an opcode it doesn't have,
+    // which stops the machine but not the frames
+    assert!(sim6502_retro_is_faulted());
+    retro_reset();
+    assert!(!sim6502_retro_is_faulted());
+
+    retro_unload_game();
+    let game = GameInfo { data: ptr::null(), ..game };
+    assert!(!unsafe { retro_load_game(&game) });
+    retro_deinit();
+}
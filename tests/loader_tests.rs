@@ -0,0 +1,105 @@
+#![cfg(feature = "loader")]
+
+use rust_6502_emulator::loader::{hexdump, ihex};
+use rust_6502_emulator::system::System;
+
+#[test]
+fn test_parse_hexdump() {
+    let text = "
+        # a comment
+        0200: EA A2 05   # trailing comment
+              a9 aa      # carries on from 0203
+        $0300: 00
+        0x0400: $01 0x02
+    ";
+    let blocks = hexdump::parse(text, 0x1000).unwrap();
+    assert_eq!(
+        blocks,
+        vec![
+            (0x0200, vec![0xea, 0xa2, 0x05, 0xa9, 0xaa]),
+            (0x0300, vec![0x00]),
+            (0x0400, vec![0x01, 0x02]),
+        ]
+    );
+    // bytes before any address go at org
+    assert_eq!(hexdump::parse("EA EA", 0x1000).unwrap(), vec![(0x1000, vec![0xea, 0xea])]);
+    assert_eq!(hexdump::parse("", 0x1000).unwrap(), vec![]);
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = hexdump::parse("0200: EA\n0201: EAX", 0).unwrap_err();
+    assert_eq!((error.line, error.text.as_str()), (2, "0201: EAX"));
+    assert_eq!(error.to_string(), "line 2: 0201: EAX");
+    assert!(hexdump::parse("zz: 00", 0).is_err());
+    assert_eq!(hexdump::parse_byte("$7f"), Some(0x7f));
+    assert_eq!(hexdump::parse_byte("100"), None);
+    assert_eq!(hexdump::parse_address("0xC000"), Some(0xc000));
+}
+
+#[test]
+fn test_dump_round_trips() {
+    let data: Vec<u8> = (0..40).collect();
+    let text = hexdump::dump_bytes(&data, 0x02f8);
+    assert_eq!(text.lines().next(), Some("02F8: 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F"));
+    assert_eq!(text.lines().count(), 3);
+    let blocks = hexdump::parse(&text, 0).unwrap();
+    let joined: Vec<u8> = blocks.iter().flat_map(|(_, d)| d.clone()).collect();
+    assert_eq!(blocks[0].0, 0x02f8);
+    assert_eq!(joined, data);
+    assert_eq!(hexdump::dump_bytes(&[], 0), "");
+
+    let mut system = System::new();
+    system.load(0xfffe, &[0x12, 0x34]);
+    assert_eq!(hexdump::dump(&*system.get_bus().borrow(), 0xfffe, 0xffff), "FFFE: 12 34\n");
+}
+
+// the debugger's memory and disassembly views redraw through these, so they mustn't acknowledge
+// the I/O registers they show
+#[cfg(feature = "devices")]
+#[test]
+fn test_dump_and_disassembly_peek() {
+    use rust_6502_emulator::devices::timer::Timer;
+    use rust_6502_emulator::devices::Peripheral;
+    use rust_6502_emulator::disasm::Disassembler;
+
+    let mut system = System::new();
+    let timer = system.add_peripheral(Timer::new(0x0300));
+    system.write(0x0300, 1);
+    system.write(0x0302, 0x05);
+    timer.borrow_mut().tick(1);
+    let bus = system.get_bus();
+    assert_eq!(hexdump::dump(&*bus.borrow(), 0x0300, 0x0303), "0300: 00 00 04 80\n");
+    Disassembler::new().disassemble(&*bus.borrow(), 0x0300, 0x0303);
+    assert!(timer.borrow().irq_asserted());
+}
+
+#[test]
+fn test_parse_ihex() {
+    let text = "
+        :020000040000FA
+        :03020000EAA2056A
+        :02020300A9AAA6
+        :010300000CF0
+        :00000001FF
+        :0104000001FA
+    ";
+    // the records at $0200 and $0203 join up and nothing after the end of file record counts
+    assert_eq!(
+        ihex::parse(text).unwrap(),
+        vec![(0x0200, vec![0xea, 0xa2, 0x05, 0xa9, 0xaa]), (0x0300, vec![0x0c])]
+    );
+    assert_eq!(ihex::parse("").unwrap(), vec![]);
+}
+
+#[test]
+fn test_ihex_errors() {
+    let message = |text| ihex::parse(text).unwrap_err().to_string();
+    assert_eq!(message(":03020000EAA2056B"), "line 1: bad checksum");
+    assert_eq!(message("\n03020000EAA2056A"), "line 2: records start with ':'");
+    assert_eq!(message(":04020000EAA2056A"), "line 1: the length doesn't match the record");
+    assert_eq!(message(":0302000EAA2056A"), "line 1: not whole hex bytes");
+    assert_eq!(message(":020000040001F9"), "line 1: addresses above $FFFF");
+    assert_eq!(message(":02FFFF00EAEA2C"), "line 1: data runs past $FFFF");
+    assert_eq!(message(":00000006FA"), "line 1: unknown record type");
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "devices")]
+
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use rust_6502_emulator::devices::timer::Timer;
+use rust_6502_emulator::logging::{self, verbosity_level, Filter, BUS, DEVICE, INSTRUCTION};
+use rust_6502_emulator::system::System;
+
+#[test]
+fn test_filter() {
+    let filter = Filter::parse("info, sim6502::bus=trace,sim6502=debug").unwrap();
+    assert_eq!(filter.get_level(BUS), LevelFilter::Trace);
+    assert_eq!(filter.get_level(INSTRUCTION), LevelFilter::Debug);
+    assert_eq!(filter.get_level("sim6502"), LevelFilter::Debug);
+    // only whole path segments match
+    assert_eq!(filter.get_level("sim6502x"), LevelFilter::Info);
+    assert_eq!(filter.get_level("rust_6502_emulator::processor"), LevelFilter::Info);
+    assert_eq!(filter.get_max_level(), LevelFilter::Trace);
+
+    // a bare target is everything for it, and the default without a level is errors
+    let filter = Filter::parse("sim6502::device").unwrap();
+    assert_eq!(filter.get_level(DEVICE), LevelFilter::Trace);
+    assert_eq!(filter.get_level(BUS), LevelFilter::Error);
+    assert_eq!(Filter::parse("").unwrap(), Filter::new(LevelFilter::Error));
+    assert_eq!(Filter::parse("bus=loud").unwrap_err(), "loud isn't a log level");
+
+    let filter = Filter::parse("error,sim6502::bus=off").unwrap().with_minimum(verbosity_level(1));
+    assert_eq!(filter.get_level("anything"), LevelFilter::Info);
+    assert_eq!(filter.get_level(BUS), LevelFilter::Info);
+    assert_eq!(Filter::default().get_max_level(), LevelFilter::Warn);
+    assert_eq!(
+        (0..5).map(verbosity_level).collect::<Vec<_>>(),
+        [LevelFilter::Warn, LevelFilter::Info, LevelFilter::Debug, LevelFilter::Trace, LevelFilter::Trace]
+    );
+}
+
+struct Capture(Mutex<Vec<String>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.lock().unwrap().push(format!("{}: {}", record.target(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(vec![]));
+
+// The only test here to install a logger, as there's one per process
+#[test]
+fn test_targets() {
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+    assert!(logging::init(0).is_err());
+
+    let mut system = System::new();
+    let timer = system.add_peripheral(Timer::new(0xd000));
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xa9, 0x42]);
+    system.step();
+    // a one shot timer of 1 cycle
+    system.write(0xd000, 1);
+    system.write(0xd002, 1);
+    CAPTURE.0.lock().unwrap().clear();
+    system.step();
+    system.reset();
+    assert!(!timer.borrow().is_running());
+
+    let lines = CAPTURE.0.lock().unwrap().clone();
+    for expected in [
+        "sim6502::instruction: $0200 LDA Immediate",
+        "sim6502::bus: read $0200=$A9",
+        "sim6502::bus: read $0201=$42",
+        "sim6502::device: resetting 1 peripherals",
+    ] {
+        assert!(lines.iter().any(|l| l == expected), "no {:?} in {:#?}", expected, lines);
+    }
+    assert!(lines.iter().any(|l| l.starts_with("sim6502::device: timer at $D000 expired")), "{:#?}", lines);
+}
@@ -0,0 +1,318 @@
+#![cfg(feature = "devices")]
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::rc::Rc;
+
+use rust_6502_emulator::bus::Data;
+use rust_6502_emulator::devices::acia::SerialBackend;
+use rust_6502_emulator::devices::Peripheral;
+use rust_6502_emulator::machines::{atari2600, easy6502, easy6502_with_seed, Apple1, BenEater, Pet, C64};
+
+#[derive(Clone, Default)]
+struct TestTerminal {
+    input: Rc<RefCell<VecDeque<Data>>>,
+    output: Rc<RefCell<Vec<Data>>>,
+}
+
+impl SerialBackend for TestTerminal {
+    fn receive(&mut self) -> Option<Data> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn transmit(&mut self, data: Data) {
+        self.output.borrow_mut().push(data);
+    }
+}
+
+#[test]
+fn test_easy6502_layout() {
+    let mut machine = easy6502();
+    machine.load(&[0xea, 0xea, 0xea]);
+    machine.system.step();
+    assert_eq!(machine.system.get_registers().pc, 0x0600, "programs start at $0600");
+
+    machine.io.borrow_mut().press(b'w');
+    assert_eq!(machine.system.read(0x00ff), b'w');
+    assert_ne!(
+        (0..8).map(|_| machine.system.read(0x00fe)).collect::<Vec<_>>(),
+        vec![machine.system.read(0x00fe); 8],
+        "$FE is random"
+    );
+
+    machine.system.write(0x0200, 0x01);
+    machine.system.write(0x05ff, 0x02);
+    assert_eq!(machine.screen.borrow().to_rgb()[0], 0xffffff);
+    assert_eq!(machine.screen.borrow().to_rgb()[32 * 32 - 1], 0x880000);
+    let rgba = machine.screen.borrow().to_rgba();
+    assert_eq!(rgba.len(), 32 * 32 * 4);
+    assert_eq!((&rgba[..4], &rgba[rgba.len() - 4..]), (&[0xff, 0xff, 0xff, 0xff][..], &[0x88, 0x00, 0x00, 0xff][..]));
+
+    let random = |seed| {
+        let machine = easy6502_with_seed(seed);
+        (0..8).map(|_| machine.system.read(0x00fe)).collect::<Vec<_>>()
+    };
+    assert_eq!(random(7), random(7));
+    assert_ne!(random(7), random(8));
+}
+
+#[test]
+#[cfg(feature = "wasm")]
+fn test_wasm_machine() {
+    use rust_6502_emulator::wasm::Machine;
+
+    let mut machine = Machine::new(1);
+    // LDA #$01, LDX #$05
+    machine.load_program(&[0xa9, 0x01, 0xa2, 0x05]);
+    machine.step();
+    assert_eq!(machine.get_pc(), 0x0600);
+    assert_eq!(machine.step(), 2);
+    assert_eq!(machine.get_pc(), 0x0602);
+    machine.write(0x0200, 0x05);
+    assert_eq!(machine.read(0x0200), 0x05);
+    let frame = machine.get_framebuffer();
+    assert_eq!(frame.len(), machine.get_width() * machine.get_height() * 4);
+    assert_eq!(&frame[..4], &[0x00, 0xcc, 0x55, 0xff]);
+    machine.press_key(b'a');
+    assert_eq!(machine.read(0x00ff), b'a');
+
+    machine.load(0x0700, &[0xea; 4]);
+    machine.set_reset_vector(0x0700);
+    machine.reset();
+    machine.step();
+    assert_eq!(machine.get_pc(), 0x0700);
+    let cycles = machine.get_total_cycles();
+    assert!(machine.run_frame(4) >= 4);
+    assert!(machine.get_total_cycles() >= cycles + 4);
+    assert!(!machine.is_halted());
+}
+
+#[test]
+fn test_apple1_keyboard_and_display() {
+    let mut rom = vec![0xea; 256];
+    rom[0xfc] = 0x00;
+    rom[0xfd] = 0xff;
+    let terminal = TestTerminal::default();
+    terminal.input.borrow_mut().extend(b"a\n");
+    let mut machine = Apple1::with_terminal(rom, Box::new(terminal.clone())).unwrap();
+    let system = &mut machine.system;
+    assert_eq!(system.read(0xff00), 0xea);
+
+    // what Wozmon does at RESET
+    system.write(0xd012, 0x7f);
+    system.write(0xd011, 0xa7);
+    system.write(0xd013, 0xa7);
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xff00, "started at the ROM's reset vector");
+
+    assert_eq!(system.read(0xd011) & 0x80, 0x80, "a key is waiting");
+    assert_eq!(system.read(0xd010), b'A' | 0x80, "upper cased with bit 7 set");
+    assert_eq!(system.read(0xd011) & 0x80, 0);
+    system.step();
+    assert_eq!(system.read(0xd010), b'\r' | 0x80);
+
+    assert_eq!(system.read(0xd012) & 0x80, 0, "the display is ready");
+    system.write(0xd012, b'O' | 0x80);
+    system.step();
+    system.step();
+    system.write(0xd012, b'\r' | 0x80);
+    system.step();
+    assert_eq!(*terminal.output.borrow(), b"O\n");
+
+    assert!(Apple1::with_terminal(vec![0; 2], Box::new(TestTerminal::default())).is_err());
+}
+
+#[test]
+fn test_ben_eater_layout() {
+    let mut rom = vec![0xea; 0x8000];
+    rom[0x7ffc] = 0x00;
+    rom[0x7ffd] = 0x80;
+    let terminal = TestTerminal::default();
+    let mut machine = BenEater::with_terminal(rom, Box::new(terminal.clone())).unwrap();
+    let system = &mut machine.system;
+    system.step();
+    assert_eq!(system.get_registers().pc, 0x8000);
+
+    system.write(0x7fff, 0x42);
+    assert_eq!(system.read(0x7fff), 0x42);
+    system.write(0x8000, 0x00);
+    assert_eq!(system.read(0x8000), 0xea, "ROM");
+
+    system.write(0x6002, 0xff);
+    system.write(0x6000, 0x38);
+    assert_eq!(machine.via.borrow().get_port_b_output(), 0x38);
+
+    system.write(0x5000, b'H');
+    system.step();
+    assert_eq!(*terminal.output.borrow(), b"H");
+}
+
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_pet_screen_and_keyboard() {
+    let mut rom = vec![0xea; 0x4000];
+    rom[0x3ffc] = 0x00;
+    rom[0x3ffd] = 0xc0;
+    let keys = TestTerminal::default();
+    keys.input.borrow_mut().extend(b"r");
+    let out = SharedOutput::default();
+    let mut machine = Pet::with_terminal(rom, Box::new(keys), Box::new(out.clone())).unwrap();
+    let system = &mut machine.system;
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xc000);
+
+    // screen codes: 1 is A, $20 space, $8D a reversed M
+    system.write(0x8000, 0x01);
+    system.write(0x8001, 0x8d);
+    assert!(machine.screen.borrow().lines()[0].starts_with("AM "));
+    machine.screen.borrow_mut().tick(20_000);
+    let drawn = String::from_utf8_lossy(&out.0.borrow()).to_string();
+    assert!(drawn.contains("A\x1b[7mM\x1b[0m"));
+
+    // R is row 3 column 1: only that row reads it, active low
+    machine.keyboard.borrow_mut().tick(1);
+    system.write(0xe810, 0x0f); // port A row select out
+    system.write(0xe811, 0x04);
+    system.write(0xe813, 0x04);
+    system.write(0xe810, 3);
+    assert_eq!(system.read(0xe812), 0xfd);
+    system.write(0xe810, 2);
+    assert_eq!(system.read(0xe812), 0xff);
+    // released after a few scans
+    system.write(0xe810, 3);
+    machine.keyboard.borrow_mut().tick(60_000);
+    assert_eq!(system.read(0xe812), 0xff);
+
+    assert!(system.read(0x7fff) == 0 && system.read(0xe840 + 0x0e) == 0x80, "RAM and VIA");
+}
+
+#[test]
+fn test_c64_banking() {
+    let mut kernal = vec![0xee; 0x2000];
+    kernal[0x1ffc] = 0xe2;
+    kernal[0x1ffd] = 0xfc;
+    let keys = TestTerminal::default();
+    keys.input.borrow_mut().extend(b"r");
+    let mut machine =
+        C64::with_terminal(vec![0xbb; 0x2000], kernal, vec![0xcc; 0x1000], Box::new(keys), Box::new(std::io::sink())).unwrap();
+    let system = &mut machine.system;
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xfce2, "started at the KERNAL's reset vector");
+
+    // all ROMs and I/O at power on; writes under ROM reach the RAM
+    assert_eq!((system.read(0xa000), system.read(0xe000)), (0xbb, 0xee));
+    system.write(0xa000, 0x12);
+    system.write(0xd020, 0x0e);
+    assert_eq!(system.read(0xd020), 0x0e, "VIC register");
+    assert_eq!(system.read(0xd060), 0x0e, "mirrored");
+    system.write(0xd800, 0x35);
+    assert_eq!(system.read(0xd800), 0xf5, "colour RAM nibbles");
+
+    // what the KERNAL sets up: BASIC, KERNAL and I/O
+    system.write(0x0000, 0x2f);
+    system.write(0x0001, 0x37);
+    assert_eq!(system.read(0xa000), 0xbb);
+    // character ROM in place of I/O
+    system.write(0x0001, 0x33);
+    assert_eq!(system.read(0xd000), 0xcc);
+    // KERNAL only
+    system.write(0x0001, 0x36);
+    assert_eq!((system.read(0xa000), system.read(0xe000)), (0x12, 0xee));
+    // all RAM
+    system.write(0x0001, 0x34);
+    assert_eq!((system.read(0xe000), system.read(0xd021)), (0x00, 0x00));
+    system.write(0x0001, 0x37);
+
+    // the raster moves and the CIAs are there
+    let raster = system.read(0xd012);
+    system.write(0xdc04, 0x10);
+    system.write(0xdc05, 0);
+    assert_eq!(system.read(0xdc04), 0x10);
+    machine.board.borrow_mut().tick(630);
+    assert_eq!(system.read(0xd012), raster.wrapping_add(10));
+
+    // typed keys go into the KERNAL's buffer and the screen at $0400 is read as text
+    assert_eq!((system.read(0x00c6), system.read(0x0277)), (1, b'R'));
+    system.write(0x0400, 0x12);
+    system.write(0x0401, 0x05);
+    system.write(0xdd02, 0x03);
+    system.write(0xdd00, 0x03);
+    system.write(0xd018, 0x14);
+    assert!(machine.board.borrow().lines()[0].starts_with("RE"));
+}
+
+#[test]
+fn test_atari2600_masks_the_bus() {
+    let mut rom = vec![0xea; 0x800];
+    rom[0x7fc] = 0x00;
+    rom[0x7fd] = 0xf8;
+    let mut machine = atari2600(rom).unwrap();
+    let system = &mut machine.system;
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xf800);
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xf801, "runs from the $F000 mirror of the cartridge");
+
+    // 13 address lines: $F000, $1000 and $3000 are the same place, 2K carts appear twice
+    assert_eq!(system.read(0xfffc), 0x00);
+    assert_eq!(system.read(0x1ffd), 0xf8);
+    assert_eq!(system.read(0x37fd), 0xf8);
+
+    system.write(0x0080, 0x42);
+    assert_eq!(system.read(0xe080), 0x42, "RIOT RAM through a mirror");
+    system.write(0x0009, 0x84);
+    system.write(0x0002, 0);
+    assert_eq!(machine.tia.borrow().get_register(0x09), 0x84);
+    assert_eq!(machine.tia.borrow().get_wsyncs(), 1);
+    assert_eq!(system.read(0x000c), 0x80, "fire released");
+    machine.tia.borrow_mut().set_fire(0, true);
+    assert_eq!(system.read(0x000c), 0x00);
+    assert_eq!(system.read(0x0280), 0xff, "joysticks released");
+
+    assert!(atari2600(vec![0; 0x2000]).is_err());
+}
+
+#[test]
+#[cfg(feature = "wasm")]
+fn test_wasm_canvas_sink() {
+    use rust_6502_emulator::wasm::{CanvasSink, Machine};
+
+    struct Frames(Rc<RefCell<Vec<Vec<u8>>>>);
+
+    impl CanvasSink for Frames {
+        fn draw(&mut self, rgba: &[u8], width: usize, height: usize) {
+            assert_eq!((width, height), (32, 32));
+            self.0.borrow_mut().push(rgba.to_vec());
+        }
+    }
+
+    let frames = Rc::new(RefCell::new(vec![]));
+    let mut machine = Machine::new(1);
+    machine.set_canvas_sink(Box::new(Frames(frames.clone())));
+    machine.load_program(&[0xea; 64]);
+    // the first frame is drawn, then only changed ones
+    machine.run_frame(10);
+    machine.run_frame(10);
+    assert_eq!(frames.borrow().len(), 1);
+    machine.write(0x0200, 0x01);
+    machine.run_frame(10);
+    assert_eq!(frames.borrow().len(), 2);
+    assert_eq!(&frames.borrow()[1][..4], &[0xff, 0xff, 0xff, 0xff]);
+
+    machine.redraw();
+    assert_eq!(frames.borrow().len(), 3);
+}
@@ -0,0 +1,83 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use rust_6502_emulator::bus::{Address, Bus, BusDevice, Data, PagedBus};
+use rust_6502_emulator::memory::{ArrayMemory, Memory, Rom};
+
+#[test]
+fn test_array_memory() {
+    let mut memory = ArrayMemory::<0x100>::new(0x0200);
+    assert!(memory.is_readable_for(0x0200) && memory.is_writable_for(0x02ff));
+    assert!(!memory.is_readable_for(0x01ff) && !memory.is_writable_for(0x0300));
+
+    memory.do_write(0x0210, 0x42);
+    assert_eq!(memory.do_read(0x0210), 0x42);
+    // writes stop at the end of the array
+    memory.write(0x02fe, &[1, 2, 3]);
+    assert_eq!(&memory.as_slice()[0xfe..], &[1, 2]);
+    memory.write(0x0100, &[9]);
+    assert!(!memory.as_slice().contains(&9));
+
+    let state = memory.save_state().unwrap();
+    assert_eq!(state.len(), 0x100);
+    let mut other = ArrayMemory::<0x100>::new(0x0200);
+    other.load_state(&state);
+    assert_eq!(other.as_slice(), memory.as_slice());
+    assert!(memory.debug_view().unwrap().format_state().contains("$0200-$02FF"));
+}
+
+// Answers $8000 while enabled, as a banked device would
+struct Banked {
+    enabled: Cell<bool>,
+}
+
+impl BusDevice for Banked {
+    fn do_read(&self, _address: Address) -> Data {
+        0x99
+    }
+
+    fn do_write(&mut self, _address: Address, _data: Data) {}
+
+    fn is_readable_for(&self, address: Address) -> bool {
+        self.enabled.get() && address == 0x8000
+    }
+
+    fn is_writable_for(&self, _address: Address) -> bool {
+        false
+    }
+}
+
+#[test]
+fn test_paged_bus() {
+    let memory = Rc::new(RefCell::new(Memory::new(0x0000, 0xffff)));
+    let rom = Rc::new(RefCell::new(Rom::new(0xf000, vec![0x42; 0x10])));
+    let mut bus = PagedBus::new();
+    bus.register_device(&(rom.clone() as Rc<RefCell<dyn BusDevice>>));
+    bus.register_device(&(memory.clone() as Rc<RefCell<dyn BusDevice>>));
+    // every page but the ROM's goes straight to RAM
+    assert_eq!(bus.get_ram_page_count(), 255);
+
+    bus.write(0x1234, 0x56);
+    assert_eq!(memory.borrow().do_read(0x1234), 0x56);
+    memory.borrow_mut().do_write(0x2000, 0x78);
+    assert_eq!(bus.read(0x2000), 0x78);
+    // on the ROM's page the ROM wins reads, and writes still reach the RAM behind it
+    bus.write(0xf001, 0x07);
+    assert_eq!(bus.read(0xf001), 0x42);
+    assert_eq!(memory.borrow().do_read(0xf001), 0x07);
+    assert_eq!(bus.read(0xf010), 0x00);
+    // a restored snapshot is what the bus sees
+    let mut state = memory.borrow().save_state().unwrap();
+    state[0x1234] = 0x9a;
+    memory.borrow_mut().load_state(&state);
+    assert_eq!(bus.read(0x1234), 0x9a);
+
+    // without a fixed mapping a device is asked on every page
+    let banked = Rc::new(RefCell::new(Banked { enabled: Cell::new(false) }));
+    bus.insert_device(0, &(banked.clone() as Rc<RefCell<dyn BusDevice>>));
+    assert_eq!(bus.get_ram_page_count(), 0);
+    assert_eq!(bus.read(0x8000), 0x00);
+    banked.borrow().enabled.set(true);
+    assert_eq!(bus.read(0x8000), 0x99);
+    assert_eq!(bus.read(0x1234), 0x9a);
+}
@@ -0,0 +1,60 @@
+// The processor without the block cache, the one part of it on the heap
+#![cfg(not(feature = "blocks"))]
+
+use std::alloc::{GlobalAlloc, Layout, System as Heap};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use rust_6502_emulator::bus::{Bus, BusDevice, Data, SimpleBus};
+use rust_6502_emulator::memory::{ArrayMemory, Rom};
+use rust_6502_emulator::processor::{create6502, ProcessorTrait, BOOT_VECTOR};
+
+// Counts this thread's allocations, as tests/alloc_tests.rs does
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        Heap.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Heap.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+// LDA #$42, NOP
+static PROGRAM: [Data; 3] = [0xa9, 0x42, 0xea];
+
+// Building the processor and running it allocates nothing: the instruction table is static and
+// the operation queue and pre-decode cache are inline
+#[test]
+fn test_processor_without_heap_memory() {
+    let ram = Rc::new(RefCell::new(ArrayMemory::<0x1000>::new(0x0000)));
+    ram.borrow_mut().write(BOOT_VECTOR, &[0x00, 0xf0]);
+    let rom = Rc::new(RefCell::new(Rom::new(0xf000, &PROGRAM[..])));
+    let mut bus = SimpleBus { registered: vec![] };
+    bus.register_device(&(ram.clone() as Rc<RefCell<dyn BusDevice>>));
+    bus.register_device(&(rom.clone() as Rc<RefCell<dyn BusDevice>>));
+
+    let before = ALLOCATIONS.with(Cell::get);
+    let mut processor = create6502();
+    let mut instructions = 0;
+    while instructions < 3 {
+        processor.tick(&bus);
+        if processor.is_at_instruction_boundary() {
+            instructions += 1;
+        }
+    }
+    let pc = processor.get_registers().pc;
+    assert_eq!(ALLOCATIONS.with(Cell::get) - before, 0);
+    // the boot sequence, LDA and NOP
+    assert_eq!(pc, 0xf003);
+}
@@ -0,0 +1,151 @@
+#![cfg(feature = "testsuite")]
+
+use rust_6502_emulator::processor::AddressingMode::{self, *};
+use rust_6502_emulator::processor::DataRegister::{X, Y};
+use rust_6502_emulator::processor::create_instruction_table;
+use rust_6502_emulator::testsuite::coverage;
+
+// The documented NMOS opcodes as "mnemonic mode bytes cycles", eight to a line starting at the
+// opcode on the left, "..." where there's no documented instruction. Cycles are the base count,
+// without the extra cycle for crossing a page or taking a branch
+const REFERENCE: &str = "
+00: BRK impl 1 7 | ORA izx 2 6  | ...          | ...          | ...          | ORA zp 2 3   | ASL zp 2 5   | ...
+08: PHP impl 1 3 | ORA imm 2 2  | ASL acc 1 2  | ...          | ...          | ORA abs 3 4  | ASL abs 3 6  | ...
+10: BPL rel 2 2  | ORA izy 2 5  | ...          | ...          | ...          | ORA zpx 2 4  | ASL zpx 2 6  | ...
+18: CLC impl 1 2 | ORA aby 3 4  | ...          | ...          | ...          | ORA abx 3 4  | ASL abx 3 7  | ...
+20: JSR abs 3 6  | AND izx 2 6  | ...          | ...          | BIT zp 2 3   | AND zp 2 3   | ROL zp 2 5   | ...
+28: PLP impl 1 4 | AND imm 2 2  | ROL acc 1 2  | ...          | BIT abs 3 4  | AND abs 3 4  | ROL abs 3 6  | ...
+30: BMI rel 2 2  | AND izy 2 5  | ...          | ...          | ...          | AND zpx 2 4  | ROL zpx 2 6  | ...
+38: SEC impl 1 2 | AND aby 3 4  | ...          | ...          | ...          | AND abx 3 4  | ROL abx 3 7  | ...
+40: RTI impl 1 6 | EOR izx 2 6  | ...          | ...          | ...          | EOR zp 2 3   | LSR zp 2 5   | ...
+48: PHA impl 1 3 | EOR imm 2 2  | LSR acc 1 2  | ...          | JMP abs 3 3  | EOR abs 3 4  | LSR abs 3 6  | ...
+50: BVC rel 2 2  | EOR izy 2 5  | ...          | ...          | ...          | EOR zpx 2 4  | LSR zpx 2 6  | ...
+58: CLI impl 1 2 | EOR aby 3 4  | ...          | ...          | ...          | EOR abx 3 4  | LSR abx 3 7  | ...
+60: RTS impl 1 6 | ADC izx 2 6  | ...          | ...          | ...          | ADC zp 2 3   | ROR zp 2 5   | ...
+68: PLA impl 1 4 | ADC imm 2 2  | ROR acc 1 2  | ...          | JMP ind 3 5  | ADC abs 3 4  | ROR abs 3 6  | ...
+70: BVS rel 2 2  | ADC izy 2 5  | ...          | ...          | ...          | ADC zpx 2 4  | ROR zpx 2 6  | ...
+78: SEI impl 1 2 | ADC aby 3 4  | ...          | ...          | ...          | ADC abx 3 4  | ROR abx 3 7  | ...
+80: ...          | STA izx 2 6  | ...          | ...          | STY zp 2 3   | STA zp 2 3   | STX zp 2 3   | ...
+88: DEY impl 1 2 | ...          | TXA impl 1 2 | ...          | STY abs 3 4  | STA abs 3 4  | STX abs 3 4  | ...
+90: BCC rel 2 2  | STA izy 2 6  | ...          | ...          | STY zpx 2 4  | STA zpx 2 4  | STX zpy 2 4  | ...
+98: TYA impl 1 2 | STA aby 3 5  | TXS impl 1 2 | ...          | ...          | STA abx 3 5  | ...          | ...
+A0: LDY imm 2 2  | LDA izx 2 6  | LDX imm 2 2  | ...          | LDY zp 2 3   | LDA zp 2 3   | LDX zp 2 3   | ...
+A8: TAY impl 1 2 | LDA imm 2 2  | TAX impl 1 2 | ...          | LDY abs 3 4  | LDA abs 3 4  | LDX abs 3 4  | ...
+B0: BCS rel 2 2  | LDA izy 2 5  | ...          | ...          | LDY zpx 2 4  | LDA zpx 2 4  | LDX zpy 2 4  | ...
+B8: CLV impl 1 2 | LDA aby 3 4  | TSX impl 1 2 | ...          | LDY abx 3 4  | LDA abx 3 4  | LDX aby 3 4  | ...
+C0: CPY imm 2 2  | CMP izx 2 6  | ...          | ...          | CPY zp 2 3   | CMP zp 2 3   | DEC zp 2 5   | ...
+C8: INY impl 1 2 | CMP imm 2 2  | DEX impl 1 2 | ...          | CPY abs 3 4  | CMP abs 3 4  | DEC abs 3 6  | ...
+D0: BNE rel 2 2  | CMP izy 2 5  | ...          | ...          | ...          | CMP zpx 2 4  | DEC zpx 2 6  | ...
+D8: CLD impl 1 2 | CMP aby 3 4  | ...          | ...          | ...          | CMP abx 3 4  | DEC abx 3 7  | ...
+E0: CPX imm 2 2  | SBC izx 2 6  | ...          | ...          | CPX zp 2 3   | SBC zp 2 3   | INC zp 2 5   | ...
+E8: INX impl 1 2 | SBC imm 2 2  | NOP impl 1 2 | ...          | CPX abs 3 4  | SBC abs 3 4  | INC abs 3 6  | ...
+F0: BEQ rel 2 2  | SBC izy 2 5  | ...          | ...          | ...          | SBC zpx 2 4  | INC zpx 2 6  | ...
+F8: SED impl 1 2 | SBC aby 3 4  | ...          | ...          | ...          | SBC abx 3 4  | INC abx 3 7  | ...
+";
+
+struct Reference {
+    opcode: u8,
+    mnemonic: &'static str,
+    mode: AddressingMode,
+    bytes: usize,
+    cycles: usize,
+}
+
+fn mode(name: &str) -> AddressingMode {
+    match name {
+        "acc" => Accumulator,
+        "abs" => Absolute,
+        "abx" => AbsIndexed { reg: X },
+        "aby" => AbsIndexed { reg: Y },
+        "imm" => Immediate,
+        "impl" => Implied,
+        "ind" => Indirect,
+        "izx" => IndexedIndirect,
+        "izy" => IndirectIndexed,
+        "rel" => Relative,
+        "zp" => ZeroPage,
+        "zpx" => ZeroPageIndexed { reg: X },
+        "zpy" => ZeroPageIndexed { reg: Y },
+        _ => panic!("unknown mode {}", name),
+    }
+}
+
+fn reference() -> Vec<Reference> {
+    let mut opcodes = vec![];
+    for line in REFERENCE.lines().filter(|l| !l.is_empty()) {
+        let (first, cells) = line.split_once(": ").unwrap();
+        let first = u8::from_str_radix(first, 16).unwrap();
+        for (i, cell) in cells.split('|').map(str::trim).enumerate() {
+            if cell == "..." {
+                continue;
+            }
+            let fields: Vec<&'static str> = cell.split_whitespace().collect();
+            opcodes.push(Reference {
+                opcode: first + i as u8,
+                mnemonic: fields[0],
+                mode: mode(fields[1]),
+                bytes: fields[2].parse().unwrap(),
+                cycles: fields[3].parse().unwrap(),
+            });
+        }
+    }
+    opcodes
+}
+
+#[test]
+fn test_reference_matrix() {
+    let reference = reference();
+    assert_eq!(reference.len(), 151);
+    assert!(reference.windows(2).all(|w| w[0].opcode < w[1].opcode));
+    for r in &reference {
+        assert_eq!(r.bytes, 1 + r.mode.operand_length(), "${:02X} {}", r.opcode, r.mnemonic);
+        assert!(r.cycles >= 2.max(r.bytes), "${:02X} {}", r.opcode, r.mnemonic);
+    }
+}
+
+#[test]
+fn test_instruction_table_against_reference() {
+    let reference = reference();
+    let table = create_instruction_table();
+    let mut errors = vec![];
+    for (opcode, instruction) in table.iter() {
+        let Some(r) = reference.iter().find(|r| r.opcode == opcode) else {
+            errors.push(format!("${:02X} {} isn't a documented opcode", opcode, instruction.get_mnemonic()));
+            continue;
+        };
+        if (instruction.get_mnemonic(), instruction.get_addressing()) != (r.mnemonic, &r.mode) {
+            errors.push(format!(
+                "${:02X} is {} {}, expected {} {}",
+                opcode,
+                instruction.get_mnemonic(),
+                instruction.get_addressing(),
+                r.mnemonic,
+                r.mode
+            ));
+        }
+        // the opcode fetch and then one cycle at least for each fetch the table queues
+        let fetches = 1 + instruction.get_fetch_operations().len();
+        if fetches > r.cycles {
+            errors.push(format!("${:02X} {} fetches for {} cycles, takes {}", opcode, r.mnemonic, fetches, r.cycles));
+        }
+    }
+    // a family generated for an instruction should have all of its modes
+    for r in &reference {
+        let implemented = table.iter().any(|(_, i)| i.get_mnemonic() == r.mnemonic);
+        if implemented && !table.contains(r.opcode) {
+            errors.push(format!("${:02X} {} {} is missing", r.opcode, r.mnemonic, r.mode));
+        }
+    }
+    errors.sort();
+    assert!(errors.is_empty(), "\n{}", errors.join("\n"));
+}
+
+#[test]
+fn test_assembler_table_against_reference() {
+    let documented = coverage::coverage();
+    let reference = reference();
+    assert_eq!(documented.len(), reference.len());
+    for (d, r) in documented.iter().zip(&reference) {
+        assert_eq!((d.opcode, d.mnemonic, &d.mode), (r.opcode, r.mnemonic, &r.mode));
+    }
+}
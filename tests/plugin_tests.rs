@@ -0,0 +1,125 @@
+#![cfg(feature = "plugins")]
+
+use std::ffi::{c_char, c_void, CStr};
+
+use rust_6502_emulator::devices::Peripheral;
+use rust_6502_emulator::plugin::{Plugin, PluginError, Sim6502Plugin, SIM6502_PLUGIN_ABI_VERSION};
+use rust_6502_emulator::prelude::*;
+use rust_6502_emulator::system::MachineConfig;
+
+// A latch at base that asserts IRQ while it holds a non-zero value, and counts the cycles
+// it's ticked at base + 1. The options set its value at reset
+struct Latch {
+    base: u16,
+    value: u8,
+    reset_value: u8,
+    cycles: u32,
+}
+
+unsafe extern "C" fn create(base: u16, options: *const c_char) -> *mut c_void {
+    let options = CStr::from_ptr(options).to_str().unwrap_or_default();
+    let reset_value = match options {
+        "" => 0,
+        _ => match options.parse() {
+            Ok(value) => value,
+            Err(_) => return std::ptr::null_mut(),
+        },
+    };
+    Box::into_raw(Box::new(Latch { base, value: reset_value, reset_value, cycles: 0 })) as *mut c_void
+}
+
+unsafe extern "C" fn destroy(device: *mut c_void) {
+    drop(Box::from_raw(device as *mut Latch));
+}
+
+unsafe extern "C" fn answers(device: *mut c_void, address: u16) -> bool {
+    let latch = &*(device as *const Latch);
+    address == latch.base || address == latch.base + 1
+}
+
+unsafe extern "C" fn read(device: *mut c_void, address: u16) -> u8 {
+    let latch = &*(device as *const Latch);
+    match address == latch.base {
+        true => latch.value,
+        false => latch.cycles as u8,
+    }
+}
+
+unsafe extern "C" fn write(device: *mut c_void, address: u16, data: u8) {
+    let latch = &mut *(device as *mut Latch);
+    if address == latch.base {
+        latch.value = data;
+    }
+}
+
+unsafe extern "C" fn tick(device: *mut c_void, cycles: u32) {
+    (*(device as *mut Latch)).cycles += cycles;
+}
+
+unsafe extern "C" fn reset(device: *mut c_void) {
+    let latch = &mut *(device as *mut Latch);
+    latch.value = latch.reset_value;
+}
+
+unsafe extern "C" fn irq_asserted(device: *mut c_void) -> bool {
+    (*(device as *const Latch)).value != 0
+}
+
+static LATCH: Sim6502Plugin = Sim6502Plugin {
+    abi_version: SIM6502_PLUGIN_ABI_VERSION,
+    name: c"latch".as_ptr(),
+    create,
+    destroy,
+    is_readable_for: answers,
+    is_writable_for: answers,
+    read,
+    write,
+    tick: Some(tick),
+    reset: Some(reset),
+    irq_asserted: Some(irq_asserted),
+};
+
+static FUTURE: Sim6502Plugin = Sim6502Plugin {
+    abi_version: SIM6502_PLUGIN_ABI_VERSION + 1,
+    tick: None,
+    reset: None,
+    irq_asserted: None,
+    ..LATCH
+};
+
+#[test]
+fn test_plugin_device() {
+    let plugin = Plugin::from_table(&LATCH).unwrap();
+    assert_eq!(plugin.get_name(), "latch");
+    let mut device = plugin.create(0xd000, "7").unwrap();
+    assert!(device.is_readable_for(0xd000) && device.is_writable_for(0xd001));
+    assert!(!device.is_readable_for(0xd002));
+    assert_eq!(device.do_read(0xd000), 7);
+    assert!(device.irq_asserted());
+    device.do_write(0xd000, 0);
+    assert!(!device.irq_asserted());
+    device.reset();
+    assert_eq!(device.do_read(0xd000), 7);
+    assert!(matches!(plugin.create(0xd000, "lots"), Err(PluginError::Create(name)) if name == "latch"));
+
+    // on a system, ticked with the rest
+    let mut system = SystemBuilder::new().peripheral(0xd000, plugin.create(0xd000, "").unwrap()).build().unwrap();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 4]);
+    system.step();
+    let cycles = system.step();
+    assert!(system.read(0xd001) as usize >= cycles);
+    system.write(0xd000, 1);
+    assert!(system.irq_asserted());
+}
+
+#[test]
+fn test_plugin_errors() {
+    let error = Plugin::from_table(&FUTURE).err().unwrap();
+    assert_eq!(error.to_string(), format!("plugin ABI version {}, the emulator wants {}", SIM6502_PLUGIN_ABI_VERSION + 1, SIM6502_PLUGIN_ABI_VERSION));
+    assert!(matches!(Plugin::load("/nonexistent/libdevice.so"), Err(PluginError::Load(..))));
+
+    let config = MachineConfig::parse("[[device]]\ntype = \"plugin\"\nbase = 0xd000\npath = \"/nonexistent/libdevice.so\"\noptions = \"1\"").unwrap();
+    let error = config.build().err().unwrap();
+    assert!(error.to_string().starts_with("can't load plugin /nonexistent/libdevice.so: "), "{}", error);
+}
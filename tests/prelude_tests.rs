@@ -0,0 +1,43 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rust_6502_emulator::prelude::*;
+
+// LDA #$42, NOP
+static PROGRAM: [Data; 3] = [0xa9, 0x42, 0xea];
+
+// Each of the core names resolves, and they fit together
+#[test]
+fn test_prelude_core_names() {
+    let mut array = ArrayMemory::<0x100>::new(0x0000);
+    array.write(0x0000, &[0x42]);
+    let array: Rc<RefCell<dyn BusDevice>> = Rc::new(RefCell::new(array));
+    let ram: Rc<RefCell<dyn BusDevice>> = Rc::new(RefCell::new(Memory::new(0x0100, 0x01ff)));
+    let rom: Rc<RefCell<dyn BusDevice>> = Rc::new(RefCell::new(Rom::new(0xf000, &PROGRAM[..])));
+    let mut simple = SimpleBus { registered: vec![] };
+    let mut paged = PagedBus::new();
+    for device in [&array, &ram, &rom] {
+        simple.register_device(device);
+        paged.register_device(device);
+    }
+    let address: Address = 0xf001;
+    assert_eq!(simple.read(0x0000), paged.read(0x0000));
+    assert_eq!(Bus::read(&paged, address), 0x42);
+
+    let processor: Proc6502 = create6502();
+    let registers: Registers = ProcessorTrait::get_registers(&processor);
+    assert_eq!(registers.pc, BOOT_VECTOR);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_prelude_system() {
+    let mut system = SystemBuilder::new().build().unwrap();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &PROGRAM);
+    system.step(); // boot vector
+    system.step();
+    assert_eq!(system.get_registers().pc, 0x0202);
+    let address: Address = 0x0201;
+    assert_eq!(system.read(address), 0x42);
+}
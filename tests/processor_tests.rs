@@ -1,35 +1,18 @@
+#![cfg(all(feature = "asm", feature = "loader"))]
+
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use rust_6502_emulator::asm::{run_asm, RunEnd};
 use rust_6502_emulator::bus::{Address, Bus, BusDevice, Data, SimpleBus};
+use rust_6502_emulator::loader::hexdump;
 use rust_6502_emulator::memory::Memory;
-use rust_6502_emulator::processor::{create6502, ProcessorTrait};
-
-pub fn char_to_hex_byte(c: char) -> u8 {
-    if ('0'..='9').contains(&c) {
-        (c as u8) - b'0'
-    } else if ('a'..='f').contains(&c){
-        (c as u8) - b'a' + 0x0a
-    } else {
-        panic!("{} not in hex byte range", c);
-    }
-}
-pub fn str_to_byte(s: &str) -> Data {
-    let x: Vec<u8> = s.to_ascii_lowercase().chars().map(char_to_hex_byte).collect();
-    let high = (x[0] << 4) as u8;
-    let low = x[1];
-    high | low
-}
+use rust_6502_emulator::processor::{create6502, Proc6502, ProcessorTrait};
 
-fn write_program_to_memory(mem: &Rc<RefCell<Memory>>, start: Address, obj_code: String) {
-    let x:Vec<Data> = obj_code.lines()
-        .filter(|s| !s.is_empty())
-        .flat_map(|s| s.split_whitespace())
-        .filter(|s| !s.ends_with(':'))
-        .map(|y| str_to_byte(y))
-        .collect();
-
-    mem.borrow_mut().write(start, x);
+fn write_program_to_memory(mem: &Rc<RefCell<Memory>>, start: Address, obj_code: &str) {
+    for (address, data) in hexdump::parse(obj_code, start).unwrap() {
+        mem.borrow_mut().write(address, data);
+    }
 }
 
 fn make_eprom_for_program(object_code_hex_dump: &str, start: Address) -> Rc<RefCell<Memory>> {
@@ -42,7 +25,7 @@ fn make_eprom_for_program(object_code_hex_dump: &str, start: Address) -> Rc<RefC
         .borrow_mut()
         .write(0xffc, vec![start_low, start_high]); // , 0xea, 0x4c, 0xfe, 0x0f, 0xfe, 0x0f]);
     // write the program
-    write_program_to_memory(&memory, start, object_code_hex_dump.to_string());
+    write_program_to_memory(&memory, start, object_code_hex_dump);
     memory
 }
 
@@ -61,7 +44,7 @@ fn test_the_case(test_case: TestCase) {
     bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
 
     loop {
-        let (_,at_break) = processor.borrow_mut().tick(Rc::clone(&bus));
+        let (_,at_break) = processor.borrow_mut().tick(&*bus.borrow());
         if at_break {
             break;
         }
@@ -102,3 +85,185 @@ fn test_addressing_modes() {
     test_the_case(NOP_CYCLE_TEST);
 }
 
+
+// STA_ZP_X_TEST from source
+#[test]
+#[ignore = "the processor doesn't carry out instructions' operations yet"]
+fn test_sta_zero_page_x() {
+    let run = run_asm(
+        "
+        LDX #$05
+        LDA #$AA
+        STA $01,X
+        BRK
+        ",
+    );
+    assert_eq!(run.end, RunEnd::Break);
+    assert_eq!(run.read(0x0006), 0xaa);
+    assert_eq!((run.registers.a, run.registers.x), (0xaa, 0x05));
+}
+
+// The pc after each cycle of the first n instructions, counting the boot sequence as one
+fn trace_instructions(processor: &mut Proc6502, bus: &Rc<RefCell<dyn Bus>>, n: usize) -> Vec<(Address, usize)> {
+    let mut trace = vec![];
+    let mut instructions = 0;
+    while instructions < n {
+        let (pc, _) = processor.tick(&*bus.borrow());
+        trace.push((pc, processor.get_total_cycles()));
+        if processor.is_at_instruction_boundary() {
+            instructions += 1;
+        }
+    }
+    trace
+}
+
+#[cfg(feature = "blocks")]
+#[test]
+fn test_block_cache() {
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    // NOP, LDA #$42, NOP, NOP
+    let memory = make_eprom_for_program("0200: EA A9 42 EA EA", 0x0200);
+    bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+
+    let mut processor = create6502();
+    let first = trace_instructions(&mut processor, &bus, 5);
+    assert_eq!(first.last().unwrap().0, 0x0205);
+    processor.reset();
+    assert_eq!(processor.get_block_count(), 1);
+    // the second pass runs from the block, cycle for cycle the same
+    let cycles = processor.get_total_cycles();
+    let second = trace_instructions(&mut processor, &bus, 5);
+    let second: Vec<(Address, usize)> = second.into_iter().map(|(pc, total)| (pc, total - cycles)).collect();
+    assert_eq!(second, first);
+
+    // a snapshot in the middle of a cached LDA picks up where it left off
+    processor.reset();
+    trace_instructions(&mut processor, &bus, 2);
+    processor.tick(&*bus.borrow());
+    assert!(!processor.is_at_instruction_boundary());
+    let snapshot = processor.snapshot();
+    let expected = trace_instructions(&mut processor, &bus, 3);
+    processor.restore(&snapshot);
+    assert_eq!(trace_instructions(&mut processor, &bus, 3), expected);
+
+    // code changed from outside the processor is decoded afresh: the second NOP becomes LDA #$EA
+    memory.borrow_mut().do_write(0x0203, 0xa9);
+    processor.reset();
+    let changed = trace_instructions(&mut processor, &bus, 4);
+    // the boot sequence, NOP, then two LDA #s of two cycles each
+    assert_eq!(changed.len(), 6);
+    assert_eq!(changed.last().unwrap().0, 0x0205);
+}
+
+#[test]
+fn test_predecode_cache() {
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    // NOP, LDA #$42, NOP at $0200 and LDX #$01, NOP at $0300, whose lines are the same modulo 256
+    let memory = make_eprom_for_program("0200: EA A9 42 EA\n0300: A2 01 EA", 0x0200);
+    bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+
+    let mut processor = create6502();
+    trace_instructions(&mut processor, &bus, 4);
+    assert_eq!(processor.get_decoded_count(), 3);
+    let mut registers = processor.get_registers();
+    registers.pc = 0x0300;
+    processor.set_registers(&registers);
+    trace_instructions(&mut processor, &bus, 2);
+    // LDX took the first NOP's line
+    assert_eq!(processor.get_decoded_count(), 4);
+
+    // an instruction changed from outside is decoded afresh: the LDA becomes NOP, NOP, NOP
+    memory.borrow_mut().do_write(0x0201, 0xea);
+    memory.borrow_mut().do_write(0x0202, 0xea);
+    registers.pc = 0x0201;
+    processor.set_registers(&registers);
+    let changed = trace_instructions(&mut processor, &bus, 3);
+    assert_eq!(changed.iter().map(|(pc, _)| *pc).collect::<Vec<_>>(), vec![0x0202, 0x0203, 0x0204]);
+    // the new NOPs took the LDA's line and $0302's
+    assert_eq!(processor.get_decoded_count(), 4);
+}
+
+#[test]
+fn test_predecode_cache_invalidated_by_writes() {
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    // LDA $1234, NOP, LDA #$42
+    let memory = make_eprom_for_program("0200: AD 34 12 EA A9 42", 0x0200);
+    bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+
+    let mut processor = create6502();
+    trace_instructions(&mut processor, &bus, 4);
+    assert_eq!(processor.get_decoded_count(), 3);
+
+    // a write past the end of an instruction leaves its line alone
+    bus.borrow().write(0x0206, 0xea);
+    processor.invalidate(0x0206);
+    assert_eq!(processor.get_decoded_count(), 3);
+    // one into the last byte of the LDA $1234's operand drops just its line, LDA $1200
+    bus.borrow().write(0x0202, 0x00);
+    processor.invalidate(0x0202);
+    assert_eq!(processor.get_decoded_count(), 2);
+    // and one into the LDA #'s operand just that
+    bus.borrow().write(0x0205, 0x10);
+    processor.invalidate(0x0205);
+    assert_eq!(processor.get_decoded_count(), 1);
+
+    // both are decoded afresh on the next pass
+    processor.reset();
+    trace_instructions(&mut processor, &bus, 4);
+    assert_eq!(processor.get_decoded_count(), 3);
+}
+
+#[test]
+#[ignore = "the processor doesn't carry out instructions' operations yet"]
+fn test_predecode_cache_with_self_modifying_code() {
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    // LDA #$EA, STA $0206, NOP, then LDA #$EA at $0206 that the STA turns into NOP, NOP
+    let memory = make_eprom_for_program("0200: A9 EA 8D 06 02 EA A9 EA", 0x0200);
+    bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+
+    let mut processor = create6502();
+    trace_instructions(&mut processor, &bus, 1);
+    // decode and cache the LDA at $0206 before the program writes over it
+    let mut registers = processor.get_registers();
+    registers.pc = 0x0206;
+    processor.set_registers(&registers);
+    trace_instructions(&mut processor, &bus, 1);
+    registers.pc = 0x0200;
+    processor.set_registers(&registers);
+
+    // where each instruction leaves the pc: the rewritten bytes run as two NOPs, not the cached LDA
+    let boundaries: Vec<Address> = (0..5).map(|_| trace_instructions(&mut processor, &bus, 1).last().unwrap().0).collect();
+    assert_eq!(memory.borrow().do_read(0x0206), 0xea);
+    assert_eq!(boundaries, vec![0x0202, 0x0205, 0x0206, 0x0207, 0x0208]);
+    assert_eq!(processor.get_registers().a, 0xea);
+}
+
+#[cfg(feature = "closure_cache")]
+#[test]
+fn test_closure_cache_translates_hot_blocks() {
+    let bus: Rc<RefCell<dyn Bus>> = Rc::new(RefCell::new(SimpleBus { registered: vec![] }));
+    // NOP, LDA #$42, LDX $10, LDY $1234,X, NOP
+    let memory = make_eprom_for_program("0200: EA A9 42 A6 10 BC 34 12 EA", 0x0200);
+    bus.borrow_mut().register_device(&memory.borrow_mut().as_cloned_bus_device(Rc::clone(&memory)));
+
+    let mut processor = create6502();
+    let relative = |trace: Vec<(Address, usize)>| -> Vec<(Address, usize)> {
+        let start = trace[0].1 - 1;
+        trace.into_iter().map(|(pc, total)| (pc, total - start)).collect()
+    };
+    let interpreted = relative(trace_instructions(&mut processor, &bus, 6));
+    let registers = processor.get_registers();
+    for _ in 0..32 {
+        processor.reset();
+        assert_eq!(relative(trace_instructions(&mut processor, &bus, 6)), interpreted);
+        assert_eq!(processor.get_registers(), registers);
+    }
+    assert_eq!(processor.get_translated_block_count(), 1);
+
+    // written over, the block goes back to the interpreter: the last NOP becomes LDA #
+    memory.borrow_mut().do_write(0x0208, 0xa9);
+    processor.reset();
+    let changed = relative(trace_instructions(&mut processor, &bus, 6));
+    assert_eq!(changed.last().unwrap().0, 0x020a);
+    assert_eq!(processor.get_translated_block_count(), 0);
+}
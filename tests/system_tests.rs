@@ -0,0 +1,669 @@
+#![cfg(feature = "devices")]
+
+use rust_6502_emulator::bus::Data;
+use rust_6502_emulator::devices::acia::{Acia, SerialBackend};
+use rust_6502_emulator::devices::timer::Timer;
+use rust_6502_emulator::devices::Peripheral;
+use rust_6502_emulator::memory::Rom;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rust_6502_emulator::system::{
+    bench, bench_system, run_controller, Clock, Command, CpuModel, DeviceStats, Event, Input, InputRecord, Recorder, Replay, Runner, Speed, StopReason,
+    System, SystemBuilder, Watchdog, WatchdogStop,
+};
+
+// boots to 0x0200 which is filled with NOPs
+fn nop_system() -> System {
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 32]);
+    system
+}
+
+#[test]
+fn test_step_and_run_whole_instructions() {
+    let mut system = nop_system();
+    system.step(); // boot vector
+    assert_eq!(system.get_registers().pc, 0x0200);
+    let cycles = system.step();
+    assert!(cycles > 0);
+    assert_eq!(system.get_registers().pc, 0x0201);
+
+    let before = system.get_total_cycles();
+    let run = system.run(10);
+    assert!(run >= 10);
+    assert_eq!(system.get_total_cycles() - before, run);
+    assert!(!system.is_halted());
+}
+
+#[test]
+fn test_devices_sit_in_front_of_ram_and_are_ticked() {
+    let mut system = nop_system();
+    let timer = system.add_peripheral(Timer::new(0x0300));
+    system.write(0x0300, 4);
+    system.write(0x0302, 0x05);
+    assert_eq!(system.read(0x0302), 0x05, "the timer answers, not the RAM behind it");
+    assert!(timer.borrow().is_running());
+
+    system.run(4);
+    assert!(system.irq_asserted());
+    assert!(timer.borrow().irq_asserted());
+
+    system.reset();
+    assert!(!system.irq_asserted());
+    system.step();
+    assert_eq!(system.get_registers().pc, 0x0200, "reset runs the boot sequence again");
+}
+
+#[test]
+fn test_device_stats() {
+    let mut system = nop_system();
+    system.add_peripheral(Timer::new(0x0300));
+    system.add_device(Rom::new(0x0400, vec![1, 2]));
+    system.write(0x0300, 4);
+    system.read(0x0302);
+    system.read(0x0400);
+    system.step(); // boot vector
+    system.step();
+
+    let stats = system.stats();
+    let cycles = system.get_total_cycles() as u64;
+    assert_eq!(
+        stats,
+        vec![
+            DeviceStats { name: "timer".to_string(), reads: 1, writes: 1, cycles: Some(cycles) },
+            DeviceStats { name: "rom".to_string(), reads: 1, writes: 0, cycles: None },
+            // the boot vector's two bytes and the NOP, and the write the timer shares with it
+            DeviceStats { name: "memory".to_string(), reads: 3, writes: 1, cycles: None },
+        ]
+    );
+    assert_eq!(stats[0].to_string(), format!("timer: 1 reads, 1 writes, {} cycles", cycles));
+    assert_eq!(stats[1].to_string(), "rom: 1 reads, 0 writes");
+}
+
+#[test]
+fn test_load_file() {
+    let path = std::env::temp_dir().join(format!("system_test_{}.bin", std::process::id()));
+    std::fs::write(&path, [0xa9, 0x42]).unwrap();
+    let mut system = System::new();
+    assert_eq!(system.load_file(0x1000, &path).unwrap(), 2);
+    assert_eq!((system.read(0x1000), system.read(0x1001)), (0xa9, 0x42));
+    assert!(system.load_file(0xffff, &path).is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert!(system.load_file(0x1000, &path).is_err());
+}
+
+#[test]
+fn test_builder() {
+    let path = std::env::temp_dir().join(format!("builder_test_{}.bin", std::process::id()));
+    std::fs::write(&path, [0xea; 16]).unwrap();
+    let mut system = SystemBuilder::new()
+        .cpu(CpuModel::Nmos6502)
+        .ram(0x0000..0x8000)
+        .rom_file(0xc000.., &path)
+        .peripheral(0xd000, Timer::new(0xd000))
+        .reset_vector(0xc000)
+        .build()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    system.write(0xc000, 0x00);
+    assert_eq!(system.read(0xc000), 0xea, "ROM ignores writes");
+    system.write(0x7fff, 0x12);
+    assert_eq!(system.read(0x7fff), 0x12);
+    system.write(0xd000, 4);
+    system.write(0xd002, 0x05);
+    assert_eq!(system.read(0xd002), 0x05);
+
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xc000);
+    system.run(4);
+    assert!(system.irq_asserted());
+}
+
+#[test]
+fn test_builder_6507_has_13_address_lines() {
+    let system = SystemBuilder::new().cpu(CpuModel::Mos6507).ram(0x0000..0x2000).build().unwrap();
+    system.write(0xf123, 0x42);
+    assert_eq!(system.read(0x1123), 0x42);
+    assert_eq!(system.get_bus().borrow().read(0x3123), 0x42, "the debugger sees the same bus");
+}
+
+#[test]
+fn test_elapsed_is_virtual_time() {
+    let mut system = nop_system();
+    system.set_clock_hz(2_000_000);
+    system.step(); // boot vector
+    let boot = system.elapsed();
+    assert_eq!(boot, system.get_total_cycles() as u64 * 500);
+    system.run(10);
+    let cycles = system.get_total_cycles() as u64;
+    assert_eq!(system.elapsed(), cycles * 500);
+
+    // halving the clock doubles the time of what runs after
+    system.set_clock_hz(1_000_000);
+    assert_eq!(system.elapsed(), cycles * 500);
+    system.run(10);
+    assert_eq!(system.elapsed(), cycles * 500 + (system.get_total_cycles() as u64 - cycles) * 1_000);
+}
+
+// Going back past a clock change, as a restored snapshot or a debugger's travel does
+#[test]
+fn test_elapsed_after_restoring_an_earlier_snapshot() {
+    let mut system = nop_system();
+    system.run(10);
+    let processor = system.get_processor();
+    let snapshot = processor.borrow().snapshot();
+    let cycles = system.get_total_cycles() as u64;
+    system.run(4);
+    system.set_clock_hz(2_000_000);
+    system.run(10);
+
+    processor.borrow_mut().restore(&snapshot);
+    assert_eq!(system.elapsed(), cycles * 1_000);
+    // a change made now replaces the abandoned one
+    system.set_clock_hz(4_000_000);
+    system.run(8);
+    assert_eq!(system.elapsed(), cycles * 1_000 + (system.get_total_cycles() as u64 - cycles) * 250);
+}
+
+#[test]
+fn test_peek_leaves_io_registers_alone() {
+    let mut system = nop_system();
+    let timer = system.add_peripheral(Timer::new(0x0300));
+    system.write(0x0300, 1);
+    system.write(0x0302, 0x05);
+    timer.borrow_mut().tick(1);
+
+    assert_eq!(system.peek(0x0303), 0x80);
+    assert!(timer.borrow().irq_asserted());
+    assert_eq!(system.read(0x0303), 0x80);
+    assert!(!timer.borrow().irq_asserted());
+}
+
+#[test]
+fn test_scheduled_callbacks_run_on_their_cycle() {
+    let mut system = nops();
+    let fired = Rc::new(RefCell::new(vec![]));
+
+    let log = fired.clone();
+    system.schedule_in(7, move |system| log.borrow_mut().push(("in 7", system.get_total_cycles())));
+    let log = fired.clone();
+    system.schedule_at(20, move |system| log.borrow_mut().push(("at 20", system.get_total_cycles())));
+    let log = fired.clone();
+    let cancelled = system.schedule_at(10, move |_| log.borrow_mut().push(("cancelled", 0)));
+    assert!(system.cancel(cancelled));
+    assert!(!system.cancel(cancelled));
+
+    // every 5 cycles from 25, from a device's handle, rescheduling itself three times
+    fn every_5(log: Rc<RefCell<Vec<(&'static str, usize)>>>, left: usize) -> impl FnOnce(&mut System) {
+        move |system| {
+            log.borrow_mut().push(("every 5", system.get_total_cycles()));
+            if left > 1 {
+                system.schedule_in(5, every_5(log, left - 1));
+            }
+        }
+    }
+    let scheduler = system.get_scheduler();
+    scheduler.borrow_mut().schedule_at(25, every_5(fired.clone(), 3));
+    assert_eq!(scheduler.borrow_mut().next_due(), Some(7));
+
+    system.run(50);
+    assert_eq!(
+        *fired.borrow(),
+        vec![("in 7", 7), ("at 20", 20), ("every 5", 25), ("every 5", 30), ("every 5", 35)]
+    );
+    assert!(scheduler.borrow().is_empty());
+}
+
+// Bytes that turn up now and then, as a host would send them
+struct Trickle {
+    bytes: Vec<Data>,
+    polls: usize,
+}
+
+impl SerialBackend for Trickle {
+    fn receive(&mut self) -> Option<Data> {
+        self.polls += 1;
+        if self.polls.is_multiple_of(3) && !self.bytes.is_empty() {
+            Some(self.bytes.remove(0))
+        } else {
+            None
+        }
+    }
+
+    fn transmit(&mut self, _data: Data) {}
+}
+
+// Runs NOPs with an ACIA on the backend, reading it every 4 cycles. Returns what was read when
+fn read_acia(backend: Box<dyn SerialBackend>, attach: impl FnOnce(&System)) -> Vec<(usize, Data)> {
+    fn reader(log: Rc<RefCell<Vec<(usize, Data)>>>) -> impl FnOnce(&mut System) {
+        move |system| {
+            if system.read(0x5001) & 0x08 != 0 {
+                log.borrow_mut().push((system.get_total_cycles(), system.read(0x5000)));
+            }
+            system.schedule_in(4, reader(log));
+        }
+    }
+    let mut system = nops();
+    system.add_peripheral(Acia::new(0x5000, backend));
+    attach(&system);
+    let log = Rc::new(RefCell::new(vec![]));
+    system.schedule_in(4, reader(log.clone()));
+    system.run(100);
+    let log = log.borrow().clone();
+    log
+}
+
+#[test]
+fn test_record_and_replay_inputs() {
+    let recorder = Recorder::new();
+    let serial = recorder.serial("acia", Box::new(Trickle { bytes: b"HI!".to_vec(), polls: 0 }));
+    let recorded = read_acia(Box::new(serial), |system| recorder.attach(system));
+    assert_eq!(recorded.iter().map(|(_, data)| *data).collect::<Vec<_>>(), b"HI!");
+    let records = recorder.get_records();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].input, Input::Serial(b'H'));
+
+    let path = std::env::temp_dir().join("rust-6502-replay-test.inputs");
+    recorder.save(&path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(InputRecord::parse(text.lines().next().unwrap()), Some(records[0].clone()));
+
+    // the same bytes on the same cycles
+    let replay = Replay::load(&path).unwrap();
+    let output = Box::new(Trickle { bytes: vec![], polls: 0 });
+    let replayed = read_acia(Box::new(replay.serial("acia", output)), |system| replay.attach(system));
+    assert_eq!(replayed, recorded);
+    std::fs::remove_file(path).unwrap();
+
+    assert!(Replay::parse("12 acia serial zz").is_err());
+    let replay = Replay::parse("# comment\n\n5 line irq 1\n9 line irq 0\n").unwrap();
+    let irq = replay.irq("line");
+    assert!(!irq());
+}
+
+// Collects log messages instead of printing them
+struct Collect(std::sync::Mutex<Vec<String>>);
+
+impl log::Log for Collect {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static COLLECT: Collect = Collect(std::sync::Mutex::new(vec![]));
+
+#[test]
+fn test_core_diagnostics_go_to_the_log() {
+    log::set_logger(&COLLECT).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    let mut system = nop_system();
+    system.run(4);
+    assert!(COLLECT.0.lock().unwrap().iter().any(|m| m == "$0200 NOP Implied"));
+}
+
+#[test]
+fn test_clock_paces_to_its_rate() {
+    let mut clock = Clock::new(1_000_000);
+    let start = Instant::now();
+    for _ in 0..20 {
+        clock.advance(1_000);
+    }
+    assert!(start.elapsed() >= Duration::from_millis(18));
+    assert_eq!(clock.get_total_cycles(), 20_000);
+
+    // a stall resyncs rather than racing to catch up
+    std::thread::sleep(Duration::from_millis(150));
+    clock.advance(1);
+    let start = Instant::now();
+    clock.advance(10_000);
+    assert!(start.elapsed() >= Duration::from_millis(8));
+
+    let mut clock = Clock::unthrottled();
+    let start = Instant::now();
+    clock.advance(1_000_000_000);
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert_eq!(clock.get_rate(), None);
+}
+
+#[test]
+fn test_run_clocked() {
+    let mut system = nop_system();
+    system.step(); // boot vector
+    let mut clock = Clock::new(5_000);
+    let start = Instant::now();
+    assert!(system.run_clocked(50, &mut clock) >= 50);
+    assert!(start.elapsed() >= Duration::from_millis(8));
+    assert_eq!(clock.get_total_cycles() as usize + 1, system.get_total_cycles());
+}
+
+#[test]
+fn test_run_frame_averages_to_its_budget() {
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 0x400]);
+    system.step(); // boot vector
+    let mut frames = 0;
+    let mut total = 0;
+    for _ in 0..10 {
+        total += system.run_frame(7, |system| {
+            assert!(!system.is_halted());
+            frames += 1;
+        });
+    }
+    assert_eq!(frames, 10);
+    // NOPs take 2 cycles, so odd frames overrun by one and the next makes it up
+    assert!((70..=71).contains(&total));
+}
+
+// 60K of NOPs from 0x1000, over a second's worth at 100kHz
+fn nops() -> System {
+    let mut system = System::new();
+    system.load(0x1000, &[0xea; 0xf000]);
+    system.set_reset_vector(0x1000);
+    system
+}
+
+#[test]
+fn test_run_controller_pauses_and_steps() {
+    let (controller, run_loop) = run_controller();
+    controller.pause();
+    controller.step();
+    controller.step();
+    controller.step();
+    controller.stop();
+    let pc = std::thread::spawn(move || {
+        let mut system = nops();
+        run_loop.run(&mut system, &mut Clock::unthrottled());
+        system.get_registers().pc
+    })
+    .join()
+    .unwrap();
+    // the boot vector, then two NOPs
+    assert_eq!(pc, 0x1002);
+    assert!(controller.is_paused());
+    assert!(controller.is_stopped());
+}
+
+#[test]
+fn test_run_controller_runs_until_stopped() {
+    let (controller, run_loop) = run_controller();
+    let handle = std::thread::spawn(move || run_loop.run(&mut nops(), &mut Clock::new(100_000)));
+    let start = Instant::now();
+    while controller.get_cycles() < 2_000 {
+        assert!(start.elapsed() < Duration::from_secs(10), "not running");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!controller.is_paused());
+    controller.stop();
+    let run = handle.join().unwrap();
+    assert!(run >= 2_000);
+    assert_eq!(controller.get_cycles(), run as u64);
+    assert!(controller.is_stopped());
+}
+
+fn next_event(runner: &Runner) -> Event {
+    runner.get_events().recv_timeout(Duration::from_secs(10)).expect("no event")
+}
+
+#[test]
+fn test_runner_steps_traces_and_breaks() {
+    let runner = Runner::spawn(nops, Clock::unthrottled());
+    runner.send(Command::Trace(true));
+    runner.send(Command::Step);
+    match next_event(&runner) {
+        Event::TraceRecord { registers, cycles } => {
+            assert_eq!(registers.pc, 0x1000);
+            assert!(cycles > 0);
+        }
+        event => panic!("{:?}", event),
+    }
+    match next_event(&runner) {
+        Event::Stopped { reason, registers } => {
+            assert_eq!(reason, StopReason::Stepped);
+            assert_eq!(registers.pc, 0x1000);
+        }
+        event => panic!("{:?}", event),
+    }
+
+    runner.send(Command::Trace(false));
+    runner.send(Command::SetBreakpoint(0x1010));
+    runner.send(Command::Run);
+    match next_event(&runner) {
+        Event::Stopped { reason, registers } => {
+            assert_eq!(reason, StopReason::Breakpoint);
+            assert_eq!(registers.pc, 0x1010);
+        }
+        event => panic!("{:?}", event),
+    }
+
+    runner.send(Command::LoadProgram {
+        address: 0x0300,
+        data: vec![1, 2, 3],
+    });
+    runner.send(Command::ReadMemory {
+        address: 0x02ff,
+        length: 4,
+    });
+    assert_eq!(
+        next_event(&runner),
+        Event::Memory {
+            address: 0x02ff,
+            data: vec![0, 1, 2, 3]
+        }
+    );
+}
+
+#[test]
+fn test_runner_sends_frames_until_paused() {
+    let build = || {
+        let mut system = nops();
+        system.set_clock_hz(6_000); // 100 cycle frames
+        system
+    };
+    let runner = Runner::spawn(build, Clock::new(6_000));
+    runner.send(Command::Run);
+    match next_event(&runner) {
+        Event::FrameReady { cycles } => assert!(cycles >= 100),
+        event => panic!("{:?}", event),
+    }
+    runner.send(Command::Pause);
+    loop {
+        match next_event(&runner) {
+            Event::FrameReady { .. } => (),
+            Event::Stopped { reason, .. } => {
+                assert_eq!(reason, StopReason::Paused);
+                break;
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+}
+
+#[test]
+fn test_runner_speeds() {
+    assert_eq!("realtime".parse(), Ok(Speed::RealTime));
+    assert_eq!("10hz".parse(), Ok(Speed::SlowMotion(10)));
+    assert_eq!(Speed::SlowMotion(10).get_rate(1_000_000), Some(10));
+    assert_eq!(Speed::RealTime.get_rate(1_000_000), Some(1_000_000));
+    assert_eq!(Speed::Max.next().next().next().next(), Speed::Max);
+
+    let runner = Runner::spawn(nops, Clock::unthrottled());
+    runner.send(Command::SetSpeed(Speed::SingleStep));
+    runner.send(Command::Run);
+    match next_event(&runner) {
+        Event::Stopped { reason, registers } => {
+            assert_eq!(reason, StopReason::Stepped);
+            assert_eq!(registers.pc, 0x1000);
+        }
+        event => panic!("{:?}", event),
+    }
+
+    // slow motion frames are a 60th of a second at the slow rate
+    runner.send(Command::SetSpeed(Speed::SlowMotion(600)));
+    runner.send(Command::Run);
+    let start = Instant::now();
+    match next_event(&runner) {
+        Event::FrameReady { .. } => assert!(start.elapsed() >= Duration::from_millis(10)),
+        event => panic!("{:?}", event),
+    }
+    runner.send(Command::Pause);
+}
+
+#[test]
+fn test_builder_rejects_bad_layouts() {
+    assert!(SystemBuilder::new().rom(0xf000..0xf002, vec![0; 3]).build().is_err(), "ROM larger than its range");
+    assert!(SystemBuilder::new().device(0xd000, Timer::new(0xe000)).build().is_err(), "device not at its address");
+    assert!(SystemBuilder::new().rom_file(0xc000.., "/nonexistent/rom.bin").build().is_err());
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn test_machine_description() {
+    use rust_6502_emulator::system::MachineConfig;
+
+    let dir = std::env::temp_dir().join(format!("machine_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("rom.bin"), [0xea; 4]).unwrap();
+    std::fs::write(
+        dir.join("machine.toml"),
+        r#"
+            clock_hz = 2000000
+            reset_vector = 0xc000
+            ram = { start = 0x0000, end = 0x7fff }
+
+            [[rom]]
+            start = 0xc000
+            file = "rom.bin"
+
+            [[device]]
+            type = "timer"
+            base = 0xd000
+        "#,
+    )
+    .unwrap();
+    let mut system = MachineConfig::load(dir.join("machine.toml")).unwrap().build().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(system.get_clock_hz(), 2_000_000);
+    assert_eq!(system.read(0xc003), 0xea);
+    system.write(0xd000, 4);
+    system.write(0xd002, 0x05);
+    assert_eq!(system.read(0xd002), 0x05);
+    system.step();
+    assert_eq!(system.get_registers().pc, 0xc000);
+
+    assert!(MachineConfig::parse("[[device]]\ntype = \"toaster\"\nbase = 0").is_err());
+    assert!(MachineConfig::parse("ram = { start = 0, end = 0xffff }\nflux = 1").is_err());
+}
+
+#[test]
+fn test_bench() {
+    // the workload only uses instructions the processor has, all the way through
+    let mut system = bench_system();
+    assert_eq!(system.get_registers().pc, 0x0400);
+    for _ in 0..64 * 16 {
+        system.step();
+    }
+    assert_eq!(system.get_registers().pc, 0x0400 + 64 * 32);
+
+    let report = bench(Duration::from_millis(20));
+    assert!(report.instructions > 0 && report.instructions.is_multiple_of(10_000));
+    assert!(report.cycles >= report.instructions);
+    assert!(report.elapsed >= Duration::from_millis(20));
+    assert!(report.mhz() > 0.0);
+    let text = report.to_string();
+    assert!(text.starts_with(&format!("{} cycles ({} instructions) in ", report.cycles, report.instructions)), "{}", text);
+    assert!(text.contains(" MHz, "), "{}", text);
+}
+
+#[test]
+fn test_watchdog() {
+    // LDA #$01, BRK
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xa9, 0x01, 0x00]);
+    let report = Watchdog::new().with_max_cycles(1000).run(&mut system);
+    assert_eq!(report.stop, WatchdogStop::Break);
+    assert_eq!(report.stop.exit_code(), 0);
+    assert_eq!(report.instructions, 1);
+    assert_eq!(system.get_registers().pc, 0x0202);
+    assert!(report.to_string().ends_with("(1 instructions), at BRK"), "{}", report);
+
+    // JMP isn't in the processor yet
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea, 0x4c, 0x00, 0x02]);
+    let report = Watchdog::new().run(&mut system);
+    assert_eq!(report.stop, WatchdogStop::UnknownOpcode(0x0201));
+    assert_eq!(report.stop.exit_code(), 3);
+    assert_eq!(report.instructions, 1);
+    assert_eq!(system.get_registers().pc, 0x0201);
+
+    let report = Watchdog::new().with_max_cycles(10).run(&mut nop_system());
+    assert_eq!(report.stop, WatchdogStop::CycleLimit);
+    assert_eq!(report.stop.exit_code(), 4);
+    assert!(report.cycles >= 10);
+
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xea; 3000]);
+    let report = Watchdog::new().with_timeout(Duration::ZERO).run(&mut system);
+    assert_eq!(report.stop, WatchdogStop::Timeout);
+    assert_eq!(report.stop.exit_code(), 5);
+    assert_eq!(report.instructions, 1000);
+
+    let stops = [
+        WatchdogStop::Break,
+        WatchdogStop::Trap(0),
+        WatchdogStop::UnknownOpcode(0),
+        WatchdogStop::CycleLimit,
+        WatchdogStop::Timeout,
+    ];
+    let mut codes: Vec<i32> = stops.iter().map(|stop| stop.exit_code()).collect();
+    codes.dedup();
+    assert_eq!(codes, [0, 2, 3, 4, 5]);
+    assert_eq!(WatchdogStop::Trap(0x3469).to_string(), "trapped at $3469");
+}
+
+#[test]
+fn test_watchdog_state_json() {
+    let mut system = System::new();
+    system.set_reset_vector(0x0200);
+    system.load(0x0200, &[0xa2, 0x07, 0x4c, 0x00, 0x02]);
+    system.load(0x0010, &[1, 2, 3]);
+    let report = Watchdog::new().run(&mut system);
+    let json = report.state_json(&system, &[(0x0010, 0x0012), (0x0200, 0x0200)]);
+    let registers = system.get_registers();
+    assert_eq!(
+        json,
+        format!(
+            r#"{{
+  "stop": "unknown_opcode",
+  "stop_address": 514,
+  "cycles": {},
+  "instructions": 1,
+  "registers": {{"pc": 514, "a": 0, "x": {}, "y": 0, "p": {}}},
+  "memory": [
+    {{"start": 16, "end": 18, "data": [1, 2, 3]}},
+    {{"start": 512, "end": 512, "data": [162]}}
+  ]
+}}
+"#,
+            report.cycles, registers.x, registers.status
+        )
+    );
+    let mut system = nop_system();
+    let json = Watchdog::new().with_max_cycles(4).run(&mut system).state_json(&system, &[]);
+    assert!(json.starts_with("{\n  \"stop\": \"cycle_limit\",\n  \"cycles\": "), "{}", json);
+    assert!(json.ends_with("\"memory\": []\n}\n"), "{}", json);
+}
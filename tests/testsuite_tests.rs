@@ -0,0 +1,447 @@
+#![cfg(feature = "testsuite")]
+
+use std::env;
+use std::path::PathBuf;
+
+use rust_6502_emulator::asm::assemble;
+use rust_6502_emulator::system::System;
+use rust_6502_emulator::testsuite::coverage::{self, Status};
+use rust_6502_emulator::testsuite::decimal::{self, DecimalTest, C, N, V, Z};
+use rust_6502_emulator::processor::{create_instruction_table, Registers};
+use rust_6502_emulator::testsuite::fuzz::{CoreFuzzer, FuzzCase, FuzzFailure};
+use rust_6502_emulator::testsuite::golden::{self, GoldenError, GoldenRun};
+use rust_6502_emulator::testsuite::harte;
+use rust_6502_emulator::testsuite::klaus::{FunctionalTest, Outcome};
+use rust_6502_emulator::testsuite::nestest::{self, LogLine, NestestCheck};
+
+// A 64K image of NOPs, with the test number in place
+fn nop_image(test_case: u8) -> Vec<u8> {
+    let mut image = vec![0xea; 0x10000];
+    image[0x0200] = test_case;
+    image
+}
+
+#[test]
+fn test_functional_test_reaching_success() {
+    let report = FunctionalTest::new().with_success(0x0410).run_image(&nop_image(0xf0));
+    assert_eq!(report.outcome, Outcome::Passed);
+    assert!(report.passed());
+    assert_eq!(report.instructions, 0x10);
+    assert_eq!(report.registers.pc, 0x0410);
+    assert!(report.to_string().starts_with("passed after 16 instructions"), "{}", report);
+}
+
+#[test]
+fn test_functional_test_reports_the_test_number() {
+    let mut image = nop_image(0x2a);
+    image[0x0408] = 0x02;
+    let report = FunctionalTest::new().run_image(&image);
+    assert_eq!(report.outcome, Outcome::UnknownOpcode { address: 0x0408, opcode: 0x02 });
+    assert_eq!(report.test_case, 0x2a);
+    assert!(!report.passed());
+    assert!(report.to_string().starts_with("test $2A stopped at unknown opcode $02 at $0408"), "{}", report);
+
+    let report = FunctionalTest::new().with_max_cycles(10).with_test_case(0x0201).run_image(&nop_image(1));
+    assert_eq!(report.outcome, Outcome::CycleLimit);
+    assert_eq!(report.test_case, 0xea);
+}
+
+// Needs 6502_functional_test.bin, built with the default settings, from
+// github.com/Klaus2m5/6502_65C02_functional_tests:
+//   KLAUS_FUNCTIONAL_TEST=path/to/6502_functional_test.bin cargo test -- --ignored
+#[test]
+#[ignore = "needs the 6502_functional_test binary, and the processor to carry out instructions' operations"]
+fn test_klaus_functional_test() {
+    let path = env::var_os("KLAUS_FUNCTIONAL_TEST")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("tests/roms/6502_functional_test.bin"));
+    let report = FunctionalTest::new()
+        .run_file(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (set KLAUS_FUNCTIONAL_TEST to the binary)", path.display(), e));
+    assert!(report.passed(), "{}", report);
+}
+
+// Two of SingleStepTests' vectors, LDA # and NOP, in their format
+const LDA_IMMEDIATE: &str = r#"[
+    {
+        "name": "a9 3f 10",
+        "initial": { "pc": 4660, "s": 253, "a": 0, "x": 1, "y": 2, "p": 36, "ram": [[4660, 169], [4661, 63]] },
+        "final": { "pc": 4662, "s": 253, "a": 63, "x": 1, "y": 2, "p": 36, "ram": [[4660, 169], [4661, 63]] },
+        "cycles": [[4660, 169, "read"], [4661, 63, "read"]]
+    }
+]"#;
+const NOP: &str = r#"[{"name": "ea 00 00", "initial": {"pc": 512, "s": 255, "a": 5, "x": 0, "y": 0, "p": 48,
+    "ram": [[512, 234], [513, 0]]}, "final": {"pc": 513, "s": 255, "a": 5, "x": 0, "y": 0, "p": 48,
+    "ram": [[512, 234], [513, 0]]}, "cycles": [[512, 234, "read"], [513, 0, "read"]]}]"#;
+
+#[test]
+fn test_harte_parse() {
+    let cases = harte::parse(LDA_IMMEDIATE).unwrap();
+    assert_eq!(cases.len(), 1);
+    let case = &cases[0];
+    assert_eq!(case.name, "a9 3f 10");
+    assert_eq!((case.initial.pc, case.initial.s, case.initial.p), (0x1234, 0xfd, 0x24));
+    assert_eq!(case.expected.a, 0x3f);
+    assert_eq!(case.initial.ram, vec![(0x1234, 0xa9), (0x1235, 0x3f)]);
+    assert_eq!(case.cycles[1], harte::BusCycle { address: 0x1235, data: 0x3f, write: false });
+    assert_eq!(case.cycles[1].to_string(), "read $1235=$3F");
+
+    assert!(harte::parse("{}").is_err());
+    let error = harte::parse(r#"[{"name": "x", "initial": {}, "final": {}, "cycles": []}]"#).unwrap_err();
+    assert_eq!(error, "test 1 (x): missing ram");
+}
+
+#[test]
+fn test_harte_run() {
+    // the processor fetches LDA's operand without loading it, so it passes only when A already has it
+    let mut cases = harte::parse(LDA_IMMEDIATE).unwrap();
+    let report = harte::run_tests(0xa9, &cases);
+    assert!(report.is_implemented());
+    assert_eq!((report.tests, report.passed, report.failed()), (1, 0, 1));
+    assert_eq!(report.to_string(), "$A9 LDA 0 of 1 passed\n  a9 3f 10: A=$00, expected $3F");
+    cases[0].initial.a = 0x3f;
+    assert!(harte::run_case(&cases[0]).is_empty());
+    assert_eq!(harte::run_tests(0xa9, &cases).to_string(), "$A9 LDA 1 of 1 passed");
+
+    // nor does it read the byte after a one byte instruction
+    let cases = harte::parse(NOP).unwrap();
+    assert_eq!(harte::run_case(&cases[0]), vec!["took 1 cycles, expected 2", "1 bus accesses, expected 2"]);
+
+    // an opcode the processor doesn't have is skipped rather than run
+    let report = harte::run_tests(0x02, &harte::parse(NOP).unwrap());
+    assert!(!report.is_implemented());
+    assert_eq!(report.failed(), 0);
+    assert_eq!(report.to_string(), "$02     not implemented, 1 tests skipped");
+}
+
+#[test]
+fn test_harte_run_dir() {
+    let dir = env::temp_dir().join(format!("harte_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("ea.json"), NOP).unwrap();
+    std::fs::write(dir.join("a9.json"), LDA_IMMEDIATE).unwrap();
+    std::fs::write(dir.join("README.md"), "not a test").unwrap();
+    let reports = harte::run_dir(&dir).unwrap();
+    assert_eq!(reports.iter().map(|r| r.opcode).collect::<Vec<_>>(), vec![0xa9, 0xea]);
+    std::fs::write(dir.join("lda.json"), LDA_IMMEDIATE).unwrap();
+    let error = harte::run_file(dir.join("lda.json")).err().unwrap().to_string();
+    assert!(error.ends_with("isn't named for an opcode, e.g. a9.json"), "{}", error);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// Needs the 6502 directory of github.com/SingleStepTests/65x02:
+//   HARTE_TESTS=path/to/65x02/6502/v1 cargo test -- --ignored
+#[test]
+#[ignore = "needs the SingleStepTests vectors, and the processor to carry out instructions' operations"]
+fn test_harte_single_step_tests() {
+    let dir = env::var_os("HARTE_TESTS").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("tests/roms/65x02/6502/v1"));
+    let reports = harte::run_dir(&dir).unwrap_or_else(|e| panic!("{}: {} (set HARTE_TESTS to the directory)", dir.display(), e));
+    let failed: Vec<String> = reports.iter().filter(|r| r.failed() > 0).map(|r| r.to_string()).collect();
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
+
+#[test]
+fn test_nmos_decimal_reference() {
+    // valid BCD
+    assert_eq!(decimal::nmos_adc(0x12, 0x34, false), (0x46, 0));
+    assert_eq!(decimal::nmos_adc(0x58, 0x46, true), (0x05, N | V | C));
+    assert_eq!(decimal::nmos_sbc(0x46, 0x12, true), (0x34, C));
+    assert_eq!(decimal::nmos_sbc(0x00, 0x01, true), (0x99, N));
+    assert_eq!(decimal::nmos_sbc(0x40, 0x40, true), (0x00, Z | C));
+    // the cases that catch emulators out: Z from the binary sum, N and V before the high digit's
+    // adjustment, and invalid digits
+    assert_eq!(decimal::nmos_adc(0x99, 0x01, false), (0x00, N | C));
+    assert_eq!(decimal::nmos_adc(0x00, 0x0f, false), (0x15, 0));
+    assert_eq!(decimal::nmos_adc(0x0f, 0x0f, true), (0x15, 0));
+    assert_eq!(decimal::nmos_adc(0xff, 0x01, false), (0x66, Z | C));
+    assert_eq!(decimal::nmos_sbc(0x1a, 0x0b, true), (0x09, C));
+}
+
+// The variables of the decimal test at $0000 and a BRK a few NOPs into the program
+fn decimal_system(variables: &[u8]) -> System {
+    let mut system = System::new();
+    system.load(0x0000, variables);
+    system.load(0x0200, &[0xea, 0xea, 0xea, 0x00]);
+    system
+}
+
+#[test]
+fn test_decimal_test_result() {
+    let report = DecimalTest::new().run(&mut decimal_system(&[0; 12]));
+    assert_eq!(report.outcome, decimal::Outcome::Passed);
+    assert_eq!(report.instructions, 3);
+
+    // failed on $99 + $01: got $9A in binary, where $00 was worked out
+    let mut system = decimal_system(&[0x99, 0x01, 0, 0, 0x9a, 0xc1, 0x00, 0, 0, 0, 0, 1]);
+    let report = DecimalTest::new().run(&mut system);
+    assert_eq!(report.outcome, decimal::Outcome::Failed);
+    assert_eq!(report.operands, (0x99, 0x01, false));
+    assert_eq!((report.result, report.flags, report.expected), (0x9a, N | V | C, 0x00));
+    assert!(
+        report.to_string().starts_with("failed on $99 and $01 with carry 0: got $9A (flags $C1), expected $00 after 3"),
+        "{}",
+        report
+    );
+
+    // an end address from the listing stops it before the BRK
+    let report = DecimalTest::new().with_end(0x0201).run(&mut decimal_system(&[0; 12]));
+    assert_eq!((report.outcome, report.instructions), (decimal::Outcome::Passed, 1));
+    let report = DecimalTest::new().with_max_cycles(2).run(&mut decimal_system(&[0; 12]));
+    assert_eq!(report.outcome, decimal::Outcome::CycleLimit);
+}
+
+// Needs the decimal test assembled for an NMOS 6502 at $0200 with its variables at $0000, e.g.
+// 6502_decimal_test from github.com/Klaus2m5/6502_65C02_functional_tests:
+//   DECIMAL_TEST=path/to/6502_decimal_test.bin cargo test -- --ignored
+#[test]
+#[ignore = "needs the decimal test binary, and the processor to have decimal mode"]
+fn test_bruce_clark_decimal_test() {
+    let path = env::var_os("DECIMAL_TEST").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("tests/roms/6502_decimal_test.bin"));
+    let report = DecimalTest::new()
+        .run_file(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (set DECIMAL_TEST to the binary)", path.display(), e));
+    assert!(report.passed(), "{}", report);
+}
+
+#[test]
+fn test_nestest_log_line() {
+    let line = "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7";
+    let line = LogLine::parse(line).unwrap();
+    assert_eq!((line.pc, line.bytes.clone(), line.p, line.cycles), (0xc000, vec![0x4c, 0xf5, 0xc5], 0x24, Some(7)));
+    // illegal opcodes are marked with a *, and memory operands annotated
+    let line = LogLine::parse("C6BD  04 A9    *NOP $A9 = 00                    A:AA X:97 Y:4E P:EF SP:F5 CYC:1128").unwrap();
+    assert_eq!((line.bytes.clone(), line.a, line.x, line.y), (vec![0x04, 0xa9], 0xaa, 0x97, 0x4e));
+    assert_eq!(nestest::parse("\nnot a trace\n").unwrap_err(), "line 2: not a trace line: not a trace");
+}
+
+// A 16K iNES image of NOPs, with changes
+fn nes_image(changes: &[(usize, u8)]) -> Vec<u8> {
+    let mut image = b"NES\x1a\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    image.extend(vec![0xea; 0x4000]);
+    for &(offset, byte) in changes {
+        image[16 + offset] = byte;
+    }
+    image
+}
+
+const NOPS_LOG: &str = "
+C000  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C001  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9
+C002  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 33 CYC:11
+C003  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 39 CYC:13
+";
+
+fn nes_system(changes: &[(usize, u8)]) -> System {
+    let mut system = System::new();
+    nestest::load_ines(&mut system, &nes_image(changes)).unwrap();
+    system
+}
+
+#[test]
+fn test_nestest_check() {
+    let log = nestest::parse(NOPS_LOG).unwrap();
+    assert_eq!(NestestCheck::new(log.clone()).run(&mut nes_system(&[])), Ok(4));
+    assert_eq!(nes_system(&[]).read(0x8000), 0xea);
+
+    let mut changed = log.clone();
+    changed[3].a = 0x01;
+    changed[3].text = changed[3].text.replace("A:00", "A:01");
+    let divergence = NestestCheck::new(changed).run(&mut nes_system(&[])).unwrap_err();
+    assert_eq!((divergence.instruction, divergence.fields.clone()), (4, vec!["A"]));
+    assert_eq!(divergence.context.len(), 3);
+    let text = divergence.to_string();
+    assert!(text.starts_with("diverged at instruction 4: A\n"), "{}", text);
+    assert!(text.contains("\nexpected   C003  EA        NOP"), "{}", text);
+    assert!(text.contains("\ngot        C003  EA        NOP "), "{}", text);
+    assert!(text.contains("\nA      01        00        <\n"), "{}", text);
+    assert!(text.contains("\nX      00        00        \n"), "{}", text);
+
+    // the processor's NOP doesn't take two cycles yet
+    let divergence = NestestCheck::new(log.clone()).with_cycles(true).run(&mut nes_system(&[])).unwrap_err();
+    assert_eq!((divergence.instruction, divergence.fields.clone()), (2, vec!["CYC"]));
+    assert_eq!((divergence.expected.cycles, divergence.actual.cycles), (Some(9), Some(8)));
+
+    let divergence = NestestCheck::new(log).run(&mut nes_system(&[(2, 0x02)])).unwrap_err();
+    assert_eq!((divergence.instruction, divergence.unknown_opcode), (3, Some(0x02)));
+    assert!(divergence.to_string().starts_with("unknown opcode $02 at instruction 3\n"));
+}
+
+#[test]
+fn test_load_ines() {
+    let mut system = System::new();
+    assert!(nestest::load_ines(&mut system, b"not a rom").is_err());
+    let mut image = nes_image(&[(0x3ffc, 0x00), (0x3ffd, 0xc0)]);
+    nestest::load_ines(&mut system, &image).unwrap();
+    assert_eq!((system.read(0xbffd), system.read(0xfffd)), (0xc0, 0xc0));
+    image[4] = 3;
+    assert!(nestest::load_ines(&mut system, &image).is_err());
+}
+
+// Needs nestest.nes and nestest.log (from the NESdev wiki's emulator tests), run from $C000:
+//   NESTEST_ROM=path/to/nestest.nes NESTEST_LOG=path/to/nestest.log cargo test -- --ignored
+#[test]
+#[ignore = "needs nestest.nes and its log, and the processor to carry out instructions' operations"]
+fn test_nestest() {
+    let path = |var: &str, default: &str| env::var_os(var).map(PathBuf::from).unwrap_or_else(|| PathBuf::from(default));
+    let (rom, log) = (path("NESTEST_ROM", "tests/roms/nestest.nes"), path("NESTEST_LOG", "tests/roms/nestest.log"));
+    let mut system = System::new();
+    let image = std::fs::read(&rom).unwrap_or_else(|e| panic!("{}: {} (set NESTEST_ROM)", rom.display(), e));
+    nestest::load_ines(&mut system, &image).unwrap();
+    let mut check = NestestCheck::from_log_file(&log).unwrap_or_else(|e| panic!("{}: {} (set NESTEST_LOG)", log.display(), e));
+    // the official opcodes end at line 5003, where the illegal ones begin
+    check = check.with_cycles(true);
+    if let Err(divergence) = check.run(&mut system) {
+        assert!(divergence.instruction > 5003, "{}", divergence);
+    }
+}
+
+fn fuzz_case(pc: u16, instruction: [u8; 3]) -> FuzzCase {
+    FuzzCase { registers: Registers { pc, a: 0x12, x: 0x34, y: 0x56, status: 0x24 }, instruction, fill: 0xea }
+}
+
+#[test]
+fn test_fuzz_case_from_bytes() {
+    assert_eq!(FuzzCase::from_bytes(&[0; 9]), None);
+    let case = FuzzCase::from_bytes(&[0x00, 0x02, 0x12, 0x34, 0x56, 0x24, 0xa9, 0x80, 0x00, 0xea, 0xff]).unwrap();
+    assert_eq!(case, fuzz_case(0x0200, [0xa9, 0x80, 0x00]));
+}
+
+#[test]
+fn test_fuzz_checks() {
+    let fuzzer = CoreFuzzer::new();
+    assert_eq!(fuzzer.check(&fuzz_case(0x0200, [0xa9, 0x80, 0x00])), Ok(()));
+    assert_eq!(fuzzer.check(&fuzz_case(0x0200, [0xea, 0x00, 0x00])), Ok(()));
+    // undocumented opcodes are skipped unless asked for
+    assert_eq!(fuzzer.check(&fuzz_case(0x0200, [0x02, 0x00, 0x00])), Ok(()));
+    let failure = CoreFuzzer::new().with_documented_only(false).check(&fuzz_case(0x0200, [0x02, 0x00, 0x00]));
+    assert_eq!(failure, Err(FuzzFailure::Panicked("No definition for opcode 0x02".to_string())));
+    // JMP isn't in the processor's table yet
+    let failure = fuzzer.check(&fuzz_case(0x0200, [0x4c, 0x34, 0x12])).unwrap_err();
+    assert_eq!(failure.to_string(), "panicked: No definition for opcode 0x4c");
+
+    // the reference model knows LDA #, which only fetches its operand so far
+    let fuzzer = CoreFuzzer::new().with_reference(true);
+    assert_eq!(fuzzer.check(&fuzz_case(0x0200, [0xea, 0x00, 0x00])), Ok(()));
+    let failure = fuzzer.check(&fuzz_case(0x0200, [0xa9, 0x80, 0x00])).unwrap_err();
+    assert_eq!(failure, FuzzFailure::Reference { register: "A", expected: 0x80, actual: 0x12 });
+    assert_eq!(failure.to_string(), "A=$12, the reference model has $80");
+}
+
+// A cheap deterministic stand-in for a fuzzer's inputs
+fn fuzz_inputs(count: usize) -> impl Iterator<Item = Vec<u8>> {
+    let mut state: u32 = 0x6502;
+    (0..count).map(move |_| {
+        (0..10)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 8) as u8
+            })
+            .collect()
+    })
+}
+
+#[test]
+fn test_fuzz_opcodes_the_processor_has() {
+    // the (zp,X) and (zp),Y fetches are still TODO, so group one's columns for them come up a
+    // byte short; take them out of here as they're fixed
+    let known = [
+        0x01, 0x11, 0x21, 0x31, 0x41, 0x51, 0x61, 0x71, 0x81, 0x91, 0xa1, 0xb1, 0xc1, 0xd1, 0xe1, 0xf1,
+    ];
+    let instructions = create_instruction_table();
+    let fuzzer = CoreFuzzer::new();
+    let mut failing = std::collections::BTreeSet::new();
+    for data in fuzz_inputs(4000) {
+        let mut case = FuzzCase::from_bytes(&data).unwrap();
+        // away from the top of memory, where the pc overflows
+        case.registers.pc &= 0x7fff;
+        if !instructions.contains(case.instruction[0]) {
+            continue;
+        }
+        match fuzzer.check(&case) {
+            Err(FuzzFailure::Length { expected: 2, actual: 1 }) if known.contains(&case.instruction[0]) => {
+                failing.insert(case.instruction[0]);
+            }
+            result => assert_eq!(result, Ok(()), "{:?}", case),
+        }
+    }
+    assert_eq!(failing.into_iter().collect::<Vec<_>>(), known);
+}
+
+#[test]
+#[ignore = "the processor's instruction table is missing most documented opcodes"]
+fn test_fuzz_every_documented_opcode() {
+    let fuzzer = CoreFuzzer::new().with_reference(true);
+    let failures: Vec<String> = fuzz_inputs(20000)
+        .filter_map(|data| {
+            let case = FuzzCase::from_bytes(&data).unwrap();
+            fuzzer.check(&case).err().map(|failure| format!("${:02X}: {}", case.instruction[0], failure))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{} failures, first: {}", failures.len(), failures[0]);
+}
+
+// The trace has the processor as it is, fetching without carrying out operations, so expect to
+// rewrite it with UPDATE_GOLDEN=1 cargo test as instructions are implemented
+#[test]
+fn test_golden_trace() {
+    let source = "
+        .org $0200
+        LDX #$03
+        LDY #$10
+        LDA #$AA
+        STA $10,X
+        NOP
+        CMP #$AA
+        BRK
+        .org $0010
+        .byte 1, 2, 3, 4
+    ";
+    let mut system = System::new();
+    assemble(source).unwrap().load(&mut system);
+    let run = GoldenRun::new(0x0200).with_memory(0x0010, 0x0017);
+    if let Err(e) = run.check(&mut system, "tests/golden/loads.trace") {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+fn test_golden_diff() {
+    if env::var_os(golden::UPDATE_VAR).is_some() {
+        return;
+    }
+    let dir = env::temp_dir().join(format!("golden_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("run.trace");
+    let error = golden::check(&path, "a\n").unwrap_err();
+    assert!(matches!(error, GoldenError::Missing(_)));
+    assert!(error.to_string().ends_with("run.trace doesn't exist, UPDATE_GOLDEN=1 writes it"), "{}", error);
+
+    std::fs::write(&path, "1\n2\n3\n4\n5\n6\n").unwrap();
+    assert!(golden::check(&path, "1\n2\n3\n4\n5\n6\n").is_ok());
+    let error = golden::check(&path, "1\n2\n3\n4\nfive\n6\n7\n").unwrap_err();
+    let GoldenError::Mismatch { line, diff, .. } = &error else { panic!("{}", error) };
+    assert_eq!(*line, 5);
+    assert_eq!(diff, "  2\n  3\n  4\n- 5\n- 6\n+ five\n+ 6\n+ 7\n(7 lines, the golden file has 6)\n");
+    assert!(error.to_string().contains("differs from line 5 (UPDATE_GOLDEN=1 rewrites it"), "{}", error);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_opcode_coverage() {
+    let report = coverage::coverage();
+    assert_eq!(report.len(), 151);
+    assert!(report.windows(2).all(|w| w[0].opcode < w[1].opcode));
+    let find = |opcode: u8| report.iter().find(|c| c.opcode == opcode).unwrap();
+    assert!(find(0xa9).is_complete());
+    assert_eq!(find(0xa9).to_string(), "$A9 LDA #       decoded");
+    assert_eq!(find(0x4c).status, Status::Missing);
+    assert_eq!(find(0x4c).to_string(), "$4C JMP abs     missing");
+    assert_eq!(find(0x09).to_string(), "$09 ORA #       decoded; todo: ComputeAndStore OR");
+    assert_eq!(find(0xc0).todo, vec!["CompareToRegister"]);
+    assert_eq!(find(0xa1).to_string(), "$A1 LDA (zp,X)  decoded; todo: (zp,X) fetch");
+    let summary = coverage::summary(&report);
+    assert!(summary.starts_with("151 documented opcodes: "), "{}", summary);
+    assert_eq!(summary.ends_with("not their operations"), !coverage::operations_run());
+}